@@ -0,0 +1,325 @@
+//! One line per request with `--access-log`: who (client address, user, user agent) did what
+//! (method, path, route, status) and how much (bytes in and out, duration), written under the
+//! `access` log target once the response body has been sent
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use http_body::{Frame, SizeHint};
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    task::{Context, Poll},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{middleware, state};
+
+tokio::task_local! {
+    static USER: RefCell<Option<String>>;
+}
+
+/// Note who the request being handled by the current task authenticated as
+pub(crate) fn note_user(username: &str) {
+    let _ = USER.try_with(|user| *user.borrow_mut() = Some(username.to_string()));
+}
+
+/// An address or CIDR range of reverse proxies trusted to set `X-Forwarded-For`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TrustedProxy {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for TrustedProxy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|e| format!("invalid proxy address {}: {}", s, e))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("invalid prefix length in {}", s))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl TrustedProxy {
+    fn contains(&self, ip: IpAddr) -> bool {
+        let (addr, ip, bits) = match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(a), IpAddr::V4(b)) => (u32::from(a) as u128, u32::from(b) as u128, 32),
+            (IpAddr::V6(a), IpAddr::V6(b)) => (u128::from(a), u128::from(b), 128),
+            _ => return false,
+        };
+        let shift = bits - u32::from(self.prefix);
+        shift >= bits || (addr >> shift) == (ip >> shift)
+    }
+}
+
+/// Whether `ip` is one of the `trusted` reverse proxies
+pub(crate) fn is_trusted(trusted: &[TrustedProxy], ip: IpAddr) -> bool {
+    trusted.iter().any(|proxy| proxy.contains(ip))
+}
+
+/// Index of the hop a chain of trusted proxies received the request from: walking `hops` from
+/// the right, the first that is not a trusted proxy (or has no address), or the leftmost when
+/// all are. Proxies append, so entries left of it come from the client and may be forged.
+pub(crate) fn nearest_untrusted(
+    trusted: &[TrustedProxy],
+    hops: &[Option<IpAddr>],
+) -> Option<usize> {
+    hops.iter()
+        .rposition(|hop| !hop.is_some_and(|ip| is_trusted(trusted, ip)))
+        .or_else(|| (!hops.is_empty()).then_some(0))
+}
+
+/// The client's address: the connection's peer, or when it is a trusted proxy, the last
+/// `X-Forwarded-For` entry that is not a trusted proxy
+pub(crate) fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted: &[TrustedProxy]) -> IpAddr {
+    if !is_trusted(trusted, peer) {
+        return peer;
+    }
+    let forwarded: Vec<Option<IpAddr>> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|hop| hop.trim().parse().ok())
+        .collect();
+    nearest_untrusted(trusted, &forwarded)
+        .and_then(|i| forwarded[i])
+        .unwrap_or(peer)
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    timestamp: u64,
+    request_id: Option<&'a str>,
+    client_ip: String,
+    user: Option<&'a str>,
+    method: &'a str,
+    path: &'a str,
+    endpoint: &'a str,
+    status: u16,
+    bytes_in: u64,
+    bytes_out: u64,
+    duration_ms: u64,
+    user_agent: Option<&'a str>,
+}
+
+/// A request in flight; the line is written when the request and response bodies are dropped
+struct Entry {
+    start: Instant,
+    request_id: Option<String>,
+    client_ip: IpAddr,
+    method: String,
+    path: String,
+    endpoint: String,
+    user_agent: Option<String>,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    /// Status and user, known once the handler returns
+    outcome: OnceLock<(u16, Option<String>)>,
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        let (status, user) = self.outcome.get().cloned().unwrap_or((0, None));
+        let line = Line {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            request_id: self.request_id.as_deref(),
+            client_ip: self.client_ip.to_string(),
+            user: user.as_deref(),
+            method: &self.method,
+            path: &self.path,
+            endpoint: &self.endpoint,
+            status,
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed),
+            duration_ms: self.start.elapsed().as_millis() as u64,
+            user_agent: self.user_agent.as_deref(),
+        };
+        match serde_json::to_string(&line) {
+            Ok(line) => log::info!(target: "access", "{}", line),
+            Err(e) => log::error!("access_log: cannot serialize line: {}", e),
+        }
+    }
+}
+
+/// Body passing frames through while counting their bytes into the request's entry
+struct Counted {
+    inner: Body,
+    entry: Arc<Entry>,
+    outgoing: bool,
+}
+
+impl HttpBody for Counted {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll {
+            if let Some(data) = frame.data_ref() {
+                let counter = if self.outgoing {
+                    &self.entry.bytes_out
+                } else {
+                    &self.entry.bytes_in
+                };
+                counter.fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Record each request in the access log when `--access-log` is set
+pub async fn log_access(
+    State(state): State<Arc<state::App>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if !state.args.access_log {
+        return next.run(req).await;
+    }
+
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    let headers = req.headers();
+    let entry = Arc::new(Entry {
+        start: Instant::now(),
+        request_id: middleware::request_id(),
+        client_ip: peer.map_or(IpAddr::from([0, 0, 0, 0]), |peer| {
+            client_ip(peer, headers, &state.args.trusted_proxies)
+        }),
+        method: req.method().to_string(),
+        path: req.uri().path().to_string(),
+        endpoint: req
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or_else(|| "unmatched".to_string(), |p| p.as_str().to_string()),
+        user_agent: headers
+            .get("user-agent")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from),
+        bytes_in: AtomicU64::new(0),
+        bytes_out: AtomicU64::new(0),
+        outcome: OnceLock::new(),
+    });
+
+    let req = req.map(|body| {
+        Body::new(Counted {
+            inner: body,
+            entry: entry.clone(),
+            outgoing: false,
+        })
+    });
+    let (response, user) = USER
+        .scope(RefCell::new(None), async {
+            let response = next.run(req).await;
+            (response, USER.with(|user| user.borrow().clone()))
+        })
+        .await;
+    let _ = entry.outcome.set((response.status().as_u16(), user));
+
+    response.map(|body| {
+        Body::new(Counted {
+            inner: body,
+            entry,
+            outgoing: true,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_ip() {
+        let trusted: Vec<TrustedProxy> = ["10.0.0.0/8", "::1"]
+            .iter()
+            .map(|p| p.parse().unwrap())
+            .collect();
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let forwarded = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-forwarded-for", value.parse().unwrap());
+            headers
+        };
+
+        // Untrusted peers cannot claim another address
+        assert_eq!(
+            client_ip(ip("203.0.113.9"), &forwarded("198.51.100.1"), &trusted),
+            ip("203.0.113.9")
+        );
+        // The nearest hop that is not a trusted proxy is the client
+        assert_eq!(
+            client_ip(
+                ip("10.1.2.3"),
+                &forwarded("192.0.2.66, 198.51.100.1, 10.4.5.6"),
+                &trusted
+            ),
+            ip("198.51.100.1")
+        );
+        // Entries the client made up ahead of the proxy's are skipped, even unparseable ones
+        assert_eq!(
+            client_ip(
+                ip("10.1.2.3"),
+                &forwarded("garbage, 198.51.100.1"),
+                &trusted
+            ),
+            ip("198.51.100.1")
+        );
+        assert_eq!(client_ip(ip("::1"), &HeaderMap::new(), &trusted), ip("::1"));
+        assert_eq!(
+            client_ip(ip("::ffff:10.0.0.1"), &forwarded("192.0.2.1"), &trusted),
+            ip("192.0.2.1")
+        );
+
+        assert!("10.0.0.0/33".parse::<TrustedProxy>().is_err());
+        assert!("proxy".parse::<TrustedProxy>().is_err());
+        assert!("0.0.0.0/0"
+            .parse::<TrustedProxy>()
+            .unwrap()
+            .contains(ip("192.0.2.1")));
+    }
+}
@@ -0,0 +1,32 @@
+//! Last pull of each repository, kept in memory for `/admin/repos/{org}/{repo}/stats`. Pushes
+//! need no tracking: the newest stored manifest tells when a repository was last pushed to.
+
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Default)]
+pub(crate) struct Activity {
+    last_pulls: RwLock<HashMap<String, u64>>,
+}
+
+impl Activity {
+    /// Note a manifest pull from `repository` now
+    pub(crate) fn record_pull(&self, repository: &str) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.last_pulls
+            .write()
+            .unwrap()
+            .insert(repository.to_string(), now);
+    }
+
+    /// Unix time of the last manifest pull from `repository` since this instance started
+    pub(crate) fn last_pull(&self, repository: &str) -> Option<u64> {
+        self.last_pulls.read().unwrap().get(repository).copied()
+    }
+}
@@ -2,14 +2,26 @@ use axum::{
     body::Body,
     extract::{Path, Query, State},
     http::{HeaderMap, StatusCode},
-    response::Response,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
 };
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
 use std::sync::Arc;
 use utoipa::ToSchema;
 
-use crate::{auth, gc, permissions, response, state};
+use crate::{
+    audit::{AuditEvent, Outcome},
+    auth,
+    changelog::{self, Change},
+    dry_run::{self, DryRunQuery, DryRunResult, PlannedChange},
+    gc,
+    pagination::{self, PageQuery},
+    response, state, storage,
+};
 
 #[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct CreateUserRequest {
@@ -17,6 +29,52 @@ pub struct CreateUserRequest {
     pub password: String,
     #[serde(default)]
     pub permissions: Vec<state::Permission>,
+    /// Grants the admin API
+    #[serde(default)]
+    pub admin: bool,
+    /// Orgs whose accounts, permissions and garbage collection the user administers
+    #[serde(default)]
+    pub admin_orgs: Vec<String>,
+    /// Roles whose permissions the user also gets
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Groups the user is a member of
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct UpdateUserRequest {
+    pub password: String,
+    /// Replaces all of the user's permissions
+    #[serde(default)]
+    pub permissions: Vec<state::Permission>,
+    /// Grants or revokes the admin API; unchanged when omitted
+    #[serde(default)]
+    pub admin: Option<bool>,
+    /// Replaces the orgs the user administers; unchanged when omitted
+    #[serde(default)]
+    pub admin_orgs: Option<Vec<String>>,
+    /// Replaces the user's roles
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Replaces the user's groups
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ChangePasswordRequest {
+    pub password: String,
+    /// Required when callers change their own password
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_password: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ChangeOwnPasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
 }
 
 #[derive(Debug, Deserialize, Serialize, ToSchema)]
@@ -24,6 +82,9 @@ pub struct AddPermissionRequest {
     pub repository: String,
     pub tag: String,
     pub actions: Vec<String>,
+    /// Unix timestamp (seconds) after which the permission lapses
+    #[serde(default)]
+    pub expires_at: Option<u64>,
 }
 
 #[derive(Debug, Deserialize, Serialize, ToSchema)]
@@ -32,19 +93,113 @@ pub struct AddPermissionWithUsernameRequest {
     pub repository: String,
     pub tag: String,
     pub actions: Vec<String>,
+    /// Unix timestamp (seconds) after which the permission lapses
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct RemovePermissionRequest {
+    pub repository: String,
+    pub tag: String,
+    /// Actions to revoke; all of them when omitted
+    #[serde(default)]
+    pub actions: Option<Vec<String>>,
+}
+
+/// 400 naming the first of `roles` and `groups` that does not exist, if any
+async fn check_role_references(
+    state: &Arc<state::App>,
+    roles: &[String],
+    groups: &[String],
+) -> Option<Response> {
+    let reason = state.roles.lock().await.check_references(roles, groups)?;
+    Some(
+        Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from(reason))
+            .unwrap(),
+    )
+}
+
+/// Check if user may use the admin API
+pub(crate) fn is_admin(user: &state::User) -> bool {
+    user.admin
+}
+
+/// Check if user administers at least one org: their accounts, permissions and garbage
+/// collection, and nothing else
+fn is_org_admin(user: &state::User) -> bool {
+    !user.admin_orgs.is_empty()
+}
+
+/// Whether `user` administers repositories matching `repository` (a name or pattern): all of
+/// them for admins, those under one of their orgs for org admins
+fn administers(user: &state::User, repository: &str) -> bool {
+    user.admin
+        || user.admin_orgs.iter().any(|org| {
+            repository
+                .strip_prefix(org.as_str())
+                .is_some_and(|rest| rest.starts_with('/'))
+        })
+}
+
+/// Whether `user` may manage `target`'s account: any account for admins. Org admins manage
+/// plain accounts (no admin rights, roles or groups) whose permissions all lie in their orgs.
+fn manages(user: &state::User, target: &state::User) -> bool {
+    user.admin
+        || (is_org_admin(user)
+            && !target.admin
+            && target.admin_orgs.is_empty()
+            && target.roles.is_empty()
+            && target.groups.is_empty()
+            && !target.permissions.is_empty()
+            && target
+                .permissions
+                .iter()
+                .all(|p| administers(user, &p.repository)))
+}
+
+/// Whether `user` may grant or revoke `target`'s permissions on `repository`. Org admins can
+/// open their orgs to any account without admin rights.
+fn grants(user: &state::User, target: &state::User, repository: &str) -> bool {
+    user.admin || (administers(user, repository) && !target.admin && target.admin_orgs.is_empty())
+}
+
+/// 400 if an org in `admin_orgs` is not a plain org name
+fn check_admin_orgs(admin_orgs: &[String]) -> Option<Response> {
+    let invalid = admin_orgs
+        .iter()
+        .find(|org| org.is_empty() || org.contains(['/', '*', '?']))?;
+    Some(
+        Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from(format!("Invalid org {}", invalid)))
+            .unwrap(),
+    )
 }
 
-/// Check if user is admin (has wildcard delete permission)
-fn is_admin(user: &state::User) -> bool {
-    permissions::has_permission(user, "*", Some("*"), permissions::Action::Delete)
+/// The account called `username`, as stored
+async fn find_user(state: &Arc<state::App>, username: &str) -> Option<state::User> {
+    state
+        .users
+        .lock()
+        .await
+        .iter()
+        .find(|u| u.username == username)
+        .cloned()
 }
 
 /// List all users (admin only)
 #[utoipa::path(
     get,
-    path = "/admin/users",
+    path = "/admin/v1/users",
+    params(
+        ("n" = Option<usize>, Query, description = "Most entries to return (default and maximum: 1000)"),
+        ("last" = Option<String>, Query, description = "Return entries after this one, from `next` of the previous page")
+    ),
     responses(
-        (status = 200, description = "List of all users with their permissions", content_type = "application/json"),
+        (status = 200, description = "Users with their permissions, by username; `next` and a `Link` header point at the next page", content_type = "application/json"),
         (status = 401, description = "Unauthorized - authentication required"),
         (status = 403, description = "Forbidden - admin permission required")
     ),
@@ -52,8 +207,12 @@ fn is_admin(user: &state::User) -> bool {
         ("basic_auth" = [])
     )
 )]
-pub async fn list_users(State(state): State<Arc<state::App>>, headers: HeaderMap) -> Response {
-    let host = &state.args.host;
+pub async fn list_users(
+    State(state): State<Arc<state::App>>,
+    Query(query): Query<PageQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let host = state.args.primary_host();
 
     // Authenticate
     let user = match auth::authenticate_user(&state, &headers).await {
@@ -61,43 +220,49 @@ pub async fn list_users(State(state): State<Arc<state::App>>, headers: HeaderMap
         Err(_) => return response::unauthorized(host),
     };
 
-    // Check admin permission
-    if !is_admin(&user) {
+    // Org admins only see the accounts they manage
+    if !is_admin(&user) && !is_org_admin(&user) {
         return response::forbidden();
     }
 
     // Get users
     let users = state.users.lock().await;
-    let user_list: Vec<_> = users
+    let page = query.page(users.iter().filter(|u| manages(&user, u)), |u| &u.username);
+    let user_list: Vec<_> = page
+        .items
         .iter()
         .map(|u| {
             serde_json::json!({
                 "username": u.username,
                 "permissions": u.permissions,
+                "admin": u.admin,
+                "admin_orgs": u.admin_orgs,
+                "roles": u.roles,
+                "groups": u.groups,
             })
         })
         .collect();
 
-    Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "application/json")
-        .body(Body::from(
-            serde_json::json!({
-                "users": user_list
-            })
-            .to_string(),
-        ))
-        .unwrap()
+    let mut body = serde_json::json!({
+        "users": user_list
+    });
+    if let Some(next) = &page.next {
+        body["next"] = serde_json::json!(next);
+    }
+    pagination::response(
+        body.to_string(),
+        pagination::next_link("/admin/v1/users", &page),
+    )
 }
 
 /// Create new user (admin only)
 #[utoipa::path(
     post,
-    path = "/admin/users",
+    path = "/admin/v1/users",
     request_body = CreateUserRequest,
     responses(
         (status = 201, description = "User created successfully", content_type = "application/json"),
-        (status = 400, description = "Bad request - invalid JSON"),
+        (status = 400, description = "Bad request - invalid JSON, password rejected by policy or unknown role or group"),
         (status = 401, description = "Unauthorized - authentication required"),
         (status = 403, description = "Forbidden - admin permission required"),
         (status = 409, description = "Conflict - user already exists"),
@@ -112,7 +277,7 @@ pub async fn create_user(
     headers: HeaderMap,
     body: Bytes,
 ) -> Response {
-    let host = &state.args.host;
+    let host = state.args.primary_host();
 
     // Authenticate
     let user = match auth::authenticate_user(&state, &headers).await {
@@ -120,8 +285,8 @@ pub async fn create_user(
         Err(_) => return response::unauthorized(host),
     };
 
-    // Check admin permission
-    if !is_admin(&user) {
+    // Org admins may create accounts within their orgs
+    if !is_admin(&user) && !is_org_admin(&user) {
         return response::forbidden();
     }
 
@@ -136,12 +301,35 @@ pub async fn create_user(
         }
     };
 
+    if let Err(violations) = state.password_policy.validate(&req.username, &req.password) {
+        log::warn!(
+            "Rejected password for new user {}: {:?}",
+            req.username,
+            violations
+        );
+        return response::password_rejected(&violations);
+    }
+
+    if let Some(rejected) = check_role_references(&state, &req.roles, &req.groups).await {
+        return rejected;
+    }
+    if let Some(rejected) = check_admin_orgs(&req.admin_orgs) {
+        return rejected;
+    }
+
     // Create new user
     let new_user = state::User {
         username: req.username.clone(),
         password: req.password,
         permissions: req.permissions,
+        admin: req.admin,
+        admin_orgs: req.admin_orgs,
+        roles: req.roles,
+        groups: req.groups,
     };
+    if !manages(&user, &new_user) {
+        return response::forbidden();
+    }
 
     // Add to users set
     {
@@ -162,6 +350,12 @@ pub async fn create_user(
     }
 
     log::info!("Created user: {}", new_user.username);
+    state.audit.record(AuditEvent::new(
+        &user.username,
+        "user.create",
+        &new_user.username,
+        Outcome::Success,
+    ));
 
     Response::builder()
         .status(StatusCode::CREATED)
@@ -170,6 +364,10 @@ pub async fn create_user(
             serde_json::json!({
                 "username": new_user.username,
                 "permissions": new_user.permissions,
+                "admin": new_user.admin,
+                "admin_orgs": new_user.admin_orgs,
+                "roles": new_user.roles,
+                "groups": new_user.groups,
             })
             .to_string(),
         ))
@@ -179,12 +377,14 @@ pub async fn create_user(
 /// Delete user (admin only)
 #[utoipa::path(
     delete,
-    path = "/admin/users/{username}",
+    path = "/admin/v1/users/{username}",
     params(
-        ("username" = String, Path, description = "Username of the user to delete")
+        ("username" = String, Path, description = "Username of the user to delete"),
+        ("dry_run" = Option<bool>, Query, description = "Only report what would be deleted")
     ),
     responses(
         (status = 204, description = "User deleted successfully"),
+        (status = 200, description = "Dry run: what would be deleted", body = DryRunResult),
         (status = 400, description = "Bad request - cannot delete yourself"),
         (status = 401, description = "Unauthorized - authentication required"),
         (status = 403, description = "Forbidden - admin permission required"),
@@ -198,9 +398,10 @@ pub async fn create_user(
 pub async fn delete_user(
     State(state): State<Arc<state::App>>,
     Path(username): Path<String>,
+    Query(query): Query<DryRunQuery>,
     headers: HeaderMap,
 ) -> Response {
-    let host = &state.args.host;
+    let host = state.args.primary_host();
 
     // Authenticate
     let user = match auth::authenticate_user(&state, &headers).await {
@@ -209,7 +410,7 @@ pub async fn delete_user(
     };
 
     // Check admin permission
-    if !is_admin(&user) {
+    if !is_admin(&user) && !is_org_admin(&user) {
         return response::forbidden();
     }
 
@@ -221,6 +422,17 @@ pub async fn delete_user(
             .unwrap();
     }
 
+    let Some(target) = find_user(&state, &username).await else {
+        return response::not_found();
+    };
+    if !manages(&user, &target) {
+        return response::forbidden();
+    }
+
+    if query.dry_run {
+        return dry_run::response(vec![PlannedChange::new("user.delete", &username)]);
+    }
+
     // Remove user
     {
         let mut users = state.users.lock().await;
@@ -239,6 +451,12 @@ pub async fn delete_user(
     }
 
     log::info!("Deleted user: {}", username);
+    state.audit.record(AuditEvent::new(
+        &user.username,
+        "user.delete",
+        &username,
+        Outcome::Success,
+    ));
 
     Response::builder()
         .status(StatusCode::OK)
@@ -246,10 +464,367 @@ pub async fn delete_user(
         .unwrap()
 }
 
+/// Replace the user called `username` with what `update` makes of it; None when there is none
+async fn update_user_with(
+    state: &Arc<state::App>,
+    username: &str,
+    update: impl FnOnce(&mut state::User),
+) -> Option<state::User> {
+    let mut users = state.users.lock().await;
+    let mut updated = users.iter().find(|u| u.username == username)?.clone();
+    users.retain(|u| u.username != username);
+    update(&mut updated);
+    users.insert(updated.clone());
+    Some(updated)
+}
+
+/// Replace a user's password and permissions (admin only)
+#[utoipa::path(
+    put,
+    path = "/admin/v1/users/{username}",
+    params(
+        ("username" = String, Path, description = "Username of the user to update")
+    ),
+    request_body = UpdateUserRequest,
+    responses(
+        (status = 200, description = "User updated", content_type = "application/json"),
+        (status = 400, description = "Bad request - invalid JSON, password rejected by policy, unknown role or group, or revoking your own admin flag"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - user does not exist"),
+        (status = 500, description = "Internal server error - failed to save users")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn update_user(
+    State(state): State<Arc<state::App>>,
+    Path(username): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let host = state.args.primary_host();
+
+    // Authenticate
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    // Check admin permission
+    if !is_admin(&user) && !is_org_admin(&user) {
+        return response::forbidden();
+    }
+
+    // Parse request
+    let req: UpdateUserRequest = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid request: {}", e)))
+                .unwrap();
+        }
+    };
+
+    if let Err(violations) = state.password_policy.validate(&username, &req.password) {
+        log::warn!("Rejected password for user {}: {:?}", username, violations);
+        return response::password_rejected(&violations);
+    }
+
+    // Prevent locking yourself out of the admin API
+    if user.username == username && req.admin == Some(false) {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from("Cannot revoke your own admin flag"))
+            .unwrap();
+    }
+
+    if let Some(rejected) = check_role_references(&state, &req.roles, &req.groups).await {
+        return rejected;
+    }
+    if let Some(rejected) = req.admin_orgs.as_deref().and_then(check_admin_orgs) {
+        return rejected;
+    }
+
+    let apply = |u: &mut state::User| {
+        u.password = req.password;
+        u.permissions = req.permissions;
+        u.roles = req.roles;
+        u.groups = req.groups;
+        if let Some(admin) = req.admin {
+            u.admin = admin;
+        }
+        if let Some(admin_orgs) = req.admin_orgs {
+            u.admin_orgs = admin_orgs;
+        }
+    };
+
+    // Org admins may only edit accounts they manage, and only within their orgs
+    let Some(mut candidate) = find_user(&state, &username).await else {
+        return response::not_found();
+    };
+    let managed = manages(&user, &candidate);
+    apply(&mut candidate);
+    if !managed || !manages(&user, &candidate) {
+        return response::forbidden();
+    }
+
+    let Some(updated) = update_user_with(&state, &username, |u| *u = candidate).await else {
+        return response::not_found();
+    };
+
+    // Persist to file
+    if let Err(e) = save_users(&state).await {
+        log::error!("Failed to save users: {}", e);
+        return response::internal_error();
+    }
+
+    log::info!("Updated user: {}", username);
+    state.audit.record(
+        AuditEvent::new(&user.username, "user.update", &username, Outcome::Success)
+            .with_detail(serde_json::to_string(&updated.permissions).unwrap_or_default()),
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::json!({
+                "username": updated.username,
+                "permissions": updated.permissions,
+                "admin": updated.admin,
+                "admin_orgs": updated.admin_orgs,
+                "roles": updated.roles,
+                "groups": updated.groups,
+            })
+            .to_string(),
+        ))
+        .unwrap()
+}
+
+/// Change a user's password, keeping their permissions (admin, or the user themselves)
+#[utoipa::path(
+    post,
+    path = "/admin/v1/users/{username}/password",
+    params(
+        ("username" = String, Path, description = "Username of the user whose password changes")
+    ),
+    request_body = ChangePasswordRequest,
+    responses(
+        (status = 204, description = "Password changed"),
+        (status = 400, description = "Bad request - invalid JSON or password rejected by policy"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required to change another user's password, or current password missing or wrong for your own"),
+        (status = 404, description = "Not found - user does not exist"),
+        (status = 500, description = "Internal server error - failed to save users")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn change_password(
+    State(state): State<Arc<state::App>>,
+    Path(username): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let host = state.args.primary_host();
+
+    // Authenticate
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    // Users may rotate their own password given the current one; anyone else's takes admin
+    // permission over them
+    let own = user.username == username;
+    if !own {
+        match find_user(&state, &username).await {
+            Some(target) if manages(&user, &target) => {}
+            Some(_) => return response::forbidden(),
+            None if is_admin(&user) => return response::not_found(),
+            None => return response::forbidden(),
+        }
+    }
+
+    // Parse request
+    let req: ChangePasswordRequest = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid request: {}", e)))
+                .unwrap();
+        }
+    };
+
+    if own {
+        let current = req.current_password.as_deref().unwrap_or_default();
+        if let Some(refused) = check_current_password(&state, &user.username, current).await {
+            return refused;
+        }
+    }
+
+    if own {
+        let current = req.current_password.as_deref().unwrap_or_default();
+        if let Some(refused) = check_current_password(&state, &user.username, current).await {
+            return refused;
+        }
+    }
+
+    if let Err(violations) = state.password_policy.validate(&username, &req.password) {
+        log::warn!("Rejected password for user {}: {:?}", username, violations);
+        return response::password_rejected(&violations);
+    }
+
+    if update_user_with(&state, &username, |u| u.password = req.password)
+        .await
+        .is_none()
+    {
+        return response::not_found();
+    }
+
+    // Persist to file
+    if let Err(e) = save_users(&state).await {
+        log::error!("Failed to save users: {}", e);
+        return response::internal_error();
+    }
+
+    log::info!("Changed password of user: {}", username);
+    state.audit.record(AuditEvent::new(
+        &user.username,
+        "user.password",
+        &username,
+        Outcome::Success,
+    ));
+
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Refuse a change of `username`'s own password unless `current` is their password. A bearer
+/// token proves who the caller is, not that they know the password.
+async fn check_current_password(
+    state: &state::App,
+    username: &str,
+    current: &str,
+) -> Option<Response> {
+    let current_matches = {
+        let users = state.users.lock().await;
+        users
+            .iter()
+            .find(|u| u.username == username)
+            .map(|u| u.password == current)
+    };
+    match current_matches {
+        Some(true) => None,
+        Some(false) => {
+            log::warn!("Wrong current password for user {}", username);
+            state.audit.record(
+                AuditEvent::new(username, "user.password", username, Outcome::Failure)
+                    .with_detail("current password does not match"),
+            );
+            Some(response::forbidden())
+        }
+        // Robots and SSO identities are not in the users file
+        None => Some(response::forbidden()),
+    }
+}
+
+/// Rotate the caller's own password, after checking the current one (any user)
+#[utoipa::path(
+    post,
+    path = "/user/password",
+    request_body = ChangeOwnPasswordRequest,
+    responses(
+        (status = 204, description = "Password changed"),
+        (status = 400, description = "Bad request - invalid JSON or password rejected by policy"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - current password is wrong, or the caller has no password (robot or SSO identity)"),
+        (status = 500, description = "Internal server error - failed to save users")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn change_own_password(
+    State(state): State<Arc<state::App>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let host = state.args.primary_host();
+
+    // Authenticate
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    // Parse request
+    let req: ChangeOwnPasswordRequest = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid request: {}", e)))
+                .unwrap();
+        }
+    };
+
+    if let Some(refused) =
+        check_current_password(&state, &user.username, &req.current_password).await
+    {
+        return refused;
+    }
+
+    if let Err(violations) = state
+        .password_policy
+        .validate(&user.username, &req.new_password)
+    {
+        log::warn!(
+            "Rejected password for user {}: {:?}",
+            user.username,
+            violations
+        );
+        return response::password_rejected(&violations);
+    }
+
+    if update_user_with(&state, &user.username, |u| u.password = req.new_password)
+        .await
+        .is_none()
+    {
+        return response::forbidden();
+    }
+
+    // Persist to file
+    if let Err(e) = save_users(&state).await {
+        log::error!("Failed to save users: {}", e);
+        return response::internal_error();
+    }
+
+    log::info!("User {} changed their password", user.username);
+    state.audit.record(AuditEvent::new(
+        &user.username,
+        "user.password",
+        &user.username,
+        Outcome::Success,
+    ));
+
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .unwrap()
+}
+
 /// Add permission to user (admin only)
 #[utoipa::path(
     post,
-    path = "/admin/users/{username}/permissions",
+    path = "/admin/v1/users/{username}/permissions",
     params(
         ("username" = String, Path, description = "Username of the user to add permission to")
     ),
@@ -272,7 +847,7 @@ pub async fn add_permission(
     headers: HeaderMap,
     body: Bytes,
 ) -> Response {
-    let host = &state.args.host;
+    let host = state.args.primary_host();
 
     // Authenticate
     let user = match auth::authenticate_user(&state, &headers).await {
@@ -281,7 +856,7 @@ pub async fn add_permission(
     };
 
     // Check admin permission
-    if !is_admin(&user) {
+    if !is_admin(&user) && !is_org_admin(&user) {
         return response::forbidden();
     }
 
@@ -300,8 +875,18 @@ pub async fn add_permission(
         repository: req.repository,
         tag: req.tag,
         actions: req.actions,
+        expires_at: req.expires_at,
     };
 
+    // Org admins may only grant and revoke within their orgs
+    match find_user(&state, &username).await {
+        None => return response::not_found(),
+        Some(target) if !grants(&user, &target, &new_permission.repository) => {
+            return response::forbidden()
+        }
+        Some(_) => {}
+    }
+
     // Add permission to user
     {
         let mut users = state.users.lock().await;
@@ -340,6 +925,15 @@ pub async fn add_permission(
         username,
         new_permission
     );
+    state.audit.record(
+        AuditEvent::new(
+            &user.username,
+            "permission.add",
+            &username,
+            Outcome::Success,
+        )
+        .with_detail(serde_json::to_string(&new_permission).unwrap_or_default()),
+    );
 
     Response::builder()
         .status(StatusCode::OK)
@@ -348,10 +942,125 @@ pub async fn add_permission(
         .unwrap()
 }
 
+/// Revoke permissions from a user (admin only)
+///
+/// Permissions are matched on their exact repository and tag patterns. Only the given actions
+/// are revoked when `actions` is set, and permissions left with no action are dropped.
+#[utoipa::path(
+    delete,
+    path = "/admin/v1/users/{username}/permissions",
+    params(
+        ("username" = String, Path, description = "Username of the user to revoke permission from")
+    ),
+    request_body = RemovePermissionRequest,
+    responses(
+        (status = 200, description = "Permission revoked, returns the remaining permissions", content_type = "application/json"),
+        (status = 400, description = "Bad request - invalid JSON"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - user does not exist or has no matching permission"),
+        (status = 500, description = "Internal server error - failed to save users")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn remove_permission(
+    State(state): State<Arc<state::App>>,
+    Path(username): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let host = state.args.primary_host();
+
+    // Authenticate
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    // Check admin permission
+    if !is_admin(&user) && !is_org_admin(&user) {
+        return response::forbidden();
+    }
+
+    // Parse request
+    let req: RemovePermissionRequest = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid request: {}", e)))
+                .unwrap();
+        }
+    };
+
+    // Org admins may only grant and revoke within their orgs
+    match find_user(&state, &username).await {
+        None => return response::not_found(),
+        Some(target) if !grants(&user, &target, &req.repository) => return response::forbidden(),
+        Some(_) => {}
+    }
+
+    let mut revoked = false;
+    let Some(updated) = update_user_with(&state, &username, |u| {
+        for permission in &mut u.permissions {
+            if permission.repository != req.repository || permission.tag != req.tag {
+                continue;
+            }
+            let before = permission.actions.len();
+            match &req.actions {
+                Some(actions) => permission.actions.retain(|a| !actions.contains(a)),
+                None => permission.actions.clear(),
+            }
+            revoked |= permission.actions.len() != before;
+        }
+        u.permissions.retain(|p| !p.actions.is_empty());
+    })
+    .await
+    else {
+        return response::not_found();
+    };
+    if !revoked {
+        return response::not_found();
+    }
+
+    // Persist to file
+    if let Err(e) = save_users(&state).await {
+        log::error!("Failed to save users: {}", e);
+        return response::internal_error();
+    }
+
+    log::info!(
+        "Revoked permission for user {}: {}:{} {:?}",
+        username,
+        req.repository,
+        req.tag,
+        req.actions
+    );
+    state.audit.record(
+        AuditEvent::new(
+            &user.username,
+            "permission.remove",
+            &username,
+            Outcome::Success,
+        )
+        .with_detail(serde_json::to_string(&req).unwrap_or_default()),
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string(&updated.permissions).unwrap(),
+        ))
+        .unwrap()
+}
+
 /// Add permission to user via body (admin only) - alternative endpoint with username in body
 #[utoipa::path(
     post,
-    path = "/admin/permissions",
+    path = "/admin/v1/permissions",
     request_body = AddPermissionWithUsernameRequest,
     responses(
         (status = 201, description = "Permission added successfully", content_type = "application/json"),
@@ -370,7 +1079,7 @@ pub async fn add_permission_with_username(
     headers: HeaderMap,
     body: Bytes,
 ) -> Response {
-    let host = &state.args.host;
+    let host = state.args.primary_host();
 
     // Authenticate
     let user = match auth::authenticate_user(&state, &headers).await {
@@ -379,7 +1088,7 @@ pub async fn add_permission_with_username(
     };
 
     // Check admin permission
-    if !is_admin(&user) {
+    if !is_admin(&user) && !is_org_admin(&user) {
         return response::forbidden();
     }
 
@@ -398,8 +1107,18 @@ pub async fn add_permission_with_username(
         repository: req.repository,
         tag: req.tag,
         actions: req.actions,
+        expires_at: req.expires_at,
     };
 
+    // Org admins may only grant and revoke within their orgs
+    match find_user(&state, &req.username).await {
+        None => return response::not_found(),
+        Some(target) if !grants(&user, &target, &new_permission.repository) => {
+            return response::forbidden()
+        }
+        Some(_) => {}
+    }
+
     // Add permission to user
     {
         let mut users = state.users.lock().await;
@@ -438,6 +1157,15 @@ pub async fn add_permission_with_username(
         req.username,
         new_permission
     );
+    state.audit.record(
+        AuditEvent::new(
+            &user.username,
+            "permission.add",
+            &req.username,
+            Outcome::Success,
+        )
+        .with_detail(serde_json::to_string(&new_permission).unwrap_or_default()),
+    );
 
     Response::builder()
         .status(StatusCode::OK)
@@ -447,43 +1175,55 @@ pub async fn add_permission_with_username(
 }
 
 /// Save users to file
-async fn save_users(state: &Arc<state::App>) -> Result<(), Box<dyn std::error::Error>> {
+pub(crate) async fn save_users(state: &Arc<state::App>) -> Result<(), Box<dyn std::error::Error>> {
     let users = state.users.lock().await;
+    let roles = state.roles.lock().await;
 
     let users_file = state::UsersFile {
         users: users.iter().cloned().collect(),
+        roles: roles.clone(),
     };
 
     let json = serde_json::to_string_pretty(&users_file)?;
-    std::fs::write(&state.args.users_file, json)?;
+    storage::write_file_atomic(
+        std::path::Path::new(&state.args.users_file),
+        json.as_bytes(),
+    )?;
+
+    changelog::record(Change::UsersUpdated);
 
     Ok(())
 }
 
+/// Overrides of the GC policy for one manual run
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct GcQuery {
-    #[serde(default)]
-    pub dry_run: bool,
-    #[serde(default = "default_grace_period")]
-    pub grace_period_hours: u64,
-}
-
-fn default_grace_period() -> u64 {
-    24
+    pub dry_run: Option<bool>,
+    pub grace_period_hours: Option<u64>,
+    pub untagged_manifests: Option<gc::UntaggedManifests>,
+    /// Only delete tags, manifests and blobs of this repository (`<org>/<repo>`) or org (`<org>`)
+    pub repository: Option<String>,
+    /// Return at once and run in the background; follow it with `GET /admin/v1/gc/status`
+    pub background: Option<bool>,
 }
 
 /// Run garbage collection (admin only)
 #[utoipa::path(
     post,
-    path = "/admin/gc",
+    path = "/admin/v1/gc",
     params(
-        ("dry_run" = Option<bool>, Query, description = "Run in dry-run mode without deleting blobs"),
-        ("grace_period_hours" = Option<u64>, Query, description = "Grace period in hours before deleting unreferenced blobs (default: 24)")
+        ("dry_run" = Option<bool>, Query, description = "Run in dry-run mode without deleting blobs (default: GC policy)"),
+        ("grace_period_hours" = Option<u64>, Query, description = "Grace period in hours before deleting unreferenced blobs (default: GC policy)"),
+        ("untagged_manifests" = Option<gc::UntaggedManifests>, Query, description = "Keep or delete manifests no tag reaches (default: GC policy)"),
+        ("repository" = Option<String>, Query, description = "Only delete tags, manifests and blobs of this repository (org/repo) or org (org); required for org admins"),
+        ("background" = Option<bool>, Query, description = "Start the run and return at once; progress is at /admin/v1/gc/status")
     ),
     responses(
-        (status = 200, description = "Garbage collection statistics", content_type = "application/json"),
+        (status = 200, description = "Garbage collection statistics", body = gc::GcStats),
+        (status = 202, description = "Run started in the background"),
         (status = 401, description = "Unauthorized - authentication required"),
         (status = 403, description = "Forbidden - admin permission required"),
+        (status = 409, description = "Conflict - a run is already in progress (background runs only)"),
         (status = 500, description = "Internal server error")
     ),
     security(
@@ -495,7 +1235,7 @@ pub async fn run_garbage_collection(
     headers: HeaderMap,
     Query(params): Query<GcQuery>,
 ) -> Response {
-    let host = &state.args.host;
+    let host = state.args.primary_host();
 
     // Authenticate
     let user = match auth::authenticate_user(&state, &headers).await {
@@ -503,30 +1243,278 @@ pub async fn run_garbage_collection(
         Err(_) => return response::unauthorized(host),
     };
 
-    // Check admin permission
-    if !is_admin(&user) {
+    // Org admins may collect within one of their orgs
+    let in_own_org = params.repository.as_deref().is_some_and(|scope| {
+        let org = scope.split('/').next().unwrap_or_default();
+        user.admin_orgs.iter().any(|o| o == org)
+    });
+    if !is_admin(&user) && !in_own_org {
         return response::forbidden();
     }
 
-    let dry_run = params.dry_run;
-    let grace_period = params.grace_period_hours;
+    let mut policy = state.gc_policy.get();
+    if let Some(dry_run) = params.dry_run {
+        policy.dry_run = dry_run;
+    }
+    if let Some(grace_period_hours) = params.grace_period_hours {
+        policy.grace_period_hours = grace_period_hours;
+    }
+    if let Some(untagged_manifests) = params.untagged_manifests {
+        policy.untagged_manifests = untagged_manifests;
+    }
+    let dry_run = policy.dry_run;
 
     log::info!(
-        "Admin {} initiated GC (dry_run: {}, grace_period: {}h)",
+        "Admin {} initiated GC (dry_run: {}, grace_period: {}h, untagged_manifests: {:?}, repository: {})",
         user.username,
         dry_run,
-        grace_period
+        policy.grace_period_hours,
+        policy.untagged_manifests,
+        params.repository.as_deref().unwrap_or("*")
     );
 
-    match gc::run_gc(dry_run, grace_period) {
-        Ok(stats) => Response::builder()
-            .status(StatusCode::OK)
-            .header("Content-Type", "application/json")
-            .body(Body::from(serde_json::to_string_pretty(&stats).unwrap()))
-            .unwrap(),
-        Err(e) => {
+    let repositories = state.repositories.list();
+    let scope = params.repository;
+    if params.background.unwrap_or(false) {
+        if gc::progress::status().running {
+            return response::conflict("garbage collection is already running");
+        }
+        let state = state.clone();
+        tokio::task::spawn_blocking(move || {
+            match gc::run_gc(&policy, &repositories, scope.as_deref()) {
+                Ok(stats) => {
+                    record_gc_run(&state, &user.username, dry_run, scope.as_deref(), &stats)
+                }
+                Err(e) => log::error!("GC failed: {}", e),
+            }
+        });
+        return Response::builder()
+            .status(StatusCode::ACCEPTED)
+            .header("Location", "/admin/v1/gc/status")
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    let run_scope = scope.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        gc::run_gc(&policy, &repositories, run_scope.as_deref()).map_err(|e| e.to_string())
+    })
+    .await;
+    match result {
+        Ok(Ok(stats)) => {
+            record_gc_run(&state, &user.username, dry_run, scope.as_deref(), &stats);
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "application/json")
+                .body(Body::from(serde_json::to_string_pretty(&stats).unwrap()))
+                .unwrap()
+        }
+        Ok(Err(e)) => {
             log::error!("GC failed: {}", e);
             response::internal_error()
         }
+        Err(e) => {
+            log::error!("GC task failed: {}", e);
+            response::internal_error()
+        }
+    }
+}
+
+/// Audit a completed manual run against its repository, or the registry; dry runs change
+/// nothing, so they are not recorded
+fn record_gc_run(
+    state: &state::App,
+    actor: &str,
+    dry_run: bool,
+    scope: Option<&str>,
+    stats: &gc::GcStats,
+) {
+    if !dry_run {
+        state.audit.record(
+            AuditEvent::new(
+                actor,
+                "gc.run",
+                scope.unwrap_or("registry"),
+                Outcome::Success,
+            )
+            .with_detail(format!(
+                "deleted {} blobs, {} manifests",
+                stats.blobs_deleted, stats.manifests_deleted
+            )),
+        );
+    }
+}
+
+/// Status of the current or last garbage collection run, with its counts so far (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/gc/status",
+    responses(
+        (status = 200, description = "Current or last GC run", body = gc::progress::GcStatus),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn gc_status(State(state): State<Arc<state::App>>, headers: HeaderMap) -> Response {
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(state.args.primary_host()),
+    };
+    if !is_admin(&user) {
+        return response::forbidden();
+    }
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string_pretty(&gc::progress::status()).unwrap(),
+        ))
+        .unwrap()
+}
+
+/// Stream the GC status as Server-Sent Events: a `status` event with the current status, then
+/// one per progress update. The stream ends after the status of a finished run (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/gc/status/events",
+    responses(
+        (status = 200, description = "`status` events carrying a GcStatus", content_type = "text/event-stream"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn gc_status_events(
+    State(state): State<Arc<state::App>>,
+    headers: HeaderMap,
+) -> Response {
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(state.args.primary_host()),
+    };
+    if !is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let events = futures_util::stream::unfold(
+        (gc::progress::subscribe(), false),
+        |(mut receiver, finished)| async move {
+            if finished {
+                return None;
+            }
+            receiver.changed().await.ok()?;
+            let status = receiver.borrow_and_update().clone();
+            let event = Event::default().event("status").json_data(&status).ok()?;
+            Some((Ok::<_, Infallible>(event), (receiver, !status.running)))
+        },
+    );
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Get the garbage collection policy (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/gc/policy",
+    responses(
+        (status = 200, description = "Current GC policy", body = gc::GcPolicy),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn get_gc_policy(State(state): State<Arc<state::App>>, headers: HeaderMap) -> Response {
+    let host = state.args.primary_host();
+
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !is_admin(&user) {
+        return response::forbidden();
+    }
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string_pretty(&state.gc_policy.get()).unwrap(),
+        ))
+        .unwrap()
+}
+
+/// Replace the garbage collection policy used by scheduled runs and as the defaults of manual
+/// runs; omitted fields take their default (admin only)
+#[utoipa::path(
+    put,
+    path = "/admin/v1/gc/policy",
+    request_body = gc::GcPolicy,
+    responses(
+        (status = 200, description = "GC policy saved", body = gc::GcPolicy),
+        (status = 400, description = "Bad request - invalid JSON"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 500, description = "Internal server error - failed to save the policy")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn set_gc_policy(
+    State(state): State<Arc<state::App>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let host = state.args.primary_host();
+
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let policy: gc::GcPolicy = match serde_json::from_slice(&body) {
+        Ok(p) => p,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid request: {}", e)))
+                .unwrap();
+        }
+    };
+
+    if let Err(e) = state.gc_policy.set(policy.clone()) {
+        log::error!("Failed to save GC policy: {}", e);
+        return response::internal_error();
     }
+
+    log::info!("Admin {} set GC policy: {:?}", user.username, policy);
+    state.audit.record(
+        AuditEvent::new(
+            &user.username,
+            "gc.policy.update",
+            "registry",
+            Outcome::Success,
+        )
+        .with_detail(serde_json::to_string(&policy).unwrap_or_default()),
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string_pretty(&policy).unwrap()))
+        .unwrap()
 }
@@ -0,0 +1,709 @@
+//! Repository archives: a repository's manifests and blobs exported to a gzipped tarball under
+//! `--archive-dir` and removed from storage, until an admin (or a pull, with
+//! `--restore-archived-on-pull`) restores them
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, Request, State},
+    http::{HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio_util::io::SyncIoBridge;
+use utoipa::ToSchema;
+
+use crate::{
+    admin,
+    audit::{AuditEvent, Outcome},
+    auth,
+    cancel::{self, CancelToken},
+    digest::Digest,
+    dry_run::{self, DryRunQuery, DryRunResult, PlannedChange},
+    pagination::{self, PageQuery},
+    permissions, response, state,
+    storage::{self, StorageError},
+};
+
+/// Index of the archives in `--archive-dir`
+const INDEX_FILE: &str = "archives.json";
+
+/// An archived repository and what its bundle holds
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Archive {
+    /// `<org>/<repo>`
+    pub repository: String,
+    /// Path of the bundle
+    pub bundle: String,
+    /// Unix timestamp (seconds) of the archival
+    pub archived_at: u64,
+    pub archived_by: String,
+    pub tags: Vec<String>,
+    /// Manifests, by tag and by digest
+    pub manifests: usize,
+    pub blobs: usize,
+    /// Storage the blobs took, freed by the archival
+    pub bytes: u64,
+    /// Size of the compressed bundle
+    pub bundle_bytes: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ArchivesFile {
+    archives: Vec<Archive>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ArchiveList {
+    pub archives: Vec<Archive>,
+    /// `last` of the next page, absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+}
+
+#[derive(Debug)]
+pub(crate) enum ArchiveError {
+    /// Nothing stored under the repository, or no archive of it
+    NotFound,
+    AlreadyArchived,
+    Storage(StorageError),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::NotFound => write!(f, "repository not found"),
+            ArchiveError::AlreadyArchived => write!(f, "repository is already archived"),
+            ArchiveError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<StorageError> for ArchiveError {
+    fn from(e: StorageError) -> Self {
+        ArchiveError::Storage(e)
+    }
+}
+
+impl From<io::Error> for ArchiveError {
+    fn from(e: io::Error) -> Self {
+        ArchiveError::Storage(e.into())
+    }
+}
+
+/// Archived repositories, persisted to `archives.json` in `--archive-dir`
+pub(crate) struct Archives {
+    dir: PathBuf,
+    entries: RwLock<BTreeMap<String, Archive>>,
+    /// Held while a repository is archived or restored, one at a time
+    operation: tokio::sync::Mutex<()>,
+}
+
+impl Archives {
+    pub(crate) fn load(dir: &str) -> Result<Self, String> {
+        let dir = PathBuf::from(dir);
+        let path = dir.join(INDEX_FILE);
+        let entries = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str::<ArchivesFile>(&content)
+                .map_err(|e| format!("failed to parse archive index {}: {}", path.display(), e))?
+                .archives
+                .into_iter()
+                .map(|a| (a.repository.clone(), a))
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                return Err(format!(
+                    "failed to read archive index {}: {}",
+                    path.display(),
+                    e
+                ))
+            }
+        };
+
+        Ok(Self {
+            dir,
+            entries: RwLock::new(entries),
+            operation: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<Archive> {
+        self.entries.read().unwrap().get(name).cloned()
+    }
+
+    pub(crate) fn list(&self) -> Vec<Archive> {
+        self.entries.read().unwrap().values().cloned().collect()
+    }
+
+    fn bundle_path(&self, org: &str, repo: &str) -> PathBuf {
+        self.dir
+            .join(storage::sanitize_string(org))
+            .join(format!("{}.tar.gz", storage::sanitize_string(repo)))
+    }
+
+    /// Record an archive and persist the index; `persist: false` only marks the repository
+    /// archived in memory, while its bundle is being written
+    fn insert(&self, archive: Archive, persist: bool) -> io::Result<()> {
+        let mut entries = self.entries.write().unwrap();
+        entries.insert(archive.repository.clone(), archive.clone());
+        if persist {
+            if let Err(e) = self.save(&entries) {
+                entries.remove(&archive.repository);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> io::Result<()> {
+        let mut entries = self.entries.write().unwrap();
+        if let Some(archive) = entries.remove(name) {
+            if let Err(e) = self.save(&entries) {
+                entries.insert(name.to_string(), archive);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn save(&self, entries: &BTreeMap<String, Archive>) -> io::Result<()> {
+        let file = ArchivesFile {
+            archives: entries.values().cloned().collect(),
+        };
+        fs::create_dir_all(&self.dir)?;
+        fs::write(
+            self.dir.join(INDEX_FILE),
+            serde_json::to_string_pretty(&file)?,
+        )
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Write the bundle: every blob under `blobs/<algorithm>/<hex>`, then every manifest under
+/// `manifests/<reference>` with its media type under `media-types/<reference>`. Returns the
+/// total blob size.
+fn write_bundle(
+    path: &std::path::Path,
+    org: &str,
+    repo: &str,
+    references: &[String],
+    blobs: &[Digest],
+    runtime: tokio::runtime::Handle,
+    cancel: &CancelToken,
+) -> Result<u64, StorageError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut bundle = tar::Builder::new(GzEncoder::new(File::create(path)?, Compression::default()));
+
+    let mut bytes = 0;
+    for digest in blobs {
+        cancel.check()?;
+        let (reader, size) = storage::open_blob(org, repo, digest, 0)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(size);
+        header.set_mode(0o644);
+        bundle.append_data(
+            &mut header,
+            format!("blobs/{}", digest.path()),
+            SyncIoBridge::new_with_handle(reader, runtime.clone()),
+        )?;
+        bytes += size;
+    }
+    for reference in references {
+        cancel.check()?;
+        let manifest = storage::read_manifest(org, repo, reference)?;
+        let media_type = storage::manifest_meta_of(org, repo, reference, &manifest).media_type;
+        for (name, data) in [
+            (format!("manifests/{}", reference), manifest.as_slice()),
+            (format!("media-types/{}", reference), media_type.as_bytes()),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            bundle.append_data(&mut header, name, data)?;
+        }
+    }
+
+    // Content is only deleted once the bundle is safely on disk
+    bundle.into_inner()?.finish()?.sync_all()?;
+    Ok(bytes)
+}
+
+/// Reference, content and media type of a manifest read from a bundle
+type BundledManifest = (String, Vec<u8>, Option<String>);
+
+/// Import the bundle's blobs into storage, returning its manifests to write once they are in,
+/// with their media types (missing from bundles written by older versions)
+fn read_bundle(
+    path: &std::path::Path,
+    org: &str,
+    repo: &str,
+    cancel: &CancelToken,
+) -> Result<Vec<BundledManifest>, StorageError> {
+    let mut bundle = tar::Archive::new(GzDecoder::new(File::open(path)?));
+    let mut manifests = Vec::new();
+    let mut media_types = HashMap::new();
+    for entry in bundle.entries()? {
+        cancel.check()?;
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if let Some(reference) = name.strip_prefix("manifests/") {
+            let mut manifest = Vec::new();
+            entry.read_to_end(&mut manifest)?;
+            manifests.push((reference.to_string(), manifest));
+        } else if let Some(reference) = name.strip_prefix("media-types/") {
+            let mut media_type = String::new();
+            entry.read_to_string(&mut media_type)?;
+            media_types.insert(reference.to_string(), media_type);
+        } else if let Some(path) = name.strip_prefix("blobs/") {
+            let digest = Digest::parse(&path.replacen('/', ":", 1))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            storage::import_blob(org, repo, &digest, &mut entry, cancel)?;
+        }
+    }
+    Ok(manifests
+        .into_iter()
+        .map(|(reference, manifest)| {
+            let media_type = media_types.remove(&reference);
+            (reference, manifest, media_type)
+        })
+        .collect())
+}
+
+/// What archiving `org/repo` would do, checked like `archive` but without touching storage
+fn plan(state: &state::App, org: &str, repo: &str) -> Result<PlannedChange, ArchiveError> {
+    let name = format!("{}/{}", org, repo);
+    if state.archives.get(&name).is_some() {
+        return Err(ArchiveError::AlreadyArchived);
+    }
+    let references = storage::list_references(org, repo)?;
+    if references.is_empty() {
+        return Err(ArchiveError::NotFound);
+    }
+    let tags = storage::list_tags(org, repo)?;
+    let (blobs, bytes) = storage::list_repository_blobs(org, repo)?.into_iter().fold(
+        (0, 0),
+        |(blobs, bytes), digest| {
+            let size = storage::blob_metadata(org, repo, &digest).map_or(0, |m| m.size);
+            (blobs + 1, bytes + size)
+        },
+    );
+    Ok(
+        PlannedChange::new("repo.archive", &name).with_detail(format!(
+            "{} tags, {} manifests, {} blobs ({} bytes) moved to {}",
+            tags.len(),
+            references.len(),
+            blobs,
+            bytes,
+            state.archives.bundle_path(org, repo).display()
+        )),
+    )
+}
+
+/// Export a repository to its bundle, then remove it from storage
+pub(crate) async fn archive(
+    state: &Arc<state::App>,
+    org: &str,
+    repo: &str,
+    actor: &str,
+) -> Result<Archive, ArchiveError> {
+    let archives = &state.archives;
+    let _operation = archives.operation.lock().await;
+    let name = format!("{}/{}", org, repo);
+    if archives.get(&name).is_some() {
+        return Err(ArchiveError::AlreadyArchived);
+    }
+
+    let bundle = archives.bundle_path(org, repo);
+    let mut archive = Archive {
+        repository: name.clone(),
+        bundle: bundle.display().to_string(),
+        archived_at: now(),
+        archived_by: actor.to_string(),
+        tags: Vec::new(),
+        manifests: 0,
+        blobs: 0,
+        bytes: 0,
+        bundle_bytes: 0,
+    };
+    // Pulls and pushes are turned away from here on, so the bundle misses nothing
+    archives.insert(archive.clone(), false)?;
+
+    let exported = async {
+        let (references, blobs, tags) =
+            storage::blocking("list_repository", org, repo, |org, repo| {
+                io::Result::Ok((
+                    storage::list_references(org, repo)?,
+                    storage::list_repository_blobs(org, repo)?,
+                    storage::list_tags(org, repo)?,
+                ))
+            })
+            .await?;
+        if references.is_empty() {
+            return Err(ArchiveError::NotFound);
+        }
+        archive.tags = tags;
+        archive.manifests = references.len();
+        archive.blobs = blobs.len();
+
+        let (path, org, repo) = (bundle.clone(), org.to_string(), repo.to_string());
+        let runtime = tokio::runtime::Handle::current();
+        archive.bytes = cancel::run_blocking("archive_repository", move |token| {
+            write_bundle(&path, &org, &repo, &references, &blobs, runtime, token)
+        })
+        .await?;
+        archive.bundle_bytes = fs::metadata(&bundle)?.len();
+        archives.insert(archive.clone(), true)?;
+        Ok(())
+    }
+    .await;
+    if let Err(e) = exported {
+        archives.entries.write().unwrap().remove(&name);
+        let _ = fs::remove_file(&bundle);
+        return Err(e);
+    }
+
+    // Purges are journaled in the change log, so standbys drop the content too
+    storage::blocking("purge_repository", org, repo, |org, repo| {
+        let name = format!("{}/{}", org, repo);
+        for reference in storage::list_references(org, repo)? {
+            if let Err(e) = storage::purge_manifest(org, repo, &reference) {
+                log::warn!("archive: failed to remove {}:{}: {}", name, reference, e);
+            }
+        }
+        for digest in storage::list_repository_blobs(org, repo)? {
+            if let Err(e) = storage::purge_blob(org, repo, &digest) {
+                log::warn!("archive: failed to remove {}@{}: {}", name, digest, e);
+            }
+        }
+        io::Result::Ok(())
+    })
+    .await?;
+
+    log::info!(
+        "archive: archived {} to {} ({} manifests, {} blobs, {} bytes)",
+        name,
+        archive.bundle,
+        archive.manifests,
+        archive.blobs,
+        archive.bytes
+    );
+    Ok(archive)
+}
+
+/// Put an archived repository back in storage and remove its bundle
+pub(crate) async fn restore(
+    state: &Arc<state::App>,
+    org: &str,
+    repo: &str,
+) -> Result<Archive, ArchiveError> {
+    let archives = &state.archives;
+    let _operation = archives.operation.lock().await;
+    let name = format!("{}/{}", org, repo);
+    let Some(archive) = archives.get(&name) else {
+        return Err(ArchiveError::NotFound);
+    };
+
+    let bundle = PathBuf::from(&archive.bundle);
+    let (path, o, r) = (bundle.clone(), org.to_string(), repo.to_string());
+    let manifests = cancel::run_blocking("restore_repository", move |token| {
+        read_bundle(&path, &o, &r, token)
+    })
+    .await?;
+    for (reference, manifest, media_type) in manifests {
+        storage::write_manifest_bytes(org, repo, &reference, &manifest, media_type.as_deref())
+            .await?;
+    }
+
+    archives.remove(&name)?;
+    if let Err(e) = fs::remove_file(&bundle) {
+        log::warn!(
+            "archive: failed to remove bundle {}: {}",
+            bundle.display(),
+            e
+        );
+    }
+    log::info!("archive: restored {} from {}", name, archive.bundle);
+    Ok(archive)
+}
+
+/// `<org>/<repo>` of a registry API path, `/v2/<org>/<repo>/...`
+pub(crate) fn repository_of(path: &str) -> Option<String> {
+    let mut segments = path.strip_prefix("/v2/")?.split('/');
+    let (org, repo) = (segments.next()?, segments.next()?);
+    (segments.next().is_some() && org != "_catalog").then(|| format!("{}/{}", org, repo))
+}
+
+/// Turn away requests for an archived repository with an error saying how to restore it, or
+/// restore it first when pulled with `--restore-archived-on-pull`
+pub async fn archived_guard(
+    State(state): State<Arc<state::App>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(name) = repository_of(req.uri().path()) else {
+        return next.run(req).await;
+    };
+    let Some(archive) = state.archives.get(&name) else {
+        return next.run(req).await;
+    };
+
+    // Callers who may not pull get the usual 401/403 and learn nothing of the archive. Nothing
+    // reaches the handlers, so callers who may only push cannot write into the archived name.
+    let is_head = req.method() == Method::HEAD;
+    let headers = req.headers();
+    let can_pull = if state.repositories.is_public(&name) && !headers.contains_key("authorization")
+    {
+        Ok(true)
+    } else {
+        auth::authenticate_for(&state, headers, &name, permissions::Action::Pull)
+            .await
+            .map(|user| {
+                state.repositories.is_public(&name)
+                    || permissions::has_permission(&user, &name, None, permissions::Action::Pull)
+            })
+    };
+    match can_pull {
+        Ok(true) => {}
+        Ok(false) if is_head => return response::forbidden_head(),
+        Ok(false) => return response::forbidden(),
+        Err(()) => {
+            let host = state.args.primary_host();
+            let authenticated = auth::authenticate_user(&state, headers).await.is_ok();
+            return match (authenticated, is_head) {
+                (true, true) => response::forbidden_head(),
+                (true, false) => response::forbidden(),
+                (false, true) => response::unauthorized_head(host),
+                (false, false) => response::unauthorized(host),
+            };
+        }
+    }
+
+    let is_read = matches!(*req.method(), Method::GET | Method::HEAD);
+    if is_read && state.args.restore_archived_on_pull {
+        let (org, repo) = name.split_once('/').expect("repository_of joins with /");
+        match restore(&state, org, repo).await {
+            Ok(_) => {
+                state.audit.record(AuditEvent::new(
+                    "system",
+                    "repo.restore",
+                    &name,
+                    Outcome::Success,
+                ));
+                return next.run(req).await;
+            }
+            // Restored by a concurrent pull
+            Err(ArchiveError::NotFound) => return next.run(req).await,
+            Err(e) => {
+                log::error!("archive: failed to restore {} on pull: {}", name, e);
+                return response::internal_error();
+            }
+        }
+    }
+
+    response::repository_archived(&name, archive.archived_at)
+}
+
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string_pretty(body).unwrap()))
+        .unwrap()
+}
+
+/// List archived repositories (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/archives",
+    params(
+        ("n" = Option<usize>, Query, description = "Most entries to return (default and maximum: 1000)"),
+        ("last" = Option<String>, Query, description = "Return entries after this one, from `next` of the previous page")
+    ),
+    responses(
+        (status = 200, description = "Archived repositories by name", body = ArchiveList),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn list_archives(
+    State(state): State<Arc<state::App>>,
+    Query(query): Query<PageQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(state.args.primary_host()),
+    };
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let page = query.page(state.archives.list(), |a| &a.repository);
+    let link = pagination::next_link("/admin/v1/archives", &page);
+    let list = ArchiveList {
+        archives: page.items,
+        next: page.next,
+    };
+    pagination::response(serde_json::to_string_pretty(&list).unwrap(), link)
+}
+
+/// Archive a repository: export it to a compressed bundle in `--archive-dir` and remove it from
+/// storage. Pulls then get an error saying how to restore it (admin only)
+#[utoipa::path(
+    post,
+    path = "/admin/v1/repos/{org}/{repo}/archive",
+    params(
+        ("org" = String, Path, description = "Organization name"),
+        ("repo" = String, Path, description = "Repository name"),
+        ("dry_run" = Option<bool>, Query, description = "Only report what would be archived")
+    ),
+    responses(
+        (status = 201, description = "Repository archived", body = Archive),
+        (status = 200, description = "Dry run: what would be archived", body = DryRunResult),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - nothing is stored under the repository"),
+        (status = 409, description = "Conflict - repository is already archived"),
+        (status = 500, description = "Internal server error - failed to write the bundle")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn archive_repository(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo)): Path<(String, String)>,
+    Query(query): Query<DryRunQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(state.args.primary_host()),
+    };
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let name = format!("{}/{}", org, repo);
+    let result = if query.dry_run {
+        let planner = state.clone();
+        storage::blocking("plan_archive", &org, &repo, move |org, repo| {
+            plan(&planner, org, repo)
+        })
+        .await
+        .map(|change| dry_run::response(vec![change]))
+    } else {
+        archive(&state, &org, &repo, &user.username)
+            .await
+            .map(|archive| {
+                state.audit.record(AuditEvent::new(
+                    &user.username,
+                    "repo.archive",
+                    &name,
+                    Outcome::Success,
+                ));
+                json_response(StatusCode::CREATED, &archive)
+            })
+    };
+    match result {
+        Ok(response) => response,
+        Err(ArchiveError::NotFound) => response::name_unknown(&name),
+        Err(ArchiveError::AlreadyArchived) => {
+            response::conflict(&format!("repository {} is already archived", name))
+        }
+        Err(ArchiveError::Storage(e)) => {
+            log::error!("archive: failed to archive {}: {}", name, e);
+            response::internal_error()
+        }
+    }
+}
+
+/// Restore an archived repository from its bundle and remove the bundle (admin only)
+#[utoipa::path(
+    post,
+    path = "/admin/v1/repos/{org}/{repo}/restore",
+    params(
+        ("org" = String, Path, description = "Organization name"),
+        ("repo" = String, Path, description = "Repository name")
+    ),
+    responses(
+        (status = 200, description = "Repository restored", body = Archive),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - repository is not archived"),
+        (status = 500, description = "Internal server error - failed to read the bundle")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn restore_repository(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(state.args.primary_host()),
+    };
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let name = format!("{}/{}", org, repo);
+    match restore(&state, &org, &repo).await {
+        Ok(archive) => {
+            state.audit.record(AuditEvent::new(
+                &user.username,
+                "repo.restore",
+                &name,
+                Outcome::Success,
+            ));
+            json_response(StatusCode::OK, &archive)
+        }
+        Err(ArchiveError::NotFound) => response::name_unknown(&name),
+        Err(e) => {
+            log::error!("archive: failed to restore {}: {}", name, e);
+            response::internal_error()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repository_of() {
+        assert_eq!(
+            repository_of("/v2/myorg/app/manifests/v1").as_deref(),
+            Some("myorg/app")
+        );
+        assert_eq!(
+            repository_of("/v2/myorg/app/blobs/uploads/").as_deref(),
+            Some("myorg/app")
+        );
+        assert_eq!(repository_of("/v2/"), None);
+        assert_eq!(repository_of("/v2/_catalog"), None);
+        assert_eq!(repository_of("/admin/v1/repos/myorg/app/archive"), None);
+    }
+}
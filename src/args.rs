@@ -1,13 +1,548 @@
-use clap::Parser;
+use clap::{parser::ValueSource, ArgAction, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use serde_json::Value;
+use std::ffi::OsString;
+
+use crate::{access_log::TrustedProxy, webhooks};
 
 #[derive(Parser, Clone)]
 #[command(author, version, about, long_about = None)]
 pub(crate) struct Args {
-    // Web server address
-    #[arg(long, env, default_value = "0.0.0.0:8888")]
-    pub(crate) host: String,
+    // TOML or YAML file setting any of the options below by name (`max_blob_size`, or `cert`
+    // in a `[tls]` table); command-line flags and env vars take precedence
+    #[arg(long, env = "GRAIN_CONFIG")]
+    pub(crate) config: Option<String>,
+
+    // Web server addresses, e.g. 0.0.0.0:8888 and [::]:8888 for dual-stack (repeatable or
+    // comma-separated); the first is the one generated URLs point at
+    #[arg(long, env, value_delimiter = ',', default_value = "0.0.0.0:8888")]
+    pub(crate) host: Vec<String>,
+
+    // Scheme of generated URLs (Location headers, token realm); https behind a TLS-terminating proxy
+    #[arg(long, env, default_value = "http", value_parser = ["http", "https"])]
+    pub(crate) url_scheme: String,
+
+    // Public URL of the registry (e.g. https://registry.example.com) that Location headers and the
+    // token realm point at, in place of --url-scheme, forwarded headers and the request's Host
+    #[arg(long, env, value_parser = parse_external_url)]
+    pub(crate) external_url: Option<String>,
+
+    // PEM certificate chain to serve HTTPS on --host directly (reloaded on SIGHUP)
+    #[arg(long, env, requires = "tls_key")]
+    pub(crate) tls_cert: Option<String>,
+
+    // PEM private key matching --tls-cert
+    #[arg(long, env, requires = "tls_cert")]
+    pub(crate) tls_key: Option<String>,
+
+    // Also listen for plain HTTP on this address and redirect it to HTTPS (e.g. 0.0.0.0:80)
+    #[arg(long, env, requires = "tls_cert")]
+    pub(crate) tls_redirect_from: Option<String>,
 
     // Path to the users file
     #[arg(long, env, default_value = "./tmp/users.json")]
     pub(crate) users_file: String,
+
+    // Seconds between checks of the users file for out-of-band edits (0 disables reloading)
+    #[arg(long, env, default_value_t = 5)]
+    pub(crate) users_reload_interval_secs: u64,
+
+    // Path to the file of repositories declared through the admin API
+    #[arg(long, env, default_value = "./tmp/repositories.json")]
+    pub(crate) repositories_file: String,
+
+    // Path to the file of robot accounts (hashed API tokens) created through the admin API
+    #[arg(long, env, default_value = "./tmp/robots.json")]
+    pub(crate) robots_file: String,
+
+    // Path to the garbage collection policy set through the admin API
+    #[arg(long, env, default_value = "./tmp/gc-policy.json")]
+    pub(crate) gc_policy_file: String,
+
+    // Directory archived repositories are exported to, e.g. a mount on cheaper storage
+    #[arg(long, env, default_value = "./tmp/archives")]
+    pub(crate) archive_dir: String,
+
+    // Restore an archived repository when it is pulled, instead of answering with an error
+    #[arg(long, env, default_value_t = false)]
+    pub(crate) restore_archived_on_pull: bool,
+
+    // Reject pushes to repositories that were not declared through the admin API
+    #[arg(long, env, default_value_t = false)]
+    pub(crate) strict_repositories: bool,
+
+    // Require the `list` action to list tags and see repositories in the catalog (otherwise pull implies it)
+    #[arg(long, env, default_value_t = false)]
+    pub(crate) strict_list: bool,
+
+    // Storage backend for blobs, manifests and mount records: fs (./tmp) or s3
+    #[arg(long, env, default_value = "fs")]
+    pub(crate) storage_backend: String,
+
+    // S3 endpoint URL, e.g. for MinIO or R2 (defaults to AWS in --s3-region)
+    #[arg(long, env)]
+    pub(crate) s3_endpoint: Option<String>,
+
+    // S3 bucket, required with --storage-backend s3
+    #[arg(long, env)]
+    pub(crate) s3_bucket: Option<String>,
+
+    // S3 region used for request signing
+    #[arg(long, env, default_value = "us-east-1")]
+    pub(crate) s3_region: String,
+
+    // Key prefix for all objects in the bucket
+    #[arg(long, env, default_value = "")]
+    pub(crate) s3_prefix: String,
+
+    // S3 access key ID (value or file:/env:/cmd: reference)
+    #[arg(long, env, default_value = "")]
+    pub(crate) s3_access_key: String,
+
+    // S3 secret access key (value or file:/env:/cmd: reference)
+    #[arg(long, env, default_value = "")]
+    pub(crate) s3_secret_key: String,
+
+    // Minimum length for passwords set via the admin API
+    #[arg(long, env, default_value_t = 1)]
+    pub(crate) password_min_length: usize,
+
+    // Require lowercase, uppercase and numeric characters in passwords
+    #[arg(long, env, default_value_t = false)]
+    pub(crate) password_require_complexity: bool,
+
+    // Reject passwords containing the username
+    #[arg(long, env, default_value_t = false)]
+    pub(crate) password_disallow_username: bool,
+
+    // Record pull/push metrics labeled per repository
+    #[arg(long, env, default_value_t = false)]
+    pub(crate) per_repo_metrics: bool,
+
+    // Repository patterns included in per-repository metrics (comma-separated, default all)
+    #[arg(long, env, value_delimiter = ',')]
+    pub(crate) metrics_repo_allow: Vec<String>,
+
+    // Repository patterns collapsed into "other" in per-repository metrics (comma-separated)
+    #[arg(long, env, value_delimiter = ',')]
+    pub(crate) metrics_repo_deny: Vec<String>,
+
+    // Maximum distinct repository labels before new repositories are collapsed into "other"
+    #[arg(long, env, default_value_t = 100)]
+    pub(crate) metrics_max_repos: usize,
+
+    // Seconds between samples of the storage usage gauges (0 disables them); each sample lists
+    // every blob and manifest in the storage backend
+    #[arg(long, env, default_value_t = 300)]
+    pub(crate) storage_metrics_interval_secs: u64,
+
+    // OCI capabilities to turn off for a minimal surface: mount, blob-delete, referrers, catalog
+    // (comma-separated)
+    #[arg(long, env, value_delimiter = ',', value_enum)]
+    pub(crate) disable_capabilities: Vec<Capability>,
+
+    // Largest manifest body accepted, in bytes; manifests are buffered in memory
+    #[arg(long, env, default_value_t = 4 * 1024 * 1024)]
+    pub(crate) max_manifest_size: u64,
+
+    // Registry reads (GET/HEAD under /v2/) allowed per user, or per IP when anonymous,
+    // per minute (0 = unlimited)
+    #[arg(long, env, default_value_t = 0)]
+    pub(crate) rate_limit_pulls: u32,
+
+    // Registry writes (POST/PUT/PATCH/DELETE under /v2/) allowed per user, or per IP when
+    // anonymous, per minute (0 = unlimited)
+    #[arg(long, env, default_value_t = 0)]
+    pub(crate) rate_limit_pushes: u32,
+
+    // Requests with rejected credentials allowed per client IP per minute (0 = unlimited)
+    #[arg(long, env, default_value_t = 0)]
+    pub(crate) rate_limit_auth_failures: u32,
+
+    // Largest admin API request body accepted, in bytes; admin requests are small JSON documents
+    #[arg(long, env, default_value_t = 1024 * 1024)]
+    pub(crate) max_admin_body_size: u64,
+
+    // Memory for manifests and small blobs served without reading storage, in bytes (0
+    // disables the cache)
+    #[arg(long, env, default_value_t = 64 * 1024 * 1024)]
+    pub(crate) content_cache_size: u64,
+
+    // Largest blob kept in the content cache, in bytes; enough for image configs, while layers
+    // are always streamed from storage
+    #[arg(long, env, default_value_t = 64 * 1024)]
+    pub(crate) content_cache_max_blob_size: u64,
+
+    // Largest blob upload accepted, in bytes (0 = unlimited); blob bodies are streamed to disk
+    #[arg(long, env, default_value_t = 0)]
+    pub(crate) max_blob_size: u64,
+
+    // Write buffer per blob upload stream, in bytes, bounding memory per connection
+    #[arg(long, env, default_value_t = 256 * 1024)]
+    pub(crate) upload_buffer_size: usize,
+
+    // Read size per blob download chunk, in bytes; larger chunks mean fewer disk reads
+    #[arg(long, env, default_value_t = 1024 * 1024)]
+    pub(crate) download_chunk_size: usize,
+
+    // Key signing download URLs (value or file:/env:/cmd: reference); random per process if empty
+    #[arg(long, env, default_value = "")]
+    pub(crate) download_url_secret: String,
+
+    // Lifetime of download URLs created without an explicit one, in seconds
+    #[arg(long, env, default_value_t = 3600)]
+    pub(crate) download_url_ttl: u64,
+
+    // Longest lifetime an admin may give a download URL, in seconds
+    #[arg(long, env, default_value_t = 7 * 24 * 3600)]
+    pub(crate) download_url_max_ttl: u64,
+
+    // Issue bearer tokens from /token and advertise them in challenges next to Basic auth
+    #[arg(long, env, default_value_t = false)]
+    pub(crate) token_auth: bool,
+
+    // Realm URL advertised in Bearer challenges (default: <external url or request origin>/token)
+    #[arg(long, env)]
+    pub(crate) token_realm: Option<String>,
+
+    // Service name bearer tokens are issued for and checked against
+    #[arg(long, env, default_value = "grain")]
+    pub(crate) token_service: String,
+
+    // Key signing bearer tokens (value or file:/env:/cmd: reference); random per process if empty
+    #[arg(long, env, default_value = "")]
+    pub(crate) token_secret: String,
+
+    // Lifetime of issued bearer tokens, in seconds
+    #[arg(long, env, default_value_t = 300)]
+    pub(crate) token_ttl: u64,
+
+    // Clock difference tolerated when checking the expiry and not-before time of bearer and
+    // OIDC tokens, in seconds
+    #[arg(long, env, default_value_t = 60)]
+    pub(crate) token_clock_skew_secs: u64,
+
+    // JSON file of OIDC issuers (CI platforms, SSO) whose ID tokens are accepted as credentials
+    #[arg(long, env)]
+    pub(crate) oidc_trust_file: Option<String>,
+
+    // JSON file of per-repository label policies enforced on image manifest push
+    #[arg(long, env)]
+    pub(crate) push_policy_file: Option<String>,
+
+    // Labels every pushed image must carry, as key or key=value (comma-separated)
+    #[arg(long, env, value_delimiter = ',')]
+    pub(crate) require_labels: Vec<String>,
+
+    // Labels pushed images must not carry, as key or key=value (comma-separated)
+    #[arg(long, env, value_delimiter = ',')]
+    pub(crate) forbid_labels: Vec<String>,
+
+    // Tag patterns that cannot be overwritten with a different manifest in any repository, e.g.
+    // v* (comma-separated; repositories can declare their own)
+    #[arg(long, env, value_delimiter = ',')]
+    pub(crate) immutable_tags: Vec<String>,
+
+    // Most concurrent upload sessions per repository (0 = unlimited)
+    #[arg(long, env, default_value_t = 0)]
+    pub(crate) max_upload_sessions_per_repo: usize,
+
+    // Largest total size of a repository's open upload sessions, in bytes (0 = unlimited)
+    #[arg(long, env, default_value_t = 0)]
+    pub(crate) max_upload_bytes_per_repo: u64,
+
+    // Seconds an upload session may go without receiving data before it is deleted (0 = never)
+    #[arg(long, env, default_value_t = 86400)]
+    pub(crate) upload_session_ttl_secs: u64,
+
+    // Most tags per repository; pushes creating a new tag beyond it are refused (0 = unlimited)
+    #[arg(long, env, default_value_t = 0)]
+    pub(crate) max_tags_per_repo: usize,
+
+    // Most layers in an image manifest (0 = unlimited)
+    #[arg(long, env, default_value_t = 0)]
+    pub(crate) max_layers_per_manifest: usize,
+
+    // Keep serving manifests past their `grain.expires-at` annotation or admin-set expiry,
+    // until garbage collection deletes them
+    #[arg(long, env, default_value_t = false)]
+    pub(crate) serve_expired_manifests: bool,
+
+    // Seconds between runs of the job removing lapsed permissions (0 disables it)
+    #[arg(long, env, default_value_t = 3600)]
+    pub(crate) credential_cleanup_interval_secs: u64,
+
+    // Keep the trace ID of sampled W3C `traceparent` requests as exemplars of the request
+    // duration histogram, served to OpenMetrics scrapers
+    #[arg(long, env, default_value_t = false)]
+    pub(crate) trace_exemplars: bool,
+
+    // Log one JSON line per request under the `access` log target: client address, user,
+    // method, path, status, bytes in and out, duration and user agent
+    #[arg(long, env, default_value_t = false)]
+    pub(crate) access_log: bool,
+
+    // Reverse proxies (addresses or CIDR ranges, comma-separated) whose X-Forwarded-For header
+    // gives the client address in the access log, and whose Forwarded/X-Forwarded-Proto/-Host
+    // headers give the scheme and host of Location headers
+    #[arg(long, env, value_delimiter = ',')]
+    pub(crate) trusted_proxies: Vec<TrustedProxy>,
+
+    // Abort requests running longer than this many seconds (0 disables the timeout)
+    #[arg(long, env, default_value_t = 0)]
+    pub(crate) request_timeout_secs: u64,
+
+    // Primary registry URL to follow as a read-only warm standby
+    #[arg(long, env)]
+    pub(crate) standby_of: Option<String>,
+
+    // Admin username used to sync from the primary
+    #[arg(long, env, default_value = "admin")]
+    pub(crate) standby_username: String,
+
+    // Admin password used to sync from the primary (value or file:/env:/cmd: reference)
+    #[arg(long, env, default_value = "")]
+    pub(crate) standby_password: String,
+
+    // Seconds between standby sync rounds
+    #[arg(long, env, default_value_t = 10)]
+    pub(crate) standby_interval_secs: u64,
+
+    // Append audit events as JSON lines to this file, with rotation
+    #[arg(long, env)]
+    pub(crate) audit_file: Option<String>,
+
+    // Rotate the audit file once it would exceed this many bytes (0 disables size rotation)
+    #[arg(long, env, default_value_t = 10 * 1024 * 1024)]
+    pub(crate) audit_file_max_bytes: u64,
+
+    // Rotate the audit file after this many seconds (0 disables time rotation)
+    #[arg(long, env, default_value_t = 0)]
+    pub(crate) audit_file_max_age_secs: u64,
+
+    // Number of rotated audit files to keep
+    #[arg(long, env, default_value_t = 5)]
+    pub(crate) audit_file_keep: usize,
+
+    // Send audit events to syslog: host:port (UDP) or a unix socket path such as /dev/log
+    #[arg(long, env)]
+    pub(crate) audit_syslog: Option<String>,
+
+    // POST audit events as JSON to this collector URL
+    #[arg(long, env)]
+    pub(crate) audit_http_url: Option<String>,
+
+    // Bearer token for the audit HTTP collector (value or file:/env:/cmd: reference)
+    #[arg(long, env)]
+    pub(crate) audit_http_token: Option<String>,
+
+    // POST docker/distribution-style registry events to these URLs (comma-separated)
+    #[arg(long, env, value_delimiter = ',')]
+    pub(crate) webhook_urls: Vec<String>,
+
+    // Registry events sent to webhooks (comma-separated: push, pull, delete)
+    #[arg(
+        long,
+        env,
+        value_delimiter = ',',
+        value_enum,
+        default_value = "push,pull,delete"
+    )]
+    pub(crate) webhook_actions: Vec<webhooks::Action>,
+
+    // Key signing webhook bodies as `X-Grain-Signature: sha256=<HMAC>` (value or
+    // file:/env:/cmd: reference)
+    #[arg(long, env)]
+    pub(crate) webhook_secret: Option<String>,
+
+    // Retries of a failed webhook delivery, with exponential backoff from 1s
+    #[arg(long, env, default_value_t = 3)]
+    pub(crate) webhook_retries: u32,
+
+    // Copy pushed manifests and their blobs to these registries (comma-separated URLs)
+    #[arg(long, env, value_delimiter = ',')]
+    pub(crate) replicate_to: Vec<String>,
+
+    // Username used to push to the --replicate-to registries
+    #[arg(long, env, default_value = "admin")]
+    pub(crate) replication_username: String,
+
+    // Password used to push to the --replicate-to registries (value or file:/env:/cmd: reference)
+    #[arg(long, env, default_value = "")]
+    pub(crate) replication_password: String,
+}
+
+/// Optional parts of the OCI surface, turned off with `--disable-capabilities`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Capability {
+    /// Cross-repository blob mount; mount requests open a regular upload session instead
+    Mount,
+    /// `DELETE /v2/<name>/blobs/<digest>`, answered with 405
+    BlobDelete,
+    /// The referrers API, answered with 404 so clients fall back to the tag schema
+    Referrers,
+    /// `GET /v2/_catalog`, answered with 404
+    Catalog,
+}
+
+impl Args {
+    pub(crate) fn is_enabled(&self, capability: Capability) -> bool {
+        !self.disable_capabilities.contains(&capability)
+    }
+
+    /// Address generated URLs and the token realm point at: the first `--host`
+    pub(crate) fn primary_host(&self) -> &str {
+        self.host.first().map_or("0.0.0.0:8888", String::as_str)
+    }
+
+    /// Parse flags and env vars, then fill the options they leave unset from `--config`
+    pub(crate) fn load() -> Result<Args, String> {
+        let cli: Vec<OsString> = std::env::args_os().collect();
+        let matches = Args::command().get_matches_from(&cli);
+        let args = Args::from_arg_matches(&matches).map_err(|e| e.to_string())?;
+        let Some(path) = &args.config else {
+            return Ok(args);
+        };
+
+        let content =
+            std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        let config: Value = if path.ends_with(".yaml") || path.ends_with(".yml") {
+            serde_yaml_ng::from_str(&content).map_err(|e| format!("{}: {}", path, e))?
+        } else {
+            toml::from_str(&content).map_err(|e| format!("{}: {}", path, e))?
+        };
+        let flags = config_flags(&config, &matches).map_err(|e| format!("{}: {}", path, e))?;
+
+        // Options set on the command line or in the environment were skipped above
+        let argv = cli[..1]
+            .iter()
+            .cloned()
+            .chain(flags.into_iter().map(OsString::from))
+            .chain(cli[1..].iter().cloned());
+        Ok(Args::parse_from(argv))
+    }
+}
+
+/// An absolute http(s) URL, without its trailing slash
+fn parse_external_url(url: &str) -> Result<String, String> {
+    let rest = (url.strip_prefix("https://"))
+        .or_else(|| url.strip_prefix("http://"))
+        .ok_or_else(|| format!("{} is not an http:// or https:// URL", url))?;
+    if rest.trim_end_matches('/').is_empty() {
+        return Err(format!("{} has no host", url));
+    }
+    Ok(url.trim_end_matches('/').to_string())
+}
+
+/// Command-line flags equivalent to the config file's settings, leaving out options that
+/// `matches` got from the command line or the environment
+fn config_flags(config: &Value, matches: &clap::ArgMatches) -> Result<Vec<String>, String> {
+    let mut settings = Vec::new();
+    flatten(config, "", &mut settings)?;
+
+    let command = Args::command();
+    let mut flags = Vec::new();
+    for (key, value) in settings {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == key.as_str() && key != "config")
+            .ok_or_else(|| format!("unknown option `{}`", key))?;
+        if matches!(
+            matches.value_source(&key),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            continue;
+        }
+
+        let long = arg.get_long().unwrap_or(&key);
+        let value = match value {
+            Value::Null => continue,
+            Value::Bool(set) if matches!(arg.get_action(), ArgAction::SetTrue) => {
+                if set {
+                    flags.push(format!("--{}", long));
+                }
+                continue;
+            }
+            Value::String(value) => value,
+            Value::Array(items) => items
+                .iter()
+                .map(|item| match item {
+                    Value::String(item) => item.clone(),
+                    other => other.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+            other => other.to_string(),
+        };
+        flags.push(format!("--{}={}", long, value));
+    }
+    Ok(flags)
+}
+
+/// `(option, value)` pairs of a config document; nested tables prefix their keys, so
+/// `[tls] cert` sets `tls_cert`
+fn flatten(value: &Value, prefix: &str, settings: &mut Vec<(String, Value)>) -> Result<(), String> {
+    let Value::Object(table) = value else {
+        return Err("expected a table of options".to_string());
+    };
+    for (key, value) in table {
+        let key = match prefix {
+            "" => key.replace('-', "_"),
+            prefix => format!("{}_{}", prefix, key.replace('-', "_")),
+        };
+        match value {
+            Value::Object(_) => flatten(value, &key, settings)?,
+            value => settings.push((key, value.clone())),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(config: &str, cli: &[&str]) -> Result<Vec<String>, String> {
+        let matches =
+            Args::command().get_matches_from(std::iter::once("grain").chain(cli.iter().copied()));
+        let mut flags = config_flags(&toml::from_str(config).unwrap(), &matches)?;
+        flags.sort();
+        Ok(flags)
+    }
+
+    #[test]
+    fn test_config_flags() {
+        let config = r#"
+            url_scheme = "https"
+            strict-list = true
+            strict_repositories = false
+            require_labels = ["org.opencontainers.image.source", "team"]
+
+            [tls]
+            cert = "/certs/fullchain.pem"
+            key = "/certs/privkey.pem"
+        "#;
+        assert_eq!(
+            flags(config, &[]).unwrap(),
+            vec![
+                "--require-labels=org.opencontainers.image.source,team",
+                "--strict-list",
+                "--tls-cert=/certs/fullchain.pem",
+                "--tls-key=/certs/privkey.pem",
+                "--url-scheme=https",
+            ]
+        );
+
+        // Command-line flags win
+        assert_eq!(
+            flags(
+                "max_blob_size = 10\nmax_manifest_size = 20",
+                &["--max-blob-size", "5"]
+            )
+            .unwrap(),
+            vec!["--max-manifest-size=20"]
+        );
+
+        assert!(flags("no_such_option = 1", &[]).is_err());
+        assert!(flags("config = \"other.toml\"", &[]).is_err());
+    }
 }
@@ -0,0 +1,400 @@
+use serde::Serialize;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    net::UdpSocket,
+    sync::mpsc::{self, Sender},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{args::Args, middleware, secrets::Secret};
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Denied,
+    Failure,
+}
+
+/// A security-relevant action, written to every configured sink as one JSON object
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEvent {
+    pub timestamp: u64,
+    pub actor: String,
+    pub action: String,
+    pub target: String,
+    pub outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// `X-Request-Id` of the request that caused the event, absent for background jobs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn new(actor: &str, action: &str, target: &str, outcome: Outcome) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            actor: actor.to_string(),
+            action: action.to_string(),
+            target: target.to_string(),
+            outcome,
+            detail: None,
+            request_id: middleware::request_id(),
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// Destination for audit events; sinks run on the audit thread and may block
+pub trait AuditSink: Send {
+    fn name(&self) -> &'static str;
+    fn write(&mut self, event: &AuditEvent) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Writes events to the application log under the `audit` target
+struct LogSink;
+
+impl AuditSink for LogSink {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    fn write(&mut self, event: &AuditEvent) -> Result<(), Box<dyn std::error::Error>> {
+        log::info!(target: "audit", "{}", serde_json::to_string(event)?);
+        Ok(())
+    }
+}
+
+/// JSON lines file rotated by size and/or age, keeping `keep` rotated files (`<path>.1` newest)
+pub struct RotatingFileSink {
+    path: String,
+    max_bytes: u64,
+    max_age: Option<Duration>,
+    keep: usize,
+    file: Option<File>,
+    size: u64,
+    opened_at: Instant,
+}
+
+impl RotatingFileSink {
+    pub fn new(path: &str, max_bytes: u64, max_age: Option<Duration>, keep: usize) -> Self {
+        Self {
+            path: path.to_string(),
+            max_bytes,
+            max_age,
+            keep,
+            file: None,
+            size: 0,
+            opened_at: Instant::now(),
+        }
+    }
+
+    fn open(&mut self) -> std::io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = file.metadata()?.len();
+        self.opened_at = Instant::now();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn should_rotate(&self, incoming: u64) -> bool {
+        let too_big = self.max_bytes > 0 && self.size > 0 && self.size + incoming > self.max_bytes;
+        let too_old = self
+            .max_age
+            .is_some_and(|age| self.size > 0 && self.opened_at.elapsed() >= age);
+        too_big || too_old
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file = None;
+
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(format!("{}.{}", self.path, self.keep));
+            for i in (1..self.keep).rev() {
+                let from = format!("{}.{}", self.path, i);
+                if fs::metadata(&from).is_ok() {
+                    fs::rename(&from, format!("{}.{}", self.path, i + 1))?;
+                }
+            }
+            fs::rename(&self.path, format!("{}.1", self.path))?;
+        }
+
+        log::info!("audit: rotated {}", self.path);
+        self.open()
+    }
+}
+
+impl AuditSink for RotatingFileSink {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn write(&mut self, event: &AuditEvent) -> Result<(), Box<dyn std::error::Error>> {
+        let mut line = serde_json::to_string(event)?;
+        line.push('\n');
+
+        if self.file.is_none() {
+            self.open()?;
+        }
+        if self.should_rotate(line.len() as u64) {
+            self.rotate()?;
+        }
+
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(line.as_bytes())?;
+            file.flush()?;
+            self.size += line.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+/// RFC 5424 syslog over UDP (`host:port`) or a local datagram socket (absolute path, e.g. `/dev/log`)
+pub struct SyslogSink {
+    target: String,
+}
+
+// facility authpriv (10), severity notice (5)
+const SYSLOG_PRIORITY: u8 = 10 * 8 + 5;
+
+impl SyslogSink {
+    pub fn new(target: &str) -> Self {
+        Self {
+            target: target.to_string(),
+        }
+    }
+
+    fn format(event: &AuditEvent) -> Result<String, serde_json::Error> {
+        Ok(format!(
+            "<{}>1 - - grain {} {} - {}",
+            SYSLOG_PRIORITY,
+            std::process::id(),
+            event.action,
+            serde_json::to_string(event)?
+        ))
+    }
+}
+
+impl AuditSink for SyslogSink {
+    fn name(&self) -> &'static str {
+        "syslog"
+    }
+
+    fn write(&mut self, event: &AuditEvent) -> Result<(), Box<dyn std::error::Error>> {
+        let message = Self::format(event)?;
+
+        if self.target.starts_with('/') {
+            #[cfg(unix)]
+            {
+                let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                socket.send_to(message.as_bytes(), &self.target)?;
+                return Ok(());
+            }
+            #[cfg(not(unix))]
+            return Err("unix syslog sockets are not supported on this platform".into());
+        }
+
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.send_to(message.as_bytes(), &self.target)?;
+        Ok(())
+    }
+}
+
+/// POSTs each event as JSON to a collector, optionally with a bearer token
+pub struct HttpSink {
+    url: String,
+    token: Option<Secret>,
+    // Built on first use: the blocking client must not be created inside the async runtime
+    client: Option<reqwest::blocking::Client>,
+}
+
+impl HttpSink {
+    pub fn new(url: &str, token: Option<Secret>) -> Self {
+        Self {
+            url: url.to_string(),
+            token,
+            client: None,
+        }
+    }
+}
+
+impl AuditSink for HttpSink {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn write(&mut self, event: &AuditEvent) -> Result<(), Box<dyn std::error::Error>> {
+        if self.client.is_none() {
+            self.client = Some(
+                reqwest::blocking::Client::builder()
+                    .timeout(Duration::from_secs(5))
+                    .build()?,
+            );
+        }
+
+        if let Some(client) = &self.client {
+            let mut request = client.post(&self.url).json(event);
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token.get());
+            }
+            request.send()?.error_for_status()?;
+        }
+        Ok(())
+    }
+}
+
+/// Fans audit events out to all sinks on a dedicated thread so handlers never block on delivery
+pub(crate) struct Auditor {
+    sender: Sender<AuditEvent>,
+}
+
+impl Auditor {
+    pub(crate) fn new(mut sinks: Vec<Box<dyn AuditSink>>) -> Self {
+        let (sender, receiver) = mpsc::channel::<AuditEvent>();
+
+        log::info!(
+            "audit: writing events to {}",
+            sinks
+                .iter()
+                .map(|s| s.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        std::thread::Builder::new()
+            .name("audit".to_string())
+            .spawn(move || {
+                for event in receiver {
+                    for sink in sinks.iter_mut() {
+                        if let Err(e) = sink.write(&event) {
+                            log::error!("audit: {} sink failed: {}", sink.name(), e);
+                        }
+                    }
+                }
+            })
+            .expect("failed to spawn audit thread");
+
+        Self { sender }
+    }
+
+    pub(crate) fn from_args(args: &Args) -> Self {
+        let mut sinks: Vec<Box<dyn AuditSink>> = vec![Box::new(LogSink)];
+
+        if let Some(path) = &args.audit_file {
+            sinks.push(Box::new(RotatingFileSink::new(
+                path,
+                args.audit_file_max_bytes,
+                (args.audit_file_max_age_secs > 0)
+                    .then(|| Duration::from_secs(args.audit_file_max_age_secs)),
+                args.audit_file_keep,
+            )));
+        }
+
+        if let Some(target) = &args.audit_syslog {
+            sinks.push(Box::new(SyslogSink::new(target)));
+        }
+
+        if let Some(url) = &args.audit_http_url {
+            let token = args
+                .audit_http_token
+                .as_deref()
+                .map(|token| Secret::resolve_or_exit("--audit-http-token", token));
+            sinks.push(Box::new(HttpSink::new(url, token)));
+        }
+
+        Self::new(sinks)
+    }
+
+    pub(crate) fn record(&self, event: AuditEvent) {
+        if self.sender.send(event).is_err() {
+            log::error!("audit: event dropped, audit thread is not running");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event() -> AuditEvent {
+        AuditEvent::new("admin", "user.create", "alice", Outcome::Success)
+    }
+
+    #[test]
+    fn test_file_sink_rotates_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let path = path.to_str().unwrap();
+
+        let line_len = serde_json::to_string(&event()).unwrap().len() as u64 + 1;
+        let mut sink = RotatingFileSink::new(path, line_len * 2, None, 2);
+
+        for _ in 0..7 {
+            sink.write(&event()).unwrap();
+        }
+
+        let lines = |p: &str| fs::read_to_string(p).unwrap().lines().count();
+        assert_eq!(lines(path), 1);
+        assert_eq!(lines(&format!("{}.1", path)), 2);
+        assert_eq!(lines(&format!("{}.2", path)), 2);
+        assert!(fs::metadata(format!("{}.3", path)).is_err());
+    }
+
+    #[test]
+    fn test_file_sink_rotates_by_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let path = path.to_str().unwrap();
+
+        let mut sink = RotatingFileSink::new(path, 0, Some(Duration::ZERO), 1);
+        sink.write(&event()).unwrap();
+        sink.write(&event()).unwrap();
+
+        assert_eq!(fs::read_to_string(path).unwrap().lines().count(), 1);
+        assert_eq!(
+            fs::read_to_string(format!("{}.1", path))
+                .unwrap()
+                .lines()
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_syslog_format() {
+        let message = SyslogSink::format(&event().with_detail("created via admin API")).unwrap();
+        assert!(message.starts_with("<85>1 - - grain "));
+        assert!(message.contains(" user.create - {"));
+        assert!(message.contains("\"detail\":\"created via admin API\""));
+    }
+
+    #[test]
+    fn test_syslog_sink_sends_udp() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let mut sink = SyslogSink::new(&receiver.local_addr().unwrap().to_string());
+        sink.write(&event()).unwrap();
+
+        let mut buf = [0u8; 1024];
+        let len = receiver.recv(&mut buf).unwrap();
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+        assert!(message.contains("\"action\":\"user.create\""));
+    }
+}
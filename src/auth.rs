@@ -1,10 +1,15 @@
 use base64::{prelude::BASE64_STANDARD, Engine};
 use std::sync::Arc;
 
+use crate::access_log;
+use crate::audit::{AuditEvent, Outcome};
 use crate::metrics;
-use crate::permissions::{has_permission, Action};
+use crate::oidc;
+use crate::permissions::{self, has_permission, Action};
 use crate::response::unauthorized;
+use crate::robots;
 use crate::state::{self, User};
+use crate::tokens;
 use axum::{
     body::Body,
     extract::State,
@@ -25,27 +30,199 @@ fn parse_auth_header(headers: &HeaderMap) -> Option<User> {
             username: parts[0].to_string(),
             password: parts[1].to_string(),
             permissions: vec![],
+            admin: false,
+            admin_orgs: vec![],
+            roles: vec![],
+            groups: vec![],
         })
     } else {
         None
     }
 }
 
+/// Username of Basic credentials, without checking the password
+pub(crate) fn basic_username(headers: &HeaderMap) -> Option<String> {
+    parse_auth_header(headers).map(|user| user.username)
+}
+
+/// Whether the request carries an SSO ID token rather than a token issued by `/token`
+fn sso_bearer<'a>(state: &state::App, headers: &'a HeaderMap) -> Option<&'a str> {
+    tokens::bearer_token(headers).filter(|token| state.ci_identities.trusts_issuer_of(token))
+}
+
 /// Authenticate user from headers and return User object
 pub async fn authenticate_user(state: &Arc<state::App>, headers: &HeaderMap) -> Result<User, ()> {
+    let user = authenticate(state, headers).await;
+    if let Ok(user) = &user {
+        access_log::note_user(&user.username);
+    }
+    user
+}
+
+async fn authenticate(state: &Arc<state::App>, headers: &HeaderMap) -> Result<User, ()> {
+    // SSO clients send the ID token from a trusted issuer as is
+    if let Some(token) = sso_bearer(state, headers) {
+        return match state.ci_identities.authenticate(token).await {
+            Ok(identity) => Ok(identity),
+            Err(e) => {
+                tokens::record_refusal("oidc", &e);
+                metrics::AUTH_FAILURES_TOTAL.inc();
+                state.audit.record(
+                    AuditEvent::new(oidc::USERNAME, "auth.login", "registry", Outcome::Failure)
+                        .with_detail(e.to_string()),
+                );
+                Err(())
+            }
+        };
+    }
+
     let user = parse_auth_header(headers).ok_or(())?;
 
-    let users = state.users.lock().await;
-    for u in users.iter() {
-        if u.username == user.username && u.password == user.password {
-            return Ok(u.clone());
+    // CI jobs log in as `oidc` with an ID token from a trusted issuer as the password
+    if user.username == oidc::USERNAME && state.ci_identities.is_enabled() {
+        match state.ci_identities.authenticate(&user.password).await {
+            Ok(identity) => return Ok(identity),
+            Err(e) => tokens::record_refusal("oidc", &e),
+        }
+    } else if user.username.starts_with(robots::USERNAME_PREFIX) {
+        if let Some(robot) = state.robots.authenticate(&user.username, &user.password) {
+            return Ok(robot);
+        }
+    } else {
+        let found = {
+            let users = state.users.lock().await;
+            users
+                .iter()
+                .find(|u| u.username == user.username && u.password == user.password)
+                .cloned()
+        };
+        if let Some(found) = found {
+            return Ok(state.roles.lock().await.resolve(found));
         }
     }
 
     metrics::AUTH_FAILURES_TOTAL.inc();
+    state.audit.record(AuditEvent::new(
+        &user.username,
+        "auth.login",
+        "registry",
+        Outcome::Failure,
+    ));
     Err(())
 }
 
+fn anonymous() -> User {
+    User {
+        username: "anonymous".to_string(),
+        password: String::new(),
+        permissions: vec![],
+        admin: false,
+        admin_orgs: vec![],
+        roles: vec![],
+        groups: vec![],
+    }
+}
+
+/// Resolve the user a verified bearer token was issued to
+async fn bearer_user(state: &Arc<state::App>, claims: &tokens::Claims) -> Result<User, ()> {
+    if claims.is_anonymous() {
+        return Ok(anonymous());
+    }
+    // OIDC identities are not in the users file; the token carries their permissions
+    if claims.sub.starts_with(oidc::IDENTITY_PREFIX) {
+        return Ok(User {
+            username: claims.sub.clone(),
+            password: String::new(),
+            permissions: claims.permissions.clone(),
+            admin: claims.admin,
+            admin_orgs: vec![],
+            roles: vec![],
+            groups: vec![],
+        });
+    }
+    // Revoked robots and users deleted since the token was issued lose access immediately
+    if claims.sub.starts_with(robots::USERNAME_PREFIX) {
+        return state.robots.user(&claims.sub).ok_or(());
+    }
+    let user = {
+        let users = state.users.lock().await;
+        users.iter().find(|u| u.username == claims.sub).cloned()
+    };
+    let user = user.ok_or(())?;
+    Ok(state.roles.lock().await.resolve(user))
+}
+
+/// Authenticate the caller with a bearer token from /token, an SSO ID token or Basic
+/// credentials, returning the bearer token's claims along with the user
+async fn authenticate_request(
+    state: &Arc<state::App>,
+    headers: &HeaderMap,
+) -> Result<(User, Option<tokens::Claims>), ()> {
+    match tokens::bearer_token(headers) {
+        Some(token) if sso_bearer(state, headers).is_none() => {
+            let claims = tokens::verify_bearer(state, token)?;
+            let user = bearer_user(state, &claims).await?;
+            access_log::note_user(&user.username);
+            Ok((user, Some(claims)))
+        }
+        _ => Ok((authenticate_user(state, headers).await?, None)),
+    }
+}
+
+/// Authenticate the caller of `action` on `repository`, without checking permissions; a bearer
+/// token must cover the action
+pub(crate) async fn authenticate_for(
+    state: &Arc<state::App>,
+    headers: &HeaderMap,
+    repository: &str,
+    action: Action,
+) -> Result<User, ()> {
+    let (user, claims) = authenticate_request(state, headers).await?;
+    // The token must cover the action; the user's own permissions still apply
+    if claims.is_some_and(|claims| !claims.grants(repository, action)) {
+        log::warn!(
+            "Bearer token of {} does not cover {} on {}",
+            user.username,
+            action.as_str(),
+            repository
+        );
+        return Err(());
+    }
+    Ok(user)
+}
+
+/// The repositories among `repositories` the caller may pull from, authenticating once and
+/// recording no denials for the others
+pub(crate) async fn pullable(
+    state: &Arc<state::App>,
+    headers: &HeaderMap,
+    repositories: Vec<String>,
+) -> Vec<String> {
+    let anonymous = !headers.contains_key("authorization");
+    let caller = if anonymous {
+        None
+    } else {
+        authenticate_request(state, headers).await.ok()
+    };
+
+    repositories
+        .into_iter()
+        .filter(|repository| {
+            let public = state.repositories.is_public(repository);
+            if anonymous {
+                return public;
+            }
+            let Some((user, claims)) = &caller else {
+                return false;
+            };
+            claims
+                .as_ref()
+                .is_none_or(|claims| claims.grants(repository, Action::Pull))
+                && (public || has_permission(user, repository, None, Action::Pull))
+        })
+        .collect()
+}
+
 /// Check if authenticated user has permission for the action
 pub async fn check_permission(
     state: &Arc<state::App>,
@@ -54,11 +231,21 @@ pub async fn check_permission(
     tag: Option<&str>,
     action: Action,
 ) -> Result<User, ()> {
-    // First authenticate
-    let user = authenticate_user(state, headers).await?;
+    let public_pull =
+        matches!(action, Action::Pull | Action::List) && state.repositories.is_public(repository);
+
+    // Public repositories can be pulled without credentials
+    if public_pull && !headers.contains_key("authorization") {
+        return Ok(anonymous());
+    }
 
-    // Then check permission
-    if has_permission(&user, repository, tag, action) {
+    // First authenticate, then check permission
+    let user = authenticate_for(state, headers, repository, action).await?;
+    let permitted = match action {
+        Action::List => permissions::can_list(&user, repository, state.args.strict_list),
+        _ => has_permission(&user, repository, tag, action),
+    };
+    if public_pull || permitted {
         Ok(user)
     } else {
         log::warn!(
@@ -69,6 +256,12 @@ pub async fn check_permission(
             tag.unwrap_or("*")
         );
         metrics::PERMISSION_DENIALS_TOTAL.inc();
+        state.audit.record(AuditEvent::new(
+            &user.username,
+            &format!("registry.{}", action.as_str()),
+            &format!("{}:{}", repository, tag.unwrap_or("*")),
+            Outcome::Denied,
+        ));
         Err(())
     }
 }
@@ -76,7 +269,17 @@ pub async fn check_permission(
 pub(crate) async fn get(State(data): State<Arc<state::App>>, headers: HeaderMap) -> Response<Body> {
     log::info!("Incoming request headers: {:?}", headers);
 
-    match authenticate_user(&data, &headers).await {
+    let authenticated = match tokens::bearer_token(&headers) {
+        Some(token) if sso_bearer(&data, &headers).is_none() => {
+            match tokens::verify_bearer(&data, token) {
+                Ok(claims) => bearer_user(&data, &claims).await,
+                Err(()) => Err(()),
+            }
+        }
+        _ => authenticate_user(&data, &headers).await,
+    };
+
+    match authenticated {
         Ok(user) => {
             log::info!("User {} authenticated successfully", user.username);
             Response::builder()
@@ -86,7 +289,7 @@ pub(crate) async fn get(State(data): State<Arc<state::App>>, headers: HeaderMap)
         }
         Err(_) => {
             log::warn!("Authentication failed");
-            unauthorized(&data.args.host)
+            unauthorized(data.args.primary_host())
         }
     }
 }
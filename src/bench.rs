@@ -0,0 +1,317 @@
+//! Synthetic load against a registry, used by `grainctl bench`
+//!
+//! Blobs are generated from a seeded pseudo-random stream, so they are unique per run, never
+//! deduplicated by the registry, and hashed and uploaded without being held in memory.
+
+use reqwest::blocking::Body;
+use serde::Serialize;
+use sha2::{Digest as _, Sha256};
+use std::{
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::sync::{Registry, SyncError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchMode {
+    /// Upload new blobs
+    Push,
+    /// Download blobs, pushing one per worker first
+    Pull,
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    pub repository: String,
+    /// Bytes per blob
+    pub size: u64,
+    /// Requests in flight at once
+    pub concurrency: usize,
+    /// Blobs pushed or pulled in total
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LatencySummary {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BenchStats {
+    pub operations: usize,
+    pub errors: usize,
+    /// First failure, when there were errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_error: Option<String>,
+    pub bytes: u64,
+    pub duration_seconds: f64,
+    pub operations_per_second: f64,
+    pub megabytes_per_second: f64,
+    /// Per-blob request latency in milliseconds, of successful operations
+    pub latency_ms: LatencySummary,
+}
+
+/// Parse a size such as `100MB`, `512KiB` or `1048576` (bytes). Decimal units are powers of
+/// 1000 and binary units powers of 1024.
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size: {}", input))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" | "K" => 1000,
+        "MB" | "M" => 1000 * 1000,
+        "GB" | "G" => 1000 * 1000 * 1000,
+        "KIB" => 1 << 10,
+        "MIB" => 1 << 20,
+        "GIB" => 1 << 30,
+        _ => return Err(format!("invalid size unit: {}", unit)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Deterministic pseudo-random content (xorshift64*) of a fixed length
+struct SyntheticBlob {
+    state: u64,
+    remaining: u64,
+}
+
+impl SyntheticBlob {
+    fn new(seed: u64, size: u64) -> Self {
+        Self {
+            // xorshift must not start from zero
+            state: seed | 1,
+            remaining: size,
+        }
+    }
+
+    fn digest(seed: u64, size: u64) -> String {
+        let mut hasher = Sha256::new();
+        let mut blob = Self::new(seed, size);
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = blob.read(&mut buffer).unwrap_or(0);
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        format!("sha256:{:x}", hasher.finalize())
+    }
+}
+
+impl Read for SyntheticBlob {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.remaining as usize);
+        for chunk in buf[..len].chunks_mut(8) {
+            self.state ^= self.state >> 12;
+            self.state ^= self.state << 25;
+            self.state ^= self.state >> 27;
+            let bytes = self.state.wrapping_mul(0x2545_F491_4F6C_DD1D).to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+        self.remaining -= len as u64;
+        Ok(len)
+    }
+}
+
+/// Upload the synthetic blob of `seed`, whose digest was computed beforehand
+fn push(
+    registry: &Registry,
+    repository: &str,
+    seed: u64,
+    size: u64,
+    digest: &str,
+) -> Result<u64, SyncError> {
+    let body = Body::sized(SyntheticBlob::new(seed, size), size);
+    registry.put_blob(repository, digest, body)?;
+    Ok(size)
+}
+
+/// Download a blob to the end, returning its size
+fn pull(registry: &Registry, repository: &str, digest: &str) -> Result<u64, SyncError> {
+    Ok(registry
+        .blob(repository, digest)?
+        .copy_to(&mut io::sink())?)
+}
+
+/// Value below which `quantile` of the sorted `values` fall (nearest rank)
+fn percentile(sorted: &[Duration], quantile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((quantile * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1].as_secs_f64() * 1000.0
+}
+
+struct Outcome {
+    latencies: Vec<Duration>,
+    bytes: u64,
+    errors: usize,
+    first_error: Option<String>,
+}
+
+/// Run a benchmark, calling `progress` with the number of operations done after each one
+pub fn run(
+    registry: &Registry,
+    mode: BenchMode,
+    options: &BenchOptions,
+    progress: impl Fn(usize) + Sync,
+) -> Result<BenchStats, SyncError> {
+    let concurrency = options.concurrency.max(1);
+    // Seeds differ between runs so pushes always upload new content
+    let run_seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    let seed = |index: usize| run_seed.wrapping_add((index as u64).wrapping_mul(0x9E37_79B9));
+
+    // Pulls read back blobs pushed up front, one per worker, outside of the measurement
+    let pull_digests = match mode {
+        BenchMode::Push => Vec::new(),
+        BenchMode::Pull => (0..concurrency)
+            .map(|worker| {
+                let digest = SyntheticBlob::digest(seed(worker), options.size);
+                push(
+                    registry,
+                    &options.repository,
+                    seed(worker),
+                    options.size,
+                    &digest,
+                )
+                .map(|_| digest)
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+
+    let next = AtomicUsize::new(0);
+    let outcome = Mutex::new(Outcome {
+        latencies: Vec::with_capacity(options.count),
+        bytes: 0,
+        errors: 0,
+        first_error: None,
+    });
+    let started = Instant::now();
+    std::thread::scope(|scope| {
+        for worker in 0..concurrency {
+            let (next, outcome, progress, pull_digests) =
+                (&next, &outcome, &progress, &pull_digests);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= options.count {
+                    break;
+                }
+                // Hashing is client work, so it stays out of the measured latency
+                let seed = seed(concurrency + index);
+                let digest = match mode {
+                    BenchMode::Push => SyntheticBlob::digest(seed, options.size),
+                    BenchMode::Pull => pull_digests[worker].clone(),
+                };
+                let request_started = Instant::now();
+                let result = match mode {
+                    BenchMode::Push => {
+                        push(registry, &options.repository, seed, options.size, &digest)
+                    }
+                    BenchMode::Pull => pull(registry, &options.repository, &digest),
+                };
+                let elapsed = request_started.elapsed();
+
+                let mut outcome = outcome.lock().unwrap();
+                match result {
+                    Ok(bytes) => {
+                        outcome.latencies.push(elapsed);
+                        outcome.bytes += bytes;
+                    }
+                    Err(e) => {
+                        outcome.errors += 1;
+                        outcome.first_error.get_or_insert_with(|| e.to_string());
+                    }
+                }
+                let done = outcome.latencies.len() + outcome.errors;
+                drop(outcome);
+                progress(done);
+            });
+        }
+    });
+    let duration = started.elapsed().as_secs_f64();
+
+    let mut outcome = outcome.into_inner().unwrap();
+    outcome.latencies.sort();
+    let per_second = |value: f64| {
+        if duration > 0.0 {
+            value / duration
+        } else {
+            0.0
+        }
+    };
+    Ok(BenchStats {
+        operations: outcome.latencies.len(),
+        errors: outcome.errors,
+        first_error: outcome.first_error,
+        bytes: outcome.bytes,
+        duration_seconds: duration,
+        operations_per_second: per_second(outcome.latencies.len() as f64),
+        megabytes_per_second: per_second(outcome.bytes as f64 / 1_000_000.0),
+        latency_ms: LatencySummary {
+            p50: percentile(&outcome.latencies, 0.50),
+            p90: percentile(&outcome.latencies, 0.90),
+            p99: percentile(&outcome.latencies, 0.99),
+            max: percentile(&outcome.latencies, 1.0),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("100MB"), Ok(100_000_000));
+        assert_eq!(parse_size("1.5 KiB"), Ok(1536));
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("2g"), Ok(2_000_000_000));
+        assert!(parse_size("10 parsecs").is_err());
+        assert!(parse_size("MB").is_err());
+    }
+
+    #[test]
+    fn test_synthetic_blob() {
+        let read_all = |seed| {
+            let mut content = Vec::new();
+            SyntheticBlob::new(seed, 1001)
+                .read_to_end(&mut content)
+                .unwrap();
+            content
+        };
+        assert_eq!(read_all(7).len(), 1001);
+        assert_eq!(read_all(7), read_all(7));
+        assert_ne!(read_all(7), read_all(8));
+        assert_eq!(
+            SyntheticBlob::digest(7, 1001),
+            format!("sha256:{}", sha256::digest(read_all(7)))
+        );
+    }
+
+    #[test]
+    fn test_percentile() {
+        let latencies: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&latencies, 0.5), 50.0);
+        assert_eq!(percentile(&latencies, 0.99), 99.0);
+        assert_eq!(percentile(&latencies, 1.0), 100.0);
+        assert_eq!(percentile(&[], 0.5), 0.0);
+    }
+}
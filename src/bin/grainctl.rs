@@ -1,33 +1,191 @@
-use clap::{Parser, Subcommand};
-use reqwest::blocking::Client;
+use clap::{Parser, Subcommand, ValueEnum};
+use grain::bench::{self, BenchMode, BenchOptions};
+use grain::client::{
+    AccessGroup, AccessRole, AdminClient, CreateDownloadUrlRequest, CreateRobotRequest,
+    CreateUserRequest, DownloadKind, GcRunOptions, Permission, PlannedChange, SetTagExpiryRequest,
+    UntaggedManifests,
+};
+use grain::contexts::{self, Config, Context};
+use grain::sync::{Mirror, Registry, SyncOptions};
 use serde_json::json;
-use std::process;
+use std::{env, io::Read, process};
 
 #[derive(Parser)]
 #[command(name = "grainctl")]
 #[command(about = "CLI tool for administering the grain OCI registry", long_about = None)]
 #[command(version)]
 struct Cli {
+    /// Context saved by `grainctl login` to take --url, --username and --password from
+    /// (default: the last context logged in to)
+    #[arg(long, global = true, env = "GRAIN_CONTEXT")]
+    context: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Check credentials and save them as a context used by later commands
+    Login {
+        /// Context name
+        #[arg(default_value = "default")]
+        name: String,
+
+        #[arg(long)]
+        url: String,
+
+        #[arg(long)]
+        username: String,
+
+        #[arg(
+            long,
+            conflicts_with = "password_stdin",
+            required_unless_present = "password_stdin"
+        )]
+        password: Option<String>,
+
+        /// Read the password from stdin
+        #[arg(long)]
+        password_stdin: bool,
+    },
+
+    /// Remove a saved context and its credentials
+    Logout {
+        /// Context name (default: the current context)
+        name: Option<String>,
+    },
+
+    /// Change your own password; saved contexts using it are updated
+    Passwd {
+        #[arg(
+            long,
+            conflicts_with = "new_password_stdin",
+            required_unless_present = "new_password_stdin"
+        )]
+        new_password: Option<String>,
+
+        /// Read the new password from stdin
+        #[arg(long)]
+        new_password_stdin: bool,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        /// Current password
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
     /// User management
     User {
         #[command(subcommand)]
         command: UserCommands,
     },
 
+    /// Robot accounts: revocable API tokens for CI pipelines
+    Robot {
+        #[command(subcommand)]
+        command: RobotCommands,
+    },
+
+    /// Roles: named permission sets users and groups reference
+    Role {
+        #[command(subcommand)]
+        command: RoleCommands,
+    },
+
+    /// Groups: sets of roles and permissions shared by their members
+    Group {
+        #[command(subcommand)]
+        command: GroupCommands,
+    },
+
+    /// Repository management
+    Repo {
+        #[command(subcommand)]
+        command: RepoCommands,
+    },
+
+    /// Image management
+    Image {
+        #[command(subcommand)]
+        command: ImageCommands,
+    },
+
+    /// Tag listing
+    Tag {
+        #[command(subcommand)]
+        command: TagCommands,
+    },
+
     /// Run garbage collection
     Gc {
-        #[arg(long, default_value = "false")]
-        dry_run: bool,
+        /// Only report what would be deleted (default: server GC policy)
+        #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+        dry_run: Option<bool>,
+
+        /// Hours before unreferenced blobs may be deleted (default: server GC policy)
+        #[arg(long)]
+        grace_period_hours: Option<u64>,
+
+        /// Keep or delete manifests no tag reaches (default: server GC policy)
+        #[arg(long, value_parser = parse_untagged_manifests)]
+        untagged_manifests: Option<UntaggedManifests>,
+
+        /// Only delete tags, manifests and blobs of this repository (org/repo)
+        #[arg(long)]
+        repository: Option<String>,
+
+        /// Start the run and return at once; check on it with `grainctl gc-status`
+        #[arg(long)]
+        background: bool,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// Show the progress of the current garbage collection run, or the result of the last one
+    GcStatus {
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// Show or change the garbage collection policy
+    GcPolicy {
+        #[command(subcommand)]
+        command: GcPolicyCommands,
+    },
+
+    /// Show the instance ID, role and replication peers of a registry
+    Cluster {
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
 
-        #[arg(long, default_value = "24")]
-        grace_period_hours: u64,
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
 
+    /// Show the replication queue and lag of each --replicate-to peer
+    Replication {
         #[arg(long, env = "GRAIN_URL")]
         url: String,
 
@@ -37,6 +195,71 @@ enum Commands {
         #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
         password: String,
     },
+
+    /// Mirror repositories and tags from one registry to another, copying only what is missing
+    Sync {
+        /// Source registry URL
+        source: String,
+
+        /// Destination registry URL
+        destination: String,
+
+        /// Repositories to mirror, comma-separated patterns (e.g. "team/*")
+        #[arg(long, value_delimiter = ',', default_value = "*")]
+        repos: Vec<String>,
+
+        /// Tags to mirror, comma-separated patterns (e.g. "v*")
+        #[arg(long, value_delimiter = ',', default_value = "*")]
+        tags: Vec<String>,
+
+        /// Only report what would be copied
+        #[arg(long)]
+        dry_run: bool,
+
+        #[arg(long, env = "GRAIN_SYNC_SOURCE_USER")]
+        source_username: Option<String>,
+
+        #[arg(long, env = "GRAIN_SYNC_SOURCE_PASSWORD")]
+        source_password: Option<String>,
+
+        #[arg(long, env = "GRAIN_SYNC_DESTINATION_USER")]
+        destination_username: Option<String>,
+
+        #[arg(long, env = "GRAIN_SYNC_DESTINATION_PASSWORD")]
+        destination_password: Option<String>,
+    },
+
+    /// Push or pull synthetic blobs and report throughput and latency percentiles
+    Bench {
+        /// push or pull
+        #[arg(value_parser = parse_bench_mode)]
+        mode: BenchMode,
+
+        /// Size of each blob (e.g. 100MB, 512KiB)
+        #[arg(long, value_parser = bench::parse_size, default_value = "10MB")]
+        size: u64,
+
+        /// Requests in flight at once
+        #[arg(long, default_value_t = 4)]
+        concurrency: usize,
+
+        /// Blobs pushed or pulled in total
+        #[arg(long, default_value_t = 32)]
+        count: usize,
+
+        /// Repository receiving the synthetic blobs
+        #[arg(long, default_value = "bench/grainctl")]
+        repository: String,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: Option<String>,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -62,6 +285,46 @@ enum UserCommands {
         #[arg(long)]
         pass: String,
 
+        /// Allow the user to use the admin API (repository permissions are granted separately)
+        #[arg(long)]
+        admin: bool,
+
+        /// Org whose accounts, permissions and garbage collection the user administers
+        /// (repeatable)
+        #[arg(long = "admin-org")]
+        admin_orgs: Vec<String>,
+
+        /// Permission as <repository>:<tag>:<actions>, e.g. "myorg/*:*:pull,push" (repeatable)
+        #[arg(long = "permission", value_parser = parse_permission)]
+        permissions: Vec<Permission>,
+
+        /// Role whose permissions the user gets (repeatable)
+        #[arg(long = "role")]
+        roles: Vec<String>,
+
+        /// Group the user joins (repeatable)
+        #[arg(long = "group")]
+        groups: Vec<String>,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// Change a user's password, keeping their permissions (your own, or anyone's as admin)
+    SetPassword {
+        /// Username whose password changes
+        user: String,
+
+        /// New password
+        #[arg(long)]
+        pass: String,
+
         #[arg(long, env = "GRAIN_URL")]
         url: String,
 
@@ -77,6 +340,10 @@ enum UserCommands {
         /// Username to delete
         user: String,
 
+        /// Only show what would be deleted
+        #[arg(long)]
+        dry_run: bool,
+
         #[arg(long, env = "GRAIN_URL")]
         url: String,
 
@@ -104,6 +371,10 @@ enum UserCommands {
         #[arg(long)]
         actions: String,
 
+        /// Unix timestamp (seconds) after which the permission lapses
+        #[arg(long)]
+        expires_at: Option<u64>,
+
         #[arg(long, env = "GRAIN_URL")]
         url: String,
 
@@ -113,178 +384,1442 @@ enum UserCommands {
         #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
         password: String,
     },
-}
-
-fn main() {
-    let cli = Cli::parse();
+    /// Revoke a permission from a user
+    RemovePermission {
+        /// Target username
+        user: String,
 
-    if let Err(e) = execute_command(&cli.command) {
-        eprintln!("Error: {}", e);
-        process::exit(1);
-    }
-}
+        /// Repository pattern, exactly as granted
+        #[arg(long)]
+        repository: String,
 
-fn execute_command(cmd: &Commands) -> Result<(), Box<dyn std::error::Error>> {
-    match cmd {
-        Commands::User { command } => execute_user_command(command),
-        Commands::Gc {
-            dry_run,
-            grace_period_hours,
-            url,
-            username,
-            password,
-        } => execute_gc_command(*dry_run, *grace_period_hours, url, username, password),
-    }
-}
+        /// Tag pattern, exactly as granted
+        #[arg(long)]
+        tag: String,
 
-fn execute_user_command(cmd: &UserCommands) -> Result<(), Box<dyn std::error::Error>> {
-    let client = Client::new();
+        /// Actions to revoke (comma-separated); all of them when omitted
+        #[arg(long)]
+        actions: Option<String>,
 
-    match cmd {
-        UserCommands::List {
-            url,
-            username,
-            password,
-        } => {
-            let response = client
-                .get(format!("{}/admin/users", url))
-                .basic_auth(username, Some(password))
-                .send()?;
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
 
-            if !response.status().is_success() {
-                let status = response.status();
-                let text = response
-                    .text()
-                    .unwrap_or_else(|_| String::from("No response body"));
-                return Err(format!("{} - {}", status, text).into());
-            }
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
 
-            let users: serde_json::Value = response.json()?;
-            println!("{}", serde_json::to_string_pretty(&users)?);
-            Ok(())
-        }
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+}
 
-        UserCommands::Create {
-            user,
-            pass,
-            url,
-            username,
-            password,
-        } => {
-            let body = json!({
-                "username": user,
-                "password": pass,
-                "permissions": []
-            });
+#[derive(Subcommand)]
+enum RoleCommands {
+    /// List roles and groups
+    List {
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
 
-            let response = client
-                .post(format!("{}/admin/users", url))
-                .basic_auth(username, Some(password))
-                .json(&body)
-                .send()?;
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
 
-            if !response.status().is_success() {
-                let status = response.status();
-                let text = response
-                    .text()
-                    .unwrap_or_else(|_| String::from("No response body"));
-                return Err(format!("{} - {}", status, text).into());
-            }
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
 
-            println!("User '{}' created successfully", user);
-            Ok(())
-        }
+    /// Create or replace a role
+    Set {
+        /// Role name (letters, digits, ".", "_" and "-")
+        name: String,
 
-        UserCommands::Delete {
-            user,
-            url,
-            username,
-            password,
-        } => {
-            let response = client
-                .delete(format!("{}/admin/users/{}", url, user))
-                .basic_auth(username, Some(password))
-                .send()?;
+        /// Permission as <repository>:<tag>:<actions>, e.g. "myorg/*:*:pull,push" (repeatable)
+        #[arg(long = "permission", value_parser = parse_permission)]
+        permissions: Vec<Permission>,
 
-            if !response.status().is_success() {
-                let status = response.status();
-                let text = response
-                    .text()
-                    .unwrap_or_else(|_| String::from("No response body"));
-                return Err(format!("{} - {}", status, text).into());
-            }
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
 
-            println!("User '{}' deleted successfully", user);
-            Ok(())
-        }
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
 
-        UserCommands::AddPermission {
-            user,
-            repository,
-            tag,
-            actions,
-            url,
-            username,
-            password,
-        } => {
-            let actions_vec: Vec<String> =
-                actions.split(',').map(|s| s.trim().to_string()).collect();
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
 
-            let body = json!({
-                "repository": repository,
-                "tag": tag,
-                "actions": actions_vec
-            });
+    /// Delete a role no user or group references
+    Delete {
+        name: String,
 
-            let response = client
-                .post(format!("{}/admin/users/{}/permissions", url, user))
-                .basic_auth(username, Some(password))
-                .json(&body)
-                .send()?;
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
 
-            if !response.status().is_success() {
-                let status = response.status();
-                let text = response
-                    .text()
-                    .unwrap_or_else(|_| String::from("No response body"));
-                return Err(format!("{} - {}", status, text).into());
-            }
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
 
-            println!(
-                "Permission added to user '{}': {} on {}:{}",
-                user, actions, repository, tag
-            );
-            Ok(())
-        }
-    }
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
 }
 
-fn execute_gc_command(
-    dry_run: bool,
-    grace_period_hours: u64,
-    url: &str,
-    username: &str,
-    password: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let client = Client::new();
-
-    let response = client
-        .post(format!(
-            "{}/admin/gc?dry_run={}&grace_period_hours={}",
-            url, dry_run, grace_period_hours
-        ))
-        .basic_auth(username, Some(password))
-        .send()?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let text = response
-            .text()
-            .unwrap_or_else(|_| String::from("No response body"));
-        return Err(format!("{} - {}", status, text).into());
-    }
+#[derive(Subcommand)]
+enum GroupCommands {
+    /// Create or replace a group
+    Set {
+        /// Group name (letters, digits, ".", "_" and "-")
+        name: String,
 
-    let stats: serde_json::Value = response.json()?;
-    println!("{}", serde_json::to_string_pretty(&stats)?);
-    Ok(())
+        /// Role granted to members (repeatable)
+        #[arg(long = "role")]
+        roles: Vec<String>,
+
+        /// Permission as <repository>:<tag>:<actions> granted to members (repeatable)
+        #[arg(long = "permission", value_parser = parse_permission)]
+        permissions: Vec<Permission>,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// Delete a group without members
+    Delete {
+        name: String,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum RobotCommands {
+    /// List robot accounts
+    List {
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// Create a robot and print its token, which is not shown again
+    Create {
+        /// Robot name (letters, digits, ".", "_" and "-")
+        name: String,
+
+        /// Repository pattern (e.g., "myorg/myrepo" or "myorg/*")
+        #[arg(long)]
+        repository: String,
+
+        /// Tag pattern (e.g., "latest" or "v*")
+        #[arg(long, default_value = "*")]
+        tag: String,
+
+        /// Actions (comma-separated: pull,push,delete)
+        #[arg(long)]
+        actions: String,
+
+        #[arg(long, default_value = "")]
+        description: String,
+
+        /// Unix timestamp (seconds) after which the token is refused
+        #[arg(long)]
+        expires_at: Option<u64>,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// Revoke a robot's token
+    Revoke {
+        /// Robot name
+        name: String,
+
+        /// Only show what would be revoked
+        #[arg(long)]
+        dry_run: bool,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum RepoCommands {
+    /// List repositories visible to the user (from /v2/_catalog)
+    List {
+        #[arg(long, value_enum, default_value = "table")]
+        output: OutputFormat,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: Option<String>,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: Option<String>,
+    },
+
+    /// Mint a pull-only robot for a repository and print it as a Kubernetes image pull secret
+    PullSecret {
+        /// Repository (org/repo)
+        repository: String,
+
+        /// Secret and robot name (default: grain-<org>-<repo>-pull)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Kubernetes namespace for the secret
+        #[arg(long)]
+        namespace: Option<String>,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// Print a time-limited URL downloading a blob or an image without credentials
+    Share {
+        /// Repository (org/repo)
+        repository: String,
+
+        /// Blob digest to share
+        #[arg(long, conflicts_with = "image", required_unless_present = "image")]
+        blob: Option<String>,
+
+        /// Image tag or digest to share as an OCI image layout tarball
+        #[arg(long)]
+        image: Option<String>,
+
+        /// Lifetime of the URL in seconds (default: server --download-url-ttl)
+        #[arg(long)]
+        expires_in: Option<u64>,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// List the pinned tags of a repository
+    Pins {
+        /// Repository (org/repo)
+        repository: String,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// Pin a tag so the retention policy never deletes it
+    Pin {
+        /// Repository (org/repo)
+        repository: String,
+
+        /// Tag to pin
+        tag: String,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// Unpin a tag
+    Unpin {
+        /// Repository (org/repo)
+        repository: String,
+
+        /// Tag to unpin
+        tag: String,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// Make a tag expire: past the expiry it is no longer served and GC deletes it. Pushing the
+    /// tag again clears the expiry.
+    Expire {
+        /// Repository (org/repo)
+        repository: String,
+
+        /// Tag to expire
+        tag: String,
+
+        /// Seconds from now
+        #[arg(long, conflicts_with = "at", required_unless_present = "at")]
+        ttl: Option<u64>,
+
+        /// Unix timestamp (seconds)
+        #[arg(long)]
+        at: Option<u64>,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// Remove the expiry set on a tag
+    Unexpire {
+        /// Repository (org/repo)
+        repository: String,
+
+        /// Tag whose expiry is removed
+        tag: String,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// Export a repository to a compressed bundle in the server's archive directory and remove
+    /// it from storage
+    Archive {
+        /// Repository (org/repo)
+        repository: String,
+
+        /// Only show what would be archived
+        #[arg(long)]
+        dry_run: bool,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// Restore an archived repository
+    Restore {
+        /// Repository (org/repo)
+        repository: String,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// List archived repositories
+    Archives {
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ImageCommands {
+    /// Delete an image (org/repo:tag or org/repo@digest) with every tag pointing to it
+    Delete {
+        /// Image reference: org/repo:tag or org/repo@sha256:<hex>
+        image: String,
+
+        /// Run garbage collection on the repository afterwards, deleting blobs no manifest
+        /// references any more (needs admin credentials)
+        #[arg(long)]
+        prune_blobs: bool,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: Option<String>,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagCommands {
+    /// List the tags of a repository (from /v2/<repo>/tags/list)
+    List {
+        /// Repository (org/repo)
+        repository: String,
+
+        #[arg(long, value_enum, default_value = "table")]
+        output: OutputFormat,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: Option<String>,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: Option<String>,
+    },
+}
+
+/// How listings are printed
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// The registry's JSON response
+    Json,
+    /// One name per line under a header
+    Table,
+}
+
+#[derive(Subcommand)]
+enum GcPolicyCommands {
+    /// Print the current policy
+    Show {
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+
+    /// Change the given settings, keeping the others
+    Set {
+        /// Hours an unreferenced blob or untagged manifest is kept
+        #[arg(long)]
+        grace_period_hours: Option<u64>,
+
+        /// Minutes between scheduled runs (0 disables scheduled runs)
+        #[arg(long)]
+        schedule_interval_minutes: Option<u64>,
+
+        /// Keep or delete manifests no tag reaches
+        #[arg(long, value_parser = parse_untagged_manifests)]
+        untagged_manifests: Option<UntaggedManifests>,
+
+        /// Whether runs only report what they would delete
+        #[arg(long)]
+        dry_run: Option<bool>,
+
+        #[arg(long, env = "GRAIN_URL")]
+        url: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_USER")]
+        username: String,
+
+        #[arg(long, env = "GRAIN_ADMIN_PASSWORD")]
+        password: String,
+    },
+}
+
+fn main() {
+    if let Err(e) = apply_context() {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+    let cli = Cli::parse();
+
+    if let Err(e) = execute_command(&cli.command) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Value of `--context` on the command line, read before the arguments are parsed
+fn context_flag() -> Option<String> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--context" {
+            return args.next();
+        }
+        if let Some(name) = arg.strip_prefix("--context=") {
+            return Some(name.to_string());
+        }
+    }
+    None
+}
+
+/// Fill `GRAIN_URL`, `GRAIN_ADMIN_USER` and `GRAIN_ADMIN_PASSWORD` from the selected context,
+/// so the flags default to it while flags and variables set explicitly still win
+fn apply_context() -> Result<(), String> {
+    let name = context_flag().or_else(|| env::var("GRAIN_CONTEXT").ok());
+    let Some(path) = contexts::config_path() else {
+        return Ok(());
+    };
+    let config =
+        Config::load(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let Some(context) = config.context(name.as_deref()) else {
+        return match name {
+            Some(name) => Err(format!("no context named {} in {}", name, path.display())),
+            None => Ok(()),
+        };
+    };
+    for (var, value) in [
+        ("GRAIN_URL", &context.url),
+        ("GRAIN_ADMIN_USER", &context.username),
+        ("GRAIN_ADMIN_PASSWORD", &context.password),
+    ] {
+        if env::var_os(var).is_none() {
+            env::set_var(var, value);
+        }
+    }
+    Ok(())
+}
+
+fn config_path() -> Result<std::path::PathBuf, String> {
+    contexts::config_path()
+        .ok_or_else(|| "cannot locate the config file: set HOME or XDG_CONFIG_HOME".to_string())
+}
+
+fn read_password_stdin() -> std::io::Result<String> {
+    let mut password = String::new();
+    std::io::stdin().read_to_string(&mut password)?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn execute_command(cmd: &Commands) -> Result<(), Box<dyn std::error::Error>> {
+    match cmd {
+        Commands::Login {
+            name,
+            url,
+            username,
+            password,
+            password_stdin: _,
+        } => {
+            // Without --password, clap requires --password-stdin
+            let password = match password {
+                Some(password) => password.clone(),
+                None => read_password_stdin()?,
+            };
+            Registry::new(url, Some((username.clone(), password.clone()))).check_credentials()?;
+
+            let path = config_path()?;
+            let mut config = Config::load(&path)?;
+            config.login(
+                name,
+                Context {
+                    url: url.trim_end_matches('/').to_string(),
+                    username: username.clone(),
+                    password,
+                },
+            );
+            config.save(&path)?;
+            println!(
+                "Logged in to {} as {} (context '{}', saved in {})",
+                url,
+                username,
+                name,
+                path.display()
+            );
+            Ok(())
+        }
+        Commands::Passwd {
+            new_password,
+            new_password_stdin: _,
+            url,
+            username,
+            password,
+        } => {
+            // Without --new-password, clap requires --new-password-stdin
+            let new_password = match new_password {
+                Some(new_password) => new_password.clone(),
+                None => read_password_stdin()?,
+            };
+            AdminClient::new(url, username, password).change_own_password(&new_password)?;
+            println!("Password changed for {}", username);
+
+            // Contexts holding the old password would stop working
+            let Some(path) = contexts::config_path() else {
+                return Ok(());
+            };
+            let mut config = Config::load(&path)?;
+            let url = url.trim_end_matches('/');
+            let mut updated = 0;
+            for context in config.contexts.values_mut() {
+                if context.url == url && context.username == *username {
+                    context.password = new_password.clone();
+                    updated += 1;
+                }
+            }
+            if updated > 0 {
+                config.save(&path)?;
+                println!("Updated {} saved context(s)", updated);
+            }
+            Ok(())
+        }
+        Commands::Logout { name } => {
+            let path = config_path()?;
+            let mut config = Config::load(&path)?;
+            let name = name
+                .clone()
+                .or_else(|| config.current_context.clone())
+                .ok_or("no current context")?;
+            if !config.logout(&name) {
+                return Err(format!("no context named {}", name).into());
+            }
+            config.save(&path)?;
+            println!("Removed context '{}'", name);
+            Ok(())
+        }
+        Commands::User { command } => execute_user_command(command),
+        Commands::Robot { command } => execute_robot_command(command),
+        Commands::Role { command } => execute_role_command(command),
+        Commands::Group { command } => execute_group_command(command),
+        Commands::Repo { command } => execute_repo_command(command),
+        Commands::Image { command } => execute_image_command(command),
+        Commands::Tag { command } => execute_tag_command(command),
+        Commands::Gc {
+            dry_run,
+            grace_period_hours,
+            untagged_manifests,
+            repository,
+            background,
+            url,
+            username,
+            password,
+        } => execute_gc_command(
+            &GcRunOptions {
+                dry_run: *dry_run,
+                grace_period_hours: *grace_period_hours,
+                untagged_manifests: *untagged_manifests,
+                repository: repository.clone(),
+            },
+            *background,
+            url,
+            username,
+            password,
+        ),
+        Commands::GcStatus {
+            url,
+            username,
+            password,
+        } => {
+            let status = AdminClient::new(url, username, password).gc_status()?;
+            println!("{}", serde_json::to_string_pretty(&status)?);
+            Ok(())
+        }
+        Commands::GcPolicy { command } => execute_gc_policy_command(command),
+        Commands::Cluster {
+            url,
+            username,
+            password,
+        } => {
+            let info = AdminClient::new(url, username, password).cluster_info()?;
+            println!("{}", serde_json::to_string_pretty(&info)?);
+            Ok(())
+        }
+        Commands::Replication {
+            url,
+            username,
+            password,
+        } => {
+            let status = AdminClient::new(url, username, password).replication_status()?;
+            println!("{}", serde_json::to_string_pretty(&status)?);
+            Ok(())
+        }
+        Commands::Sync {
+            source,
+            destination,
+            repos,
+            tags,
+            dry_run,
+            source_username,
+            source_password,
+            destination_username,
+            destination_password,
+        } => {
+            let credentials = |username: &Option<String>, password: &Option<String>| {
+                username
+                    .clone()
+                    .map(|u| (u, password.clone().unwrap_or_default()))
+            };
+            let source = Registry::new(source, credentials(source_username, source_password));
+            let destination = Registry::new(
+                destination,
+                credentials(destination_username, destination_password),
+            );
+            let options = SyncOptions {
+                repositories: repos.clone(),
+                tags: tags.clone(),
+                dry_run: *dry_run,
+            };
+            let stats = Mirror::new(&source, &destination, &options).run(|tag| {
+                if *dry_run {
+                    println!("Would sync {}", tag);
+                } else {
+                    println!("Synced {}", tag);
+                }
+            })?;
+            println!("{}", serde_json::to_string_pretty(&stats)?);
+            Ok(())
+        }
+        Commands::Bench {
+            mode,
+            size,
+            concurrency,
+            count,
+            repository,
+            url,
+            username,
+            password,
+        } => {
+            let registry = registry(url, username, password);
+            let options = BenchOptions {
+                repository: repository.clone(),
+                size: *size,
+                concurrency: *concurrency,
+                count: *count,
+            };
+            let stats = bench::run(&registry, *mode, &options, |done| {
+                eprint!("\r{}/{}", done, count);
+            })?;
+            eprintln!();
+            println!("{}", serde_json::to_string_pretty(&stats)?);
+            if stats.operations == 0 && stats.errors > 0 {
+                return Err(stats.first_error.unwrap_or_default().into());
+            }
+            Ok(())
+        }
+    }
+}
+
+fn execute_user_command(cmd: &UserCommands) -> Result<(), Box<dyn std::error::Error>> {
+    match cmd {
+        UserCommands::List {
+            url,
+            username,
+            password,
+        } => {
+            let users = AdminClient::new(url, username, password).list_users()?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json!({ "users": users }))?
+            );
+            Ok(())
+        }
+
+        UserCommands::Create {
+            user,
+            pass,
+            admin,
+            admin_orgs,
+            permissions,
+            roles,
+            groups,
+            url,
+            username,
+            password,
+        } => {
+            AdminClient::new(url, username, password).create_user(&CreateUserRequest {
+                username: user.clone(),
+                password: pass.clone(),
+                permissions: permissions.clone(),
+                admin: *admin,
+                admin_orgs: admin_orgs.clone(),
+                roles: roles.clone(),
+                groups: groups.clone(),
+            })?;
+
+            println!("User '{}' created successfully", user);
+            Ok(())
+        }
+
+        UserCommands::SetPassword {
+            user,
+            pass,
+            url,
+            username,
+            password,
+        } => {
+            AdminClient::new(url, username, password).change_password(user, pass)?;
+            println!("Password of '{}' changed", user);
+            Ok(())
+        }
+
+        UserCommands::Delete {
+            user,
+            dry_run,
+            url,
+            username,
+            password,
+        } => {
+            let client = AdminClient::new(url, username, password);
+            if *dry_run {
+                print_planned_changes(&client.plan_delete_user(user)?);
+                return Ok(());
+            }
+            client.delete_user(user)?;
+
+            println!("User '{}' deleted successfully", user);
+            Ok(())
+        }
+
+        UserCommands::AddPermission {
+            user,
+            repository,
+            tag,
+            actions,
+            expires_at,
+            url,
+            username,
+            password,
+        } => {
+            let permission = Permission {
+                repository: repository.clone(),
+                tag: tag.clone(),
+                actions: actions.split(',').map(|s| s.trim().to_string()).collect(),
+                expires_at: *expires_at,
+            };
+
+            AdminClient::new(url, username, password).add_permission(user, &permission)?;
+
+            println!(
+                "Permission added to user '{}': {} on {}:{}",
+                user, actions, repository, tag
+            );
+            Ok(())
+        }
+        UserCommands::RemovePermission {
+            user,
+            repository,
+            tag,
+            actions,
+            url,
+            username,
+            password,
+        } => {
+            let actions: Option<Vec<String>> = actions
+                .as_ref()
+                .map(|a| a.split(',').map(|s| s.trim().to_string()).collect());
+
+            let remaining = AdminClient::new(url, username, password).remove_permission(
+                user,
+                repository,
+                tag,
+                actions.as_deref(),
+            )?;
+
+            println!(
+                "Permission revoked from user '{}': {} on {}:{} ({} permission(s) left)",
+                user,
+                actions.map_or_else(|| "all".to_string(), |a| a.join(",")),
+                repository,
+                tag,
+                remaining.len()
+            );
+            Ok(())
+        }
+    }
+}
+
+fn execute_robot_command(cmd: &RobotCommands) -> Result<(), Box<dyn std::error::Error>> {
+    match cmd {
+        RobotCommands::List {
+            url,
+            username,
+            password,
+        } => {
+            let robots = AdminClient::new(url, username, password).list_robots()?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json!({ "robots": robots }))?
+            );
+            Ok(())
+        }
+
+        RobotCommands::Create {
+            name,
+            repository,
+            tag,
+            actions,
+            description,
+            expires_at,
+            url,
+            username,
+            password,
+        } => {
+            let created =
+                AdminClient::new(url, username, password).create_robot(&CreateRobotRequest {
+                    name: name.clone(),
+                    description: description.clone(),
+                    permissions: vec![Permission {
+                        repository: repository.clone(),
+                        tag: tag.clone(),
+                        actions: actions.split(',').map(|s| s.trim().to_string()).collect(),
+                        expires_at: None,
+                    }],
+                    expires_at: *expires_at,
+                })?;
+
+            eprintln!(
+                "Robot '{}' created; log in as '{}' with this token, it is not shown again:",
+                name, created.username
+            );
+            println!("{}", created.token);
+            Ok(())
+        }
+
+        RobotCommands::Revoke {
+            name,
+            dry_run,
+            url,
+            username,
+            password,
+        } => {
+            let client = AdminClient::new(url, username, password);
+            if *dry_run {
+                print_planned_changes(&client.plan_revoke_robot(name)?);
+                return Ok(());
+            }
+            client.revoke_robot(name)?;
+            println!("Robot '{}' revoked successfully", name);
+            Ok(())
+        }
+    }
+}
+
+/// `<repository>:<tag>:<actions>`, actions comma-separated
+fn parse_permission(spec: &str) -> Result<Permission, String> {
+    let mut parts = spec.splitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(repository), Some(tag), Some(actions))
+            if !repository.is_empty() && !tag.is_empty() && !actions.is_empty() =>
+        {
+            Ok(Permission {
+                repository: repository.to_string(),
+                tag: tag.to_string(),
+                actions: actions.split(',').map(|s| s.trim().to_string()).collect(),
+                expires_at: None,
+            })
+        }
+        _ => Err(format!(
+            "expected <repository>:<tag>:<actions>, got '{}'",
+            spec
+        )),
+    }
+}
+
+fn execute_role_command(cmd: &RoleCommands) -> Result<(), Box<dyn std::error::Error>> {
+    match cmd {
+        RoleCommands::List {
+            url,
+            username,
+            password,
+        } => {
+            let roles = AdminClient::new(url, username, password).list_roles()?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(
+                    &json!({ "roles": roles.roles, "groups": roles.groups })
+                )?
+            );
+            Ok(())
+        }
+
+        RoleCommands::Set {
+            name,
+            permissions,
+            url,
+            username,
+            password,
+        } => {
+            AdminClient::new(url, username, password).set_role(
+                name,
+                &AccessRole {
+                    permissions: permissions.clone(),
+                },
+            )?;
+            println!(
+                "Role '{}' saved with {} permission(s)",
+                name,
+                permissions.len()
+            );
+            Ok(())
+        }
+
+        RoleCommands::Delete {
+            name,
+            url,
+            username,
+            password,
+        } => {
+            AdminClient::new(url, username, password).delete_role(name)?;
+            println!("Role '{}' deleted", name);
+            Ok(())
+        }
+    }
+}
+
+fn execute_group_command(cmd: &GroupCommands) -> Result<(), Box<dyn std::error::Error>> {
+    match cmd {
+        GroupCommands::Set {
+            name,
+            roles,
+            permissions,
+            url,
+            username,
+            password,
+        } => {
+            AdminClient::new(url, username, password).set_group(
+                name,
+                &AccessGroup {
+                    roles: roles.clone(),
+                    permissions: permissions.clone(),
+                },
+            )?;
+            println!(
+                "Group '{}' saved with {} role(s) and {} permission(s)",
+                name,
+                roles.len(),
+                permissions.len()
+            );
+            Ok(())
+        }
+
+        GroupCommands::Delete {
+            name,
+            url,
+            username,
+            password,
+        } => {
+            AdminClient::new(url, username, password).delete_group(name)?;
+            println!("Group '{}' deleted", name);
+            Ok(())
+        }
+    }
+}
+
+fn execute_repo_command(cmd: &RepoCommands) -> Result<(), Box<dyn std::error::Error>> {
+    match cmd {
+        RepoCommands::List {
+            output,
+            url,
+            username,
+            password,
+        } => {
+            let repositories = registry(url, username, password).catalog()?;
+            match output {
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&json!({ "repositories": repositories }))?
+                ),
+                OutputFormat::Table => print_table("REPOSITORY", &repositories),
+            }
+            Ok(())
+        }
+
+        RepoCommands::PullSecret {
+            repository,
+            name,
+            namespace,
+            url,
+            username,
+            password,
+        } => {
+            let (org, repo) = repository
+                .split_once('/')
+                .ok_or("repository must be in org/repo form")?;
+            let secret = AdminClient::new(url, username, password).pull_secret(
+                org,
+                repo,
+                name.as_deref(),
+                namespace.as_deref(),
+            )?;
+            println!("{}", serde_json::to_string_pretty(&secret)?);
+            Ok(())
+        }
+
+        RepoCommands::Share {
+            repository,
+            blob,
+            image,
+            expires_in,
+            url,
+            username,
+            password,
+        } => {
+            let (org, repo) = repository
+                .split_once('/')
+                .ok_or("repository must be in org/repo form")?;
+            let (kind, reference) = match (blob, image) {
+                (Some(digest), _) => (DownloadKind::Blob, digest.clone()),
+                (None, Some(reference)) => (DownloadKind::Image, reference.clone()),
+                (None, None) => return Err("one of --blob or --image is required".into()),
+            };
+            let download = AdminClient::new(url, username, password).create_download_url(
+                org,
+                repo,
+                &CreateDownloadUrlRequest {
+                    kind,
+                    reference,
+                    expires_in: *expires_in,
+                },
+            )?;
+            println!("{}{}", url.trim_end_matches('/'), download.path);
+            Ok(())
+        }
+
+        RepoCommands::Pins {
+            repository,
+            url,
+            username,
+            password,
+        } => {
+            let (org, repo) = repository
+                .split_once('/')
+                .ok_or("repository must be in org/repo form")?;
+            for tag in AdminClient::new(url, username, password).list_pins(org, repo)? {
+                println!("{}", tag);
+            }
+            Ok(())
+        }
+
+        RepoCommands::Pin {
+            repository,
+            tag,
+            url,
+            username,
+            password,
+        } => {
+            let (org, repo) = repository
+                .split_once('/')
+                .ok_or("repository must be in org/repo form")?;
+            AdminClient::new(url, username, password).pin_tag(org, repo, tag)?;
+            println!("Tag '{}:{}' pinned successfully", repository, tag);
+            Ok(())
+        }
+
+        RepoCommands::Unpin {
+            repository,
+            tag,
+            url,
+            username,
+            password,
+        } => {
+            let (org, repo) = repository
+                .split_once('/')
+                .ok_or("repository must be in org/repo form")?;
+            AdminClient::new(url, username, password).unpin_tag(org, repo, tag)?;
+            println!("Tag '{}:{}' unpinned successfully", repository, tag);
+            Ok(())
+        }
+
+        RepoCommands::Expire {
+            repository,
+            tag,
+            ttl,
+            at,
+            url,
+            username,
+            password,
+        } => {
+            let (org, repo) = repository
+                .split_once('/')
+                .ok_or("repository must be in org/repo form")?;
+            let expiry = AdminClient::new(url, username, password).set_tag_expiry(
+                org,
+                repo,
+                tag,
+                &SetTagExpiryRequest {
+                    expires_at: *at,
+                    ttl_secs: *ttl,
+                },
+            )?;
+            println!(
+                "Tag '{}:{}' expires at {}",
+                repository, tag, expiry.expires_at
+            );
+            Ok(())
+        }
+
+        RepoCommands::Unexpire {
+            repository,
+            tag,
+            url,
+            username,
+            password,
+        } => {
+            let (org, repo) = repository
+                .split_once('/')
+                .ok_or("repository must be in org/repo form")?;
+            AdminClient::new(url, username, password).clear_tag_expiry(org, repo, tag)?;
+            println!("Tag '{}:{}' no longer expires", repository, tag);
+            Ok(())
+        }
+
+        RepoCommands::Archive {
+            repository,
+            dry_run,
+            url,
+            username,
+            password,
+        } => {
+            let (org, repo) = repository
+                .split_once('/')
+                .ok_or("repository must be in org/repo form")?;
+            let client = AdminClient::new(url, username, password);
+            if *dry_run {
+                print_planned_changes(&client.plan_archive_repository(org, repo)?);
+                return Ok(());
+            }
+            let archive = client.archive_repository(org, repo)?;
+            println!("{}", serde_json::to_string_pretty(&archive)?);
+            Ok(())
+        }
+
+        RepoCommands::Restore {
+            repository,
+            url,
+            username,
+            password,
+        } => {
+            let (org, repo) = repository
+                .split_once('/')
+                .ok_or("repository must be in org/repo form")?;
+            AdminClient::new(url, username, password).restore_repository(org, repo)?;
+            println!("Repository '{}' restored successfully", repository);
+            Ok(())
+        }
+
+        RepoCommands::Archives {
+            url,
+            username,
+            password,
+        } => {
+            let archives = AdminClient::new(url, username, password).list_archives()?;
+            println!("{}", serde_json::to_string_pretty(&archives)?);
+            Ok(())
+        }
+    }
+}
+
+fn execute_image_command(cmd: &ImageCommands) -> Result<(), Box<dyn std::error::Error>> {
+    match cmd {
+        ImageCommands::Delete {
+            image,
+            prune_blobs,
+            url,
+            username,
+            password,
+        } => {
+            let (repository, reference) = parse_image_reference(image)?;
+            let digest = registry(url, username, password)
+                .delete_manifest(repository, reference)?
+                .ok_or_else(|| format!("{} not found", image))?;
+            println!("Deleted {}@{}", repository, digest);
+
+            if *prune_blobs {
+                let username = username
+                    .as_deref()
+                    .ok_or("--prune-blobs needs admin credentials (--username)")?;
+                let stats = AdminClient::new(url, username, password.as_deref().unwrap_or(""))
+                    .run_gc(&GcRunOptions {
+                        repository: Some(repository.to_string()),
+                        ..Default::default()
+                    })?;
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn execute_tag_command(cmd: &TagCommands) -> Result<(), Box<dyn std::error::Error>> {
+    match cmd {
+        TagCommands::List {
+            repository,
+            output,
+            url,
+            username,
+            password,
+        } => {
+            let tags = registry(url, username, password).tags(repository)?;
+            match output {
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&json!({ "name": repository, "tags": tags }))?
+                ),
+                OutputFormat::Table => print_table("TAG", &tags),
+            }
+            Ok(())
+        }
+    }
+}
+
+fn execute_gc_command(
+    options: &GcRunOptions,
+    background: bool,
+    url: &str,
+    username: &str,
+    password: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = AdminClient::new(url, username, password);
+    if background {
+        client.start_gc(options)?;
+        println!("Garbage collection started; follow it with `grainctl gc-status`");
+        return Ok(());
+    }
+    let stats = client.run_gc(options)?;
+    println!("{}", serde_json::to_string_pretty(&stats)?);
+    Ok(())
+}
+
+fn execute_gc_policy_command(cmd: &GcPolicyCommands) -> Result<(), Box<dyn std::error::Error>> {
+    match cmd {
+        GcPolicyCommands::Show {
+            url,
+            username,
+            password,
+        } => {
+            let policy = AdminClient::new(url, username, password).get_gc_policy()?;
+            println!("{}", serde_json::to_string_pretty(&policy)?);
+            Ok(())
+        }
+        GcPolicyCommands::Set {
+            grace_period_hours,
+            schedule_interval_minutes,
+            untagged_manifests,
+            dry_run,
+            url,
+            username,
+            password,
+        } => {
+            let client = AdminClient::new(url, username, password);
+            let mut policy = client.get_gc_policy()?;
+            if let Some(hours) = grace_period_hours {
+                policy.grace_period_hours = *hours;
+            }
+            if let Some(minutes) = schedule_interval_minutes {
+                policy.schedule_interval_minutes = *minutes;
+            }
+            if let Some(untagged) = untagged_manifests {
+                policy.untagged_manifests = *untagged;
+            }
+            if let Some(dry_run) = dry_run {
+                policy.dry_run = *dry_run;
+            }
+            let policy = client.set_gc_policy(&policy)?;
+            println!("{}", serde_json::to_string_pretty(&policy)?);
+            Ok(())
+        }
+    }
+}
+
+/// Registry API client, anonymous when no username is given
+fn registry(url: &str, username: &Option<String>, password: &Option<String>) -> Registry {
+    let credentials = username
+        .clone()
+        .map(|u| (u, password.clone().unwrap_or_default()));
+    Registry::new(url, credentials)
+}
+
+fn print_table(header: &str, names: &[String]) {
+    println!("{}", header);
+    for name in names {
+        println!("{}", name);
+    }
+}
+
+/// Print the changes a dry run reported, one per line
+fn print_planned_changes(changes: &[PlannedChange]) {
+    println!("Dry run, nothing was changed:");
+    for change in changes {
+        match &change.detail {
+            Some(detail) => println!("  {} {}: {}", change.action, change.target, detail),
+            None => println!("  {} {}", change.action, change.target),
+        }
+    }
+}
+
+/// Repository and tag or digest of `org/repo:tag` or `org/repo@<digest>`
+fn parse_image_reference(image: &str) -> Result<(&str, &str), String> {
+    let parsed = match image.split_once('@') {
+        Some(parsed) => Some(parsed),
+        None => image.rsplit_once(':').filter(|(_, tag)| !tag.contains('/')),
+    };
+    parsed
+        .filter(|(repository, reference)| !repository.is_empty() && !reference.is_empty())
+        .ok_or_else(|| format!("expected org/repo:tag or org/repo@digest, got {}", image))
+}
+
+fn parse_bench_mode(value: &str) -> Result<BenchMode, String> {
+    match value {
+        "push" => Ok(BenchMode::Push),
+        "pull" => Ok(BenchMode::Pull),
+        _ => Err(format!("expected push or pull, got {}", value)),
+    }
+}
+
+fn parse_untagged_manifests(value: &str) -> Result<UntaggedManifests, String> {
+    match value {
+        "keep" => Ok(UntaggedManifests::Keep),
+        "delete" => Ok(UntaggedManifests::Delete),
+        _ => Err(format!("expected keep or delete, got {}", value)),
+    }
 }
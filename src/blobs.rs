@@ -9,12 +9,24 @@
 // | end-10 | `DELETE`       | `/v2/<name>/blobs/<digest>`                                  | `202`       | `404`/`405`       |
 // | end-11 | `POST`         | `/v2/<name>/blobs/uploads/?mount=<digest>&from=<other_name>` | `201`       | `404`             |
 
+use futures_util::Stream;
 use serde::Deserialize;
-use std::sync::Arc;
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use crate::{
-    auth, metrics, permissions, response, state,
-    storage::{self, write_blob},
+    args::{Args, Capability},
+    audit::{AuditEvent, Outcome},
+    auth,
+    body::BodyError,
+    cancel,
+    digest::{Algorithm, Digest},
+    gc, metrics, permissions, response, state,
+    storage::{self, StorageError},
 };
 use axum::{
     body::Body,
@@ -22,7 +34,472 @@ use axum::{
     http::{HeaderMap, StatusCode},
     response::Response,
 };
-use bytes::Bytes;
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Seconds an upload session may stay idle before it is deleted
+const UPLOAD_EXPIRES_HEADER: &str = "Grain-Upload-Expires-In";
+
+/// Remove an upload session that failed or can never complete
+async fn discard_upload(org: &str, repo: &str, uuid: &str) -> Result<(), std::io::Error> {
+    let uuid = uuid.to_string();
+    storage::blocking("delete_upload_session", org, repo, move |org, repo| {
+        storage::delete_upload_session(org, repo, &uuid)
+    })
+    .await
+}
+
+async fn init_upload_session(
+    org: &str,
+    repo: &str,
+    uuid: &str,
+    algorithm: Algorithm,
+) -> Result<(), std::io::Error> {
+    let uuid = uuid.to_string();
+    storage::blocking("init_upload_session", org, repo, move |org, repo| {
+        storage::init_upload_session(org, repo, &uuid, algorithm)
+    })
+    .await
+}
+
+/// Map a failed upload body to an OCI error; a session pushed past the size limit can never
+/// complete, so it is removed
+async fn upload_body_error(org: &str, repo: &str, uuid: &str, e: BodyError) -> Response<Body> {
+    log::warn!("Failed to stream body for upload {}: {}", uuid, e);
+    match e {
+        BodyError::TooLarge(limit) => {
+            let _ = discard_upload(org, repo, uuid).await;
+            response::payload_too_large(limit)
+        }
+        BodyError::Io(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            response::blob_upload_unknown(uuid)
+        }
+        BodyError::Io(_) => response::internal_error(),
+        e @ BodyError::Read(_) => response::blob_upload_invalid(&e.to_string()),
+    }
+}
+
+/// Refuse a new upload session once the repository has too many open sessions or they hold
+/// too many bytes, so one runaway pipeline cannot fill the uploads volume
+async fn check_upload_quota(state: &state::App, org: &str, repo: &str) -> Option<Response<Body>> {
+    let (max_sessions, max_bytes) = (
+        state.args.max_upload_sessions_per_repo,
+        state.args.max_upload_bytes_per_repo,
+    );
+    if max_sessions == 0 && max_bytes == 0 {
+        return None;
+    }
+
+    let usage = storage::blocking("upload_sessions_usage", org, repo, |org, repo| {
+        storage::upload_sessions_usage(org, repo)
+    })
+    .await;
+    let (sessions, bytes) = match usage {
+        Ok(usage) => usage,
+        Err(e) => {
+            log::warn!("Failed to read upload sessions of {}/{}: {}", org, repo, e);
+            return None;
+        }
+    };
+
+    let (limit, reason) = if max_sessions > 0 && sessions >= max_sessions {
+        (
+            "sessions",
+            format!("{} upload sessions open (limit {})", sessions, max_sessions),
+        )
+    } else if max_bytes > 0 && bytes >= max_bytes {
+        (
+            "bytes",
+            format!(
+                "{} bytes in open upload sessions (limit {})",
+                bytes, max_bytes
+            ),
+        )
+    } else {
+        return None;
+    };
+
+    log::warn!("Refused upload session for {}/{}: {}", org, repo, reason);
+    metrics::UPLOAD_QUOTA_REJECTIONS_TOTAL
+        .with_label_values(&[limit])
+        .inc();
+    Some(response::too_many_requests(&reason))
+}
+
+/// Refuse to add a blob of `size` bytes to a declared repository when it would go over the
+/// repository's quota. Blobs the repository already stores take no extra space.
+async fn check_repository_quota(
+    state: &state::App,
+    org: &str,
+    repo: &str,
+    digest: &Digest,
+    size: u64,
+) -> Result<(), StorageError> {
+    let repository = format!("{}/{}", org, repo);
+    let Some(quota) = state
+        .repositories
+        .get(&repository)
+        .and_then(|declared| declared.quota_bytes)
+    else {
+        return Ok(());
+    };
+    let stored = digest.clone();
+    let usage = storage::blocking("repository_blob_usage", org, repo, move |org, repo| {
+        if storage::blob_metadata(org, repo, &stored).is_ok() {
+            return Ok(None);
+        }
+        storage::repository_blob_usage(org, repo).map(|(_, used)| Some(used))
+    })
+    .await;
+    let used = match usage {
+        Ok(Some(used)) => used,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            log::warn!("Failed to measure {}: {}", repository, e);
+            return Ok(());
+        }
+    };
+    if used + size <= quota {
+        return Ok(());
+    }
+
+    log::warn!(
+        "Refused blob {} for {}: {} bytes used, quota {}",
+        digest,
+        repository,
+        used,
+        quota
+    );
+    Err(StorageError::QuotaExceeded(format!(
+        "{} bytes used of {}, blob is {} bytes",
+        used, quota, size
+    )))
+}
+
+/// Hash and move a completed upload session into blob storage off the async runtime
+async fn finalize_upload(
+    org: &str,
+    repo: &str,
+    uuid: &str,
+    digest: &Digest,
+) -> Result<Digest, StorageError> {
+    let (org, repo, uuid, digest) = (
+        org.to_string(),
+        repo.to_string(),
+        uuid.to_string(),
+        digest.clone(),
+    );
+    // A blob finalized during a GC run is kept until its manifest is pushed
+    gc::in_use::record(&org, &repo, &digest);
+    cancel::run_blocking("finalize_upload", move |token| {
+        storage::finalize_upload(&org, &repo, &uuid, &digest, token)
+    })
+    .await
+}
+
+/// Blob response body read from storage chunk by chunk; it accounts sent bytes, and once
+/// dropped (client gone) no further reads are issued. Chunks are read straight into the
+/// buffer handed to hyper, without an intermediate copy.
+pub(crate) struct TrackedDownload {
+    file: storage::BlobReader,
+    buf: BytesMut,
+    chunk_size: usize,
+    size: u64,
+    sent: u64,
+}
+
+impl TrackedDownload {
+    pub(crate) fn new(file: storage::BlobReader, size: u64, chunk_size: usize) -> Self {
+        let chunk_size = chunk_size.max(1);
+        Self {
+            file,
+            buf: BytesMut::new(),
+            chunk_size,
+            size,
+            sent: 0,
+        }
+    }
+}
+
+impl Stream for TrackedDownload {
+    type Item = Result<Bytes, std::io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.sent >= self.size {
+            return Poll::Ready(None);
+        }
+
+        let this = &mut *self;
+        let want = (this.size - this.sent).min(this.chunk_size as u64) as usize;
+        // The previous chunk was split off and frozen, so this allocates a fresh chunk
+        this.buf.reserve(want);
+
+        match tokio_util::io::poll_read_buf(
+            this.file.as_mut(),
+            cx,
+            &mut (&mut this.buf).limit(want),
+        ) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(Ok(0)) => Poll::Ready(Some(Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "blob truncated while streaming",
+            )))),
+            Poll::Ready(Ok(_)) => {
+                let chunk: Bytes = this.buf.split().freeze();
+                this.sent += chunk.len() as u64;
+                metrics::BLOB_DOWNLOAD_BYTES_TOTAL.inc_by(chunk.len() as u64);
+                Poll::Ready(Some(Ok(chunk)))
+            }
+        }
+    }
+}
+
+impl Drop for TrackedDownload {
+    fn drop(&mut self) {
+        if self.sent < self.size {
+            log::warn!(
+                "blobs/download: client went away after {} of {} bytes",
+                self.sent,
+                self.size
+            );
+            metrics::BLOB_DOWNLOADS_ABORTED_TOTAL.inc();
+        }
+    }
+}
+
+/// Resolve a `Range` header against a blob of `size` bytes to an inclusive `(start, end)`.
+/// `None` means the header is ignored (malformed, or several ranges) and the whole blob is sent;
+/// `Some(Err(()))` means the range lies entirely past the end of the blob.
+fn parse_range(header: &str, size: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let range = if start.is_empty() {
+        // Suffix range: the last `end` bytes
+        let suffix: u64 = end.parse().ok()?;
+        if suffix == 0 || size == 0 {
+            return Some(Err(()));
+        }
+        (size.saturating_sub(suffix), size - 1)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = match end {
+            "" => u64::MAX,
+            end => end.parse().ok()?,
+        };
+        if end < start {
+            return None;
+        }
+        if start >= size {
+            return Some(Err(()));
+        }
+        (start, end.min(size - 1))
+    };
+
+    Some(Ok(range))
+}
+
+/// Parse a chunk's `Content-Range` (`<start>-<end>`, inclusive on both ends, per end-5)
+fn parse_chunk_range(header: &str) -> Option<(u64, u64)> {
+    let (start, end) = header.trim().split_once('-')?;
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !is_number(start) || !is_number(end) {
+        return None;
+    }
+    let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+    (start <= end).then_some((start, end))
+}
+
+async fn upload_session_size(org: &str, repo: &str, uuid: &str) -> Result<u64, std::io::Error> {
+    let uuid = uuid.to_string();
+    storage::blocking("upload_session_size", org, repo, move |org, repo| {
+        storage::upload_session_size(org, repo, &uuid)
+    })
+    .await
+}
+
+/// Response carrying the progress of an upload session: its location, `Range` of the bytes
+/// received so far, UUID and idle expiry
+fn upload_progress(
+    state: &state::App,
+    headers: &HeaderMap,
+    org: &str,
+    repo: &str,
+    uuid: &str,
+    size: u64,
+    status: StatusCode,
+) -> Response<Body> {
+    let location = response::location(
+        &state.args,
+        headers,
+        &format!("/v2/{}/{}/blobs/uploads/{}", org, repo, uuid),
+    );
+
+    let mut builder = Response::builder()
+        .status(status)
+        .header("Location", location)
+        .header("Range", format!("0-{}", size.saturating_sub(1)))
+        .header("Docker-Upload-UUID", uuid);
+    // Sessions are deleted once idle this long; every chunk restarts the countdown
+    if state.args.upload_session_ttl_secs > 0 {
+        builder = builder.header(UPLOAD_EXPIRES_HEADER, state.args.upload_session_ttl_secs);
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+/// Refuse a chunk whose `Content-Range` does not start right after the bytes already received:
+/// chunks must arrive in order, and the client resumes from the returned `Range`
+async fn check_chunk_range(
+    state: &state::App,
+    headers: &HeaderMap,
+    org: &str,
+    repo: &str,
+    uuid: &str,
+) -> Option<Response<Body>> {
+    let header = headers.get("content-range")?;
+    let Some((start, end)) = header.to_str().ok().and_then(parse_chunk_range) else {
+        return Some(response::blob_upload_invalid(
+            "Content-Range must be <start>-<end>",
+        ));
+    };
+    // `None` for a range too long for any chunk, such as `0-18446744073709551615`
+    let chunk_length = end.checked_sub(start).and_then(|n| n.checked_add(1));
+    let length = headers
+        .get("content-length")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if length.is_some_and(|length| chunk_length.is_some_and(|chunk| chunk != length)) {
+        return Some(response::blob_upload_invalid(
+            "Content-Range does not match Content-Length",
+        ));
+    }
+
+    let size = match upload_session_size(org, repo, uuid).await {
+        Ok(size) => size,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Some(response::blob_upload_unknown(uuid))
+        }
+        Err(e) => {
+            log::error!("Failed to read upload session {}: {}", uuid, e);
+            return Some(response::internal_error());
+        }
+    };
+    if chunk_length.is_none() || start != size {
+        log::warn!(
+            "blobs: chunk {}-{} out of order for upload {} holding {} bytes",
+            start,
+            end,
+            uuid,
+            size
+        );
+        return Some(upload_progress(
+            state,
+            headers,
+            org,
+            repo,
+            uuid,
+            size,
+            StatusCode::RANGE_NOT_SATISFIABLE,
+        ));
+    }
+    None
+}
+
+/// `201 Created` for the blob `digest` now stored in `repository`
+fn blob_created(
+    args: &Args,
+    headers: &HeaderMap,
+    repository: &str,
+    digest: &Digest,
+) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::CREATED)
+        .header(
+            "Location",
+            response::location(
+                args,
+                headers,
+                &format!("/v2/{}/blobs/{}", repository, digest),
+            ),
+        )
+        .header("Docker-Content-Digest", digest.to_string())
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Blobs never change under their digest, so any cache may keep them for a year. Only public
+/// repositories' blobs go in shared caches, which would otherwise serve them without credentials.
+fn blob_cache_control(state: &state::App, repository: &str) -> &'static str {
+    if state.repositories.is_public(repository) {
+        "public, max-age=31536000, immutable"
+    } else {
+        "private, max-age=31536000, immutable"
+    }
+}
+
+async fn blob_metadata(
+    org: &str,
+    repo: &str,
+    digest: &Digest,
+) -> Result<storage::ObjectMeta, std::io::Error> {
+    let digest = digest.clone();
+    storage::blocking("blob_metadata", org, repo, move |org, repo| {
+        storage::blob_metadata(org, repo, &digest)
+    })
+    .await
+}
+
+/// Content of a blob small enough for the content cache, read into it on a miss; `None` when
+/// the blob is too large or missing from the repository
+async fn cached_blob(
+    state: &Arc<state::App>,
+    org: &str,
+    repo: &str,
+    digest: &Digest,
+) -> Option<Bytes> {
+    let (state, digest) = (state.clone(), digest.clone());
+    storage::blocking("read_cached_blob", org, repo, move |org, repo| {
+        // Another repository may have cached it, so the blob must still be stored in this one
+        let size = storage::blob_metadata(org, repo, &digest).ok()?.size;
+        if !state.content_cache.holds_blob(size) {
+            return None;
+        }
+        let key = digest.to_string();
+        if let Some(content) = state.content_cache.get(&key) {
+            return Some(content);
+        }
+        let content = Bytes::from(storage::read_blob(org, repo, &digest).ok()?);
+        state.content_cache.insert(&key, content.clone());
+        Some(content)
+    })
+    .await
+}
+
+/// `304 Not Modified` for a cached blob, when the client's copy is `digest` and it still exists
+async fn blob_not_modified(
+    state: &state::App,
+    headers: &HeaderMap,
+    org: &str,
+    repo: &str,
+    digest: &Digest,
+) -> Option<Response<Body>> {
+    let etag = format!("\"{}\"", digest);
+    if !response::if_none_match(headers, &etag) || blob_metadata(org, repo, digest).await.is_err() {
+        return None;
+    }
+    let mut not_modified = response::not_modified(&etag, &digest.to_string());
+    not_modified.headers_mut().insert(
+        "Cache-Control",
+        blob_cache_control(state, &format!("{}/{}", org, repo))
+            .parse()
+            .unwrap(),
+    );
+    Some(not_modified)
+}
 
 // end-2 GET /v2/:name/blobs/:digest
 pub(crate) async fn get_blob_by_digest(
@@ -37,7 +514,7 @@ pub(crate) async fn get_blob_by_digest(
         digest_string
     );
 
-    let host = &state.args.host;
+    let host = state.args.primary_host();
     let repository = format!("{}/{}", org, repo);
 
     // Check permission (Pull for blob retrieval)
@@ -60,32 +537,107 @@ pub(crate) async fn get_blob_by_digest(
         }
     }
 
-    // Strip sha256: prefix if present
-    let clean_digest = digest_string
-        .strip_prefix("sha256:")
-        .unwrap_or(&digest_string);
+    let digest = match Digest::parse(&digest_string) {
+        Ok(digest) => digest,
+        Err(e) => {
+            log::warn!("blobs/get_blob_by_digest: {}", e);
+            return response::blob_unknown(&digest_string);
+        }
+    };
+    if let Some(not_modified) = blob_not_modified(&state, &headers, &org, &repo, &digest).await {
+        return not_modified;
+    }
 
-    // Read blob from storage
-    match storage::read_blob(&org, &repo, clean_digest) {
-        Ok(blob_data) => {
+    // A single byte range lets clients resume interrupted downloads; anything else gets the
+    // whole blob
+    let range = match headers.get("range").and_then(|v| v.to_str().ok()) {
+        Some(range) => {
+            let size = match blob_metadata(&org, &repo, &digest).await {
+                Ok(metadata) => metadata.size,
+                Err(e) => {
+                    log::warn!(
+                        "blobs/get_blob_by_digest: blob not found: {}/{}/{}: {}",
+                        org,
+                        repo,
+                        digest,
+                        e
+                    );
+                    return response::blob_unknown(&digest.to_string());
+                }
+            };
+            match parse_range(range, size) {
+                Some(Ok(range)) => Some(range),
+                Some(Err(())) => {
+                    metrics::record_blob_download(StatusCode::RANGE_NOT_SATISFIABLE, 0);
+                    return response::range_not_satisfiable(size);
+                }
+                None => None,
+            }
+        }
+        None => None,
+    };
+    let offset = range.map_or(0, |(start, _)| start);
+
+    // Small blobs such as image configs are served from memory; others are streamed
+    let cached = match range {
+        None => cached_blob(&state, &org, &repo, &digest).await,
+        Some(_) => None,
+    };
+    let opened = match cached {
+        Some(content) => {
+            let size = content.len() as u64;
+            metrics::BLOB_DOWNLOAD_BYTES_TOTAL.inc_by(size);
+            Ok((Body::from(content), size))
+        }
+        None => {
+            let (digest, chunk_size) = (digest.clone(), state.args.download_chunk_size);
+            storage::blocking("open_blob", &org, &repo, move |org, repo| {
+                storage::open_blob(org, repo, &digest, offset)
+            })
+            .await
+            .map(|(file, size)| {
+                let length = range.map_or(size, |(start, end)| end - start + 1);
+                let body = Body::from_stream(TrackedDownload::new(file, length, chunk_size));
+                (body, size)
+            })
+        }
+    };
+    match opened {
+        Ok((body, size)) => {
             metrics::BLOB_DOWNLOADS_TOTAL.inc();
-            Response::builder()
-                .status(StatusCode::OK)
-                .header("Content-Length", blob_data.len().to_string())
-                .header("Docker-Content-Digest", format!("sha256:{}", clean_digest))
-                .header("Content-Type", "application/octet-stream")
-                .body(Body::from(blob_data))
-                .unwrap()
+            state
+                .repo_metrics
+                .record(&repository, permissions::Action::Pull);
+
+            let (status, length) = match range {
+                Some((start, end)) => (StatusCode::PARTIAL_CONTENT, end - start + 1),
+                None => (StatusCode::OK, size),
+            };
+            metrics::record_blob_download(status, offset);
+
+            let mut builder = Response::builder()
+                .status(status)
+                .header("Content-Length", length.to_string())
+                .header("Accept-Ranges", "bytes")
+                .header("Docker-Content-Digest", digest.to_string())
+                .header("ETag", format!("\"{}\"", digest))
+                .header("Cache-Control", blob_cache_control(&state, &repository))
+                .header("Content-Type", "application/octet-stream");
+            if let Some((start, end)) = range {
+                builder =
+                    builder.header("Content-Range", format!("bytes {}-{}/{}", start, end, size));
+            }
+            builder.body(body).unwrap()
         }
         Err(e) => {
             log::warn!(
                 "blobs/get_blob_by_digest: blob not found: {}/{}/{}: {}",
                 org,
                 repo,
-                clean_digest,
+                digest,
                 e
             );
-            response::blob_unknown(&format!("sha256:{}", clean_digest))
+            response::blob_unknown(&digest.to_string())
         }
     }
 }
@@ -103,7 +655,7 @@ pub(crate) async fn head_blob_by_digest(
         digest_string
     );
 
-    let host = &state.args.host;
+    let host = state.args.primary_host();
     let repository = format!("{}/{}", org, repo);
 
     // Check permission (Pull for blob retrieval)
@@ -119,34 +671,34 @@ pub(crate) async fn head_blob_by_digest(
         Ok(_) => {}
         Err(_) => {
             return if auth::authenticate_user(&state, &headers).await.is_ok() {
-                Response::builder()
-                    .status(StatusCode::FORBIDDEN)
-                    .body(Body::empty())
-                    .unwrap()
+                response::forbidden_head()
             } else {
-                Response::builder()
-                    .status(StatusCode::UNAUTHORIZED)
-                    .header(
-                        "WWW-Authenticate",
-                        format!("Basic realm=\"{}\", charset=\"UTF-8\"", host),
-                    )
-                    .body(Body::empty())
-                    .unwrap()
+                response::unauthorized_head(host)
             };
         }
     }
 
-    // Strip sha256: prefix if present
-    let clean_digest = digest_string
-        .strip_prefix("sha256:")
-        .unwrap_or(&digest_string);
+    let digest = match Digest::parse(&digest_string) {
+        Ok(digest) => digest,
+        Err(e) => {
+            log::warn!("blobs/head_blob_by_digest: {}", e);
+            return response::blob_unknown(&digest_string);
+        }
+    };
 
-    // Check if blob exists and get metadata
-    match storage::blob_metadata(&org, &repo, clean_digest) {
+    // Clients skip uploading blobs found here, so GC must keep them until the manifest arrives
+    gc::in_use::record(&org, &repo, &digest);
+    if let Some(not_modified) = blob_not_modified(&state, &headers, &org, &repo, &digest).await {
+        return not_modified;
+    }
+    match blob_metadata(&org, &repo, &digest).await {
         Ok(metadata) => Response::builder()
             .status(StatusCode::OK)
-            .header("Content-Length", metadata.len().to_string())
-            .header("Docker-Content-Digest", format!("sha256:{}", clean_digest))
+            .header("Content-Length", metadata.size.to_string())
+            .header("Accept-Ranges", "bytes")
+            .header("Docker-Content-Digest", digest.to_string())
+            .header("ETag", format!("\"{}\"", digest))
+            .header("Cache-Control", blob_cache_control(&state, &repository))
             .header("Content-Type", "application/octet-stream")
             .body(Body::empty())
             .unwrap(),
@@ -155,17 +707,46 @@ pub(crate) async fn head_blob_by_digest(
                 "blobs/head_blob_by_digest: blob not found: {}/{}/{}: {}",
                 org,
                 repo,
-                clean_digest,
+                digest,
                 e
             );
-            response::blob_unknown(&format!("sha256:{}", clean_digest))
+            response::blob_unknown(&digest.to_string())
         }
     }
 }
 
+/// First repository other than `repository` storing the blob `digest` that the user can pull
+/// from, to mount it from when the client gave no `from`
+async fn mount_source(
+    state: &Arc<state::App>,
+    headers: &HeaderMap,
+    repository: &str,
+    digest: &str,
+) -> Option<String> {
+    let digest = Digest::parse(digest).ok()?;
+    let holders = cancel::run_blocking("repositories_with_blob", move |_| {
+        storage::repositories_with_blob(&digest)
+    })
+    .await;
+    let holders = match holders {
+        Ok(holders) => holders,
+        Err(e) => {
+            log::error!("Failed to look up repositories storing a blob: {}", e);
+            return None;
+        }
+    };
+
+    let holders = holders.into_iter().filter(|holder| holder != repository);
+    auth::pullable(state, headers, holders.collect())
+        .await
+        .into_iter()
+        .next()
+}
+
 // end-4a POST /v2/:name/blobs/uploads/
 // end-4b POST /v2/:name/blobs/uploads/?digest=:digest
 // end-11 POST /v2/:name/blobs/uploads/?mount=:digest&from=:other_name
+// end-11 POST /v2/:name/blobs/uploads/?mount=:digest
 #[derive(Deserialize)]
 pub(crate) struct PostBlobUploadQueryParams {
     digest: Option<String>,
@@ -178,11 +759,11 @@ pub(crate) async fn post_blob_upload(
     Path((org, repo)): Path<(String, String)>,
     Query(params): Query<PostBlobUploadQueryParams>,
     headers: HeaderMap,
-    body: Bytes,
+    body: Body,
 ) -> Response<Body> {
     log::info!("blobs/post_blob_upload: org: {}, repo: {}", org, repo);
 
-    let host = &state.args.host;
+    let host = state.args.primary_host();
     let repository = format!("{}/{}", org, repo);
 
     // Check permission (Push for blob upload)
@@ -205,13 +786,50 @@ pub(crate) async fn post_blob_upload(
         }
     }
 
-    // Handle blob mounting (end-11)
-    if let (Some(mount_digest), Some(from_repo)) = (&params.mount, &params.from) {
-        let clean_digest = mount_digest.strip_prefix("sha256:").unwrap_or(mount_digest);
+    if state.args.strict_repositories && !state.repositories.is_declared(&repository) {
+        return response::name_unknown(&repository);
+    }
 
-        // Parse source repository (format: "org/repo")
+    // A blob the repository already holds is not transferred again (end-4b, or a mount of a
+    // blob already here): the body is left unread and the existing blob is returned. This tells
+    // push-only callers that the repository holds the digest, which is intended: they could
+    // push the same bytes anyway, the blob's content is not revealed, and only the repository
+    // they may push to is checked (other repositories are looked at for mounts, after a pull
+    // check on them).
+    let announced = (params.digest.as_deref()).or(params
+        .mount
+        .as_deref()
+        .filter(|_| state.args.is_enabled(Capability::Mount)));
+    if let Some(digest) = announced.and_then(|d| Digest::parse(d).ok()) {
+        if blob_metadata(&org, &repo, &digest).await.is_ok() {
+            log::info!(
+                "blobs/post_blob_upload: {}@{} already exists, skipping upload",
+                repository,
+                digest
+            );
+            gc::in_use::record(&org, &repo, &digest);
+            return blob_created(&state.args, &headers, &repository, &digest);
+        }
+    }
+
+    // Handle blob mounting (end-11); when disabled, the request opens an upload session as the
+    // spec allows. Without `from`, the blob is mounted from any repository holding it that the
+    // user can pull from.
+    let mount_enabled = state.args.is_enabled(Capability::Mount);
+    let from = match (&params.mount, &params.from) {
+        (Some(mount_digest), None) if mount_enabled => {
+            mount_source(&state, &headers, &repository, mount_digest).await
+        }
+        (_, from) => from.clone(),
+    };
+    let mount = (params.mount.as_ref())
+        .zip(from.as_ref())
+        .filter(|_| mount_enabled);
+    if let Some((mount_digest, from_repo)) = mount {
+        // Parse source repository (format: "org/repo"); an invalid digest falls back to upload
         let from_parts: Vec<&str> = from_repo.split('/').collect();
-        if from_parts.len() == 2 {
+        let digest = Digest::parse(mount_digest);
+        if let (2, Ok(digest)) = (from_parts.len(), digest) {
             let source_org = from_parts[0];
             let source_repo = from_parts[1];
             let source_repository = format!("{}/{}", source_org, source_repo);
@@ -227,32 +845,79 @@ pub(crate) async fn post_blob_upload(
             .await
             .is_ok()
             {
+                gc::in_use::record(source_org, source_repo, &digest);
+                gc::in_use::record(&org, &repo, &digest);
+                if let Ok(source) = blob_metadata(source_org, source_repo, &digest).await {
+                    if let Err(e) =
+                        check_repository_quota(&state, &org, &repo, &digest, source.size).await
+                    {
+                        return response::storage_error(&e);
+                    }
+                }
+
                 // Attempt to mount blob
-                match storage::mount_blob(source_org, source_repo, &org, &repo, clean_digest) {
-                    Ok(()) => {
+                let mounted = {
+                    let (target_org, target_repo, digest) =
+                        (org.clone(), repo.clone(), digest.clone());
+                    storage::blocking("mount_blob", source_org, source_repo, move |org, repo| {
+                        storage::mount_blob(org, repo, &target_org, &target_repo, &digest)
+                    })
+                    .await
+                };
+                match mounted {
+                    Ok(linked) => {
+                        let mounted_by = auth::authenticate_user(&state, &headers)
+                            .await
+                            .map(|u| u.username)
+                            .unwrap_or_default();
                         log::info!(
-                            "Mounted blob {} from {} to {}",
-                            clean_digest,
+                            "Mounted blob {} from {} to {} (user: {})",
+                            digest,
                             from_repo,
-                            repository
+                            repository,
+                            mounted_by
                         );
-
-                        let location = format!(
-                            "http://{}/v2/{}/{}/blobs/sha256:{}",
-                            host, org, repo, clean_digest
+                        state.audit.record(
+                            AuditEvent::new(
+                                &mounted_by,
+                                "blob.mount",
+                                &format!("{}@{}", repository, digest),
+                                Outcome::Success,
+                            )
+                            .with_detail(format!("from {}", from_repo)),
                         );
 
-                        return Response::builder()
-                            .status(StatusCode::CREATED)
-                            .header("Location", location)
-                            .header("Docker-Content-Digest", format!("sha256:{}", clean_digest))
-                            .body(Body::empty())
-                            .unwrap();
+                        // Keep the origin of the first mount; later mounts of an existing blob are no-ops
+                        if linked {
+                            let record = storage::MountRecord {
+                                source: source_repository.clone(),
+                                mounted_by,
+                                mounted_at: SystemTime::now()
+                                    .duration_since(UNIX_EPOCH)
+                                    .map(|d| d.as_secs())
+                                    .unwrap_or(0),
+                            };
+                            let mounted = digest.clone();
+                            let written = storage::blocking(
+                                "write_mount_record",
+                                &org,
+                                &repo,
+                                move |org, repo| {
+                                    storage::write_mount_record(org, repo, &mounted, &record)
+                                },
+                            )
+                            .await;
+                            if let Err(e) = written {
+                                log::error!("Failed to record mount source for {}: {}", digest, e);
+                            }
+                        }
+
+                        return blob_created(&state.args, &headers, &repository, &digest);
                     }
                     Err(e) => {
                         log::warn!(
                             "Failed to mount blob {}: {} - falling back to upload",
-                            clean_digest,
+                            digest,
                             e
                         );
                         // Fall through to regular upload session creation
@@ -265,51 +930,76 @@ pub(crate) async fn post_blob_upload(
         }
     }
 
+    if let Some(rejected) = check_upload_quota(&state, &org, &repo).await {
+        return rejected;
+    }
+
     // If digest is provided, handle monolithic upload (end-4b)
     if let Some(digest_string) = params.digest {
-        let success = write_blob(&org, &repo, &digest_string, Body::from(body)).await;
-
-        if !success {
+        let Ok(digest) = Digest::parse(&digest_string) else {
             return response::digest_invalid(&digest_string);
+        };
+
+        // Stream into a private upload session, then finalize it like a chunked upload
+        let uuid = uuid::Uuid::new_v4().to_string();
+        if let Err(e) = init_upload_session(&org, &repo, &uuid, digest.algorithm()).await {
+            log::error!("Failed to init upload session: {}", e);
+            return response::internal_error();
+        }
+
+        let size = match storage::append_upload_body(
+            &org,
+            &repo,
+            &uuid,
+            body,
+            state.args.upload_buffer_size,
+            state.args.max_blob_size,
+        )
+        .await
+        {
+            Ok(size) => size,
+            Err(e) => {
+                let _ = discard_upload(&org, &repo, &uuid).await;
+                return upload_body_error(&org, &repo, &uuid, e).await;
+            }
+        };
+
+        if let Err(e) = check_repository_quota(&state, &org, &repo, &digest, size).await {
+            let _ = discard_upload(&org, &repo, &uuid).await;
+            return response::storage_error(&e);
+        }
+
+        if let Err(e) = finalize_upload(&org, &repo, &uuid, &digest).await {
+            log::warn!("Monolithic upload failed: {}", e);
+            let _ = discard_upload(&org, &repo, &uuid).await;
+            return response::storage_error(&e);
         }
 
         metrics::BLOB_UPLOADS_TOTAL.inc();
+        state
+            .repo_metrics
+            .record(&repository, permissions::Action::Push);
 
-        let clean_digest = digest_string
-            .strip_prefix("sha256:")
-            .unwrap_or(&digest_string);
-
-        return Response::builder()
-            .status(StatusCode::CREATED)
-            .header(
-                "Location",
-                format!(
-                    "http://{}/v2/{}/{}/blobs/sha256:{}",
-                    host, org, repo, clean_digest
-                ),
-            )
-            .header("Docker-Content-Digest", format!("sha256:{}", clean_digest))
-            .body(Body::empty())
-            .unwrap();
+        return blob_created(&state.args, &headers, &repository, &digest);
     }
 
     // Create new upload session (end-4a)
     let uuid = uuid::Uuid::new_v4().to_string();
 
-    if let Err(e) = storage::init_upload_session(&org, &repo, &uuid) {
+    if let Err(e) = init_upload_session(&org, &repo, &uuid, Algorithm::Sha256).await {
         log::error!("Failed to init upload session: {}", e);
         return response::internal_error();
     }
 
-    let location = format!("http://{}/v2/{}/{}/blobs/uploads/{}", host, org, repo, uuid);
-
-    Response::builder()
-        .status(StatusCode::ACCEPTED)
-        .header("Location", location)
-        .header("Range", "0-0")
-        .header("Docker-Upload-UUID", uuid)
-        .body(Body::empty())
-        .unwrap()
+    upload_progress(
+        &state,
+        &headers,
+        &org,
+        &repo,
+        &uuid,
+        0,
+        StatusCode::ACCEPTED,
+    )
 }
 
 // end-5 PATCH /v2/:name/blobs/uploads/:reference
@@ -317,7 +1007,7 @@ pub(crate) async fn patch_blob_upload(
     State(state): State<Arc<state::App>>,
     Path((org, repo, uuid)): Path<(String, String, String)>,
     headers: HeaderMap,
-    body: Bytes,
+    body: Body,
 ) -> Response<Body> {
     log::info!(
         "blobs/patch_blob_upload: org: {}, repo: {}, uuid: {}",
@@ -326,7 +1016,7 @@ pub(crate) async fn patch_blob_upload(
         uuid
     );
 
-    let host = &state.args.host;
+    let host = state.args.primary_host();
     let repository = format!("{}/{}", org, repo);
 
     // Check permission (Push for blob upload)
@@ -349,21 +1039,132 @@ pub(crate) async fn patch_blob_upload(
         }
     }
 
-    match storage::append_upload_chunk(&org, &repo, &uuid, &body) {
-        Ok(total_size) => {
-            let location = format!("http://{}/v2/{}/{}/blobs/uploads/{}", host, org, repo, uuid);
+    if let Some(rejected) = check_chunk_range(&state, &headers, &org, &repo, &uuid).await {
+        return rejected;
+    }
 
-            Response::builder()
-                .status(StatusCode::ACCEPTED)
-                .header("Location", location)
-                .header("Range", format!("0-{}", total_size.saturating_sub(1)))
-                .header("Docker-Upload-UUID", &uuid)
-                .body(Body::empty())
-                .unwrap()
+    match storage::append_upload_body(
+        &org,
+        &repo,
+        &uuid,
+        body,
+        state.args.upload_buffer_size,
+        state.args.max_blob_size,
+    )
+    .await
+    {
+        Ok(total_size) => upload_progress(
+            &state,
+            &headers,
+            &org,
+            &repo,
+            &uuid,
+            total_size,
+            StatusCode::ACCEPTED,
+        ),
+        Err(e) => upload_body_error(&org, &repo, &uuid, e).await,
+    }
+}
+
+// end-13 GET /v2/:name/blobs/uploads/:reference
+pub(crate) async fn get_blob_upload_status(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo, uuid)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    log::info!(
+        "blobs/get_blob_upload_status: org: {}, repo: {}, uuid: {}",
+        org,
+        repo,
+        uuid
+    );
+
+    let host = state.args.primary_host();
+    let repository = format!("{}/{}", org, repo);
+
+    // Only the pusher resumes an upload, so status needs the same permission
+    match auth::check_permission(
+        &state,
+        &headers,
+        &repository,
+        None,
+        permissions::Action::Push,
+    )
+    .await
+    {
+        Ok(_) => {}
+        Err(_) => {
+            return if auth::authenticate_user(&state, &headers).await.is_ok() {
+                response::forbidden()
+            } else {
+                response::unauthorized(host)
+            };
         }
+    }
+
+    match upload_session_size(&org, &repo, &uuid).await {
+        Ok(size) => upload_progress(
+            &state,
+            &headers,
+            &org,
+            &repo,
+            &uuid,
+            size,
+            StatusCode::NO_CONTENT,
+        ),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => response::blob_upload_unknown(&uuid),
         Err(e) => {
-            log::error!("Failed to append chunk for upload {}: {}", uuid, e);
-            response::blob_upload_unknown(&uuid)
+            log::error!("Failed to read upload session {}: {}", uuid, e);
+            response::internal_error()
+        }
+    }
+}
+
+// DELETE /v2/:name/blobs/uploads/:reference
+pub(crate) async fn delete_blob_upload(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo, uuid)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    log::info!(
+        "blobs/delete_blob_upload: org: {}, repo: {}, uuid: {}",
+        org,
+        repo,
+        uuid
+    );
+
+    let host = state.args.primary_host();
+    let repository = format!("{}/{}", org, repo);
+
+    // Cancelling is part of pushing, so it needs the same permission
+    match auth::check_permission(
+        &state,
+        &headers,
+        &repository,
+        None,
+        permissions::Action::Push,
+    )
+    .await
+    {
+        Ok(_) => {}
+        Err(_) => {
+            return if auth::authenticate_user(&state, &headers).await.is_ok() {
+                response::forbidden()
+            } else {
+                response::unauthorized(host)
+            };
+        }
+    }
+
+    match discard_upload(&org, &repo, &uuid).await {
+        Ok(()) => Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => response::blob_upload_unknown(&uuid),
+        Err(e) => {
+            log::error!("Failed to delete upload session {}: {}", uuid, e);
+            response::internal_error()
         }
     }
 }
@@ -379,7 +1180,7 @@ pub(crate) async fn put_blob_upload_by_reference(
     Path((org, repo, uuid)): Path<(String, String, String)>,
     Query(params): Query<End6QueryParams>,
     headers: HeaderMap,
-    body: Bytes,
+    body: Body,
 ) -> Response<Body> {
     log::info!(
         "blobs/put_blob_upload_by_reference: org: {}, repo: {}, uuid: {}, digest: {}",
@@ -389,7 +1190,7 @@ pub(crate) async fn put_blob_upload_by_reference(
         params.digest
     );
 
-    let host = &state.args.host;
+    let host = state.args.primary_host();
     let repository = format!("{}/{}", org, repo);
 
     // Check permission (Push for blob upload)
@@ -412,42 +1213,51 @@ pub(crate) async fn put_blob_upload_by_reference(
         }
     }
 
-    // Append final chunk if body is not empty
-    if !body.is_empty() {
-        if let Err(e) = storage::append_upload_chunk(&org, &repo, &uuid, &body) {
-            log::error!("Failed to append final chunk: {}", e);
-            return response::internal_error();
-        }
+    let Ok(digest) = Digest::parse(&params.digest) else {
+        return response::digest_invalid(&params.digest);
+    };
+
+    if let Some(rejected) = check_chunk_range(&state, &headers, &org, &repo, &uuid).await {
+        return rejected;
+    }
+
+    // Append the final chunk, if any
+    let size = match storage::append_upload_body(
+        &org,
+        &repo,
+        &uuid,
+        body,
+        state.args.upload_buffer_size,
+        state.args.max_blob_size,
+    )
+    .await
+    {
+        Ok(size) => size,
+        Err(e) => return upload_body_error(&org, &repo, &uuid, e).await,
+    };
+
+    // A blob over the quota can never be stored, so the session is discarded
+    if let Err(e) = check_repository_quota(&state, &org, &repo, &digest, size).await {
+        let _ = discard_upload(&org, &repo, &uuid).await;
+        return response::storage_error(&e);
     }
 
     // Finalize upload and validate digest
-    match storage::finalize_upload(&org, &repo, &uuid, &params.digest) {
+    match finalize_upload(&org, &repo, &uuid, &digest).await {
         Ok(actual_digest) => {
             metrics::BLOB_UPLOADS_TOTAL.inc();
+            state
+                .repo_metrics
+                .record(&repository, permissions::Action::Push);
 
-            let location = format!(
-                "http://{}/v2/{}/{}/blobs/sha256:{}",
-                host, org, repo, actual_digest
-            );
-
-            Response::builder()
-                .status(StatusCode::CREATED)
-                .header("Location", location)
-                .header("Docker-Content-Digest", format!("sha256:{}", actual_digest))
-                .body(Body::empty())
-                .unwrap()
+            blob_created(&state.args, &headers, &repository, &actual_digest)
         }
         Err(e) => {
             log::error!("Failed to finalize upload: {}", e);
 
             // Clean up failed upload
-            let _ = storage::delete_upload_session(&org, &repo, &uuid);
-
-            if e.contains("Digest mismatch") {
-                response::digest_invalid(&params.digest)
-            } else {
-                response::internal_error()
-            }
+            let _ = discard_upload(&org, &repo, &uuid).await;
+            response::storage_error(&e)
         }
     }
 }
@@ -458,11 +1268,15 @@ pub(crate) async fn delete_blob_by_digest(
     Path((org, repo, digest_string)): Path<(String, String, String)>,
     headers: HeaderMap,
 ) -> Response<Body> {
-    let host = &state.args.host;
+    if !state.args.is_enabled(Capability::BlobDelete) {
+        return response::capability_disabled(StatusCode::METHOD_NOT_ALLOWED, "blob deletion");
+    }
+
+    let host = state.args.primary_host();
     let repository = format!("{}/{}", org, repo);
 
     // Check permission (Delete for blob deletion)
-    match auth::check_permission(
+    let user = match auth::check_permission(
         &state,
         &headers,
         &repository,
@@ -471,7 +1285,7 @@ pub(crate) async fn delete_blob_by_digest(
     )
     .await
     {
-        Ok(_) => {}
+        Ok(user) => user,
         Err(_) => {
             return if auth::authenticate_user(&state, &headers).await.is_ok() {
                 response::forbidden()
@@ -479,24 +1293,41 @@ pub(crate) async fn delete_blob_by_digest(
                 response::unauthorized(host)
             };
         }
-    }
+    };
 
-    // Clean digest (strip sha256: prefix if present)
-    let clean_digest = digest_string
-        .strip_prefix("sha256:")
-        .unwrap_or(&digest_string);
+    let digest = match Digest::parse(&digest_string) {
+        Ok(digest) => digest,
+        Err(e) => {
+            log::warn!("blobs/delete_blob_by_digest: {}", e);
+            return response::blob_unknown(&digest_string);
+        }
+    };
 
     log::info!(
         "blobs/delete_blob_by_digest: org: {}, repo: {}, digest: {}",
         org,
         repo,
-        clean_digest
+        digest
     );
 
     // Delete blob
-    match storage::delete_blob(&org, &repo, clean_digest) {
+    let deleted = {
+        let digest = digest.clone();
+        storage::blocking("delete_blob", &org, &repo, move |org, repo| {
+            storage::delete_blob(org, repo, &digest)
+        })
+        .await
+    };
+    match deleted {
         Ok(()) => {
-            log::info!("Deleted blob {}/{}/{}", org, repo, clean_digest);
+            log::info!("Deleted blob {}/{}/{}", org, repo, digest);
+            state.content_cache.remove(&digest.to_string());
+            state.audit.record(AuditEvent::new(
+                &user.username,
+                "blob.delete",
+                &format!("{}@{}", repository, digest),
+                Outcome::Success,
+            ));
 
             Response::builder()
                 .status(StatusCode::ACCEPTED)
@@ -504,24 +1335,17 @@ pub(crate) async fn delete_blob_by_digest(
                 .unwrap()
         }
         Err(e) => {
-            if e.kind() == std::io::ErrorKind::NotFound {
+            if e.is_not_found() {
                 log::warn!(
                     "Attempted to delete non-existent blob {}/{}/{}",
                     org,
                     repo,
-                    clean_digest
+                    digest
                 );
-                response::blob_unknown(&format!("sha256:{}", clean_digest))
             } else {
-                log::error!(
-                    "Failed to delete blob {}/{}/{}: {}",
-                    org,
-                    repo,
-                    clean_digest,
-                    e
-                );
-                response::internal_error()
+                log::error!("Failed to delete blob {}/{}/{}: {}", org, repo, digest, e);
             }
+            response::storage_error(&e)
         }
     }
 }
@@ -0,0 +1,148 @@
+use axum::body::{Body, Bytes, HttpBody};
+use bytes::BytesMut;
+use futures_util::StreamExt;
+use std::fmt;
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
+
+use crate::digest::Hasher;
+
+/// Why a request body could not be consumed
+#[derive(Debug)]
+pub(crate) enum BodyError {
+    /// The body exceeded the route's limit (in bytes)
+    TooLarge(u64),
+    /// The client sent a malformed body or went away mid-stream
+    Read(axum::Error),
+    /// Writing the body to its destination failed
+    Io(std::io::Error),
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BodyError::TooLarge(limit) => write!(f, "body exceeds limit of {} bytes", limit),
+            BodyError::Read(e) => write!(f, "failed to read body: {}", e),
+            BodyError::Io(e) => write!(f, "failed to write body: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for BodyError {
+    fn from(e: std::io::Error) -> Self {
+        BodyError::Io(e)
+    }
+}
+
+/// Buffer a small body (e.g. a manifest) in memory, rejecting it once it exceeds `limit` bytes
+pub(crate) async fn read_limited(body: Body, limit: u64) -> Result<Bytes, BodyError> {
+    // A Content-Length above the limit is rejected before reading anything
+    if body.size_hint().lower() > limit {
+        return Err(BodyError::TooLarge(limit));
+    }
+
+    let mut buf = BytesMut::new();
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(BodyError::Read)?;
+        if (buf.len() + chunk.len()) as u64 > limit {
+            return Err(BodyError::TooLarge(limit));
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf.freeze())
+}
+
+/// Stream a large body (e.g. a blob upload) into `writer` through a write buffer of
+/// `buffer_size` bytes, so memory per connection stays bounded regardless of body size.
+/// `limit` of 0 means unlimited. Written bytes are fed to `hasher` on the way, so the digest
+/// needs no second read. Returns the number of bytes written.
+pub(crate) async fn stream_to<W: AsyncWrite + Unpin>(
+    body: Body,
+    writer: W,
+    buffer_size: usize,
+    limit: u64,
+    mut hasher: Option<&mut Hasher>,
+) -> Result<u64, BodyError> {
+    if limit > 0 && body.size_hint().lower() > limit {
+        return Err(BodyError::TooLarge(limit));
+    }
+
+    let mut writer = BufWriter::with_capacity(buffer_size, writer);
+    let mut written = 0u64;
+    let mut stream = body.into_data_stream();
+
+    // Each frame is written before the next is polled, so a slow disk slows the client down
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(BodyError::Read)?;
+        if limit > 0 && written + chunk.len() as u64 > limit {
+            return Err(BodyError::TooLarge(limit));
+        }
+        writer.write_all(&chunk).await?;
+        if let Some(hasher) = hasher.as_deref_mut() {
+            hasher.update(&chunk);
+        }
+        written += chunk.len() as u64;
+    }
+
+    writer.flush().await?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::{Algorithm, Digest};
+
+    fn chunked(chunks: &[&'static [u8]]) -> Body {
+        let stream = futures_util::stream::iter(
+            chunks
+                .iter()
+                .map(|c| Ok::<_, std::io::Error>(Bytes::from_static(c)))
+                .collect::<Vec<_>>(),
+        );
+        Body::from_stream(stream)
+    }
+
+    #[tokio::test]
+    async fn test_read_limited() {
+        let bytes = read_limited(chunked(&[b"abc", b"def"]), 6).await.unwrap();
+        assert_eq!(&bytes[..], b"abcdef");
+
+        assert!(matches!(
+            read_limited(chunked(&[b"abc", b"defg"]), 6).await,
+            Err(BodyError::TooLarge(6))
+        ));
+        // Known length is rejected up front
+        assert!(matches!(
+            read_limited(Body::from("abcdefg"), 6).await,
+            Err(BodyError::TooLarge(6))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stream_to() {
+        let mut out = Vec::new();
+        let mut hasher = Algorithm::Sha256.hasher();
+        let written = stream_to(
+            chunked(&[b"abc", b"def", b"gh"]),
+            &mut out,
+            4,
+            0,
+            Some(&mut hasher),
+        )
+        .await
+        .unwrap();
+        assert_eq!(written, 8);
+        assert_eq!(out, b"abcdefgh");
+        assert_eq!(
+            hasher.finalize(),
+            Digest::of(Algorithm::Sha256, b"abcdefgh")
+        );
+
+        let mut out = Vec::new();
+        assert!(matches!(
+            stream_to(chunked(&[b"abc", b"def"]), &mut out, 4, 5, None).await,
+            Err(BodyError::TooLarge(5))
+        ));
+    }
+}
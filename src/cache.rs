@@ -0,0 +1,150 @@
+//! In-memory LRU cache of manifests and small blobs (image configs), keyed by digest, so tags
+//! pulled thousands of times an hour are served without reading storage. Content never changes
+//! under its digest: callers check the object still exists in the repository before serving a
+//! cached copy, and deletions only drop entries to free memory.
+
+use bytes::Bytes;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use crate::{args::Args, metrics};
+
+pub(crate) struct ContentCache {
+    /// Most bytes held at once (0 disables the cache)
+    capacity: u64,
+    /// Largest blob worth keeping; manifests are always small enough
+    max_blob_size: u64,
+    lru: Mutex<Lru>,
+}
+
+#[derive(Default)]
+struct Lru {
+    /// Content and last use of each digest
+    entries: HashMap<String, (Bytes, u64)>,
+    /// Digests by last use, least recently used first
+    order: BTreeMap<u64, String>,
+    clock: u64,
+    bytes: u64,
+}
+
+impl Lru {
+    fn touch(&mut self, digest: &str) -> Option<Bytes> {
+        self.clock += 1;
+        let clock = self.clock;
+        let (content, last_use) = self.entries.get_mut(digest)?;
+        self.order.remove(last_use);
+        *last_use = clock;
+        self.order.insert(clock, digest.to_string());
+        Some(content.clone())
+    }
+
+    fn remove(&mut self, digest: &str) {
+        if let Some((content, last_use)) = self.entries.remove(digest) {
+            self.order.remove(&last_use);
+            self.bytes -= content.len() as u64;
+        }
+    }
+}
+
+impl ContentCache {
+    pub(crate) fn new(capacity: u64, max_blob_size: u64) -> Self {
+        Self {
+            capacity,
+            max_blob_size,
+            lru: Mutex::new(Lru::default()),
+        }
+    }
+
+    pub(crate) fn from_args(args: &Args) -> Self {
+        Self::new(args.content_cache_size, args.content_cache_max_blob_size)
+    }
+
+    /// Whether a blob of `size` bytes is kept once read
+    pub(crate) fn holds_blob(&self, size: u64) -> bool {
+        self.capacity > 0 && size <= self.max_blob_size
+    }
+
+    pub(crate) fn get(&self, digest: &str) -> Option<Bytes> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let content = self.lru.lock().unwrap().touch(digest);
+        let result = if content.is_some() { "hit" } else { "miss" };
+        metrics::CONTENT_CACHE_REQUESTS_TOTAL
+            .with_label_values(&[result])
+            .inc();
+        content
+    }
+
+    /// Keep `content` under `digest`, evicting the least recently used entries to make room
+    pub(crate) fn insert(&self, digest: &str, content: Bytes) {
+        let size = content.len() as u64;
+        if size > self.capacity {
+            return;
+        }
+
+        let mut lru = self.lru.lock().unwrap();
+        lru.remove(digest);
+        while lru.bytes + size > self.capacity {
+            let Some((_, oldest)) = lru.order.pop_first() else {
+                break;
+            };
+            if let Some((evicted, _)) = lru.entries.remove(&oldest) {
+                lru.bytes -= evicted.len() as u64;
+            }
+        }
+
+        lru.clock += 1;
+        let clock = lru.clock;
+        lru.order.insert(clock, digest.to_string());
+        lru.entries.insert(digest.to_string(), (content, clock));
+        lru.bytes += size;
+        metrics::CONTENT_CACHE_BYTES.set(lru.bytes as i64);
+    }
+
+    /// Drop a deleted manifest or blob
+    pub(crate) fn remove(&self, digest: &str) {
+        let mut lru = self.lru.lock().unwrap();
+        lru.remove(digest);
+        metrics::CONTENT_CACHE_BYTES.set(lru.bytes as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = ContentCache::new(10, 10);
+        cache.insert("a", Bytes::from_static(b"aaaa"));
+        cache.insert("b", Bytes::from_static(b"bbbb"));
+        // Reading `a` makes `b` the least recently used
+        assert_eq!(cache.get("a").unwrap(), "aaaa");
+
+        cache.insert("c", Bytes::from_static(b"cccc"));
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+
+        cache.remove("a");
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.lru.lock().unwrap().bytes, 4);
+    }
+
+    #[test]
+    fn test_oversized_and_disabled() {
+        let cache = ContentCache::new(4, 2);
+        cache.insert("big", Bytes::from_static(b"too large"));
+        assert!(cache.get("big").is_none());
+        assert!(cache.holds_blob(2));
+        assert!(!cache.holds_blob(3));
+
+        let disabled = ContentCache::new(0, 2);
+        disabled.insert("a", Bytes::new());
+        assert!(disabled.get("a").is_none());
+        assert!(!disabled.holds_blob(0));
+    }
+}
@@ -0,0 +1,117 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::metrics;
+
+/// Cancellation flag shared between a request handler and the blocking storage work it started
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub(crate) fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Error to return from storage loops once the request is gone
+    pub(crate) fn check(&self) -> Result<(), std::io::Error> {
+        if self.is_cancelled() {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "request cancelled",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Cancels its token when dropped, which happens when axum drops the handler future
+/// after a client disconnect or request timeout
+struct CancelOnDrop {
+    token: CancelToken,
+    operation: &'static str,
+    finished: bool,
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if !self.finished {
+            log::warn!("cancel: {} abandoned by client, cancelling", self.operation);
+            metrics::STORAGE_OPERATIONS_CANCELLED_TOTAL
+                .with_label_values(&[self.operation])
+                .inc();
+            self.token.cancel();
+        }
+    }
+}
+
+/// Run blocking storage work off the async runtime, cancelling it if the caller goes away
+pub(crate) async fn run_blocking<T, F>(operation: &'static str, f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce(&CancelToken) -> T + Send + 'static,
+{
+    let mut guard = CancelOnDrop {
+        token: CancelToken::default(),
+        operation,
+        finished: false,
+    };
+    let token = guard.token.clone();
+
+    let result = tokio::task::spawn_blocking(move || f(&token))
+        .await
+        .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+
+    guard.finished = true;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_check() {
+        let token = CancelToken::default();
+        assert!(token.check().is_ok());
+
+        token.clone().cancel();
+        assert_eq!(
+            token.check().unwrap_err().kind(),
+            std::io::ErrorKind::Interrupted
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dropped_caller_cancels_work() {
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+
+        let task = tokio::spawn(run_blocking("test", move |token| {
+            started_tx.send(()).unwrap();
+            while !token.is_cancelled() {
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+            done_tx.send(()).unwrap();
+        }));
+
+        tokio::task::spawn_blocking(move || started_rx.recv().unwrap())
+            .await
+            .unwrap();
+        task.abort();
+
+        tokio::task::spawn_blocking(move || {
+            done_rx
+                .recv_timeout(std::time::Duration::from_secs(5))
+                .expect("blocking work was not cancelled")
+        })
+        .await
+        .unwrap();
+    }
+}
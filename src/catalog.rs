@@ -0,0 +1,73 @@
+// | Method         | API Endpoint                                                 | Success     | Failure           |
+// | -------------- | ------------------------------------------------------------ | ----------- | ----------------- |
+// | `GET`          | `/v2/_catalog`                                               | `200`       | `401`             |
+// | `GET`          | `/v2/_catalog?n=<integer>&last=<string>`                     | `200`       | `401`             |
+//
+// Not part of the OCI distribution spec, but implemented by most registries and expected by UIs
+// and mirroring tools. Only repositories the caller may list (pull, unless --strict-list) are shown.
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use std::sync::Arc;
+
+use crate::{
+    args::Capability,
+    auth, cancel,
+    pagination::{self, PageQuery},
+    permissions, response, state, storage,
+};
+
+// GET /v2/_catalog?n=<integer>&last=<string>
+pub(crate) async fn get_catalog(
+    State(state): State<Arc<state::App>>,
+    Query(params): Query<PageQuery>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    if !state.args.is_enabled(Capability::Catalog) {
+        return response::capability_disabled(StatusCode::NOT_FOUND, "catalog");
+    }
+
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(user) => user,
+        Err(_) => return response::unauthorized(state.args.primary_host()),
+    };
+
+    let repositories =
+        cancel::run_blocking("list_repositories", |_| storage::list_repositories()).await;
+    let repositories = match repositories {
+        Ok(repositories) => repositories,
+        Err(e) => {
+            log::error!("catalog: failed to list repositories: {}", e);
+            return response::internal_error();
+        }
+    };
+
+    // Filter before paginating so pages are not short on hidden repositories
+    let visible: Vec<String> = repositories
+        .into_iter()
+        .filter(|repository| {
+            state.repositories.is_public(repository)
+                || permissions::can_list(&user, repository, state.args.strict_list)
+        })
+        .collect();
+
+    log::info!(
+        "catalog/get_catalog: user: {}, visible repositories: {}",
+        user.username,
+        visible.len()
+    );
+
+    // Pages are capped so large registries are listed in several requests
+    let page = params.page(visible, |r| r);
+    let response_body = serde_json::json!({
+        "repositories": page.items,
+    });
+    pagination::response(
+        response_body.to_string(),
+        pagination::next_link("/v2/_catalog", &page),
+    )
+}
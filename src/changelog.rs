@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const CHANGELOG_PATH: &str = "./tmp/changelog.jsonl";
+const MAX_ENTRIES: usize = 100_000;
+
+/// A storage mutation replayed by standby instances
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    ManifestPut {
+        org: String,
+        repo: String,
+        reference: String,
+    },
+    ManifestDelete {
+        org: String,
+        repo: String,
+        reference: String,
+    },
+    BlobPut {
+        org: String,
+        repo: String,
+        digest: String,
+    },
+    BlobDelete {
+        org: String,
+        repo: String,
+        digest: String,
+    },
+    UsersUpdated,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeEntry {
+    pub seq: u64,
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+/// Returned when the requested position has been compacted away
+#[derive(Debug)]
+pub struct ChangesCompacted {
+    pub first_seq: u64,
+}
+
+struct ChangeLog {
+    next_seq: u64,
+    first_seq: u64,
+    count: usize,
+}
+
+lazy_static::lazy_static! {
+    static ref CHANGELOG: Mutex<ChangeLog> = Mutex::new(ChangeLog::load());
+}
+
+fn read_entries() -> Vec<ChangeEntry> {
+    fs::read_to_string(CHANGELOG_PATH)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+impl ChangeLog {
+    fn load() -> Self {
+        let entries = read_entries();
+        let first_seq = entries.first().map(|e| e.seq).unwrap_or(1);
+        let next_seq = entries.last().map(|e| e.seq + 1).unwrap_or(1);
+
+        ChangeLog {
+            next_seq,
+            first_seq,
+            count: entries.len(),
+        }
+    }
+
+    fn append(&mut self, change: Change) -> std::io::Result<()> {
+        let entry = ChangeEntry {
+            seq: self.next_seq,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            change,
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(CHANGELOG_PATH)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+
+        self.next_seq += 1;
+        self.count += 1;
+
+        if self.count > MAX_ENTRIES {
+            self.compact()?;
+        }
+
+        Ok(())
+    }
+
+    /// Keep the newest half of the log so followers have a window to catch up
+    fn compact(&mut self) -> std::io::Result<()> {
+        let entries = read_entries();
+        let keep = &entries[entries.len().saturating_sub(MAX_ENTRIES / 2)..];
+
+        let mut content = String::new();
+        for entry in keep {
+            content.push_str(&serde_json::to_string(entry)?);
+            content.push('\n');
+        }
+        fs::write(CHANGELOG_PATH, content)?;
+
+        self.first_seq = keep.first().map(|e| e.seq).unwrap_or(self.next_seq);
+        self.count = keep.len();
+        log::info!(
+            "changelog: compacted to {} entries starting at seq {}",
+            self.count,
+            self.first_seq
+        );
+
+        Ok(())
+    }
+}
+
+/// Append a change to the log; failures are logged and never fail the mutation itself
+pub fn record(change: Change) {
+    let mut changelog = CHANGELOG.lock().unwrap();
+    if let Err(e) = changelog.append(change) {
+        log::error!("changelog: failed to record change: {}", e);
+    }
+}
+
+/// Sequence number of the most recently recorded change (0 when empty)
+pub fn latest_seq() -> u64 {
+    CHANGELOG.lock().unwrap().next_seq - 1
+}
+
+/// Changes recorded after `since`, oldest first
+pub fn changes_since(since: u64, limit: usize) -> Result<Vec<ChangeEntry>, ChangesCompacted> {
+    let changelog = CHANGELOG.lock().unwrap();
+    if since + 1 < changelog.first_seq {
+        return Err(ChangesCompacted {
+            first_seq: changelog.first_seq,
+        });
+    }
+
+    Ok(read_entries()
+        .into_iter()
+        .filter(|e| e.seq > since)
+        .take(limit)
+        .collect())
+}
@@ -0,0 +1,123 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    admin,
+    audit::{AuditEvent, Outcome},
+    metrics, state, storage,
+};
+
+/// Remove lapsed permissions from all users, persisting and auditing each removal.
+/// Returns the number of entries removed.
+pub(crate) async fn run_cleanup(state: &Arc<state::App>) -> usize {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut removed = Vec::new();
+    {
+        let mut users = state.users.lock().await;
+        if !users
+            .iter()
+            .any(|u| u.permissions.iter().any(|p| p.is_expired(now)))
+        {
+            return 0;
+        }
+
+        *users = users
+            .drain()
+            .map(|mut user| {
+                let (expired, kept) = user
+                    .permissions
+                    .into_iter()
+                    .partition(|p| p.is_expired(now));
+                user.permissions = kept;
+                removed.extend(
+                    expired
+                        .into_iter()
+                        .map(|p: state::Permission| (user.username.clone(), p)),
+                );
+                user
+            })
+            .collect();
+    }
+
+    if let Err(e) = admin::save_users(state).await {
+        // Expired entries are already ignored by permission checks; retry on the next run
+        log::error!("cleanup: failed to save users: {}", e);
+    }
+
+    for (username, permission) in &removed {
+        log::info!(
+            "cleanup: removed lapsed permission for user {}: {:?}",
+            username,
+            permission
+        );
+        state.audit.record(
+            AuditEvent::new("system", "permission.expire", username, Outcome::Success)
+                .with_detail(serde_json::to_string(permission).unwrap_or_default()),
+        );
+    }
+    metrics::AUTH_ENTRIES_REMOVED_TOTAL
+        .with_label_values(&["permission"])
+        .inc_by(removed.len() as u64);
+
+    removed.len()
+}
+
+/// Start the periodic cleanup of stale auth entries
+pub(crate) fn spawn_cleanup(state: Arc<state::App>) {
+    let interval_secs = state.args.credential_cleanup_interval_secs;
+    if interval_secs == 0 {
+        return;
+    }
+    // Users are replicated from the primary, which runs its own cleanup
+    if state.args.standby_of.is_some() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            let removed = run_cleanup(&state).await;
+            if removed > 0 {
+                log::info!("cleanup: removed {} stale auth entries", removed);
+            }
+        }
+    });
+}
+
+/// Start the periodic removal of upload sessions idle for longer than
+/// `--upload-session-ttl-secs`
+pub(crate) fn spawn_upload_expiry(state: Arc<state::App>) {
+    let ttl_secs = state.args.upload_session_ttl_secs;
+    if ttl_secs == 0 {
+        return;
+    }
+    let ttl = Duration::from_secs(ttl_secs);
+
+    tokio::spawn(async move {
+        // Sessions outlive the TTL by at most half of it, checking at least every 15 minutes
+        let mut interval = tokio::time::interval(Duration::from_secs((ttl_secs / 2).clamp(1, 900)));
+        loop {
+            interval.tick().await;
+            match tokio::task::spawn_blocking(move || storage::expire_upload_sessions(ttl)).await {
+                Ok(Ok((0, _))) => {}
+                Ok(Ok((sessions, bytes))) => {
+                    log::info!(
+                        "cleanup: deleted {} expired upload sessions ({} bytes)",
+                        sessions,
+                        bytes
+                    );
+                    metrics::UPLOAD_SESSIONS_EXPIRED_TOTAL.inc_by(sessions as u64);
+                }
+                Ok(Err(e)) => log::error!("cleanup: failed to expire upload sessions: {}", e),
+                Err(e) => log::error!("cleanup: upload expiry task failed: {}", e),
+            }
+        }
+    });
+}
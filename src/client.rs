@@ -0,0 +1,914 @@
+//! Typed blocking client for the grain admin API (`/admin/v1`)
+//!
+//! Request and response types mirror the schemas published at `/api-docs/openapi.json`.
+
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+
+pub const ADMIN_API_PREFIX: &str = "/admin/v1";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Permission {
+    pub repository: String,
+    pub tag: String,
+    pub actions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserSummary {
+    pub username: String,
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+    #[serde(default)]
+    pub admin: bool,
+    #[serde(default)]
+    pub admin_orgs: Vec<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateUserRequest {
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+    /// Grants the admin API
+    #[serde(default)]
+    pub admin: bool,
+    /// Orgs the user administers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admin_orgs: Vec<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+/// New password and permission set of an existing user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateUserRequest {
+    pub password: String,
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+    /// Grants or revokes the admin API; unchanged when None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin: Option<bool>,
+    /// Replaces the orgs the user administers; unchanged when None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_orgs: Option<Vec<String>>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+/// A named permission set users and groups reference
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AccessRole {
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AccessGroup {
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Roles {
+    #[serde(default)]
+    pub roles: BTreeMap<String, AccessRole>,
+    #[serde(default)]
+    pub groups: BTreeMap<String, AccessGroup>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GcStats {
+    pub blobs_scanned: usize,
+    pub manifests_scanned: usize,
+    pub blobs_referenced: usize,
+    pub blobs_unreferenced: usize,
+    pub blobs_deleted: usize,
+    pub bytes_freed: u64,
+    pub duration_seconds: u64,
+    #[serde(default)]
+    pub blobs_shared: usize,
+    #[serde(default)]
+    pub blobs_mounted: usize,
+    #[serde(default)]
+    pub tags_expired: usize,
+    #[serde(default)]
+    pub tags_exempt: usize,
+    #[serde(default)]
+    pub manifests_expired: usize,
+    #[serde(default)]
+    pub manifests_untagged: usize,
+    #[serde(default)]
+    pub manifests_deleted: usize,
+    #[serde(default)]
+    pub blobs_in_use: usize,
+    #[serde(default)]
+    pub journal_recovered: usize,
+    #[serde(default)]
+    pub journal_pending: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UntaggedManifests {
+    #[default]
+    Keep,
+    Delete,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GcPolicy {
+    pub grace_period_hours: u64,
+    /// Minutes between scheduled runs (0 disables scheduled runs)
+    pub schedule_interval_minutes: u64,
+    pub untagged_manifests: UntaggedManifests,
+    pub dry_run: bool,
+}
+
+/// Overrides of the server's GC policy for one run; unset fields follow the policy
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GcRunOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grace_period_hours: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub untagged_manifests: Option<UntaggedManifests>,
+    /// Only delete tags, manifests and blobs of this repository
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+}
+
+/// Current or last GC run, with its counts so far
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GcStatus {
+    pub running: bool,
+    /// `idle`, `recovering`, `expiring`, `marking_manifests`, `scanning_manifests`,
+    /// `scanning_blobs`, `sweeping`, `done` or `failed`
+    pub phase: String,
+    pub dry_run: bool,
+    #[serde(default)]
+    pub started_at: Option<u64>,
+    #[serde(default)]
+    pub finished_at: Option<u64>,
+    pub stats: GcStats,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlatformSummary {
+    pub digest: String,
+    pub os: Option<String>,
+    pub architecture: Option<String>,
+    #[serde(default)]
+    pub variant: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Resolution {
+    pub repository: String,
+    pub reference: String,
+    pub digest: String,
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub platforms: Vec<PlatformSummary>,
+    pub total_size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistorySummary {
+    pub created: Option<String>,
+    pub created_by: Option<String>,
+    #[serde(default)]
+    pub empty_layer: bool,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageConfig {
+    pub repository: String,
+    pub digest: String,
+    pub architecture: Option<String>,
+    pub os: Option<String>,
+    #[serde(default)]
+    pub variant: Option<String>,
+    pub created: Option<String>,
+    pub env: Vec<String>,
+    pub labels: BTreeMap<String, String>,
+    pub entrypoint: Vec<String>,
+    pub cmd: Vec<String>,
+    pub working_dir: Option<String>,
+    pub user: Option<String>,
+    pub exposed_ports: Vec<String>,
+    pub layers: usize,
+    pub history: Vec<HistorySummary>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    #[default]
+    Private,
+    Public,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub tags: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_last: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_days: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Repository {
+    pub name: String,
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub quota_bytes: Option<u64>,
+    #[serde(default)]
+    pub retention: Option<RetentionPolicy>,
+    #[serde(default)]
+    pub pinned_tags: Vec<String>,
+    /// Unix time (seconds) at which a tag expires, set through the admin API
+    #[serde(default)]
+    pub tag_expiry: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub immutable_tags: Vec<String>,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DefaultPermission {
+    pub username: String,
+    pub tag: String,
+    pub actions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateRepositoryRequest {
+    pub name: String,
+    pub visibility: Visibility,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retention: Option<RetentionPolicy>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub immutable_tags: Vec<String>,
+    pub default_permissions: Vec<DefaultPermission>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadKind {
+    Blob,
+    Image,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateDownloadUrlRequest {
+    pub kind: DownloadKind,
+    pub reference: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadUrl {
+    /// Path and query relative to the registry URL
+    pub path: String,
+    pub kind: DownloadKind,
+    pub digest: String,
+    pub expires_at: u64,
+}
+
+#[derive(Deserialize)]
+struct RepositoryList {
+    repositories: Vec<Repository>,
+    #[serde(default)]
+    next: Option<String>,
+}
+
+/// A robot account; its token is only returned when it is created
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Robot {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub permissions: Vec<Permission>,
+    pub created_by: String,
+    pub created_at: u64,
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CreateRobotRequest {
+    pub name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    pub permissions: Vec<Permission>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreatedRobot {
+    #[serde(flatten)]
+    pub robot: Robot,
+    /// Basic auth username, `robot$<name>`
+    pub username: String,
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Primary,
+    Standby,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Peer {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance_id: Option<String>,
+    pub role: Role,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_sync: Option<u64>,
+    pub position: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageInfo {
+    pub backend: String,
+    pub layout_version: u32,
+}
+
+/// Identity, role and replication peers of one instance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterInfo {
+    pub instance_id: String,
+    pub role: Role,
+    pub version: String,
+    pub storage: StorageInfo,
+    pub position: u64,
+    pub peers: Vec<Peer>,
+}
+
+/// Manifest waiting to be copied to a replication peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingPush {
+    pub repository: String,
+    pub reference: String,
+    pub digest: String,
+    pub queued_at: u64,
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicationPeer {
+    pub url: String,
+    pub queued: usize,
+    pub lag_secs: u64,
+    pub replicated: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_success: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    pub pending: Vec<PendingPush>,
+}
+
+/// Replication queue of each `--replicate-to` peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicationStatus {
+    pub peers: Vec<ReplicationPeer>,
+}
+
+/// An archived repository and what its bundle holds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Archive {
+    pub repository: String,
+    pub bundle: String,
+    pub archived_at: u64,
+    pub archived_by: String,
+    pub tags: Vec<String>,
+    pub manifests: usize,
+    pub blobs: usize,
+    pub bytes: u64,
+    pub bundle_bytes: u64,
+}
+
+/// A change a destructive request would make, as reported by its dry run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedChange {
+    pub action: String,
+    pub target: String,
+    #[serde(default)]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DryRunResult {
+    changes: Vec<PlannedChange>,
+}
+
+/// When a tag expires: give either `expires_at` or `ttl_secs`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SetTagExpiryRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagExpiry {
+    pub repository: String,
+    pub tag: String,
+    pub expires_at: u64,
+}
+
+#[derive(Deserialize)]
+struct ArchiveList {
+    archives: Vec<Archive>,
+    #[serde(default)]
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RobotList {
+    robots: Vec<Robot>,
+    #[serde(default)]
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PinList {
+    pinned_tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct UserList {
+    users: Vec<UserSummary>,
+    #[serde(default)]
+    next: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    Transport(reqwest::Error),
+    Api {
+        status: reqwest::StatusCode,
+        body: String,
+    },
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Transport(e) => write!(f, "{}", e),
+            ClientError::Api { status, body } => {
+                let violations = serde_json::from_str::<serde_json::Value>(body)
+                    .ok()
+                    .and_then(|v| v.get("violations").and_then(|v| v.as_array()).cloned());
+
+                match violations {
+                    Some(violations) => {
+                        write!(f, "{} - password rejected by policy:", status)?;
+                        for v in violations.iter().filter_map(|v| v.as_str()) {
+                            write!(f, "\n  - {}", v)?;
+                        }
+                        Ok(())
+                    }
+                    None => write!(f, "{} - {}", status, body),
+                }
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Transport(e)
+    }
+}
+
+/// Admin API client authenticating with HTTP Basic Auth
+pub struct AdminClient {
+    base_url: String,
+    username: String,
+    password: String,
+    http: Client,
+}
+
+impl AdminClient {
+    pub fn new(base_url: &str, username: &str, password: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+            http: Client::new(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}{}", self.base_url, ADMIN_API_PREFIX, path)
+    }
+
+    fn send(&self, request: RequestBuilder) -> Result<Response, ClientError> {
+        let response = request
+            .basic_auth(&self.username, Some(&self.password))
+            .send()?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response
+                .text()
+                .unwrap_or_else(|_| String::from("No response body"));
+            return Err(ClientError::Api { status, body });
+        }
+
+        Ok(response)
+    }
+
+    fn send_json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, ClientError> {
+        Ok(self.send(request)?.json()?)
+    }
+
+    /// Send a destructive request with `?dry_run=true`, returning what it would change
+    fn dry_run(&self, request: RequestBuilder) -> Result<Vec<PlannedChange>, ClientError> {
+        let result: DryRunResult = self.send_json(request.query(&[("dry_run", "true")]))?;
+        Ok(result.changes)
+    }
+
+    /// Fetch every page of a paginated list, `split` returning a page's entries and the cursor
+    /// of the next page
+    fn list_pages<L: DeserializeOwned, T>(
+        &self,
+        path: &str,
+        split: impl Fn(L) -> (Vec<T>, Option<String>),
+    ) -> Result<Vec<T>, ClientError> {
+        let mut items = Vec::new();
+        let mut last: Option<String> = None;
+        loop {
+            let mut request = self.http.get(self.url(path));
+            if let Some(last) = &last {
+                request = request.query(&[("last", last)]);
+            }
+            let (page, next) = split(self.send_json(request)?);
+            items.extend(page);
+            match next {
+                Some(next) => last = Some(next),
+                None => return Ok(items),
+            }
+        }
+    }
+
+    pub fn list_users(&self) -> Result<Vec<UserSummary>, ClientError> {
+        self.list_pages("/users", |list: UserList| (list.users, list.next))
+    }
+
+    pub fn create_user(&self, request: &CreateUserRequest) -> Result<UserSummary, ClientError> {
+        self.send_json(self.http.post(self.url("/users")).json(request))
+    }
+
+    pub fn update_user(
+        &self,
+        username: &str,
+        request: &UpdateUserRequest,
+    ) -> Result<UserSummary, ClientError> {
+        self.send_json(
+            self.http
+                .put(self.url(&format!("/users/{}", username)))
+                .json(request),
+        )
+    }
+
+    /// Set a user's password; changing the client's own sends the password it logs in with
+    pub fn change_password(&self, username: &str, password: &str) -> Result<(), ClientError> {
+        let mut body = serde_json::json!({ "password": password });
+        if username == self.username {
+            body["current_password"] = self.password.clone().into();
+        }
+        self.send(
+            self.http
+                .post(self.url(&format!("/users/{}/password", username)))
+                .json(&body),
+        )?;
+        Ok(())
+    }
+
+    /// Rotate the password this client logs in with; the client keeps using the old one
+    pub fn change_own_password(&self, new_password: &str) -> Result<(), ClientError> {
+        self.send(
+            self.http
+                .post(format!("{}/user/password", self.base_url))
+                .json(&serde_json::json!({
+                    "current_password": self.password,
+                    "new_password": new_password,
+                })),
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_user(&self, username: &str) -> Result<(), ClientError> {
+        self.send(self.http.delete(self.url(&format!("/users/{}", username))))?;
+        Ok(())
+    }
+
+    pub fn plan_delete_user(&self, username: &str) -> Result<Vec<PlannedChange>, ClientError> {
+        self.dry_run(self.http.delete(self.url(&format!("/users/{}", username))))
+    }
+
+    pub fn add_permission(
+        &self,
+        username: &str,
+        permission: &Permission,
+    ) -> Result<Permission, ClientError> {
+        self.send_json(
+            self.http
+                .post(self.url(&format!("/users/{}/permissions", username)))
+                .json(permission),
+        )
+    }
+
+    /// Revoke `actions` (all when None) from the user's permissions on exactly
+    /// `repository`:`tag`, returning the permissions they keep
+    pub fn remove_permission(
+        &self,
+        username: &str,
+        repository: &str,
+        tag: &str,
+        actions: Option<&[String]>,
+    ) -> Result<Vec<Permission>, ClientError> {
+        self.send_json(
+            self.http
+                .delete(self.url(&format!("/users/{}/permissions", username)))
+                .json(&serde_json::json!({
+                    "repository": repository,
+                    "tag": tag,
+                    "actions": actions,
+                })),
+        )
+    }
+
+    pub fn list_robots(&self) -> Result<Vec<Robot>, ClientError> {
+        self.list_pages("/robots", |list: RobotList| (list.robots, list.next))
+    }
+
+    /// Mint a robot token; the returned token is not retrievable later
+    pub fn create_robot(&self, request: &CreateRobotRequest) -> Result<CreatedRobot, ClientError> {
+        self.send_json(self.http.post(self.url("/robots")).json(request))
+    }
+
+    pub fn revoke_robot(&self, name: &str) -> Result<(), ClientError> {
+        self.send(self.http.delete(self.url(&format!("/robots/{}", name))))?;
+        Ok(())
+    }
+
+    pub fn list_roles(&self) -> Result<Roles, ClientError> {
+        self.send_json(self.http.get(self.url("/roles")))
+    }
+
+    /// Create or replace a role
+    pub fn set_role(&self, name: &str, role: &AccessRole) -> Result<AccessRole, ClientError> {
+        self.send_json(
+            self.http
+                .put(self.url(&format!("/roles/{}", name)))
+                .json(role),
+        )
+    }
+
+    /// Delete a role; fails while users or groups reference it
+    pub fn delete_role(&self, name: &str) -> Result<(), ClientError> {
+        self.send(self.http.delete(self.url(&format!("/roles/{}", name))))?;
+        Ok(())
+    }
+
+    /// Create or replace a group
+    pub fn set_group(&self, name: &str, group: &AccessGroup) -> Result<AccessGroup, ClientError> {
+        self.send_json(
+            self.http
+                .put(self.url(&format!("/groups/{}", name)))
+                .json(group),
+        )
+    }
+
+    /// Delete a group; fails while it has members
+    pub fn delete_group(&self, name: &str) -> Result<(), ClientError> {
+        self.send(self.http.delete(self.url(&format!("/groups/{}", name))))?;
+        Ok(())
+    }
+
+    pub fn plan_revoke_robot(&self, name: &str) -> Result<Vec<PlannedChange>, ClientError> {
+        self.dry_run(self.http.delete(self.url(&format!("/robots/{}", name))))
+    }
+
+    pub fn run_gc(&self, options: &GcRunOptions) -> Result<GcStats, ClientError> {
+        self.send_json(self.http.post(self.url("/gc")).query(options))
+    }
+
+    /// Start a GC run in the background; follow it with `gc_status`
+    pub fn start_gc(&self, options: &GcRunOptions) -> Result<(), ClientError> {
+        self.send(
+            self.http
+                .post(self.url("/gc"))
+                .query(options)
+                .query(&[("background", "true")]),
+        )?;
+        Ok(())
+    }
+
+    pub fn gc_status(&self) -> Result<GcStatus, ClientError> {
+        self.send_json(self.http.get(self.url("/gc/status")))
+    }
+
+    pub fn cluster_info(&self) -> Result<ClusterInfo, ClientError> {
+        self.send_json(self.http.get(self.url("/cluster")))
+    }
+
+    pub fn replication_status(&self) -> Result<ReplicationStatus, ClientError> {
+        self.send_json(self.http.get(self.url("/replication/status")))
+    }
+
+    pub fn get_gc_policy(&self) -> Result<GcPolicy, ClientError> {
+        self.send_json(self.http.get(self.url("/gc/policy")))
+    }
+
+    pub fn set_gc_policy(&self, policy: &GcPolicy) -> Result<GcPolicy, ClientError> {
+        self.send_json(self.http.put(self.url("/gc/policy")).json(policy))
+    }
+
+    pub fn list_repositories(&self) -> Result<Vec<Repository>, ClientError> {
+        self.list_pages("/repos", |list: RepositoryList| {
+            (list.repositories, list.next)
+        })
+    }
+
+    /// Declare a repository with its visibility, quota, retention and default permissions
+    pub fn create_repository(
+        &self,
+        request: &CreateRepositoryRequest,
+    ) -> Result<Repository, ClientError> {
+        self.send_json(self.http.post(self.url("/repos")).json(request))
+    }
+
+    pub fn resolve(
+        &self,
+        org: &str,
+        repo: &str,
+        reference: &str,
+    ) -> Result<Resolution, ClientError> {
+        self.send_json(self.http.get(self.url(&format!(
+            "/repos/{}/{}/resolve?ref={}",
+            org, repo, reference
+        ))))
+    }
+
+    /// Architecture, OS, env, labels and history from an image config blob
+    pub fn image_config(
+        &self,
+        org: &str,
+        repo: &str,
+        digest: &str,
+    ) -> Result<ImageConfig, ClientError> {
+        self.send_json(
+            self.http
+                .get(self.url(&format!("/repos/{}/{}/config/{}", org, repo, digest))),
+        )
+    }
+
+    /// Time-limited URL fetching a blob or an image tarball without credentials
+    pub fn create_download_url(
+        &self,
+        org: &str,
+        repo: &str,
+        request: &CreateDownloadUrlRequest,
+    ) -> Result<DownloadUrl, ClientError> {
+        self.send_json(
+            self.http
+                .post(self.url(&format!("/repos/{}/{}/download-urls", org, repo)))
+                .json(request),
+        )
+    }
+
+    /// Tags of a declared repository exempt from its retention policy
+    pub fn list_pins(&self, org: &str, repo: &str) -> Result<Vec<String>, ClientError> {
+        let list: PinList = self.send_json(
+            self.http
+                .get(self.url(&format!("/repos/{}/{}/pins", org, repo))),
+        )?;
+        Ok(list.pinned_tags)
+    }
+
+    pub fn pin_tag(&self, org: &str, repo: &str, tag: &str) -> Result<(), ClientError> {
+        self.send(
+            self.http
+                .put(self.url(&format!("/repos/{}/{}/pins/{}", org, repo, tag))),
+        )?;
+        Ok(())
+    }
+
+    /// Make a tag expire: it stops being served and GC deletes it
+    pub fn set_tag_expiry(
+        &self,
+        org: &str,
+        repo: &str,
+        tag: &str,
+        request: &SetTagExpiryRequest,
+    ) -> Result<TagExpiry, ClientError> {
+        self.send_json(
+            self.http
+                .put(self.url(&format!("/repos/{}/{}/expiry/{}", org, repo, tag)))
+                .json(request),
+        )
+    }
+
+    pub fn clear_tag_expiry(&self, org: &str, repo: &str, tag: &str) -> Result<(), ClientError> {
+        self.send(
+            self.http
+                .delete(self.url(&format!("/repos/{}/{}/expiry/{}", org, repo, tag))),
+        )?;
+        Ok(())
+    }
+
+    pub fn archive_repository(&self, org: &str, repo: &str) -> Result<Archive, ClientError> {
+        self.send_json(
+            self.http
+                .post(self.url(&format!("/repos/{}/{}/archive", org, repo))),
+        )
+    }
+
+    pub fn plan_archive_repository(
+        &self,
+        org: &str,
+        repo: &str,
+    ) -> Result<Vec<PlannedChange>, ClientError> {
+        self.dry_run(
+            self.http
+                .post(self.url(&format!("/repos/{}/{}/archive", org, repo))),
+        )
+    }
+
+    pub fn restore_repository(&self, org: &str, repo: &str) -> Result<Archive, ClientError> {
+        self.send_json(
+            self.http
+                .post(self.url(&format!("/repos/{}/{}/restore", org, repo))),
+        )
+    }
+
+    pub fn list_archives(&self) -> Result<Vec<Archive>, ClientError> {
+        self.list_pages("/archives", |list: ArchiveList| (list.archives, list.next))
+    }
+
+    pub fn unpin_tag(&self, org: &str, repo: &str, tag: &str) -> Result<(), ClientError> {
+        self.send(
+            self.http
+                .delete(self.url(&format!("/repos/{}/{}/pins/{}", org, repo, tag))),
+        )?;
+        Ok(())
+    }
+
+    /// Kubernetes dockerconfigjson Secret with the token of a new pull-only robot for `org/repo`
+    pub fn pull_secret(
+        &self,
+        org: &str,
+        repo: &str,
+        name: Option<&str>,
+        namespace: Option<&str>,
+    ) -> Result<serde_json::Value, ClientError> {
+        let mut request = self
+            .http
+            .post(self.url(&format!("/repos/{}/{}/pull-secret", org, repo)));
+        if let Some(name) = name {
+            request = request.query(&[("name", name)]);
+        }
+        if let Some(namespace) = namespace {
+            request = request.query(&[("namespace", namespace)]);
+        }
+        self.send_json(request)
+    }
+}
@@ -0,0 +1,180 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+use crate::{admin, auth, changelog, metrics, response, state, storage, utils};
+
+const INSTANCE_ID_PATH: &str = "./tmp/instance-id";
+
+/// Header carrying the instance ID of a standby on its sync requests
+pub(crate) const INSTANCE_ID_HEADER: &str = "Grain-Instance-Id";
+
+/// Standbys are listed until they have not synced for this long
+const PEER_EXPIRY_SECS: u64 = 3600;
+
+/// Standbys that synced from this instance, by instance ID
+static STANDBYS: Mutex<BTreeMap<String, Peer>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Primary,
+    Standby,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Peer {
+    /// Not known for the primary of a standby
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance_id: Option<String>,
+    pub role: Role,
+    /// URL of the primary, as given to `--standby-of`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Unix time of the last successful sync between this instance and the peer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_sync: Option<u64>,
+    /// Change log sequence number the standby had applied at that sync
+    pub position: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StorageInfo {
+    /// `fs` or `s3`
+    pub backend: String,
+    pub layout_version: u32,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ClusterInfo {
+    pub instance_id: String,
+    pub role: Role,
+    pub version: String,
+    pub storage: StorageInfo,
+    /// Latest change log sequence number recorded (primary) or applied (standby)
+    pub position: u64,
+    /// The primary of a standby, or the standbys that synced from a primary in the last hour
+    pub peers: Vec<Peer>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// ID of this instance, generated on first start and kept across restarts
+pub(crate) fn load_instance_id() -> String {
+    if let Ok(id) = std::fs::read_to_string(INSTANCE_ID_PATH) {
+        let id = id.trim();
+        if !id.is_empty() {
+            return id.to_string();
+        }
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    if let Err(e) = std::fs::write(INSTANCE_ID_PATH, &id) {
+        log::warn!(
+            "cluster: failed to save instance ID to {}, it changes on restart: {}",
+            INSTANCE_ID_PATH,
+            e
+        );
+    }
+    id
+}
+
+/// Remember a standby from the instance ID header of its sync request
+pub(crate) fn record_standby(headers: &HeaderMap, position: u64) {
+    let Some(instance_id) = headers
+        .get(INSTANCE_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| !id.is_empty())
+    else {
+        return;
+    };
+
+    let peer = Peer {
+        instance_id: Some(instance_id.to_string()),
+        role: Role::Standby,
+        url: None,
+        last_sync: Some(now()),
+        position,
+    };
+    STANDBYS
+        .lock()
+        .unwrap()
+        .insert(instance_id.to_string(), peer);
+}
+
+fn peers(state: &state::App) -> Vec<Peer> {
+    if let Some(primary) = &state.args.standby_of {
+        let last_sync = metrics::STANDBY_LAST_SYNC_TIMESTAMP_SECONDS.get() as u64;
+        return vec![Peer {
+            instance_id: None,
+            role: Role::Primary,
+            url: Some(primary.clone()),
+            last_sync: (last_sync > 0).then_some(last_sync),
+            position: metrics::STANDBY_POSITION.get() as u64,
+        }];
+    }
+
+    let cutoff = now().saturating_sub(PEER_EXPIRY_SECS);
+    let mut standbys = STANDBYS.lock().unwrap();
+    standbys.retain(|_, peer| peer.last_sync.unwrap_or(0) >= cutoff);
+    standbys.values().cloned().collect()
+}
+
+/// Identity, role and replication peers of this instance, to check the topology after a
+/// deployment (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/cluster",
+    responses(
+        (status = 200, description = "Instance identity and peers", body = ClusterInfo),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn cluster_info(State(state): State<Arc<state::App>>, headers: HeaderMap) -> Response {
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(state.args.primary_host()),
+    };
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let (role, position) = match state.args.standby_of {
+        Some(_) => (Role::Standby, metrics::STANDBY_POSITION.get() as u64),
+        None => (Role::Primary, changelog::latest_seq()),
+    };
+    let info = ClusterInfo {
+        instance_id: state.instance_id.clone(),
+        role,
+        version: utils::get_build_info(),
+        storage: StorageInfo {
+            backend: state.args.storage_backend.clone(),
+            layout_version: storage::LAYOUT_VERSION,
+        },
+        position,
+        peers: peers(&state),
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string_pretty(&info).unwrap()))
+        .unwrap()
+}
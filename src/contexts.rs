@@ -0,0 +1,136 @@
+//! Named grainctl contexts: a registry URL and the credentials used with it, saved by
+//! `grainctl login` in `$XDG_CONFIG_HOME/grain/config.json` (default `~/.config/grain/config.json`)
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Context {
+    pub url: String,
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Context used when `--context` is not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_context: Option<String>,
+    #[serde(default)]
+    pub contexts: BTreeMap<String, Context>,
+}
+
+/// Where grainctl keeps its contexts, or None when neither `XDG_CONFIG_HOME` nor `HOME` is set
+pub fn config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("grain").join("config.json"))
+}
+
+impl Config {
+    /// Read the config file, or an empty config when there is none yet
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write the config file, readable by its owner only as it holds passwords
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&tmp)?
+            .write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        fs::rename(&tmp, path)
+    }
+
+    /// The context called `name`, or the current one
+    pub fn context(&self, name: Option<&str>) -> Option<&Context> {
+        self.contexts.get(name.or(self.current_context.as_deref())?)
+    }
+
+    /// Add or replace context `name` and make it the current one
+    pub fn login(&mut self, name: &str, context: Context) {
+        self.contexts.insert(name.to_string(), context);
+        self.current_context = Some(name.to_string());
+    }
+
+    /// Remove context `name`, returning whether it existed
+    pub fn logout(&mut self, name: &str) -> bool {
+        if self.current_context.as_deref() == Some(name) {
+            self.current_context = None;
+        }
+        self.contexts.remove(name).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(url: &str) -> Context {
+        Context {
+            url: url.to_string(),
+            username: "admin".to_string(),
+            password: "secret".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("grain").join("config.json");
+        assert!(Config::load(&path).unwrap().context(None).is_none());
+
+        let mut config = Config::default();
+        config.login("prod", context("https://registry.example.com"));
+        config.login("dev", context("http://localhost:8888"));
+        config.save(&path).unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.current_context.as_deref(), Some("dev"));
+        assert_eq!(config.context(None).unwrap().url, "http://localhost:8888");
+        assert_eq!(
+            config.context(Some("prod")).unwrap().url,
+            "https://registry.example.com"
+        );
+        assert!(config.context(Some("staging")).is_none());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_logout() {
+        let mut config = Config::default();
+        config.login("prod", context("https://registry.example.com"));
+        config.login("dev", context("http://localhost:8888"));
+        assert!(config.logout("dev"));
+        assert!(!config.logout("dev"));
+        assert!(config.context(None).is_none());
+        assert!(config.context(Some("prod")).is_some());
+    }
+}
@@ -0,0 +1,169 @@
+use sha2::{Digest as _, Sha256, Sha512};
+use std::fmt;
+
+/// Digest algorithms accepted for blobs; each gets its own directory in blob storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Algorithm {
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha512 => "sha512",
+        }
+    }
+
+    pub(crate) fn parse(name: &str) -> Option<Self> {
+        match name {
+            "sha256" => Some(Algorithm::Sha256),
+            "sha512" => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    fn hex_len(&self) -> usize {
+        match self {
+            Algorithm::Sha256 => 64,
+            Algorithm::Sha512 => 128,
+        }
+    }
+
+    pub(crate) fn hasher(&self) -> Hasher {
+        match self {
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+}
+
+/// Incremental hasher producing a `Digest` of the chosen algorithm
+#[derive(Clone)]
+pub(crate) enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    pub(crate) fn algorithm(&self) -> Algorithm {
+        match self {
+            Hasher::Sha256(_) => Algorithm::Sha256,
+            Hasher::Sha512(_) => Algorithm::Sha512,
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Sha512(h) => h.update(data),
+        }
+    }
+
+    pub(crate) fn finalize(self) -> Digest {
+        match self {
+            Hasher::Sha256(h) => Digest {
+                algorithm: Algorithm::Sha256,
+                hex: format!("{:x}", h.finalize()),
+            },
+            Hasher::Sha512(h) => Digest {
+                algorithm: Algorithm::Sha512,
+                hex: format!("{:x}", h.finalize()),
+            },
+        }
+    }
+}
+
+/// A validated content digest, displayed as `<algorithm>:<hex>`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Digest {
+    algorithm: Algorithm,
+    hex: String,
+}
+
+impl Digest {
+    /// Parse `<algorithm>:<hex>`; a bare hex string is a sha256 digest (legacy form)
+    pub(crate) fn parse(input: &str) -> Result<Self, String> {
+        let (algorithm, hex) = match input.split_once(':') {
+            Some((name, hex)) => (
+                Algorithm::parse(name)
+                    .ok_or_else(|| format!("unsupported digest algorithm: {}", name))?,
+                hex,
+            ),
+            None => (Algorithm::Sha256, input),
+        };
+
+        if hex.len() != algorithm.hex_len()
+            || !hex
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+        {
+            return Err(format!("invalid {} digest: {}", algorithm.as_str(), input));
+        }
+
+        Ok(Self {
+            algorithm,
+            hex: hex.to_string(),
+        })
+    }
+
+    pub(crate) fn of(algorithm: Algorithm, data: &[u8]) -> Self {
+        let mut hasher = algorithm.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    pub(crate) fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub(crate) fn hex(&self) -> &str {
+        &self.hex
+    }
+
+    /// Storage path relative to a repository directory: `<algorithm>/<hex>`
+    pub(crate) fn path(&self) -> String {
+        format!("{}/{}", self.algorithm.as_str(), self.hex)
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm.as_str(), self.hex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA256_HEX: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn test_parse() {
+        let digest = Digest::parse(&format!("sha256:{}", SHA256_HEX)).unwrap();
+        assert_eq!(digest.algorithm(), Algorithm::Sha256);
+        assert_eq!(digest.hex(), SHA256_HEX);
+        assert_eq!(digest.path(), format!("sha256/{}", SHA256_HEX));
+        assert_eq!(digest.to_string(), format!("sha256:{}", SHA256_HEX));
+
+        // Legacy bare hex
+        assert_eq!(Digest::parse(SHA256_HEX).unwrap(), digest);
+
+        assert!(Digest::parse("md5:abc").is_err());
+        assert!(Digest::parse("sha256:abc").is_err());
+        assert!(Digest::parse(&format!("sha512:{}", SHA256_HEX)).is_err());
+        assert!(Digest::parse(&format!("sha256:{}", SHA256_HEX.to_uppercase())).is_err());
+        assert!(Digest::parse("sha256:../../../etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_of() {
+        assert_eq!(Digest::of(Algorithm::Sha256, b"hello").hex(), SHA256_HEX);
+
+        let sha512 = Digest::of(Algorithm::Sha512, b"hello");
+        assert_eq!(sha512.hex().len(), 128);
+        assert_eq!(Digest::parse(&sha512.to_string()).unwrap(), sha512);
+    }
+}
@@ -0,0 +1,744 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::{
+    collections::HashSet,
+    io,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio_util::io::ReaderStream;
+use utoipa::ToSchema;
+
+use crate::{
+    admin,
+    args::Args,
+    audit::{AuditEvent, Outcome},
+    auth,
+    blobs::TrackedDownload,
+    digest::{Algorithm, Digest},
+    response,
+    secrets::Secret,
+    state, storage,
+};
+
+const IMAGE_INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+
+/// What a download URL gives access to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadKind {
+    /// A single blob, served as is
+    Blob,
+    /// A manifest with everything it references, served as an OCI image layout tarball
+    Image,
+}
+
+impl DownloadKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DownloadKind::Blob => "blob",
+            DownloadKind::Image => "image",
+        }
+    }
+
+    /// Path segment of the download route
+    fn segment(&self) -> &'static str {
+        match self {
+            DownloadKind::Blob => "blobs",
+            DownloadKind::Image => "images",
+        }
+    }
+}
+
+/// Signs and checks download URLs with `--download-url-secret`
+pub(crate) struct Signer {
+    key: Secret,
+}
+
+impl Signer {
+    pub(crate) fn from_args(args: &Args) -> Self {
+        let key = if args.download_url_secret.is_empty() {
+            // URLs then stop working on restart, which is acceptable for short-lived links
+            log::info!("No --download-url-secret set, download URLs are valid until restart");
+            Secret::resolve(&format!("{}{}", uuid::Uuid::new_v4(), uuid::Uuid::new_v4()))
+                .expect("literal secrets always resolve")
+        } else {
+            Secret::resolve_or_exit("--download-url-secret", &args.download_url_secret)
+        };
+        Self { key }
+    }
+
+    fn mac(
+        &self,
+        kind: DownloadKind,
+        repository: &str,
+        digest: &str,
+        expires: u64,
+    ) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.get().as_bytes())
+            .expect("HMAC accepts any key length");
+        mac.update(
+            format!("{}\n{}\n{}\n{}", kind.as_str(), repository, digest, expires).as_bytes(),
+        );
+        mac
+    }
+
+    pub(crate) fn sign(
+        &self,
+        kind: DownloadKind,
+        repository: &str,
+        digest: &str,
+        expires: u64,
+    ) -> String {
+        self.mac(kind, repository, digest, expires)
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Check a URL's signature and expiry, returning why it is refused
+    pub(crate) fn verify(
+        &self,
+        kind: DownloadKind,
+        repository: &str,
+        digest: &str,
+        expires: u64,
+        signature: &str,
+        now: u64,
+    ) -> Result<(), &'static str> {
+        let signature = decode_hex(signature).ok_or("malformed signature")?;
+        self.mac(kind, repository, digest, expires)
+            .verify_slice(&signature)
+            .map_err(|_| "invalid signature")?;
+        if now > expires {
+            return Err("download URL expired");
+        }
+        Ok(())
+    }
+}
+
+fn decode_hex(input: &str) -> Option<Vec<u8>> {
+    if !input.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..input.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(input.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateDownloadUrlRequest {
+    pub kind: DownloadKind,
+    /// Blob digest, or image tag or digest; tags are resolved when the URL is created
+    pub reference: String,
+    /// Lifetime of the URL in seconds (default: `--download-url-ttl`)
+    pub expires_in: Option<u64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DownloadUrl {
+    /// Path and query to append to the registry URL; no credentials are needed to fetch it
+    pub path: String,
+    pub kind: DownloadKind,
+    pub digest: String,
+    /// Unix timestamp (seconds) after which the URL is refused
+    pub expires_at: u64,
+}
+
+/// Create a time-limited download URL for a blob or an image (admin only)
+#[utoipa::path(
+    post,
+    path = "/admin/v1/repos/{org}/{repo}/download-urls",
+    params(
+        ("org" = String, Path, description = "Organization name"),
+        ("repo" = String, Path, description = "Repository name")
+    ),
+    request_body = CreateDownloadUrlRequest,
+    responses(
+        (status = 201, description = "Download URL created", body = DownloadUrl),
+        (status = 400, description = "Bad request - lifetime above --download-url-max-ttl"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - blob or manifest does not exist")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn create_download_url(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let host = state.args.primary_host();
+    let repository = format!("{}/{}", org, repo);
+
+    let admin_user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !admin::is_admin(&admin_user) {
+        return response::forbidden();
+    }
+
+    let request: CreateDownloadUrlRequest = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid request: {}", e)))
+                .unwrap();
+        }
+    };
+
+    let expires_in = request.expires_in.unwrap_or(state.args.download_url_ttl);
+    if expires_in > state.args.download_url_max_ttl {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from(format!(
+                "Invalid request: expires_in exceeds {} seconds",
+                state.args.download_url_max_ttl
+            )))
+            .unwrap();
+    }
+
+    // URLs always name a digest, so a tag moved afterwards does not change what is shared
+    let (kind, reference) = (request.kind, request.reference.clone());
+    let digest = storage::blocking(
+        "resolve_download",
+        &org,
+        &repo,
+        move |org, repo| match kind {
+            DownloadKind::Blob => Digest::parse(&reference)
+                .ok()
+                .filter(|digest| storage::blob_metadata(org, repo, digest).is_ok()),
+            DownloadKind::Image => {
+                let clean_reference = reference.strip_prefix("sha256:").unwrap_or(&reference);
+                storage::read_manifest(org, repo, clean_reference)
+                    .ok()
+                    .map(|bytes| Digest::of(Algorithm::Sha256, &bytes))
+            }
+        },
+    )
+    .await;
+    let digest = match (digest, request.kind) {
+        (Some(digest), _) => digest,
+        (None, DownloadKind::Blob) => return response::blob_unknown(&request.reference),
+        (None, DownloadKind::Image) => return response::manifest_unknown(&request.reference),
+    };
+
+    let expires_at = now_secs() + expires_in;
+    let signature =
+        state
+            .download_signer
+            .sign(request.kind, &repository, &digest.to_string(), expires_at);
+    let url = DownloadUrl {
+        path: format!(
+            "/downloads/{}/{}/{}/{}?expires={}&signature={}",
+            org,
+            repo,
+            request.kind.segment(),
+            digest,
+            expires_at,
+            signature
+        ),
+        kind: request.kind,
+        digest: digest.to_string(),
+        expires_at,
+    };
+
+    log::info!(
+        "Admin {} created a {} download URL for {}@{} expiring at {}",
+        admin_user.username,
+        request.kind.as_str(),
+        repository,
+        digest,
+        expires_at
+    );
+    state.audit.record(
+        AuditEvent::new(
+            &admin_user.username,
+            "download_url.create",
+            &format!("{}@{}", repository, digest),
+            Outcome::Success,
+        )
+        .with_detail(format!(
+            "{}, expires at {}",
+            request.kind.as_str(),
+            expires_at
+        )),
+    );
+
+    Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&url).unwrap()))
+        .unwrap()
+}
+
+#[derive(Deserialize)]
+pub(crate) struct DownloadQuery {
+    pub expires: u64,
+    pub signature: String,
+}
+
+/// Check a download URL and record its use, returning the digest it grants or the refusal
+fn authorize_download(
+    state: &state::App,
+    kind: DownloadKind,
+    org: &str,
+    repo: &str,
+    digest: &str,
+    query: &DownloadQuery,
+) -> Result<Digest, &'static str> {
+    let repository = format!("{}/{}", org, repo);
+    let target = format!("{}@{}", repository, digest);
+
+    if let Err(reason) = state.download_signer.verify(
+        kind,
+        &repository,
+        digest,
+        query.expires,
+        &query.signature,
+        now_secs(),
+    ) {
+        log::warn!(
+            "Refused {} download of {}: {}",
+            kind.as_str(),
+            target,
+            reason
+        );
+        state.audit.record(
+            AuditEvent::new("download-url", "download_url.use", &target, Outcome::Denied)
+                .with_detail(reason.to_string()),
+        );
+        return Err(reason);
+    }
+
+    state.audit.record(AuditEvent::new(
+        "download-url",
+        "download_url.use",
+        &target,
+        Outcome::Success,
+    ));
+    // Only digests signed by this registry get here
+    Digest::parse(digest).map_err(|_| "invalid digest")
+}
+
+// GET /downloads/:org/:repo/blobs/:digest?expires=<unix>&signature=<hex>
+pub(crate) async fn download_blob(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo, digest)): Path<(String, String, String)>,
+    Query(query): Query<DownloadQuery>,
+) -> Response<Body> {
+    let digest = match authorize_download(&state, DownloadKind::Blob, &org, &repo, &digest, &query)
+    {
+        Ok(digest) => digest,
+        Err(reason) => return response::download_url_invalid(reason),
+    };
+
+    let blob = digest.clone();
+    let opened = storage::blocking("open_blob", &org, &repo, move |org, repo| {
+        storage::open_blob(org, repo, &blob, 0)
+    })
+    .await;
+    match opened {
+        Ok((file, size)) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Length", size.to_string())
+            .header("Content-Type", "application/octet-stream")
+            .header("Docker-Content-Digest", digest.to_string())
+            .body(Body::from_stream(TrackedDownload::new(
+                file,
+                size,
+                state.args.download_chunk_size,
+            )))
+            .unwrap(),
+        Err(e) => {
+            log::warn!(
+                "downloads/download_blob: blob not found: {}/{}/{}: {}",
+                org,
+                repo,
+                digest,
+                e
+            );
+            response::blob_unknown(&digest.to_string())
+        }
+    }
+}
+
+// GET /downloads/:org/:repo/images/:digest?expires=<unix>&signature=<hex>
+pub(crate) async fn download_image(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo, digest)): Path<(String, String, String)>,
+    Query(query): Query<DownloadQuery>,
+) -> Response<Body> {
+    let digest = match authorize_download(&state, DownloadKind::Image, &org, &repo, &digest, &query)
+    {
+        Ok(digest) => digest,
+        Err(reason) => return response::download_url_invalid(reason),
+    };
+
+    let layout = digest.clone();
+    let archive = storage::blocking("image_layout", &org, &repo, move |org, repo| {
+        image_layout(org, repo, &layout).and_then(TarArchive::new)
+    })
+    .await;
+    let archive = match archive {
+        Ok(archive) => archive,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            log::warn!(
+                "downloads/download_image: {}/{}@{}: {}",
+                org,
+                repo,
+                digest,
+                e
+            );
+            return response::manifest_unknown(&digest.to_string());
+        }
+        Err(e) => {
+            log::error!(
+                "downloads/download_image: {}/{}@{}: {}",
+                org,
+                repo,
+                digest,
+                e
+            );
+            return response::internal_error();
+        }
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Length", archive.len().to_string())
+        .header("Content-Type", "application/x-tar")
+        .header(
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"{}-{}-{}.tar\"",
+                org,
+                repo,
+                &digest.hex()[..12]
+            ),
+        )
+        .body(Body::from_stream(archive.into_stream(org, repo)))
+        .unwrap()
+}
+
+/// Content of an archive entry
+enum EntryData {
+    Bytes(Bytes),
+    /// A stored blob of the exported repository, streamed when reached
+    Blob(Digest),
+}
+
+struct Entry {
+    path: String,
+    size: u64,
+    data: EntryData,
+}
+
+/// Entries of an OCI image layout holding `digest` and everything it references
+fn image_layout(org: &str, repo: &str, digest: &Digest) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut seen = HashSet::new();
+
+    let manifest = storage::read_manifest(org, repo, digest.hex())?;
+    let media_type = serde_json::from_slice::<Value>(&manifest)
+        .ok()
+        .and_then(|v| v.get("mediaType")?.as_str().map(String::from))
+        .unwrap_or_else(|| "application/vnd.oci.image.manifest.v1+json".to_string());
+    let index = serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": IMAGE_INDEX_MEDIA_TYPE,
+        "manifests": [{
+            "mediaType": media_type,
+            "digest": digest.to_string(),
+            "size": manifest.len(),
+        }],
+    });
+
+    entries.push(bytes_entry(
+        "oci-layout",
+        br#"{"imageLayoutVersion":"1.0.0"}"#.to_vec(),
+    ));
+    entries.push(bytes_entry("index.json", index.to_string().into_bytes()));
+    add_manifest(org, repo, digest, manifest, &mut seen, &mut entries)?;
+    Ok(entries)
+}
+
+fn bytes_entry(path: &str, data: Vec<u8>) -> Entry {
+    Entry {
+        path: path.to_string(),
+        size: data.len() as u64,
+        data: EntryData::Bytes(Bytes::from(data)),
+    }
+}
+
+/// Add a manifest and, recursively, the manifests and blobs it references
+fn add_manifest(
+    org: &str,
+    repo: &str,
+    digest: &Digest,
+    manifest: Vec<u8>,
+    seen: &mut HashSet<Digest>,
+    entries: &mut Vec<Entry>,
+) -> io::Result<()> {
+    if !seen.insert(digest.clone()) {
+        return Ok(());
+    }
+    let value: Value = serde_json::from_slice(&manifest)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    entries.push(bytes_entry(&format!("blobs/{}", digest.path()), manifest));
+
+    let descriptor_digest = |descriptor: &Value| {
+        descriptor
+            .get("digest")
+            .and_then(|d| d.as_str())
+            .and_then(|d| Digest::parse(d).ok())
+    };
+
+    for child in value
+        .get("manifests")
+        .and_then(|m| m.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let Some(child_digest) = descriptor_digest(child) else {
+            continue;
+        };
+        let child_manifest = storage::read_manifest(org, repo, child_digest.hex())?;
+        add_manifest(org, repo, &child_digest, child_manifest, seen, entries)?;
+    }
+
+    let blobs = value.get("config").into_iter().chain(
+        value
+            .get("layers")
+            .and_then(|l| l.as_array())
+            .into_iter()
+            .flatten(),
+    );
+    for blob in blobs {
+        let Some(blob_digest) = descriptor_digest(blob) else {
+            continue;
+        };
+        if !seen.insert(blob_digest.clone()) {
+            continue;
+        }
+        let size = storage::blob_metadata(org, repo, &blob_digest)?.size;
+        entries.push(Entry {
+            path: format!("blobs/{}", blob_digest.path()),
+            size,
+            data: EntryData::Blob(blob_digest),
+        });
+    }
+    Ok(())
+}
+
+const BLOCK: usize = 512;
+
+/// Uncompressed GNU tar streamed entry by entry, with headers built by the `tar` crate
+struct TarArchive {
+    /// Header block(s) of each entry, ahead of its data
+    entries: Vec<(Vec<u8>, Entry)>,
+}
+
+impl TarArchive {
+    fn new(entries: Vec<Entry>) -> io::Result<Self> {
+        let entries = entries
+            .into_iter()
+            .map(|entry| Ok((tar_header(&entry.path, entry.size)?, entry)))
+            .collect::<io::Result<_>>()?;
+        Ok(Self { entries })
+    }
+
+    /// Total size in bytes, known before streaming so clients see progress
+    fn len(&self) -> u64 {
+        let entries: u64 = self
+            .entries
+            .iter()
+            .map(|(header, e)| header.len() as u64 + e.size + padding(e.size) as u64)
+            .sum();
+        entries + 2 * BLOCK as u64
+    }
+
+    fn into_stream(self, org: String, repo: String) -> BoxStream<'static, io::Result<Bytes>> {
+        let parts = self.entries.into_iter().flat_map(move |(header, entry)| {
+            let data = match entry.data {
+                EntryData::Bytes(bytes) => Part::Bytes(bytes),
+                EntryData::Blob(digest) => Part::Blob(org.clone(), repo.clone(), digest),
+            };
+            [
+                Part::Bytes(Bytes::from(header)),
+                data,
+                Part::Bytes(Bytes::from(vec![0u8; padding(entry.size)])),
+            ]
+        });
+        let end = Part::Bytes(Bytes::from(vec![0u8; 2 * BLOCK]));
+
+        stream::iter(parts.chain(std::iter::once(end)))
+            .flat_map(|part| -> BoxStream<'static, io::Result<Bytes>> {
+                match part {
+                    Part::Bytes(bytes) => stream::once(async move { Ok(bytes) }).boxed(),
+                    // Blobs are opened only when reached, so one open file at a time
+                    Part::Blob(org, repo, digest) => stream::once(async move {
+                        storage::blocking("open_blob", &org, &repo, move |org, repo| {
+                            storage::open_blob(org, repo, &digest, 0)
+                        })
+                        .await
+                    })
+                    .flat_map(|opened| -> BoxStream<'static, io::Result<Bytes>> {
+                        match opened {
+                            Ok((reader, _)) => ReaderStream::new(reader).boxed(),
+                            Err(e) => stream::once(async move { Err(e) }).boxed(),
+                        }
+                    })
+                    .boxed(),
+                }
+            })
+            .boxed()
+    }
+}
+
+enum Part {
+    Bytes(Bytes),
+    Blob(String, String, Digest),
+}
+
+fn padding(size: u64) -> usize {
+    (BLOCK - (size % BLOCK as u64) as usize) % BLOCK
+}
+
+/// Header block(s) of a regular file: paths over 100 bytes get a GNU long name entry first,
+/// and sizes of 8 GiB and more are stored in base-256
+fn tar_header(path: &str, size: u64) -> io::Result<Vec<u8>> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_size(size);
+    // No data is appended, so the builder writes the header blocks alone
+    let mut builder = tar::Builder::new(Vec::new());
+    builder.append_data(&mut header, path, io::empty())?;
+    Ok(builder.get_ref().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer() -> Signer {
+        Signer {
+            key: Secret::resolve("test-key").unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signer = signer();
+        let digest = format!("sha256:{}", "a".repeat(64));
+        let signature = signer.sign(DownloadKind::Blob, "org/repo", &digest, 100);
+
+        assert!(signer
+            .verify(DownloadKind::Blob, "org/repo", &digest, 100, &signature, 50)
+            .is_ok());
+        assert_eq!(
+            signer.verify(
+                DownloadKind::Blob,
+                "org/repo",
+                &digest,
+                100,
+                &signature,
+                101
+            ),
+            Err("download URL expired")
+        );
+        // Any change to the signed fields invalidates the signature
+        assert_eq!(
+            signer.verify(DownloadKind::Blob, "org/repo", &digest, 200, &signature, 50),
+            Err("invalid signature")
+        );
+        assert_eq!(
+            signer.verify(
+                DownloadKind::Image,
+                "org/repo",
+                &digest,
+                100,
+                &signature,
+                50
+            ),
+            Err("invalid signature")
+        );
+        assert_eq!(
+            signer.verify(
+                DownloadKind::Blob,
+                "org/other",
+                &digest,
+                100,
+                &signature,
+                50
+            ),
+            Err("invalid signature")
+        );
+        assert_eq!(
+            signer.verify(DownloadKind::Blob, "org/repo", &digest, 100, "zz", 50),
+            Err("malformed signature")
+        );
+    }
+
+    #[test]
+    fn test_tar_header() {
+        let read = |header: Vec<u8>, size: u64| {
+            let mut archive = header;
+            archive.resize(archive.len() + size as usize + padding(size), 0);
+            archive.resize(archive.len() + 2 * BLOCK, 0);
+            let mut archive = tar::Archive::new(archive.as_slice());
+            let entries = archive.entries().unwrap();
+            entries
+                .map(|entry| {
+                    let entry = entry.unwrap();
+                    let path = entry.path().unwrap().display().to_string();
+                    (path, entry.header().entry_size().unwrap())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let header = tar_header("index.json", 1234).unwrap();
+        assert_eq!(header.len(), BLOCK);
+        assert_eq!(read(header, 1234), [("index.json".to_string(), 1234)]);
+
+        // sha512 blob paths exceed the ustar name field
+        let long = format!("blobs/sha512/{}", "b".repeat(128));
+        let header = tar_header(&long, 1).unwrap();
+        assert_eq!(header.len(), 3 * BLOCK);
+        assert_eq!(read(header, 1), [(long, 1)]);
+
+        // Multi-GB layers overflow the 11 octal digits of the size field
+        let size = 9 << 30;
+        let header = tar_header("blobs/sha256/layer", size).unwrap();
+        assert_eq!(header.len(), BLOCK);
+        let header = tar::Header::from_byte_slice(&header);
+        assert_eq!(header.entry_size().unwrap(), size);
+    }
+}
@@ -0,0 +1,60 @@
+//! `?dry_run=true` on destructive admin endpoints. The request is authorized and validated as
+//! usual (so a dry run fails the way the real request would), then answered with the changes
+//! it would make instead of making them.
+
+use axum::{body::Body, http::StatusCode, response::Response};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct DryRunQuery {
+    /// Report what would change without changing it
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// A change a destructive request would make, named like the audit event it would record
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PlannedChange {
+    /// Audit action, e.g. `user.delete`
+    pub action: String,
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl PlannedChange {
+    pub(crate) fn new(action: &str, target: &str) -> Self {
+        Self {
+            action: action.to_string(),
+            target: target.to_string(),
+            detail: None,
+        }
+    }
+
+    pub(crate) fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// Response of a dry run
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DryRunResult {
+    /// Always `true`, so a dry run response cannot be mistaken for a real one
+    pub dry_run: bool,
+    pub changes: Vec<PlannedChange>,
+}
+
+/// `200 OK` listing `changes`, none of which were made
+pub(crate) fn response(changes: Vec<PlannedChange>) -> Response {
+    let result = DryRunResult {
+        dry_run: true,
+        changes,
+    };
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string_pretty(&result).unwrap()))
+        .unwrap()
+}
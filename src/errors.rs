@@ -47,6 +47,9 @@ pub enum ErrorCode {
 
     #[serde(rename = "UNSUPPORTED")]
     Unsupported,
+
+    #[serde(rename = "TOOMANYREQUESTS")]
+    TooManyRequests,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -117,6 +120,7 @@ impl IntoResponse for OciErrorResponse {
                 | ErrorCode::SizeInvalid
                 | ErrorCode::BlobUploadInvalid => StatusCode::BAD_REQUEST,
                 ErrorCode::Unsupported => StatusCode::METHOD_NOT_ALLOWED,
+                ErrorCode::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
                 ErrorCode::ManifestBlobUnknown | ErrorCode::ManifestUnverified => {
                     StatusCode::BAD_REQUEST
                 }
@@ -1,12 +1,99 @@
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
-use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+use crate::audit::{AuditEvent, Outcome};
+use crate::digest::{Algorithm, Digest};
+use crate::repositories::{self, Repository};
+use crate::{state, storage};
+
+pub(crate) mod in_use;
+mod journal;
+pub(crate) mod progress;
+
+use journal::{Deletion, Journal};
+use progress::GcPhase;
 
 type BlobLocation = (String, String, u64); // (org, repo, size)
-type UnreferencedBlob = (String, String, String, u64); // (org, repo, digest, size)
+type UnreferencedBlob = (String, String, Digest, u64); // (org, repo, digest, size)
+type UntaggedManifest = (String, String, String); // (org, repo, hex)
+
+/// How often the scheduler checks whether a run is due
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What garbage collection does with digest-addressed manifests that no tag reaches
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum UntaggedManifests {
+    /// Keep them, along with the blobs they reference
+    #[default]
+    Keep,
+    /// Delete them once past the grace period, so their blobs are collected too
+    Delete,
+}
+
+/// Registry-wide garbage collection settings, used by scheduled runs and as the defaults of
+/// manual runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct GcPolicy {
+    /// Hours an unreferenced blob or untagged manifest is kept before it may be deleted
+    pub grace_period_hours: u64,
+    /// Minutes between scheduled runs (0 disables scheduled runs)
+    pub schedule_interval_minutes: u64,
+    pub untagged_manifests: UntaggedManifests,
+    /// Only report what would be deleted
+    pub dry_run: bool,
+}
+
+impl Default for GcPolicy {
+    fn default() -> Self {
+        Self {
+            grace_period_hours: 24,
+            schedule_interval_minutes: 0,
+            untagged_manifests: UntaggedManifests::Keep,
+            dry_run: false,
+        }
+    }
+}
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The GC policy, persisted to `--gc-policy-file`
+pub(crate) struct GcPolicyStore {
+    path: String,
+    policy: RwLock<GcPolicy>,
+}
+
+impl GcPolicyStore {
+    pub(crate) fn load(path: &str) -> Result<Self, String> {
+        let policy = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("failed to parse GC policy file {}: {}", path, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => GcPolicy::default(),
+            Err(e) => return Err(format!("failed to read GC policy file {}: {}", path, e)),
+        };
+
+        Ok(Self {
+            path: path.to_string(),
+            policy: RwLock::new(policy),
+        })
+    }
+
+    pub(crate) fn get(&self) -> GcPolicy {
+        self.policy.read().unwrap().clone()
+    }
+
+    /// Replace the policy, persisting it first
+    pub(crate) fn set(&self, policy: GcPolicy) -> Result<(), std::io::Error> {
+        let mut current = self.policy.write().unwrap();
+        std::fs::write(&self.path, serde_json::to_string_pretty(&policy)?)?;
+        *current = policy;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct GcStats {
     pub blobs_scanned: usize,
     pub manifests_scanned: usize,
@@ -15,28 +102,106 @@ pub struct GcStats {
     pub blobs_deleted: usize,
     pub bytes_freed: u64,
     pub duration_seconds: u64,
+    /// Digests stored in more than one repository
+    pub blobs_shared: usize,
+    /// Repository copies created by cross-repository mount
+    pub blobs_mounted: usize,
+    /// Tags deleted (or, in a dry run, due for deletion) by repository retention policies
+    pub tags_expired: usize,
+    /// Tags exempt from retention policies: pinned, or annotated `grain.keep=true`
+    pub tags_exempt: usize,
+    /// Tags and manifests deleted (or, in a dry run, due for deletion) because they expired,
+    /// by a `grain.expires-at` annotation or an expiry set on the tag
+    pub manifests_expired: usize,
+    /// Digest-addressed manifests no tag reaches, whatever the policy
+    pub manifests_untagged: usize,
+    /// Untagged manifests deleted
+    pub manifests_deleted: usize,
+    /// Unreferenced blobs kept because a push uploaded, mounted, checked for or referenced them
+    /// during the run or its grace period
+    pub blobs_in_use: usize,
+    /// Deletions left by an interrupted run that this run completed
+    pub journal_recovered: usize,
+    /// Deletions still recorded in the journal after this run (interrupted deletions a dry run
+    /// left alone, or that could not be completed)
+    pub journal_pending: usize,
 }
 
-/// Run garbage collection with optional dry-run mode
+/// Run garbage collection as `policy` says. Retention policies of `repositories` expire tags
+/// first, so blobs only they referenced are collected in the same run. With `scope`
+/// (`<org>/<repo>`, or `<org>` for all of its repositories), only tags, manifests and blobs of
+/// the scope are deleted; manifests of every repository still keep the blobs they reference.
 pub fn run_gc(
-    dry_run: bool,
-    grace_period_hours: u64,
+    policy: &GcPolicy,
+    repositories: &[Repository],
+    scope: Option<&str>,
 ) -> Result<GcStats, Box<dyn std::error::Error>> {
-    let start_time = SystemTime::now();
+    log::info!("Starting garbage collection (dry_run: {})", policy.dry_run);
+
+    // Also waits for a concurrent run to finish, and completes whatever a crashed one left
+    let mut journal = Journal::lock();
+    progress::start(policy.dry_run);
+    let result = collect(policy, repositories, scope, &mut journal);
+    progress::finish(result.as_ref().map_err(|e| e.to_string()));
+    let stats = result?;
+    journal.close()?;
+    Ok(stats)
+}
 
-    let mut stats = GcStats {
-        blobs_scanned: 0,
-        manifests_scanned: 0,
-        blobs_referenced: 0,
-        blobs_unreferenced: 0,
-        blobs_deleted: 0,
-        bytes_freed: 0,
-        duration_seconds: 0,
+fn collect(
+    policy: &GcPolicy,
+    repositories: &[Repository],
+    scope: Option<&str>,
+    journal: &mut Journal,
+) -> Result<GcStats, Box<dyn std::error::Error>> {
+    let in_scope = |org: &str, repo: &str| {
+        scope.is_none_or(|scope| match scope.split_once('/') {
+            Some(scope) => scope == (org, repo),
+            None => scope == org,
+        })
     };
+    let repositories: Vec<Repository> = repositories
+        .iter()
+        .filter(|r| {
+            r.name
+                .split_once('/')
+                .is_some_and(|(org, repo)| in_scope(org, repo))
+        })
+        .cloned()
+        .collect();
+    let start_time = SystemTime::now();
+    // Pushes from here on are protected, even if their manifests arrive after the mark phase
+    let started = Instant::now();
+    let dry_run = policy.dry_run;
 
-    log::info!("Starting garbage collection (dry_run: {})", dry_run);
+    let mut stats = GcStats::default();
+    if dry_run {
+        stats.journal_pending = journal.pending().iter().map(Vec::len).sum();
+    } else {
+        (stats.journal_recovered, stats.journal_pending) = journal.recover()?;
+    }
+
+    progress::phase(GcPhase::Expiring, &stats);
+    expire_manifests(&repositories, &in_scope, dry_run, journal, &mut stats)?;
+    apply_retention(&repositories, dry_run, journal, &mut stats)?;
+
+    // Untagged manifests are always marked, but only swept when the policy says so
+    progress::phase(GcPhase::MarkingManifests, &stats);
+    let mut untagged_manifests = mark_untagged_manifests()?;
+    untagged_manifests.retain(|(org, repo, _)| in_scope(org, repo));
+    stats.manifests_untagged = untagged_manifests.len();
+    log::info!("Identified {} untagged manifests", stats.manifests_untagged);
+    if !dry_run && policy.untagged_manifests == UntaggedManifests::Delete {
+        sweep_untagged_manifests(
+            &untagged_manifests,
+            policy.grace_period_hours,
+            journal,
+            &mut stats,
+        )?;
+    }
 
     // Step 1: Scan all manifests and build referenced blob set
+    progress::phase(GcPhase::ScanningManifests, &stats);
     let referenced_blobs = scan_manifests(&mut stats)?;
     stats.blobs_referenced = referenced_blobs.len();
 
@@ -47,19 +212,36 @@ pub fn run_gc(
     );
 
     // Step 2: Scan all blobs and identify unreferenced ones
+    progress::phase(GcPhase::ScanningBlobs, &stats);
     let all_blobs = scan_all_blobs(&mut stats)?;
 
     log::info!("Scanned {} total blobs", stats.blobs_scanned);
 
+    stats.blobs_shared = all_blobs.values().filter(|l| l.len() > 1).count();
+    stats.blobs_mounted = storage::list_mount_records()?.len();
+    log::info!(
+        "Found {} blobs shared across repositories ({} copies via mount)",
+        stats.blobs_shared,
+        stats.blobs_mounted
+    );
+
     // Step 3: Mark unreferenced blobs
-    let unreferenced_blobs = mark_unreferenced_blobs(&all_blobs, &referenced_blobs)?;
+    let mut unreferenced_blobs = mark_unreferenced_blobs(&all_blobs, &referenced_blobs)?;
+    unreferenced_blobs.retain(|(org, repo, _, _)| in_scope(org, repo));
     stats.blobs_unreferenced = unreferenced_blobs.len();
 
     log::info!("Identified {} unreferenced blobs", stats.blobs_unreferenced);
 
     // Step 4: Sweep marked blobs that are past grace period
     if !dry_run {
-        sweep_marked_blobs(&unreferenced_blobs, grace_period_hours, &mut stats)?;
+        progress::phase(GcPhase::Sweeping, &stats);
+        sweep_marked_blobs(
+            &unreferenced_blobs,
+            policy.grace_period_hours,
+            started,
+            journal,
+            &mut stats,
+        )?;
         log::info!(
             "Deleted {} blobs, freed {} bytes",
             stats.blobs_deleted,
@@ -74,43 +256,325 @@ pub fn run_gc(
     Ok(stats)
 }
 
-/// Scan all manifests and extract referenced blob digests
-fn scan_manifests(stats: &mut GcStats) -> Result<HashSet<String>, Box<dyn std::error::Error>> {
-    let mut referenced = HashSet::new();
-    let manifests_dir = Path::new("./tmp/manifests");
+/// Complete the deletions of a GC run interrupted by a crash, before serving requests
+pub(crate) fn recover_journal() -> Result<usize, std::io::Error> {
+    let mut journal = Journal::lock();
+    let (recovered, pending) = journal.recover()?;
+    if pending > 0 {
+        log::warn!("GC journal: {} deletions could not be completed", pending);
+    }
+    journal.close()?;
+    Ok(recovered)
+}
 
-    if !manifests_dir.exists() {
-        return Ok(referenced);
+/// Delete expired manifests: tags and digest-addressed manifests past their `grain.expires-at`
+/// annotation, and tags past the expiry set on them. Pinned tags are kept, and so is the
+/// digest-addressed copy of a manifest a remaining tag points to.
+fn expire_manifests(
+    repositories: &[Repository],
+    in_scope: &dyn Fn(&str, &str) -> bool,
+    dry_run: bool,
+    journal: &mut Journal,
+    stats: &mut GcStats,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let declared: HashMap<&str, &Repository> =
+        repositories.iter().map(|r| (r.name.as_str(), r)).collect();
+    let mut stored: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+    for (org, repo, reference) in storage::list_manifests()? {
+        if in_scope(&org, &repo) {
+            stored.entry((org, repo)).or_default().push(reference);
+        }
     }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    for ((org, repo), references) in &stored {
+        let repository = declared.get(format!("{}/{}", org, repo).as_str()).copied();
+        let mut expired = Vec::new();
+        let mut kept = HashSet::new();
+        for reference in references {
+            let Ok(bytes) = storage::read_manifest(org, repo, reference) else {
+                continue;
+            };
+            let digest = Digest::of(Algorithm::Sha256, &bytes);
+            let tag = (!is_digest_reference(reference)).then_some(reference.as_str());
+            let pinned =
+                tag.is_some_and(|tag| repository.is_some_and(|r| r.pinned_tags.contains(tag)));
+            let expires_at = repositories::expires_at(repository, tag, &bytes);
+            if !pinned && expires_at.is_some_and(|at| at <= now) {
+                expired.push((reference.clone(), digest));
+            } else if tag.is_some() {
+                kept.insert(digest);
+            }
+        }
 
-    // Walk through org/repo/manifest structure
-    for org_entry in std::fs::read_dir(manifests_dir)? {
-        let org_entry = org_entry?;
-        if !org_entry.path().is_dir() {
+        // The digest-addressed copy of an expired tag is deleted with it, in the same journal
+        // entry, unless a remaining tag reaches it
+        let mut scheduled = HashSet::new();
+        let mut groups: Vec<Vec<String>> = Vec::new();
+        for (reference, digest) in &expired {
+            let hex = digest.hex().to_string();
+            let mut group = Vec::new();
+            if !is_digest_reference(reference) {
+                group.push(reference.clone());
+            }
+            if !kept.contains(digest) && references.contains(&hex) && scheduled.insert(hex.clone())
+            {
+                group.push(hex);
+            }
+            if !group.is_empty() {
+                groups.push(group);
+            }
+        }
+        stats.manifests_expired += groups.iter().map(Vec::len).sum::<usize>();
+        if dry_run {
             continue;
         }
 
-        for repo_entry in std::fs::read_dir(org_entry.path())? {
-            let repo_entry = repo_entry?;
-            if !repo_entry.path().is_dir() {
+        for group in &groups {
+            let deletions: Vec<Deletion> = group
+                .iter()
+                .map(|reference| Deletion::manifest(org, repo, reference))
+                .collect();
+            let seq = journal.begin(&deletions)?;
+            for reference in group {
+                match storage::delete_manifest(org, repo, reference) {
+                    Ok(()) => {
+                        log::info!("Deleted expired manifest {}/{}:{}", org, repo, reference)
+                    }
+                    Err(e) => log::warn!(
+                        "Failed to delete expired manifest {}/{}:{}: {}",
+                        org,
+                        repo,
+                        reference,
+                        e
+                    ),
+                }
+            }
+            journal.finish(seq)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Delete tags expired by retention policies, and the digest-addressed copy of each expired
+/// manifest that no remaining tag points to
+fn apply_retention(
+    repositories: &[Repository],
+    dry_run: bool,
+    journal: &mut Journal,
+    stats: &mut GcStats,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let now = SystemTime::now();
+
+    for repository in repositories {
+        let Some(policy) = &repository.retention else {
+            continue;
+        };
+        let Some((org, repo)) = repository.name.split_once('/') else {
+            continue;
+        };
+
+        // Pinned and `grain.keep` tags are left out of the policy, so they neither expire
+        // nor count towards `keep_last`
+        let mut tags = Vec::new();
+        let mut exempt = Vec::new();
+        for tag in storage::list_tags(org, repo)? {
+            if repository.pinned_tags.contains(&tag)
+                || storage::read_manifest(org, repo, &tag)
+                    .is_ok_and(|bytes| repositories::has_keep_annotation(&bytes))
+            {
+                exempt.push(tag);
                 continue;
             }
+            let metadata = storage::manifest_metadata(org, repo, &tag)?;
+            tags.push((tag, metadata.modified));
+        }
+        stats.tags_exempt += exempt.len();
+
+        let expired = policy.expired_tags(&tags, now);
+        stats.tags_expired += expired.len();
+        if dry_run || expired.is_empty() {
+            continue;
+        }
 
-            for manifest_entry in std::fs::read_dir(repo_entry.path())? {
-                let manifest_entry = manifest_entry?;
-                if !manifest_entry.path().is_file() {
-                    continue;
+        let manifest_digest = |tag: &str| {
+            storage::read_manifest(org, repo, tag)
+                .ok()
+                .map(|bytes| Digest::of(Algorithm::Sha256, &bytes))
+        };
+        let kept: HashSet<Digest> = tags
+            .iter()
+            .map(|(tag, _)| tag)
+            .filter(|tag| !expired.contains(tag))
+            .chain(&exempt)
+            .filter_map(|tag| manifest_digest(tag))
+            .collect();
+
+        for tag in &expired {
+            // Manifests pushed by tag are also stored under their bare hex digest, which goes
+            // in the same journal entry so a crash cannot strand it without its tag
+            let digest = manifest_digest(tag).filter(|d| !kept.contains(d));
+            let mut deletions = vec![Deletion::manifest(org, repo, tag)];
+            deletions.extend(
+                digest
+                    .iter()
+                    .map(|digest| Deletion::manifest(org, repo, digest.hex())),
+            );
+            let seq = journal.begin(&deletions)?;
+
+            match storage::delete_manifest(org, repo, tag) {
+                Ok(()) => {
+                    log::info!("Expired tag {}:{} (retention policy)", repository.name, tag);
+                    if let Some(digest) = &digest {
+                        let _ = storage::delete_manifest(org, repo, digest.hex());
+                    }
                 }
+                Err(e) => log::warn!("Failed to expire tag {}:{}: {}", repository.name, tag, e),
+            }
+            journal.finish(seq)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn is_digest_reference(reference: &str) -> bool {
+    matches!(reference.len(), 64 | 128) && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Hex digests of an index's child manifests, and of the manifest's subject if it has one
+fn manifest_links(bytes: &[u8]) -> (Vec<String>, Option<String>) {
+    let Ok(manifest) = serde_json::from_slice::<serde_json::Value>(bytes) else {
+        return (Vec::new(), None);
+    };
+    let hex = |descriptor: &serde_json::Value| {
+        descriptor
+            .get("digest")
+            .and_then(|d| d.as_str())
+            .and_then(|d| Digest::parse(d).ok())
+            .map(|d| d.hex().to_string())
+    };
+
+    let children = manifest
+        .get("manifests")
+        .and_then(|m| m.as_array())
+        .map(|m| m.iter().filter_map(hex).collect())
+        .unwrap_or_default();
+    (children, manifest.get("subject").and_then(hex))
+}
+
+/// Find digest-addressed manifests that no tag (or `grain.keep` annotation) reaches, either
+/// directly, as a child of an index or as a referrer (signature, SBOM) of a reached manifest
+fn mark_untagged_manifests() -> Result<Vec<UntaggedManifest>, Box<dyn std::error::Error>> {
+    let mut repositories: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+    for (org, repo, reference) in storage::list_manifests()? {
+        repositories.entry((org, repo)).or_default().push(reference);
+    }
 
-                stats.manifests_scanned += 1;
+    let mut untagged = Vec::new();
+    for ((org, repo), references) in &repositories {
+        let mut links = HashMap::new();
+        let mut pending = Vec::new();
+        for reference in references {
+            let Ok(bytes) = storage::read_manifest(org, repo, reference) else {
+                continue;
+            };
+            if repositories::has_keep_annotation(&bytes) {
+                // Kept manifests count as tagged, so their children and referrers stay too
+                pending.push(Digest::of(Algorithm::Sha256, &bytes).hex().to_string());
+            }
+            if is_digest_reference(reference) {
+                links.insert(reference.clone(), manifest_links(&bytes));
+            } else {
+                pending.push(Digest::of(Algorithm::Sha256, &bytes).hex().to_string());
+            }
+        }
 
-                // Read and parse manifest
-                if let Ok(manifest_data) = std::fs::read(manifest_entry.path()) {
-                    if let Ok(manifest_str) = std::str::from_utf8(&manifest_data) {
-                        extract_blob_references(manifest_str, &mut referenced);
+        let mut reached = HashSet::new();
+        while !pending.is_empty() {
+            while let Some(hex) = pending.pop() {
+                if reached.insert(hex.clone()) {
+                    if let Some((children, _)) = links.get(&hex) {
+                        pending.extend(children.iter().cloned());
                     }
                 }
             }
+            pending = links
+                .iter()
+                .filter(|(hex, (_, subject))| {
+                    !reached.contains(*hex) && subject.as_ref().is_some_and(|s| reached.contains(s))
+                })
+                .map(|(hex, _)| hex.clone())
+                .collect();
+        }
+
+        let mut unreached: Vec<&String> =
+            links.keys().filter(|hex| !reached.contains(*hex)).collect();
+        unreached.sort();
+        untagged.extend(
+            unreached
+                .into_iter()
+                .map(|hex| (org.clone(), repo.clone(), hex.clone())),
+        );
+    }
+
+    Ok(untagged)
+}
+
+/// Delete marked untagged manifests once past the grace period, as a push writes child
+/// manifests before the index that lists them
+fn sweep_untagged_manifests(
+    untagged: &[UntaggedManifest],
+    grace_period_hours: u64,
+    journal: &mut Journal,
+    stats: &mut GcStats,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let now = SystemTime::now();
+    let grace_period = Duration::from_secs(grace_period_hours * 3600);
+
+    for (org, repo, hex) in untagged {
+        progress::tick(stats);
+        // Tagged or deleted since it was marked
+        let Ok(metadata) = storage::manifest_metadata(org, repo, hex) else {
+            continue;
+        };
+        if now.duration_since(metadata.modified).unwrap_or_default() < grace_period {
+            log::debug!("Manifest {}/{}/{} still in grace period", org, repo, hex);
+            continue;
+        }
+        let seq = journal.begin(&[Deletion::manifest(org, repo, hex)])?;
+        match storage::delete_manifest(org, repo, hex) {
+            Ok(()) => {
+                log::info!("Deleted untagged manifest: {}/{}/{}", org, repo, hex);
+                stats.manifests_deleted += 1;
+            }
+            Err(e) => {
+                log::warn!("Failed to delete manifest {}/{}/{}: {}", org, repo, hex, e);
+            }
+        }
+        journal.finish(seq)?;
+    }
+
+    Ok(())
+}
+
+/// Scan all manifests and extract referenced blob digests
+fn scan_manifests(stats: &mut GcStats) -> Result<HashSet<Digest>, Box<dyn std::error::Error>> {
+    let mut referenced = HashSet::new();
+
+    for (org, repo, reference) in storage::list_manifests()? {
+        stats.manifests_scanned += 1;
+        progress::tick(stats);
+
+        // Read and parse manifest
+        if let Ok(manifest_data) = storage::read_manifest(&org, &repo, &reference) {
+            if let Ok(manifest_str) = std::str::from_utf8(&manifest_data) {
+                extract_blob_references(manifest_str, &mut referenced);
+            }
         }
     }
 
@@ -118,34 +582,32 @@ fn scan_manifests(stats: &mut GcStats) -> Result<HashSet<String>, Box<dyn std::e
 }
 
 /// Extract blob digest references from manifest JSON
-fn extract_blob_references(manifest_json: &str, referenced: &mut HashSet<String>) {
+fn extract_blob_references(manifest_json: &str, referenced: &mut HashSet<Digest>) {
     if let Ok(manifest) = serde_json::from_str::<serde_json::Value>(manifest_json) {
+        let mut reference = |desc: &serde_json::Value| {
+            if let Some(digest) = desc.get("digest").and_then(|d| d.as_str()) {
+                match Digest::parse(digest) {
+                    Ok(digest) => {
+                        referenced.insert(digest);
+                    }
+                    Err(e) => log::warn!("GC: ignoring malformed reference: {}", e),
+                }
+            }
+        };
+
         // Extract config digest
         if let Some(config) = manifest.get("config") {
-            if let Some(digest) = config.get("digest").and_then(|d| d.as_str()) {
-                let clean_digest = digest.strip_prefix("sha256:").unwrap_or(digest);
-                referenced.insert(clean_digest.to_string());
-            }
+            reference(config);
         }
 
         // Extract layer digests
         if let Some(layers) = manifest.get("layers").and_then(|l| l.as_array()) {
-            for layer in layers {
-                if let Some(digest) = layer.get("digest").and_then(|d| d.as_str()) {
-                    let clean_digest = digest.strip_prefix("sha256:").unwrap_or(digest);
-                    referenced.insert(clean_digest.to_string());
-                }
-            }
+            layers.iter().for_each(&mut reference);
         }
 
         // Extract manifests from image index
         if let Some(manifests) = manifest.get("manifests").and_then(|m| m.as_array()) {
-            for manifest_desc in manifests {
-                if let Some(digest) = manifest_desc.get("digest").and_then(|d| d.as_str()) {
-                    let clean_digest = digest.strip_prefix("sha256:").unwrap_or(digest);
-                    referenced.insert(clean_digest.to_string());
-                }
-            }
+            manifests.iter().for_each(&mut reference);
         }
     }
 }
@@ -153,48 +615,17 @@ fn extract_blob_references(manifest_json: &str, referenced: &mut HashSet<String>
 /// Scan all blobs in storage
 fn scan_all_blobs(
     stats: &mut GcStats,
-) -> Result<HashMap<String, Vec<BlobLocation>>, Box<dyn std::error::Error>> {
-    let mut all_blobs: HashMap<String, Vec<BlobLocation>> = HashMap::new();
-    let blobs_dir = Path::new("./tmp/blobs");
-
-    if !blobs_dir.exists() {
-        return Ok(all_blobs);
-    }
-
-    for org_entry in std::fs::read_dir(blobs_dir)? {
-        let org_entry = org_entry?;
-        if !org_entry.path().is_dir() {
-            continue;
-        }
-
-        let org = org_entry.file_name().to_string_lossy().to_string();
-
-        for repo_entry in std::fs::read_dir(org_entry.path())? {
-            let repo_entry = repo_entry?;
-            if !repo_entry.path().is_dir() {
-                continue;
-            }
-
-            let repo = repo_entry.file_name().to_string_lossy().to_string();
-
-            for blob_entry in std::fs::read_dir(repo_entry.path())? {
-                let blob_entry = blob_entry?;
-                if !blob_entry.path().is_file() {
-                    continue;
-                }
+) -> Result<HashMap<Digest, Vec<BlobLocation>>, Box<dyn std::error::Error>> {
+    let mut all_blobs: HashMap<Digest, Vec<BlobLocation>> = HashMap::new();
 
-                stats.blobs_scanned += 1;
+    for (org, repo, digest) in storage::list_blobs()? {
+        stats.blobs_scanned += 1;
+        progress::tick(stats);
 
-                let digest = blob_entry.file_name().to_string_lossy().to_string();
-                let size = blob_entry.metadata()?.len();
+        let size = storage::blob_metadata(&org, &repo, &digest)?.size;
 
-                // Track all locations for this digest
-                all_blobs
-                    .entry(digest)
-                    .or_default()
-                    .push((org.clone(), repo.clone(), size));
-            }
-        }
+        // Track all locations for this digest
+        all_blobs.entry(digest).or_default().push((org, repo, size));
     }
 
     Ok(all_blobs)
@@ -202,8 +633,8 @@ fn scan_all_blobs(
 
 /// Mark unreferenced blobs for deletion
 fn mark_unreferenced_blobs(
-    all_blobs: &HashMap<String, Vec<BlobLocation>>,
-    referenced_blobs: &HashSet<String>,
+    all_blobs: &HashMap<Digest, Vec<BlobLocation>>,
+    referenced_blobs: &HashSet<Digest>,
 ) -> Result<Vec<UnreferencedBlob>, Box<dyn std::error::Error>> {
     let mut unreferenced = Vec::new();
 
@@ -223,45 +654,57 @@ fn mark_unreferenced_blobs(
 fn sweep_marked_blobs(
     unreferenced_blobs: &[UnreferencedBlob],
     grace_period_hours: u64,
+    started: Instant,
+    journal: &mut Journal,
     stats: &mut GcStats,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
     let grace_period_secs = grace_period_hours * 3600;
+    let grace_period = Duration::from_secs(grace_period_secs);
+    in_use::prune(started, grace_period);
 
     for (org, repo, digest, size) in unreferenced_blobs {
+        progress::tick(stats);
+        // Held until the blob is deleted, so a push cannot start relying on it in between
+        let last_used = in_use::last_used();
+        if in_use::in_use(&last_used, org, repo, digest, started, grace_period) {
+            log::debug!("Blob {} is in use by a push", digest);
+            stats.blobs_in_use += 1;
+            continue;
+        }
+
         // Check blob modification time
-        let blob_path = format!("./tmp/blobs/{}/{}/{}", org, repo, digest);
-
-        if let Ok(metadata) = std::fs::metadata(&blob_path) {
-            if let Ok(modified) = metadata.modified() {
-                let modified_secs = modified.duration_since(UNIX_EPOCH)?.as_secs();
-                let age_secs = now.saturating_sub(modified_secs);
-
-                // Only delete if past grace period
-                if age_secs >= grace_period_secs {
-                    match std::fs::remove_file(&blob_path) {
-                        Ok(()) => {
-                            log::info!(
-                                "Deleted unreferenced blob: {}/{}/{} ({} bytes)",
-                                org,
-                                repo,
-                                digest,
-                                size
-                            );
-                            stats.blobs_deleted += 1;
-                            stats.bytes_freed += size;
-                        }
-                        Err(e) => {
-                            log::warn!("Failed to delete blob {}: {}", blob_path, e);
-                        }
+        if let Ok(metadata) = storage::blob_metadata(org, repo, digest) {
+            let modified_secs = metadata.modified.duration_since(UNIX_EPOCH)?.as_secs();
+            let age_secs = now.saturating_sub(modified_secs);
+
+            // Only delete if past grace period
+            if age_secs >= grace_period_secs {
+                // Also removes the mount record and records the deletion in the changelog
+                let seq = journal.begin(&[Deletion::blob(org, repo, digest)])?;
+                match storage::delete_blob(org, repo, digest) {
+                    Ok(()) => {
+                        log::info!(
+                            "Deleted unreferenced blob: {}/{}/{} ({} bytes)",
+                            org,
+                            repo,
+                            digest,
+                            size
+                        );
+                        stats.blobs_deleted += 1;
+                        stats.bytes_freed += size;
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to delete blob {}/{}/{}: {}", org, repo, digest, e);
                     }
-                } else {
-                    log::debug!(
-                        "Blob {} still in grace period ({} hours old)",
-                        digest,
-                        age_secs / 3600
-                    );
                 }
+                journal.finish(seq)?;
+            } else {
+                log::debug!(
+                    "Blob {} still in grace period ({} hours old)",
+                    digest,
+                    age_secs / 3600
+                );
             }
         }
     }
@@ -269,45 +712,119 @@ fn sweep_marked_blobs(
     Ok(())
 }
 
+/// Start running garbage collection every `schedule_interval_minutes` of the GC policy.
+/// The policy is re-read on each check, so schedule changes apply without a restart.
+pub(crate) fn spawn_scheduled_gc(state: Arc<state::App>) {
+    // A standby replays the primary's deletions instead
+    if state.args.standby_of.is_some() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut last_run = Instant::now();
+        loop {
+            tokio::time::sleep(SCHEDULE_CHECK_INTERVAL).await;
+
+            let policy = state.gc_policy.get();
+            let interval = Duration::from_secs(policy.schedule_interval_minutes * 60);
+            if interval.is_zero() || last_run.elapsed() < interval {
+                continue;
+            }
+            last_run = Instant::now();
+
+            log::info!("Starting scheduled GC (dry_run: {})", policy.dry_run);
+            let repositories = state.repositories.list();
+            let dry_run = policy.dry_run;
+            let result = tokio::task::spawn_blocking(move || {
+                run_gc(&policy, &repositories, None).map_err(|e| e.to_string())
+            })
+            .await;
+            match result {
+                Ok(Ok(stats)) => {
+                    if !dry_run {
+                        state.audit.record(
+                            AuditEvent::new("system", "gc.run", "registry", Outcome::Success)
+                                .with_detail(format!(
+                                    "deleted {} blobs, {} manifests",
+                                    stats.blobs_deleted, stats.manifests_deleted
+                                )),
+                        );
+                    }
+                }
+                Ok(Err(e)) => log::error!("Scheduled GC failed: {}", e),
+                Err(e) => log::error!("Scheduled GC panicked: {}", e),
+            }
+        }
+    });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_extract_blob_references() {
-        let manifest = r#"{
-            "config": {
-                "digest": "sha256:abc123"
-            },
+        let (config, layer1, layer2) = ("a".repeat(64), "b".repeat(64), "c".repeat(128));
+        let manifest = format!(
+            r#"{{
+            "config": {{
+                "digest": "sha256:{}"
+            }},
             "layers": [
-                {"digest": "sha256:layer1"},
-                {"digest": "sha256:layer2"}
+                {{"digest": "sha256:{}"}},
+                {{"digest": "sha512:{}"}},
+                {{"digest": "sha256:malformed"}}
             ]
-        }"#;
+        }}"#,
+            config, layer1, layer2
+        );
 
         let mut referenced = HashSet::new();
-        extract_blob_references(manifest, &mut referenced);
+        extract_blob_references(&manifest, &mut referenced);
 
         assert_eq!(referenced.len(), 3);
-        assert!(referenced.contains("abc123"));
-        assert!(referenced.contains("layer1"));
-        assert!(referenced.contains("layer2"));
+        assert!(referenced.contains(&Digest::parse(&format!("sha256:{}", config)).unwrap()));
+        assert!(referenced.contains(&Digest::parse(&format!("sha256:{}", layer1)).unwrap()));
+        assert!(referenced.contains(&Digest::parse(&format!("sha512:{}", layer2)).unwrap()));
     }
 
     #[test]
     fn test_extract_image_index_references() {
-        let manifest = r#"{
+        let (manifest1, manifest2) = ("1".repeat(64), "2".repeat(64));
+        let manifest = format!(
+            r#"{{
             "manifests": [
-                {"digest": "sha256:manifest1"},
-                {"digest": "sha256:manifest2"}
+                {{"digest": "sha256:{}"}},
+                {{"digest": "sha256:{}"}}
             ]
-        }"#;
+        }}"#,
+            manifest1, manifest2
+        );
 
         let mut referenced = HashSet::new();
-        extract_blob_references(manifest, &mut referenced);
+        extract_blob_references(&manifest, &mut referenced);
 
         assert_eq!(referenced.len(), 2);
-        assert!(referenced.contains("manifest1"));
-        assert!(referenced.contains("manifest2"));
+        assert!(referenced.contains(&Digest::parse(&manifest1).unwrap()));
+        assert!(referenced.contains(&Digest::parse(&manifest2).unwrap()));
+    }
+
+    #[test]
+    fn test_manifest_links() {
+        let (child, subject) = ("a".repeat(64), "b".repeat(64));
+        let index = format!(
+            r#"{{"manifests":[{{"digest":"sha256:{}"}},{{"digest":"bogus"}}],
+                "subject":{{"digest":"sha256:{}"}}}}"#,
+            child, subject
+        );
+        assert_eq!(
+            manifest_links(index.as_bytes()),
+            (vec![child], Some(subject))
+        );
+        assert_eq!(manifest_links(b"not json"), (Vec::new(), None));
+
+        assert!(is_digest_reference(&"c".repeat(64)));
+        assert!(is_digest_reference(&"c".repeat(128)));
+        assert!(!is_digest_reference("latest"));
     }
 }
@@ -0,0 +1,149 @@
+//! Blobs that pushes rely on, so garbage collection never sweeps a blob out from under a push
+//! whose manifest has not arrived yet (or arrived after the mark phase read the manifests)
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::digest::Digest;
+
+type BlobKey = (String, String, Digest); // (org, repo, digest)
+
+/// How often recording a use also forgets uses too old to count
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub(super) struct Uses {
+    times: HashMap<BlobKey, Instant>,
+    /// Longest grace period a run has used (at least the default policy's); older uses never
+    /// count, so they are forgotten even on registries where GC never runs
+    retention: Duration,
+    pruned: Instant,
+}
+
+impl Uses {
+    fn insert(&mut self, key: BlobKey, now: Instant) {
+        self.times.insert(key, now);
+        if now.saturating_duration_since(self.pruned) >= PRUNE_INTERVAL {
+            let retention = self.retention;
+            self.times
+                .retain(|_, used| now.saturating_duration_since(*used) <= retention);
+            self.pruned = now;
+        }
+    }
+}
+
+/// When each blob was last uploaded, mounted, checked for or referenced by a pushed manifest.
+/// The sweep holds this lock while it checks and deletes a blob, so a push either records the
+/// blob first and it is kept, or finds it already gone and uploads it again.
+static LAST_USED: OnceLock<Mutex<Uses>> = OnceLock::new();
+
+pub(super) fn last_used() -> MutexGuard<'static, Uses> {
+    LAST_USED
+        .get_or_init(|| {
+            Mutex::new(Uses {
+                times: HashMap::new(),
+                retention: Duration::from_secs(
+                    super::GcPolicy::default().grace_period_hours * 3600,
+                ),
+                pruned: Instant::now(),
+            })
+        })
+        .lock()
+        .unwrap()
+}
+
+/// Record that a push relies on `digest` in `org/repo`. Call before checking that the blob
+/// exists or writing what references it.
+pub(crate) fn record(org: &str, repo: &str, digest: &Digest) {
+    last_used().insert(
+        (org.to_string(), repo.to_string(), digest.clone()),
+        Instant::now(),
+    );
+}
+
+/// Record the blobs (config, layers, index children) a manifest about to be pushed references
+pub(crate) fn record_manifest(org: &str, repo: &str, manifest: &[u8]) {
+    let Ok(manifest) = std::str::from_utf8(manifest) else {
+        return;
+    };
+    let mut referenced = HashSet::new();
+    super::extract_blob_references(manifest, &mut referenced);
+    let now = Instant::now();
+    let mut last_used = last_used();
+    for digest in referenced {
+        last_used.insert((org.to_string(), repo.to_string(), digest), now);
+    }
+}
+
+/// Whether a push used a blob during a run that started at `started`, or within
+/// `grace_period` before it
+pub(super) fn in_use(
+    last_used: &Uses,
+    org: &str,
+    repo: &str,
+    digest: &Digest,
+    started: Instant,
+    grace_period: Duration,
+) -> bool {
+    last_used
+        .times
+        .get(&(org.to_string(), repo.to_string(), digest.clone()))
+        .is_some_and(|used| started.saturating_duration_since(*used) <= grace_period)
+}
+
+/// Forget uses a run started at `started` with `grace_period` would not count, and keep uses
+/// at least that long from now on
+pub(super) fn prune(started: Instant, grace_period: Duration) {
+    let mut last_used = last_used();
+    last_used.retention = last_used.retention.max(grace_period);
+    last_used
+        .times
+        .retain(|_, used| started.saturating_duration_since(*used) <= grace_period);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::Algorithm;
+
+    #[test]
+    fn test_in_use() {
+        let digest = Digest::of(Algorithm::Sha256, b"in flight");
+        let before = Instant::now();
+        record("org", "repo", &digest);
+        let later = Instant::now() + Duration::from_secs(60);
+
+        let last_used = last_used();
+        let in_use =
+            |repo, started, grace| in_use(&last_used, "org", repo, &digest, started, grace);
+        // Used while the run was in progress
+        assert!(in_use("repo", before, Duration::ZERO));
+        // Used before the run, within or past the grace period
+        assert!(in_use("repo", later, Duration::from_secs(3600)));
+        assert!(!in_use("repo", later, Duration::ZERO));
+        // Copies in other repositories are tracked separately
+        assert!(!in_use("other", before, Duration::ZERO));
+    }
+
+    #[test]
+    fn test_forgets_old_uses_on_insert() {
+        let start = Instant::now();
+        let mut uses = Uses {
+            times: HashMap::new(),
+            retention: Duration::from_secs(3600),
+            pruned: start,
+        };
+        let key = |name: &str| {
+            let digest = Digest::of(Algorithm::Sha256, name.as_bytes());
+            ("org".to_string(), "repo".to_string(), digest)
+        };
+
+        uses.insert(key("old"), start);
+        uses.insert(key("recent"), start + Duration::from_secs(3000));
+        assert_eq!(uses.times.len(), 2);
+
+        uses.insert(key("new"), start + Duration::from_secs(3700));
+        assert_eq!(uses.times.len(), 2);
+        assert!(!uses.times.contains_key(&key("old")));
+    }
+}
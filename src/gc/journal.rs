@@ -0,0 +1,202 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::digest::Digest;
+use crate::storage;
+
+const JOURNAL_PATH: &str = "./tmp/gc-journal.jsonl";
+
+/// Held by whoever reads or writes the journal, so only one GC run sweeps at a time
+static JOURNAL_LOCK: Mutex<()> = Mutex::new(());
+
+/// A deletion garbage collection is about to make
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum Deletion {
+    Manifest {
+        org: String,
+        repo: String,
+        reference: String,
+    },
+    Blob {
+        org: String,
+        repo: String,
+        digest: String,
+    },
+}
+
+impl Deletion {
+    pub(crate) fn manifest(org: &str, repo: &str, reference: &str) -> Self {
+        Deletion::Manifest {
+            org: org.to_string(),
+            repo: repo.to_string(),
+            reference: reference.to_string(),
+        }
+    }
+
+    pub(crate) fn blob(org: &str, repo: &str, digest: &Digest) -> Self {
+        Deletion::Blob {
+            org: org.to_string(),
+            repo: repo.to_string(),
+            digest: digest.to_string(),
+        }
+    }
+
+    /// Complete the deletion, whether or not it was started before
+    fn replay(&self) -> Result<(), storage::StorageError> {
+        match self {
+            Deletion::Manifest {
+                org,
+                repo,
+                reference,
+            } => storage::purge_manifest(org, repo, reference),
+            Deletion::Blob { org, repo, digest } => match Digest::parse(digest) {
+                Ok(digest) => storage::purge_blob(org, repo, &digest),
+                Err(e) => {
+                    log::warn!("GC journal: dropping malformed blob deletion: {}", e);
+                    Ok(())
+                }
+            },
+        }
+    }
+}
+
+/// One line of the journal: a group of deletions to make together, or the mark that they were
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    seq: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    deletions: Vec<Deletion>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    done: bool,
+}
+
+/// Write-ahead log of GC deletions. Each group is synced to disk before its first file is
+/// removed, so a run that crashes mid-sweep leaves behind what it still had to delete.
+pub(crate) struct Journal {
+    _lock: MutexGuard<'static, ()>,
+    file: Option<File>,
+    next_seq: u64,
+    open: usize,
+}
+
+impl Journal {
+    /// Wait for any other run to finish with the journal
+    pub(crate) fn lock() -> Self {
+        Self {
+            _lock: JOURNAL_LOCK.lock().unwrap_or_else(|e| e.into_inner()),
+            file: None,
+            next_seq: 1,
+            open: 0,
+        }
+    }
+
+    /// Deletions recorded by an interrupted run that were never marked done
+    pub(crate) fn pending(&self) -> Vec<Vec<Deletion>> {
+        let mut groups = BTreeMap::new();
+        let content = fs::read_to_string(JOURNAL_PATH).unwrap_or_default();
+        // A torn last line is an intent that never reached disk, so nothing was deleted for it
+        for entry in content
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Entry>(line).ok())
+        {
+            if entry.done {
+                groups.remove(&entry.seq);
+            } else {
+                groups.insert(entry.seq, entry.deletions);
+            }
+        }
+        groups.into_values().collect()
+    }
+
+    /// Finish the deletions left by an interrupted run, returning how many were completed and
+    /// how many still failed. Failed ones stay in the journal for the next attempt.
+    pub(crate) fn recover(&mut self) -> io::Result<(usize, usize)> {
+        let mut recovered = 0;
+        let mut failed = Vec::new();
+        for group in self.pending() {
+            let mut remaining = Vec::new();
+            for deletion in group {
+                match deletion.replay() {
+                    Ok(()) => {
+                        log::info!("GC journal: completed interrupted deletion {:?}", deletion);
+                        recovered += 1;
+                    }
+                    Err(e) => {
+                        log::warn!("GC journal: cannot complete {:?}: {}", deletion, e);
+                        remaining.push(deletion);
+                    }
+                }
+            }
+            if !remaining.is_empty() {
+                failed.push(remaining);
+            }
+        }
+
+        remove_journal()?;
+        let pending = failed.iter().map(Vec::len).sum();
+        // Re-recorded as open groups, so `close` keeps them
+        for group in &failed {
+            self.begin(group)?;
+        }
+        Ok((recovered, pending))
+    }
+
+    /// Record `deletions` before making them, returning the sequence number to pass to `finish`
+    pub(crate) fn begin(&mut self, deletions: &[Deletion]) -> io::Result<u64> {
+        let seq = self.next_seq;
+        self.append(&Entry {
+            seq,
+            deletions: deletions.to_vec(),
+            done: false,
+        })?;
+        self.next_seq += 1;
+        self.open += 1;
+        Ok(seq)
+    }
+
+    /// Mark a group as settled, whether its deletions succeeded or were given up on
+    pub(crate) fn finish(&mut self, seq: u64) -> io::Result<()> {
+        self.append(&Entry {
+            seq,
+            deletions: Vec::new(),
+            done: true,
+        })?;
+        self.open = self.open.saturating_sub(1);
+        Ok(())
+    }
+
+    /// Remove the journal once every group written by this run is settled
+    pub(crate) fn close(self) -> io::Result<()> {
+        if self.file.is_some() && self.open == 0 {
+            remove_journal()?;
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, entry: &Entry) -> io::Result<()> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(JOURNAL_PATH)?,
+            ),
+        };
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+        file.sync_data()
+    }
+}
+
+fn remove_journal() -> io::Result<()> {
+    match fs::remove_file(JOURNAL_PATH) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
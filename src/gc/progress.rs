@@ -0,0 +1,130 @@
+//! Progress of the current (or last) GC run, for `GET /admin/gc/status` and its event stream
+
+use serde::Serialize;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+use super::GcStats;
+
+/// Least time between two progress updates within a phase, so large registries do not flood
+/// subscribers with one update per blob
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Step a GC run is at
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GcPhase {
+    /// No run since the registry started
+    #[default]
+    Idle,
+    /// Completing deletions of an interrupted run
+    Recovering,
+    /// Deleting expired manifests and tags past their retention policy
+    Expiring,
+    /// Looking for manifests no tag reaches
+    MarkingManifests,
+    /// Reading manifests for the blobs they reference
+    ScanningManifests,
+    /// Listing stored blobs
+    ScanningBlobs,
+    /// Deleting unreferenced blobs
+    Sweeping,
+    Done,
+    Failed,
+}
+
+/// State of the current or last GC run. `stats` counts up while the run progresses.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct GcStatus {
+    pub running: bool,
+    pub phase: GcPhase,
+    pub dry_run: bool,
+    /// Unix timestamps (seconds) of the start and end of the run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+    pub stats: GcStats,
+    /// Why the run failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+static STATUS: OnceLock<watch::Sender<GcStatus>> = OnceLock::new();
+
+/// When the last update within the current phase was published
+static LAST_UPDATE: Mutex<Option<Instant>> = Mutex::new(None);
+
+fn sender() -> &'static watch::Sender<GcStatus> {
+    STATUS.get_or_init(|| watch::channel(GcStatus::default()).0)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Status of the current or last run
+pub(crate) fn status() -> GcStatus {
+    sender().borrow().clone()
+}
+
+/// Receive every update of the status, starting with the current one
+pub(crate) fn subscribe() -> watch::Receiver<GcStatus> {
+    let mut receiver = sender().subscribe();
+    receiver.mark_changed();
+    receiver
+}
+
+pub(super) fn start(dry_run: bool) {
+    *LAST_UPDATE.lock().unwrap() = Some(Instant::now());
+    sender().send_replace(GcStatus {
+        running: true,
+        phase: GcPhase::Recovering,
+        dry_run,
+        started_at: Some(now()),
+        ..Default::default()
+    });
+}
+
+/// Enter `phase`, publishing the counts so far
+pub(super) fn phase(phase: GcPhase, stats: &GcStats) {
+    *LAST_UPDATE.lock().unwrap() = Some(Instant::now());
+    sender().send_modify(|status| {
+        status.phase = phase;
+        status.stats = stats.clone();
+    });
+}
+
+/// Publish the counts so far, unless an update went out less than `UPDATE_INTERVAL` ago
+pub(super) fn tick(stats: &GcStats) {
+    {
+        let mut last = LAST_UPDATE.lock().unwrap();
+        if last.is_some_and(|last| last.elapsed() < UPDATE_INTERVAL) {
+            return;
+        }
+        *last = Some(Instant::now());
+    }
+    sender().send_modify(|status| status.stats = stats.clone());
+}
+
+pub(super) fn finish(result: Result<&GcStats, String>) {
+    sender().send_modify(|status| {
+        status.running = false;
+        status.finished_at = Some(now());
+        match result {
+            Ok(stats) => {
+                status.phase = GcPhase::Done;
+                status.stats = stats.clone();
+            }
+            Err(e) => {
+                status.phase = GcPhase::Failed;
+                status.error = Some(e);
+            }
+        }
+    });
+}
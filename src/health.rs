@@ -1,10 +1,9 @@
 use axum::{body::Body, extract::State, http::StatusCode, response::Response};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
 use std::sync::Arc;
 use std::time::SystemTime;
 
-use crate::state;
+use crate::{cancel, state, storage};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthResponse {
@@ -49,7 +48,8 @@ pub async fn liveness() -> Response {
 
 /// Readiness probe - is the server ready to handle requests?
 pub async fn readiness(State(state): State<Arc<state::App>>) -> Response {
-    let storage_accessible = check_storage_accessibility();
+    let storage_accessible =
+        cancel::run_blocking("storage_accessible", |_| storage::is_accessible()).await;
     let users_loaded = check_users_loaded(&state).await;
 
     let ready = storage_accessible && users_loaded;
@@ -79,11 +79,15 @@ pub async fn readiness(State(state): State<Arc<state::App>>) -> Response {
 pub async fn health(State(_state): State<Arc<state::App>>) -> Response {
     let uptime = START_TIME.elapsed().map(|d| d.as_secs()).unwrap_or(0);
 
+    let (accessible, writable) = cancel::run_blocking("storage_health", |_| {
+        (storage::is_accessible(), storage::is_writable())
+    })
+    .await;
     let storage = StorageHealth {
-        accessible: check_storage_accessibility(),
-        blobs_path: "./tmp/blobs".to_string(),
-        manifests_path: "./tmp/manifests".to_string(),
-        writable: check_storage_writable(),
+        accessible,
+        blobs_path: storage::location("blobs"),
+        manifests_path: storage::location("manifests"),
+        writable,
     };
 
     let health = HealthResponse {
@@ -110,16 +114,6 @@ pub async fn health(State(_state): State<Arc<state::App>>) -> Response {
         .unwrap()
 }
 
-fn check_storage_accessibility() -> bool {
-    Path::new("./tmp/blobs").exists() && Path::new("./tmp/manifests").exists()
-}
-
-fn check_storage_writable() -> bool {
-    // Try to create a test file
-    let test_file = "./tmp/.health_check";
-    std::fs::write(test_file, "test").is_ok() && std::fs::remove_file(test_file).is_ok()
-}
-
 async fn check_users_loaded(state: &Arc<state::App>) -> bool {
     let users = state.users.lock().await;
     !users.is_empty()
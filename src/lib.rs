@@ -0,0 +1,13 @@
+//! Library surface of grain shared by grainctl and external tooling
+
+#[cfg(feature = "client")]
+pub mod client;
+
+#[cfg(feature = "client")]
+pub mod sync;
+
+#[cfg(feature = "client")]
+pub mod bench;
+
+#[cfg(feature = "client")]
+pub mod contexts;
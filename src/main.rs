@@ -1,19 +1,32 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use axum::{
     extract::DefaultBodyLimit,
     routing::{delete, get, head, patch, post, put},
     Router,
 };
-use clap::Parser;
 use tower_http::cors::CorsLayer;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+mod access_log;
+mod activity;
 mod admin;
+mod archive;
 mod args;
+mod audit;
 mod auth;
 mod blobs;
+mod body;
+mod cache;
+mod cancel;
+mod catalog;
+mod changelog;
+mod cleanup;
+mod cluster;
+mod digest;
+mod downloads;
+mod dry_run;
 mod errors;
 mod gc;
 mod health;
@@ -21,25 +34,134 @@ mod manifests;
 mod meta;
 mod metrics;
 mod middleware;
+mod oidc;
 mod openapi;
+mod pagination;
+mod password;
 mod permissions;
+mod policy;
+mod ratelimit;
+mod referrers;
+mod replication;
+mod repos;
+mod repositories;
 mod response;
+mod robots;
+mod roles;
+mod secrets;
+mod signatures;
+mod standby;
 mod state;
 mod storage;
 mod tags;
+mod tls;
+mod tokens;
 mod utils;
 mod validation;
+mod warnings;
+mod webhooks;
 
 #[tokio::main]
 async fn main() {
-    let args = args::Args::parse();
-    env_logger::init();
+    middleware::init_logging();
+    let mut args = args::Args::load().unwrap_or_else(|e| {
+        log::error!("Invalid configuration: {}", e);
+        std::process::exit(1);
+    });
     log::info!("Starting grain build: {}", utils::get_build_info());
 
+    if let Err(e) = storage::init(&args) {
+        log::error!("Failed to initialize storage: {}", e);
+        std::process::exit(1);
+    }
+
+    // Generated URLs point at the HTTPS listener
+    let tls_config = tls::load_config(&args).await;
+    if tls_config.is_some() {
+        args.url_scheme = "https".to_string();
+    }
+
+    if args.token_auth {
+        response::advertise_bearer(&args);
+    }
+
     // Shared app state
     let shared_state = Arc::new(state::new_app(&args));
     let state_clone = shared_state.clone();
 
+    let admin_routes = Router::new()
+        .route("/users", get(admin::list_users))
+        .route("/users", post(admin::create_user))
+        .route("/users/{username}", delete(admin::delete_user))
+        .route("/users/{username}", put(admin::update_user))
+        .route("/users/{username}/password", post(admin::change_password))
+        .route("/users/{username}/permissions", post(admin::add_permission))
+        .route(
+            "/users/{username}/permissions",
+            delete(admin::remove_permission),
+        )
+        .route("/permissions", post(admin::add_permission_with_username))
+        .route("/gc", post(admin::run_garbage_collection))
+        .route("/gc/status", get(admin::gc_status))
+        .route("/gc/status/events", get(admin::gc_status_events))
+        .route("/gc/policy", get(admin::get_gc_policy))
+        .route("/gc/policy", put(admin::set_gc_policy))
+        .route("/cluster", get(cluster::cluster_info))
+        .route("/robots", get(robots::list_robots))
+        .route("/robots", post(robots::create_robot))
+        .route("/robots/{name}", delete(robots::revoke_robot))
+        .route("/roles", get(roles::list_roles))
+        .route("/roles/{name}", put(roles::set_role))
+        .route("/roles/{name}", delete(roles::delete_role))
+        .route("/groups/{name}", put(roles::set_group))
+        .route("/groups/{name}", delete(roles::delete_group))
+        .route("/repos", get(repos::list_repositories))
+        .route("/repos", post(repos::create_repository))
+        .route("/repos/{org}/{repo}/resolve", get(repos::resolve))
+        .route("/repos/{org}/{repo}/pull-secret", post(repos::pull_secret))
+        .route(
+            "/repos/{org}/{repo}/download-urls",
+            post(downloads::create_download_url),
+        )
+        .route("/repos/{org}/{repo}/stats", get(repos::repository_stats))
+        .route(
+            "/repos/{org}/{repo}/archive",
+            post(archive::archive_repository),
+        )
+        .route(
+            "/repos/{org}/{repo}/restore",
+            post(archive::restore_repository),
+        )
+        .route("/archives", get(archive::list_archives))
+        .route("/repos/{org}/{repo}/pins", get(repos::list_pins))
+        .route("/repos/{org}/{repo}/pins/{tag}", put(repos::pin_tag))
+        .route("/repos/{org}/{repo}/pins/{tag}", delete(repos::unpin_tag))
+        .route(
+            "/repos/{org}/{repo}/expiry/{tag}",
+            put(repos::set_tag_expiry),
+        )
+        .route(
+            "/repos/{org}/{repo}/expiry/{tag}",
+            delete(repos::clear_tag_expiry),
+        )
+        .route("/repos/{org}/{repo}/blobs/{digest}", get(repos::blob_info))
+        .route(
+            "/repos/{org}/{repo}/signatures/{reference}",
+            get(signatures::list_signatures),
+        )
+        .route(
+            "/repos/{org}/{repo}/config/{digest}",
+            get(repos::image_config),
+        )
+        .route("/sync/changes", get(standby::sync_changes))
+        .route("/sync/snapshot", get(standby::sync_snapshot))
+        .route("/sync/users", get(standby::sync_users))
+        .route("/replication/status", get(replication::replication_status))
+        .layer(axum::middleware::from_fn_with_state(
+            shared_state.clone(),
+            middleware::limit_admin_body,
+        ));
+
     let app = Router::new()
         .route("/", get(meta::index)) // Index, info
         // Health endpoints (no auth required)
@@ -49,6 +171,15 @@ async fn main() {
         // Metrics endpoint (no auth for Prometheus scraping)
         .route("/metrics", get(metrics::metrics))
         .route("/v2/", get(auth::get)) // end-1
+        .route(
+            "/user/password",
+            post(admin::change_own_password).layer(axum::middleware::from_fn_with_state(
+                shared_state.clone(),
+                middleware::limit_admin_body,
+            )),
+        )
+        .route("/token", get(tokens::get_token)) // Docker token flow, with --token-auth
+        .route("/v2/_catalog", get(catalog::get_catalog))
         .route(
             "/v2/{org}/{repo}/manifests/{reference}",
             head(manifests::head_manifest_by_reference),
@@ -73,6 +204,14 @@ async fn main() {
             "/v2/{org}/{repo}/blobs/uploads/{reference}",
             patch(blobs::patch_blob_upload),
         ) // end-5
+        .route(
+            "/v2/{org}/{repo}/blobs/uploads/{reference}",
+            get(blobs::get_blob_upload_status),
+        ) // end-13
+        .route(
+            "/v2/{org}/{repo}/blobs/uploads/{reference}",
+            delete(blobs::delete_blob_upload),
+        )
         .route(
             "/v2/{org}/{repo}/blobs/uploads/{reference}",
             put(blobs::put_blob_upload_by_reference),
@@ -82,6 +221,10 @@ async fn main() {
             put(manifests::put_manifest_by_reference),
         ) // end-7
         .route("/v2/{org}/{repo}/tags/list", get(tags::get_tags_list)) // end-8a, end-8b
+        .route(
+            "/v2/{org}/{repo}/referrers/{digest}",
+            get(referrers::get_referrers),
+        ) // end-12a, end-12b
         .route(
             "/v2/{org}/{repo}/manifests/{reference}",
             delete(manifests::delete_manifest_by_reference),
@@ -90,19 +233,18 @@ async fn main() {
             "/v2/{org}/{repo}/blobs/{digest}",
             delete(blobs::delete_blob_by_digest),
         ) // end-10
-        // Admin API routes
-        .route("/admin/users", get(admin::list_users))
-        .route("/admin/users", post(admin::create_user))
-        .route("/admin/users/{username}", delete(admin::delete_user))
+        // Signed download URLs (no auth, the signature grants access)
         .route(
-            "/admin/users/{username}/permissions",
-            post(admin::add_permission),
+            "/downloads/{org}/{repo}/blobs/{digest}",
+            get(downloads::download_blob),
         )
         .route(
-            "/admin/permissions",
-            post(admin::add_permission_with_username),
+            "/downloads/{org}/{repo}/images/{digest}",
+            get(downloads::download_image),
         )
-        .route("/admin/gc", post(admin::run_garbage_collection))
+        // Admin API routes (versioned, unversioned paths kept as aliases)
+        .nest("/admin/v1", admin_routes.clone())
+        .nest("/admin", admin_routes)
         // Catch-all routes for debugging
         .route("/{*path}", head(meta::catch_all_head))
         .route("/{*path}", get(meta::catch_all_get))
@@ -112,15 +254,85 @@ async fn main() {
         .route("/{*path}", delete(meta::catch_all_delete))
         .with_state(state_clone)
         .layer(DefaultBodyLimit::disable()) // Allow unlimited body size for blob uploads
-        .layer(axum::middleware::from_fn(middleware::track_metrics))
+        .layer(axum::middleware::from_fn_with_state(
+            shared_state.clone(),
+            archive::archived_guard,
+        ))
+        .layer(axum::middleware::from_fn(
+            middleware::validate_repository_name,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            shared_state.clone(),
+            standby::read_only_guard,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            shared_state.clone(),
+            middleware::request_timeout,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            shared_state.clone(),
+            ratelimit::rate_limit,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            shared_state.clone(),
+            middleware::track_metrics,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            shared_state.clone(),
+            access_log::log_access,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            shared_state.clone(),
+            middleware::drop_untrusted_forwarding,
+        ))
+        .layer(axum::middleware::from_fn(middleware::propagate_request_id))
         .layer(CorsLayer::permissive())
         .merge(
             SwaggerUi::new("/swagger-ui")
                 .url("/api-docs/openapi.json", openapi::AdminApiDoc::openapi()),
         );
 
-    log::info!("Listening on: {}", &args.host);
-    let listener = tokio::net::TcpListener::bind(&args.host).await.unwrap();
+    // Blobs written before digest algorithms were namespaced move under sha256/
+    match storage::migrate_digest_layout() {
+        Ok(0) => {}
+        Ok(moved) => log::info!("Migrated {} blobs to the per-algorithm layout", moved),
+        Err(e) => {
+            log::error!("Failed to migrate blob layout: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // Deletions of a GC run cut short by a crash are finished before anything reads storage
+    match gc::recover_journal() {
+        Ok(0) => {}
+        Ok(recovered) => log::info!("Completed {} interrupted GC deletions", recovered),
+        Err(e) => {
+            log::error!("Failed to recover the GC journal: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let scheme = if tls_config.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    let mut listeners = Vec::new();
+    for address in &args.host {
+        match tokio::net::TcpListener::bind(address).await {
+            Ok(listener) => {
+                log::info!("Listening on: {} ({})", address, scheme);
+                listeners.push((address.clone(), listener));
+            }
+            Err(e) => {
+                log::error!("Cannot listen on {}: {}", address, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(address) = &args.tls_redirect_from {
+        tls::serve_redirect(address.clone(), &args).await;
+    }
 
     // Mark server as ready after successful bind
     {
@@ -129,5 +341,48 @@ async fn main() {
         log::info!("Server status: Ready");
     }
 
-    axum::serve(listener, app).await.unwrap();
+    standby::spawn_follower(shared_state.clone());
+    replication::spawn_workers(&shared_state);
+    cleanup::spawn_cleanup(shared_state.clone());
+    cleanup::spawn_upload_expiry(shared_state.clone());
+    gc::spawn_scheduled_gc(shared_state.clone());
+    metrics::spawn_storage_sampler(&args);
+    state::spawn_users_reload(shared_state.clone());
+    secrets::spawn_reload_on_sighup();
+
+    if let Some(config) = &tls_config {
+        tls::spawn_reload_on_sighup(config.clone(), &args);
+    }
+    // Each listener labels its requests with its address; the first to stop ends the process
+    let mut servers = tokio::task::JoinSet::new();
+    for (address, listener) in listeners {
+        let app = app.clone().layer(axum::Extension(middleware::Listener(
+            address.as_str().into(),
+        )));
+        let tls_config = tls_config.clone();
+        servers.spawn(async move {
+            let result = match tls_config {
+                Some(config) => match listener.into_std() {
+                    Ok(listener) => {
+                        axum_server::from_tcp_rustls(listener, config)
+                            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                            .await
+                    }
+                    Err(e) => Err(e),
+                },
+                None => {
+                    axum::serve(
+                        listener,
+                        app.into_make_service_with_connect_info::<SocketAddr>(),
+                    )
+                    .await
+                }
+            };
+            result.map_err(|e| (address, e))
+        });
+    }
+    if let Some(Ok(Err((address, e)))) = servers.join_next().await {
+        log::error!("Listener on {} stopped: {}", address, e);
+        std::process::exit(1);
+    }
 }
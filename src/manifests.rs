@@ -4,10 +4,21 @@
 // | end-7  | `PUT`          | `/v2/<name>/manifests/<reference>`                           | `201`       | `404`             |
 // | end-9  | `DELETE`       | `/v2/<name>/manifests/<reference>`                           | `202`       | `404`/`400`/`405` |
 
+use bytes::Bytes;
 use serde_json::Value;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::{auth, metrics, permissions, response, state, storage, validation};
+use crate::{
+    args::Capability,
+    audit::{AuditEvent, Outcome},
+    auth,
+    body::{self, BodyError},
+    digest::Digest,
+    gc, metrics, permissions, repositories, response, state, storage, validation,
+    warnings::Warnings,
+    webhooks,
+};
 use axum::{
     body::Body,
     extract::{Path, State},
@@ -15,7 +26,12 @@ use axum::{
     response::Response,
 };
 
-fn detect_manifest_content_type(manifest_data: &[u8]) -> String {
+/// The tag a manifest reference names, `None` for digests
+fn tag_of(reference: &str) -> Option<&str> {
+    (!reference.starts_with("sha256:")).then_some(reference)
+}
+
+pub(crate) fn detect_manifest_content_type(manifest_data: &[u8]) -> String {
     if let Ok(json_str) = std::str::from_utf8(manifest_data) {
         if let Ok(parsed) = serde_json::from_str::<Value>(json_str) {
             if let Some(media_type) = parsed.get("mediaType").and_then(|v| v.as_str()) {
@@ -26,18 +42,134 @@ fn detect_manifest_content_type(manifest_data: &[u8]) -> String {
     "application/vnd.oci.image.manifest.v1+json".to_string()
 }
 
+/// Refuse manifests over `--max-layers-per-manifest`, and new tags once the repository holds
+/// `--max-tags-per-repo`; re-pushing an existing tag is always allowed
+async fn check_manifest_limits(
+    state: &state::App,
+    org: &str,
+    repo: &str,
+    reference: &str,
+    bytes: &[u8],
+) -> Option<Response> {
+    let max_layers = state.args.max_layers_per_manifest;
+    if max_layers > 0 {
+        let layers = serde_json::from_slice::<Value>(bytes)
+            .ok()
+            .and_then(|manifest| manifest.get("layers")?.as_array().map(Vec::len))
+            .unwrap_or(0);
+        if layers > max_layers {
+            let reason = format!("manifest has {} layers (limit {})", layers, max_layers);
+            log::warn!("Refused manifest for {}/{}: {}", org, repo, reason);
+            metrics::MANIFEST_LIMIT_REJECTIONS_TOTAL
+                .with_label_values(&["layers"])
+                .inc();
+            return Some(response::manifest_invalid(&reason));
+        }
+    }
+
+    let max_tags = state.args.max_tags_per_repo;
+    if max_tags == 0 || reference.starts_with("sha256:") {
+        return None;
+    }
+    let tags = storage::blocking("list_tags", org, repo, |org, repo| {
+        storage::list_tags(org, repo)
+    })
+    .await;
+    let tags = match tags {
+        Ok(tags) => tags,
+        Err(e) => {
+            log::warn!("Failed to list tags of {}/{}: {}", org, repo, e);
+            return None;
+        }
+    };
+    if tags.len() < max_tags || tags.iter().any(|tag| tag == reference) {
+        return None;
+    }
+
+    let reason = format!(
+        "repository has {} tags (limit {}), delete tags before pushing new ones",
+        tags.len(),
+        max_tags
+    );
+    log::warn!("Refused tag {}/{}:{}: {}", org, repo, reference, reason);
+    metrics::MANIFEST_LIMIT_REJECTIONS_TOTAL
+        .with_label_values(&["tags"])
+        .inc();
+    Some(response::limit_exceeded(&reason))
+}
+
+/// Refuse moving an immutable tag (`--immutable-tags` or the repository's `immutable_tags`) to
+/// a manifest other than the one it points to; re-pushing the same manifest is allowed
+async fn check_immutable(
+    state: &state::App,
+    org: &str,
+    repo: &str,
+    reference: &str,
+    digest: &str,
+) -> Option<String> {
+    if reference.starts_with("sha256:") {
+        return None;
+    }
+    let repository = state.repositories.get(&format!("{}/{}", org, repo));
+    if !repositories::is_immutable(&state.args.immutable_tags, repository.as_ref(), reference) {
+        return None;
+    }
+    let tag = reference.to_string();
+    let current = storage::blocking("read_manifest", org, repo, move |org, repo| {
+        storage::read_manifest(org, repo, &tag)
+    })
+    .await
+    .ok()?;
+    let current = sha256::digest(current.as_slice());
+    (current != digest).then(|| format!("sha256:{}", current))
+}
+
+/// Refuse a manifest past its `grain.expires-at` annotation or the expiry set on its tag, unless
+/// `--serve-expired-manifests`. Expired manifests stay stored until GC deletes them.
+fn check_expiry(
+    state: &state::App,
+    org: &str,
+    repo: &str,
+    reference: &str,
+    annotation: Option<u64>,
+) -> Option<Response> {
+    if state.args.serve_expired_manifests {
+        return None;
+    }
+    let repository = state.repositories.get(&format!("{}/{}", org, repo));
+    let tag = (!reference.starts_with("sha256:")).then_some(reference);
+    let expires_at = repositories::expiry(repository.as_ref(), tag, annotation)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    if now < expires_at {
+        return None;
+    }
+
+    log::info!(
+        "Refused expired manifest {}/{}:{} (expired at {})",
+        org,
+        repo,
+        reference,
+        expires_at
+    );
+    metrics::EXPIRED_MANIFEST_PULLS_TOTAL.inc();
+    Some(response::manifest_expired(reference, expires_at))
+}
+
 // end-3 GET /v2/:name/manifests/:reference
 pub(crate) async fn get_manifest_by_reference(
     State(state): State<Arc<state::App>>,
     Path((org, repo, reference)): Path<(String, String, String)>,
     headers: HeaderMap,
 ) -> Response<Body> {
-    let host = &state.args.host;
+    let host = state.args.primary_host();
     let repository = format!("{}/{}", org, repo);
     let clean_reference = reference.strip_prefix("sha256:").unwrap_or(&reference);
 
     // Check permission (Pull for manifest retrieval, tag-specific)
-    match auth::check_permission(
+    let user = match auth::check_permission(
         &state,
         &headers,
         &repository,
@@ -46,7 +178,7 @@ pub(crate) async fn get_manifest_by_reference(
     )
     .await
     {
-        Ok(_) => {}
+        Ok(user) => user,
         Err(_) => {
             return if auth::authenticate_user(&state, &headers).await.is_ok() {
                 response::forbidden()
@@ -54,7 +186,7 @@ pub(crate) async fn get_manifest_by_reference(
                 response::unauthorized(host)
             };
         }
-    }
+    };
 
     log::info!(
         "manifests/get_manifest_by_reference: org: {}, repo: {}, reference: {}",
@@ -63,18 +195,58 @@ pub(crate) async fn get_manifest_by_reference(
         clean_reference
     );
 
-    match storage::read_manifest(&org, &repo, clean_reference) {
-        Ok(manifest_data) => {
+    let read = {
+        let (state, reference) = (state.clone(), clean_reference.to_string());
+        storage::blocking("read_manifest", &org, &repo, move |org, repo| {
+            // The stored metadata names the digest, so hot manifests are not read again
+            let meta = storage::manifest_meta(org, repo, &reference)?;
+            if let Some(manifest_data) = state.content_cache.get(&meta.digest) {
+                return Ok((manifest_data, meta));
+            }
+            let manifest_data = Bytes::from(storage::read_manifest(org, repo, &reference)?);
+            let meta = storage::manifest_meta_of(org, repo, &reference, &manifest_data);
+            state
+                .content_cache
+                .insert(&meta.digest, manifest_data.clone());
+            Ok::<_, std::io::Error>((manifest_data, meta))
+        })
+        .await
+    };
+    match read {
+        Ok((manifest_data, meta)) => {
+            if let Some(expired) = check_expiry(&state, &org, &repo, &reference, meta.expires_at) {
+                return expired;
+            }
+            // The digest identifies the content, so it makes a strong entity tag
+            let etag = format!("\"{}\"", meta.digest);
+            if response::if_none_match(&headers, &etag) {
+                return response::not_modified(&etag, &meta.digest);
+            }
+
             metrics::MANIFEST_DOWNLOADS_TOTAL.inc();
+            state
+                .repo_metrics
+                .record(&repository, permissions::Action::Pull);
+            state.activity.record_pull(&repository);
 
-            let digest = sha256::digest(&manifest_data);
-            let content_type = detect_manifest_content_type(&manifest_data);
+            state.webhooks.notify(
+                webhooks::Event::new(
+                    webhooks::Action::Pull,
+                    &user.username,
+                    &repository,
+                    tag_of(&reference),
+                    &meta.digest,
+                    &manifest_data,
+                )
+                .with_request("GET", &headers),
+            );
 
             Response::builder()
                 .status(StatusCode::OK)
                 .header("Content-Length", manifest_data.len().to_string())
-                .header("Content-Type", content_type)
-                .header("Docker-Content-Digest", format!("sha256:{}", digest))
+                .header("Content-Type", meta.media_type)
+                .header("Docker-Content-Digest", &meta.digest)
+                .header("ETag", etag)
                 .body(Body::from(manifest_data))
                 .unwrap()
         }
@@ -97,7 +269,7 @@ pub(crate) async fn head_manifest_by_reference(
     Path((org, repo, reference)): Path<(String, String, String)>,
     headers: HeaderMap,
 ) -> Response<Body> {
-    let host = &state.args.host;
+    let host = state.args.primary_host();
     let repository = format!("{}/{}", org, repo);
     let clean_reference = reference.strip_prefix("sha256:").unwrap_or(&reference);
 
@@ -114,9 +286,9 @@ pub(crate) async fn head_manifest_by_reference(
         Ok(_) => {}
         Err(_) => {
             return if auth::authenticate_user(&state, &headers).await.is_ok() {
-                response::forbidden()
+                response::forbidden_head()
             } else {
-                response::unauthorized(host)
+                response::unauthorized_head(host)
             };
         }
     }
@@ -128,31 +300,42 @@ pub(crate) async fn head_manifest_by_reference(
         clean_reference
     );
 
-    if !storage::manifest_exists(&org, &repo, clean_reference) {
-        return response::manifest_unknown(clean_reference);
-    }
-
-    match storage::read_manifest(&org, &repo, clean_reference) {
-        Ok(manifest_data) => {
-            let digest = sha256::digest(&manifest_data);
-            let content_type = detect_manifest_content_type(&manifest_data);
+    let meta = {
+        let reference = clean_reference.to_string();
+        storage::blocking("manifest_meta", &org, &repo, move |org, repo| {
+            storage::manifest_meta(org, repo, &reference)
+        })
+        .await
+    };
+    match meta {
+        Ok(meta) => {
+            if let Some(expired) = check_expiry(&state, &org, &repo, &reference, meta.expires_at) {
+                return expired;
+            }
+            let etag = format!("\"{}\"", meta.digest);
+            if response::if_none_match(&headers, &etag) {
+                return response::not_modified(&etag, &meta.digest);
+            }
 
             Response::builder()
                 .status(StatusCode::OK)
-                .header("Content-Length", manifest_data.len().to_string())
-                .header("Content-Type", content_type)
-                .header("Docker-Content-Digest", format!("sha256:{}", digest))
+                .header("Content-Length", meta.size.to_string())
+                .header("Content-Type", meta.media_type)
+                .header("Docker-Content-Digest", &meta.digest)
+                .header("ETag", etag)
                 .body(Body::empty())
                 .unwrap()
         }
         Err(e) => {
-            log::error!(
-                "Failed to read manifest {}/{}/{}: {}",
-                org,
-                repo,
-                clean_reference,
-                e
-            );
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::error!(
+                    "Failed to read manifest {}/{}/{}: {}",
+                    org,
+                    repo,
+                    clean_reference,
+                    e
+                );
+            }
             response::manifest_unknown(clean_reference)
         }
     }
@@ -164,7 +347,7 @@ pub(crate) async fn put_manifest_by_reference(
     State(state): State<Arc<state::App>>,
     Path((org, repo, reference)): Path<(String, String, String)>,
     headers: HeaderMap,
-    body: Request<Body>,
+    request: Request<Body>,
 ) -> Response {
     log::info!(
         "manifests/put_manifest_by_reference: org: {}, repo: {}, reference: {}",
@@ -173,12 +356,12 @@ pub(crate) async fn put_manifest_by_reference(
         reference
     );
 
-    let host = &state.args.host;
+    let host = state.args.primary_host();
     let repository = format!("{}/{}", org, repo);
     let clean_reference = reference.strip_prefix("sha256:").unwrap_or(&reference);
 
     // Check permission (Push for manifest upload, tag-specific)
-    match auth::check_permission(
+    let user = match auth::check_permission(
         &state,
         &headers,
         &repository,
@@ -187,7 +370,7 @@ pub(crate) async fn put_manifest_by_reference(
     )
     .await
     {
-        Ok(_) => {}
+        Ok(user) => user,
         Err(_) => {
             return if auth::authenticate_user(&state, &headers).await.is_ok() {
                 response::forbidden()
@@ -195,21 +378,59 @@ pub(crate) async fn put_manifest_by_reference(
                 response::unauthorized(host)
             };
         }
+    };
+
+    // References with an algorithm prefix (`sha256:...`) are digests, not tags
+    let reference_digest = if reference.contains(':') {
+        match Digest::parse(&reference) {
+            Ok(digest) => Some(digest),
+            Err(e) => {
+                log::warn!("Rejected manifest reference {}: {}", reference, e);
+                return response::digest_invalid(&reference);
+            }
+        }
+    } else if validation::is_valid_tag(&reference) {
+        None
+    } else {
+        log::warn!("Rejected invalid tag {}:{}", repository, reference);
+        return response::tag_invalid(&reference);
+    };
+
+    if state.args.strict_repositories && !state.repositories.is_declared(&repository) {
+        return response::name_unknown(&repository);
     }
 
     // Convert body to bytes for validation
-    let bytes = match axum::body::to_bytes(body.into_body(), usize::MAX).await {
+    let bytes = match body::read_limited(request.into_body(), state.args.max_manifest_size).await {
         Ok(b) => b,
+        Err(BodyError::TooLarge(limit)) => {
+            log::warn!("Rejected manifest larger than {} bytes", limit);
+            return response::payload_too_large(limit);
+        }
         Err(e) => {
             log::error!("Failed to read request body: {}", e);
             return response::manifest_invalid("failed to read request body");
         }
     };
 
+    // A manifest pushed by digest must be the content that digest names
+    if let Some(expected) = &reference_digest {
+        if Digest::of(expected.algorithm(), &bytes) != *expected {
+            log::warn!(
+                "Rejected manifest for {}: content does not match {}",
+                repository,
+                reference
+            );
+            return response::digest_invalid(&reference);
+        }
+    }
+
     // Validate manifest
+    let mut warnings = Warnings::new();
     match validation::validate_manifest(&bytes) {
         Ok(media_type) => {
             log::info!("Validated manifest of type: {}", media_type);
+            warnings.extend(validation::manifest_warnings(&bytes, &media_type));
         }
         Err(e) => {
             log::warn!("Manifest validation failed: {}", e);
@@ -217,33 +438,166 @@ pub(crate) async fn put_manifest_by_reference(
         }
     }
 
+    if let Some(rejected) = check_manifest_limits(&state, &org, &repo, &reference, &bytes).await {
+        return rejected;
+    }
+
+    // Enforce label policies on the image config and manifest annotations
+    let checked = {
+        let (state, bytes) = (state.clone(), bytes.clone());
+        storage::blocking("check_push_policy", &org, &repo, move |org, repo| {
+            state.push_policy.check_manifest(org, repo, &bytes)
+        })
+        .await
+    };
+    if let Err(violations) = checked {
+        let reason = format!("push policy violated: {}", violations.join("; "));
+        log::warn!("Rejected manifest for {}: {}", repository, reason);
+        state.audit.record(
+            AuditEvent::new(
+                &user.username,
+                "manifest.push",
+                &format!("{}:{}", repository, reference),
+                Outcome::Denied,
+            )
+            .with_detail(reason.clone()),
+        );
+        return response::manifest_invalid(&reason);
+    }
+
     // Calculate digest first (will be used for storage and header)
     let digest = sha256::digest(bytes.as_ref());
 
+    if let Some(current) = check_immutable(&state, &org, &repo, &reference, &digest).await {
+        log::warn!(
+            "Refused to move immutable tag {}:{} from {}",
+            repository,
+            reference,
+            current
+        );
+        state.audit.record(
+            AuditEvent::new(
+                &user.username,
+                "manifest.push",
+                &format!("{}:{}", repository, reference),
+                Outcome::Denied,
+            )
+            .with_detail(format!("immutable tag points to {}", current)),
+        );
+        return response::tag_immutable(&reference, &current);
+    }
+
+    // A GC run that read the manifests before this one must not sweep its blobs
+    gc::in_use::record_manifest(&org, &repo, &bytes);
+
     // Store the validated manifest by the requested reference (tag or digest)
-    let success = storage::write_manifest_bytes(&org, &repo, &reference, &bytes).await;
-    if !success {
-        return response::manifest_invalid("failed to write manifest");
+    // Note: We store without "sha256:" prefix to match how GET strips the prefix
+    // Served back verbatim, as clients tell Docker and OCI manifests apart by it. Plain JSON
+    // says nothing about the manifest, which is then served with the media type found in it.
+    let content_type = headers
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| {
+            let essence = v.split(';').next().unwrap_or_default().trim();
+            !essence.is_empty() && !essence.eq_ignore_ascii_case("application/json")
+        });
+    if let Err(e) =
+        storage::write_manifest_bytes(&org, &repo, clean_reference, &bytes, content_type).await
+    {
+        log::error!(
+            "Failed to write manifest {}:{}: {}",
+            repository,
+            reference,
+            e
+        );
+        return response::storage_error(&e);
     }
 
     // If reference is a tag (not a digest), also store by digest for retrieval by digest
     // This allows manifests to be retrieved both by tag and by content-addressable digest
-    // Note: We store without "sha256:" prefix to match how GET strips the prefix
     if !reference.starts_with("sha256:") {
-        storage::write_manifest_bytes(&org, &repo, &digest, &bytes).await;
+        if let Err(e) =
+            storage::write_manifest_bytes(&org, &repo, &digest, &bytes, content_type).await
+        {
+            log::error!(
+                "Failed to write manifest {}@sha256:{}: {}",
+                repository,
+                digest,
+                e
+            );
+            return response::storage_error(&e);
+        }
+    }
+
+    // An expiry set on the tag applied to the manifest it replaced
+    if !reference.starts_with("sha256:")
+        && state
+            .repositories
+            .get(&repository)
+            .is_some_and(|r| r.tag_expiry.contains_key(&reference))
+    {
+        if let Err(e) = state.repositories.set_expiry(&repository, &reference, None) {
+            log::warn!(
+                "Failed to clear expiry of {}:{}: {}",
+                repository,
+                reference,
+                e
+            );
+        }
     }
 
     metrics::MANIFEST_UPLOADS_TOTAL.inc();
+    state.audit.record(
+        AuditEvent::new(
+            &user.username,
+            "manifest.push",
+            &format!("{}:{}", repository, reference),
+            Outcome::Success,
+        )
+        .with_detail(format!("sha256:{}", digest)),
+    );
+    state
+        .repo_metrics
+        .record(&repository, permissions::Action::Push);
+    state.webhooks.notify(
+        webhooks::Event::new(
+            webhooks::Action::Push,
+            &user.username,
+            &repository,
+            tag_of(&reference),
+            &format!("sha256:{}", digest),
+            &bytes,
+        )
+        .with_request("PUT", &headers),
+    );
+    state.replication.enqueue(&org, &repo, &reference, &digest);
+    state
+        .content_cache
+        .insert(&format!("sha256:{}", digest), bytes.clone());
 
-    Response::builder()
+    let mut builder = Response::builder()
         .status(201)
         .header(
             "Location",
-            format!("/v2/{}/{}/manifests/{}", org, repo, reference),
+            response::location(
+                &state.args,
+                &headers,
+                &format!("/v2/{}/{}/manifests/{}", org, repo, reference),
+            ),
         )
-        .header("Docker-Content-Digest", format!("sha256:{}", digest))
-        .body(Body::empty())
-        .expect("Failed to build response")
+        .header("Docker-Content-Digest", format!("sha256:{}", digest));
+    // Tell clients the referrers API indexed the manifest, so they skip the tag schema fallback
+    if let Some(subject) = validation::manifest_subject(&bytes)
+        .filter(|_| state.args.is_enabled(Capability::Referrers))
+    {
+        builder = builder.header("OCI-Subject", subject);
+    }
+
+    warnings.apply(
+        builder
+            .body(Body::empty())
+            .expect("Failed to build response"),
+    )
 }
 
 // end-9 DELETE /v2/:name/manifests/:reference
@@ -252,12 +606,12 @@ pub(crate) async fn delete_manifest_by_reference(
     Path((org, repo, reference)): Path<(String, String, String)>,
     headers: HeaderMap,
 ) -> Response<Body> {
-    let host = &state.args.host;
+    let host = state.args.primary_host();
     let repository = format!("{}/{}", org, repo);
     let clean_reference = reference.strip_prefix("sha256:").unwrap_or(&reference);
 
     // Check permission (Delete for manifest deletion, tag-specific)
-    match auth::check_permission(
+    let user = match auth::check_permission(
         &state,
         &headers,
         &repository,
@@ -266,7 +620,7 @@ pub(crate) async fn delete_manifest_by_reference(
     )
     .await
     {
-        Ok(_) => {}
+        Ok(user) => user,
         Err(_) => {
             return if auth::authenticate_user(&state, &headers).await.is_ok() {
                 response::forbidden()
@@ -274,7 +628,7 @@ pub(crate) async fn delete_manifest_by_reference(
                 response::unauthorized(host)
             };
         }
-    }
+    };
 
     log::info!(
         "manifests/delete_manifest_by_reference: org: {}, repo: {}, reference: {}",
@@ -283,10 +637,114 @@ pub(crate) async fn delete_manifest_by_reference(
         clean_reference
     );
 
-    // Delete manifest
-    match storage::delete_manifest(&org, &repo, clean_reference) {
-        Ok(()) => {
-            log::info!("Deleted manifest {}/{}/{}", org, repo, clean_reference);
+    // Deleting a tag only untags; deleting a digest removes the manifest and every tag
+    // pointing to it, which the caller must be allowed to delete too
+    let by_digest = clean_reference != reference;
+    let tags = if by_digest {
+        let reference = clean_reference.to_string();
+        let tags = storage::blocking("tags_of", &org, &repo, move |org, repo| {
+            if storage::manifest_exists(org, repo, &reference) {
+                storage::tags_of(org, repo, &reference)
+            } else {
+                Ok(Vec::new())
+            }
+        })
+        .await;
+        match tags {
+            Ok(tags) => tags,
+            Err(e) => {
+                log::error!("Failed to list tags of {}/{}: {}", org, repo, e);
+                return response::storage_error(&e.into());
+            }
+        }
+    } else {
+        Vec::new()
+    };
+    if let Some(tag) = tags.iter().find(|tag| {
+        !permissions::has_permission(&user, &repository, Some(tag), permissions::Action::Delete)
+    }) {
+        log::warn!(
+            "User {} may not delete tag {}:{} of manifest {}",
+            user.username,
+            repository,
+            tag,
+            reference
+        );
+        state.audit.record(
+            AuditEvent::new(
+                &user.username,
+                "manifest.delete",
+                &format!("{}@{}", repository, reference),
+                Outcome::Denied,
+            )
+            .with_detail(format!("no delete permission on tag {}", tag)),
+        );
+        return response::forbidden();
+    }
+
+    // The manifest is kept for the webhook event, which describes what was deleted. Tags go
+    // first, so an interrupted deletion leaves an untagged manifest rather than tags pointing
+    // to nothing.
+    let deleted = {
+        let (reference, tags) = (clean_reference.to_string(), tags.clone());
+        storage::blocking("delete_manifest", &org, &repo, move |org, repo| {
+            let manifest = storage::read_manifest(org, repo, &reference).unwrap_or_default();
+            for tag in &tags {
+                if let Err(e) = storage::delete_manifest(org, repo, tag) {
+                    if !e.is_not_found() {
+                        log::error!("Failed to untag {}/{}:{}: {}", org, repo, tag, e);
+                        return Err(e);
+                    }
+                }
+            }
+            storage::delete_manifest(org, repo, &reference).map(|()| manifest)
+        })
+        .await
+    };
+
+    match deleted {
+        Ok(manifest) => {
+            let event = if by_digest {
+                state.content_cache.remove(&reference);
+                log::info!(
+                    "Deleted manifest {}/{}@{} and {} tags",
+                    org,
+                    repo,
+                    reference,
+                    tags.len()
+                );
+                let event = AuditEvent::new(
+                    &user.username,
+                    "manifest.delete",
+                    &format!("{}@{}", repository, reference),
+                    Outcome::Success,
+                );
+                if tags.is_empty() {
+                    event
+                } else {
+                    event.with_detail(format!("untagged {}", tags.join(", ")))
+                }
+            } else {
+                log::info!("Untagged {}/{}:{}", org, repo, reference);
+                AuditEvent::new(
+                    &user.username,
+                    "tag.delete",
+                    &format!("{}:{}", repository, reference),
+                    Outcome::Success,
+                )
+            };
+            state.audit.record(event);
+            state.webhooks.notify(
+                webhooks::Event::new(
+                    webhooks::Action::Delete,
+                    &user.username,
+                    &repository,
+                    tag_of(&reference),
+                    &format!("sha256:{}", sha256::digest(manifest.as_slice())),
+                    &manifest,
+                )
+                .with_request("DELETE", &headers),
+            );
 
             Response::builder()
                 .status(StatusCode::ACCEPTED)
@@ -294,14 +752,13 @@ pub(crate) async fn delete_manifest_by_reference(
                 .unwrap()
         }
         Err(e) => {
-            if e.kind() == std::io::ErrorKind::NotFound {
+            if e.is_not_found() {
                 log::warn!(
                     "Attempted to delete non-existent manifest {}/{}/{}",
                     org,
                     repo,
                     clean_reference
                 );
-                response::manifest_unknown(clean_reference)
             } else {
                 log::error!(
                     "Failed to delete manifest {}/{}/{}: {}",
@@ -310,8 +767,8 @@ pub(crate) async fn delete_manifest_by_reference(
                     clean_reference,
                     e
                 );
-                response::internal_error()
             }
+            response::storage_error(&e)
         }
     }
 }
@@ -1,9 +1,34 @@
-use axum::{body::Body, http::StatusCode, response::Response};
+use axum::{
+    body::Body,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
 use prometheus::{
-    register_histogram_vec, register_int_counter, register_int_counter_vec, Encoder, HistogramVec,
-    IntCounter, IntCounterVec, TextEncoder,
+    exponential_buckets, proto::MetricType, register_histogram, register_histogram_vec,
+    register_int_counter, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    Encoder, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder, DEFAULT_BUCKETS,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use crate::{args::Args, permissions, state, storage};
+
+const OTHER_REPOSITORY_LABEL: &str = "other";
+
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// A traced observation, shown next to the histogram bucket it fell into
+struct Exemplar {
+    trace_id: String,
+    value: f64,
+    timestamp: f64,
+}
+
 lazy_static::lazy_static! {
     // Request counters
     pub static ref HTTP_REQUESTS_TOTAL: IntCounterVec = register_int_counter_vec!(
@@ -17,11 +42,176 @@ lazy_static::lazy_static! {
         "Total number of blob uploads"
     ).unwrap();
 
+    pub static ref ACTIVE_UPLOAD_SESSIONS: IntGauge = register_int_gauge!(
+        "grain_active_upload_sessions",
+        "Blob upload sessions started and not yet completed, cancelled or expired"
+    ).unwrap();
+
+    pub static ref UPLOAD_SESSIONS_EXPIRED_TOTAL: IntCounter = register_int_counter!(
+        "grain_upload_sessions_expired_total",
+        "Total number of idle upload sessions deleted after --upload-session-ttl-secs"
+    ).unwrap();
+
+    pub static ref EXPIRED_MANIFEST_PULLS_TOTAL: IntCounter = register_int_counter!(
+        "grain_expired_manifest_pulls_total",
+        "Total number of manifest pulls refused because the manifest expired"
+    ).unwrap();
+
     pub static ref BLOB_DOWNLOADS_TOTAL: IntCounter = register_int_counter!(
         "grain_blob_downloads_total",
         "Total number of blob downloads"
     ).unwrap();
 
+    // Blob download accounting (full vs partial content, resumes, aborts)
+    pub static ref LISTENER_REQUESTS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "grain_listener_requests_total",
+        "Total number of HTTP requests per listen address",
+        &["listener"]
+    ).unwrap();
+
+    pub static ref BLOB_DOWNLOAD_RESPONSES_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "grain_blob_download_responses_total",
+        "Total number of blob download responses by status code",
+        &["status"]
+    ).unwrap();
+
+    pub static ref BLOB_DOWNLOAD_BYTES_TOTAL: IntCounter = register_int_counter!(
+        "grain_blob_download_bytes_total",
+        "Total number of blob bytes sent to clients"
+    ).unwrap();
+
+    pub static ref BLOB_DOWNLOAD_RESUME_OFFSET_BYTES: Histogram = register_histogram!(
+        "grain_blob_download_resume_offset_bytes",
+        "Start offset of resumed blob downloads (bytes not retransferred)",
+        exponential_buckets(1024.0, 4.0, 12).unwrap()
+    ).unwrap();
+
+    pub static ref BLOB_DOWNLOADS_ABORTED_TOTAL: IntCounter = register_int_counter!(
+        "grain_blob_downloads_aborted_total",
+        "Total number of blob downloads dropped before the body was fully sent"
+    ).unwrap();
+
+    // Per-repository operations (opt-in, cardinality bounded by RepoLabeler)
+    pub static ref REPOSITORY_OPERATIONS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "grain_repository_operations_total",
+        "Total number of pull/push operations per repository",
+        &["repository", "operation"]
+    ).unwrap();
+
+    pub static ref RATE_LIMITED_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "grain_rate_limited_total",
+        "Total number of requests refused by a rate limit",
+        &["class"]
+    ).unwrap();
+
+    pub static ref WEBHOOK_DELIVERIES_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "grain_webhook_deliveries_total",
+        "Total number of webhook deliveries, by whether they succeeded within the retries or were dropped from a full queue",
+        &["outcome"]
+    ).unwrap();
+
+    pub static ref REPLICATION_PUSHES_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "grain_replication_pushes_total",
+        "Total number of manifest copies to --replicate-to peers, by outcome",
+        &["outcome"]
+    ).unwrap();
+
+    pub static ref REPLICATION_QUEUED: IntGaugeVec = register_int_gauge_vec!(
+        "grain_replication_queued",
+        "Manifests waiting to be copied to each --replicate-to peer",
+        &["peer"]
+    ).unwrap();
+
+    pub static ref UPLOAD_QUOTA_REJECTIONS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "grain_upload_quota_rejections_total",
+        "Total number of upload sessions refused because a repository quota was reached",
+        &["limit"]
+    ).unwrap();
+
+    pub static ref MANIFEST_LIMIT_REJECTIONS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "grain_manifest_limit_rejections_total",
+        "Total number of manifest pushes refused by the tag or layer count limits",
+        &["limit"]
+    ).unwrap();
+
+    // Storage backend operations, to tell slow disks or object stores apart from the rest
+    pub static ref STORAGE_OPERATION_DURATION: HistogramVec = register_histogram_vec!(
+        "grain_storage_operation_duration_seconds",
+        "Storage operation duration in seconds",
+        &["backend", "operation"],
+        exponential_buckets(0.0005, 4.0, 10).unwrap()
+    ).unwrap();
+
+    pub static ref STORAGE_OPERATION_ERRORS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "grain_storage_operation_errors_total",
+        "Total number of failed storage operations (missing objects excluded)",
+        &["backend", "operation"]
+    ).unwrap();
+
+    pub static ref STORAGE_OPERATIONS_CANCELLED_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "grain_storage_operations_cancelled_total",
+        "Total number of storage operations cancelled because the client went away",
+        &["operation"]
+    ).unwrap();
+
+    pub static ref CONTENT_CACHE_REQUESTS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "grain_content_cache_requests_total",
+        "Total number of manifest and blob lookups in the content cache, by result (hit, miss)",
+        &["result"]
+    ).unwrap();
+
+    pub static ref CONTENT_CACHE_BYTES: IntGauge = register_int_gauge!(
+        "grain_content_cache_bytes",
+        "Size of the manifests and blobs held in the content cache"
+    ).unwrap();
+
+    // Storage usage, sampled every --storage-metrics-interval-secs
+    pub static ref STORAGE_BLOB_BYTES: IntGauge = register_int_gauge!(
+        "grain_storage_blob_bytes",
+        "Total size of the blobs held in storage"
+    ).unwrap();
+
+    pub static ref STORAGE_BLOBS: IntGauge = register_int_gauge!(
+        "grain_storage_blobs",
+        "Number of blobs held in storage, counted once per repository"
+    ).unwrap();
+
+    pub static ref STORAGE_MANIFESTS: IntGauge = register_int_gauge!(
+        "grain_storage_manifests",
+        "Number of manifests held in storage, counted by digest"
+    ).unwrap();
+
+    pub static ref UPLOAD_SESSIONS: IntGauge = register_int_gauge!(
+        "grain_upload_sessions",
+        "Number of open blob upload sessions"
+    ).unwrap();
+
+    pub static ref STORAGE_USAGE_SAMPLED_TIMESTAMP_SECONDS: IntGauge = register_int_gauge!(
+        "grain_storage_usage_sampled_timestamp_seconds",
+        "Unix time of the last successful storage usage sample"
+    ).unwrap();
+
+    // Warm standby replication
+    pub static ref STANDBY_CHANGES_APPLIED_TOTAL: IntCounter = register_int_counter!(
+        "grain_standby_changes_applied_total",
+        "Total number of primary changes applied by this standby"
+    ).unwrap();
+
+    pub static ref STANDBY_SYNC_FAILURES_TOTAL: IntCounter = register_int_counter!(
+        "grain_standby_sync_failures_total",
+        "Total number of failed standby sync rounds"
+    ).unwrap();
+
+    pub static ref STANDBY_POSITION: IntGauge = register_int_gauge!(
+        "grain_standby_position",
+        "Primary change log sequence number applied by this standby"
+    ).unwrap();
+
+    pub static ref STANDBY_LAST_SYNC_TIMESTAMP_SECONDS: IntGauge = register_int_gauge!(
+        "grain_standby_last_sync_timestamp_seconds",
+        "Unix time of the last successful standby sync round"
+    ).unwrap();
+
     pub static ref MANIFEST_UPLOADS_TOTAL: IntCounter = register_int_counter!(
         "grain_manifest_uploads_total",
         "Total number of manifest uploads"
@@ -37,21 +227,210 @@ lazy_static::lazy_static! {
         "Total number of authentication failures"
     ).unwrap();
 
+    pub static ref TOKEN_VALIDATION_FAILURES_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "grain_token_validation_failures_total",
+        "Total number of refused bearer and OIDC tokens, by source and failure class",
+        &["source", "reason"]
+    ).unwrap();
+
     pub static ref PERMISSION_DENIALS_TOTAL: IntCounter = register_int_counter!(
         "grain_permission_denials_total",
         "Total number of permission denials"
     ).unwrap();
 
+    pub static ref AUTH_ENTRIES_REMOVED_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "grain_auth_entries_removed_total",
+        "Total number of stale auth entries removed by the cleanup job",
+        &["kind"]
+    ).unwrap();
+
     // Latency histograms
     pub static ref REQUEST_DURATION: HistogramVec = register_histogram_vec!(
         "grain_request_duration_seconds",
         "HTTP request duration in seconds",
         &["method", "endpoint"]
     ).unwrap();
+
+    // Latest exemplar of REQUEST_DURATION per method, endpoint and bucket index
+    static ref REQUEST_DURATION_EXEMPLARS: Mutex<HashMap<(String, String, usize), Exemplar>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Observe a request's duration, keeping the trace it belongs to as the bucket's exemplar
+pub fn observe_request_duration(
+    method: &str,
+    endpoint: &str,
+    duration: f64,
+    trace_id: Option<&str>,
+) {
+    REQUEST_DURATION
+        .with_label_values(&[method, endpoint])
+        .observe(duration);
+
+    if let Some(trace_id) = trace_id {
+        let bucket = DEFAULT_BUCKETS
+            .iter()
+            .position(|bound| duration <= *bound)
+            .unwrap_or(DEFAULT_BUCKETS.len());
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0);
+        REQUEST_DURATION_EXEMPLARS.lock().unwrap().insert(
+            (method.to_string(), endpoint.to_string(), bucket),
+            Exemplar {
+                trace_id: trace_id.to_string(),
+                value: duration,
+                timestamp,
+            },
+        );
+    }
+}
+
+/// Record a blob download response, observing the resume offset for partial content
+pub fn record_blob_download(status: StatusCode, offset: u64) {
+    BLOB_DOWNLOAD_RESPONSES_TOTAL
+        .with_label_values(&[status.as_str()])
+        .inc();
+
+    if offset > 0 {
+        BLOB_DOWNLOAD_RESUME_OFFSET_BYTES.observe(offset as f64);
+    }
+}
+
+/// Time a storage operation and count its failure. `NotFound` is an answer rather than a failure,
+/// and cancellations are counted by `STORAGE_OPERATIONS_CANCELLED_TOTAL`.
+pub fn observe_storage_operation<T>(
+    backend: &str,
+    operation: &str,
+    f: impl FnOnce() -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    let timer = STORAGE_OPERATION_DURATION
+        .with_label_values(&[backend, operation])
+        .start_timer();
+    let result = f();
+    timer.observe_duration();
+
+    if let Err(e) = &result {
+        if !matches!(
+            e.kind(),
+            std::io::ErrorKind::NotFound | std::io::ErrorKind::Interrupted
+        ) {
+            STORAGE_OPERATION_ERRORS_TOTAL
+                .with_label_values(&[backend, operation])
+                .inc();
+        }
+    }
+    result
+}
+
+/// Maps repositories to metric labels, collapsing filtered and long-tail repositories into "other"
+pub struct RepoLabeler {
+    enabled: bool,
+    allow: Vec<String>,
+    deny: Vec<String>,
+    max_labels: usize,
+    labels: Mutex<HashSet<String>>,
+}
+
+impl RepoLabeler {
+    pub fn new(enabled: bool, allow: Vec<String>, deny: Vec<String>, max_labels: usize) -> Self {
+        Self {
+            enabled,
+            allow,
+            deny,
+            max_labels,
+            labels: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn from_args(args: &Args) -> Self {
+        Self::new(
+            args.per_repo_metrics,
+            args.metrics_repo_allow.clone(),
+            args.metrics_repo_deny.clone(),
+            args.metrics_max_repos,
+        )
+    }
+
+    /// Label for a repository, or None when per-repository metrics are disabled
+    pub fn label(&self, repository: &str) -> Option<String> {
+        if !self.enabled {
+            return None;
+        }
+
+        let denied = self
+            .deny
+            .iter()
+            .any(|p| permissions::matches_pattern(p, repository));
+        let allowed = self.allow.is_empty()
+            || self
+                .allow
+                .iter()
+                .any(|p| permissions::matches_pattern(p, repository));
+        if denied || !allowed {
+            return Some(OTHER_REPOSITORY_LABEL.to_string());
+        }
+
+        let mut labels = self.labels.lock().unwrap();
+        if labels.contains(repository) || labels.len() < self.max_labels {
+            labels.insert(repository.to_string());
+            Some(repository.to_string())
+        } else {
+            Some(OTHER_REPOSITORY_LABEL.to_string())
+        }
+    }
+
+    /// Count a pull/push against the repository when per-repository metrics are enabled
+    pub fn record(&self, repository: &str, operation: permissions::Action) {
+        if let Some(label) = self.label(repository) {
+            REPOSITORY_OPERATIONS_TOTAL
+                .with_label_values(&[label.as_str(), operation.as_str()])
+                .inc();
+        }
+    }
+}
+
+/// Sample storage usage into the storage gauges
+fn sample_storage_usage() -> Result<(), std::io::Error> {
+    let usage = storage::usage()?;
+    let sessions = storage::upload_session_count()?;
+    STORAGE_BLOB_BYTES.set(i64::try_from(usage.blob_bytes).unwrap_or(i64::MAX));
+    STORAGE_BLOBS.set(usage.blobs as i64);
+    STORAGE_MANIFESTS.set(usage.manifests as i64);
+    UPLOAD_SESSIONS.set(sessions as i64);
+    STORAGE_USAGE_SAMPLED_TIMESTAMP_SECONDS.set(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0),
+    );
+    Ok(())
+}
+
+/// Start refreshing the storage usage gauges every `--storage-metrics-interval-secs`
+pub(crate) fn spawn_storage_sampler(args: &Args) {
+    let interval_secs = args.storage_metrics_interval_secs;
+    if interval_secs == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            match tokio::task::spawn_blocking(sample_storage_usage).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => log::error!("metrics: failed to sample storage usage: {}", e),
+                Err(e) => log::error!("metrics: storage usage task failed: {}", e),
+            }
+        }
+    });
 }
 
-/// Prometheus metrics endpoint
-pub async fn metrics() -> Response {
+/// Prometheus metrics endpoint. With `--trace-exemplars`, scrapers asking for OpenMetrics also
+/// get the request duration exemplars.
+pub async fn metrics(State(state): State<Arc<state::App>>, headers: HeaderMap) -> Response {
     let encoder = TextEncoder::new();
     let metric_families = prometheus::gather();
 
@@ -64,6 +443,25 @@ pub async fn metrics() -> Response {
             .unwrap();
     }
 
+    let openmetrics = headers
+        .get("accept")
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/openmetrics-text"));
+    if state.args.trace_exemplars && openmetrics {
+        let counters: HashSet<&str> = metric_families
+            .iter()
+            .filter(|family| family.get_field_type() == MetricType::COUNTER)
+            .map(|family| family.name())
+            .collect();
+        let text = String::from_utf8_lossy(&buffer);
+        let exemplars = REQUEST_DURATION_EXEMPLARS.lock().unwrap();
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", OPENMETRICS_CONTENT_TYPE)
+            .body(Body::from(to_openmetrics(&text, &counters, &exemplars)))
+            .unwrap();
+    }
+
     Response::builder()
         .status(StatusCode::OK)
         .header(
@@ -73,3 +471,166 @@ pub async fn metrics() -> Response {
         .body(Body::from(buffer))
         .unwrap()
 }
+
+/// Convert the Prometheus text format to OpenMetrics: counter families drop their `_total`
+/// suffix, request duration buckets get their exemplars and the exposition ends with `# EOF`
+fn to_openmetrics(
+    text: &str,
+    counters: &HashSet<&str>,
+    exemplars: &HashMap<(String, String, usize), Exemplar>,
+) -> String {
+    let mut output = String::with_capacity(text.len());
+    for line in text.lines() {
+        if let Some((kind, rest)) = line
+            .strip_prefix("# HELP ")
+            .map(|rest| ("HELP", rest))
+            .or_else(|| line.strip_prefix("# TYPE ").map(|rest| ("TYPE", rest)))
+        {
+            let (name, tail) = rest.split_once(' ').unwrap_or((rest, ""));
+            let name = match name.strip_suffix("_total") {
+                Some(family) if counters.contains(name) => family,
+                _ => name,
+            };
+            output.push_str(&format!("# {} {} {}\n", kind, name, tail));
+            continue;
+        }
+
+        output.push_str(line);
+        if let Some(exemplar) = line
+            .strip_prefix("grain_request_duration_seconds_bucket{")
+            .and_then(|labels| bucket_exemplar(labels, exemplars))
+        {
+            output.push_str(&format!(
+                " # {{trace_id=\"{}\"}} {} {}",
+                exemplar.trace_id, exemplar.value, exemplar.timestamp
+            ));
+        }
+        output.push('\n');
+    }
+    output.push_str("# EOF\n");
+    output
+}
+
+fn bucket_exemplar<'a>(
+    labels: &str,
+    exemplars: &'a HashMap<(String, String, usize), Exemplar>,
+) -> Option<&'a Exemplar> {
+    let method = label_value(labels, "method")?;
+    let endpoint = label_value(labels, "endpoint")?;
+    let le: f64 = label_value(labels, "le")?.parse().ok()?;
+    let bucket = DEFAULT_BUCKETS
+        .iter()
+        .position(|bound| *bound == le)
+        .unwrap_or(DEFAULT_BUCKETS.len());
+    exemplars.get(&(method, endpoint, bucket))
+}
+
+/// Unescaped value of a label in the `name="value",...` list of a sample
+fn label_value(labels: &str, name: &str) -> Option<String> {
+    let prefix = format!("{}=\"", name);
+    let start = if labels.starts_with(&prefix) {
+        prefix.len()
+    } else {
+        labels.find(&format!(",{}", prefix))? + prefix.len() + 1
+    };
+
+    let mut value = String::new();
+    let mut chars = labels[start..].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                escaped => value.push(escaped),
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo_labeler_disabled() {
+        let labeler = RepoLabeler::new(false, vec![], vec![], 10);
+        assert_eq!(labeler.label("myorg/myrepo"), None);
+    }
+
+    #[test]
+    fn test_repo_labeler_allow_deny() {
+        let labeler = RepoLabeler::new(
+            true,
+            vec!["team/*".to_string()],
+            vec!["team/ci-*".to_string()],
+            10,
+        );
+        assert_eq!(labeler.label("team/app").as_deref(), Some("team/app"));
+        assert_eq!(labeler.label("team/ci-1234").as_deref(), Some("other"));
+        assert_eq!(labeler.label("elsewhere/app").as_deref(), Some("other"));
+    }
+
+    #[test]
+    fn test_repo_labeler_cardinality_cap() {
+        let labeler = RepoLabeler::new(true, vec![], vec![], 2);
+        assert_eq!(labeler.label("a/one").as_deref(), Some("a/one"));
+        assert_eq!(labeler.label("a/two").as_deref(), Some("a/two"));
+        assert_eq!(labeler.label("a/three").as_deref(), Some("other"));
+        // Already-known repositories keep their label
+        assert_eq!(labeler.label("a/one").as_deref(), Some("a/one"));
+    }
+
+    #[test]
+    fn test_to_openmetrics() {
+        let text = "# HELP grain_http_requests_total Total number of HTTP requests\n\
+# TYPE grain_http_requests_total counter\n\
+grain_http_requests_total{endpoint=\"/v2/\",method=\"GET\",status=\"200\"} 1\n\
+# HELP grain_request_duration_seconds HTTP request duration in seconds\n\
+# TYPE grain_request_duration_seconds histogram\n\
+grain_request_duration_seconds_bucket{endpoint=\"/v2/\",method=\"GET\",le=\"0.005\"} 0\n\
+grain_request_duration_seconds_bucket{endpoint=\"/v2/\",method=\"GET\",le=\"0.01\"} 1\n\
+grain_request_duration_seconds_bucket{endpoint=\"/v2/\",method=\"GET\",le=\"+Inf\"} 1\n";
+        let counters = HashSet::from(["grain_http_requests_total"]);
+        let exemplars = HashMap::from([(
+            ("GET".to_string(), "/v2/".to_string(), 1),
+            Exemplar {
+                trace_id: "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+                value: 0.007,
+                timestamp: 1700000000.5,
+            },
+        )]);
+
+        let output = to_openmetrics(text, &counters, &exemplars);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            "# HELP grain_http_requests Total number of HTTP requests"
+        );
+        assert_eq!(lines[1], "# TYPE grain_http_requests counter");
+        assert_eq!(lines[4], "# TYPE grain_request_duration_seconds histogram");
+        assert!(!lines[5].contains('#'));
+        assert_eq!(
+            lines[6],
+            "grain_request_duration_seconds_bucket{endpoint=\"/v2/\",method=\"GET\",le=\"0.01\"} 1 \
+             # {trace_id=\"4bf92f3577b34da6a3ce929d0e0e4736\"} 0.007 1700000000.5"
+        );
+        assert_eq!(lines.last(), Some(&"# EOF"));
+    }
+
+    #[test]
+    fn test_label_value() {
+        let labels = r#"endpoint="/v2/{org}/{repo}/tags/list",method="GET",le="0.5"} 3"#;
+        assert_eq!(
+            label_value(labels, "endpoint").as_deref(),
+            Some("/v2/{org}/{repo}/tags/list")
+        );
+        assert_eq!(label_value(labels, "le").as_deref(), Some("0.5"));
+        assert_eq!(
+            label_value(r#"path="a\"b""#, "path").as_deref(),
+            Some("a\"b")
+        );
+        assert_eq!(label_value(labels, "status"), None);
+    }
+}
@@ -1,12 +1,157 @@
-use axum::{extract::Request, middleware::Next, response::Response};
-use std::time::Instant;
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::{
+    io::Write,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use crate::metrics;
+use crate::{
+    access_log, archive,
+    body::{self, BodyError},
+    metrics, response, state, validation,
+};
 
-pub async fn track_metrics(req: Request, next: Next) -> Response {
+// Label for requests that did not match any route
+const UNMATCHED_ENDPOINT: &str = "unmatched";
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest incoming `X-Request-Id` kept; longer ones are replaced by a generated ID
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Largest error body the request ID is added to; bigger ones are passed through untouched
+const MAX_ERROR_BODY_SIZE: u64 = 64 * 1024;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// ID of the request being handled by the current task, if any
+pub(crate) fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Log like env_logger's default format, with the ID of the request being handled
+pub(crate) fn init_logging() {
+    env_logger::Builder::from_default_env()
+        .format(|buf, record| {
+            let request_id = request_id()
+                .map(|id| format!(" request_id={}", id))
+                .unwrap_or_default();
+            writeln!(
+                buf,
+                "[{} {} {}{}] {}",
+                buf.timestamp(),
+                record.level(),
+                record.target(),
+                request_id,
+                record.args()
+            )
+        })
+        .init();
+}
+
+/// The client's `X-Request-Id` when it is short printable ASCII, or a new UUID
+fn incoming_request_id(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.chars().all(|c| c.is_ascii_graphic())
+        })
+        .map_or_else(|| uuid::Uuid::new_v4().to_string(), String::from)
+}
+
+/// Tag each request with an `X-Request-Id`, the client's or a generated one. Handlers see it in
+/// the request headers, log lines written while handling the request carry it, and responses
+/// echo it, in the header and in the body of OCI errors.
+pub async fn propagate_request_id(mut req: Request, next: Next) -> Response {
+    let id = incoming_request_id(req.headers());
+    let value = HeaderValue::from_str(&id).expect("request IDs are printable ASCII");
+    req.headers_mut().insert(REQUEST_ID_HEADER, value.clone());
+    // HEAD responses have no body to add the ID to
+    let has_body = req.method() != Method::HEAD;
+
+    let mut response = REQUEST_ID.scope(id.clone(), next.run(req)).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    if has_body && (response.status().is_client_error() || response.status().is_server_error()) {
+        response = with_request_id_in_errors(response, &id).await;
+    }
+    response
+}
+
+/// Add `request_id` next to the `errors` of an OCI error body
+async fn with_request_id_in_errors(response: Response, id: &str) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match body::read_limited(body, MAX_ERROR_BODY_SIZE).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            log::warn!(
+                "middleware/propagate_request_id: cannot read error body: {}",
+                e
+            );
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let mut json = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(json) if json.get("errors").is_some_and(|e| e.is_array()) => json,
+        _ => return Response::from_parts(parts, Body::from(bytes)),
+    };
+    json["request_id"] = serde_json::Value::from(id);
+    let bytes = serde_json::to_vec(&json).unwrap_or_else(|_| bytes.to_vec());
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// Listen address (`--host`) a request came in on, attached to each listener's router
+#[derive(Clone)]
+pub(crate) struct Listener(pub(crate) Arc<str>);
+
+fn listener(req: &Request) -> Arc<str> {
+    req.extensions()
+        .get::<Listener>()
+        .map_or_else(|| Arc::from("unknown"), |l| l.0.clone())
+}
+
+pub async fn track_metrics(
+    State(state): State<Arc<state::App>>,
+    req: Request,
+    next: Next,
+) -> Response {
     let start = Instant::now();
     let method = req.method().to_string();
-    let path = req.uri().path().to_string();
+    let listener = listener(&req);
+    let trace_id = state
+        .args
+        .trace_exemplars
+        .then(|| sampled_trace_id(req.headers()))
+        .flatten();
+
+    // Label by route template (e.g. /v2/{org}/{repo}/blobs/{digest}) to avoid cardinality explosion
+    let endpoint = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| UNMATCHED_ENDPOINT.to_string());
 
     // Process request
     let response = next.run(req).await;
@@ -15,47 +160,142 @@ pub async fn track_metrics(req: Request, next: Next) -> Response {
     let duration = start.elapsed().as_secs_f64();
     let status = response.status().as_u16().to_string();
 
-    // Normalize endpoint for metrics (avoid cardinality explosion)
-    let endpoint = normalize_endpoint(&path);
-
     metrics::HTTP_REQUESTS_TOTAL
         .with_label_values(&[&method, &endpoint, &status])
         .inc();
 
-    metrics::REQUEST_DURATION
-        .with_label_values(&[&method, &endpoint])
-        .observe(duration);
+    metrics::observe_request_duration(&method, &endpoint, duration, trace_id.as_deref());
+    metrics::LISTENER_REQUESTS_TOTAL
+        .with_label_values(&[&*listener])
+        .inc();
 
     response
 }
 
-fn normalize_endpoint(path: &str) -> String {
-    // Replace dynamic segments with placeholders
-    if path == "/v2/" {
-        return "/v2/".to_string();
+/// Trace ID of a W3C `traceparent` header (`00-<trace-id>-<parent-id>-<flags>`) whose trace is
+/// sampled; unsampled traces never reach the tracing backend, so they make no exemplar
+fn sampled_trace_id(headers: &HeaderMap) -> Option<String> {
+    let traceparent = headers.get("traceparent")?.to_str().ok()?;
+    let mut parts = traceparent.trim().split('-');
+    let (Some(version), Some(trace_id), Some(parent_id), Some(flags)) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    let is_hex = |s: &str, len: usize| s.len() == len && s.chars().all(|c| c.is_ascii_hexdigit());
+    let sampled = u8::from_str_radix(flags, 16).is_ok_and(|flags| flags & 1 == 1);
+    (version != "ff"
+        && is_hex(version, 2)
+        && is_hex(trace_id, 32)
+        && is_hex(parent_id, 16)
+        && is_hex(flags, 2)
+        && sampled
+        && trace_id.chars().any(|c| c != '0'))
+    .then(|| trace_id.to_ascii_lowercase())
+}
+
+/// Refuse registry API requests for repository names the distribution spec does not allow
+/// with `400 NAME_INVALID`, rather than storing them under a mangled name
+pub async fn validate_repository_name(req: Request, next: Next) -> Response {
+    match archive::repository_of(req.uri().path()) {
+        Some(name) if !validation::is_valid_repository_name(&name) => {
+            log::warn!("middleware/validate_repository_name: invalid name {}", name);
+            response::name_invalid(&name)
+        }
+        _ => next.run(req).await,
     }
-    if path.starts_with("/v2/") {
-        if path.contains("/blobs/") {
-            if path.contains("/uploads/") {
-                return "/v2/{name}/blobs/uploads/{reference}".to_string();
-            }
-            return "/v2/{name}/blobs/{digest}".to_string();
-        } else if path.contains("/manifests/") {
-            return "/v2/{name}/manifests/{reference}".to_string();
-        } else if path.contains("/tags/") {
-            return "/v2/{name}/tags/list".to_string();
+}
+
+/// Headers a reverse proxy describes the client-facing URL with
+const FORWARDING_HEADERS: [&str; 3] = ["forwarded", "x-forwarded-proto", "x-forwarded-host"];
+
+/// Remove forwarding headers unless the peer is one of `--trusted-proxies`, so clients cannot
+/// point generated `Location` headers or auth challenge realms elsewhere, then handle the request
+/// with the origin those headers describe
+pub async fn drop_untrusted_forwarding(
+    State(state): State<Arc<state::App>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let trusted = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .is_some_and(|info| access_log::is_trusted(&state.args.trusted_proxies, info.0.ip()));
+    if !trusted {
+        for name in FORWARDING_HEADERS {
+            req.headers_mut().remove(name);
         }
     }
-    if path.starts_with("/admin/") {
-        if path.contains("/users/") && path.split('/').count() > 3 {
-            if path.contains("/permissions") {
-                return "/admin/users/{username}/permissions".to_string();
-            }
-            return "/admin/users/{username}".to_string();
+    let origin = response::origin(&state.args, req.headers());
+    response::with_origin(origin, next.run(req)).await
+}
+
+/// Buffer admin API request bodies up to `--max-admin-body-size`, answering larger ones with
+/// `413 SIZE_INVALID` before any handler reads them
+pub async fn limit_admin_body(
+    State(state): State<Arc<state::App>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let (parts, body) = req.into_parts();
+    match body::read_limited(body, state.args.max_admin_body_size).await {
+        Ok(bytes) => {
+            next.run(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        }
+        Err(BodyError::TooLarge(limit)) => {
+            log::warn!(
+                "middleware/limit_admin_body: {} {} exceeds {} bytes",
+                parts.method,
+                parts.uri.path(),
+                limit
+            );
+            response::payload_too_large(limit)
+        }
+        Err(e) => {
+            log::warn!("middleware/limit_admin_body: {}", e);
+            Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap()
+        }
+    }
+}
+
+/// Drop requests exceeding `--request-timeout-secs`; dropping the handler cancels its storage work
+pub async fn request_timeout(
+    State(state): State<Arc<state::App>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let timeout_secs = state.args.request_timeout_secs;
+    if timeout_secs == 0 {
+        return next.run(req).await;
+    }
+
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let listener = listener(&req);
+
+    match tokio::time::timeout(Duration::from_secs(timeout_secs), next.run(req)).await {
+        Ok(response) => response,
+        Err(_) => {
+            log::warn!(
+                "middleware/request_timeout: {} {} on {} exceeded {}s",
+                method,
+                path,
+                listener,
+                timeout_secs
+            );
+            Response::builder()
+                .status(StatusCode::REQUEST_TIMEOUT)
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    r#"{"errors":[{"code":"UNKNOWN","message":"request timed out"}]}"#,
+                ))
+                .unwrap()
         }
-        return path.to_string();
     }
-    path.to_string()
 }
 
 #[cfg(test)]
@@ -63,20 +303,25 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_normalize_endpoint() {
+    fn test_sampled_trace_id() {
+        let trace_id = |traceparent: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("traceparent", traceparent.parse().unwrap());
+            sampled_trace_id(&headers)
+        };
         assert_eq!(
-            normalize_endpoint("/v2/myorg/myrepo/blobs/sha256:abc123"),
-            "/v2/{name}/blobs/{digest}"
+            trace_id("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01").as_deref(),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736")
         );
         assert_eq!(
-            normalize_endpoint("/v2/myorg/myrepo/manifests/latest"),
-            "/v2/{name}/manifests/{reference}"
+            trace_id("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00"),
+            None
         );
         assert_eq!(
-            normalize_endpoint("/v2/myorg/myrepo/tags/list"),
-            "/v2/{name}/tags/list"
+            trace_id("00-00000000000000000000000000000000-00f067aa0ba902b7-01"),
+            None
         );
-        assert_eq!(normalize_endpoint("/health"), "/health");
-        assert_eq!(normalize_endpoint("/metrics"), "/metrics");
+        assert_eq!(trace_id("00-4bf92f35-00f067aa0ba902b7-01"), None);
+        assert_eq!(sampled_trace_id(&HeaderMap::new()), None);
     }
 }
@@ -0,0 +1,601 @@
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use regex::Regex;
+use ring::signature;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Mutex;
+
+use crate::{
+    args::Args,
+    permissions,
+    state::{Permission, User},
+    tokens::{self, TokenError},
+};
+
+/// Basic auth username under which an OIDC token is presented as the password
+pub(crate) const USERNAME: &str = "oidc";
+
+/// Prefix of the usernames given to OIDC identities, which are not in the users file
+pub(crate) const IDENTITY_PREFIX: &str = "oidc:";
+
+/// Keys of an issuer are fetched again for an unknown `kid` at most this often
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{([A-Za-z0-9_.-]+)\}").expect("valid placeholder regex"));
+
+#[derive(Debug, Deserialize)]
+struct TrustFile {
+    issuers: Vec<TrustedIssuer>,
+}
+
+/// An OIDC issuer whose ID tokens are accepted as credentials: a CI platform or a corporate SSO
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct TrustedIssuer {
+    /// `iss` claim, e.g. `https://token.actions.githubusercontent.com`
+    pub issuer: String,
+    /// Required `aud` claim
+    pub audience: String,
+    /// Key set URL; discovered from `<issuer>/.well-known/openid-configuration` when unset
+    #[serde(default)]
+    pub jwks_url: Option<String>,
+    /// Claim naming the identity, e.g. `email` for SSO users
+    #[serde(default = "default_username_claim")]
+    pub username_claim: String,
+    /// Claim listing the identity's groups
+    #[serde(default = "default_groups_claim")]
+    pub groups_claim: String,
+    pub rules: Vec<ClaimRule>,
+}
+
+fn default_username_claim() -> String {
+    "sub".to_string()
+}
+
+fn default_groups_claim() -> String {
+    "groups".to_string()
+}
+
+/// Permissions granted to tokens whose claims match every pattern and, if `groups` is set,
+/// that are a member of one of the groups
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ClaimRule {
+    /// Claim name to pattern (`*` and `?` wildcards)
+    #[serde(default)]
+    pub claims: BTreeMap<String, String>,
+    /// Group patterns, matched against the issuer's `groups_claim`
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// `{claim}` in `repository` and `tag` is replaced by the claim's value
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+    /// Grants the admin API
+    #[serde(default)]
+    pub admin: bool,
+}
+
+impl ClaimRule {
+    fn matches(&self, claims: &Value, groups: &[String]) -> bool {
+        let claims_match = self.claims.iter().all(|(name, pattern)| {
+            claim_string(claims, name).is_some_and(|v| permissions::matches_pattern(pattern, &v))
+        });
+        let groups_match = self.groups.is_empty()
+            || self.groups.iter().any(|pattern| {
+                groups
+                    .iter()
+                    .any(|group| permissions::matches_pattern(pattern, group))
+            });
+        claims_match && groups_match
+    }
+
+    /// The rule's permissions with placeholders filled in; a permission whose placeholder is
+    /// missing, or would inject a wildcard, is dropped
+    fn grant(&self, claims: &Value, expires_at: u64) -> Vec<Permission> {
+        self.permissions
+            .iter()
+            .filter_map(|p| {
+                Some(Permission {
+                    repository: substitute(&p.repository, claims)?,
+                    tag: substitute(&p.tag, claims)?,
+                    actions: p.actions.clone(),
+                    expires_at: Some(p.expires_at.map_or(expires_at, |e| e.min(expires_at))),
+                })
+            })
+            .collect()
+    }
+}
+
+fn claim_string(claims: &Value, name: &str) -> Option<String> {
+    match claims.get(name)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Group names from a claim holding an array of names or a single name
+fn claim_groups(claims: &Value, name: &str) -> Vec<String> {
+    match claims.get(name) {
+        Some(Value::Array(groups)) => groups
+            .iter()
+            .filter_map(|g| g.as_str().map(str::to_string))
+            .collect(),
+        Some(Value::String(group)) => vec![group.clone()],
+        _ => Vec::new(),
+    }
+}
+
+fn substitute(template: &str, claims: &Value) -> Option<String> {
+    let mut missing = false;
+    let value =
+        PLACEHOLDER.replace_all(template, |captures: &regex::Captures| {
+            match claim_string(claims, &captures[1]) {
+                Some(v) if !v.contains(['*', '?']) => v,
+                _ => {
+                    missing = true;
+                    String::new()
+                }
+            }
+        });
+    (!missing).then(|| value.into_owned())
+}
+
+enum VerificationKey {
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    EcP256(Vec<u8>),
+}
+
+impl VerificationKey {
+    fn from_jwk(jwk: &Value) -> Option<Self> {
+        let field = |name: &str| {
+            jwk.get(name)
+                .and_then(|v| v.as_str())
+                .and_then(|v| BASE64_URL_SAFE_NO_PAD.decode(v).ok())
+        };
+        match jwk.get("kty")?.as_str()? {
+            "RSA" => Some(Self::Rsa {
+                n: field("n")?,
+                e: field("e")?,
+            }),
+            "EC" if jwk.get("crv")?.as_str()? == "P-256" => {
+                let mut point = vec![0x04];
+                point.extend(field("x")?);
+                point.extend(field("y")?);
+                Some(Self::EcP256(point))
+            }
+            _ => None,
+        }
+    }
+
+    fn verify(&self, alg: &str, message: &[u8], sig: &[u8]) -> Result<(), &'static str> {
+        let verified = match (alg, self) {
+            ("RS256", Self::Rsa { n, e }) => signature::RsaPublicKeyComponents { n, e }
+                .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, sig)
+                .is_ok(),
+            ("ES256", Self::EcP256(point)) => {
+                signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                    .verify(message, sig)
+                    .is_ok()
+            }
+            _ => return Err("unsupported token algorithm"),
+        };
+        verified.then_some(()).ok_or("invalid token signature")
+    }
+}
+
+#[derive(Default)]
+struct KeySet {
+    keys: HashMap<String, VerificationKey>,
+    fetched_at: Option<Instant>,
+    /// Held while the keys are fetched, so requests needing them wait for one fetch
+    fetching: Arc<Mutex<()>>,
+}
+
+impl KeySet {
+    /// Whether `kid` is unknown and the keys were not fetched recently
+    fn needs_fetch(&self, kid: &str) -> bool {
+        !self.keys.contains_key(kid)
+            && self
+                .fetched_at
+                .is_none_or(|at| at.elapsed() >= JWKS_REFRESH_INTERVAL)
+    }
+}
+
+/// OIDC identities: ID tokens from trusted issuers (GitHub Actions, GitLab CI, corporate SSO)
+/// mapped to permissions by their claims and groups, configured with `--oidc-trust-file`
+pub(crate) struct CiIdentities {
+    issuers: Vec<TrustedIssuer>,
+    key_sets: Mutex<HashMap<String, KeySet>>,
+    http: reqwest::Client,
+    /// Allowed clock difference with the issuers when checking `exp` and `nbf`
+    clock_skew: u64,
+}
+
+impl CiIdentities {
+    pub(crate) fn from_args(args: &Args) -> Result<Self, String> {
+        let issuers = match &args.oidc_trust_file {
+            Some(path) => {
+                let content = std::fs::read_to_string(path)
+                    .map_err(|e| format!("failed to read OIDC trust file {}: {}", path, e))?;
+                serde_json::from_str::<TrustFile>(&content)
+                    .map_err(|e| format!("failed to parse OIDC trust file {}: {}", path, e))?
+                    .issuers
+            }
+            None => Vec::new(),
+        };
+        if !issuers.is_empty() {
+            log::info!("Trusting OIDC tokens from {} issuers", issuers.len());
+        }
+
+        Ok(Self {
+            issuers,
+            key_sets: Mutex::new(HashMap::new()),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .map_err(|e| format!("failed to build OIDC HTTP client: {}", e))?,
+            clock_skew: args.token_clock_skew_secs,
+        })
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.issuers.is_empty()
+    }
+
+    /// Whether a bearer token claims to come from a trusted issuer, as opposed to `/token`.
+    /// Only routes the token; `authenticate` verifies it.
+    pub(crate) fn trusts_issuer_of(&self, token: &str) -> bool {
+        split_token(token).is_some_and(|(_, claims, _)| {
+            let iss = claim_string(&claims, "iss").unwrap_or_default();
+            self.issuers.iter().any(|i| i.issuer == iss)
+        })
+    }
+
+    /// Verify an ID token and return the identity it maps to, or why it is refused
+    pub(crate) async fn authenticate(&self, token: &str) -> Result<User, TokenError> {
+        let (header, claims, sig) = split_token(token).ok_or(TokenError::Malformed)?;
+
+        // The issuer picks the keys; it is only trusted once the signature checks out
+        let iss = claim_string(&claims, "iss").unwrap_or_default();
+        let issuer = self
+            .issuers
+            .iter()
+            .find(|i| i.issuer == iss)
+            .ok_or_else(|| TokenError::InvalidClaims(format!("untrusted issuer {}", iss)))?;
+        let alg = header.get("alg").and_then(|a| a.as_str()).unwrap_or("");
+        let kid = header.get("kid").and_then(|k| k.as_str()).unwrap_or("");
+        let message = &token[..header_payload_len(token)];
+        self.verify_signature(issuer, kid, alg, message.as_bytes(), &sig)
+            .await?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        check_claims(issuer, &claims, now, self.clock_skew)?;
+
+        let exp = claims.get("exp").and_then(|e| e.as_u64()).unwrap_or(0);
+        let groups = claim_groups(&claims, &issuer.groups_claim);
+        let rules: Vec<&ClaimRule> = issuer
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(&claims, &groups))
+            .collect();
+        let permissions: Vec<Permission> = rules
+            .iter()
+            .flat_map(|rule| rule.grant(&claims, exp))
+            .collect();
+        let admin = rules.iter().any(|rule| rule.admin);
+        if permissions.is_empty() && !admin {
+            return Err(TokenError::InvalidClaims(
+                "no rule matches the token's claims".to_string(),
+            ));
+        }
+
+        Ok(User {
+            username: format!(
+                "{}{}",
+                IDENTITY_PREFIX,
+                claim_string(&claims, &issuer.username_claim).unwrap_or_default()
+            ),
+            password: String::new(),
+            permissions,
+            admin,
+            admin_orgs: vec![],
+            roles: vec![],
+            groups: vec![],
+        })
+    }
+
+    async fn verify_signature(
+        &self,
+        issuer: &TrustedIssuer,
+        kid: &str,
+        alg: &str,
+        message: &[u8],
+        sig: &[u8],
+    ) -> Result<(), TokenError> {
+        // Issuers rotate keys; an unknown kid triggers a (rate-limited) refetch. The key sets are
+        // not locked during the fetch, so other issuers' tokens are verified meanwhile.
+        let fetching = {
+            let mut key_sets = self.key_sets.lock().await;
+            let key_set = key_sets.entry(issuer.issuer.clone()).or_default();
+            key_set.needs_fetch(kid).then(|| key_set.fetching.clone())
+        };
+        if let Some(fetching) = fetching {
+            let _fetching = fetching.lock().await;
+            // Another request may have fetched the keys while this one waited
+            let needs_fetch = self
+                .key_sets
+                .lock()
+                .await
+                .get(&issuer.issuer)
+                .is_some_and(|key_set| key_set.needs_fetch(kid));
+            if needs_fetch {
+                let fetched = self.fetch_keys(issuer).await;
+                let mut key_sets = self.key_sets.lock().await;
+                let key_set = key_sets.entry(issuer.issuer.clone()).or_default();
+                match fetched {
+                    Ok(keys) => key_set.keys = keys,
+                    Err(e) => log::warn!("oidc: failed to fetch keys of {}: {}", issuer.issuer, e),
+                }
+                key_set.fetched_at = Some(Instant::now());
+            }
+        }
+
+        let key_sets = self.key_sets.lock().await;
+        let key = key_sets
+            .get(&issuer.issuer)
+            .and_then(|key_set| key_set.keys.get(kid))
+            .ok_or_else(|| TokenError::BadSignature(format!("unknown signing key {}", kid)))?;
+        key.verify(alg, message, sig)
+            .map_err(|e| TokenError::BadSignature(e.to_string()))
+    }
+
+    async fn fetch_keys(
+        &self,
+        issuer: &TrustedIssuer,
+    ) -> Result<HashMap<String, VerificationKey>, reqwest::Error> {
+        let jwks_url = match &issuer.jwks_url {
+            Some(url) => url.clone(),
+            None => {
+                let discovery: Value = self
+                    .http
+                    .get(format!(
+                        "{}/.well-known/openid-configuration",
+                        issuer.issuer.trim_end_matches('/')
+                    ))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                discovery
+                    .get("jwks_uri")
+                    .and_then(|u| u.as_str())
+                    .unwrap_or_default()
+                    .to_string()
+            }
+        };
+
+        let jwks: Value = self
+            .http
+            .get(&jwks_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let keys = jwks
+            .get("keys")
+            .and_then(|k| k.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|jwk| {
+                let kid = jwk.get("kid")?.as_str()?.to_string();
+                Some((kid, VerificationKey::from_jwk(jwk)?))
+            })
+            .collect::<HashMap<_, _>>();
+        log::info!("oidc: loaded {} keys of {}", keys.len(), issuer.issuer);
+        Ok(keys)
+    }
+}
+
+/// Decoded header, claims and signature of a JWT
+fn split_token(token: &str) -> Option<(Value, Value, Vec<u8>)> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(payload), Some(sig), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    let decode = |part: &str| -> Option<Value> {
+        serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(part).ok()?).ok()
+    };
+    Some((
+        decode(header)?,
+        decode(payload)?,
+        BASE64_URL_SAFE_NO_PAD.decode(sig).ok()?,
+    ))
+}
+
+/// Length of the signed `<header>.<payload>` part of a JWT
+fn header_payload_len(token: &str) -> usize {
+    token.rfind('.').unwrap_or(token.len())
+}
+
+fn check_claims(
+    issuer: &TrustedIssuer,
+    claims: &Value,
+    now: u64,
+    skew: u64,
+) -> Result<(), TokenError> {
+    let audience_matches = match claims.get("aud") {
+        Some(Value::String(aud)) => *aud == issuer.audience,
+        Some(Value::Array(auds)) => auds.iter().any(|a| a.as_str() == Some(&issuer.audience)),
+        _ => false,
+    };
+    if !audience_matches {
+        return Err(TokenError::InvalidClaims(format!(
+            "token audience is not {}",
+            issuer.audience
+        )));
+    }
+
+    let exp = claims
+        .get("exp")
+        .and_then(|e| e.as_u64())
+        .ok_or_else(|| TokenError::InvalidClaims("token has no expiry".to_string()))?;
+    let nbf = claims.get("nbf").and_then(|n| n.as_u64());
+    tokens::check_validity(exp, nbf, now, skew)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn github_claims() -> Value {
+        serde_json::json!({
+            "iss": "https://token.actions.githubusercontent.com",
+            "aud": "grain",
+            "sub": "repo:myorg/app:ref:refs/heads/main",
+            "repository": "myorg/app",
+            "repository_owner": "myorg",
+            "ref": "refs/heads/main",
+            "exp": 2_000,
+        })
+    }
+
+    fn issuer(rules: Vec<ClaimRule>) -> TrustedIssuer {
+        TrustedIssuer {
+            issuer: "https://token.actions.githubusercontent.com".to_string(),
+            audience: "grain".to_string(),
+            jwks_url: None,
+            username_claim: default_username_claim(),
+            groups_claim: default_groups_claim(),
+            rules,
+        }
+    }
+
+    #[test]
+    fn test_rule_grants() {
+        let rule: ClaimRule = serde_json::from_value(serde_json::json!({
+            "claims": { "repository_owner": "myorg", "ref": "refs/heads/*" },
+            "permissions": [
+                { "repository": "{repository}", "tag": "*", "actions": ["pull", "push"] },
+                { "repository": "cache/{missing}", "tag": "*", "actions": ["push"] }
+            ]
+        }))
+        .unwrap();
+
+        let claims = github_claims();
+        assert!(rule.matches(&claims, &[]));
+        assert_eq!(
+            rule.grant(&claims, 2_000),
+            vec![Permission {
+                repository: "myorg/app".to_string(),
+                tag: "*".to_string(),
+                actions: vec!["pull".to_string(), "push".to_string()],
+                expires_at: Some(2_000),
+            }]
+        );
+
+        let mut other = claims.clone();
+        other["ref"] = "refs/tags/v1".into();
+        assert!(!rule.matches(&other, &[]));
+
+        // Claim values cannot widen a pattern
+        let mut wildcard = claims;
+        wildcard["repository"] = "myorg/*".into();
+        assert!(rule.grant(&wildcard, 2_000).is_empty());
+    }
+
+    #[test]
+    fn test_rule_groups() {
+        let rule: ClaimRule = serde_json::from_value(serde_json::json!({
+            "groups": ["platform-admins", "team-*"],
+            "permissions": [{ "repository": "*", "tag": "*", "actions": ["pull"] }]
+        }))
+        .unwrap();
+        let claims = serde_json::json!({ "groups": ["staff", "team-web"], "role": "dev" });
+        let groups = claim_groups(&claims, "groups");
+        assert!(rule.matches(&claims, &groups));
+        assert!(!rule.matches(&claims, &claim_groups(&claims, "role")));
+        assert!(!rule.matches(&claims, &[]));
+        assert_eq!(claim_groups(&claims, "role"), vec!["dev"]);
+    }
+
+    #[test]
+    fn test_check_claims() {
+        let issuer = issuer(Vec::new());
+        let claims = github_claims();
+        assert!(check_claims(&issuer, &claims, 1_000, 60).is_ok());
+        assert!(check_claims(&issuer, &claims, 2_059, 60).is_ok());
+        assert_eq!(
+            check_claims(&issuer, &claims, 2_060, 60),
+            Err(TokenError::Expired {
+                exp: 2_000,
+                now: 2_060,
+                skew: 60
+            })
+        );
+
+        let mut early = claims.clone();
+        early["nbf"] = serde_json::json!(1_100);
+        assert!(check_claims(&issuer, &early, 1_040, 60).is_ok());
+        assert_eq!(
+            check_claims(&issuer, &early, 1_000, 60).map_err(|e| e.class()),
+            Err("not_yet_valid")
+        );
+
+        let mut audiences = claims.clone();
+        audiences["aud"] = serde_json::json!(["sts.amazonaws.com", "grain"]);
+        assert!(check_claims(&issuer, &audiences, 1_000, 60).is_ok());
+        audiences["aud"] = serde_json::json!("sts.amazonaws.com");
+        assert_eq!(
+            check_claims(&issuer, &audiences, 1_000, 60).map_err(|e| e.class()),
+            Err("invalid_claims")
+        );
+    }
+
+    #[test]
+    fn test_verify_es256() {
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = signature::EcdsaKeyPair::generate_pkcs8(
+            &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+            &rng,
+        )
+        .unwrap();
+        let key_pair = signature::EcdsaKeyPair::from_pkcs8(
+            &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+            pkcs8.as_ref(),
+            &rng,
+        )
+        .unwrap();
+        let point = signature::KeyPair::public_key(&key_pair).as_ref();
+        let jwk = serde_json::json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": BASE64_URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": BASE64_URL_SAFE_NO_PAD.encode(&point[33..]),
+        });
+        let key = VerificationKey::from_jwk(&jwk).unwrap();
+
+        let sig = key_pair.sign(&rng, b"header.payload").unwrap();
+        assert!(key.verify("ES256", b"header.payload", sig.as_ref()).is_ok());
+        assert_eq!(
+            key.verify("ES256", b"header.tampered", sig.as_ref()),
+            Err("invalid token signature")
+        );
+        assert_eq!(
+            key.verify("RS256", b"header.payload", sig.as_ref()),
+            Err("unsupported token algorithm")
+        );
+    }
+}
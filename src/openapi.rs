@@ -1,6 +1,9 @@
 use utoipa::OpenApi;
 
-use crate::{admin, state};
+use crate::{
+    admin, archive, cluster, downloads, dry_run, gc, replication, repos, repositories, robots,
+    roles, signatures, standby, state, storage,
+};
 
 #[derive(OpenApi)]
 #[openapi(
@@ -8,14 +11,103 @@ use crate::{admin, state};
         admin::list_users,
         admin::create_user,
         admin::delete_user,
-        admin::add_permission
+        admin::update_user,
+        admin::change_password,
+        admin::change_own_password,
+        admin::add_permission,
+        admin::remove_permission,
+        admin::run_garbage_collection,
+        admin::gc_status,
+        admin::gc_status_events,
+        admin::get_gc_policy,
+        admin::set_gc_policy,
+        cluster::cluster_info,
+        robots::list_robots,
+        robots::create_robot,
+        robots::revoke_robot,
+        roles::list_roles,
+        roles::set_role,
+        roles::delete_role,
+        roles::set_group,
+        roles::delete_group,
+        repos::resolve,
+        repos::pull_secret,
+        repos::blob_info,
+        repos::image_config,
+        repos::list_repositories,
+        repos::create_repository,
+        repos::list_pins,
+        repos::pin_tag,
+        repos::unpin_tag,
+        repos::set_tag_expiry,
+        repos::clear_tag_expiry,
+        repos::repository_stats,
+        signatures::list_signatures,
+        archive::list_archives,
+        archive::archive_repository,
+        archive::restore_repository,
+        downloads::create_download_url,
+        standby::sync_changes,
+        standby::sync_snapshot,
+        standby::sync_users,
+        replication::replication_status
     ),
     components(
         schemas(
             admin::CreateUserRequest,
+            admin::UpdateUserRequest,
+            admin::ChangePasswordRequest,
+            dry_run::PlannedChange,
+            dry_run::DryRunResult,
+            admin::ChangeOwnPasswordRequest,
             admin::AddPermissionRequest,
+            admin::RemovePermissionRequest,
             state::User,
-            state::Permission
+            state::Permission,
+            state::UsersFile,
+            gc::GcPolicy,
+            gc::GcStats,
+            gc::progress::GcStatus,
+            gc::progress::GcPhase,
+            gc::UntaggedManifests,
+            cluster::ClusterInfo,
+            cluster::Role,
+            cluster::Peer,
+            cluster::StorageInfo,
+            robots::Robot,
+            robots::CreateRobotRequest,
+            robots::CreatedRobot,
+            robots::RobotList,
+            roles::AccessRole,
+            roles::AccessGroup,
+            roles::Roles,
+            repos::Resolution,
+            repos::PlatformSummary,
+            repos::BlobInfo,
+            repos::ImageConfig,
+            repos::HistorySummary,
+            repos::CreateRepositoryRequest,
+            repos::DefaultPermission,
+            repos::RepositoryList,
+            repos::PinList,
+            repos::SetTagExpiryRequest,
+            repos::TagExpiry,
+            repos::RepositoryStats,
+            repositories::Repository,
+            repositories::RetentionPolicy,
+            repositories::Visibility,
+            downloads::CreateDownloadUrlRequest,
+            downloads::DownloadUrl,
+            downloads::DownloadKind,
+            storage::MountRecord,
+            signatures::SignatureList,
+            signatures::Signature,
+            signatures::Certificate,
+            archive::Archive,
+            archive::ArchiveList,
+            replication::ReplicationStatus,
+            replication::PeerStatus,
+            replication::PendingPush
         )
     ),
     tags(
@@ -23,8 +115,8 @@ use crate::{admin, state};
     ),
     info(
         title = "Grain Registry - Admin API",
-        version = "0.1.0",
-        description = "Administration API for the Grain registry. Provides endpoints for managing users and their granular tag-level permissions.",
+        version = "1.0.0",
+        description = "Administration API for the Grain registry. Provides endpoints for managing users and their granular tag-level permissions. Endpoints are served under `/admin/v1`; the unversioned `/admin` paths are kept as aliases.",
         contact(
             name = "Grain Registry",
             url = "https://github.com/pierrelefevre/grain"
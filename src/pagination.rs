@@ -0,0 +1,143 @@
+//! Cursor pagination of list endpoints with `?n=<limit>&last=<cursor>`, as in the OCI catalog.
+//! Entries are ordered by a stable key (repository, tag or user name) and `last` is the key of
+//! the last entry already seen, so pages stay consistent while entries are added or removed.
+
+use axum::{body::Body, http::StatusCode, response::Response};
+use serde::Deserialize;
+
+/// Most entries returned in one page of an admin list or the catalog
+pub(crate) const MAX_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct PageQuery {
+    /// Most entries to return, capped at `MAX_PAGE_SIZE`
+    pub n: Option<usize>,
+    /// Key of the last entry of the previous page
+    pub last: Option<String>,
+}
+
+impl PageQuery {
+    /// Page through `items` by `key` with this query's `n` and `last`
+    pub(crate) fn page<T>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        key: impl Fn(&T) -> &str,
+    ) -> Page<T> {
+        page(items, key, self.n, self.last.as_deref(), MAX_PAGE_SIZE)
+    }
+}
+
+/// One page of entries, with the cursor of the next page when there are more
+#[derive(Debug)]
+pub(crate) struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<String>,
+    /// Page size, to carry over to the link of the next page
+    pub limit: usize,
+}
+
+/// Page through `items` in `key` order, returning at most `n` (or `max`) entries after `last`
+pub(crate) fn page<T>(
+    items: impl IntoIterator<Item = T>,
+    key: impl Fn(&T) -> &str,
+    n: Option<usize>,
+    last: Option<&str>,
+    max: usize,
+) -> Page<T> {
+    let limit = n.unwrap_or(max).min(max);
+    let mut items: Vec<T> = items
+        .into_iter()
+        .filter(|item| last.is_none_or(|last| key(item) > last))
+        .collect();
+    items.sort_by(|a, b| key(a).cmp(key(b)));
+
+    let next = (items.len() > limit && limit > 0).then(|| key(&items[limit - 1]).to_string());
+    items.truncate(limit);
+    Page { items, next, limit }
+}
+
+/// `Link` header pointing at the page after `page`, relative to the registry root. `path` may
+/// carry query parameters of its own, which are kept.
+pub(crate) fn next_link<T>(path: &str, page: &Page<T>) -> Option<String> {
+    let separator = if path.contains('?') { '&' } else { '?' };
+    page.next.as_ref().map(|next| {
+        format!(
+            "<{}{}n={}&last={}>; rel=\"next\"",
+            path,
+            separator,
+            page.limit,
+            encode(next)
+        )
+    })
+}
+
+/// JSON response for a page, with the `Link` header of the next page if any
+pub(crate) fn response(body: String, link: Option<String>) -> Response {
+    let mut builder = Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json");
+    if let Some(link) = link {
+        builder = builder.header("Link", link);
+    }
+    builder.body(Body::from(body)).unwrap()
+}
+
+/// Percent-encode a cursor for a query string
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(count: usize) -> Vec<String> {
+        // Deliberately unsorted
+        (0..count)
+            .rev()
+            .map(|i| format!("org/repo-{:02}", i))
+            .collect()
+    }
+
+    #[test]
+    fn test_page() {
+        let first = page(names(5), |s| s, Some(2), None, 100);
+        assert_eq!(first.items, ["org/repo-00", "org/repo-01"]);
+        assert_eq!(first.next.as_deref(), Some("org/repo-01"));
+
+        let last = page(names(5), |s| s, Some(2), Some("org/repo-03"), 100);
+        assert_eq!(last.items, ["org/repo-04"]);
+        assert_eq!(last.next, None);
+
+        // Without n, pages are capped at the maximum
+        let capped = page(names(5), |s| s, None, None, 3);
+        assert_eq!(capped.items.len(), 3);
+        assert_eq!(capped.limit, 3);
+        assert_eq!(page(names(5), |s| s, Some(10), None, 3).items.len(), 3);
+
+        let empty = page(names(5), |s| s, Some(0), None, 100);
+        assert!(empty.items.is_empty());
+        assert_eq!(empty.next, None);
+    }
+
+    #[test]
+    fn test_next_link() {
+        let page = page(names(3), |s| s, Some(1), None, 100);
+        assert_eq!(
+            next_link("/v2/_catalog", &page).as_deref(),
+            Some("</v2/_catalog?n=1&last=org%2Frepo-00>; rel=\"next\"")
+        );
+        assert_eq!(
+            next_link("/v2/org/repo/tags/list?detail=true", &page).as_deref(),
+            Some("</v2/org/repo/tags/list?detail=true&n=1&last=org%2Frepo-00>; rel=\"next\"")
+        );
+    }
+}
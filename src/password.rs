@@ -0,0 +1,120 @@
+use crate::args::Args;
+
+/// A single password requirement
+#[derive(Debug, Clone, PartialEq)]
+pub enum PasswordRule {
+    MinLength(usize),
+    Complexity,
+    DisallowUsername,
+}
+
+impl PasswordRule {
+    fn check(&self, username: &str, password: &str) -> Result<(), String> {
+        match self {
+            PasswordRule::MinLength(min) => {
+                if password.chars().count() < *min {
+                    return Err(format!("password must be at least {} characters", min));
+                }
+            }
+            PasswordRule::Complexity => {
+                let has_lower = password.chars().any(|c| c.is_lowercase());
+                let has_upper = password.chars().any(|c| c.is_uppercase());
+                let has_digit = password.chars().any(|c| c.is_ascii_digit());
+                if !(has_lower && has_upper && has_digit) {
+                    return Err(
+                        "password must contain lowercase, uppercase and numeric characters"
+                            .to_string(),
+                    );
+                }
+            }
+            PasswordRule::DisallowUsername => {
+                if !username.is_empty()
+                    && password.to_lowercase().contains(&username.to_lowercase())
+                {
+                    return Err("password must not contain the username".to_string());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Set of rules applied to passwords set through the admin API
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    rules: Vec<PasswordRule>,
+}
+
+impl PasswordPolicy {
+    pub fn new(rules: Vec<PasswordRule>) -> Self {
+        Self { rules }
+    }
+
+    pub fn from_args(args: &Args) -> Self {
+        let mut rules = vec![PasswordRule::MinLength(args.password_min_length.max(1))];
+
+        if args.password_require_complexity {
+            rules.push(PasswordRule::Complexity);
+        }
+
+        if args.password_disallow_username {
+            rules.push(PasswordRule::DisallowUsername);
+        }
+
+        Self::new(rules)
+    }
+
+    /// Check a password against every rule, returning all violations
+    pub fn validate(&self, username: &str, password: &str) -> Result<(), Vec<String>> {
+        let violations: Vec<String> = self
+            .rules
+            .iter()
+            .filter_map(|rule| rule.check(username, password).err())
+            .collect();
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_min_length() {
+        let policy = PasswordPolicy::new(vec![PasswordRule::MinLength(8)]);
+        assert!(policy.validate("alice", "short").is_err());
+        assert!(policy.validate("alice", "long enough").is_ok());
+    }
+
+    #[test]
+    fn test_complexity() {
+        let policy = PasswordPolicy::new(vec![PasswordRule::Complexity]);
+        assert!(policy.validate("alice", "alllowercase1").is_err());
+        assert!(policy.validate("alice", "NoDigitsHere").is_err());
+        assert!(policy.validate("alice", "Mixed1Case").is_ok());
+    }
+
+    #[test]
+    fn test_disallow_username() {
+        let policy = PasswordPolicy::new(vec![PasswordRule::DisallowUsername]);
+        assert!(policy.validate("alice", "xxAliCe123").is_err());
+        assert!(policy.validate("alice", "s3cret-pass").is_ok());
+    }
+
+    #[test]
+    fn test_reports_all_violations() {
+        let policy = PasswordPolicy::new(vec![
+            PasswordRule::MinLength(12),
+            PasswordRule::Complexity,
+            PasswordRule::DisallowUsername,
+        ]);
+        let violations = policy.validate("bob", "bob").unwrap_err();
+        assert_eq!(violations.len(), 3);
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use crate::state::User;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -5,6 +7,8 @@ pub enum Action {
     Pull,
     Push,
     Delete,
+    /// Enumerate a repository's tags and see it in the catalog
+    List,
 }
 
 impl Action {
@@ -13,6 +17,7 @@ impl Action {
             Action::Pull => "pull",
             Action::Push => "push",
             Action::Delete => "delete",
+            Action::List => "list",
         }
     }
 }
@@ -25,8 +30,17 @@ pub fn has_permission(user: &User, repository: &str, tag: Option<&str>, action:
     }
 
     let action_str = action.as_str();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
 
     for perm in &user.permissions {
+        // Lapsed permissions are ignored until the cleanup job removes them
+        if perm.is_expired(now) {
+            continue;
+        }
+
         // Check if repository matches
         if !matches_pattern(&perm.repository, repository) {
             continue;
@@ -48,8 +62,15 @@ pub fn has_permission(user: &User, repository: &str, tag: Option<&str>, action:
     false
 }
 
+/// Whether a user may enumerate a repository's tags. `pull` implies `list` unless
+/// `--strict-list` is set, so pull-only accounts can be kept from discovering tags.
+pub fn can_list(user: &User, repository: &str, strict_list: bool) -> bool {
+    has_permission(user, repository, None, Action::List)
+        || (!strict_list && has_permission(user, repository, None, Action::Pull))
+}
+
 /// Match a pattern with wildcards (* and ?)
-fn matches_pattern(pattern: &str, value: &str) -> bool {
+pub fn matches_pattern(pattern: &str, value: &str) -> bool {
     if pattern == "*" {
         return true;
     }
@@ -112,13 +133,19 @@ mod tests {
                     repository: "myorg/myrepo".to_string(),
                     tag: "latest".to_string(),
                     actions: vec!["pull".to_string()],
+                    expires_at: None,
                 },
                 Permission {
                     repository: "myorg/myrepo".to_string(),
                     tag: "dev".to_string(),
                     actions: vec!["pull".to_string(), "push".to_string()],
+                    expires_at: None,
                 },
             ],
+            admin: false,
+            admin_orgs: vec![],
+            roles: vec![],
+            groups: vec![],
         };
 
         assert!(has_permission(
@@ -156,7 +183,12 @@ mod tests {
                 repository: "*".to_string(),
                 tag: "*".to_string(),
                 actions: vec!["pull".to_string(), "push".to_string(), "delete".to_string()],
+                expires_at: None,
             }],
+            admin: false,
+            admin_orgs: vec![],
+            roles: vec![],
+            groups: vec![],
         };
 
         assert!(has_permission(
@@ -185,6 +217,10 @@ mod tests {
             username: "noperms".to_string(),
             password: "pass".to_string(),
             permissions: vec![],
+            admin: false,
+            admin_orgs: vec![],
+            roles: vec![],
+            groups: vec![],
         };
 
         assert!(!has_permission(
@@ -210,7 +246,12 @@ mod tests {
                 repository: "myorg/*".to_string(),
                 tag: "*".to_string(),
                 actions: vec!["pull".to_string()],
+                expires_at: None,
             }],
+            admin: false,
+            admin_orgs: vec![],
+            roles: vec![],
+            groups: vec![],
         };
 
         assert!(has_permission(
@@ -242,7 +283,12 @@ mod tests {
                 repository: "myorg/myrepo".to_string(),
                 tag: "v*".to_string(),
                 actions: vec!["pull".to_string()],
+                expires_at: None,
             }],
+            admin: false,
+            admin_orgs: vec![],
+            roles: vec![],
+            groups: vec![],
         };
 
         assert!(has_permission(
@@ -264,4 +310,58 @@ mod tests {
             Action::Pull
         ));
     }
+
+    #[test]
+    fn test_expired_permission_ignored() {
+        let mut user = User {
+            username: "ci".to_string(),
+            password: "pass".to_string(),
+            permissions: vec![Permission {
+                repository: "myorg/*".to_string(),
+                tag: "*".to_string(),
+                actions: vec!["push".to_string()],
+                expires_at: Some(1),
+            }],
+            admin: false,
+            admin_orgs: vec![],
+            roles: vec![],
+            groups: vec![],
+        };
+
+        assert!(!has_permission(
+            &user,
+            "myorg/app",
+            Some("v1"),
+            Action::Push
+        ));
+
+        user.permissions[0].expires_at = Some(u64::MAX);
+        assert!(has_permission(&user, "myorg/app", Some("v1"), Action::Push));
+    }
+
+    #[test]
+    fn test_can_list() {
+        let mut user = User {
+            username: "partner".to_string(),
+            password: "pass".to_string(),
+            permissions: vec![Permission {
+                repository: "myorg/*".to_string(),
+                tag: "*".to_string(),
+                actions: vec!["pull".to_string()],
+                expires_at: None,
+            }],
+            admin: false,
+            admin_orgs: vec![],
+            roles: vec![],
+            groups: vec![],
+        };
+
+        assert!(can_list(&user, "myorg/app", false));
+        assert!(!can_list(&user, "myorg/app", true));
+        assert!(!can_list(&user, "other/app", false));
+
+        user.permissions[0].actions = vec!["list".to_string()];
+        assert!(can_list(&user, "myorg/app", true));
+        assert!(!has_permission(&user, "myorg/app", None, Action::Pull));
+    }
 }
@@ -0,0 +1,218 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::{args::Args, digest::Digest, permissions, repos, storage};
+
+/// Label requirements for image manifests pushed to repositories matching `repository`.
+/// Rules are `key` (label must be present / absent) or `key=value` (label must have / not have
+/// that value).
+#[derive(Debug, Clone, Deserialize)]
+pub struct LabelPolicy {
+    pub repository: String,
+    #[serde(default)]
+    pub require: Vec<String>,
+    #[serde(default)]
+    pub forbid: Vec<String>,
+}
+
+/// `--push-policy-file` contents
+#[derive(Debug, Clone, Deserialize)]
+pub struct PushPolicy {
+    #[serde(default)]
+    pub labels: Vec<LabelPolicy>,
+}
+
+fn split_rule(rule: &str) -> (&str, Option<&str>) {
+    match rule.split_once('=') {
+        Some((key, value)) => (key, Some(value)),
+        None => (rule, None),
+    }
+}
+
+impl PushPolicy {
+    pub fn new(labels: Vec<LabelPolicy>) -> Self {
+        Self { labels }
+    }
+
+    pub fn from_args(args: &Args) -> Result<Self, String> {
+        let mut policy = match &args.push_policy_file {
+            Some(path) => {
+                let content = std::fs::read_to_string(path)
+                    .map_err(|e| format!("failed to read push policy file {}: {}", path, e))?;
+                serde_json::from_str(&content)
+                    .map_err(|e| format!("failed to parse push policy file {}: {}", path, e))?
+            }
+            None => Self::new(Vec::new()),
+        };
+
+        if !args.require_labels.is_empty() || !args.forbid_labels.is_empty() {
+            policy.labels.push(LabelPolicy {
+                repository: "*".to_string(),
+                require: args.require_labels.clone(),
+                forbid: args.forbid_labels.clone(),
+            });
+        }
+
+        Ok(policy)
+    }
+
+    pub fn applies_to(&self, repository: &str) -> bool {
+        self.labels
+            .iter()
+            .any(|p| permissions::matches_pattern(&p.repository, repository))
+    }
+
+    /// Check labels against every policy matching the repository, returning all violations
+    pub fn check_labels(
+        &self,
+        repository: &str,
+        labels: &BTreeMap<String, String>,
+    ) -> Result<(), Vec<String>> {
+        let mut violations = Vec::new();
+
+        for policy in &self.labels {
+            if !permissions::matches_pattern(&policy.repository, repository) {
+                continue;
+            }
+
+            for rule in &policy.require {
+                let (key, expected) = split_rule(rule);
+                match (labels.get(key), expected) {
+                    (None, _) => violations.push(format!("missing required label {}", key)),
+                    (Some(actual), Some(expected)) if actual != expected => violations.push(
+                        format!("label {} must be {:?}, got {:?}", key, expected, actual),
+                    ),
+                    _ => {}
+                }
+            }
+
+            for rule in &policy.forbid {
+                let (key, value) = split_rule(rule);
+                match (labels.get(key), value) {
+                    (Some(_), None) => violations.push(format!("forbidden label {} is set", key)),
+                    (Some(actual), Some(value)) if actual == value => {
+                        violations.push(format!("forbidden label {}={} is set", key, value))
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Check an image manifest being pushed: labels come from its config blob, merged with the
+    /// manifest's annotations. Image indexes carry no config and are checked per child manifest.
+    pub(crate) fn check_manifest(
+        &self,
+        org: &str,
+        repo: &str,
+        manifest: &[u8],
+    ) -> Result<(), Vec<String>> {
+        let repository = format!("{}/{}", org, repo);
+        if !self.applies_to(&repository) {
+            return Ok(());
+        }
+
+        let Ok(manifest) = serde_json::from_slice::<serde_json::Value>(manifest) else {
+            return Ok(());
+        };
+        let Some(config) = manifest.get("config") else {
+            return Ok(());
+        };
+
+        let mut labels = config
+            .get("digest")
+            .and_then(|d| d.as_str())
+            .and_then(|d| Digest::parse(d).ok())
+            .and_then(|digest| {
+                let data = storage::read_blob(org, repo, &digest).ok()?;
+                repos::parse_image_config(&repository, &digest, &data).ok()
+            })
+            .map(|config| config.labels)
+            .unwrap_or_default();
+
+        if let Some(annotations) = manifest.get("annotations").and_then(|a| a.as_object()) {
+            for (key, value) in annotations {
+                if let Some(value) = value.as_str() {
+                    labels.insert(key.clone(), value.to_string());
+                }
+            }
+        }
+
+        self.check_labels(&repository, &labels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_require_labels() {
+        let policy = PushPolicy::new(vec![LabelPolicy {
+            repository: "team-a/*".to_string(),
+            require: vec![
+                "org.opencontainers.image.source".to_string(),
+                "tier=prod".to_string(),
+            ],
+            forbid: vec![],
+        }]);
+
+        let violations = policy.check_labels("team-a/app", &labels(&[])).unwrap_err();
+        assert_eq!(violations.len(), 2);
+
+        let violations = policy
+            .check_labels(
+                "team-a/app",
+                &labels(&[("org.opencontainers.image.source", "x"), ("tier", "dev")]),
+            )
+            .unwrap_err();
+        assert_eq!(violations, vec![r#"label tier must be "prod", got "dev""#]);
+
+        assert!(policy
+            .check_labels(
+                "team-a/app",
+                &labels(&[("org.opencontainers.image.source", "x"), ("tier", "prod")]),
+            )
+            .is_ok());
+
+        // Other repositories are not covered
+        assert!(!policy.applies_to("team-b/app"));
+        assert!(policy.check_labels("team-b/app", &labels(&[])).is_ok());
+    }
+
+    #[test]
+    fn test_forbid_labels() {
+        let policy = PushPolicy::new(vec![LabelPolicy {
+            repository: "*".to_string(),
+            require: vec![],
+            forbid: vec!["debug".to_string(), "tier=dev".to_string()],
+        }]);
+
+        assert!(policy
+            .check_labels("a/b", &labels(&[("tier", "prod")]))
+            .is_ok());
+        let violations = policy
+            .check_labels("a/b", &labels(&[("debug", ""), ("tier", "dev")]))
+            .unwrap_err();
+        assert_eq!(
+            violations,
+            vec![
+                "forbidden label debug is set",
+                "forbidden label tier=dev is set"
+            ]
+        );
+    }
+}
@@ -0,0 +1,365 @@
+//! Token bucket rate limits for pulls, pushes and failed logins, so one misbehaving client
+//! (a CI farm retrying in a loop) cannot starve the others. Pulls and pushes are counted per
+//! set of credentials (a username and a fingerprint of its password, or the subject of a bearer
+//! token), or else per client IP; failed logins are counted per client IP.
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{BuildHasher, RandomState},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{access_log, args::Args, auth, metrics, response, state, tokens};
+
+/// Buckets kept before the least recently used are dropped
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Class {
+    Pull,
+    Push,
+    AuthFailure,
+}
+
+impl Class {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Class::Pull => "pull",
+            Class::Push => "push",
+            Class::AuthFailure => "auth_failure",
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Position in `Buckets::order`
+    last_use: u64,
+}
+
+#[derive(Default)]
+struct Buckets {
+    entries: HashMap<(Class, String), Bucket>,
+    /// Keys by last use, least recently used first
+    order: BTreeMap<u64, (Class, String)>,
+    clock: u64,
+}
+
+pub(crate) struct RateLimiter {
+    pulls_per_minute: u32,
+    pushes_per_minute: u32,
+    auth_failures_per_minute: u32,
+    buckets: Mutex<Buckets>,
+    /// Keys credential fingerprints, so they reveal nothing outside this process
+    fingerprints: RandomState,
+}
+
+impl RateLimiter {
+    pub(crate) fn from_args(args: &Args) -> Self {
+        Self {
+            pulls_per_minute: args.rate_limit_pulls,
+            pushes_per_minute: args.rate_limit_pushes,
+            auth_failures_per_minute: args.rate_limit_auth_failures,
+            buckets: Mutex::new(Buckets::default()),
+            fingerprints: RandomState::new(),
+        }
+    }
+
+    /// Bucket key of the request's credentials, checked no further than a bearer token's
+    /// signature: credentials that turn out wrong are refunded and counted as failed logins, and
+    /// a wrong password lands in its own bucket rather than spending the user's
+    fn credential_key(&self, state: &state::App, headers: &HeaderMap) -> Option<String> {
+        if let Some(token) = tokens::bearer_token(headers) {
+            if !state.args.token_auth {
+                return None;
+            }
+            return state
+                .token_issuer
+                .verify(token, tokens::unix_now())
+                .ok()
+                .filter(|claims| !claims.is_anonymous())
+                .map(|claims| format!("user:{}", claims.sub));
+        }
+        let username = auth::basic_username(headers)?;
+        let fingerprint = self.fingerprints.hash_one(headers.get("authorization")?);
+        Some(format!("user:{}#{:016x}", username, fingerprint))
+    }
+
+    /// Requests per minute allowed for `class`, which is also the burst size (0 = unlimited)
+    fn limit(&self, class: Class) -> u32 {
+        match class {
+            Class::Pull => self.pulls_per_minute,
+            Class::Push => self.pushes_per_minute,
+            Class::AuthFailure => self.auth_failures_per_minute,
+        }
+    }
+
+    /// Run `f` on the bucket of `key`, refilled up to now
+    fn with_bucket<T>(&self, class: Class, key: &str, f: impl FnOnce(&mut Bucket, f64) -> T) -> T {
+        let refill = |bucket: &Bucket, limit: f64, now: Instant| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            (bucket.tokens + elapsed * limit / 60.0).min(limit)
+        };
+        let limit = f64::from(self.limit(class));
+        let now = Instant::now();
+        let mut guard = self.buckets.lock().unwrap();
+        let buckets = &mut *guard;
+        buckets.clock += 1;
+        let clock = buckets.clock;
+        let key = (class, key.to_string());
+
+        match buckets.entries.get_mut(&key) {
+            Some(bucket) => {
+                buckets.order.remove(&bucket.last_use);
+                bucket.last_use = clock;
+            }
+            None => {
+                while buckets.entries.len() >= MAX_BUCKETS {
+                    let Some((_, oldest)) = buckets.order.pop_first() else {
+                        break;
+                    };
+                    buckets.entries.remove(&oldest);
+                }
+                buckets.entries.insert(
+                    key.clone(),
+                    Bucket {
+                        tokens: limit,
+                        updated: now,
+                        last_use: clock,
+                    },
+                );
+            }
+        }
+        buckets.order.insert(clock, key.clone());
+
+        let bucket = buckets.entries.get_mut(&key).unwrap();
+        bucket.tokens = refill(bucket, limit, now);
+        bucket.updated = now;
+        f(bucket, limit)
+    }
+
+    /// Time until a token of `class` refills, if `key` has none left
+    fn retry_after(tokens: f64, limit: f64) -> Duration {
+        Duration::from_secs_f64(((1.0 - tokens) * 60.0 / limit).max(0.0))
+    }
+
+    /// Take a token of `class` for `key`, or the time until one is available
+    pub(crate) fn try_take(&self, class: Class, key: &str) -> Result<(), Duration> {
+        if self.limit(class) == 0 {
+            return Ok(());
+        }
+        self.with_bucket(class, key, |bucket, limit| {
+            if bucket.tokens >= 1.0 {
+                bucket.tokens -= 1.0;
+                Ok(())
+            } else {
+                Err(Self::retry_after(bucket.tokens, limit))
+            }
+        })
+    }
+
+    /// Whether `key` has a token of `class` left, without taking it
+    pub(crate) fn check(&self, class: Class, key: &str) -> Result<(), Duration> {
+        if self.limit(class) == 0 {
+            return Ok(());
+        }
+        self.with_bucket(class, key, |bucket, limit| {
+            if bucket.tokens >= 1.0 {
+                Ok(())
+            } else {
+                Err(Self::retry_after(bucket.tokens, limit))
+            }
+        })
+    }
+
+    /// Take a token of `class` for `key` if one is left, for events counted after the fact
+    pub(crate) fn take(&self, class: Class, key: &str) {
+        let _ = self.try_take(class, key);
+    }
+
+    /// Return a token taken by `try_take`
+    pub(crate) fn refund(&self, class: Class, key: &str) {
+        if self.limit(class) == 0 {
+            return;
+        }
+        self.with_bucket(class, key, |bucket, limit| {
+            bucket.tokens = (bucket.tokens + 1.0).min(limit);
+        });
+    }
+}
+
+/// Pull or push, for registry API requests that count towards a limit
+fn classify(method: &Method, path: &str) -> Option<Class> {
+    let rest = path.strip_prefix("/v2/")?;
+    if rest.is_empty() {
+        return None;
+    }
+    match *method {
+        Method::GET | Method::HEAD => Some(Class::Pull),
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE => Some(Class::Push),
+        _ => None,
+    }
+}
+
+fn limited(class: Class, key: &str, retry_after: Duration) -> Response {
+    log::warn!(
+        "ratelimit: {} limit reached for {}, retry in {:.1}s",
+        class.as_str(),
+        key,
+        retry_after.as_secs_f64()
+    );
+    metrics::RATE_LIMITED_TOTAL
+        .with_label_values(&[class.as_str()])
+        .inc();
+    let mut response =
+        response::too_many_requests(&format!("{} rate limit reached", class.as_str()));
+    response.headers_mut().insert(
+        "Retry-After",
+        retry_after
+            .as_secs_f64()
+            .ceil()
+            .max(1.0)
+            .to_string()
+            .parse()
+            .unwrap(),
+    );
+    response
+}
+
+/// Refuse requests over `--rate-limit-pulls`, `--rate-limit-pushes` or
+/// `--rate-limit-auth-failures` with `429 TOOMANYREQUESTS` and `Retry-After`
+pub async fn rate_limit(
+    State(state): State<Arc<state::App>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let limiter = &state.rate_limiter;
+    let ip = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or_else(
+            || "unknown".to_string(),
+            |info| {
+                access_log::client_ip(info.0.ip(), req.headers(), &state.args.trusted_proxies)
+                    .to_string()
+            },
+        );
+
+    // Clients that keep failing to log in are turned away before their credentials are checked
+    let has_credentials = req.headers().contains_key("authorization");
+    if has_credentials {
+        if let Err(retry_after) = limiter.check(Class::AuthFailure, &ip) {
+            return limited(Class::AuthFailure, &ip, retry_after);
+        }
+    }
+
+    let class = classify(req.method(), req.uri().path());
+    let key = limiter
+        .credential_key(&state, req.headers())
+        .unwrap_or_else(|| format!("ip:{}", ip));
+    if let Some(class) = class {
+        if let Err(retry_after) = limiter.try_take(class, &key) {
+            return limited(class, &key, retry_after);
+        }
+    }
+
+    let response = next.run(req).await;
+
+    // Rejected credentials are counted as failed logins instead of spending a pull or push.
+    // Anonymous 401s are the usual start of a login and are not failures.
+    if has_credentials && response.status() == StatusCode::UNAUTHORIZED {
+        if let Some(class) = class {
+            limiter.refund(class, &key);
+        }
+        limiter.take(Class::AuthFailure, &ip);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(per_minute: u32) -> RateLimiter {
+        RateLimiter {
+            pulls_per_minute: per_minute,
+            pushes_per_minute: 0,
+            auth_failures_per_minute: per_minute,
+            buckets: Mutex::new(Buckets::default()),
+            fingerprints: RandomState::new(),
+        }
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = limiter(2);
+        assert!(limiter.try_take(Class::Pull, "ci").is_ok());
+        assert!(limiter.try_take(Class::Pull, "ci").is_ok());
+        let retry_after = limiter.try_take(Class::Pull, "ci").unwrap_err();
+        assert!(retry_after > Duration::from_secs(25) && retry_after <= Duration::from_secs(30));
+
+        // Keys, classes and unlimited classes are independent
+        assert!(limiter.try_take(Class::Pull, "other").is_ok());
+        assert!(limiter.check(Class::AuthFailure, "ci").is_ok());
+        for _ in 0..10 {
+            assert!(limiter.try_take(Class::Push, "ci").is_ok());
+        }
+
+        limiter.refund(Class::Pull, "ci");
+        assert!(limiter.try_take(Class::Pull, "ci").is_ok());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let limiter = limiter(2);
+        for i in 0..MAX_BUCKETS {
+            limiter.take(Class::Pull, &format!("client-{}", i));
+        }
+        limiter.take(Class::Pull, "client-0");
+        limiter.take(Class::Pull, "newcomer");
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.entries.len(), MAX_BUCKETS);
+        assert_eq!(buckets.order.len(), MAX_BUCKETS);
+        assert!(buckets
+            .entries
+            .contains_key(&(Class::Pull, "client-0".to_string())));
+        assert!(!buckets
+            .entries
+            .contains_key(&(Class::Pull, "client-1".to_string())));
+        assert!(buckets
+            .entries
+            .contains_key(&(Class::Pull, "newcomer".to_string())));
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(&Method::GET, "/v2/"), None);
+        assert_eq!(
+            classify(&Method::GET, "/v2/org/app/manifests/v1"),
+            Some(Class::Pull)
+        );
+        assert_eq!(
+            classify(&Method::HEAD, "/v2/org/app/blobs/sha256:00"),
+            Some(Class::Pull)
+        );
+        assert_eq!(
+            classify(&Method::PATCH, "/v2/org/app/blobs/uploads/1"),
+            Some(Class::Push)
+        );
+        assert_eq!(
+            classify(&Method::DELETE, "/v2/org/app/manifests/v1"),
+            Some(Class::Push)
+        );
+        assert_eq!(classify(&Method::POST, "/admin/v1/users"), None);
+    }
+}
@@ -0,0 +1,311 @@
+// | ID      | Method         | API Endpoint                                                | Success     | Failure           |
+// | ------- | -------------- | ----------------------------------------------------------- | ----------- | ----------------- |
+// | end-12a | `GET`          | `/v2/<name>/referrers/<digest>`                             | `200`       | `404`/`400`       |
+// | end-12b | `GET`          | `/v2/<name>/referrers/<digest>?artifactType=<artifactType>` | `200`       | `404`/`400`       |
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+};
+
+use crate::{
+    args::Capability,
+    auth,
+    digest::{Algorithm, Digest},
+    permissions, response, state, storage,
+};
+
+const IMAGE_INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+
+/// Descriptor of a manifest whose `subject` points at another manifest
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Referrer {
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// Referrers of one repository, keyed by subject digest
+type RepositoryIndex = HashMap<String, Vec<Referrer>>;
+
+/// Referrers per repository. A repository is indexed from storage on its first referrers query
+/// and kept current by `record`/`forget` as manifests are written and deleted.
+static INDEX: OnceLock<Mutex<HashMap<String, RepositoryIndex>>> = OnceLock::new();
+
+fn index() -> MutexGuard<'static, HashMap<String, RepositoryIndex>> {
+    INDEX
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap()
+}
+
+fn is_digest_reference(reference: &str) -> bool {
+    reference.len() == 64 && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// The subject digest and referrer descriptor of a manifest, if it has a subject
+fn parse_referrer(bytes: &[u8]) -> Option<(String, Referrer)> {
+    let manifest: Value = serde_json::from_slice(bytes).ok()?;
+    let subject = manifest
+        .get("subject")?
+        .get("digest")?
+        .as_str()?
+        .to_string();
+
+    let str_field = |value: Option<&Value>| value.and_then(|v| v.as_str()).map(str::to_string);
+    let media_type = str_field(manifest.get("mediaType"))
+        .unwrap_or_else(|| "application/vnd.oci.image.manifest.v1+json".to_string());
+    // Image manifests without an explicit artifactType are typed by their config
+    let artifact_type = str_field(manifest.get("artifactType"))
+        .or_else(|| str_field(manifest.get("config").and_then(|c| c.get("mediaType"))));
+    let annotations = manifest
+        .get("annotations")
+        .and_then(|a| a.as_object())
+        .map(|a| {
+            a.iter()
+                .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Some((
+        subject,
+        Referrer {
+            media_type,
+            digest: Digest::of(Algorithm::Sha256, bytes).to_string(),
+            size: bytes.len() as u64,
+            artifact_type,
+            annotations,
+        },
+    ))
+}
+
+fn insert(index: &mut RepositoryIndex, subject: String, referrer: Referrer) {
+    let referrers = index.entry(subject).or_default();
+    if !referrers.iter().any(|r| r.digest == referrer.digest) {
+        referrers.push(referrer);
+    }
+}
+
+/// Index every digest-addressed manifest of a repository
+fn load(org: &str, repo: &str) -> RepositoryIndex {
+    let mut repository_index = RepositoryIndex::new();
+    let references = match storage::list_references(org, repo) {
+        Ok(references) => references,
+        Err(e) => {
+            log::error!("referrers: failed to list {}/{}: {}", org, repo, e);
+            return repository_index;
+        }
+    };
+
+    for reference in references.iter().filter(|r| is_digest_reference(r)) {
+        match storage::read_manifest(org, repo, reference) {
+            Ok(bytes) => {
+                if let Some((subject, referrer)) = parse_referrer(&bytes) {
+                    insert(&mut repository_index, subject, referrer);
+                }
+            }
+            Err(e) => log::warn!(
+                "referrers: failed to read {}/{}/{}: {}",
+                org,
+                repo,
+                reference,
+                e
+            ),
+        }
+    }
+    repository_index
+}
+
+/// Record a manifest written under `reference`; only digest-addressed copies are indexed so a
+/// manifest pushed by tag is counted once
+pub(crate) fn record(org: &str, repo: &str, reference: &str, bytes: &[u8]) {
+    if !is_digest_reference(reference) {
+        return;
+    }
+    let mut index = index();
+    // Unloaded repositories pick the manifest up when they are first indexed
+    let Some(repository_index) = index.get_mut(&format!("{}/{}", org, repo)) else {
+        return;
+    };
+    if let Some((subject, referrer)) = parse_referrer(bytes) {
+        insert(repository_index, subject, referrer);
+    }
+}
+
+/// Drop a deleted digest-addressed manifest from its subject's referrers
+pub(crate) fn forget(org: &str, repo: &str, reference: &str) {
+    if !is_digest_reference(reference) {
+        return;
+    }
+    let digest = format!("sha256:{}", reference);
+    if let Some(repository_index) = index().get_mut(&format!("{}/{}", org, repo)) {
+        repository_index.retain(|_, referrers| {
+            referrers.retain(|r| r.digest != digest);
+            !referrers.is_empty()
+        });
+    }
+}
+
+/// Referrers of `subject`, optionally restricted to one artifact type, sorted by digest
+pub(crate) fn list(
+    org: &str,
+    repo: &str,
+    subject: &Digest,
+    artifact_type: Option<&str>,
+) -> Vec<Referrer> {
+    let mut index = index();
+    let repository_index = index
+        .entry(format!("{}/{}", org, repo))
+        .or_insert_with(|| load(org, repo));
+
+    let mut referrers: Vec<Referrer> = repository_index
+        .get(&subject.to_string())
+        .into_iter()
+        .flatten()
+        .filter(|r| artifact_type.is_none_or(|t| r.artifact_type.as_deref() == Some(t)))
+        .cloned()
+        .collect();
+    referrers.sort_by(|a, b| a.digest.cmp(&b.digest));
+    referrers
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ReferrersQuery {
+    #[serde(rename = "artifactType")]
+    pub artifact_type: Option<String>,
+}
+
+// end-12a GET /v2/:name/referrers/:digest
+// end-12b GET /v2/:name/referrers/:digest?artifactType=<artifactType>
+pub(crate) async fn get_referrers(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo, digest)): Path<(String, String, String)>,
+    Query(params): Query<ReferrersQuery>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    if !state.args.is_enabled(Capability::Referrers) {
+        return response::capability_disabled(StatusCode::NOT_FOUND, "referrers");
+    }
+
+    let host = state.args.primary_host();
+    let repository = format!("{}/{}", org, repo);
+
+    // Check permission (Pull for listing referrers)
+    match auth::check_permission(
+        &state,
+        &headers,
+        &repository,
+        None,
+        permissions::Action::Pull,
+    )
+    .await
+    {
+        Ok(_) => {}
+        Err(_) => {
+            return if auth::authenticate_user(&state, &headers).await.is_ok() {
+                response::forbidden()
+            } else {
+                response::unauthorized(host)
+            };
+        }
+    }
+
+    let subject = match Digest::parse(&digest) {
+        Ok(subject) => subject,
+        Err(e) => {
+            log::warn!("referrers: invalid digest {}: {}", digest, e);
+            return response::digest_invalid(&digest);
+        }
+    };
+
+    log::info!(
+        "referrers/get_referrers: org: {}, repo: {}, digest: {}, artifactType: {:?}",
+        org,
+        repo,
+        subject,
+        params.artifact_type
+    );
+
+    // A subject without referrers, or not pushed yet, gets an empty index
+    let artifact_type = params.artifact_type.clone();
+    let referrers = storage::blocking("list_referrers", &org, &repo, move |org, repo| {
+        list(org, repo, &subject, artifact_type.as_deref())
+    })
+    .await;
+    let body = serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": IMAGE_INDEX_MEDIA_TYPE,
+        "manifests": referrers,
+    });
+
+    let mut builder = Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", IMAGE_INDEX_MEDIA_TYPE);
+    if params.artifact_type.is_some() {
+        builder = builder.header("OCI-Filters-Applied", "artifactType");
+    }
+    builder.body(Body::from(body.to_string())).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_referrer() {
+        let subject = format!("sha256:{}", "a".repeat(64));
+        let manifest = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {
+                "mediaType": "application/vnd.example.sbom",
+                "digest": format!("sha256:{}", "b".repeat(64)),
+                "size": 2
+            },
+            "layers": [],
+            "subject": {
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "digest": subject,
+                "size": 100
+            },
+            "annotations": { "org.example.kind": "sbom" }
+        });
+        let bytes = serde_json::to_vec(&manifest).unwrap();
+
+        let (parsed_subject, referrer) = parse_referrer(&bytes).unwrap();
+        assert_eq!(parsed_subject, subject);
+        assert_eq!(
+            referrer.artifact_type.as_deref(),
+            Some("application/vnd.example.sbom")
+        );
+        assert_eq!(referrer.size, bytes.len() as u64);
+        assert_eq!(referrer.annotations["org.example.kind"], "sbom");
+
+        // An explicit artifactType wins over the config media type
+        let mut explicit = manifest.clone();
+        explicit["artifactType"] = "application/vnd.example.signature".into();
+        let (_, referrer) = parse_referrer(&serde_json::to_vec(&explicit).unwrap()).unwrap();
+        assert_eq!(
+            referrer.artifact_type.as_deref(),
+            Some("application/vnd.example.signature")
+        );
+
+        let mut plain = manifest;
+        plain.as_object_mut().unwrap().remove("subject");
+        assert!(parse_referrer(&serde_json::to_vec(&plain).unwrap()).is_none());
+    }
+}
@@ -0,0 +1,527 @@
+//! Push-based replication: manifests pushed here are copied, with their blobs and child
+//! manifests, to every registry in `--replicate-to`. Copies run in the background and failed
+//! ones stay queued, retried with exponential backoff, until the peer accepts them.
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use serde::Serialize;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Notify;
+use utoipa::ToSchema;
+
+use crate::{
+    admin, args::Args, auth, digest::Digest, metrics, response, secrets::Secret, state, storage,
+};
+
+/// First wait before retrying a failed push, doubled on each attempt
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest wait between two attempts of the same push
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Pushes queued per peer before the oldest are dropped
+const MAX_QUEUED: usize = 10_000;
+
+/// Pending pushes listed per peer by `/admin/replication/status`
+const MAX_LISTED: usize = 100;
+
+const DEFAULT_MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+type ReplicationResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Manifest waiting to be copied to a peer
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PendingPush {
+    /// `<org>/<repo>`
+    pub repository: String,
+    /// Tag or `sha256:<hex>` the manifest was pushed by
+    pub reference: String,
+    pub digest: String,
+    /// Unix time the push was first queued
+    pub queued_at: u64,
+    pub attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(skip)]
+    next_attempt: Option<Instant>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PeerStatus {
+    /// URL as given to `--replicate-to`
+    pub url: String,
+    pub queued: usize,
+    /// Seconds since the oldest queued push was made here, 0 when the peer is caught up
+    pub lag_secs: u64,
+    /// Manifests copied since this instance started
+    pub replicated: u64,
+    /// Unix time of the last successful copy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_success: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Oldest queued pushes, at most 100
+    pub pending: Vec<PendingPush>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReplicationStatus {
+    pub peers: Vec<PeerStatus>,
+}
+
+#[derive(Default)]
+struct Queue {
+    pending: VecDeque<PendingPush>,
+    replicated: u64,
+    last_success: Option<u64>,
+    last_error: Option<String>,
+}
+
+/// Downstream registry and the pushes it has not received yet
+struct Peer {
+    url: String,
+    username: String,
+    password: Secret,
+    http: reqwest::Client,
+    queue: Mutex<Queue>,
+    wake: Notify,
+}
+
+pub(crate) struct Replicator {
+    peers: Vec<Arc<Peer>>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl Replicator {
+    pub(crate) fn from_args(args: &Args) -> Self {
+        if args.replicate_to.is_empty() {
+            return Self { peers: Vec::new() };
+        }
+
+        let password =
+            Secret::resolve_or_exit("--replication-password", &args.replication_password);
+        let peers = args
+            .replicate_to
+            .iter()
+            .map(|url| {
+                Arc::new(Peer {
+                    url: url.trim_end_matches('/').to_string(),
+                    username: args.replication_username.clone(),
+                    password: password.clone(),
+                    http: reqwest::Client::new(),
+                    queue: Mutex::new(Queue::default()),
+                    wake: Notify::new(),
+                })
+            })
+            .collect();
+        Self { peers }
+    }
+
+    /// Queue manifest `digest` (hex) of `org/repo`, pushed as `reference`, for every peer
+    pub(crate) fn enqueue(&self, org: &str, repo: &str, reference: &str, digest: &str) {
+        let repository = format!("{}/{}", org, repo);
+        for peer in &self.peers {
+            {
+                let mut queue = peer.queue.lock().unwrap();
+                // A tag pushed again before it was copied only needs its latest manifest copied
+                if let Some(push) = queue
+                    .pending
+                    .iter_mut()
+                    .find(|p| p.repository == repository && p.reference == reference)
+                {
+                    push.digest = format!("sha256:{}", digest);
+                    push.attempts = 0;
+                    push.next_attempt = None;
+                } else {
+                    if queue.pending.len() >= MAX_QUEUED {
+                        if let Some(dropped) = queue.pending.pop_front() {
+                            log::error!(
+                                "replication: queue for {} full, dropped {}:{}",
+                                peer.url,
+                                dropped.repository,
+                                dropped.reference
+                            );
+                            metrics::REPLICATION_PUSHES_TOTAL
+                                .with_label_values(&["dropped"])
+                                .inc();
+                        }
+                    }
+                    queue.pending.push_back(PendingPush {
+                        repository: repository.clone(),
+                        reference: reference.to_string(),
+                        digest: format!("sha256:{}", digest),
+                        queued_at: now(),
+                        attempts: 0,
+                        last_error: None,
+                        next_attempt: None,
+                    });
+                }
+                metrics::REPLICATION_QUEUED
+                    .with_label_values(&[&peer.url])
+                    .set(queue.pending.len() as i64);
+            }
+            peer.wake.notify_one();
+        }
+    }
+
+    fn status(&self) -> ReplicationStatus {
+        let now = now();
+        ReplicationStatus {
+            peers: self
+                .peers
+                .iter()
+                .map(|peer| {
+                    let queue = peer.queue.lock().unwrap();
+                    PeerStatus {
+                        url: peer.url.clone(),
+                        queued: queue.pending.len(),
+                        lag_secs: queue
+                            .pending
+                            .iter()
+                            .map(|p| now.saturating_sub(p.queued_at))
+                            .max()
+                            .unwrap_or(0),
+                        replicated: queue.replicated,
+                        last_success: queue.last_success,
+                        last_error: queue.last_error.clone(),
+                        pending: queue.pending.iter().take(MAX_LISTED).cloned().collect(),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Manifest read from local storage, with what it references
+struct StoredManifest {
+    digest: String,
+    bytes: Vec<u8>,
+    media_type: String,
+}
+
+/// Child manifests of `bytes` (deepest first) and the blobs of every manifest, read from
+/// local storage
+fn collect(
+    org: &str,
+    repo: &str,
+    bytes: &[u8],
+    children: &mut Vec<StoredManifest>,
+    blobs: &mut Vec<Digest>,
+) -> ReplicationResult<()> {
+    let manifest: serde_json::Value = serde_json::from_slice(bytes)?;
+    let digest_of = |desc: &serde_json::Value| desc.get("digest")?.as_str().map(String::from);
+
+    if let Some(config) = manifest.get("config").and_then(digest_of) {
+        blobs.push(Digest::parse(&config)?);
+    }
+    for layer in manifest
+        .get("layers")
+        .and_then(|l| l.as_array())
+        .into_iter()
+        .flatten()
+    {
+        if let Some(layer) = digest_of(layer) {
+            blobs.push(Digest::parse(&layer)?);
+        }
+    }
+    for child in manifest
+        .get("manifests")
+        .and_then(|m| m.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let Some(digest) = digest_of(child) else {
+            continue;
+        };
+        let hex = digest.trim_start_matches("sha256:");
+        let child_bytes = storage::read_manifest(org, repo, hex)?;
+        collect(org, repo, &child_bytes, children, blobs)?;
+        children.push(StoredManifest {
+            media_type: media_type_of(&child_bytes),
+            digest,
+            bytes: child_bytes,
+        });
+    }
+    Ok(())
+}
+
+fn media_type_of(bytes: &[u8]) -> String {
+    serde_json::from_slice::<serde_json::Value>(bytes)
+        .ok()
+        .and_then(|m| m.get("mediaType")?.as_str().map(String::from))
+        .unwrap_or_else(|| DEFAULT_MANIFEST_MEDIA_TYPE.to_string())
+}
+
+impl Peer {
+    fn url(&self, org: &str, repo: &str, path: &str) -> String {
+        format!("{}/v2/{}/{}/{}", self.url, org, repo, path)
+    }
+
+    async fn push_blob(&self, org: &str, repo: &str, digest: &Digest) -> ReplicationResult<()> {
+        let exists = self
+            .http
+            .head(self.url(org, repo, &format!("blobs/{}", digest)))
+            .basic_auth(&self.username, Some(self.password.get()))
+            .send()
+            .await?;
+        if exists.status().is_success() {
+            return Ok(());
+        }
+
+        let blob = digest.clone();
+        let (reader, size) = storage::blocking("open_blob", org, repo, move |org, repo| {
+            storage::open_blob(org, repo, &blob, 0)
+        })
+        .await?;
+        self.http
+            .post(self.url(org, repo, &format!("blobs/uploads/?digest={}", digest)))
+            .basic_auth(&self.username, Some(self.password.get()))
+            .header("Content-Type", "application/octet-stream")
+            .header("Content-Length", size)
+            .body(reqwest::Body::wrap_stream(
+                tokio_util::io::ReaderStream::new(reader),
+            ))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn push_manifest(
+        &self,
+        org: &str,
+        repo: &str,
+        reference: &str,
+        manifest: &StoredManifest,
+    ) -> ReplicationResult<()> {
+        self.http
+            .put(self.url(org, repo, &format!("manifests/{}", reference)))
+            .basic_auth(&self.username, Some(self.password.get()))
+            .header("Content-Type", &manifest.media_type)
+            .body(manifest.bytes.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Copy the manifest of `push` with everything it references, blobs first, so the peer
+    /// can validate it
+    async fn replicate(&self, push: &PendingPush) -> ReplicationResult<()> {
+        let (org, repo) = push
+            .repository
+            .split_once('/')
+            .ok_or("repository without organization")?;
+        let hex = push.digest.trim_start_matches("sha256:").to_string();
+        let (bytes, children, blobs) =
+            storage::blocking("read_replicated_manifest", org, repo, move |org, repo| {
+                let bytes = storage::read_manifest(org, repo, &hex)?;
+                let mut children = Vec::new();
+                let mut blobs = Vec::new();
+                collect(org, repo, &bytes, &mut children, &mut blobs)?;
+                ReplicationResult::Ok((bytes, children, blobs))
+            })
+            .await?;
+
+        let mut seen = HashSet::new();
+        for blob in blobs.iter().filter(|b| seen.insert(*b)) {
+            self.push_blob(org, repo, blob).await?;
+        }
+        for child in &children {
+            self.push_manifest(org, repo, &child.digest, child).await?;
+        }
+        let manifest = StoredManifest {
+            digest: push.digest.clone(),
+            media_type: media_type_of(&bytes),
+            bytes,
+        };
+        self.push_manifest(org, repo, &push.reference, &manifest)
+            .await
+    }
+
+    /// Oldest queued push that is due, or how long until one is
+    fn next_due(&self) -> Result<PendingPush, Option<Duration>> {
+        let queue = self.queue.lock().unwrap();
+        let now = Instant::now();
+        let mut wait: Option<Duration> = None;
+        for push in &queue.pending {
+            match push.next_attempt {
+                Some(at) if at > now => {
+                    let until = at - now;
+                    wait = Some(wait.map_or(until, |w| w.min(until)));
+                }
+                _ => return Ok(push.clone()),
+            }
+        }
+        Err(wait)
+    }
+
+    /// Record the outcome of `push`, unless it was replaced by a newer push of its reference
+    fn finish(&self, push: &PendingPush, result: ReplicationResult<()>) {
+        let mut queue = self.queue.lock().unwrap();
+        let Some(index) = queue.pending.iter().position(|p| {
+            p.repository == push.repository
+                && p.reference == push.reference
+                && p.digest == push.digest
+        }) else {
+            return;
+        };
+
+        match result {
+            Ok(()) => {
+                queue.pending.remove(index);
+                queue.replicated += 1;
+                queue.last_success = Some(now());
+                metrics::REPLICATION_PUSHES_TOTAL
+                    .with_label_values(&["success"])
+                    .inc();
+                log::info!(
+                    "replication: copied {}:{} to {}",
+                    push.repository,
+                    push.reference,
+                    self.url
+                );
+            }
+            Err(e) => {
+                let error = e.to_string();
+                let pending = &mut queue.pending[index];
+                pending.attempts += 1;
+                let backoff = RETRY_BACKOFF
+                    .saturating_mul(2u32.saturating_pow(pending.attempts - 1))
+                    .min(MAX_RETRY_BACKOFF);
+                pending.next_attempt = Some(Instant::now() + backoff);
+                pending.last_error = Some(error.clone());
+                log::warn!(
+                    "replication: copying {}:{} to {} failed ({}), retrying in {}s",
+                    push.repository,
+                    push.reference,
+                    self.url,
+                    error,
+                    backoff.as_secs()
+                );
+                queue.last_error = Some(error);
+                metrics::REPLICATION_PUSHES_TOTAL
+                    .with_label_values(&["failure"])
+                    .inc();
+            }
+        }
+        metrics::REPLICATION_QUEUED
+            .with_label_values(&[&self.url])
+            .set(queue.pending.len() as i64);
+    }
+}
+
+/// Start one worker per `--replicate-to` peer, draining its queue
+pub(crate) fn spawn_workers(state: &Arc<state::App>) {
+    for peer in &state.replication.peers {
+        log::info!("replication: copying pushed manifests to {}", peer.url);
+        let peer = peer.clone();
+        tokio::spawn(async move {
+            loop {
+                match peer.next_due() {
+                    Ok(push) => {
+                        let result = peer.replicate(&push).await;
+                        peer.finish(&push, result);
+                    }
+                    Err(Some(wait)) => {
+                        let _ = tokio::time::timeout(wait, peer.wake.notified()).await;
+                    }
+                    Err(None) => peer.wake.notified().await,
+                }
+            }
+        });
+    }
+}
+
+/// Pushes queued for each `--replicate-to` peer and how far behind it is (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/replication/status",
+    responses(
+        (status = 200, description = "Replication queue of each peer", body = ReplicationStatus),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn replication_status(
+    State(state): State<Arc<state::App>>,
+    headers: HeaderMap,
+) -> Response {
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(state.args.primary_host()),
+    };
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string_pretty(&state.replication.status()).unwrap(),
+        ))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enqueue_keeps_latest_push_of_a_tag() {
+        let replicator = Replicator {
+            peers: vec![Arc::new(Peer {
+                url: "http://peer".to_string(),
+                username: String::new(),
+                password: Secret::resolve("").unwrap(),
+                http: reqwest::Client::new(),
+                queue: Mutex::new(Queue::default()),
+                wake: Notify::new(),
+            })],
+        };
+        replicator.enqueue("team", "app", "v1", "aa");
+        replicator.enqueue("team", "app", "v2", "bb");
+        replicator.enqueue("team", "app", "v1", "cc");
+
+        let status = replicator.status();
+        let pending = &status.peers[0].pending;
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].reference, "v1");
+        assert_eq!(pending[0].digest, "sha256:cc");
+        assert_eq!(pending[1].reference, "v2");
+
+        // A copy of the replaced manifest does not complete the newer push
+        let mut stale = pending[0].clone();
+        stale.digest = "sha256:aa".to_string();
+        replicator.peers[0].finish(&stale, Ok(()));
+        assert_eq!(replicator.status().peers[0].queued, 2);
+
+        replicator.peers[0].finish(&pending[0], Err("connection refused".into()));
+        let status = replicator.status();
+        assert_eq!(status.peers[0].pending[0].attempts, 1);
+        assert_eq!(
+            status.peers[0].last_error.as_deref(),
+            Some("connection refused")
+        );
+        // Not due until its backoff elapsed, but the next push is
+        assert_eq!(replicator.peers[0].next_due().unwrap().reference, "v2");
+    }
+}
@@ -0,0 +1,1371 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+use crate::{
+    admin,
+    audit::{AuditEvent, Outcome},
+    auth,
+    digest::Digest,
+    pagination::{self, PageQuery},
+    permissions,
+    repositories::{Repository, RetentionPolicy, Visibility},
+    response,
+    robots::{self, Robot},
+    state, storage, validation,
+};
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PlatformSummary {
+    pub digest: String,
+    pub os: Option<String>,
+    pub architecture: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Resolution {
+    pub repository: String,
+    pub reference: String,
+    pub digest: String,
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub platforms: Vec<PlatformSummary>,
+    pub total_size: u64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ResolveQuery {
+    #[serde(rename = "ref", default = "default_reference")]
+    pub reference: String,
+}
+
+fn default_reference() -> String {
+    "latest".to_string()
+}
+
+fn digest_of(descriptor: &Value) -> Option<&str> {
+    descriptor.get("digest").and_then(|d| d.as_str())
+}
+
+fn size_of(descriptor: &Value) -> u64 {
+    descriptor.get("size").and_then(|s| s.as_u64()).unwrap_or(0)
+}
+
+/// Sum config and layer sizes of an image manifest, counting each blob once
+fn image_size(manifest: &Value, seen: &mut HashSet<String>) -> u64 {
+    let layers = manifest
+        .get("layers")
+        .and_then(|l| l.as_array())
+        .into_iter()
+        .flatten();
+
+    manifest
+        .get("config")
+        .into_iter()
+        .chain(layers)
+        .filter(|desc| digest_of(desc).is_some_and(|d| seen.insert(d.to_string())))
+        .map(size_of)
+        .sum()
+}
+
+/// Read the platform of a single image manifest from its config blob
+fn image_platform(org: &str, repo: &str, digest: &str, manifest: &Value) -> PlatformSummary {
+    let config = manifest
+        .get("config")
+        .and_then(digest_of)
+        .and_then(|d| Digest::parse(d).ok())
+        .and_then(|d| storage::read_blob(org, repo, &d).ok())
+        .and_then(|data| serde_json::from_slice::<Value>(&data).ok())
+        .unwrap_or(Value::Null);
+
+    let field = |name: &str| config.get(name).and_then(|v| v.as_str()).map(String::from);
+
+    PlatformSummary {
+        digest: digest.to_string(),
+        os: field("os"),
+        architecture: field("architecture"),
+        variant: field("variant"),
+    }
+}
+
+/// Resolve a tag or digest to its digest, platforms and total size in one pass
+pub fn resolve_reference(
+    org: &str,
+    repo: &str,
+    reference: &str,
+) -> Result<Resolution, std::io::Error> {
+    let clean_reference = reference.strip_prefix("sha256:").unwrap_or(reference);
+    let manifest_data = storage::read_manifest(org, repo, clean_reference)?;
+    let digest = format!("sha256:{}", sha256::digest(&manifest_data));
+
+    let manifest: Value = serde_json::from_slice(&manifest_data)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    let mut seen = HashSet::new();
+    let mut platforms = Vec::new();
+    let mut total_size = 0;
+
+    if let Some(children) = manifest.get("manifests").and_then(|m| m.as_array()) {
+        for child in children {
+            let Some(child_digest) = digest_of(child) else {
+                continue;
+            };
+            let platform = child.get("platform");
+            let field = |name: &str| {
+                platform
+                    .and_then(|p| p.get(name))
+                    .and_then(|v| v.as_str())
+                    .map(String::from)
+            };
+
+            platforms.push(PlatformSummary {
+                digest: child_digest.to_string(),
+                os: field("os"),
+                architecture: field("architecture"),
+                variant: field("variant"),
+            });
+
+            let clean_child = child_digest.strip_prefix("sha256:").unwrap_or(child_digest);
+            if let Some(child_manifest) = storage::read_manifest(org, repo, clean_child)
+                .ok()
+                .and_then(|data| serde_json::from_slice::<Value>(&data).ok())
+            {
+                total_size += image_size(&child_manifest, &mut seen);
+            }
+        }
+    } else {
+        platforms.push(image_platform(org, repo, &digest, &manifest));
+        total_size = image_size(&manifest, &mut seen);
+    }
+
+    let media_type = manifest
+        .get("mediaType")
+        .and_then(|v| v.as_str())
+        .unwrap_or("application/vnd.oci.image.manifest.v1+json")
+        .to_string();
+
+    Ok(Resolution {
+        repository: format!("{}/{}", org, repo),
+        reference: reference.to_string(),
+        digest,
+        media_type,
+        platforms,
+        total_size,
+    })
+}
+
+/// Resolve a reference to its digest, platform list and total size
+#[utoipa::path(
+    get,
+    path = "/admin/v1/repos/{org}/{repo}/resolve",
+    params(
+        ("org" = String, Path, description = "Organization name"),
+        ("repo" = String, Path, description = "Repository name"),
+        ("ref" = Option<String>, Query, description = "Tag or digest to resolve (default: latest)")
+    ),
+    responses(
+        (status = 200, description = "Resolved reference", body = Resolution),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - pull permission required"),
+        (status = 404, description = "Not found - manifest does not exist")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn resolve(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo)): Path<(String, String)>,
+    Query(params): Query<ResolveQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let host = state.args.primary_host();
+    let repository = format!("{}/{}", org, repo);
+    let clean_reference = params
+        .reference
+        .strip_prefix("sha256:")
+        .unwrap_or(&params.reference);
+
+    // Check permission (Pull on the resolved reference)
+    match auth::check_permission(
+        &state,
+        &headers,
+        &repository,
+        Some(clean_reference),
+        permissions::Action::Pull,
+    )
+    .await
+    {
+        Ok(_) => {}
+        Err(_) => {
+            return if auth::authenticate_user(&state, &headers).await.is_ok() {
+                response::forbidden()
+            } else {
+                response::unauthorized(host)
+            };
+        }
+    }
+
+    let reference = params.reference.clone();
+    let resolution = storage::blocking("resolve_reference", &org, &repo, move |org, repo| {
+        resolve_reference(org, repo, &reference)
+    })
+    .await;
+    match resolution {
+        Ok(resolution) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::to_string(&resolution).unwrap()))
+            .unwrap(),
+        Err(e) => {
+            log::warn!(
+                "repos/resolve: failed to resolve {}:{}: {}",
+                repository,
+                params.reference,
+                e
+            );
+            response::manifest_unknown(clean_reference)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PullSecretQuery {
+    /// Secret name, also naming the robot it embeds (default: grain-<org>-<repo>-pull)
+    pub name: Option<String>,
+    pub namespace: Option<String>,
+    /// Registry host clients pull from (default: the host from `--external-url` or the request)
+    pub registry: Option<String>,
+}
+
+/// Lowercase and replace characters not allowed in Kubernetes object names
+fn kubernetes_name(name: &str) -> String {
+    let name: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    name.trim_matches(|c| c == '-' || c == '.')
+        .chars()
+        .take(253)
+        .collect()
+}
+
+/// Build a `kubernetes.io/dockerconfigjson` Secret for the given credentials
+pub fn pull_secret_manifest(
+    name: &str,
+    namespace: Option<&str>,
+    registry: &str,
+    username: &str,
+    password: &str,
+) -> Value {
+    let auth = BASE64_STANDARD.encode(format!("{}:{}", username, password));
+    let docker_config = serde_json::json!({
+        "auths": {
+            registry: {
+                "username": username,
+                "password": password,
+                "auth": auth,
+            }
+        }
+    });
+
+    let mut metadata = serde_json::json!({ "name": name });
+    if let Some(namespace) = namespace {
+        metadata["namespace"] = Value::from(namespace);
+    }
+
+    serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "Secret",
+        "metadata": metadata,
+        "type": "kubernetes.io/dockerconfigjson",
+        "data": {
+            ".dockerconfigjson": BASE64_STANDARD.encode(docker_config.to_string()),
+        }
+    })
+}
+
+/// Mint a pull-only robot for a repository and export its token as a Kubernetes image pull
+/// secret (admin only)
+#[utoipa::path(
+    post,
+    path = "/admin/v1/repos/{org}/{repo}/pull-secret",
+    params(
+        ("org" = String, Path, description = "Organization name"),
+        ("repo" = String, Path, description = "Repository name"),
+        ("name" = Option<String>, Query, description = "Secret and robot name (default: grain-<org>-<repo>-pull)"),
+        ("namespace" = Option<String>, Query, description = "Secret namespace (omitted by default)"),
+        ("registry" = Option<String>, Query, description = "Registry host used by clients (default: host from --external-url or the request)")
+    ),
+    responses(
+        (status = 200, description = "Kubernetes Secret of type kubernetes.io/dockerconfigjson", content_type = "application/json"),
+        (status = 400, description = "Bad request - name is not a valid robot name"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 409, description = "Conflict - robot already exists"),
+        (status = 500, description = "Internal server error - failed to save robots")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn pull_secret(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo)): Path<(String, String)>,
+    Query(params): Query<PullSecretQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let host = state.args.primary_host();
+    let repository = format!("{}/{}", org, repo);
+
+    let admin_user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !admin::is_admin(&admin_user) {
+        return response::forbidden();
+    }
+
+    let name = kubernetes_name(
+        params
+            .name
+            .as_deref()
+            .unwrap_or(&format!("grain-{}-{}-pull", org, repo)),
+    );
+    if !robots::valid_name(&name) {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from(format!("Invalid robot name {}", name)))
+            .unwrap();
+    }
+
+    // The secret carries a token that can only pull, never a user's password
+    let robot = Robot::puller(&name, &repository, &admin_user.username);
+    let token = match state.robots.create(robot.clone()) {
+        Ok(Some(token)) => token,
+        Ok(None) => return response::conflict("Robot already exists"),
+        Err(e) => {
+            log::error!("Failed to save robots: {}", e);
+            return response::internal_error();
+        }
+    };
+
+    let registry = params.registry.clone().unwrap_or_else(|| {
+        response::authority(&response::origin(&state.args, &headers)).to_string()
+    });
+
+    log::info!(
+        "Admin {} exported pull secret {} for {}",
+        admin_user.username,
+        name,
+        repository
+    );
+    state.audit.record(
+        AuditEvent::new(
+            &admin_user.username,
+            "pull_secret.export",
+            &repository,
+            Outcome::Success,
+        )
+        .with_detail(format!("as robot {}", robot.name)),
+    );
+
+    let secret = pull_secret_manifest(
+        &name,
+        params.namespace.as_deref(),
+        &registry,
+        &robot.username(),
+        &token,
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string_pretty(&secret).unwrap()))
+        .unwrap()
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BlobInfo {
+    pub repository: String,
+    pub digest: String,
+    pub size: u64,
+    /// Present when the blob was mounted from another repository
+    pub mounted_from: Option<storage::MountRecord>,
+    /// Other repositories storing the same digest
+    pub shared_with: Vec<String>,
+}
+
+/// Blob size, mount origin and the other repositories sharing it (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/repos/{org}/{repo}/blobs/{digest}",
+    params(
+        ("org" = String, Path, description = "Organization name"),
+        ("repo" = String, Path, description = "Repository name"),
+        ("digest" = String, Path, description = "Blob digest")
+    ),
+    responses(
+        (status = 200, description = "Blob metadata", body = BlobInfo),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - blob does not exist")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn blob_info(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo, digest)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    let host = state.args.primary_host();
+
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let Ok(digest) = Digest::parse(&digest) else {
+        return response::blob_unknown(&digest);
+    };
+    let repository = format!(
+        "{}/{}",
+        storage::sanitize_string(&org),
+        storage::sanitize_string(&repo)
+    );
+    let (blob, name) = (digest.clone(), repository.clone());
+    let info = storage::blocking("blob_info", &org, &repo, move |org, repo| {
+        let size = storage::blob_metadata(org, repo, &blob).ok()?.size;
+        let shared_with = storage::repositories_with_blob(&blob)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|r| *r != name)
+            .collect();
+        Some(BlobInfo {
+            repository: name,
+            digest: blob.to_string(),
+            size,
+            mounted_from: storage::read_mount_record(org, repo, &blob),
+            shared_with,
+        })
+    })
+    .await;
+    let Some(info) = info else {
+        return response::blob_unknown(&digest.to_string());
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&info).unwrap()))
+        .unwrap()
+}
+
+/// Raw OCI image config, as stored in the config blob
+#[derive(Debug, Default, Deserialize)]
+struct RawImageConfig {
+    created: Option<String>,
+    architecture: Option<String>,
+    os: Option<String>,
+    variant: Option<String>,
+    #[serde(default)]
+    config: RawContainerConfig,
+    #[serde(default)]
+    rootfs: Option<RawRootfs>,
+    #[serde(default)]
+    history: Vec<HistorySummary>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawContainerConfig {
+    env: Option<Vec<String>>,
+    labels: Option<BTreeMap<String, String>>,
+    entrypoint: Option<Vec<String>>,
+    cmd: Option<Vec<String>>,
+    working_dir: Option<String>,
+    user: Option<String>,
+    exposed_ports: Option<BTreeMap<String, Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawRootfs {
+    #[serde(default)]
+    diff_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct HistorySummary {
+    pub created: Option<String>,
+    pub created_by: Option<String>,
+    #[serde(default)]
+    pub empty_layer: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// Platform, runtime settings, labels and build history from an image config blob
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImageConfig {
+    pub repository: String,
+    pub digest: String,
+    pub architecture: Option<String>,
+    pub os: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    pub created: Option<String>,
+    pub env: Vec<String>,
+    pub labels: BTreeMap<String, String>,
+    pub entrypoint: Vec<String>,
+    pub cmd: Vec<String>,
+    pub working_dir: Option<String>,
+    pub user: Option<String>,
+    pub exposed_ports: Vec<String>,
+    /// Number of filesystem layers (`rootfs.diff_ids`)
+    pub layers: usize,
+    pub history: Vec<HistorySummary>,
+}
+
+/// Parse an OCI/Docker image config blob; fails if it is not a JSON object with an `os` or `architecture`
+pub(crate) fn parse_image_config(
+    repository: &str,
+    digest: &Digest,
+    data: &[u8],
+) -> Result<ImageConfig, String> {
+    let raw: RawImageConfig = serde_json::from_slice(data).map_err(|e| e.to_string())?;
+    if raw.os.is_none() && raw.architecture.is_none() {
+        return Err("blob is not an image config (no os or architecture)".to_string());
+    }
+
+    let config = raw.config;
+    Ok(ImageConfig {
+        repository: repository.to_string(),
+        digest: digest.to_string(),
+        architecture: raw.architecture,
+        os: raw.os,
+        variant: raw.variant,
+        created: raw.created,
+        env: config.env.unwrap_or_default(),
+        labels: config.labels.unwrap_or_default(),
+        entrypoint: config.entrypoint.unwrap_or_default(),
+        cmd: config.cmd.unwrap_or_default(),
+        working_dir: config.working_dir.filter(|d| !d.is_empty()),
+        user: config.user.filter(|u| !u.is_empty()),
+        exposed_ports: config
+            .exposed_ports
+            .unwrap_or_default()
+            .into_keys()
+            .collect(),
+        layers: raw.rootfs.map(|r| r.diff_ids.len()).unwrap_or(0),
+        history: raw.history,
+    })
+}
+
+/// Inspect an image config blob (requires pull permission)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/repos/{org}/{repo}/config/{digest}",
+    params(
+        ("org" = String, Path, description = "Organization name"),
+        ("repo" = String, Path, description = "Repository name"),
+        ("digest" = String, Path, description = "Config blob digest")
+    ),
+    responses(
+        (status = 200, description = "Parsed image config", body = ImageConfig),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - pull permission required"),
+        (status = 404, description = "Not found - blob does not exist"),
+        (status = 422, description = "Blob is not an image config")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn image_config(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo, digest)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    let host = state.args.primary_host();
+    let repository = format!("{}/{}", org, repo);
+
+    match auth::check_permission(
+        &state,
+        &headers,
+        &repository,
+        None,
+        permissions::Action::Pull,
+    )
+    .await
+    {
+        Ok(_) => {}
+        Err(_) => {
+            return if auth::authenticate_user(&state, &headers).await.is_ok() {
+                response::forbidden()
+            } else {
+                response::unauthorized(host)
+            };
+        }
+    }
+
+    let Ok(digest) = Digest::parse(&digest) else {
+        return response::blob_unknown(&digest);
+    };
+
+    // Configs are small JSON documents; don't load arbitrary layers into memory
+    let (blob, max_size) = (digest.clone(), state.args.max_manifest_size);
+    let data = storage::blocking("read_image_config", &org, &repo, move |org, repo| {
+        match storage::blob_metadata(org, repo, &blob) {
+            Ok(metadata) if metadata.size > max_size => return Ok(None),
+            Ok(_) => {}
+            Err(e) => return Err(e),
+        }
+        storage::read_blob(org, repo, &blob).map(Some)
+    })
+    .await;
+    let data = match data {
+        Ok(Some(data)) => data,
+        Ok(None) => return response::unprocessable("blob is too large to be an image config"),
+        Err(_) => return response::blob_unknown(&digest.to_string()),
+    };
+
+    match parse_image_config(&repository, &digest, &data) {
+        Ok(config) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::to_string(&config).unwrap()))
+            .unwrap(),
+        Err(e) => {
+            log::warn!(
+                "repos/image_config: {}@{} is not an image config: {}",
+                repository,
+                digest,
+                e
+            );
+            response::unprocessable(&format!("blob is not an image config: {}", e))
+        }
+    }
+}
+
+/// Permission granted to an existing user when a repository is declared
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct DefaultPermission {
+    pub username: String,
+    #[serde(default = "default_tag")]
+    pub tag: String,
+    pub actions: Vec<String>,
+}
+
+fn default_tag() -> String {
+    "*".to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct CreateRepositoryRequest {
+    /// `<org>/<repo>`
+    pub name: String,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub quota_bytes: Option<u64>,
+    #[serde(default)]
+    pub retention: Option<RetentionPolicy>,
+    /// Tag patterns that cannot be moved to another manifest once pushed (e.g. `v*`)
+    #[serde(default)]
+    pub immutable_tags: Vec<String>,
+    #[serde(default)]
+    pub default_permissions: Vec<DefaultPermission>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RepositoryList {
+    pub repositories: Vec<Repository>,
+    /// `last` of the next page, absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+}
+
+/// List repositories declared through the admin API (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/repos",
+    params(
+        ("n" = Option<usize>, Query, description = "Most entries to return (default and maximum: 1000)"),
+        ("last" = Option<String>, Query, description = "Return entries after this one, from `next` of the previous page")
+    ),
+    responses(
+        (status = 200, description = "Declared repositories by name", body = RepositoryList),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn list_repositories(
+    State(state): State<Arc<state::App>>,
+    Query(query): Query<PageQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let host = state.args.primary_host();
+
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let page = query.page(state.repositories.list(), |r| &r.name);
+    let link = pagination::next_link("/admin/v1/repos", &page);
+    let list = RepositoryList {
+        repositories: page.items,
+        next: page.next,
+    };
+    pagination::response(serde_json::to_string_pretty(&list).unwrap(), link)
+}
+
+/// Declare a repository with its visibility, quota, retention policy and default permissions
+/// (admin only)
+#[utoipa::path(
+    post,
+    path = "/admin/v1/repos",
+    request_body = CreateRepositoryRequest,
+    responses(
+        (status = 201, description = "Repository declared", body = Repository),
+        (status = 400, description = "Bad request - invalid JSON, name or unknown user"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 409, description = "Conflict - repository already declared"),
+        (status = 500, description = "Internal server error - failed to save repositories or users")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn create_repository(
+    State(state): State<Arc<state::App>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let host = state.args.primary_host();
+
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let req: CreateRepositoryRequest = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid request: {}", e)))
+                .unwrap();
+        }
+    };
+
+    // Routes address repositories as /v2/<org>/<repo>/...
+    if req.name.split('/').count() != 2 || !validation::is_valid_repository_name(&req.name) {
+        return response::name_invalid(&req.name);
+    }
+
+    // Every user must exist before anything is written
+    {
+        let users = state.users.lock().await;
+        if let Some(missing) = req
+            .default_permissions
+            .iter()
+            .find(|p| !users.iter().any(|u| u.username == p.username))
+        {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Unknown user {}", missing.username)))
+                .unwrap();
+        }
+    }
+
+    let repository = Repository {
+        name: req.name.clone(),
+        visibility: req.visibility,
+        quota_bytes: req.quota_bytes,
+        retention: req.retention,
+        pinned_tags: Default::default(),
+        tag_expiry: Default::default(),
+        immutable_tags: req.immutable_tags,
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    };
+
+    match state.repositories.insert(repository.clone()) {
+        Ok(true) => {}
+        Ok(false) => return response::conflict("Repository already exists"),
+        Err(e) => {
+            log::error!("Failed to save repositories: {}", e);
+            return response::internal_error();
+        }
+    }
+
+    if !req.default_permissions.is_empty() {
+        {
+            let mut users = state.users.lock().await;
+            *users = users
+                .drain()
+                .map(|mut u| {
+                    for p in req
+                        .default_permissions
+                        .iter()
+                        .filter(|p| p.username == u.username)
+                    {
+                        u.permissions.push(state::Permission {
+                            repository: repository.name.clone(),
+                            tag: p.tag.clone(),
+                            actions: p.actions.clone(),
+                            expires_at: None,
+                        });
+                    }
+                    u
+                })
+                .collect();
+        }
+
+        if let Err(e) = admin::save_users(&state).await {
+            log::error!("Failed to save users: {}", e);
+            return response::internal_error();
+        }
+    }
+
+    log::info!(
+        "Admin {} declared repository {}",
+        user.username,
+        repository.name
+    );
+    state.audit.record(
+        AuditEvent::new(
+            &user.username,
+            "repo.create",
+            &repository.name,
+            Outcome::Success,
+        )
+        .with_detail(serde_json::to_string(&repository).unwrap_or_default()),
+    );
+
+    Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&repository).unwrap()))
+        .unwrap()
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PinList {
+    pub repository: String,
+    /// Tags exempt from the repository's retention policy
+    pub pinned_tags: Vec<String>,
+}
+
+/// List the pinned tags of a declared repository (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/repos/{org}/{repo}/pins",
+    params(
+        ("org" = String, Path, description = "Organization name"),
+        ("repo" = String, Path, description = "Repository name")
+    ),
+    responses(
+        (status = 200, description = "Pinned tags", body = PinList),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - repository is not declared")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn list_pins(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    let host = state.args.primary_host();
+
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let name = format!("{}/{}", org, repo);
+    let Some(repository) = state.repositories.get(&name) else {
+        return response::name_unknown(&name);
+    };
+
+    let list = PinList {
+        repository: name,
+        pinned_tags: repository.pinned_tags.into_iter().collect(),
+    };
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&list).unwrap()))
+        .unwrap()
+}
+
+/// Pin a tag so retention never deletes it (admin only)
+#[utoipa::path(
+    put,
+    path = "/admin/v1/repos/{org}/{repo}/pins/{tag}",
+    params(
+        ("org" = String, Path, description = "Organization name"),
+        ("repo" = String, Path, description = "Repository name"),
+        ("tag" = String, Path, description = "Tag to pin")
+    ),
+    responses(
+        (status = 201, description = "Tag pinned"),
+        (status = 204, description = "Tag was already pinned"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - repository is not declared or tag does not exist"),
+        (status = 500, description = "Internal server error - failed to save repositories")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn pin_tag(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo, tag)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    set_pinned(&state, &headers, &org, &repo, &tag, true).await
+}
+
+/// Unpin a tag (admin only)
+#[utoipa::path(
+    delete,
+    path = "/admin/v1/repos/{org}/{repo}/pins/{tag}",
+    params(
+        ("org" = String, Path, description = "Organization name"),
+        ("repo" = String, Path, description = "Repository name"),
+        ("tag" = String, Path, description = "Tag to unpin")
+    ),
+    responses(
+        (status = 204, description = "Tag unpinned"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - repository is not declared or tag is not pinned"),
+        (status = 500, description = "Internal server error - failed to save repositories")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn unpin_tag(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo, tag)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    set_pinned(&state, &headers, &org, &repo, &tag, false).await
+}
+
+async fn set_pinned(
+    state: &Arc<state::App>,
+    headers: &HeaderMap,
+    org: &str,
+    repo: &str,
+    tag: &str,
+    pinned: bool,
+) -> Response {
+    let host = state.args.primary_host();
+
+    let user = match auth::authenticate_user(state, headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let name = format!("{}/{}", org, repo);
+    // Pins are only taken on existing tags, so a typo does not silently protect nothing
+    if pinned && !manifest_exists(org, repo, tag).await {
+        return response::manifest_unknown(tag);
+    }
+
+    let changed = match state.repositories.set_pinned(&name, tag, pinned) {
+        Ok(None) => return response::name_unknown(&name),
+        Ok(Some(changed)) => changed,
+        Err(e) => {
+            log::error!("Failed to save repositories: {}", e);
+            return response::internal_error();
+        }
+    };
+    if !changed && !pinned {
+        return response::manifest_unknown(tag);
+    }
+
+    if changed {
+        let action = if pinned { "tag.pin" } else { "tag.unpin" };
+        log::info!("Admin {} {} {}:{}", user.username, action, name, tag);
+        state.audit.record(AuditEvent::new(
+            &user.username,
+            action,
+            &format!("{}:{}", name, tag),
+            Outcome::Success,
+        ));
+    }
+
+    let status = if pinned && changed {
+        StatusCode::CREATED
+    } else {
+        StatusCode::NO_CONTENT
+    };
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+async fn manifest_exists(org: &str, repo: &str, reference: &str) -> bool {
+    let reference = reference.to_string();
+    storage::blocking("manifest_exists", org, repo, move |org, repo| {
+        storage::manifest_exists(org, repo, &reference)
+    })
+    .await
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct SetTagExpiryRequest {
+    /// Unix timestamp (seconds) at which the tag expires
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Seconds from now after which the tag expires, instead of `expires_at`
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TagExpiry {
+    pub repository: String,
+    pub tag: String,
+    /// Unix timestamp (seconds) after which the tag is no longer served and GC deletes it
+    pub expires_at: u64,
+}
+
+/// Make a tag expire, like a `grain.expires-at` annotation: past that time it is no longer
+/// served (unless `--serve-expired-manifests`) and GC deletes it (admin only)
+#[utoipa::path(
+    put,
+    path = "/admin/v1/repos/{org}/{repo}/expiry/{tag}",
+    params(
+        ("org" = String, Path, description = "Organization name"),
+        ("repo" = String, Path, description = "Repository name"),
+        ("tag" = String, Path, description = "Tag to expire")
+    ),
+    request_body = SetTagExpiryRequest,
+    responses(
+        (status = 200, description = "Expiry set", body = TagExpiry),
+        (status = 400, description = "Bad request - give exactly one of expires_at and ttl_secs"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - repository is not declared or tag does not exist"),
+        (status = 500, description = "Internal server error - failed to save repositories")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn set_tag_expiry(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo, tag)): Path<(String, String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let host = state.args.primary_host();
+
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let expires_at = match serde_json::from_slice::<SetTagExpiryRequest>(&body) {
+        Ok(SetTagExpiryRequest {
+            expires_at: Some(at),
+            ttl_secs: None,
+        }) => at,
+        Ok(SetTagExpiryRequest {
+            expires_at: None,
+            ttl_secs: Some(ttl),
+        }) => now() + ttl,
+        Ok(_) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(
+                    "Invalid request: give exactly one of expires_at and ttl_secs",
+                ))
+                .unwrap();
+        }
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid request: {}", e)))
+                .unwrap();
+        }
+    };
+
+    let name = format!("{}/{}", org, repo);
+    if !manifest_exists(&org, &repo, &tag).await {
+        return response::manifest_unknown(&tag);
+    }
+    match state.repositories.set_expiry(&name, &tag, Some(expires_at)) {
+        Ok(None) => return response::name_unknown(&name),
+        Ok(Some(_)) => {}
+        Err(e) => {
+            log::error!("Failed to save repositories: {}", e);
+            return response::internal_error();
+        }
+    }
+
+    log::info!(
+        "Admin {} set expiry of {}:{} to {}",
+        user.username,
+        name,
+        tag,
+        expires_at
+    );
+    state.audit.record(
+        AuditEvent::new(
+            &user.username,
+            "tag.expiry_set",
+            &format!("{}:{}", name, tag),
+            Outcome::Success,
+        )
+        .with_detail(format!("expires_at: {}", expires_at)),
+    );
+
+    let expiry = TagExpiry {
+        repository: name,
+        tag,
+        expires_at,
+    };
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&expiry).unwrap()))
+        .unwrap()
+}
+
+/// Remove the expiry set on a tag through the admin API; a `grain.expires-at` annotation still
+/// applies (admin only)
+#[utoipa::path(
+    delete,
+    path = "/admin/v1/repos/{org}/{repo}/expiry/{tag}",
+    params(
+        ("org" = String, Path, description = "Organization name"),
+        ("repo" = String, Path, description = "Repository name"),
+        ("tag" = String, Path, description = "Tag whose expiry is removed")
+    ),
+    responses(
+        (status = 204, description = "Expiry removed"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - repository is not declared or tag has no expiry"),
+        (status = 500, description = "Internal server error - failed to save repositories")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn clear_tag_expiry(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo, tag)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    let host = state.args.primary_host();
+
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let name = format!("{}/{}", org, repo);
+    match state.repositories.set_expiry(&name, &tag, None) {
+        Ok(None) => return response::name_unknown(&name),
+        Ok(Some(None)) => return response::manifest_unknown(&tag),
+        Ok(Some(Some(_))) => {}
+        Err(e) => {
+            log::error!("Failed to save repositories: {}", e);
+            return response::internal_error();
+        }
+    }
+
+    log::info!("Admin {} cleared expiry of {}:{}", user.username, name, tag);
+    state.audit.record(AuditEvent::new(
+        &user.username,
+        "tag.expiry_clear",
+        &format!("{}:{}", name, tag),
+        Outcome::Success,
+    ));
+
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Current counts of a repository next to the limits that apply to it
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RepositoryStats {
+    pub repository: String,
+    pub tag_count: usize,
+    /// Manifests stored by digest, tagged or not
+    pub manifest_count: usize,
+    pub blob_count: usize,
+    /// Total size of the repository's blobs, in bytes
+    pub total_size: u64,
+    /// Unix time of the newest stored manifest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_pushed_at: Option<u64>,
+    /// Unix time of the last manifest pull since this instance started, absent when none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_pulled_at: Option<u64>,
+    /// `--max-tags-per-repo`, absent when unlimited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tags: Option<usize>,
+    /// `--max-layers-per-manifest`, absent when unlimited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_layers_per_manifest: Option<usize>,
+}
+
+/// Repository statistics (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/repos/{org}/{repo}/stats",
+    params(
+        ("org" = String, Path, description = "Organization name"),
+        ("repo" = String, Path, description = "Repository name")
+    ),
+    responses(
+        (status = 200, description = "Repository statistics", body = RepositoryStats),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - repository has no manifests"),
+        (status = 500, description = "Internal server error - failed to read storage")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn repository_stats(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    let host = state.args.primary_host();
+
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let name = format!("{}/{}", org, repo);
+    let listing = storage::blocking("repository_stats", &org, &repo, |org, repo| {
+        let references = storage::list_references(org, repo)?;
+        if references.is_empty() {
+            return Ok(None);
+        }
+        let tags = storage::list_tags(org, repo)?;
+        let usage = storage::repository_blob_usage(org, repo)?;
+        let last_pushed_at = references
+            .iter()
+            .filter_map(|reference| storage::manifest_metadata(org, repo, reference).ok())
+            .filter_map(|meta| meta.modified.duration_since(UNIX_EPOCH).ok())
+            .map(|age| age.as_secs())
+            .max();
+        Ok::<_, std::io::Error>(Some((references.len(), tags.len(), usage, last_pushed_at)))
+    })
+    .await;
+    let (references, tags, (blob_count, total_size), last_pushed_at) = match listing {
+        Ok(Some(listing)) => listing,
+        Ok(None) => return response::name_unknown(&name),
+        Err(e) => {
+            log::error!("Failed to read the contents of {}: {}", name, e);
+            return response::internal_error();
+        }
+    };
+
+    let limit = |value: usize| (value > 0).then_some(value);
+    let stats = RepositoryStats {
+        last_pulled_at: state.activity.last_pull(&name),
+        repository: name,
+        tag_count: tags,
+        manifest_count: references - tags,
+        blob_count,
+        total_size,
+        last_pushed_at,
+        max_tags: limit(state.args.max_tags_per_repo),
+        max_layers_per_manifest: limit(state.args.max_layers_per_manifest),
+    };
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&stats).unwrap()))
+        .unwrap()
+}
@@ -0,0 +1,393 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::RwLock,
+    time::{Duration, SystemTime},
+};
+use utoipa::ToSchema;
+
+use crate::permissions;
+
+/// Who may pull from a declared repository
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    /// Pulls require a user with pull permission
+    #[default]
+    Private,
+    /// Anyone may pull, including anonymous clients; pushes still require permission
+    Public,
+}
+
+/// Tags removed by garbage collection. Tags matching `tags` beyond the `keep_last` most recent,
+/// or older than `max_age_days`, are deleted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct RetentionPolicy {
+    /// Tag pattern the policy applies to (default: all tags)
+    #[serde(default = "default_tag_pattern")]
+    pub tags: String,
+    /// Number of most recently pushed matching tags to keep
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_last: Option<usize>,
+    /// Age in days after which matching tags are deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_days: Option<u64>,
+}
+
+/// Manifest annotation exempting an image from retention and untagged manifest collection
+pub const KEEP_ANNOTATION: &str = "grain.keep";
+
+/// Whether a manifest is annotated with `grain.keep=true`
+pub fn has_keep_annotation(manifest: &[u8]) -> bool {
+    serde_json::from_slice::<serde_json::Value>(manifest).is_ok_and(|m| {
+        m.get("annotations")
+            .and_then(|a| a.get(KEEP_ANNOTATION))
+            .and_then(|v| v.as_str())
+            == Some("true")
+    })
+}
+
+/// Manifest annotation giving the time after which an image expires, as Unix seconds or an
+/// RFC 3339 timestamp
+pub const EXPIRES_AT_ANNOTATION: &str = "grain.expires-at";
+
+/// Unix time from a manifest's `grain.expires-at` annotation; unparsable values are ignored
+pub fn expires_at_annotation(manifest: &[u8]) -> Option<u64> {
+    let manifest = serde_json::from_slice::<serde_json::Value>(manifest).ok()?;
+    let value = manifest
+        .get("annotations")?
+        .get(EXPIRES_AT_ANNOTATION)?
+        .as_str()?;
+    value.parse().ok().or_else(|| {
+        time::OffsetDateTime::parse(value, &time::format_description::well_known::Rfc3339)
+            .ok()
+            .map(|at| at.unix_timestamp().max(0) as u64)
+    })
+}
+
+/// When a manifest expires: the earlier of its annotation and, for a tag of a declared
+/// repository, the expiry set through the admin API
+pub(crate) fn expires_at(
+    repository: Option<&Repository>,
+    tag: Option<&str>,
+    manifest: &[u8],
+) -> Option<u64> {
+    expiry(repository, tag, expires_at_annotation(manifest))
+}
+
+/// `expires_at` of a manifest whose annotation has already been read
+pub(crate) fn expiry(
+    repository: Option<&Repository>,
+    tag: Option<&str>,
+    annotation: Option<u64>,
+) -> Option<u64> {
+    let set = repository
+        .zip(tag)
+        .and_then(|(repository, tag)| repository.tag_expiry.get(tag).copied());
+    match (annotation, set) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Whether `tag` is immutable, by a registry-wide pattern or one of the repository's
+pub(crate) fn is_immutable(
+    patterns: &[String],
+    repository: Option<&Repository>,
+    tag: &str,
+) -> bool {
+    patterns
+        .iter()
+        .chain(repository.into_iter().flat_map(|r| &r.immutable_tags))
+        .any(|pattern| permissions::matches_pattern(pattern, tag))
+}
+
+fn default_tag_pattern() -> String {
+    "*".to_string()
+}
+
+impl RetentionPolicy {
+    /// Tags to delete, given every tag of the repository with its push time
+    pub fn expired_tags(&self, tags: &[(String, SystemTime)], now: SystemTime) -> Vec<String> {
+        let mut matching: Vec<&(String, SystemTime)> = tags
+            .iter()
+            .filter(|(tag, _)| permissions::matches_pattern(&self.tags, tag))
+            .collect();
+        // Newest first
+        matching.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let max_age = self
+            .max_age_days
+            .map(|days| Duration::from_secs(days * 24 * 3600));
+
+        matching
+            .into_iter()
+            .enumerate()
+            .filter(|(index, (_, pushed))| {
+                self.keep_last.is_some_and(|keep| *index >= keep)
+                    || max_age.is_some_and(|max_age| {
+                        now.duration_since(*pushed).unwrap_or_default() > max_age
+                    })
+            })
+            .map(|(_, (tag, _))| tag.clone())
+            .collect()
+    }
+}
+
+/// A repository declared through `POST /admin/repos`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Repository {
+    /// `<org>/<repo>`
+    pub name: String,
+    #[serde(default)]
+    pub visibility: Visibility,
+    /// Largest total size of the repository's blobs, in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<RetentionPolicy>,
+    /// Tags retention never deletes
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub pinned_tags: BTreeSet<String>,
+    /// Unix time (seconds) at which a tag expires, set through the admin API
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tag_expiry: BTreeMap<String, u64>,
+    /// Tag patterns (`*` wildcards) that cannot be moved to another manifest once pushed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub immutable_tags: Vec<String>,
+    /// Unix timestamp (seconds) of the declaration
+    pub created_at: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RepositoriesFile {
+    repositories: Vec<Repository>,
+}
+
+/// Declared repositories, persisted to `--repositories-file`
+pub(crate) struct Repositories {
+    path: String,
+    entries: RwLock<BTreeMap<String, Repository>>,
+}
+
+impl Repositories {
+    pub(crate) fn load(path: &str) -> Result<Self, String> {
+        let entries = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str::<RepositoriesFile>(&content)
+                .map_err(|e| format!("failed to parse repositories file {}: {}", path, e))?
+                .repositories
+                .into_iter()
+                .map(|r| (r.name.clone(), r))
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(format!("failed to read repositories file {}: {}", path, e)),
+        };
+
+        log::info!("Loaded {} declared repositories", entries.len());
+        Ok(Self {
+            path: path.to_string(),
+            entries: RwLock::new(entries),
+        })
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<Repository> {
+        self.entries.read().unwrap().get(name).cloned()
+    }
+
+    pub(crate) fn is_declared(&self, name: &str) -> bool {
+        self.entries.read().unwrap().contains_key(name)
+    }
+
+    pub(crate) fn is_public(&self, name: &str) -> bool {
+        self.get(name)
+            .is_some_and(|r| r.visibility == Visibility::Public)
+    }
+
+    pub(crate) fn list(&self) -> Vec<Repository> {
+        self.entries.read().unwrap().values().cloned().collect()
+    }
+
+    /// Declare a repository and persist the file; `Ok(false)` if it was already declared
+    pub(crate) fn insert(&self, repository: Repository) -> Result<bool, std::io::Error> {
+        let mut entries = self.entries.write().unwrap();
+        if entries.contains_key(&repository.name) {
+            return Ok(false);
+        }
+        entries.insert(repository.name.clone(), repository.clone());
+
+        if let Err(e) = self.save(&entries) {
+            entries.remove(&repository.name);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Pin or unpin a tag and persist the file; `None` if the repository is not declared,
+    /// otherwise whether the pins changed
+    pub(crate) fn set_pinned(
+        &self,
+        name: &str,
+        tag: &str,
+        pinned: bool,
+    ) -> Result<Option<bool>, std::io::Error> {
+        let mut entries = self.entries.write().unwrap();
+        let Some(repository) = entries.get_mut(name) else {
+            return Ok(None);
+        };
+        let changed = if pinned {
+            repository.pinned_tags.insert(tag.to_string())
+        } else {
+            repository.pinned_tags.remove(tag)
+        };
+        if !changed {
+            return Ok(Some(false));
+        }
+
+        if let Err(e) = self.save(&entries) {
+            // Undo the change so memory matches the file
+            let repository = entries.get_mut(name).expect("repository checked above");
+            if pinned {
+                repository.pinned_tags.remove(tag);
+            } else {
+                repository.pinned_tags.insert(tag.to_string());
+            }
+            return Err(e);
+        }
+        Ok(Some(true))
+    }
+
+    /// Set or clear when a tag expires and persist the file; `None` if the repository is not
+    /// declared, otherwise the previous expiry
+    pub(crate) fn set_expiry(
+        &self,
+        name: &str,
+        tag: &str,
+        expires_at: Option<u64>,
+    ) -> Result<Option<Option<u64>>, std::io::Error> {
+        let mut entries = self.entries.write().unwrap();
+        let Some(repository) = entries.get_mut(name) else {
+            return Ok(None);
+        };
+        let previous = match expires_at {
+            Some(at) => repository.tag_expiry.insert(tag.to_string(), at),
+            None => repository.tag_expiry.remove(tag),
+        };
+        if previous == expires_at {
+            return Ok(Some(previous));
+        }
+
+        if let Err(e) = self.save(&entries) {
+            // Undo the change so memory matches the file
+            let repository = entries.get_mut(name).expect("repository checked above");
+            match previous {
+                Some(at) => repository.tag_expiry.insert(tag.to_string(), at),
+                None => repository.tag_expiry.remove(tag),
+            };
+            return Err(e);
+        }
+        Ok(Some(previous))
+    }
+
+    fn save(&self, entries: &BTreeMap<String, Repository>) -> Result<(), std::io::Error> {
+        let file = RepositoriesFile {
+            repositories: entries.values().cloned().collect(),
+        };
+        std::fs::write(&self.path, serde_json::to_string_pretty(&file)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn at(days: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(days * 24 * 3600)
+    }
+
+    #[test]
+    fn test_expired_tags() {
+        let tags = vec![
+            ("v1".to_string(), at(1)),
+            ("v2".to_string(), at(5)),
+            ("v3".to_string(), at(9)),
+            ("latest".to_string(), at(2)),
+        ];
+
+        let keep_last = RetentionPolicy {
+            tags: "v*".to_string(),
+            keep_last: Some(2),
+            max_age_days: None,
+        };
+        assert_eq!(keep_last.expired_tags(&tags, at(10)), vec!["v1"]);
+
+        let max_age = RetentionPolicy {
+            tags: "*".to_string(),
+            keep_last: None,
+            max_age_days: Some(6),
+        };
+        assert_eq!(max_age.expired_tags(&tags, at(10)), vec!["latest", "v1"]);
+
+        let none = RetentionPolicy {
+            tags: "*".to_string(),
+            keep_last: None,
+            max_age_days: None,
+        };
+        assert!(none.expired_tags(&tags, at(10)).is_empty());
+    }
+
+    #[test]
+    fn test_has_keep_annotation() {
+        assert!(has_keep_annotation(
+            br#"{"annotations":{"grain.keep":"true"}}"#
+        ));
+        assert!(!has_keep_annotation(
+            br#"{"annotations":{"grain.keep":"false"}}"#
+        ));
+        assert!(!has_keep_annotation(br#"{"schemaVersion":2}"#));
+    }
+
+    #[test]
+    fn test_expires_at() {
+        assert_eq!(
+            expires_at_annotation(br#"{"annotations":{"grain.expires-at":"1767225600"}}"#),
+            Some(1_767_225_600)
+        );
+        assert_eq!(
+            expires_at_annotation(
+                br#"{"annotations":{"grain.expires-at":"2026-01-01T00:00:00Z"}}"#
+            ),
+            Some(1_767_225_600)
+        );
+        assert_eq!(
+            expires_at_annotation(br#"{"annotations":{"grain.expires-at":"soon"}}"#),
+            None
+        );
+
+        let manifest = br#"{"annotations":{"grain.expires-at":"2000"}}"#;
+        let mut repository = Repository {
+            name: "org/repo".to_string(),
+            visibility: Visibility::Private,
+            quota_bytes: None,
+            retention: None,
+            pinned_tags: BTreeSet::new(),
+            tag_expiry: BTreeMap::new(),
+            immutable_tags: Vec::new(),
+            created_at: 0,
+        };
+        repository.tag_expiry.insert("pr-1".to_string(), 1000);
+        assert_eq!(
+            expires_at(Some(&repository), Some("pr-1"), manifest),
+            Some(1000)
+        );
+        assert_eq!(
+            expires_at(Some(&repository), Some("pr-2"), manifest),
+            Some(2000)
+        );
+        assert_eq!(
+            expires_at(Some(&repository), Some("pr-1"), b"{}"),
+            Some(1000)
+        );
+        assert_eq!(expires_at(None, Some("pr-1"), b"{}"), None);
+    }
+}
@@ -1,14 +1,180 @@
+use crate::access_log::{self, TrustedProxy};
+use crate::args::Args;
 use crate::errors::{ErrorCode, OciErrorResponse};
-use axum::{body::Body, http::Response, http::StatusCode, response::IntoResponse};
+use crate::storage::StorageError;
+use axum::{
+    body::Body,
+    http::{HeaderMap, Response, StatusCode},
+    response::IntoResponse,
+};
+use std::net::IpAddr;
+use std::sync::OnceLock;
+use std::time::{Duration, UNIX_EPOCH};
 
+/// Parameters of the Bearer challenge sent with every 401 once token auth is enabled
+struct BearerChallenge {
+    /// `--token-realm`, or else the token endpoint under the request's origin
+    realm: Option<String>,
+    service: String,
+    /// Origin used outside a request, from `--external-url` or the listen address
+    fallback_origin: String,
+}
+
+static BEARER_CHALLENGE: OnceLock<BearerChallenge> = OnceLock::new();
+
+tokio::task_local! {
+    /// Public origin of the request being handled, for the realms `unauthorized` advertises
+    static ORIGIN: String;
+}
+
+/// Advertise the token endpoint in `unauthorized` responses, ahead of the Basic challenge
+pub(crate) fn advertise_bearer(args: &Args) {
+    let _ = BEARER_CHALLENGE.set(BearerChallenge {
+        realm: args.token_realm.clone(),
+        service: args.token_service.clone(),
+        fallback_origin: origin(args, &HeaderMap::new()),
+    });
+}
+
+/// Handle a request with its public `origin`, so challenges point clients at a URL they reach
+pub(crate) async fn with_origin<F: std::future::Future>(origin: String, handling: F) -> F::Output {
+    ORIGIN.scope(origin, handling).await
+}
+
+/// Absolute URL of `path` for `Location` headers
+pub(crate) fn location(args: &Args, headers: &HeaderMap, path: &str) -> String {
+    format!("{}{}", origin(args, headers), path)
+}
+
+/// Scheme and host clients address the registry by: `--external-url` when set, or else the scheme
+/// and host a trusted proxy forwarded (`Forwarded`, then `X-Forwarded-Proto`/`-Host`), falling
+/// back to `--url-scheme` and the `Host` the client addressed (the listen address when it sent
+/// none). `middleware::drop_untrusted_forwarding` removes forwarding headers from other peers,
+/// and of those a trusted chain sends, only the entry added by the proxy the client reached
+/// counts; the client can forge any entry left of it.
+pub(crate) fn origin(args: &Args, headers: &HeaderMap) -> String {
+    if let Some(url) = &args.external_url {
+        return url.clone();
+    }
+    let trusted = &args.trusted_proxies;
+    let forwarded = forwarded_element(headers, trusted);
+    let scheme = forwarded
+        .and_then(|element| forwarded_param(element, "proto"))
+        .or_else(|| forwarded_value(headers, "x-forwarded-proto", trusted))
+        .and_then(|proto| {
+            ["http", "https"]
+                .into_iter()
+                .find(|s| s.eq_ignore_ascii_case(proto))
+        })
+        .unwrap_or(&args.url_scheme);
+    let host = forwarded
+        .and_then(|element| forwarded_param(element, "host"))
+        .filter(|host| is_authority(host))
+        .or_else(|| {
+            forwarded_value(headers, "x-forwarded-host", trusted).filter(|host| is_authority(host))
+        })
+        .or_else(|| {
+            headers
+                .get("host")
+                .and_then(|v| v.to_str().ok())
+                .filter(|host| is_authority(host))
+        })
+        .unwrap_or(args.primary_host());
+    format!("{}://{}", scheme, host)
+}
+
+/// `host[:port]` of an origin from `origin`, without its scheme or any `--external-url` path
+pub(crate) fn authority(origin: &str) -> &str {
+    let rest = origin.split_once("://").map_or(origin, |(_, rest)| rest);
+    rest.split('/').next().unwrap_or(rest)
+}
+
+/// `host[:port]` with nothing else a URL could smuggle in (user info, path, query)
+fn is_authority(host: &str) -> bool {
+    !host.contains('@') && host.parse::<axum::http::uri::Authority>().is_ok()
+}
+
+/// Entries of every `name` header, split at commas
+fn entries<'a>(headers: &'a HeaderMap, name: &str) -> Vec<&'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect()
+}
+
+/// Entry of an `X-Forwarded-*` header added by the proxy the client reached: each trusted proxy
+/// after it in `X-Forwarded-For` appended one more entry on the right
+fn forwarded_value<'a>(
+    headers: &'a HeaderMap,
+    name: &str,
+    trusted: &[TrustedProxy],
+) -> Option<&'a str> {
+    let hops: Vec<Option<IpAddr>> = entries(headers, "x-forwarded-for")
+        .into_iter()
+        .map(|hop| hop.parse().ok())
+        .collect();
+    let behind = access_log::nearest_untrusted(trusted, &hops).map_or(0, |i| hops.len() - 1 - i);
+    let values = entries(headers, name);
+    let index = values.len().saturating_sub(behind + 1);
+    values.get(index).copied().filter(|v| !v.is_empty())
+}
+
+/// Element of the RFC 7239 `Forwarded` header added by the proxy the client reached: the
+/// rightmost whose `for` is not a trusted proxy
+fn forwarded_element<'a>(headers: &'a HeaderMap, trusted: &[TrustedProxy]) -> Option<&'a str> {
+    let elements = entries(headers, "forwarded");
+    let hops: Vec<Option<IpAddr>> = elements
+        .iter()
+        .map(|element| forwarded_param(element, "for").and_then(node_ip))
+        .collect();
+    access_log::nearest_untrusted(trusted, &hops).map(|i| elements[i])
+}
+
+/// Address of a `Forwarded` node: `192.0.2.1`, `192.0.2.1:8080` or `[2001:db8::1]:8080`
+fn node_ip(node: &str) -> Option<IpAddr> {
+    match node.strip_prefix('[') {
+        Some(v6) => v6.split(']').next()?.parse().ok(),
+        None => node.split(':').next()?.parse().ok(),
+    }
+}
+
+/// Parameter of one `Forwarded` element
+fn forwarded_param<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    element
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim().trim_matches('"'))
+        .filter(|value| !value.is_empty())
+}
+
+/// `401 UNAUTHORIZED` with challenges naming the request's origin, or `host` outside a request
 pub(crate) fn unauthorized(host: &str) -> Response<Body> {
     let error = OciErrorResponse::new(ErrorCode::Unauthorized, "authentication required");
+    let origin = ORIGIN.try_with(Clone::clone).ok();
 
-    Response::builder()
-        .status(StatusCode::UNAUTHORIZED)
+    let mut builder = Response::builder().status(StatusCode::UNAUTHORIZED);
+    if let Some(challenge) = BEARER_CHALLENGE.get() {
+        let realm = challenge.realm.clone().unwrap_or_else(|| {
+            let origin = origin.as_deref().unwrap_or(&challenge.fallback_origin);
+            format!("{}/token", origin)
+        });
+        builder = builder.header(
+            "WWW-Authenticate",
+            format!(
+                "Bearer realm=\"{}\",service=\"{}\"",
+                realm, challenge.service
+            ),
+        );
+    }
+    let realm_host = origin.as_deref().map_or(host, authority);
+    builder
         .header(
             "WWW-Authenticate",
-            format!("Basic realm=\"{}\", charset=\"UTF-8\"", host),
+            format!("Basic realm=\"{}\", charset=\"UTF-8\"", realm_host),
         )
         .header("Content-Type", "application/json")
         .body(Body::from(serde_json::to_string(&error).unwrap_or_else(
@@ -25,6 +191,21 @@ pub(crate) fn forbidden() -> Response<Body> {
         .into_response()
 }
 
+/// `unauthorized` for HEAD requests: same status and headers, no body
+pub(crate) fn unauthorized_head(host: &str) -> Response<Body> {
+    without_body(unauthorized(host))
+}
+
+/// `forbidden` for HEAD requests: same status and headers, no body
+pub(crate) fn forbidden_head() -> Response<Body> {
+    without_body(forbidden())
+}
+
+fn without_body(response: Response<Body>) -> Response<Body> {
+    let (parts, _) = response.into_parts();
+    Response::from_parts(parts, Body::empty())
+}
+
 pub(crate) fn not_found() -> Response<Body> {
     OciErrorResponse::new(ErrorCode::BlobUnknown, "resource not found").into_response()
 }
@@ -47,6 +228,33 @@ pub(crate) fn manifest_unknown(reference: &str) -> Response<Body> {
     .into_response()
 }
 
+/// A manifest past its expiry, no longer served
+pub(crate) fn manifest_expired(reference: &str, expires_at: u64) -> Response<Body> {
+    OciErrorResponse::with_detail(
+        ErrorCode::ManifestUnknown,
+        "manifest expired",
+        format!("reference: {}, expired at: {}", reference, expires_at),
+    )
+    .into_response()
+}
+
+pub(crate) fn tag_invalid(tag: &str) -> Response<Body> {
+    OciErrorResponse::with_detail(ErrorCode::TagInvalid, "invalid tag", tag).into_response()
+}
+
+/// Refusal to move an immutable tag to another manifest
+pub(crate) fn tag_immutable(tag: &str, current: &str) -> Response<Body> {
+    OciErrorResponse::with_detail(
+        ErrorCode::TagInvalid,
+        "tag is immutable",
+        format!(
+            "{} already points to {}; push under a new tag",
+            tag, current
+        ),
+    )
+    .to_response(StatusCode::CONFLICT)
+}
+
 pub(crate) fn digest_invalid(digest: &str) -> Response<Body> {
     OciErrorResponse::with_detail(
         ErrorCode::DigestInvalid,
@@ -61,12 +269,36 @@ pub(crate) fn manifest_invalid(reason: &str) -> Response<Body> {
         .into_response()
 }
 
-#[allow(dead_code)]
 pub(crate) fn name_invalid(name: &str) -> Response<Body> {
     OciErrorResponse::with_detail(ErrorCode::NameInvalid, "invalid repository name", name)
         .into_response()
 }
 
+pub(crate) fn name_unknown(name: &str) -> Response<Body> {
+    OciErrorResponse::with_detail(
+        ErrorCode::NameUnknown,
+        "repository name not known to registry",
+        name,
+    )
+    .into_response()
+}
+
+/// Error for any request on an archived repository, telling the client how to get it back
+pub(crate) fn repository_archived(name: &str, archived_at: u64) -> Response<Body> {
+    let archived_at = UNIX_EPOCH + Duration::from_secs(archived_at);
+    OciErrorResponse::with_detail(
+        ErrorCode::NameUnknown,
+        "repository is archived",
+        format!(
+            "{} was archived on {}; ask an administrator to restore it (POST /admin/v1/repos/{}/restore)",
+            name,
+            httpdate::fmt_http_date(archived_at),
+            name
+        ),
+    )
+    .into_response()
+}
+
 pub(crate) fn blob_upload_unknown(uuid: &str) -> Response<Body> {
     OciErrorResponse::with_detail(
         ErrorCode::BlobUploadUnknown,
@@ -76,6 +308,83 @@ pub(crate) fn blob_upload_unknown(uuid: &str) -> Response<Body> {
     .into_response()
 }
 
+pub(crate) fn blob_upload_invalid(reason: &str) -> Response<Body> {
+    OciErrorResponse::with_detail(ErrorCode::BlobUploadInvalid, "blob upload invalid", reason)
+        .into_response()
+}
+
+pub(crate) fn payload_too_large(limit: u64) -> Response<Body> {
+    let mut response = OciErrorResponse::with_detail(
+        ErrorCode::SizeInvalid,
+        "request body too large",
+        format!("limit: {} bytes", limit),
+    )
+    .into_response();
+    *response.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+    response
+}
+
+pub(crate) fn quota_exceeded(reason: &str) -> Response<Body> {
+    OciErrorResponse::with_detail(ErrorCode::Denied, "repository quota exceeded", reason)
+        .into_response()
+}
+
+pub(crate) fn limit_exceeded(reason: &str) -> Response<Body> {
+    OciErrorResponse::with_detail(ErrorCode::Denied, "repository limit exceeded", reason)
+        .into_response()
+}
+
+pub(crate) fn download_url_invalid(reason: &str) -> Response<Body> {
+    OciErrorResponse::with_detail(ErrorCode::Denied, "download URL refused", reason).into_response()
+}
+
+pub(crate) fn too_many_requests(reason: &str) -> Response<Body> {
+    OciErrorResponse::with_detail(ErrorCode::TooManyRequests, "too many requests", reason)
+        .into_response()
+}
+
+pub(crate) fn range_not_satisfiable(size: u64) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::RANGE_NOT_SATISFIABLE)
+        .header("Content-Range", format!("bytes */{}", size))
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Whether `If-None-Match` lists `etag` or `*`. Weak tags match too, as RFC 9110 compares
+/// them weakly for this header.
+pub(crate) fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all("if-none-match")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// `304 Not Modified` for a client whose cached copy of the content with `digest` is current
+pub(crate) fn not_modified(etag: &str, digest: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_MODIFIED)
+        .header("ETag", etag)
+        .header("Docker-Content-Digest", digest)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Error for an endpoint turned off with `--disable-capabilities`
+pub(crate) fn capability_disabled(status: StatusCode, capability: &str) -> Response<Body> {
+    let mut response = OciErrorResponse::with_detail(
+        ErrorCode::Unsupported,
+        "operation not supported by this registry",
+        format!("{} is disabled", capability),
+    )
+    .into_response();
+    *response.status_mut() = status;
+    response
+}
+
 pub(crate) fn internal_error() -> Response<Body> {
     Response::builder()
         .status(StatusCode::INTERNAL_SERVER_ERROR)
@@ -86,6 +395,18 @@ pub(crate) fn internal_error() -> Response<Body> {
         .unwrap()
 }
 
+/// OCI error for a failed storage operation
+pub(crate) fn storage_error(e: &StorageError) -> Response<Body> {
+    match e {
+        StorageError::BlobNotFound(digest) => blob_unknown(&digest.to_string()),
+        StorageError::ManifestNotFound(reference) => manifest_unknown(reference),
+        StorageError::UploadNotFound(uuid) => blob_upload_unknown(uuid),
+        StorageError::DigestMismatch { expected, .. } => digest_invalid(&expected.to_string()),
+        StorageError::QuotaExceeded(reason) => quota_exceeded(reason),
+        StorageError::Cancelled | StorageError::Io(_) => internal_error(),
+    }
+}
+
 pub(crate) fn conflict(message: &str) -> Response<Body> {
     Response::builder()
         .status(StatusCode::CONFLICT)
@@ -96,3 +417,216 @@ pub(crate) fn conflict(message: &str) -> Response<Body> {
         )))
         .unwrap()
 }
+
+pub(crate) fn unprocessable(message: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::UNPROCESSABLE_ENTITY)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::json!({"errors": [{"code": "UNSUPPORTED", "message": message}]})
+                .to_string(),
+        ))
+        .unwrap()
+}
+
+pub(crate) fn token_request_invalid(reason: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::json!({"errors": [{"code": "UNSUPPORTED", "message": "token request invalid", "detail": reason}]})
+                .to_string(),
+        ))
+        .unwrap()
+}
+
+pub(crate) fn password_rejected(violations: &[String]) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::json!({
+                "error": "password does not meet policy",
+                "violations": violations,
+            })
+            .to_string(),
+        ))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body_len(response: Response<Body>) -> usize {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .len()
+    }
+
+    #[tokio::test]
+    async fn test_head_variants_match_get() {
+        let pairs = [
+            (
+                unauthorized("localhost:8888"),
+                unauthorized_head("localhost:8888"),
+            ),
+            (forbidden(), forbidden_head()),
+        ];
+
+        for (get, head) in pairs {
+            assert_eq!(get.status(), head.status());
+            assert_eq!(get.headers(), head.headers());
+            assert!(body_len(get).await > 0);
+            assert_eq!(body_len(head).await, 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized_realm_follows_origin() {
+        let realm = |response: Response<Body>| {
+            response.headers()["WWW-Authenticate"]
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(
+            realm(unauthorized("0.0.0.0:8888")),
+            "Basic realm=\"0.0.0.0:8888\", charset=\"UTF-8\""
+        );
+        let scoped = with_origin("https://registry.example.com:5000".to_string(), async {
+            unauthorized("0.0.0.0:8888")
+        })
+        .await;
+        assert_eq!(
+            realm(scoped),
+            "Basic realm=\"registry.example.com:5000\", charset=\"UTF-8\""
+        );
+    }
+
+    #[test]
+    fn test_location() {
+        use clap::Parser;
+
+        let args = Args::parse_from(["grain", "--host", "127.0.0.1:8888"]);
+        let location = |pairs: &[(&'static str, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, value.parse().unwrap());
+            }
+            location(&args, &headers, "/v2/")
+        };
+
+        assert_eq!(location(&[]), "http://127.0.0.1:8888/v2/");
+        assert_eq!(
+            location(&[("host", "registry.local")]),
+            "http://registry.local/v2/"
+        );
+        // Without X-Forwarded-For, the entry the nearest proxy appended counts
+        assert_eq!(
+            location(&[
+                ("host", "10.0.0.5:8888"),
+                ("x-forwarded-proto", "https"),
+                ("x-forwarded-host", "evil.example.com, registry.example.com"),
+            ]),
+            "https://registry.example.com/v2/"
+        );
+        // Forwarded takes precedence
+        assert_eq!(
+            location(&[
+                ("x-forwarded-host", "ignored.example.com"),
+                (
+                    "forwarded",
+                    "for=1.2.3.4;proto=http;host=evil.example.com, for=5.6.7.8;proto=HTTPS;host=\"registry.example.com:443\""
+                ),
+            ]),
+            "https://registry.example.com:443/v2/"
+        );
+        assert_eq!(
+            location(&[("x-forwarded-proto", "gopher")]),
+            "http://127.0.0.1:8888/v2/"
+        );
+        // Hosts that are not a bare authority fall back to the next source
+        assert_eq!(
+            location(&[
+                ("host", "registry.local"),
+                ("x-forwarded-host", "evil.example.com/path"),
+            ]),
+            "http://registry.local/v2/"
+        );
+        assert_eq!(
+            location(&[("forwarded", "host=user@evil.example.com")]),
+            "http://127.0.0.1:8888/v2/"
+        );
+
+        // Behind a chain of trusted proxies, the entry of the one the client reached counts
+        let args = Args::parse_from(["grain", "--trusted-proxies", "10.0.0.0/8"]);
+        let location = |pairs: &[(&'static str, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, value.parse().unwrap());
+            }
+            super::location(&args, &headers, "/v2/")
+        };
+        assert_eq!(
+            location(&[
+                ("x-forwarded-for", "203.0.113.7, 10.0.0.2"),
+                (
+                    "x-forwarded-host",
+                    "evil.example.com, registry.example.com, internal:8080"
+                ),
+            ]),
+            "http://registry.example.com/v2/"
+        );
+        assert_eq!(
+            location(&[(
+                "forwarded",
+                "for=203.0.113.7;host=evil.example.com, for=\"198.51.100.7:4711\";proto=https;host=registry.example.com, for=\"[::ffff:10.0.0.2]\";host=internal:8080"
+            )]),
+            "https://registry.example.com/v2/"
+        );
+
+        let args = Args::parse_from(["grain", "--external-url", "https://registry.example.com/"]);
+        assert_eq!(
+            super::location(&args, &HeaderMap::new(), "/v2/"),
+            "https://registry.example.com/v2/"
+        );
+    }
+
+    #[test]
+    fn test_storage_error_mapping() {
+        let digest = crate::digest::Digest::parse(&format!("sha256:{}", "a".repeat(64))).unwrap();
+        let cases = [
+            (
+                StorageError::BlobNotFound(digest.clone()),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                StorageError::UploadNotFound("uuid".to_string()),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                StorageError::DigestMismatch {
+                    expected: digest.clone(),
+                    actual: digest,
+                },
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                StorageError::QuotaExceeded("full".to_string()),
+                StatusCode::FORBIDDEN,
+            ),
+            (StorageError::Cancelled, StatusCode::INTERNAL_SERVER_ERROR),
+            (
+                std::io::Error::other("disk").into(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ];
+
+        for (error, status) in cases {
+            assert_eq!(storage_error(&error).status(), status, "{}", error);
+        }
+    }
+}
@@ -0,0 +1,502 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use utoipa::ToSchema;
+
+use crate::{
+    admin,
+    audit::{AuditEvent, Outcome},
+    auth,
+    dry_run::{self, DryRunQuery, DryRunResult, PlannedChange},
+    pagination::{self, PageQuery},
+    response,
+    state::{self, Permission, User},
+};
+
+/// Robots authenticate as `robot$<name>`; users file accounts cannot log in with this prefix
+pub(crate) const USERNAME_PREFIX: &str = "robot$";
+
+/// A robot account: a named, revocable API token scoped to a permission set
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Robot {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    pub permissions: Vec<Permission>,
+    /// Admin who minted the token
+    pub created_by: String,
+    /// Unix timestamp (seconds) of creation
+    pub created_at: u64,
+    /// Unix timestamp (seconds) after which the token is refused
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+impl Robot {
+    /// A robot that may only pull from `repository`
+    pub(crate) fn puller(name: &str, repository: &str, created_by: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: format!("pulls {}", repository),
+            permissions: vec![Permission {
+                repository: repository.to_string(),
+                tag: "*".to_string(),
+                actions: vec!["pull".to_string()],
+                expires_at: None,
+            }],
+            created_by: created_by.to_string(),
+            created_at: now(),
+            expires_at: None,
+        }
+    }
+
+    pub(crate) fn username(&self) -> String {
+        format!("{}{}", USERNAME_PREFIX, self.name)
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// A robot as persisted: its token is only kept as a SHA-256 hash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RobotEntry {
+    #[serde(flatten)]
+    robot: Robot,
+    token_sha256: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RobotsFile {
+    robots: Vec<RobotEntry>,
+}
+
+/// Robot accounts, persisted to `--robots-file`
+pub(crate) struct Robots {
+    path: String,
+    entries: RwLock<BTreeMap<String, RobotEntry>>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl Robots {
+    pub(crate) fn load(path: &str) -> Result<Self, String> {
+        let entries = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str::<RobotsFile>(&content)
+                .map_err(|e| format!("failed to parse robots file {}: {}", path, e))?
+                .robots
+                .into_iter()
+                .map(|r| (r.robot.name.clone(), r))
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(format!("failed to read robots file {}: {}", path, e)),
+        };
+
+        log::info!("Loaded {} robot accounts", entries.len());
+        Ok(Self {
+            path: path.to_string(),
+            entries: RwLock::new(entries),
+        })
+    }
+
+    pub(crate) fn list(&self) -> Vec<Robot> {
+        self.entries
+            .read()
+            .unwrap()
+            .values()
+            .map(|e| e.robot.clone())
+            .collect()
+    }
+
+    /// Store a robot with a new token and return the token; `Ok(None)` if the name is taken
+    pub(crate) fn create(&self, robot: Robot) -> Result<Option<String>, std::io::Error> {
+        let mut entries = self.entries.write().unwrap();
+        if entries.contains_key(&robot.name) {
+            return Ok(None);
+        }
+
+        let token = format!(
+            "grain_{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let name = robot.name.clone();
+        entries.insert(
+            name.clone(),
+            RobotEntry {
+                robot,
+                token_sha256: sha256::digest(&token),
+            },
+        );
+
+        if let Err(e) = self.save(&entries) {
+            entries.remove(&name);
+            return Err(e);
+        }
+        Ok(Some(token))
+    }
+
+    /// Revoke a robot's token; `Ok(false)` if there is no such robot
+    pub(crate) fn revoke(&self, name: &str) -> Result<bool, std::io::Error> {
+        let mut entries = self.entries.write().unwrap();
+        let Some(entry) = entries.remove(name) else {
+            return Ok(false);
+        };
+
+        if let Err(e) = self.save(&entries) {
+            entries.insert(name.to_string(), entry);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// The identity of a `robot$<name>` username, if the robot exists and has not expired
+    pub(crate) fn user(&self, username: &str) -> Option<User> {
+        let name = username.strip_prefix(USERNAME_PREFIX)?;
+        let entries = self.entries.read().unwrap();
+        let entry = entries.get(name).filter(|e| !e.robot.is_expired(now()))?;
+        Some(User {
+            username: entry.robot.username(),
+            password: String::new(),
+            permissions: entry.robot.permissions.clone(),
+            admin: false,
+            admin_orgs: vec![],
+            roles: vec![],
+            groups: vec![],
+        })
+    }
+
+    /// Check a robot's token, returning its identity
+    pub(crate) fn authenticate(&self, username: &str, token: &str) -> Option<User> {
+        let name = username.strip_prefix(USERNAME_PREFIX)?;
+        let valid = self
+            .entries
+            .read()
+            .unwrap()
+            .get(name)
+            .is_some_and(|e| e.token_sha256 == sha256::digest(token));
+        if valid {
+            self.user(username)
+        } else {
+            None
+        }
+    }
+
+    fn save(&self, entries: &BTreeMap<String, RobotEntry>) -> Result<(), std::io::Error> {
+        let file = RobotsFile {
+            robots: entries.values().cloned().collect(),
+        };
+        std::fs::write(&self.path, serde_json::to_string_pretty(&file)?)
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateRobotRequest {
+    /// Letters, digits, `.`, `_` and `-`
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub permissions: Vec<Permission>,
+    /// Unix timestamp (seconds) after which the token is refused
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+/// A newly minted robot token; the token is only ever shown in this response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedRobot {
+    #[serde(flatten)]
+    pub robot: Robot,
+    /// Basic auth username, `robot$<name>`
+    pub username: String,
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RobotList {
+    pub robots: Vec<Robot>,
+    /// `last` of the next page, absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+}
+
+pub(crate) fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// List robot accounts (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/robots",
+    params(
+        ("n" = Option<usize>, Query, description = "Most entries to return (default and maximum: 1000)"),
+        ("last" = Option<String>, Query, description = "Return entries after this one, from `next` of the previous page")
+    ),
+    responses(
+        (status = 200, description = "Robot accounts by name, without their tokens", body = RobotList),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn list_robots(
+    State(state): State<Arc<state::App>>,
+    Query(query): Query<PageQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let host = state.args.primary_host();
+
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let page = query.page(state.robots.list(), |r| &r.name);
+    let link = pagination::next_link("/admin/v1/robots", &page);
+    let list = RobotList {
+        robots: page.items,
+        next: page.next,
+    };
+    pagination::response(serde_json::to_string_pretty(&list).unwrap(), link)
+}
+
+/// Mint a robot token scoped to a permission set (admin only)
+#[utoipa::path(
+    post,
+    path = "/admin/v1/robots",
+    request_body = CreateRobotRequest,
+    responses(
+        (status = 201, description = "Robot created; the token is not shown again", body = CreatedRobot),
+        (status = 400, description = "Bad request - invalid JSON or name"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 409, description = "Conflict - robot already exists"),
+        (status = 500, description = "Internal server error - failed to save robots")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn create_robot(
+    State(state): State<Arc<state::App>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let host = state.args.primary_host();
+
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    let req: CreateRobotRequest = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid request: {}", e)))
+                .unwrap();
+        }
+    };
+
+    if !valid_name(&req.name) {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from(format!("Invalid robot name {}", req.name)))
+            .unwrap();
+    }
+
+    let robot = Robot {
+        name: req.name,
+        description: req.description,
+        permissions: req.permissions,
+        created_by: user.username.clone(),
+        created_at: now(),
+        expires_at: req.expires_at,
+    };
+
+    let token = match state.robots.create(robot.clone()) {
+        Ok(Some(token)) => token,
+        Ok(None) => return response::conflict("Robot already exists"),
+        Err(e) => {
+            log::error!("Failed to save robots: {}", e);
+            return response::internal_error();
+        }
+    };
+
+    log::info!("Admin {} created robot {}", user.username, robot.name);
+    state.audit.record(
+        AuditEvent::new(
+            &user.username,
+            "robot.create",
+            &robot.name,
+            Outcome::Success,
+        )
+        .with_detail(serde_json::to_string(&robot.permissions).unwrap_or_default()),
+    );
+
+    let created = CreatedRobot {
+        username: robot.username(),
+        robot,
+        token,
+    };
+
+    Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&created).unwrap()))
+        .unwrap()
+}
+
+/// Revoke a robot token (admin only)
+#[utoipa::path(
+    delete,
+    path = "/admin/v1/robots/{name}",
+    params(
+        ("name" = String, Path, description = "Robot name"),
+        ("dry_run" = Option<bool>, Query, description = "Only report what would be revoked")
+    ),
+    responses(
+        (status = 204, description = "Robot revoked"),
+        (status = 200, description = "Dry run: what would be revoked", body = DryRunResult),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - no such robot"),
+        (status = 500, description = "Internal server error - failed to save robots")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn revoke_robot(
+    State(state): State<Arc<state::App>>,
+    Path(name): Path<String>,
+    Query(query): Query<DryRunQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let host = state.args.primary_host();
+
+    let user = match auth::authenticate_user(&state, &headers).await {
+        Ok(u) => u,
+        Err(_) => return response::unauthorized(host),
+    };
+
+    if !admin::is_admin(&user) {
+        return response::forbidden();
+    }
+
+    if query.dry_run {
+        if !state.robots.list().iter().any(|r| r.name == name) {
+            return response::not_found();
+        }
+        return dry_run::response(vec![PlannedChange::new("robot.revoke", &name)]);
+    }
+
+    match state.robots.revoke(&name) {
+        Ok(true) => {}
+        Ok(false) => return response::not_found(),
+        Err(e) => {
+            log::error!("Failed to save robots: {}", e);
+            return response::internal_error();
+        }
+    }
+
+    log::info!("Admin {} revoked robot {}", user.username, name);
+    state.audit.record(AuditEvent::new(
+        &user.username,
+        "robot.revoke",
+        &name,
+        Outcome::Success,
+    ));
+
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn robot(name: &str, expires_at: Option<u64>) -> Robot {
+        Robot {
+            name: name.to_string(),
+            description: String::new(),
+            permissions: vec![Permission {
+                repository: "ci/*".to_string(),
+                tag: "*".to_string(),
+                actions: vec!["pull".to_string(), "push".to_string()],
+                expires_at: None,
+            }],
+            created_by: "admin".to_string(),
+            created_at: 0,
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn test_robot_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("robots.json");
+        let robots = Robots::load(path.to_str().unwrap()).unwrap();
+
+        let token = robots.create(robot("builder", None)).unwrap().unwrap();
+        assert!(robots.create(robot("builder", None)).unwrap().is_none());
+        let user = robots.authenticate("robot$builder", &token).unwrap();
+        assert_eq!(user.username, "robot$builder");
+        assert_eq!(user.permissions.len(), 1);
+        assert!(robots.authenticate("robot$builder", "wrong").is_none());
+        assert!(robots.authenticate("builder", &token).is_none());
+
+        // Only the hash is persisted, and it survives a reload
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains(&token));
+        let reloaded = Robots::load(path.to_str().unwrap()).unwrap();
+        assert!(reloaded.authenticate("robot$builder", &token).is_some());
+
+        let expired = robots.create(robot("old", Some(1))).unwrap().unwrap();
+        assert!(robots.authenticate("robot$old", &expired).is_none());
+
+        assert!(robots.revoke("builder").unwrap());
+        assert!(!robots.revoke("builder").unwrap());
+        assert!(robots.authenticate("robot$builder", &token).is_none());
+    }
+
+    #[test]
+    fn test_valid_name() {
+        assert!(valid_name("ci-builder_1.0"));
+        assert!(!valid_name(""));
+        assert!(!valid_name("a/b"));
+        assert!(!valid_name("robot$x"));
+    }
+}
@@ -0,0 +1,493 @@
+//! Named roles (permission sets) and groups (sets of roles) declared in the users file, so
+//! users reference shared permission blocks instead of each carrying a copy
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
+use utoipa::ToSchema;
+
+use crate::{
+    admin,
+    audit::{AuditEvent, Outcome},
+    auth, response,
+    state::{self, Permission, User},
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct AccessRole {
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+}
+
+/// Members get the group's roles and permissions
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct AccessGroup {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Roles {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub roles: BTreeMap<String, AccessRole>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, AccessGroup>,
+}
+
+impl Roles {
+    /// `user` with the permissions of their roles and groups added to their own. Unknown
+    /// names grant nothing.
+    pub(crate) fn resolve(&self, mut user: User) -> User {
+        let groups: Vec<&AccessGroup> = user
+            .groups
+            .iter()
+            .filter_map(|name| self.groups.get(name))
+            .collect();
+        let mut granted: Vec<Permission> = groups
+            .iter()
+            .flat_map(|group| group.permissions.iter().cloned())
+            .collect();
+        granted.extend(
+            user.roles
+                .iter()
+                .chain(groups.iter().flat_map(|group| group.roles.iter()))
+                .filter_map(|name| self.roles.get(name))
+                .flat_map(|role| role.permissions.iter().cloned()),
+        );
+        user.permissions.extend(granted);
+        user
+    }
+
+    /// Why `roles` and `groups` cannot be referenced, if one of them does not exist
+    pub(crate) fn check_references(&self, roles: &[String], groups: &[String]) -> Option<String> {
+        if let Some(role) = roles.iter().find(|r| !self.roles.contains_key(*r)) {
+            return Some(format!("Unknown role {}", role));
+        }
+        if let Some(group) = groups.iter().find(|g| !self.groups.contains_key(*g)) {
+            return Some(format!("Unknown group {}", group));
+        }
+        None
+    }
+
+    /// Users and groups referencing role `name`
+    fn role_referrers(&self, users: &HashSet<User>, name: &str) -> Vec<String> {
+        let mut referrers: Vec<String> = users
+            .iter()
+            .filter(|u| u.roles.iter().any(|r| r == name))
+            .map(|u| format!("user {}", u.username))
+            .collect();
+        referrers.extend(
+            self.groups
+                .iter()
+                .filter(|(_, group)| group.roles.iter().any(|r| r == name))
+                .map(|(group, _)| format!("group {}", group)),
+        );
+        referrers.sort();
+        referrers
+    }
+
+    /// Log references to roles and groups missing from the users file
+    pub(crate) fn warn_dangling(&self, users: &HashSet<User>) {
+        for user in users {
+            if let Some(reason) = self.check_references(&user.roles, &user.groups) {
+                log::warn!("User {}: {}, ignored", user.username, reason);
+            }
+        }
+        for (name, group) in &self.groups {
+            if let Some(reason) = self.check_references(&group.roles, &[]) {
+                log::warn!("Group {}: {}, ignored", name, reason);
+            }
+        }
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+fn bad_request(message: String) -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Body::from(message))
+        .unwrap()
+}
+
+/// Authenticate an admin, or the response refusing the request
+async fn require_admin(state: &Arc<state::App>, headers: &HeaderMap) -> Result<User, Response> {
+    let host = state.args.primary_host();
+    let user = match auth::authenticate_user(state, headers).await {
+        Ok(u) => u,
+        Err(_) => return Err(response::unauthorized(host)),
+    };
+    if !admin::is_admin(&user) {
+        return Err(response::forbidden());
+    }
+    Ok(user)
+}
+
+/// List roles and groups (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/roles",
+    responses(
+        (status = 200, description = "Roles and groups by name", body = Roles),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn list_roles(State(state): State<Arc<state::App>>, headers: HeaderMap) -> Response {
+    if let Err(denied) = require_admin(&state, &headers).await {
+        return denied;
+    }
+
+    let roles = state.roles.lock().await.clone();
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string_pretty(&roles).unwrap()))
+        .unwrap()
+}
+
+/// Create or replace a role (admin only)
+#[utoipa::path(
+    put,
+    path = "/admin/v1/roles/{name}",
+    params(
+        ("name" = String, Path, description = "Role name (letters, digits, \".\", \"_\" and \"-\")")
+    ),
+    request_body = AccessRole,
+    responses(
+        (status = 200, description = "Role saved", body = AccessRole),
+        (status = 400, description = "Bad request - invalid JSON or name"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 500, description = "Internal server error - failed to save users")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn set_role(
+    State(state): State<Arc<state::App>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let user = match require_admin(&state, &headers).await {
+        Ok(user) => user,
+        Err(denied) => return denied,
+    };
+
+    let role: AccessRole = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(e) => return bad_request(format!("Invalid request: {}", e)),
+    };
+    if !valid_name(&name) {
+        return bad_request(format!("Invalid role name {}", name));
+    }
+
+    state
+        .roles
+        .lock()
+        .await
+        .roles
+        .insert(name.clone(), role.clone());
+
+    if let Err(e) = admin::save_users(&state).await {
+        log::error!("Failed to save users: {}", e);
+        return response::internal_error();
+    }
+
+    log::info!("Admin {} saved role {}", user.username, name);
+    state.audit.record(
+        AuditEvent::new(&user.username, "role.set", &name, Outcome::Success)
+            .with_detail(serde_json::to_string(&role.permissions).unwrap_or_default()),
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&role).unwrap()))
+        .unwrap()
+}
+
+/// Delete a role no user or group references (admin only)
+#[utoipa::path(
+    delete,
+    path = "/admin/v1/roles/{name}",
+    params(
+        ("name" = String, Path, description = "Role name")
+    ),
+    responses(
+        (status = 204, description = "Role deleted"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - role does not exist"),
+        (status = 409, description = "Conflict - users or groups still reference the role"),
+        (status = 500, description = "Internal server error - failed to save users")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn delete_role(
+    State(state): State<Arc<state::App>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let user = match require_admin(&state, &headers).await {
+        Ok(user) => user,
+        Err(denied) => return denied,
+    };
+
+    {
+        // Users before roles, as everywhere both are held
+        let users = state.users.lock().await;
+        let mut roles = state.roles.lock().await;
+        if !roles.roles.contains_key(&name) {
+            return response::not_found();
+        }
+        let referrers = roles.role_referrers(&users, &name);
+        if !referrers.is_empty() {
+            return response::conflict(&format!(
+                "Role is still referenced by {}",
+                referrers.join(", ")
+            ));
+        }
+        roles.roles.remove(&name);
+    }
+
+    if let Err(e) = admin::save_users(&state).await {
+        log::error!("Failed to save users: {}", e);
+        return response::internal_error();
+    }
+
+    log::info!("Admin {} deleted role {}", user.username, name);
+    state.audit.record(AuditEvent::new(
+        &user.username,
+        "role.delete",
+        &name,
+        Outcome::Success,
+    ));
+
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Create or replace a group (admin only)
+#[utoipa::path(
+    put,
+    path = "/admin/v1/groups/{name}",
+    params(
+        ("name" = String, Path, description = "Group name (letters, digits, \".\", \"_\" and \"-\")")
+    ),
+    request_body = AccessGroup,
+    responses(
+        (status = 200, description = "Group saved", body = AccessGroup),
+        (status = 400, description = "Bad request - invalid JSON or name, or unknown role"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 500, description = "Internal server error - failed to save users")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn set_group(
+    State(state): State<Arc<state::App>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let user = match require_admin(&state, &headers).await {
+        Ok(user) => user,
+        Err(denied) => return denied,
+    };
+
+    let group: AccessGroup = match serde_json::from_slice(&body) {
+        Ok(g) => g,
+        Err(e) => return bad_request(format!("Invalid request: {}", e)),
+    };
+    if !valid_name(&name) {
+        return bad_request(format!("Invalid group name {}", name));
+    }
+
+    {
+        let mut roles = state.roles.lock().await;
+        if let Some(reason) = roles.check_references(&group.roles, &[]) {
+            return bad_request(reason);
+        }
+        roles.groups.insert(name.clone(), group.clone());
+    }
+
+    if let Err(e) = admin::save_users(&state).await {
+        log::error!("Failed to save users: {}", e);
+        return response::internal_error();
+    }
+
+    log::info!("Admin {} saved group {}", user.username, name);
+    state.audit.record(
+        AuditEvent::new(&user.username, "group.set", &name, Outcome::Success)
+            .with_detail(serde_json::to_string(&group).unwrap_or_default()),
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&group).unwrap()))
+        .unwrap()
+}
+
+/// Delete a group no user is a member of (admin only)
+#[utoipa::path(
+    delete,
+    path = "/admin/v1/groups/{name}",
+    params(
+        ("name" = String, Path, description = "Group name")
+    ),
+    responses(
+        (status = 204, description = "Group deleted"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 404, description = "Not found - group does not exist"),
+        (status = 409, description = "Conflict - users are still members of the group"),
+        (status = 500, description = "Internal server error - failed to save users")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn delete_group(
+    State(state): State<Arc<state::App>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let user = match require_admin(&state, &headers).await {
+        Ok(user) => user,
+        Err(denied) => return denied,
+    };
+
+    {
+        let users = state.users.lock().await;
+        let mut roles = state.roles.lock().await;
+        if !roles.groups.contains_key(&name) {
+            return response::not_found();
+        }
+        let mut members: Vec<&str> = users
+            .iter()
+            .filter(|u| u.groups.contains(&name))
+            .map(|u| u.username.as_str())
+            .collect();
+        if !members.is_empty() {
+            members.sort();
+            return response::conflict(&format!("Group still has members: {}", members.join(", ")));
+        }
+        roles.groups.remove(&name);
+    }
+
+    if let Err(e) = admin::save_users(&state).await {
+        log::error!("Failed to save users: {}", e);
+        return response::internal_error();
+    }
+
+    log::info!("Admin {} deleted group {}", user.username, name);
+    state.audit.record(AuditEvent::new(
+        &user.username,
+        "group.delete",
+        &name,
+        Outcome::Success,
+    ));
+
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permission(repository: &str) -> Permission {
+        Permission {
+            repository: repository.to_string(),
+            tag: "*".to_string(),
+            actions: vec!["pull".to_string()],
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut roles = Roles::default();
+        roles.roles.insert(
+            "reader".to_string(),
+            AccessRole {
+                permissions: vec![permission("team/*")],
+            },
+        );
+        roles.roles.insert(
+            "base".to_string(),
+            AccessRole {
+                permissions: vec![permission("base/*")],
+            },
+        );
+        roles.groups.insert(
+            "platform".to_string(),
+            AccessGroup {
+                roles: vec!["base".to_string()],
+                permissions: vec![permission("platform/*")],
+            },
+        );
+        let user = User {
+            username: "alice".to_string(),
+            password: String::new(),
+            permissions: vec![permission("alice/*")],
+            admin: false,
+            admin_orgs: vec![],
+            roles: vec!["reader".to_string(), "missing".to_string()],
+            groups: vec!["platform".to_string()],
+        };
+
+        let resolved = roles.resolve(user);
+        let mut repositories: Vec<&str> = resolved
+            .permissions
+            .iter()
+            .map(|p| p.repository.as_str())
+            .collect();
+        repositories.sort();
+        assert_eq!(repositories, ["alice/*", "base/*", "platform/*", "team/*"]);
+
+        assert_eq!(
+            roles.check_references(&["reader".to_string()], &["platform".to_string()]),
+            None
+        );
+        assert_eq!(
+            roles.check_references(&["missing".to_string()], &[]),
+            Some("Unknown role missing".to_string())
+        );
+        assert_eq!(
+            roles.check_references(&[], &["nobody".to_string()]),
+            Some("Unknown group nobody".to_string())
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    process::Command,
+    sync::{Arc, Mutex, RwLock},
+};
+
+lazy_static::lazy_static! {
+    // Secrets read from a file, the environment or a command, refreshed by `reload_all`
+    static ref RESOLVED: Mutex<Vec<Secret>> = Mutex::new(Vec::new());
+}
+
+/// A credential given on the command line either literally or as a reference:
+///
+/// - `file:<path>` reads the file, without its trailing newline
+/// - `env:<NAME>` reads an environment variable
+/// - `cmd:<command>` runs the command with `sh -c` and uses its standard output
+///
+/// Referenced secrets are re-read on `SIGHUP`, so rotated credentials are picked up without a
+/// restart.
+#[derive(Clone)]
+pub(crate) struct Secret {
+    source: String,
+    value: Arc<RwLock<String>>,
+}
+
+impl Secret {
+    /// Resolve a secret reference, or take the value literally when it has no known prefix
+    pub(crate) fn resolve(source: &str) -> Result<Self, String> {
+        let secret = Self {
+            source: source.to_string(),
+            value: Arc::new(RwLock::new(read(source)?)),
+        };
+        if secret.is_reference() {
+            RESOLVED.lock().unwrap().push(secret.clone());
+        }
+        Ok(secret)
+    }
+
+    /// Resolve a secret given by `flag`, exiting when it cannot be read
+    pub(crate) fn resolve_or_exit(flag: &str, source: &str) -> Self {
+        Self::resolve(source).unwrap_or_else(|e| {
+            log::error!("Failed to resolve {}: {}", flag, e);
+            std::process::exit(1);
+        })
+    }
+
+    pub(crate) fn get(&self) -> String {
+        self.value.read().unwrap().clone()
+    }
+
+    fn is_reference(&self) -> bool {
+        ["file:", "env:", "cmd:"]
+            .iter()
+            .any(|prefix| self.source.starts_with(prefix))
+    }
+
+    /// Re-read the secret from its source; the previous value is kept on failure
+    fn reload(&self) -> Result<(), String> {
+        let value = read(&self.source)?;
+        *self.value.write().unwrap() = value;
+        Ok(())
+    }
+}
+
+fn read(source: &str) -> Result<String, String> {
+    if let Some(path) = source.strip_prefix("file:") {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read secret file {}: {}", path, e))?;
+        Ok(content.trim_end_matches(['\r', '\n']).to_string())
+    } else if let Some(name) = source.strip_prefix("env:") {
+        std::env::var(name).map_err(|e| format!("failed to read secret from ${}: {}", name, e))
+    } else if let Some(command) = source.strip_prefix("cmd:") {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .output()
+            .map_err(|e| format!("failed to run secret command: {}", e))?;
+        if !output.status.success() {
+            // stdout may hold part of the secret, only the status is reported
+            return Err(format!("secret command failed: {}", output.status));
+        }
+        let value = String::from_utf8(output.stdout)
+            .map_err(|_| "secret command printed invalid UTF-8".to_string())?;
+        Ok(value.trim_end_matches(['\r', '\n']).to_string())
+    } else {
+        Ok(source.to_string())
+    }
+}
+
+/// Re-read every referenced secret, returning how many failed
+pub(crate) fn reload_all() -> usize {
+    let secrets = RESOLVED.lock().unwrap().clone();
+    let mut failed = 0;
+    for secret in &secrets {
+        if let Err(e) = secret.reload() {
+            log::error!("secrets: keeping previous value: {}", e);
+            failed += 1;
+        }
+    }
+    log::info!(
+        "secrets: reloaded {} of {}",
+        secrets.len() - failed,
+        secrets.len()
+    );
+    failed
+}
+
+/// Reload secrets whenever the process receives `SIGHUP`
+#[cfg(unix)]
+pub(crate) fn spawn_reload_on_sighup() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            log::error!("secrets: cannot listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            log::info!("secrets: SIGHUP received, reloading");
+            // Secret commands may block for a while
+            let _ = tokio::task::spawn_blocking(reload_all).await;
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub(crate) fn spawn_reload_on_sighup() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_sources() {
+        assert_eq!(Secret::resolve("plain").unwrap().get(), "plain");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret");
+        std::fs::write(&path, "from-file\n").unwrap();
+        let secret = Secret::resolve(&format!("file:{}", path.display())).unwrap();
+        assert_eq!(secret.get(), "from-file");
+
+        // Rotated files are picked up on reload
+        std::fs::write(&path, "rotated\n").unwrap();
+        secret.reload().unwrap();
+        assert_eq!(secret.get(), "rotated");
+
+        // A failed reload keeps the previous value
+        std::fs::remove_file(&path).unwrap();
+        assert!(secret.reload().is_err());
+        assert_eq!(secret.get(), "rotated");
+
+        std::env::set_var("GRAIN_TEST_SECRET", "from-env");
+        assert_eq!(
+            Secret::resolve("env:GRAIN_TEST_SECRET").unwrap().get(),
+            "from-env"
+        );
+        assert!(Secret::resolve("env:GRAIN_TEST_SECRET_UNSET").is_err());
+
+        assert_eq!(
+            Secret::resolve("cmd:echo from-cmd").unwrap().get(),
+            "from-cmd"
+        );
+        assert!(Secret::resolve("cmd:exit 3").is_err());
+    }
+}
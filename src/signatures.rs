@@ -0,0 +1,452 @@
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use base64::{
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+    Engine,
+};
+use ciborium::Value as CborValue;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use utoipa::ToSchema;
+
+use crate::{
+    auth,
+    digest::{Algorithm, Digest},
+    permissions, referrers, response, state, storage,
+};
+
+/// `artifactType` of notation (Notary Project) signature manifests
+pub(crate) const NOTATION_ARTIFACT_TYPE: &str = "application/vnd.cncf.notary.signature";
+
+const JWS_MEDIA_TYPE: &str = "application/jose+json";
+const COSE_MEDIA_TYPE: &str = "application/cose";
+
+/// Manifest annotation listing the SHA-256 thumbprints of the signing certificate chain
+const THUMBPRINT_ANNOTATION: &str = "io.cncf.notary.x509chain.thumbprint#S256";
+
+const SIGNING_SCHEME: &str = "io.cncf.notary.signingScheme";
+const SIGNING_TIME: &str = "io.cncf.notary.signingTime";
+const EXPIRY: &str = "io.cncf.notary.expiry";
+const SIGNING_AGENT: &str = "io.cncf.notary.signingAgent";
+
+/// COSE header label of the certificate chain (RFC 9360)
+const COSE_X5CHAIN: u8 = 33;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    pub serial: String,
+    /// Unix time
+    pub not_before: i64,
+    /// Unix time
+    pub not_after: i64,
+    /// SHA-256 of the DER encoding, as listed in the signature's thumbprint annotation
+    pub thumbprint: String,
+}
+
+/// A notation signature attached to an image. Identities are read from the signature envelope
+/// as claimed by the signer; grain does not verify signatures, `notation verify` does.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct Signature {
+    /// Digest of the signature manifest
+    pub digest: String,
+    /// `application/jose+json` or `application/cose`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub envelope_type: Option<String>,
+    /// Subject of the signing certificate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signer: Option<String>,
+    /// `notary.x509` or `notary.x509.signingAuthority`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_scheme: Option<String>,
+    /// RFC 3339
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_time: Option<String>,
+    /// RFC 3339
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_agent: Option<String>,
+    /// Signing certificate first
+    pub certificate_chain: Vec<Certificate>,
+    /// Thumbprints from the manifest annotation, available even when the envelope is not
+    pub thumbprints: Vec<String>,
+    /// Why the envelope could not be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SignatureList {
+    pub repository: String,
+    /// Digest of the signed manifest
+    pub subject: String,
+    pub signatures: Vec<Signature>,
+}
+
+/// Claims read from a signature envelope
+#[derive(Debug, Default, PartialEq)]
+struct Envelope {
+    signing_scheme: Option<String>,
+    signing_time: Option<String>,
+    expiry: Option<String>,
+    signing_agent: Option<String>,
+    certificates: Vec<Vec<u8>>,
+}
+
+fn rfc3339(unix: i64) -> Option<String> {
+    OffsetDateTime::from_unix_timestamp(unix)
+        .ok()?
+        .format(&Rfc3339)
+        .ok()
+}
+
+/// Notation JWS envelopes use the flattened JSON serialization, with the certificate chain in
+/// the unprotected `x5c` header
+fn parse_jws(bytes: &[u8]) -> Result<Envelope, String> {
+    let envelope: Value = serde_json::from_slice(bytes).map_err(|e| e.to_string())?;
+    let protected = envelope
+        .get("protected")
+        .and_then(|p| p.as_str())
+        .ok_or("missing protected header")?;
+    let protected: Value = BASE64_URL_SAFE_NO_PAD
+        .decode(protected.trim_end_matches('='))
+        .map_err(|e| e.to_string())
+        .and_then(|json| serde_json::from_slice(&json).map_err(|e| e.to_string()))
+        .map_err(|e| format!("invalid protected header: {}", e))?;
+    let header = envelope.get("header");
+
+    let text = |value: Option<&Value>| value.and_then(|v| v.as_str()).map(str::to_string);
+    let certificates = header
+        .and_then(|h| h.get("x5c"))
+        .and_then(|x5c| x5c.as_array())
+        .map(|x5c| {
+            x5c.iter()
+                .filter_map(|c| c.as_str())
+                .map(|c| BASE64_STANDARD.decode(c).map_err(|e| e.to_string()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    Ok(Envelope {
+        signing_scheme: text(protected.get(SIGNING_SCHEME)),
+        signing_time: text(protected.get(SIGNING_TIME)),
+        expiry: text(protected.get(EXPIRY)),
+        signing_agent: text(header.and_then(|h| h.get(SIGNING_AGENT))),
+        certificates,
+    })
+}
+
+fn cbor_entry<'a>(map: &'a [(CborValue, CborValue)], label: &CborValue) -> Option<&'a CborValue> {
+    map.iter().find(|(k, _)| k == label).map(|(_, v)| v)
+}
+
+/// Notation COSE envelopes are `COSE_Sign1` messages; times are epoch-based date/time tags
+fn parse_cose(bytes: &[u8]) -> Result<Envelope, String> {
+    let message: CborValue = ciborium::from_reader(bytes).map_err(|e| e.to_string())?;
+    let message = match message {
+        CborValue::Tag(18, message) => *message,
+        message => message,
+    };
+    let parts = message.as_array().ok_or("not a COSE_Sign1 message")?;
+    let [protected, unprotected, ..] = parts.as_slice() else {
+        return Err("not a COSE_Sign1 message".to_string());
+    };
+
+    let protected: CborValue = match protected.as_bytes() {
+        Some(bytes) if !bytes.is_empty() => {
+            ciborium::from_reader(bytes.as_slice()).map_err(|e| e.to_string())?
+        }
+        _ => CborValue::Map(Vec::new()),
+    };
+    let empty = Vec::new();
+    let protected = protected.as_map().unwrap_or(&empty);
+    let unprotected = unprotected.as_map().unwrap_or(&empty);
+
+    let label = |name: &str| CborValue::Text(name.to_string());
+    let text = |value: Option<&CborValue>| value.and_then(|v| v.as_text()).map(str::to_string);
+    let time = |value: Option<&CborValue>| match value? {
+        CborValue::Tag(1, epoch) => rfc3339(i128::from(epoch.as_integer()?) as i64),
+        _ => None,
+    };
+    let certificates = match cbor_entry(unprotected, &CborValue::Integer(COSE_X5CHAIN.into())) {
+        Some(CborValue::Bytes(certificate)) => vec![certificate.clone()],
+        Some(CborValue::Array(chain)) => {
+            chain.iter().filter_map(|c| c.as_bytes().cloned()).collect()
+        }
+        _ => Vec::new(),
+    };
+
+    Ok(Envelope {
+        signing_scheme: text(cbor_entry(protected, &label(SIGNING_SCHEME))),
+        signing_time: time(cbor_entry(protected, &label(SIGNING_TIME))),
+        expiry: time(cbor_entry(protected, &label(EXPIRY))),
+        signing_agent: text(cbor_entry(unprotected, &label(SIGNING_AGENT))),
+        certificates,
+    })
+}
+
+fn parse_certificate(der: &[u8]) -> Result<Certificate, String> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(der)
+        .map_err(|e| format!("invalid certificate: {}", e))?;
+    let validity = certificate.validity();
+    Ok(Certificate {
+        subject: certificate.subject().to_string(),
+        issuer: certificate.issuer().to_string(),
+        serial: certificate.raw_serial_as_string(),
+        not_before: validity.not_before.timestamp(),
+        not_after: validity.not_after.timestamp(),
+        thumbprint: Digest::of(Algorithm::Sha256, der).hex().to_string(),
+    })
+}
+
+/// Read a signature manifest and its envelope
+fn read_signature(
+    org: &str,
+    repo: &str,
+    referrer: &referrers::Referrer,
+    max_size: u64,
+) -> Signature {
+    let mut signature = Signature {
+        digest: referrer.digest.clone(),
+        thumbprints: referrer
+            .annotations
+            .get(THUMBPRINT_ANNOTATION)
+            .and_then(|t| serde_json::from_str(t).ok())
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+
+    let result = (|| {
+        let hex = referrer
+            .digest
+            .strip_prefix("sha256:")
+            .unwrap_or(&referrer.digest);
+        let manifest: Value = storage::read_manifest(org, repo, hex)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| serde_json::from_slice(&bytes).map_err(|e| e.to_string()))?;
+        let layer = manifest
+            .get("layers")
+            .and_then(|l| l.as_array())
+            .and_then(|layers| layers.first())
+            .ok_or("signature manifest has no envelope layer")?;
+        let media_type = layer
+            .get("mediaType")
+            .and_then(|m| m.as_str())
+            .unwrap_or_default();
+        let digest = layer
+            .get("digest")
+            .and_then(|d| d.as_str())
+            .ok_or("envelope layer has no digest")?;
+        let digest = Digest::parse(digest).map_err(|e| e.to_string())?;
+        signature.envelope_type = Some(media_type.to_string());
+
+        // Envelopes are a few kilobytes; don't load arbitrary blobs into memory
+        let metadata = storage::blob_metadata(org, repo, &digest).map_err(|e| e.to_string())?;
+        if metadata.size > max_size {
+            return Err("envelope is too large".to_string());
+        }
+        let bytes = storage::read_blob(org, repo, &digest).map_err(|e| e.to_string())?;
+        let envelope = match media_type {
+            JWS_MEDIA_TYPE => parse_jws(&bytes)?,
+            COSE_MEDIA_TYPE => parse_cose(&bytes)?,
+            other => return Err(format!("unsupported envelope type {}", other)),
+        };
+
+        signature.signing_scheme = envelope.signing_scheme;
+        signature.signing_time = envelope.signing_time;
+        signature.expiry = envelope.expiry;
+        signature.signing_agent = envelope.signing_agent;
+        signature.certificate_chain = envelope
+            .certificates
+            .iter()
+            .map(|der| parse_certificate(der))
+            .collect::<Result<_, _>>()?;
+        signature.signer = signature
+            .certificate_chain
+            .first()
+            .map(|c| c.subject.clone());
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        log::warn!(
+            "signatures: cannot read {}/{}@{}: {}",
+            org,
+            repo,
+            referrer.digest,
+            e
+        );
+        signature.error = Some(e);
+    }
+    signature
+}
+
+/// Notation signatures attached to an image, with the identities they were signed with
+/// (requires pull permission)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/repos/{org}/{repo}/signatures/{reference}",
+    params(
+        ("org" = String, Path, description = "Organization name"),
+        ("repo" = String, Path, description = "Repository name"),
+        ("reference" = String, Path, description = "Tag or digest of the signed image")
+    ),
+    responses(
+        (status = 200, description = "Signatures of the image, sorted by digest", body = SignatureList),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - pull permission required"),
+        (status = 404, description = "Not found - manifest does not exist")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn list_signatures(
+    State(state): State<Arc<state::App>>,
+    Path((org, repo, reference)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    let host = state.args.primary_host();
+    let repository = format!("{}/{}", org, repo);
+
+    match auth::check_permission(
+        &state,
+        &headers,
+        &repository,
+        None,
+        permissions::Action::Pull,
+    )
+    .await
+    {
+        Ok(_) => {}
+        Err(_) => {
+            return if auth::authenticate_user(&state, &headers).await.is_ok() {
+                response::forbidden()
+            } else {
+                response::unauthorized(host)
+            };
+        }
+    }
+
+    let clean_reference = reference
+        .strip_prefix("sha256:")
+        .unwrap_or(&reference)
+        .to_string();
+    let (subject_reference, max_size) = (clean_reference.clone(), state.args.max_manifest_size);
+    let listing = storage::blocking("list_signatures", &org, &repo, move |org, repo| {
+        let bytes = storage::read_manifest(org, repo, &subject_reference).ok()?;
+        let subject = Digest::of(Algorithm::Sha256, &bytes);
+        let signatures = referrers::list(org, repo, &subject, Some(NOTATION_ARTIFACT_TYPE))
+            .iter()
+            .map(|referrer| read_signature(org, repo, referrer, max_size))
+            .collect();
+        Some((subject, signatures))
+    })
+    .await;
+    let Some((subject, signatures)) = listing else {
+        return response::manifest_unknown(&clean_reference);
+    };
+
+    let list = SignatureList {
+        repository,
+        subject: subject.to_string(),
+        signatures,
+    };
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&list).unwrap()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_jws() {
+        let protected = serde_json::json!({
+            "alg": "PS256",
+            "cty": "application/vnd.cncf.notary.payload.v1+json",
+            "io.cncf.notary.signingScheme": "notary.x509",
+            "io.cncf.notary.signingTime": "2026-01-02T03:04:05Z",
+        });
+        let envelope = serde_json::json!({
+            "payload": "e30",
+            "protected": BASE64_URL_SAFE_NO_PAD.encode(protected.to_string()),
+            "header": {
+                "x5c": [BASE64_STANDARD.encode(b"leaf"), BASE64_STANDARD.encode(b"root")],
+                "io.cncf.notary.signingAgent": "notation-go/1.3.0",
+            },
+            "signature": "c2ln",
+        });
+
+        assert_eq!(
+            parse_jws(envelope.to_string().as_bytes()),
+            Ok(Envelope {
+                signing_scheme: Some("notary.x509".to_string()),
+                signing_time: Some("2026-01-02T03:04:05Z".to_string()),
+                expiry: None,
+                signing_agent: Some("notation-go/1.3.0".to_string()),
+                certificates: vec![b"leaf".to_vec(), b"root".to_vec()],
+            })
+        );
+        assert!(parse_jws(b"{}").is_err());
+    }
+
+    #[test]
+    fn test_parse_cose() {
+        let label = |name: &str| CborValue::Text(name.to_string());
+        let mut protected = Vec::new();
+        ciborium::into_writer(
+            &CborValue::Map(vec![
+                (
+                    CborValue::Integer(1.into()),
+                    CborValue::Integer((-37).into()),
+                ),
+                (label(SIGNING_SCHEME), label("notary.x509")),
+                (
+                    label(SIGNING_TIME),
+                    CborValue::Tag(1, Box::new(CborValue::Integer(1767323045.into()))),
+                ),
+            ]),
+            &mut protected,
+        )
+        .unwrap();
+        let message = CborValue::Tag(
+            18,
+            Box::new(CborValue::Array(vec![
+                CborValue::Bytes(protected),
+                CborValue::Map(vec![(
+                    CborValue::Integer(33.into()),
+                    CborValue::Array(vec![CborValue::Bytes(b"leaf".to_vec())]),
+                )]),
+                CborValue::Bytes(b"{}".to_vec()),
+                CborValue::Bytes(b"sig".to_vec()),
+            ])),
+        );
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&message, &mut bytes).unwrap();
+
+        assert_eq!(
+            parse_cose(&bytes),
+            Ok(Envelope {
+                signing_scheme: Some("notary.x509".to_string()),
+                signing_time: Some("2026-01-02T03:04:05Z".to_string()),
+                expiry: None,
+                signing_agent: None,
+                certificates: vec![b"leaf".to_vec()],
+            })
+        );
+        assert!(parse_cose(b"not cbor").is_err());
+    }
+}
@@ -0,0 +1,538 @@
+use axum::{
+    body::Body,
+    extract::{Query, Request, State},
+    http::{HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use utoipa::ToSchema;
+
+use crate::{
+    admin, auth, cancel,
+    changelog::{self, Change, ChangeEntry},
+    cluster,
+    digest::Digest,
+    errors::{ErrorCode, OciErrorResponse},
+    metrics, response,
+    secrets::Secret,
+    state,
+    storage::{self, StorageError},
+};
+
+const POSITION_PATH: &str = "./tmp/standby.json";
+const MAX_CHANGES_PER_PAGE: usize = 1000;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ChangesQuery {
+    #[serde(default)]
+    pub since: u64,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+fn default_limit() -> usize {
+    MAX_CHANGES_PER_PAGE
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChangesPage {
+    latest_seq: u64,
+    entries: Vec<ChangeEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+struct ManifestRef {
+    org: String,
+    repo: String,
+    reference: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+struct BlobRef {
+    org: String,
+    repo: String,
+    digest: String,
+}
+
+/// Full listing of primary storage, used to bootstrap or resync a standby
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    seq: u64,
+    manifests: Vec<ManifestRef>,
+    blobs: Vec<BlobRef>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Position {
+    seq: u64,
+}
+
+/// Reject writes while running as a standby; reads are served from the local copy
+pub async fn read_only_guard(
+    State(state): State<Arc<state::App>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let is_write = !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let path = req.uri().path();
+
+    if state.args.standby_of.is_some()
+        && is_write
+        && (path.starts_with("/v2/") || path.starts_with("/admin/") || path.starts_with("/user/"))
+    {
+        log::warn!(
+            "standby: rejected {} {} on read-only standby",
+            req.method(),
+            path
+        );
+        return OciErrorResponse::new(ErrorCode::Unsupported, "registry is a read-only standby")
+            .into_response();
+    }
+
+    next.run(req).await
+}
+
+async fn require_admin(state: &Arc<state::App>, headers: &HeaderMap) -> Option<Response> {
+    match auth::authenticate_user(state, headers).await {
+        Ok(user) if admin::is_admin(&user) => None,
+        Ok(_) => Some(response::forbidden()),
+        Err(_) => Some(response::unauthorized(state.args.primary_host())),
+    }
+}
+
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(body).unwrap()))
+        .unwrap()
+}
+
+/// Storage and user changes recorded after a sequence number (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/sync/changes",
+    params(
+        ("since" = Option<u64>, Query, description = "Return changes after this sequence number (default: 0)"),
+        ("limit" = Option<usize>, Query, description = "Maximum number of changes to return (default: 1000)")
+    ),
+    responses(
+        (status = 200, description = "Changes in sequence order with the latest recorded sequence number"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 410, description = "Gone - requested changes were compacted, resync from snapshot")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn sync_changes(
+    State(state): State<Arc<state::App>>,
+    Query(params): Query<ChangesQuery>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(denied) = require_admin(&state, &headers).await {
+        return denied;
+    }
+
+    // Lists the standby in `/admin/cluster`
+    cluster::record_standby(&headers, params.since);
+
+    let limit = params.limit.clamp(1, MAX_CHANGES_PER_PAGE);
+    match changelog::changes_since(params.since, limit) {
+        Ok(entries) => json_response(
+            StatusCode::OK,
+            &ChangesPage {
+                latest_seq: changelog::latest_seq(),
+                entries,
+            },
+        ),
+        Err(compacted) => {
+            log::info!(
+                "sync/changes: since {} compacted (first retained: {})",
+                params.since,
+                compacted.first_seq
+            );
+            json_response(
+                StatusCode::GONE,
+                &serde_json::json!({
+                    "error": "changes compacted, resync from snapshot",
+                    "first_seq": compacted.first_seq,
+                }),
+            )
+        }
+    }
+}
+
+/// Every stored manifest and blob with the change log position it reflects (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/sync/snapshot",
+    responses(
+        (status = 200, description = "Snapshot of stored manifests and blobs"),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn sync_snapshot(State(state): State<Arc<state::App>>, headers: HeaderMap) -> Response {
+    if let Some(denied) = require_admin(&state, &headers).await {
+        return denied;
+    }
+
+    // Read the position first: changes racing the listing are replayed again, which is idempotent
+    let seq = changelog::latest_seq();
+
+    let listing = cancel::run_blocking("sync_snapshot", |_| {
+        storage::list_manifests()
+            .and_then(|manifests| storage::list_blobs().map(|blobs| (manifests, blobs)))
+    })
+    .await;
+
+    match listing {
+        Ok((manifests, blobs)) => json_response(
+            StatusCode::OK,
+            &Snapshot {
+                seq,
+                manifests: manifests
+                    .into_iter()
+                    .map(|(org, repo, reference)| ManifestRef {
+                        org,
+                        repo,
+                        reference,
+                    })
+                    .collect(),
+                blobs: blobs
+                    .into_iter()
+                    .map(|(org, repo, digest)| BlobRef {
+                        org,
+                        repo,
+                        digest: digest.to_string(),
+                    })
+                    .collect(),
+            },
+        ),
+        Err(e) => {
+            log::error!("sync/snapshot: failed to list storage: {}", e);
+            response::internal_error()
+        }
+    }
+}
+
+/// Users file including credentials, for replication to standbys (admin only)
+#[utoipa::path(
+    get,
+    path = "/admin/v1/sync/users",
+    responses(
+        (status = 200, description = "All users with credentials and permissions", body = state::UsersFile),
+        (status = 401, description = "Unauthorized - authentication required"),
+        (status = 403, description = "Forbidden - admin permission required")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+pub async fn sync_users(State(state): State<Arc<state::App>>, headers: HeaderMap) -> Response {
+    if let Some(denied) = require_admin(&state, &headers).await {
+        return denied;
+    }
+
+    let users = state.users.lock().await;
+    let roles = state.roles.lock().await;
+    json_response(
+        StatusCode::OK,
+        &state::UsersFile {
+            users: users.iter().cloned().collect(),
+            roles: roles.clone(),
+        },
+    )
+}
+
+/// Pull-based follower replicating a primary into local storage
+struct Follower {
+    primary: String,
+    username: String,
+    password: Secret,
+    instance_id: String,
+    http: reqwest::Client,
+}
+
+type SyncResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+impl Follower {
+    async fn get(&self, path: &str) -> SyncResult<reqwest::Response> {
+        Ok(self
+            .http
+            .get(format!("{}{}", self.primary, path))
+            .basic_auth(&self.username, Some(self.password.get()))
+            .header(cluster::INSTANCE_ID_HEADER, &self.instance_id)
+            .send()
+            .await?)
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, path: &str) -> SyncResult<T> {
+        Ok(self.get(path).await?.error_for_status()?.json().await?)
+    }
+
+    async fn fetch_manifest(&self, org: &str, repo: &str, reference: &str) -> SyncResult<()> {
+        // Digest-addressed manifests are stored without their algorithm prefix
+        let is_digest = reference.len() == 64 && reference.chars().all(|c| c.is_ascii_hexdigit());
+        let remote_reference = if is_digest {
+            format!("sha256:{}", reference)
+        } else {
+            reference.to_string()
+        };
+
+        let resp = self
+            .get(&format!(
+                "/v2/{}/{}/manifests/{}",
+                org, repo, remote_reference
+            ))
+            .await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            log::warn!(
+                "standby: manifest {}/{}:{} gone on primary, skipping",
+                org,
+                repo,
+                reference
+            );
+            return Ok(());
+        }
+
+        let resp = resp.error_for_status()?;
+        let content_type = resp
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        let bytes = resp.bytes().await?;
+        storage::write_manifest_bytes(org, repo, reference, &bytes, content_type.as_deref())
+            .await?;
+        Ok(())
+    }
+
+    async fn fetch_blob(&self, org: &str, repo: &str, digest: &str) -> SyncResult<()> {
+        let digest = Digest::parse(digest)?;
+        if storage::blob_metadata(org, repo, &digest).is_ok() {
+            return Ok(());
+        }
+
+        let resp = self
+            .get(&format!("/v2/{}/{}/blobs/{}", org, repo, digest))
+            .await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            log::warn!(
+                "standby: blob {}/{}@{} gone on primary, skipping",
+                org,
+                repo,
+                digest
+            );
+            return Ok(());
+        }
+
+        let bytes = resp.error_for_status()?.bytes().await?;
+        storage::write_blob(org, repo, &digest, &bytes).await?;
+        Ok(())
+    }
+
+    async fn fetch_users(&self, state: &Arc<state::App>) -> SyncResult<()> {
+        let users_file: state::UsersFile = self.get_json("/admin/v1/sync/users").await?;
+        let count = users_file.users.len();
+
+        {
+            let mut users = state.users.lock().await;
+            *users = HashSet::from_iter(users_file.users);
+            *state.roles.lock().await = users_file.roles;
+        }
+        admin::save_users(state)
+            .await
+            .map_err(|e| format!("failed to save users: {}", e))?;
+
+        log::info!("standby: replicated {} users", count);
+        Ok(())
+    }
+
+    async fn apply(&self, state: &Arc<state::App>, change: &Change) -> SyncResult<()> {
+        match change {
+            Change::ManifestPut {
+                org,
+                repo,
+                reference,
+            } => self.fetch_manifest(org, repo, reference).await,
+            Change::BlobPut { org, repo, digest } => self.fetch_blob(org, repo, digest).await,
+            Change::ManifestDelete {
+                org,
+                repo,
+                reference,
+            } => ignore_missing(storage::delete_manifest(org, repo, reference)),
+            Change::BlobDelete { org, repo, digest } => {
+                ignore_missing(storage::delete_blob(org, repo, &Digest::parse(digest)?))
+            }
+            Change::UsersUpdated => self.fetch_users(state).await,
+        }
+    }
+
+    /// Mirror the primary's full contents, removing anything it no longer has
+    async fn resync(&self, state: &Arc<state::App>) -> SyncResult<u64> {
+        let snapshot: Snapshot = self.get_json("/admin/v1/sync/snapshot").await?;
+        log::info!(
+            "standby: resyncing from snapshot at seq {} ({} manifests, {} blobs)",
+            snapshot.seq,
+            snapshot.manifests.len(),
+            snapshot.blobs.len()
+        );
+
+        for blob in &snapshot.blobs {
+            self.fetch_blob(&blob.org, &blob.repo, &blob.digest).await?;
+        }
+        for manifest in &snapshot.manifests {
+            self.fetch_manifest(&manifest.org, &manifest.repo, &manifest.reference)
+                .await?;
+        }
+        self.fetch_users(state).await?;
+
+        let manifests: HashSet<_> = snapshot.manifests.into_iter().collect();
+        for (org, repo, reference) in storage::list_manifests()? {
+            let key = ManifestRef {
+                org,
+                repo,
+                reference,
+            };
+            if !manifests.contains(&key) {
+                ignore_missing(storage::delete_manifest(
+                    &key.org,
+                    &key.repo,
+                    &key.reference,
+                ))?;
+            }
+        }
+
+        // Compare canonical `<algorithm>:<hex>` forms; older primaries list bare sha256 hex
+        let blobs: HashSet<_> = snapshot
+            .blobs
+            .into_iter()
+            .filter_map(|b| Some((b.org, b.repo, Digest::parse(&b.digest).ok()?)))
+            .collect();
+        for key in storage::list_blobs()? {
+            if !blobs.contains(&key) {
+                ignore_missing(storage::delete_blob(&key.0, &key.1, &key.2))?;
+            }
+        }
+
+        Ok(snapshot.seq)
+    }
+
+    /// Apply pending changes, falling back to a full resync when the position is unknown or compacted
+    async fn sync_once(&self, state: &Arc<state::App>, position: Option<u64>) -> SyncResult<u64> {
+        let Some(mut seq) = position else {
+            return self.resync(state).await;
+        };
+
+        loop {
+            let resp = self
+                .get(&format!(
+                    "/admin/v1/sync/changes?since={}&limit={}",
+                    seq, MAX_CHANGES_PER_PAGE
+                ))
+                .await?;
+            if resp.status() == StatusCode::GONE {
+                log::warn!("standby: position {} compacted on primary", seq);
+                return self.resync(state).await;
+            }
+
+            let page: ChangesPage = resp.error_for_status()?.json().await?;
+            for entry in &page.entries {
+                self.apply(state, &entry.change).await?;
+                seq = entry.seq;
+                save_position(seq)?;
+                metrics::STANDBY_CHANGES_APPLIED_TOTAL.inc();
+            }
+
+            if page.entries.is_empty() || seq >= page.latest_seq {
+                return Ok(seq);
+            }
+        }
+    }
+}
+
+fn ignore_missing(result: Result<(), StorageError>) -> SyncResult<()> {
+    match result {
+        Err(e) if !e.is_not_found() => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn load_position() -> Option<u64> {
+    let content = std::fs::read_to_string(POSITION_PATH).ok()?;
+    serde_json::from_str::<Position>(&content)
+        .ok()
+        .map(|p| p.seq)
+}
+
+fn save_position(seq: u64) -> std::io::Result<()> {
+    std::fs::write(POSITION_PATH, serde_json::to_string(&Position { seq })?)
+}
+
+/// Start the background sync loop when `--standby-of` is set
+pub(crate) fn spawn_follower(state: Arc<state::App>) {
+    let Some(primary) = state.args.standby_of.clone() else {
+        return;
+    };
+
+    let follower = Follower {
+        primary: primary.trim_end_matches('/').to_string(),
+        username: state.args.standby_username.clone(),
+        password: Secret::resolve_or_exit("--standby-password", &state.args.standby_password),
+        instance_id: state.instance_id.clone(),
+        http: reqwest::Client::new(),
+    };
+    let interval = Duration::from_secs(state.args.standby_interval_secs.max(1));
+
+    log::info!(
+        "standby: following {} every {}s (read-only)",
+        follower.primary,
+        interval.as_secs()
+    );
+
+    tokio::spawn(async move {
+        let mut position = load_position();
+
+        loop {
+            match follower.sync_once(&state, position).await {
+                Ok(seq) => {
+                    if position != Some(seq) {
+                        log::info!("standby: synced to seq {}", seq);
+                    }
+                    if let Err(e) = save_position(seq) {
+                        log::error!("standby: failed to save position: {}", e);
+                    }
+                    position = Some(seq);
+                    metrics::STANDBY_POSITION.set(seq as i64);
+                    metrics::STANDBY_LAST_SYNC_TIMESTAMP_SECONDS.set(
+                        SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map(|d| d.as_secs() as i64)
+                            .unwrap_or(0),
+                    );
+                }
+                Err(e) => {
+                    log::error!("standby: sync from {} failed: {}", follower.primary, e);
+                    metrics::STANDBY_SYNC_FAILURES_TOTAL.inc();
+                    // Keep the last applied position; a partial page resumes after its last applied entry
+                    position = load_position().or(position);
+                }
+            }
+
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
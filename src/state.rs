@@ -2,9 +2,33 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 use utoipa::ToSchema;
 
-use std::{collections::HashSet, fmt, fs};
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
-use crate::args::Args;
+use crate::{
+    activity::Activity,
+    archive::Archives,
+    args::Args,
+    audit::{AuditEvent, Auditor, Outcome},
+    cache::ContentCache,
+    changelog::{self, Change},
+    cluster, downloads,
+    gc::GcPolicyStore,
+    metrics::RepoLabeler,
+    oidc::CiIdentities,
+    password::PasswordPolicy,
+    policy::PushPolicy,
+    ratelimit::RateLimiter,
+    replication::Replicator,
+    repositories::Repositories,
+    robots::Robots,
+    roles::Roles,
+    tokens, webhooks,
+};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub(crate) enum ServerStatus {
@@ -17,6 +41,15 @@ pub struct Permission {
     pub repository: String,
     pub tag: String,
     pub actions: Vec<String>,
+    /// Unix timestamp (seconds) after which the permission no longer applies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+impl Permission {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, ToSchema)]
@@ -25,11 +58,25 @@ pub struct User {
     pub password: String,
     #[serde(default)]
     pub permissions: Vec<Permission>,
+    /// Grants the admin API; repository permissions are granted separately
+    #[serde(default)]
+    pub admin: bool,
+    /// Orgs whose accounts, permissions and garbage collection the user administers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admin_orgs: Vec<String>,
+    /// Roles whose permissions the user also has
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    /// Groups whose roles and permissions the user also has
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct UsersFile {
     pub users: Vec<User>,
+    #[serde(flatten)]
+    pub roles: Roles,
 }
 
 impl fmt::Display for ServerStatus {
@@ -44,38 +91,158 @@ impl fmt::Display for ServerStatus {
 pub(crate) struct App {
     pub(crate) server_status: Mutex<ServerStatus>,
     pub(crate) users: Mutex<HashSet<User>>,
+    /// Locked after `users` when both are needed
+    pub(crate) roles: Mutex<Roles>,
+    pub(crate) password_policy: PasswordPolicy,
+    pub(crate) push_policy: PushPolicy,
+    pub(crate) repositories: Repositories,
+    pub(crate) archives: Archives,
+    pub(crate) robots: Robots,
+    pub(crate) gc_policy: GcPolicyStore,
+    pub(crate) repo_metrics: RepoLabeler,
+    pub(crate) activity: Activity,
+    pub(crate) content_cache: ContentCache,
+    pub(crate) audit: Auditor,
+    pub(crate) download_signer: downloads::Signer,
+    pub(crate) token_issuer: tokens::TokenIssuer,
+    pub(crate) ci_identities: CiIdentities,
+    pub(crate) rate_limiter: RateLimiter,
+    pub(crate) webhooks: webhooks::Notifier,
+    pub(crate) replication: Replicator,
+    pub(crate) instance_id: String,
     pub(crate) args: Args,
 }
 
-fn load_users_from_file(file_path: &str) -> HashSet<User> {
-    let file_content = match fs::read_to_string(file_path) {
-        Ok(content) => content,
+fn read_users_file(file_path: &str) -> Result<(HashSet<User>, Roles), String> {
+    let file_content = fs::read_to_string(file_path)
+        .map_err(|err| format!("Failed to read users file {}: {}", file_path, err))?;
+
+    let users_file: UsersFile = serde_json::from_str(&file_content).map_err(|err| {
+        format!(
+            "Failed to parse JSON from users file {}: {}",
+            file_path, err
+        )
+    })?;
+
+    let users = HashSet::from_iter(users_file.users);
+    users_file.roles.warn_dangling(&users);
+    Ok((users, users_file.roles))
+}
+
+fn load_users_from_file(file_path: &str) -> (HashSet<User>, Roles) {
+    match read_users_file(file_path) {
+        Ok((users, roles)) => {
+            log::info!(
+                "Loaded {} users, {} roles and {} groups",
+                users.len(),
+                roles.roles.len(),
+                roles.groups.len()
+            );
+            (users, roles)
+        }
         Err(err) => {
-            log::error!("Failed to read users file {}: {}", file_path, err);
-            return HashSet::new();
+            log::error!("{}", err);
+            (HashSet::new(), Roles::default())
         }
-    };
+    }
+}
 
-    let users_file: UsersFile = match serde_json::from_str(&file_content) {
-        Ok(users_file) => users_file,
-        Err(err) => {
-            log::error!(
-                "Failed to parse JSON from users file {}: {}",
-                file_path,
-                err
+fn modified(file_path: &str) -> Option<SystemTime> {
+    fs::metadata(file_path).and_then(|m| m.modified()).ok()
+}
+
+/// Reload `--users-file` whenever its modification time changes, so users added out-of-band
+/// apply without a restart. A file that fails to parse keeps the current users.
+pub(crate) fn spawn_users_reload(state: Arc<App>) {
+    let interval = Duration::from_secs(state.args.users_reload_interval_secs);
+    // A standby replicates the primary's users instead
+    if interval.is_zero() || state.args.standby_of.is_some() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let path = state.args.users_file.clone();
+        let mut last_modified = modified(&path);
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let current = modified(&path);
+            if current.is_none() || current == last_modified {
+                continue;
+            }
+            last_modified = current;
+
+            // Read under the lock, as admin changes write the file while holding it
+            let mut users = state.users.lock().await;
+            let mut roles = state.roles.lock().await;
+            let (reloaded, reloaded_roles) = match read_users_file(&path) {
+                Ok(reloaded) => reloaded,
+                Err(err) => {
+                    log::error!("{}; keeping the current users", err);
+                    continue;
+                }
+            };
+            if reloaded == *users && reloaded_roles == *roles {
+                continue;
+            }
+            let count = reloaded.len();
+            *users = reloaded;
+            *roles = reloaded_roles;
+            drop(roles);
+            drop(users);
+
+            log::info!("Reloaded {} users from {}", count, path);
+            changelog::record(Change::UsersUpdated);
+            state.audit.record(
+                AuditEvent::new("system", "users.reload", &path, Outcome::Success)
+                    .with_detail(format!("{} users", count)),
             );
-            return HashSet::new();
         }
-    };
-
-    log::info!("Loaded {} users", users_file.users.len());
-    HashSet::from_iter(users_file.users)
+    });
 }
 
 pub(crate) fn new_app(args: &Args) -> App {
+    let instance_id = cluster::load_instance_id();
+    let (users, roles) = load_users_from_file(&args.users_file);
     App {
         server_status: Mutex::new(ServerStatus::Starting),
-        users: Mutex::new(load_users_from_file(&args.users_file)),
+        users: Mutex::new(users),
+        roles: Mutex::new(roles),
+        password_policy: PasswordPolicy::from_args(args),
+        push_policy: PushPolicy::from_args(args).unwrap_or_else(|e| {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }),
+        repositories: Repositories::load(&args.repositories_file).unwrap_or_else(|e| {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }),
+        archives: Archives::load(&args.archive_dir).unwrap_or_else(|e| {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }),
+        robots: Robots::load(&args.robots_file).unwrap_or_else(|e| {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }),
+        gc_policy: GcPolicyStore::load(&args.gc_policy_file).unwrap_or_else(|e| {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }),
+        repo_metrics: RepoLabeler::from_args(args),
+        activity: Activity::default(),
+        content_cache: ContentCache::from_args(args),
+        audit: Auditor::from_args(args),
+        download_signer: downloads::Signer::from_args(args),
+        token_issuer: tokens::TokenIssuer::from_args(args),
+        ci_identities: CiIdentities::from_args(args).unwrap_or_else(|e| {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }),
+        rate_limiter: RateLimiter::from_args(args),
+        webhooks: webhooks::Notifier::from_args(args, &instance_id),
+        replication: Replicator::from_args(args),
+        instance_id,
         args: args.clone(),
     }
 }
@@ -1,8 +1,271 @@
 use axum::body::Body;
+use serde::{Deserialize, Serialize};
 use std::{
-    fs::{create_dir_all, File},
-    io::Write,
+    collections::HashMap,
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    path::Path,
+    pin::Pin,
+    sync::{Mutex, OnceLock},
+    time::{Duration, SystemTime},
 };
+use tokio::io::AsyncRead;
+
+use crate::{
+    args::Args,
+    body::{self, BodyError},
+    cancel::{self, CancelToken},
+    changelog::{self, Change},
+    digest::{Algorithm, Digest, Hasher},
+    manifests, metrics, referrers, repositories,
+};
+
+mod fs;
+mod holders;
+mod instrumented;
+mod s3;
+
+const HASH_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Directory holding in-progress upload sessions, whatever the backend
+const UPLOADS_DIR: &str = "./tmp/uploads";
+
+/// Size and last modification time of a stored object
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ObjectMeta {
+    pub(crate) size: u64,
+    pub(crate) modified: SystemTime,
+}
+
+/// Failure of a storage facade operation; `response::storage_error` maps it to an OCI error
+#[derive(Debug)]
+pub(crate) enum StorageError {
+    BlobNotFound(Digest),
+    ManifestNotFound(String),
+    /// Upload session (uuid) that does not exist or was already finalized
+    UploadNotFound(String),
+    /// The uploaded content does not hash to the digest the client announced
+    DigestMismatch {
+        expected: Digest,
+        actual: Digest,
+    },
+    /// Storing the blob would take the repository over its quota
+    QuotaExceeded(String),
+    /// The request that started the operation went away
+    Cancelled,
+    Io(io::Error),
+}
+
+impl StorageError {
+    pub(crate) fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            StorageError::BlobNotFound(_)
+                | StorageError::ManifestNotFound(_)
+                | StorageError::UploadNotFound(_)
+        )
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::BlobNotFound(digest) => write!(f, "blob {} not found", digest),
+            StorageError::ManifestNotFound(reference) => {
+                write!(f, "manifest {} not found", reference)
+            }
+            StorageError::UploadNotFound(uuid) => write!(f, "upload {} not found", uuid),
+            StorageError::DigestMismatch { expected, actual } => {
+                write!(f, "digest mismatch: expected {}, got {}", expected, actual)
+            }
+            StorageError::QuotaExceeded(reason) => write!(f, "quota exceeded: {}", reason),
+            StorageError::Cancelled => write!(f, "cancelled"),
+            StorageError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::Interrupted {
+            StorageError::Cancelled
+        } else {
+            StorageError::Io(e)
+        }
+    }
+}
+
+/// Streaming reader over a blob's content
+pub(crate) type BlobReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// Where blobs, manifests and mount records are kept.
+///
+/// Objects are addressed by `org`/`repo` plus a digest or reference; backends share the key
+/// layout of `blob_key`, `manifest_key`, `manifest_meta_key` and `mount_key`. Upload sessions are staged on local
+/// disk whatever the backend and handed over with `store_upload` once their digest is verified.
+/// Missing objects are reported as `io::ErrorKind::NotFound`.
+pub(crate) trait StorageBackend: Send + Sync {
+    /// Human-readable location of a section (`blobs`, `manifests`), for health output
+    fn location(&self, section: &str) -> String;
+
+    fn read_blob(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<Vec<u8>>;
+    /// Open a blob for streaming, returning the reader and the blob size
+    /// Stream a blob from `offset` to its end, along with the blob's full size
+    fn open_blob(
+        &self,
+        org: &str,
+        repo: &str,
+        digest: &Digest,
+        offset: u64,
+    ) -> io::Result<(BlobReader, u64)>;
+    fn blob_metadata(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<ObjectMeta>;
+    fn write_blob(&self, org: &str, repo: &str, digest: &Digest, bytes: &[u8]) -> io::Result<()>;
+    /// Move a verified upload session file into blob storage
+    fn store_upload(&self, org: &str, repo: &str, digest: &Digest, upload: &Path)
+        -> io::Result<()>;
+    /// Store an upload session file that was not hashed yet, having the backend check it
+    /// against `digest` as it receives it. `Ok(false)` leaves the file to be hashed locally:
+    /// the backend cannot check this digest, or the content did not match.
+    fn store_checked_upload(
+        &self,
+        _org: &str,
+        _repo: &str,
+        _digest: &Digest,
+        _upload: &Path,
+    ) -> io::Result<bool> {
+        Ok(false)
+    }
+    fn delete_blob(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<()>;
+    /// Copy a blob into another repository; `Ok(false)` if the target already has it
+    fn copy_blob(
+        &self,
+        source_org: &str,
+        source_repo: &str,
+        target_org: &str,
+        target_repo: &str,
+        digest: &Digest,
+    ) -> io::Result<bool>;
+    /// `(org, repo, digest)` of every stored blob
+    fn list_blobs(&self) -> io::Result<Vec<(String, String, Digest)>>;
+    /// Digests of the blobs stored in one repository
+    fn list_repository_blobs(&self, org: &str, repo: &str) -> io::Result<Vec<Digest>>;
+
+    fn read_manifest(&self, org: &str, repo: &str, reference: &str) -> io::Result<Vec<u8>>;
+    fn manifest_metadata(&self, org: &str, repo: &str, reference: &str) -> io::Result<ObjectMeta>;
+    fn write_manifest(
+        &self,
+        org: &str,
+        repo: &str,
+        reference: &str,
+        bytes: &[u8],
+    ) -> io::Result<()>;
+    fn delete_manifest(&self, org: &str, repo: &str, reference: &str) -> io::Result<()>;
+    /// Serialized `ManifestMeta` kept next to a manifest
+    fn read_manifest_meta(&self, org: &str, repo: &str, reference: &str) -> io::Result<Vec<u8>>;
+    fn write_manifest_meta(
+        &self,
+        org: &str,
+        repo: &str,
+        reference: &str,
+        bytes: &[u8],
+    ) -> io::Result<()>;
+    fn delete_manifest_meta(&self, org: &str, repo: &str, reference: &str) -> io::Result<()>;
+    /// Stored manifest references (tags and digests) of one repository, unsorted
+    fn list_references(&self, org: &str, repo: &str) -> io::Result<Vec<String>>;
+    /// `(org, repo, reference)` of every stored manifest
+    fn list_manifests(&self) -> io::Result<Vec<(String, String, String)>>;
+
+    fn read_mount_record(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<Vec<u8>>;
+    fn write_mount_record(
+        &self,
+        org: &str,
+        repo: &str,
+        digest: &Digest,
+        bytes: &[u8],
+    ) -> io::Result<()>;
+    fn delete_mount_record(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<()>;
+    fn list_mount_records(&self) -> io::Result<Vec<(String, String, Digest)>>;
+
+    fn is_accessible(&self) -> bool;
+    fn is_writable(&self) -> bool;
+
+    /// Upgrade data written by older versions; returns the number of objects moved
+    fn migrate(&self) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+static BACKEND: OnceLock<Box<dyn StorageBackend>> = OnceLock::new();
+
+/// Running digest of an upload session's first `offset` bytes
+#[derive(Clone)]
+struct UploadHash {
+    offset: u64,
+    hasher: Hasher,
+}
+
+/// Upload sessions hashed as their chunks arrive, keyed by upload path. Sessions missing here
+/// (e.g. after a restart) are hashed from disk when finalized.
+static UPLOAD_HASHES: OnceLock<Mutex<HashMap<String, UploadHash>>> = OnceLock::new();
+
+fn upload_hashes() -> std::sync::MutexGuard<'static, HashMap<String, UploadHash>> {
+    UPLOAD_HASHES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap()
+}
+
+/// Select the storage backend from `--storage-backend`. Must run before the first storage access;
+/// without it the filesystem backend under `./tmp` is used.
+pub(crate) fn init(args: &Args) -> Result<(), String> {
+    let backend: Box<dyn StorageBackend> = match args.storage_backend.as_str() {
+        "fs" => Box::new(instrumented::Instrumented::new(
+            "fs",
+            Box::new(fs::FsBackend::new("./tmp")),
+        )),
+        "s3" => Box::new(instrumented::Instrumented::new(
+            "s3",
+            Box::new(s3::S3Backend::from_args(args)?),
+        )),
+        other => return Err(format!("unknown storage backend: {}", other)),
+    };
+    log::info!("storage: using {}", backend.location(""));
+    BACKEND
+        .set(backend)
+        .map_err(|_| "storage backend already initialized".to_string())?;
+
+    // Sessions left by a previous run can still be resumed
+    match upload_session_count() {
+        Ok(sessions) => metrics::ACTIVE_UPLOAD_SESSIONS.set(sessions as i64),
+        Err(e) => log::warn!("storage: failed to count upload sessions: {}", e),
+    }
+    Ok(())
+}
+
+fn backend() -> &'static dyn StorageBackend {
+    BACKEND
+        .get_or_init(|| {
+            Box::new(instrumented::Instrumented::new(
+                "fs",
+                Box::new(fs::FsBackend::new("./tmp")),
+            ))
+        })
+        .as_ref()
+}
+
+/// Run storage calls for one repository on the blocking thread pool. Backends block on disk
+/// and network I/O, so request handlers go through here rather than stall the async runtime.
+pub(crate) async fn blocking<T, F>(operation: &'static str, org: &str, repo: &str, f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce(&str, &str) -> T + Send + 'static,
+{
+    let (org, repo) = (org.to_string(), repo.to_string());
+    cancel::run_blocking(operation, move |_| f(&org, &repo)).await
+}
 
 pub(crate) fn sanitize_string(input: &str) -> String {
     input
@@ -17,357 +280,883 @@ pub(crate) fn sanitize_string(input: &str) -> String {
         .collect()
 }
 
-pub(crate) async fn write_blob(org: &str, repo: &str, req_digest_string: &str, body: Body) -> bool {
-    let bytes_res = axum::body::to_bytes(body, usize::MAX).await;
-    if bytes_res.is_err() {
-        return false;
+/// Replace the file at `path` with `bytes` so that a crash leaves either the old or the new
+/// content: write a temporary file next to it, flush it to disk, then rename it over `path`.
+/// The file keeps its permissions. Temporary files are dotfiles, which listings skip.
+pub(crate) fn write_file_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let tmp = dir.join(format!(
+        ".{}.tmp-{}",
+        name.to_string_lossy(),
+        uuid::Uuid::new_v4()
+    ));
+
+    let written = (|| {
+        let mut file = File::create(&tmp)?;
+        if let Ok(existing) = std::fs::metadata(path) {
+            file.set_permissions(existing.permissions())?;
+        }
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)
+    })();
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp);
     }
-    let bytes = bytes_res.unwrap();
+    written?;
+    sync_dir(dir)
+}
 
-    let req_digest = req_digest_string
-        .strip_prefix("sha256:")
-        .unwrap_or(req_digest_string);
-    let body_digest = sha256::digest(bytes.as_ref());
-    let matches = req_digest == body_digest;
+/// Flush a directory's entries, making a file created or renamed in it durable
+pub(crate) fn sync_dir(dir: &Path) -> io::Result<()> {
+    // Directories cannot be opened as files on Windows
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
 
-    log::info!(
-        "storage/write_file: digest: {}, body_digest: {}, matches: {}",
-        req_digest,
-        body_digest,
-        matches
-    );
+/// Blob key for a digest: `blobs/<org>/<repo>/<algorithm>/<hex>`
+fn blob_key(org: &str, repo: &str, digest: &Digest) -> String {
+    format!(
+        "blobs/{}/{}/{}",
+        sanitize_string(org),
+        sanitize_string(repo),
+        digest.path()
+    )
+}
 
-    if !matches {
-        return false;
-    }
+/// Manifest key for a tag or digest reference: `manifests/<org>/<repo>/<reference>`
+fn manifest_key(org: &str, repo: &str, reference: &str) -> String {
+    format!(
+        "manifests/{}/{}/{}",
+        sanitize_string(org),
+        sanitize_string(repo),
+        sanitize_string(reference)
+    )
+}
 
-    let base_path = format!(
-        "./tmp/blobs/{}/{}",
+/// Manifest metadata key for a tag or digest reference: `manifest-meta/<org>/<repo>/<reference>`
+fn manifest_meta_key(org: &str, repo: &str, reference: &str) -> String {
+    format!(
+        "manifest-meta/{}/{}/{}",
         sanitize_string(org),
         sanitize_string(repo),
-    );
+        sanitize_string(reference)
+    )
+}
+
+/// Mount record key for a digest: `mounts/<org>/<repo>/<algorithm>/<hex>`
+fn mount_key(org: &str, repo: &str, digest: &Digest) -> String {
+    format!(
+        "mounts/{}/{}/{}",
+        sanitize_string(org),
+        sanitize_string(repo),
+        digest.path()
+    )
+}
+
+fn upload_path(org: &str, repo: &str, uuid: &str) -> String {
+    format!(
+        "{}/{}/{}/{}",
+        UPLOADS_DIR,
+        sanitize_string(org),
+        sanitize_string(repo),
+        sanitize_string(uuid)
+    )
+}
+
+pub(crate) fn location(section: &str) -> String {
+    backend().location(section)
+}
+
+pub(crate) fn is_accessible() -> bool {
+    backend().is_accessible()
+}
+
+pub(crate) fn is_writable() -> bool {
+    backend().is_writable()
+}
+
+pub(crate) async fn write_blob(
+    org: &str,
+    repo: &str,
+    digest: &Digest,
+    bytes: &[u8],
+) -> Result<(), StorageError> {
+    let (digest, bytes) = (digest.clone(), bytes.to_vec());
+    blocking("write_blob", org, repo, move |org, repo| {
+        let body_digest = Digest::of(digest.algorithm(), &bytes);
+        if digest != body_digest {
+            return Err(StorageError::DigestMismatch {
+                expected: digest,
+                actual: body_digest,
+            });
+        }
 
-    write_bytes_to_file(&base_path, req_digest, &bytes).await
+        backend().write_blob(org, repo, &digest, &bytes)?;
+        holders::added(org, repo, &digest);
+
+        changelog::record(Change::BlobPut {
+            org: org.to_string(),
+            repo: repo.to_string(),
+            digest: digest.to_string(),
+        });
+        Ok(())
+    })
+    .await
 }
 
+/// Store a manifest, served with `media_type` (the `Content-Type` it was pushed with) when given,
+/// or else the media type found in the manifest
 pub(crate) async fn write_manifest_bytes(
     org: &str,
     repo: &str,
     reference: &str,
     bytes: &[u8],
-) -> bool {
-    let base_path = format!(
-        "./tmp/manifests/{}/{}",
-        sanitize_string(org),
-        sanitize_string(repo),
+    media_type: Option<&str>,
+) -> Result<(), StorageError> {
+    let (reference, bytes, media_type) = (
+        reference.to_string(),
+        bytes.to_vec(),
+        media_type.map(String::from),
     );
+    blocking("write_manifest", org, repo, move |org, repo| {
+        // Drop the old metadata first, so it is never served with the new manifest
+        delete_manifest_meta(org, repo, &reference)?;
+        backend().write_manifest(org, repo, &reference, &bytes)?;
+        let mut meta = ManifestMeta::of(&bytes);
+        if let Some(media_type) = media_type {
+            meta.media_type = media_type;
+        }
+        write_manifest_meta(org, repo, &reference, &meta);
+        referrers::record(org, repo, &reference, &bytes);
+
+        changelog::record(Change::ManifestPut {
+            org: org.to_string(),
+            repo: repo.to_string(),
+            reference,
+        });
+        Ok(())
+    })
+    .await
+}
 
-    write_bytes_to_file(&base_path, reference, bytes).await
+pub(crate) fn read_blob(org: &str, repo: &str, digest: &Digest) -> Result<Vec<u8>, io::Error> {
+    backend().read_blob(org, repo, digest)
 }
 
-pub(crate) async fn write_bytes_to_file(base_path: &str, file_name: &str, bytes: &[u8]) -> bool {
-    if let Err(e) = create_dir_all(base_path) {
-        log::error!("storage/write_file: error creating directory: {}", e);
-        return false;
-    }
+/// Open a blob for streaming, returning the reader and its size
+pub(crate) fn open_blob(
+    org: &str,
+    repo: &str,
+    digest: &Digest,
+    offset: u64,
+) -> Result<(BlobReader, u64), io::Error> {
+    backend().open_blob(org, repo, digest, offset)
+}
 
-    let mut file = match File::create(format!("{}/{}", base_path, file_name)) {
-        Ok(file) => file,
-        Err(e) => {
-            log::error!("storage/write_file: error creating file: {}", e);
-            return false;
-        }
-    };
+pub(crate) fn blob_metadata(
+    org: &str,
+    repo: &str,
+    digest: &Digest,
+) -> Result<ObjectMeta, io::Error> {
+    backend().blob_metadata(org, repo, digest)
+}
 
-    if let Err(e) = file.write_all(bytes) {
-        log::error!("storage/write_file: error writing to file: {}", e);
-        return false;
-    }
+pub(crate) fn read_manifest(org: &str, repo: &str, reference: &str) -> Result<Vec<u8>, io::Error> {
+    backend().read_manifest(org, repo, reference)
+}
 
-    if let Err(e) = file.flush() {
-        log::error!("storage/write_file: error flushing file: {}", e);
-        return false;
-    }
+pub(crate) fn manifest_metadata(
+    org: &str,
+    repo: &str,
+    reference: &str,
+) -> Result<ObjectMeta, io::Error> {
+    backend().manifest_metadata(org, repo, reference)
+}
 
-    log::info!("storage/write_file: wrote to {}", base_path);
+/// What manifest GET and HEAD serve besides the manifest itself, stored next to each manifest so
+/// they need neither hash it nor, for HEAD, read it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ManifestMeta {
+    /// `sha256:<hex>` digest of the manifest
+    pub(crate) digest: String,
+    /// `Content-Type` the manifest was pushed with, or the media type found in it
+    pub(crate) media_type: String,
+    pub(crate) size: u64,
+    /// Unix time from the `grain.expires-at` annotation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) expires_at: Option<u64>,
+}
 
-    true
+impl ManifestMeta {
+    pub(crate) fn of(bytes: &[u8]) -> Self {
+        Self {
+            digest: Digest::of(Algorithm::Sha256, bytes).to_string(),
+            media_type: manifests::detect_manifest_content_type(bytes),
+            size: bytes.len() as u64,
+            expires_at: repositories::expires_at_annotation(bytes),
+        }
+    }
 }
 
-pub(crate) fn read_blob(org: &str, repo: &str, digest: &str) -> Result<Vec<u8>, std::io::Error> {
-    let sanitized_org = sanitize_string(org);
-    let sanitized_repo = sanitize_string(repo);
-    let sanitized_digest = sanitize_string(digest);
+/// Stored metadata of a manifest, unless missing or written for content of another size
+fn stored_manifest_meta(org: &str, repo: &str, reference: &str, size: u64) -> Option<ManifestMeta> {
+    let data = backend().read_manifest_meta(org, repo, reference).ok()?;
+    serde_json::from_slice::<ManifestMeta>(&data)
+        .ok()
+        .filter(|meta| meta.size == size)
+}
 
-    let blob_path = format!(
-        "./tmp/blobs/{}/{}/{}",
-        sanitized_org, sanitized_repo, sanitized_digest
-    );
-    std::fs::read(blob_path)
+/// Best effort: without metadata, the manifest is hashed whenever it is served
+fn write_manifest_meta(org: &str, repo: &str, reference: &str, meta: &ManifestMeta) {
+    let written = serde_json::to_vec(meta)
+        .map_err(io::Error::from)
+        .and_then(|bytes| backend().write_manifest_meta(org, repo, reference, &bytes));
+    if let Err(e) = written {
+        log::warn!(
+            "storage: cannot write metadata of manifest {}/{}/{}: {}",
+            org,
+            repo,
+            reference,
+            e
+        );
+    }
 }
 
-pub(crate) fn blob_metadata(
+fn delete_manifest_meta(org: &str, repo: &str, reference: &str) -> Result<(), io::Error> {
+    match backend().delete_manifest_meta(org, repo, reference) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Metadata of a manifest read as `bytes`, hashing it only when none is stored. Metadata is
+/// filled in for digest references, whose content cannot change; a tag's could be replaced by
+/// a concurrent push, so it is only written with the manifest.
+pub(crate) fn manifest_meta_of(
     org: &str,
     repo: &str,
-    digest: &str,
-) -> Result<std::fs::Metadata, std::io::Error> {
-    let sanitized_org = sanitize_string(org);
-    let sanitized_repo = sanitize_string(repo);
-    let sanitized_digest = sanitize_string(digest);
-
-    let blob_path = format!(
-        "./tmp/blobs/{}/{}/{}",
-        sanitized_org, sanitized_repo, sanitized_digest
-    );
-    std::fs::metadata(blob_path)
+    reference: &str,
+    bytes: &[u8],
+) -> ManifestMeta {
+    if let Some(meta) = stored_manifest_meta(org, repo, reference, bytes.len() as u64) {
+        return meta;
+    }
+    let meta = ManifestMeta::of(bytes);
+    if is_digest_reference(reference) {
+        write_manifest_meta(org, repo, reference, &meta);
+    }
+    meta
 }
 
-pub(crate) fn read_manifest(
+/// Metadata of a stored manifest, for which a stat is enough when its metadata is stored
+pub(crate) fn manifest_meta(
     org: &str,
     repo: &str,
     reference: &str,
-) -> Result<Vec<u8>, std::io::Error> {
-    let sanitized_org = sanitize_string(org);
-    let sanitized_repo = sanitize_string(repo);
-    let sanitized_reference = sanitize_string(reference);
-
-    let manifest_path = format!(
-        "./tmp/manifests/{}/{}/{}",
-        sanitized_org, sanitized_repo, sanitized_reference
-    );
-    std::fs::read(manifest_path)
+) -> Result<ManifestMeta, io::Error> {
+    let size = backend().manifest_metadata(org, repo, reference)?.size;
+    match stored_manifest_meta(org, repo, reference, size) {
+        Some(meta) => Ok(meta),
+        None => Ok(manifest_meta_of(
+            org,
+            repo,
+            reference,
+            &backend().read_manifest(org, repo, reference)?,
+        )),
+    }
 }
 
 pub(crate) fn manifest_exists(org: &str, repo: &str, reference: &str) -> bool {
-    let sanitized_org = sanitize_string(org);
-    let sanitized_repo = sanitize_string(repo);
-    let sanitized_reference = sanitize_string(reference);
+    backend().manifest_metadata(org, repo, reference).is_ok()
+}
 
-    let manifest_path = format!(
-        "./tmp/manifests/{}/{}/{}",
-        sanitized_org, sanitized_repo, sanitized_reference
-    );
-    std::path::Path::new(&manifest_path).exists()
+/// Stored manifest references (tags and digests) of one repository, unsorted
+pub(crate) fn list_references(org: &str, repo: &str) -> Result<Vec<String>, io::Error> {
+    backend().list_references(org, repo)
+}
+
+/// Whether a stored reference is a manifest digest (64-char hex string or sha256: prefixed)
+/// rather than a tag
+fn is_digest_reference(name: &str) -> bool {
+    name.starts_with("sha256:") || (name.len() == 64 && name.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+pub(crate) fn list_tags(org: &str, repo: &str) -> Result<Vec<String>, io::Error> {
+    let mut tags: Vec<String> = backend()
+        .list_references(org, repo)?
+        .into_iter()
+        // Only include tag names
+        .filter(|name| !is_digest_reference(name))
+        .collect();
+
+    // Sort tags alphabetically for consistent ordering
+    tags.sort();
+    Ok(tags)
+}
+
+/// Tags of one repository that point to the manifest with sha256 digest `hex`, sorted
+pub(crate) fn tags_of(org: &str, repo: &str, hex: &str) -> Result<Vec<String>, io::Error> {
+    Ok(list_tags(org, repo)?
+        .into_iter()
+        .filter(|tag| {
+            read_manifest(org, repo, tag).is_ok_and(|bytes| sha256::digest(bytes.as_slice()) == hex)
+        })
+        .collect())
 }
 
-pub(crate) fn list_tags(org: &str, repo: &str) -> Result<Vec<String>, std::io::Error> {
-    let sanitized_org = sanitize_string(org);
-    let sanitized_repo = sanitize_string(repo);
+/// Create an empty upload session, hashed with `algorithm` as chunks arrive. Chunked uploads
+/// learn their digest only when finalized and assume sha256; other digests are hashed from disk.
+pub(crate) fn init_upload_session(
+    org: &str,
+    repo: &str,
+    uuid: &str,
+    algorithm: Algorithm,
+) -> Result<(), io::Error> {
+    let upload_path = upload_path(org, repo, uuid);
+    if let Some(upload_dir) = Path::new(&upload_path).parent() {
+        std::fs::create_dir_all(upload_dir)?;
+    }
+    File::create(&upload_path)?;
+    metrics::ACTIVE_UPLOAD_SESSIONS.inc();
+    upload_hashes().insert(
+        upload_path,
+        UploadHash {
+            offset: 0,
+            hasher: algorithm.hasher(),
+        },
+    );
+    Ok(())
+}
 
-    let manifests_dir = format!("./tmp/manifests/{}/{}", sanitized_org, sanitized_repo);
-    let path = std::path::Path::new(&manifests_dir);
+/// Bytes received so far by an upload session
+pub(crate) fn upload_session_size(org: &str, repo: &str, uuid: &str) -> Result<u64, io::Error> {
+    Ok(std::fs::metadata(upload_path(org, repo, uuid))?.len())
+}
 
-    if !path.exists() {
-        return Ok(Vec::new());
+/// Number and total size of a repository's open upload sessions
+pub(crate) fn upload_sessions_usage(org: &str, repo: &str) -> Result<(usize, u64), io::Error> {
+    let dir = Path::new(UPLOADS_DIR)
+        .join(sanitize_string(org))
+        .join(sanitize_string(repo));
+    if !dir.exists() {
+        return Ok((0, 0));
     }
 
-    let mut tags = Vec::new();
+    let mut sessions = 0;
+    let mut bytes = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let metadata = entry?.metadata()?;
+        if metadata.is_file() {
+            sessions += 1;
+            bytes += metadata.len();
+        }
+    }
+    Ok((sessions, bytes))
+}
 
-    for entry in std::fs::read_dir(path)? {
-        let entry = entry?;
-        if entry.path().is_file() {
-            if let Some(filename) = entry.file_name().to_str() {
-                // Filter out digest references (64-char hex strings or sha256: prefixed)
-                // Only include tag names
-                let is_digest = filename.starts_with("sha256:")
-                    || (filename.len() == 64 && filename.chars().all(|c| c.is_ascii_hexdigit()));
+/// Number of open upload sessions across all repositories
+pub(crate) fn upload_session_count() -> Result<usize, io::Error> {
+    let root = Path::new(UPLOADS_DIR);
+    if !root.exists() {
+        return Ok(0);
+    }
 
-                if !is_digest {
-                    tags.push(filename.to_string());
+    let mut sessions = 0;
+    for org in std::fs::read_dir(root)? {
+        let org = org?.path();
+        if !org.is_dir() {
+            continue;
+        }
+        for repo in std::fs::read_dir(org)? {
+            let repo = repo?.path();
+            if !repo.is_dir() {
+                continue;
+            }
+            for entry in std::fs::read_dir(repo)? {
+                if entry?.metadata()?.is_file() {
+                    sessions += 1;
                 }
             }
         }
     }
-
-    // Sort tags alphabetically for consistent ordering
-    tags.sort();
-    Ok(tags)
+    Ok(sessions)
 }
 
-pub(crate) fn init_upload_session(org: &str, repo: &str, uuid: &str) -> Result<(), std::io::Error> {
-    let sanitized_org = sanitize_string(org);
-    let sanitized_repo = sanitize_string(repo);
-    let sanitized_uuid = sanitize_string(uuid);
-
-    let upload_dir = format!("./tmp/uploads/{}/{}", sanitized_org, sanitized_repo);
-    std::fs::create_dir_all(&upload_dir)?;
+/// Delete upload sessions that received no data for `max_age`, returning how many were removed
+/// and their total size. Abandoned sessions otherwise stay in `uploads/` forever.
+pub(crate) fn expire_upload_sessions(max_age: Duration) -> Result<(usize, u64), io::Error> {
+    let root = Path::new(UPLOADS_DIR);
+    if !root.exists() {
+        return Ok((0, 0));
+    }
 
-    let upload_path = format!("{}/{}", upload_dir, sanitized_uuid);
-    std::fs::File::create(upload_path)?;
-    Ok(())
+    let now = SystemTime::now();
+    let mut sessions = 0;
+    let mut bytes = 0;
+    for org in std::fs::read_dir(root)? {
+        let org = org?.path();
+        if !org.is_dir() {
+            continue;
+        }
+        for repo in std::fs::read_dir(org)? {
+            let repo = repo?.path();
+            if !repo.is_dir() {
+                continue;
+            }
+            for entry in std::fs::read_dir(repo)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                // Every chunk appended refreshes the modification time
+                let idle = now.duration_since(metadata.modified()?).unwrap_or_default();
+                if !metadata.is_file() || idle < max_age {
+                    continue;
+                }
+                let path = entry.path();
+                upload_hashes().remove(path.to_string_lossy().as_ref());
+                match std::fs::remove_file(&path) {
+                    Ok(()) => {
+                        sessions += 1;
+                        bytes += metadata.len();
+                        metrics::ACTIVE_UPLOAD_SESSIONS.dec();
+                    }
+                    // Finalized or cancelled in the meantime
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+    Ok((sessions, bytes))
 }
 
-pub(crate) fn append_upload_chunk(
+/// Stream a request body onto an upload session, keeping the session's total size within
+/// `max_size` (0 = unlimited). On failure the session is truncated back to its previous size
+/// so the client can retry the chunk.
+pub(crate) async fn append_upload_body(
     org: &str,
     repo: &str,
     uuid: &str,
-    chunk_data: &[u8],
-) -> Result<u64, std::io::Error> {
-    use std::fs::OpenOptions;
-
-    let sanitized_org = sanitize_string(org);
-    let sanitized_repo = sanitize_string(repo);
-    let sanitized_uuid = sanitize_string(uuid);
-
-    let upload_path = format!(
-        "./tmp/uploads/{}/{}/{}",
-        sanitized_org, sanitized_repo, sanitized_uuid
-    );
-
-    let mut file = OpenOptions::new().append(true).open(&upload_path)?;
-
-    file.write_all(chunk_data)?;
+    body: Body,
+    buffer_size: usize,
+    max_size: u64,
+) -> Result<u64, BodyError> {
+    let upload_path = upload_path(org, repo, uuid);
+    let file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .open(&upload_path)
+        .await?;
+    let start = file.metadata().await?.len();
+
+    let remaining = if max_size == 0 {
+        0
+    } else if start >= max_size {
+        return Err(BodyError::TooLarge(max_size));
+    } else {
+        max_size - start
+    };
 
-    let metadata = std::fs::metadata(&upload_path)?;
-    Ok(metadata.len())
+    // Keep hashing only if the running digest covers everything already on disk
+    let mut hash = upload_hashes()
+        .get(&upload_path)
+        .filter(|hash| hash.offset == start)
+        .cloned();
+
+    let truncate_file = file.try_clone().await?;
+    let result = body::stream_to(
+        body,
+        file,
+        buffer_size,
+        remaining,
+        hash.as_mut().map(|hash| &mut hash.hasher),
+    )
+    .await;
+
+    match result {
+        Ok(written) => {
+            if let Some(mut hash) = hash {
+                hash.offset = start + written;
+                upload_hashes().insert(upload_path, hash);
+            }
+            Ok(start + written)
+        }
+        Err(BodyError::TooLarge(_)) => {
+            let _ = truncate_file.set_len(start).await;
+            Err(BodyError::TooLarge(max_size))
+        }
+        Err(e) => {
+            let _ = truncate_file.set_len(start).await;
+            Err(e)
+        }
+    }
 }
 
 pub(crate) fn finalize_upload(
     org: &str,
     repo: &str,
     uuid: &str,
-    expected_digest: &str,
-) -> Result<String, String> {
-    let sanitized_org = sanitize_string(org);
-    let sanitized_repo = sanitize_string(repo);
-    let sanitized_uuid = sanitize_string(uuid);
-
-    let upload_path = format!(
-        "./tmp/uploads/{}/{}/{}",
-        sanitized_org, sanitized_repo, sanitized_uuid
-    );
-
-    let upload_data =
-        std::fs::read(&upload_path).map_err(|e| format!("Failed to read upload: {}", e))?;
+    expected_digest: &Digest,
+    cancel: &CancelToken,
+) -> Result<Digest, StorageError> {
+    let upload_path = upload_path(org, repo, uuid);
+    let size = match std::fs::metadata(&upload_path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(StorageError::UploadNotFound(uuid.to_string()))
+        }
+        Err(e) => return Err(e.into()),
+    };
 
-    let actual_digest = sha256::digest(&upload_data);
-    let clean_expected = expected_digest
-        .strip_prefix("sha256:")
-        .unwrap_or(expected_digest);
+    let running = upload_hashes()
+        .get(&upload_path)
+        .filter(|hash| hash.offset == size)
+        .map(|hash| hash.hasher.clone())
+        .filter(|hasher| hasher.algorithm() == expected_digest.algorithm());
+
+    // Without a running hash, a backend that checks digests itself stores the upload as is,
+    // instead of it being read once to hash it and again to store it
+    let offloaded = running.is_none()
+        && backend().store_checked_upload(org, repo, expected_digest, Path::new(&upload_path))?;
+    let actual_digest = match running {
+        Some(hasher) => hasher.finalize(),
+        None if offloaded => expected_digest.clone(),
+        None => {
+            // Hash in chunks so an abandoned request stops reading promptly; the session is kept
+            // for retries
+            log::debug!("storage: hashing upload {} from disk", uuid);
+            // Upload sessions are local whatever the backend
+            metrics::observe_storage_operation("uploads", "hash_upload", || {
+                hash_file(&upload_path, expected_digest.algorithm(), cancel)
+            })?
+        }
+    };
 
-    if actual_digest != clean_expected {
-        return Err(format!(
-            "Digest mismatch: expected {}, got {}",
-            clean_expected, actual_digest
-        ));
+    if actual_digest != *expected_digest {
+        return Err(StorageError::DigestMismatch {
+            expected: expected_digest.clone(),
+            actual: actual_digest,
+        });
     }
 
-    let blob_dir = format!("./tmp/blobs/{}/{}", sanitized_org, sanitized_repo);
-    std::fs::create_dir_all(&blob_dir).map_err(|e| format!("Failed to create blob dir: {}", e))?;
+    if !offloaded {
+        backend().store_upload(org, repo, &actual_digest, Path::new(&upload_path))?;
+    }
+    holders::added(org, repo, &actual_digest);
+    upload_hashes().remove(&upload_path);
+    metrics::ACTIVE_UPLOAD_SESSIONS.dec();
 
-    let blob_path = format!("{}/{}", blob_dir, actual_digest);
-    std::fs::rename(&upload_path, &blob_path)
-        .map_err(|e| format!("Failed to move upload to blob: {}", e))?;
+    changelog::record(Change::BlobPut {
+        org: org.to_string(),
+        repo: repo.to_string(),
+        digest: actual_digest.to_string(),
+    });
 
     Ok(actual_digest)
 }
 
-pub(crate) fn delete_upload_session(
+/// Store a blob read from `reader`, staged and verified like an upload
+pub(crate) fn import_blob(
     org: &str,
     repo: &str,
-    uuid: &str,
-) -> Result<(), std::io::Error> {
-    let sanitized_org = sanitize_string(org);
-    let sanitized_repo = sanitize_string(repo);
-    let sanitized_uuid = sanitize_string(uuid);
-
-    let upload_path = format!(
-        "./tmp/uploads/{}/{}/{}",
-        sanitized_org, sanitized_repo, sanitized_uuid
-    );
-    std::fs::remove_file(upload_path)
+    digest: &Digest,
+    reader: &mut impl Read,
+    cancel: &CancelToken,
+) -> Result<(), StorageError> {
+    let uuid = uuid::Uuid::new_v4().to_string();
+    init_upload_session(org, repo, &uuid, digest.algorithm())?;
+    let result = (|| -> Result<(), StorageError> {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(upload_path(org, repo, &uuid))?;
+        io::copy(reader, &mut file)?;
+        finalize_upload(org, repo, &uuid, digest, cancel)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = delete_upload_session(org, repo, &uuid);
+    }
+    result
 }
 
-pub(crate) fn delete_manifest(
-    org: &str,
-    repo: &str,
-    reference: &str,
-) -> Result<(), std::io::Error> {
-    let sanitized_org = sanitize_string(org);
-    let sanitized_repo = sanitize_string(repo);
-    let sanitized_reference = sanitize_string(reference);
-
-    let manifest_path = format!(
-        "./tmp/manifests/{}/{}/{}",
-        sanitized_org, sanitized_repo, sanitized_reference
-    );
+/// Digest of a file, checking for cancellation between chunks
+fn hash_file(path: &str, algorithm: Algorithm, cancel: &CancelToken) -> Result<Digest, io::Error> {
+    let mut file = File::open(path)?;
+    let mut hasher = algorithm.hasher();
+    let mut buf = vec![0u8; HASH_CHUNK_SIZE];
+
+    loop {
+        cancel.check()?;
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
 
-    if !std::path::Path::new(&manifest_path).exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "Manifest not found",
-        ));
+    Ok(hasher.finalize())
+}
+
+pub(crate) fn delete_upload_session(org: &str, repo: &str, uuid: &str) -> Result<(), io::Error> {
+    let upload_path = upload_path(org, repo, uuid);
+    upload_hashes().remove(&upload_path);
+    std::fs::remove_file(upload_path)?;
+    metrics::ACTIVE_UPLOAD_SESSIONS.dec();
+    Ok(())
+}
+
+pub(crate) fn delete_manifest(org: &str, repo: &str, reference: &str) -> Result<(), StorageError> {
+    if !manifest_exists(org, repo, reference) {
+        return Err(StorageError::ManifestNotFound(reference.to_string()));
     }
 
-    std::fs::remove_file(manifest_path)
+    backend().delete_manifest(org, repo, reference)?;
+    delete_manifest_meta(org, repo, reference)?;
+    referrers::forget(org, repo, reference);
+
+    changelog::record(Change::ManifestDelete {
+        org: org.to_string(),
+        repo: repo.to_string(),
+        reference: reference.to_string(),
+    });
+
+    Ok(())
 }
 
-pub(crate) fn delete_blob(org: &str, repo: &str, digest: &str) -> Result<(), std::io::Error> {
-    let sanitized_org = sanitize_string(org);
-    let sanitized_repo = sanitize_string(repo);
-    let sanitized_digest = sanitize_string(digest);
+pub(crate) fn delete_blob(org: &str, repo: &str, digest: &Digest) -> Result<(), StorageError> {
+    if blob_metadata(org, repo, digest).is_err() {
+        return Err(StorageError::BlobNotFound(digest.clone()));
+    }
 
-    let blob_path = format!(
-        "./tmp/blobs/{}/{}/{}",
-        sanitized_org, sanitized_repo, sanitized_digest
-    );
+    backend().delete_blob(org, repo, digest)?;
+    holders::removed(org, repo, digest);
+    delete_mount_record(org, repo, digest)?;
+
+    changelog::record(Change::BlobDelete {
+        org: org.to_string(),
+        repo: repo.to_string(),
+        digest: digest.to_string(),
+    });
 
-    if !std::path::Path::new(&blob_path).exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "Blob not found",
-        ));
+    Ok(())
+}
+
+/// Finish deleting a manifest whose deletion may have been interrupted: remove it if still
+/// present, and record the deletion either way
+pub(crate) fn purge_manifest(org: &str, repo: &str, reference: &str) -> Result<(), StorageError> {
+    match delete_manifest(org, repo, reference) {
+        Err(StorageError::ManifestNotFound(_)) => {
+            delete_manifest_meta(org, repo, reference)?;
+            referrers::forget(org, repo, reference);
+            changelog::record(Change::ManifestDelete {
+                org: org.to_string(),
+                repo: repo.to_string(),
+                reference: reference.to_string(),
+            });
+            Ok(())
+        }
+        result => result,
     }
+}
 
-    std::fs::remove_file(blob_path)
+/// Finish deleting a blob whose deletion may have been interrupted, including its mount record
+pub(crate) fn purge_blob(org: &str, repo: &str, digest: &Digest) -> Result<(), StorageError> {
+    match delete_blob(org, repo, digest) {
+        Err(StorageError::BlobNotFound(_)) => {
+            delete_mount_record(org, repo, digest)?;
+            changelog::record(Change::BlobDelete {
+                org: org.to_string(),
+                repo: repo.to_string(),
+                digest: digest.to_string(),
+            });
+            Ok(())
+        }
+        result => result,
+    }
 }
 
+/// Mount a blob from another repository, returning whether a new copy was linked
 pub(crate) fn mount_blob(
     source_org: &str,
     source_repo: &str,
     target_org: &str,
     target_repo: &str,
-    digest: &str,
-) -> Result<(), std::io::Error> {
-    let sanitized_source_org = sanitize_string(source_org);
-    let sanitized_source_repo = sanitize_string(source_repo);
-    let sanitized_target_org = sanitize_string(target_org);
-    let sanitized_target_repo = sanitize_string(target_repo);
-    let sanitized_digest = sanitize_string(digest);
-
-    // Check if blob exists in source repository
-    let source_path = format!(
-        "./tmp/blobs/{}/{}/{}",
-        sanitized_source_org, sanitized_source_repo, sanitized_digest
-    );
+    digest: &Digest,
+) -> Result<bool, io::Error> {
+    let linked = backend().copy_blob(source_org, source_repo, target_org, target_repo, digest)?;
+    holders::added(target_org, target_repo, digest);
+
+    if linked {
+        changelog::record(Change::BlobPut {
+            org: target_org.to_string(),
+            repo: target_repo.to_string(),
+            digest: digest.to_string(),
+        });
+    }
+
+    Ok(linked)
+}
+
+/// Origin of a blob copied into a repository by cross-repository mount
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct MountRecord {
+    /// Source repository (org/repo)
+    pub source: String,
+    pub mounted_by: String,
+    /// Unix time of the mount
+    pub mounted_at: u64,
+}
+
+pub(crate) fn write_mount_record(
+    org: &str,
+    repo: &str,
+    digest: &Digest,
+    record: &MountRecord,
+) -> Result<(), io::Error> {
+    backend().write_mount_record(org, repo, digest, &serde_json::to_vec(record)?)
+}
+
+pub(crate) fn read_mount_record(org: &str, repo: &str, digest: &Digest) -> Option<MountRecord> {
+    let data = backend().read_mount_record(org, repo, digest).ok()?;
+    serde_json::from_slice(&data).ok()
+}
 
-    if !std::path::Path::new(&source_path).exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "Source blob not found",
-        ));
+/// Remove the mount record of a deleted blob (no-op for uploaded blobs)
+pub(crate) fn delete_mount_record(org: &str, repo: &str, digest: &Digest) -> Result<(), io::Error> {
+    match backend().delete_mount_record(org, repo, digest) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
     }
+}
 
-    // Create target directory
-    let target_dir = format!(
-        "./tmp/blobs/{}/{}",
-        sanitized_target_org, sanitized_target_repo
-    );
-    std::fs::create_dir_all(&target_dir)?;
+/// List `(org, repo, reference)` for every stored manifest
+pub(crate) fn list_manifests() -> Result<Vec<(String, String, String)>, io::Error> {
+    backend().list_manifests()
+}
 
-    // Create target path
-    let target_path = format!("{}/{}", target_dir, sanitized_digest);
+/// List `(org, repo, digest)` for every stored blob
+pub(crate) fn list_blobs() -> Result<Vec<(String, String, Digest)>, io::Error> {
+    backend().list_blobs()
+}
 
-    // If target already exists, that's fine (already mounted)
-    if std::path::Path::new(&target_path).exists() {
-        return Ok(());
+/// Digests of the blobs stored in `org/repo`
+pub(crate) fn list_repository_blobs(org: &str, repo: &str) -> Result<Vec<Digest>, io::Error> {
+    backend().list_repository_blobs(org, repo)
+}
+
+/// `org/repo` names of every repository storing the blob `digest`, sorted, from an index
+/// rather than a listing of every blob
+pub(crate) fn repositories_with_blob(digest: &Digest) -> Result<Vec<String>, io::Error> {
+    holders::of(digest)
+}
+
+/// `org/repo` names of every repository holding a manifest or a blob, sorted
+pub(crate) fn list_repositories() -> Result<Vec<String>, io::Error> {
+    let manifests = backend().list_manifests()?;
+    let blobs = backend().list_blobs()?;
+
+    let names: std::collections::BTreeSet<String> = manifests
+        .into_iter()
+        .map(|(org, repo, _)| format!("{}/{}", org, repo))
+        .chain(
+            blobs
+                .into_iter()
+                .map(|(org, repo, _)| format!("{}/{}", org, repo)),
+        )
+        .collect();
+    Ok(names.into_iter().collect())
+}
+
+/// Number and total size of one repository's blobs
+pub(crate) fn repository_blob_usage(org: &str, repo: &str) -> Result<(usize, u64), io::Error> {
+    let (mut count, mut total) = (0, 0);
+    for digest in backend().list_repository_blobs(org, repo)? {
+        count += 1;
+        total += backend().blob_metadata(org, repo, &digest)?.size;
     }
+    Ok((count, total))
+}
 
-    // Try hard link first (most efficient - no data duplication)
-    if std::fs::hard_link(&source_path, &target_path).is_err() {
-        // If hard link fails (cross-device), copy the file
-        std::fs::copy(&source_path, &target_path)?;
+/// Blobs, their total size and manifests (by digest) held in storage, as sampled for `/metrics`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Usage {
+    pub(crate) blobs: usize,
+    pub(crate) blob_bytes: u64,
+    pub(crate) manifests: usize,
+}
+
+/// Count every stored blob and manifest. Blobs stored in several repositories count once per
+/// repository, as they take space once per repository.
+pub(crate) fn usage() -> Result<Usage, io::Error> {
+    let blobs = backend().list_blobs()?;
+    let mut blob_bytes = 0;
+    for (org, repo, digest) in &blobs {
+        match backend().blob_metadata(org, repo, digest) {
+            Ok(meta) => blob_bytes += meta.size,
+            // Deleted since it was listed
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
     }
+    Ok(Usage {
+        blobs: blobs.len(),
+        blob_bytes,
+        manifests: backend()
+            .list_manifests()?
+            .iter()
+            .filter(|(_, _, reference)| is_digest_reference(reference))
+            .count(),
+    })
+}
 
-    Ok(())
+/// List `(org, repo, digest)` for every blob copied in by cross-repository mount
+pub(crate) fn list_mount_records() -> Result<Vec<(String, String, Digest)>, io::Error> {
+    backend().list_mount_records()
+}
+
+/// Version of the key layout, reported by `/admin/cluster`: 2 since blobs and mount records
+/// are namespaced by digest algorithm
+pub(crate) const LAYOUT_VERSION: u32 = 2;
+
+/// Bring data written by older versions up to the current layout.
+/// Returns the number of objects moved.
+pub(crate) fn migrate_digest_layout() -> Result<usize, io::Error> {
+    backend().migrate()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_file_atomic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.json");
+
+        write_file_atomic(&path, b"first").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        write_file_atomic(&path, b"second").unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // No temporary file is left behind
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
 }
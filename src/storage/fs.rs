@@ -0,0 +1,371 @@
+use std::{
+    ffi::OsStr,
+    fs::{create_dir_all, File},
+    io::{self, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use super::{BlobReader, ObjectMeta, StorageBackend};
+use crate::digest::{Algorithm, Digest};
+
+/// Local filesystem backend, rooted at `./tmp` by default
+pub(crate) struct FsBackend {
+    root: PathBuf,
+}
+
+impl FsBackend {
+    pub(crate) fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+
+    fn write(&self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        let path = self.path(key);
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
+
+        super::write_file_atomic(&path, bytes)?;
+
+        log::info!("storage/fs: wrote {}", path.display());
+        Ok(())
+    }
+
+    fn metadata(&self, key: &str) -> io::Result<ObjectMeta> {
+        let metadata = std::fs::metadata(self.path(key))?;
+        if !metadata.is_file() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "not a file"));
+        }
+        Ok(ObjectMeta {
+            size: metadata.len(),
+            modified: metadata.modified()?,
+        })
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()> {
+        std::fs::remove_file(self.path(key))
+    }
+
+    /// List `(org, repo, digest)` for every file under a digest-addressed section
+    /// (`blobs` or `mounts`), laid out as `<org>/<repo>/<algorithm>/<hex>`
+    fn list_digest_files(&self, section: &str) -> io::Result<Vec<(String, String, Digest)>> {
+        let root = self.path(section);
+        let mut files = Vec::new();
+
+        for (org, repo, algorithm_dir) in list_repository_entries(&root, false)? {
+            for digest in digest_files(&root.join(&org).join(&repo).join(&algorithm_dir))? {
+                files.push((org.clone(), repo.clone(), digest));
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Digests of the files under one repository's `<section>/<org>/<repo>/<algorithm>/<hex>`
+    fn list_repository_digests(
+        &self,
+        section: &str,
+        org: &str,
+        repo: &str,
+    ) -> io::Result<Vec<Digest>> {
+        let dir = self.path(section).join(org).join(repo);
+        let mut digests = Vec::new();
+        if !dir.is_dir() {
+            return Ok(digests);
+        }
+
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.path().is_dir() {
+                digests.extend(digest_files(&entry.path())?);
+            }
+        }
+
+        Ok(digests)
+    }
+}
+
+/// Digests of the files in an `<algorithm>` directory; none when the algorithm is unknown
+fn digest_files(dir: &Path) -> io::Result<Vec<Digest>> {
+    let Some(algorithm) = (dir.file_name())
+        .and_then(|name| name.to_str())
+        .and_then(Algorithm::parse)
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut digests = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.path().is_file() || is_temporary(&entry.file_name()) {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        match Digest::parse(&format!("{}:{}", algorithm.as_str(), name)) {
+            Ok(digest) => digests.push(digest),
+            Err(_) => log::warn!(
+                "storage: ignoring unexpected file {}",
+                entry.path().display()
+            ),
+        }
+    }
+    Ok(digests)
+}
+
+/// Files being written by `write_file_atomic`, which are not stored objects yet
+fn is_temporary(name: &OsStr) -> bool {
+    name.to_string_lossy().starts_with('.')
+}
+
+/// List `(org, repo, name)` for every file (or directory, when `files` is false) directly
+/// inside a repository directory under `root`
+fn list_repository_entries(root: &Path, files: bool) -> io::Result<Vec<(String, String, String)>> {
+    let mut entries = Vec::new();
+
+    if !root.exists() {
+        return Ok(entries);
+    }
+
+    for org_entry in std::fs::read_dir(root)? {
+        let org_entry = org_entry?;
+        if !org_entry.path().is_dir() {
+            continue;
+        }
+        let org = org_entry.file_name().to_string_lossy().to_string();
+
+        for repo_entry in std::fs::read_dir(org_entry.path())? {
+            let repo_entry = repo_entry?;
+            if !repo_entry.path().is_dir() {
+                continue;
+            }
+            let repo = repo_entry.file_name().to_string_lossy().to_string();
+
+            for entry in std::fs::read_dir(repo_entry.path())? {
+                let entry = entry?;
+                let path = entry.path();
+                if is_temporary(&entry.file_name()) {
+                    continue;
+                }
+                if (files && path.is_file()) || (!files && path.is_dir()) {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    entries.push((org.clone(), repo.clone(), name));
+                }
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+impl StorageBackend for FsBackend {
+    fn location(&self, section: &str) -> String {
+        self.path(section).display().to_string()
+    }
+
+    fn read_blob(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<Vec<u8>> {
+        std::fs::read(self.path(&super::blob_key(org, repo, digest)))
+    }
+
+    fn open_blob(
+        &self,
+        org: &str,
+        repo: &str,
+        digest: &Digest,
+        offset: u64,
+    ) -> io::Result<(BlobReader, u64)> {
+        let mut file = File::open(self.path(&super::blob_key(org, repo, digest)))?;
+        let size = file.metadata()?.len();
+        if offset > 0 {
+            file.seek(SeekFrom::Start(offset))?;
+        }
+        Ok((Box::pin(tokio::fs::File::from_std(file)), size))
+    }
+
+    fn blob_metadata(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<ObjectMeta> {
+        self.metadata(&super::blob_key(org, repo, digest))
+    }
+
+    fn write_blob(&self, org: &str, repo: &str, digest: &Digest, bytes: &[u8]) -> io::Result<()> {
+        self.write(&super::blob_key(org, repo, digest), bytes)
+    }
+
+    fn store_upload(
+        &self,
+        org: &str,
+        repo: &str,
+        digest: &Digest,
+        upload: &Path,
+    ) -> io::Result<()> {
+        let blob_path = self.path(&super::blob_key(org, repo, digest));
+        let blob_dir = blob_path.parent().unwrap_or(self.root.as_path());
+        create_dir_all(blob_dir)?;
+        // The session is flushed before it is renamed into place, so a crash cannot leave a
+        // truncated blob under a verified digest
+        File::open(upload)?.sync_all()?;
+        std::fs::rename(upload, &blob_path)?;
+        super::sync_dir(blob_dir)
+    }
+
+    fn delete_blob(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<()> {
+        self.remove(&super::blob_key(org, repo, digest))
+    }
+
+    fn copy_blob(
+        &self,
+        source_org: &str,
+        source_repo: &str,
+        target_org: &str,
+        target_repo: &str,
+        digest: &Digest,
+    ) -> io::Result<bool> {
+        let source_path = self.path(&super::blob_key(source_org, source_repo, digest));
+        if !source_path.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Source blob not found",
+            ));
+        }
+
+        let target_path = self.path(&super::blob_key(target_org, target_repo, digest));
+        if let Some(target_dir) = target_path.parent() {
+            create_dir_all(target_dir)?;
+        }
+
+        // If target already exists, that's fine (already mounted)
+        if target_path.exists() {
+            return Ok(false);
+        }
+
+        // Try hard link first (most efficient - no data duplication)
+        if std::fs::hard_link(&source_path, &target_path).is_err() {
+            // If hard link fails (cross-device), copy the file
+            std::fs::copy(&source_path, &target_path)?;
+        }
+
+        Ok(true)
+    }
+
+    fn list_blobs(&self) -> io::Result<Vec<(String, String, Digest)>> {
+        self.list_digest_files("blobs")
+    }
+
+    fn list_repository_blobs(&self, org: &str, repo: &str) -> io::Result<Vec<Digest>> {
+        self.list_repository_digests("blobs", org, repo)
+    }
+
+    fn read_manifest(&self, org: &str, repo: &str, reference: &str) -> io::Result<Vec<u8>> {
+        std::fs::read(self.path(&super::manifest_key(org, repo, reference)))
+    }
+
+    fn manifest_metadata(&self, org: &str, repo: &str, reference: &str) -> io::Result<ObjectMeta> {
+        self.metadata(&super::manifest_key(org, repo, reference))
+    }
+
+    fn write_manifest(
+        &self,
+        org: &str,
+        repo: &str,
+        reference: &str,
+        bytes: &[u8],
+    ) -> io::Result<()> {
+        self.write(&super::manifest_key(org, repo, reference), bytes)
+    }
+
+    fn delete_manifest(&self, org: &str, repo: &str, reference: &str) -> io::Result<()> {
+        self.remove(&super::manifest_key(org, repo, reference))
+    }
+
+    fn read_manifest_meta(&self, org: &str, repo: &str, reference: &str) -> io::Result<Vec<u8>> {
+        std::fs::read(self.path(&super::manifest_meta_key(org, repo, reference)))
+    }
+
+    fn write_manifest_meta(
+        &self,
+        org: &str,
+        repo: &str,
+        reference: &str,
+        bytes: &[u8],
+    ) -> io::Result<()> {
+        self.write(&super::manifest_meta_key(org, repo, reference), bytes)
+    }
+
+    fn delete_manifest_meta(&self, org: &str, repo: &str, reference: &str) -> io::Result<()> {
+        self.remove(&super::manifest_meta_key(org, repo, reference))
+    }
+
+    fn list_references(&self, org: &str, repo: &str) -> io::Result<Vec<String>> {
+        let dir = self.path(&super::manifest_key(org, repo, ""));
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut references = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.path().is_file() && !is_temporary(&entry.file_name()) {
+                references.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        Ok(references)
+    }
+
+    fn list_manifests(&self) -> io::Result<Vec<(String, String, String)>> {
+        list_repository_entries(&self.path("manifests"), true)
+    }
+
+    fn read_mount_record(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<Vec<u8>> {
+        std::fs::read(self.path(&super::mount_key(org, repo, digest)))
+    }
+
+    fn write_mount_record(
+        &self,
+        org: &str,
+        repo: &str,
+        digest: &Digest,
+        bytes: &[u8],
+    ) -> io::Result<()> {
+        self.write(&super::mount_key(org, repo, digest), bytes)
+    }
+
+    fn delete_mount_record(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<()> {
+        self.remove(&super::mount_key(org, repo, digest))
+    }
+
+    fn list_mount_records(&self) -> io::Result<Vec<(String, String, Digest)>> {
+        self.list_digest_files("mounts")
+    }
+
+    fn is_accessible(&self) -> bool {
+        self.path("blobs").exists() && self.path("manifests").exists()
+    }
+
+    fn is_writable(&self) -> bool {
+        let test_file = self.path(".health_check");
+        std::fs::write(&test_file, "test").is_ok() && std::fs::remove_file(&test_file).is_ok()
+    }
+
+    /// Move blobs and mount records from the flat `<org>/<repo>/<hex>` layout into
+    /// `<org>/<repo>/sha256/<hex>`
+    fn migrate(&self) -> io::Result<usize> {
+        let mut moved = 0;
+
+        for section in ["blobs", "mounts"] {
+            let root = self.path(section);
+            for (org, repo, name) in list_repository_entries(&root, true)? {
+                let Ok(digest) = Digest::parse(&name) else {
+                    continue;
+                };
+                let repo_dir = root.join(&org).join(&repo);
+                let to_dir = repo_dir.join(digest.algorithm().as_str());
+                create_dir_all(&to_dir)?;
+                std::fs::rename(repo_dir.join(&name), to_dir.join(digest.hex()))?;
+                moved += 1;
+            }
+        }
+
+        Ok(moved)
+    }
+}
@@ -0,0 +1,152 @@
+//! Repositories storing each blob digest, so finding where a blob is stored (mounts without
+//! `from`, admin blob details) does not list every blob in the registry. The index is built
+//! from one listing on first use and then kept up to date by the storage facade, which calls
+//! `added`/`removed` wherever it records a blob change. It holds a digest and a repository name
+//! per stored blob.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    io,
+    sync::Mutex,
+};
+
+use crate::digest::Digest;
+
+enum Index {
+    Unbuilt,
+    /// Changes made while the listing the index is built from runs, applied once it is done
+    Building(Vec<(Change, String, Digest)>),
+    Built(HashMap<Digest, BTreeSet<String>>),
+}
+
+#[derive(Clone, Copy)]
+enum Change {
+    Added,
+    Removed,
+}
+
+static INDEX: Mutex<Index> = Mutex::new(Index::Unbuilt);
+/// Held while the index is built, so concurrent lookups wait for one listing
+static BUILD: Mutex<()> = Mutex::new(());
+
+fn apply(
+    holders: &mut HashMap<Digest, BTreeSet<String>>,
+    change: Change,
+    repository: String,
+    digest: Digest,
+) {
+    match change {
+        Change::Added => {
+            holders.entry(digest).or_default().insert(repository);
+        }
+        Change::Removed => {
+            if let Some(repositories) = holders.get_mut(&digest) {
+                repositories.remove(&repository);
+                if repositories.is_empty() {
+                    holders.remove(&digest);
+                }
+            }
+        }
+    }
+}
+
+fn record(change: Change, org: &str, repo: &str, digest: &Digest) {
+    let repository = format!("{}/{}", org, repo);
+    match &mut *INDEX.lock().unwrap() {
+        Index::Unbuilt => {}
+        Index::Building(pending) => pending.push((change, repository, digest.clone())),
+        Index::Built(holders) => apply(holders, change, repository, digest.clone()),
+    }
+}
+
+pub(super) fn added(org: &str, repo: &str, digest: &Digest) {
+    record(Change::Added, org, repo, digest);
+}
+
+pub(super) fn removed(org: &str, repo: &str, digest: &Digest) {
+    record(Change::Removed, org, repo, digest);
+}
+
+fn lookup(digest: &Digest) -> Option<Vec<String>> {
+    match &*INDEX.lock().unwrap() {
+        Index::Built(holders) => Some(
+            holders
+                .get(digest)
+                .map(|repositories| repositories.iter().cloned().collect())
+                .unwrap_or_default(),
+        ),
+        _ => None,
+    }
+}
+
+/// `org/repo` names of the repositories storing `digest`, sorted
+pub(super) fn of(digest: &Digest) -> io::Result<Vec<String>> {
+    if let Some(repositories) = lookup(digest) {
+        return Ok(repositories);
+    }
+
+    let _build = BUILD.lock().unwrap();
+    if let Some(repositories) = lookup(digest) {
+        return Ok(repositories);
+    }
+    *INDEX.lock().unwrap() = Index::Building(Vec::new());
+    let listed = super::backend().list_blobs();
+
+    let mut index = INDEX.lock().unwrap();
+    let Index::Building(pending) = std::mem::replace(&mut *index, Index::Unbuilt) else {
+        unreachable!("only the build leaves the building state");
+    };
+    let mut holders = HashMap::new();
+    for (org, repo, blob) in listed? {
+        apply(
+            &mut holders,
+            Change::Added,
+            format!("{}/{}", org, repo),
+            blob,
+        );
+    }
+    for (change, repository, blob) in pending {
+        apply(&mut holders, change, repository, blob);
+    }
+    *index = Index::Built(holders);
+    drop(index);
+
+    Ok(lookup(digest).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let digest = Digest::of(crate::digest::Algorithm::Sha256, b"blob");
+        let mut holders = HashMap::new();
+        apply(&mut holders, Change::Added, "b/repo".into(), digest.clone());
+        apply(&mut holders, Change::Added, "a/repo".into(), digest.clone());
+        assert_eq!(
+            holders[&digest].iter().collect::<Vec<_>>(),
+            ["a/repo", "b/repo"]
+        );
+
+        apply(
+            &mut holders,
+            Change::Removed,
+            "a/repo".into(),
+            digest.clone(),
+        );
+        apply(
+            &mut holders,
+            Change::Removed,
+            "c/repo".into(),
+            digest.clone(),
+        );
+        apply(
+            &mut holders,
+            Change::Removed,
+            "b/repo".into(),
+            digest.clone(),
+        );
+        assert!(holders.is_empty());
+    }
+}
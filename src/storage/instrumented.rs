@@ -0,0 +1,210 @@
+use std::{io, path::Path};
+
+use super::{BlobReader, ObjectMeta, StorageBackend};
+use crate::{digest::Digest, metrics};
+
+/// Wraps a backend, timing every operation and counting failures per backend and operation.
+/// Missing objects are expected (existence checks) and not counted as errors.
+pub(crate) struct Instrumented {
+    name: &'static str,
+    inner: Box<dyn StorageBackend>,
+}
+
+impl Instrumented {
+    pub(crate) fn new(name: &'static str, inner: Box<dyn StorageBackend>) -> Self {
+        Self { name, inner }
+    }
+
+    fn timed<T>(&self, operation: &str, f: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+        metrics::observe_storage_operation(self.name, operation, f)
+    }
+}
+
+impl StorageBackend for Instrumented {
+    fn location(&self, section: &str) -> String {
+        self.inner.location(section)
+    }
+
+    fn read_blob(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<Vec<u8>> {
+        self.timed("read_blob", || self.inner.read_blob(org, repo, digest))
+    }
+
+    fn open_blob(
+        &self,
+        org: &str,
+        repo: &str,
+        digest: &Digest,
+        offset: u64,
+    ) -> io::Result<(BlobReader, u64)> {
+        // Covers opening only; the body is streamed afterwards
+        self.timed("open_blob", || {
+            self.inner.open_blob(org, repo, digest, offset)
+        })
+    }
+
+    fn blob_metadata(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<ObjectMeta> {
+        self.timed("blob_metadata", || {
+            self.inner.blob_metadata(org, repo, digest)
+        })
+    }
+
+    fn write_blob(&self, org: &str, repo: &str, digest: &Digest, bytes: &[u8]) -> io::Result<()> {
+        self.timed("write_blob", || {
+            self.inner.write_blob(org, repo, digest, bytes)
+        })
+    }
+
+    fn store_upload(
+        &self,
+        org: &str,
+        repo: &str,
+        digest: &Digest,
+        upload: &Path,
+    ) -> io::Result<()> {
+        self.timed("store_upload", || {
+            self.inner.store_upload(org, repo, digest, upload)
+        })
+    }
+
+    fn store_checked_upload(
+        &self,
+        org: &str,
+        repo: &str,
+        digest: &Digest,
+        upload: &Path,
+    ) -> io::Result<bool> {
+        self.timed("store_checked_upload", || {
+            self.inner.store_checked_upload(org, repo, digest, upload)
+        })
+    }
+
+    fn delete_blob(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<()> {
+        self.timed("delete_blob", || self.inner.delete_blob(org, repo, digest))
+    }
+
+    fn copy_blob(
+        &self,
+        source_org: &str,
+        source_repo: &str,
+        target_org: &str,
+        target_repo: &str,
+        digest: &Digest,
+    ) -> io::Result<bool> {
+        self.timed("copy_blob", || {
+            self.inner
+                .copy_blob(source_org, source_repo, target_org, target_repo, digest)
+        })
+    }
+
+    fn list_blobs(&self) -> io::Result<Vec<(String, String, Digest)>> {
+        self.timed("list_blobs", || self.inner.list_blobs())
+    }
+
+    fn list_repository_blobs(&self, org: &str, repo: &str) -> io::Result<Vec<Digest>> {
+        self.timed("list_repository_blobs", || {
+            self.inner.list_repository_blobs(org, repo)
+        })
+    }
+
+    fn read_manifest(&self, org: &str, repo: &str, reference: &str) -> io::Result<Vec<u8>> {
+        self.timed("read_manifest", || {
+            self.inner.read_manifest(org, repo, reference)
+        })
+    }
+
+    fn manifest_metadata(&self, org: &str, repo: &str, reference: &str) -> io::Result<ObjectMeta> {
+        self.timed("manifest_metadata", || {
+            self.inner.manifest_metadata(org, repo, reference)
+        })
+    }
+
+    fn write_manifest(
+        &self,
+        org: &str,
+        repo: &str,
+        reference: &str,
+        bytes: &[u8],
+    ) -> io::Result<()> {
+        self.timed("write_manifest", || {
+            self.inner.write_manifest(org, repo, reference, bytes)
+        })
+    }
+
+    fn delete_manifest(&self, org: &str, repo: &str, reference: &str) -> io::Result<()> {
+        self.timed("delete_manifest", || {
+            self.inner.delete_manifest(org, repo, reference)
+        })
+    }
+
+    fn read_manifest_meta(&self, org: &str, repo: &str, reference: &str) -> io::Result<Vec<u8>> {
+        self.timed("read_manifest_meta", || {
+            self.inner.read_manifest_meta(org, repo, reference)
+        })
+    }
+
+    fn write_manifest_meta(
+        &self,
+        org: &str,
+        repo: &str,
+        reference: &str,
+        bytes: &[u8],
+    ) -> io::Result<()> {
+        self.timed("write_manifest_meta", || {
+            self.inner.write_manifest_meta(org, repo, reference, bytes)
+        })
+    }
+
+    fn delete_manifest_meta(&self, org: &str, repo: &str, reference: &str) -> io::Result<()> {
+        self.timed("delete_manifest_meta", || {
+            self.inner.delete_manifest_meta(org, repo, reference)
+        })
+    }
+
+    fn list_references(&self, org: &str, repo: &str) -> io::Result<Vec<String>> {
+        self.timed("list_references", || self.inner.list_references(org, repo))
+    }
+
+    fn list_manifests(&self) -> io::Result<Vec<(String, String, String)>> {
+        self.timed("list_manifests", || self.inner.list_manifests())
+    }
+
+    fn read_mount_record(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<Vec<u8>> {
+        self.timed("read_mount_record", || {
+            self.inner.read_mount_record(org, repo, digest)
+        })
+    }
+
+    fn write_mount_record(
+        &self,
+        org: &str,
+        repo: &str,
+        digest: &Digest,
+        bytes: &[u8],
+    ) -> io::Result<()> {
+        self.timed("write_mount_record", || {
+            self.inner.write_mount_record(org, repo, digest, bytes)
+        })
+    }
+
+    fn delete_mount_record(&self, org: &str, repo: &str, digest: &Digest) -> io::Result<()> {
+        self.timed("delete_mount_record", || {
+            self.inner.delete_mount_record(org, repo, digest)
+        })
+    }
+
+    fn list_mount_records(&self) -> io::Result<Vec<(String, String, Digest)>> {
+        self.timed("list_mount_records", || self.inner.list_mount_records())
+    }
+
+    fn is_accessible(&self) -> bool {
+        self.inner.is_accessible()
+    }
+
+    fn is_writable(&self) -> bool {
+        self.inner.is_writable()
+    }
+
+    fn migrate(&self) -> io::Result<usize> {
+        self.timed("migrate", || self.inner.migrate())
+    }
+}
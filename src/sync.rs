@@ -0,0 +1,638 @@
+//! Registry-to-registry mirroring over the OCI distribution API, used by `grainctl sync`
+//!
+//! Sync is incremental: tags whose manifest digest already matches on the destination are
+//! skipped, and blobs and child manifests are only copied when a HEAD request misses.
+
+use reqwest::{
+    blocking::{Body, Client, RequestBuilder, Response},
+    header, Method, StatusCode,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+const MANIFEST_MEDIA_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+
+/// Page size requested when listing repositories and tags
+const PAGE_SIZE: usize = 1000;
+
+/// Bearer tokens are refreshed this long before their `expires_in` runs out
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum SyncError {
+    Transport(reqwest::Error),
+    Registry {
+        url: String,
+        status: StatusCode,
+        body: String,
+    },
+    InvalidManifest(String),
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::Transport(e) => write!(f, "{}", e),
+            SyncError::Registry { url, status, body } => {
+                write!(f, "{} {} - {}", status, url, body)
+            }
+            SyncError::InvalidManifest(reason) => write!(f, "invalid manifest: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+impl From<reqwest::Error> for SyncError {
+    fn from(e: reqwest::Error) -> Self {
+        SyncError::Transport(e)
+    }
+}
+
+/// Repositories and tags to mirror; patterns support `*` and `?`
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    pub repositories: Vec<String>,
+    pub tags: Vec<String>,
+    /// Report what would be copied without writing to the destination
+    pub dry_run: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            repositories: vec!["*".to_string()],
+            tags: vec!["*".to_string()],
+            dry_run: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncStats {
+    pub repositories: usize,
+    pub tags_synced: usize,
+    pub tags_up_to_date: usize,
+    pub manifests_copied: usize,
+    pub blobs_copied: usize,
+    pub blobs_present: usize,
+    pub bytes_copied: u64,
+}
+
+/// OCI distribution API client authenticating with Basic credentials, or with bearer tokens
+/// when the registry answers with a `Bearer` challenge
+pub struct Registry {
+    base_url: String,
+    credentials: Option<(String, String)>,
+    http: Client,
+    // Bearer tokens by scope, with when they should be refreshed. Deadlines are measured on
+    // the local monotonic clock from `expires_in`, so a skewed wall clock does not matter.
+    tokens: Mutex<HashMap<String, (String, Option<Instant>)>>,
+}
+
+impl Registry {
+    pub fn new(base_url: &str, credentials: Option<(String, String)>) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            credentials,
+            // Large layers take longer than the default request timeout
+            http: Client::builder().timeout(None).build().unwrap_or_default(),
+            tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        if path.starts_with("http://") || path.starts_with("https://") {
+            path.to_string()
+        } else {
+            format!("{}{}", self.base_url, path)
+        }
+    }
+
+    fn authorize(&self, request: RequestBuilder, scope: &str) -> RequestBuilder {
+        let mut tokens = self.tokens.lock().unwrap();
+        match tokens.get(scope) {
+            Some((_, Some(refresh_at))) if Instant::now() >= *refresh_at => {
+                // Expired tokens are dropped so the challenge fetches a new one
+                tokens.remove(scope);
+            }
+            Some((token, _)) => return request.bearer_auth(token),
+            None => {}
+        }
+        drop(tokens);
+        match &self.credentials {
+            Some((username, password)) => request.basic_auth(username, Some(password)),
+            None => request,
+        }
+    }
+
+    /// Send a request, fetching a bearer token for `scope` and retrying once on a challenge
+    fn send(&self, scope: &str, build: impl Fn() -> RequestBuilder) -> Result<Response, SyncError> {
+        let response = self.authorize(build(), scope).send()?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+        let challenge = response
+            .headers()
+            .get_all(header::WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .find_map(|value| value.strip_prefix("Bearer "))
+            .map(parse_challenge);
+        match challenge {
+            Some(challenge) => {
+                self.fetch_token(scope, &challenge)?;
+                Ok(self.authorize(build(), scope).send()?)
+            }
+            None => Ok(response),
+        }
+    }
+
+    fn fetch_token(
+        &self,
+        scope: &str,
+        challenge: &HashMap<String, String>,
+    ) -> Result<(), SyncError> {
+        let realm = challenge.get("realm").cloned().unwrap_or_default();
+        let mut request = self.http.get(&realm).query(&[("scope", scope)]);
+        if let Some(service) = challenge.get("service") {
+            request = request.query(&[("service", service)]);
+        }
+        if let Some((username, password)) = &self.credentials {
+            request = request.basic_auth(username, Some(password));
+        }
+        let response = check(request.send()?)?;
+        let body: Value = response.json()?;
+        let token = body
+            .get("token")
+            .or_else(|| body.get("access_token"))
+            .and_then(|t| t.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let refresh_at = body.get("expires_in").and_then(|e| e.as_u64()).map(|secs| {
+            Instant::now() + Duration::from_secs(secs).saturating_sub(TOKEN_REFRESH_MARGIN)
+        });
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(scope.to_string(), (token, refresh_at));
+        Ok(())
+    }
+
+    /// Follow `Link: <...>; rel="next"` pages, collecting `field` from each
+    fn list_paged(&self, path: &str, scope: &str, field: &str) -> Result<Vec<String>, SyncError> {
+        let mut items = Vec::new();
+        let mut next = Some(format!("{}?n={}", path, PAGE_SIZE));
+        while let Some(page) = next.take() {
+            let url = self.url(&page);
+            let response = check(self.send(scope, || self.http.get(&url))?)?;
+            next = response
+                .headers()
+                .get(header::LINK)
+                .and_then(|link| link.to_str().ok())
+                .and_then(next_link);
+            let body: Value = response.json()?;
+            items.extend(
+                body.get(field)
+                    .and_then(|v| v.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|v| v.as_str().map(str::to_string)),
+            );
+        }
+        Ok(items)
+    }
+
+    /// Check the credentials against the registry's API version check (`GET /v2/`)
+    pub fn check_credentials(&self) -> Result<(), SyncError> {
+        let url = self.url("/v2/");
+        check(self.send("", || self.http.get(&url))?)?;
+        Ok(())
+    }
+
+    pub fn catalog(&self) -> Result<Vec<String>, SyncError> {
+        self.list_paged("/v2/_catalog", "registry:catalog:*", "repositories")
+    }
+
+    pub fn tags(&self, repository: &str) -> Result<Vec<String>, SyncError> {
+        self.list_paged(
+            &format!("/v2/{}/tags/list", repository),
+            &pull_scope(repository),
+            "tags",
+        )
+    }
+
+    /// Digest of a manifest, or None when the registry does not have it
+    pub fn manifest_digest(
+        &self,
+        repository: &str,
+        reference: &str,
+        scope: &str,
+    ) -> Result<Option<String>, SyncError> {
+        let url = self.url(&format!("/v2/{}/manifests/{}", repository, reference));
+        let response = self.send(scope, || {
+            self.http
+                .head(&url)
+                .header(header::ACCEPT, MANIFEST_MEDIA_TYPES)
+        })?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = check(response)?;
+        Ok(response
+            .headers()
+            .get("docker-content-digest")
+            .and_then(|d| d.to_str().ok())
+            .map(str::to_string))
+    }
+
+    /// Manifest bytes and media type
+    pub fn manifest(
+        &self,
+        repository: &str,
+        reference: &str,
+    ) -> Result<(Vec<u8>, String), SyncError> {
+        let url = self.url(&format!("/v2/{}/manifests/{}", repository, reference));
+        let response = check(self.send(&pull_scope(repository), || {
+            self.http
+                .get(&url)
+                .header(header::ACCEPT, MANIFEST_MEDIA_TYPES)
+        })?)?;
+        let media_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|t| t.to_str().ok())
+            .map(str::to_string);
+        let bytes = response.bytes()?.to_vec();
+        // Fall back to the manifest's own mediaType when the registry sends no Content-Type
+        let media_type = media_type.filter(|t| !t.is_empty()).unwrap_or_else(|| {
+            serde_json::from_slice::<Value>(&bytes)
+                .ok()
+                .and_then(|m| m.get("mediaType")?.as_str().map(str::to_string))
+                .unwrap_or_default()
+        });
+        Ok((bytes, media_type))
+    }
+
+    pub fn put_manifest(
+        &self,
+        repository: &str,
+        reference: &str,
+        bytes: &[u8],
+        media_type: &str,
+    ) -> Result<(), SyncError> {
+        let url = self.url(&format!("/v2/{}/manifests/{}", repository, reference));
+        check(self.send(&push_scope(repository), || {
+            self.http
+                .put(&url)
+                .header(header::CONTENT_TYPE, media_type)
+                .body(bytes.to_vec())
+        })?)?;
+        Ok(())
+    }
+
+    /// Delete the manifest a tag or digest resolves to, which also removes every tag pointing to
+    /// it. Returns its digest, or None when the registry does not have it.
+    pub fn delete_manifest(
+        &self,
+        repository: &str,
+        reference: &str,
+    ) -> Result<Option<String>, SyncError> {
+        let scope = delete_scope(repository);
+        let Some(digest) = self.manifest_digest(repository, reference, &scope)? else {
+            return Ok(None);
+        };
+        let url = self.url(&format!("/v2/{}/manifests/{}", repository, digest));
+        check(self.send(&scope, || self.http.delete(&url))?)?;
+        Ok(Some(digest))
+    }
+
+    pub fn blob_exists(
+        &self,
+        repository: &str,
+        digest: &str,
+        scope: &str,
+    ) -> Result<bool, SyncError> {
+        let url = self.url(&format!("/v2/{}/blobs/{}", repository, digest));
+        let response = self.send(scope, || self.http.head(&url))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        check(response)?;
+        Ok(true)
+    }
+
+    /// Blob body, streamed
+    pub fn blob(&self, repository: &str, digest: &str) -> Result<Response, SyncError> {
+        let url = self.url(&format!("/v2/{}/blobs/{}", repository, digest));
+        check(self.send(&pull_scope(repository), || self.http.get(&url))?)
+    }
+
+    /// Upload a blob in one request, streaming `body`
+    pub fn put_blob(&self, repository: &str, digest: &str, body: Body) -> Result<(), SyncError> {
+        let scope = push_scope(repository);
+        let url = self.url(&format!("/v2/{}/blobs/uploads/", repository));
+        let response = check(self.send(&scope, || self.http.post(&url))?)?;
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|l| l.to_str().ok())
+            .ok_or_else(|| SyncError::Registry {
+                url: url.clone(),
+                status: response.status(),
+                body: "upload session without Location".to_string(),
+            })?;
+        let separator = if location.contains('?') { '&' } else { '?' };
+        let upload_url = format!("{}{}digest={}", self.url(location), separator, digest);
+
+        // The body can only be sent once; the token was obtained by the POST above
+        let request = self
+            .http
+            .request(Method::PUT, &upload_url)
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(body);
+        check(self.authorize(request, &scope).send()?)?;
+        Ok(())
+    }
+}
+
+fn pull_scope(repository: &str) -> String {
+    format!("repository:{}:pull", repository)
+}
+
+fn push_scope(repository: &str) -> String {
+    format!("repository:{}:pull,push", repository)
+}
+
+fn delete_scope(repository: &str) -> String {
+    format!("repository:{}:pull,delete", repository)
+}
+
+fn check(response: Response) -> Result<Response, SyncError> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let url = response.url().to_string();
+    let status = response.status();
+    let body = response
+        .text()
+        .unwrap_or_else(|_| String::from("No response body"));
+    Err(SyncError::Registry { url, status, body })
+}
+
+/// `realm="...",service="..."` parameters of a Bearer challenge
+fn parse_challenge(params: &str) -> HashMap<String, String> {
+    let mut parsed = HashMap::new();
+    let mut rest = params.trim();
+    while let Some((key, value)) = rest.split_once("=\"") {
+        let Some(end) = value.find('"') else {
+            break;
+        };
+        parsed.insert(
+            key.trim_start_matches(',').trim().to_string(),
+            value[..end].to_string(),
+        );
+        rest = &value[end + 1..];
+    }
+    parsed
+}
+
+/// Target of the `rel="next"` link of a paginated listing
+fn next_link(link: &str) -> Option<String> {
+    link.split(',')
+        .find(|part| part.contains("rel=\"next\""))
+        .and_then(|part| {
+            let start = part.find('<')? + 1;
+            let end = part.find('>')?;
+            Some(part[start..end].to_string())
+        })
+}
+
+/// Glob match with `*` (any run of characters) and `?` (one character)
+pub fn matches(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack = None;
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    v = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn matches_any(patterns: &[String], value: &str) -> bool {
+    patterns.iter().any(|pattern| matches(pattern, value))
+}
+
+/// Mirrors repositories and tags from one registry to another
+pub struct Mirror<'a> {
+    source: &'a Registry,
+    destination: &'a Registry,
+    options: &'a SyncOptions,
+    stats: SyncStats,
+}
+
+impl<'a> Mirror<'a> {
+    pub fn new(source: &'a Registry, destination: &'a Registry, options: &'a SyncOptions) -> Self {
+        Self {
+            source,
+            destination,
+            options,
+            stats: SyncStats::default(),
+        }
+    }
+
+    /// Mirror every matching tag, calling `progress` with each synced `repository:tag`
+    pub fn run(mut self, mut progress: impl FnMut(&str)) -> Result<SyncStats, SyncError> {
+        // Literal repository names need no catalog, which many registries do not serve
+        let literal = self
+            .options
+            .repositories
+            .iter()
+            .all(|pattern| !pattern.contains(['*', '?']));
+        let repositories = if literal {
+            self.options.repositories.clone()
+        } else {
+            let mut repositories = self.source.catalog()?;
+            repositories.retain(|r| matches_any(&self.options.repositories, r));
+            repositories
+        };
+
+        for repository in &repositories {
+            self.stats.repositories += 1;
+            for tag in self.source.tags(repository)? {
+                if !matches_any(&self.options.tags, &tag) {
+                    continue;
+                }
+                if self.sync_tag(repository, &tag)? {
+                    progress(&format!("{}:{}", repository, tag));
+                }
+            }
+        }
+        Ok(self.stats)
+    }
+
+    /// Copy one tag; false when the destination already has the same manifest
+    fn sync_tag(&mut self, repository: &str, tag: &str) -> Result<bool, SyncError> {
+        let source_digest =
+            self.source
+                .manifest_digest(repository, tag, &pull_scope(repository))?;
+        let destination_digest =
+            self.destination
+                .manifest_digest(repository, tag, &push_scope(repository))?;
+        if source_digest.is_some() && source_digest == destination_digest {
+            self.stats.tags_up_to_date += 1;
+            return Ok(false);
+        }
+
+        let (bytes, media_type) = self.source.manifest(repository, tag)?;
+        self.copy_manifest_content(repository, &bytes)?;
+        if !self.options.dry_run {
+            self.destination
+                .put_manifest(repository, tag, &bytes, &media_type)?;
+        }
+        self.stats.manifests_copied += 1;
+        self.stats.tags_synced += 1;
+        Ok(true)
+    }
+
+    /// Copy what a manifest references: the children of an index, or the config and layers
+    /// of an image manifest
+    fn copy_manifest_content(&mut self, repository: &str, bytes: &[u8]) -> Result<(), SyncError> {
+        let manifest: Value =
+            serde_json::from_slice(bytes).map_err(|e| SyncError::InvalidManifest(e.to_string()))?;
+        let descriptors = |field: &str| -> Vec<Value> {
+            manifest
+                .get(field)
+                .and_then(|d| d.as_array())
+                .cloned()
+                .unwrap_or_default()
+        };
+
+        for child in descriptors("manifests") {
+            let digest = descriptor_digest(&child)?;
+            let present = self
+                .destination
+                .manifest_digest(repository, &digest, &push_scope(repository))?
+                .is_some();
+            if present {
+                continue;
+            }
+            let (child_bytes, media_type) = self.source.manifest(repository, &digest)?;
+            self.copy_manifest_content(repository, &child_bytes)?;
+            if !self.options.dry_run {
+                self.destination
+                    .put_manifest(repository, &digest, &child_bytes, &media_type)?;
+            }
+            self.stats.manifests_copied += 1;
+        }
+
+        let blobs = manifest
+            .get("config")
+            .cloned()
+            .into_iter()
+            .chain(descriptors("layers"));
+        for blob in blobs {
+            // Foreign layers are fetched from their own URLs, not from the registry
+            let media_type = blob.get("mediaType").and_then(|m| m.as_str()).unwrap_or("");
+            if media_type.contains("foreign") || media_type.contains("nondistributable") {
+                continue;
+            }
+            self.copy_blob(repository, &descriptor_digest(&blob)?)?;
+        }
+        Ok(())
+    }
+
+    fn copy_blob(&mut self, repository: &str, digest: &str) -> Result<(), SyncError> {
+        if self
+            .destination
+            .blob_exists(repository, digest, &push_scope(repository))?
+        {
+            self.stats.blobs_present += 1;
+            return Ok(());
+        }
+
+        let response = self.source.blob(repository, digest)?;
+        let size = response.content_length().unwrap_or(0);
+        if !self.options.dry_run {
+            let body = match response.content_length() {
+                Some(length) => Body::sized(response, length),
+                None => Body::new(response),
+            };
+            self.destination.put_blob(repository, digest, body)?;
+        }
+        self.stats.blobs_copied += 1;
+        self.stats.bytes_copied += size;
+        Ok(())
+    }
+}
+
+fn descriptor_digest(descriptor: &Value) -> Result<String, SyncError> {
+    descriptor
+        .get("digest")
+        .and_then(|d| d.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| SyncError::InvalidManifest("descriptor without digest".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("*", "team/app"));
+        assert!(matches("team/*", "team/app"));
+        assert!(!matches("team/*", "other/app"));
+        assert!(matches("v*", "v1.2.0"));
+        assert!(matches("v?.*", "v1.2"));
+        assert!(!matches("v?.*", "v12.0"));
+        assert!(matches("*-rc*", "v1-rc2"));
+        assert!(!matches("latest", "latest2"));
+    }
+
+    #[test]
+    fn test_parse_challenge() {
+        let challenge = parse_challenge(
+            r#"realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/alpine:pull""#,
+        );
+        assert_eq!(challenge["realm"], "https://auth.docker.io/token");
+        assert_eq!(challenge["service"], "registry.docker.io");
+        assert_eq!(challenge["scope"], "repository:library/alpine:pull");
+    }
+
+    #[test]
+    fn test_next_link() {
+        assert_eq!(
+            next_link(r#"</v2/_catalog?last=b&n=2>; rel="next""#).as_deref(),
+            Some("/v2/_catalog?last=b&n=2")
+        );
+        assert_eq!(next_link(r#"<https://x/prev>; rel="prev""#), None);
+    }
+}
@@ -2,14 +2,17 @@
 // | ------ | -------------- | ------------------------------------------------------------ | ----------- | ----------------- |
 // | end-8a | `GET`          | `/v2/<name>/tags/list`                                       | `200`       | `404`             |
 // | end-8b | `GET`          | `/v2/<name>/tags/list?n=<integer>&last=<integer>`            | `200`       | `404`             |
+//
+// Extension: `?detail=true` adds a `details` array with digest, mediaType, size and push time per tag.
 
 use axum::body::Body;
 use axum::http::{HeaderMap, StatusCode};
 use axum::response::Response;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use std::time::UNIX_EPOCH;
 
-use crate::{auth, permissions, response, state, storage};
+use crate::{auth, pagination, permissions, repos, response, state, storage};
 use axum::extract::{Path, Query, State};
 
 // end-8a GET /v2/:name/tags/list
@@ -18,25 +21,46 @@ use axum::extract::{Path, Query, State};
 pub(crate) struct TagsQuery {
     pub n: Option<usize>,
     pub last: Option<String>,
+    #[serde(default)]
+    pub detail: bool,
 }
 
-fn paginate_tags(tags: Vec<String>, n: Option<usize>, last: Option<String>) -> Vec<String> {
-    let mut result = tags;
-
-    // Filter tags after 'last' cursor
-    if let Some(last_tag) = last {
-        result = result
-            .into_iter()
-            .skip_while(|tag| tag <= &last_tag)
-            .collect();
-    }
-
-    // Limit to 'n' results
-    if let Some(limit) = n {
-        result.truncate(limit);
-    }
+#[derive(Debug, Serialize)]
+pub(crate) struct TagDetail {
+    pub tag: String,
+    pub digest: String,
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub size: u64,
+    pub pushed_at: Option<u64>,
+}
 
-    result
+/// Resolve each tag so clients don't need a manifest fetch per tag
+fn tag_details(org: &str, repo: &str, tags: &[String]) -> Vec<TagDetail> {
+    tags.iter()
+        .filter_map(|tag| match repos::resolve_reference(org, repo, tag) {
+            Ok(resolution) => Some(TagDetail {
+                tag: tag.clone(),
+                digest: resolution.digest,
+                media_type: resolution.media_type,
+                size: resolution.total_size,
+                pushed_at: storage::manifest_metadata(org, repo, tag)
+                    .ok()
+                    .and_then(|m| m.modified.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs()),
+            }),
+            Err(e) => {
+                log::warn!(
+                    "tags/list: failed to resolve {}/{}:{}: {}",
+                    org,
+                    repo,
+                    tag,
+                    e
+                );
+                None
+            }
+        })
+        .collect()
 }
 
 pub(crate) async fn get_tags_list(
@@ -45,16 +69,16 @@ pub(crate) async fn get_tags_list(
     Query(params): Query<TagsQuery>,
     headers: HeaderMap,
 ) -> Response<Body> {
-    let host = &state.args.host;
+    let host = state.args.primary_host();
     let repository = format!("{}/{}", org, repo);
 
-    // Check permission (Pull for tag listing)
+    // Check permission (List, implied by Pull unless --strict-list)
     match auth::check_permission(
         &state,
         &headers,
         &repository,
         None,
-        permissions::Action::Pull,
+        permissions::Action::List,
     )
     .await
     {
@@ -69,22 +93,42 @@ pub(crate) async fn get_tags_list(
     }
 
     // Get all tags from storage
-    match storage::list_tags(&org, &repo) {
+    let tags = storage::blocking("list_tags", &org, &repo, |org, repo| {
+        storage::list_tags(org, repo)
+    })
+    .await;
+    match tags {
         Ok(all_tags) => {
-            // Apply pagination
-            let paginated_tags = paginate_tags(all_tags, params.n, params.last);
+            // Apply pagination; tag lists are only split into pages when the client asks
+            let page = pagination::page(
+                all_tags,
+                |t| t,
+                params.n,
+                params.last.as_deref(),
+                usize::MAX,
+            );
+            let mut path = format!("/v2/{}/{}/tags/list", org, repo);
+            if params.detail {
+                path.push_str("?detail=true");
+            }
+            let link = pagination::next_link(&path, &page);
+            let paginated_tags = page.items;
 
             // Build response JSON
-            let response_body = serde_json::json!({
+            let mut response_body = serde_json::json!({
                 "name": format!("{}/{}", org, repo),
                 "tags": paginated_tags
             });
 
-            Response::builder()
-                .status(StatusCode::OK)
-                .header("Content-Type", "application/json")
-                .body(Body::from(response_body.to_string()))
-                .unwrap()
+            if params.detail {
+                let details = storage::blocking("tag_details", &org, &repo, move |org, repo| {
+                    tag_details(org, repo, &paginated_tags)
+                })
+                .await;
+                response_body["details"] = serde_json::json!(details);
+            }
+
+            pagination::response(response_body.to_string(), link)
         }
         Err(e) => {
             log::error!("Failed to list tags for {}/{}: {}", org, repo, e);
@@ -0,0 +1,140 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, StatusCode, Uri},
+    response::Response,
+    Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+
+use crate::args::Args;
+
+/// Load `--tls-cert`/`--tls-key` when HTTPS is enabled
+pub(crate) async fn load_config(args: &Args) -> Option<RustlsConfig> {
+    let (cert, key) = (args.tls_cert.as_ref()?, args.tls_key.as_ref()?);
+
+    // Only the ring provider is compiled in; rustls needs it set before building configs
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    match RustlsConfig::from_pem_file(cert, key).await {
+        Ok(config) => Some(config),
+        Err(e) => {
+            log::error!("tls: failed to load {} / {}: {}", cert, key, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reload the certificate and key whenever the process receives `SIGHUP`, so renewed
+/// certificates apply without a restart. A failed reload keeps serving the previous ones.
+#[cfg(unix)]
+pub(crate) fn spawn_reload_on_sighup(config: RustlsConfig, args: &Args) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let (Some(cert), Some(key)) = (args.tls_cert.clone(), args.tls_key.clone()) else {
+        return;
+    };
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            log::error!("tls: cannot listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match config.reload_from_pem_file(&cert, &key).await {
+                Ok(()) => log::info!("tls: reloaded {}", cert),
+                Err(e) => log::error!("tls: failed to reload {}: {}", cert, e),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub(crate) fn spawn_reload_on_sighup(_config: RustlsConfig, _args: &Args) {}
+
+/// Plain HTTP listener answering every request with a redirect to the same URL over HTTPS
+pub(crate) async fn serve_redirect(address: String, args: &Args) {
+    let https_port = https_port(args.primary_host());
+    let fallback_host = args.primary_host().to_string();
+    let app = Router::new()
+        .fallback(redirect_to_https)
+        .with_state((https_port, fallback_host));
+
+    let listener = match tokio::net::TcpListener::bind(&address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("tls: cannot listen on {} for redirects: {}", address, e);
+            std::process::exit(1);
+        }
+    };
+    log::info!("Redirecting HTTP on {} to HTTPS", address);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            log::error!("tls: redirect listener stopped: {}", e);
+        }
+    });
+}
+
+async fn redirect_to_https(
+    State((https_port, fallback_host)): State<(Option<u16>, String)>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response<Body> {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or(&fallback_host);
+    let target = format!(
+        "https://{}{}",
+        authority(host, https_port),
+        uri.path_and_query().map_or("/", |p| p.as_str())
+    );
+
+    // 308 keeps the method and body, so pushes sent over HTTP are retried as is
+    Response::builder()
+        .status(StatusCode::PERMANENT_REDIRECT)
+        .header(header::LOCATION, target)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Port of the HTTPS listener, from `--host`
+fn https_port(host: &str) -> Option<u16> {
+    host.rsplit_once(':')?.1.parse().ok()
+}
+
+/// `host` with its port replaced by the HTTPS one (omitted when it is the default 443)
+fn authority(host: &str, https_port: Option<u16>) -> String {
+    let hostname = match host.rsplit_once(':') {
+        // Keep bracketed IPv6 literals whole
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    };
+    match https_port {
+        Some(443) | None => hostname.to_string(),
+        Some(port) => format!("{}:{}", hostname, port),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redirect_authority() {
+        assert_eq!(https_port("0.0.0.0:8443"), Some(8443));
+        assert_eq!(
+            authority("registry.example.com", Some(443)),
+            "registry.example.com"
+        );
+        assert_eq!(
+            authority("registry.example.com:80", Some(8443)),
+            "registry.example.com:8443"
+        );
+        assert_eq!(authority("[::1]:80", Some(8443)), "[::1]:8443");
+        assert_eq!(authority("[::1]", Some(443)), "[::1]");
+    }
+}
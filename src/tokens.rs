@@ -0,0 +1,520 @@
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    fmt,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    args::Args,
+    audit::{AuditEvent, Outcome},
+    auth, metrics, oidc,
+    permissions::{self, Action},
+    response,
+    secrets::Secret,
+    state,
+};
+
+const ISSUER: &str = "grain";
+const ANONYMOUS: &str = "anonymous";
+
+/// Access granted on one resource, as in the Docker token specification
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Access {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub name: String,
+    pub actions: Vec<String>,
+}
+
+/// Claims of a bearer token
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Claims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub exp: u64,
+    pub nbf: u64,
+    pub iat: u64,
+    pub jti: String,
+    pub access: Vec<Access>,
+    /// Permissions of a subject that is not in the users file (an OIDC identity)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<state::Permission>,
+    /// Whether a subject that is not in the users file may use the admin API
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub admin: bool,
+}
+
+impl Claims {
+    /// Whether the token covers `action` on `repository`
+    pub(crate) fn grants(&self, repository: &str, action: Action) -> bool {
+        self.access.iter().any(|a| {
+            a.kind == "repository"
+                && a.name == repository
+                // Clients request a pull scope to list tags; the user's list permission is
+                // checked separately
+                && a.actions.iter().any(|granted| {
+                    granted == action.as_str() || (action == Action::List && granted == "pull")
+                })
+        })
+    }
+
+    pub(crate) fn is_anonymous(&self) -> bool {
+        self.sub == ANONYMOUS
+    }
+}
+
+/// Why a bearer or OIDC token was refused
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TokenError {
+    Malformed,
+    /// Signature does not verify, or uses a key or algorithm that is not accepted
+    BadSignature(String),
+    Expired {
+        exp: u64,
+        now: u64,
+        skew: u64,
+    },
+    NotYetValid {
+        nbf: u64,
+        now: u64,
+        skew: u64,
+    },
+    /// Issuer, audience or other claims are not accepted
+    InvalidClaims(String),
+}
+
+impl TokenError {
+    /// Failure class, the `reason` label of `grain_token_validation_failures_total`
+    pub(crate) fn class(&self) -> &'static str {
+        match self {
+            TokenError::Malformed => "malformed",
+            TokenError::BadSignature(_) => "bad_signature",
+            TokenError::Expired { .. } => "expired",
+            TokenError::NotYetValid { .. } => "not_yet_valid",
+            TokenError::InvalidClaims(_) => "invalid_claims",
+        }
+    }
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Malformed => write!(f, "malformed token"),
+            TokenError::BadSignature(reason) => write!(f, "bad signature: {}", reason),
+            TokenError::Expired { exp, now, skew } => write!(
+                f,
+                "token expired {}s ago (exp {}, server time {}, {}s clock skew allowed)",
+                now - exp,
+                exp,
+                now,
+                skew
+            ),
+            TokenError::NotYetValid { nbf, now, skew } => write!(
+                f,
+                "token not valid for another {}s (nbf {}, server time {}, {}s clock skew \
+                 allowed); check the clocks of the issuer and the registry",
+                nbf - now,
+                nbf,
+                now,
+                skew
+            ),
+            TokenError::InvalidClaims(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// Check a token's validity window, tolerating `skew` seconds of clock difference with the
+/// issuer on either end
+pub(crate) fn check_validity(
+    exp: u64,
+    nbf: Option<u64>,
+    now: u64,
+    skew: u64,
+) -> Result<(), TokenError> {
+    if now >= exp.saturating_add(skew) {
+        return Err(TokenError::Expired { exp, now, skew });
+    }
+    if let Some(nbf) = nbf.filter(|nbf| *nbf > now.saturating_add(skew)) {
+        return Err(TokenError::NotYetValid { nbf, now, skew });
+    }
+    Ok(())
+}
+
+/// Log and count a refused token; `source` is `registry` or `oidc`
+pub(crate) fn record_refusal(source: &'static str, error: &TokenError) {
+    log::warn!("{} token refused ({}): {}", source, error.class(), error);
+    metrics::TOKEN_VALIDATION_FAILURES_TOTAL
+        .with_label_values(&[source, error.class()])
+        .inc();
+}
+
+/// Issues and checks HS256 bearer tokens signed with `--token-secret`
+pub(crate) struct TokenIssuer {
+    key: Secret,
+    service: String,
+    ttl: u64,
+    /// Clock difference tolerated on `exp` and `nbf`, for tokens checked by other instances
+    clock_skew: u64,
+}
+
+impl TokenIssuer {
+    pub(crate) fn from_args(args: &Args) -> Self {
+        let key = if args.token_secret.is_empty() {
+            // Clients fetch a new token on the next challenge, so a restart only costs a round trip
+            if args.token_auth {
+                log::info!("No --token-secret set, bearer tokens are valid until restart");
+            }
+            Secret::resolve(&format!("{}{}", uuid::Uuid::new_v4(), uuid::Uuid::new_v4()))
+                .expect("literal secrets always resolve")
+        } else {
+            Secret::resolve_or_exit("--token-secret", &args.token_secret)
+        };
+        Self {
+            key,
+            service: args.token_service.clone(),
+            ttl: args.token_ttl,
+            clock_skew: args.token_clock_skew_secs,
+        }
+    }
+
+    fn mac(&self, signing_input: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.get().as_bytes())
+            .expect("HMAC accepts any key length");
+        mac.update(signing_input.as_bytes());
+        mac
+    }
+
+    pub(crate) fn issue(
+        &self,
+        subject: &str,
+        access: Vec<Access>,
+        permissions: Vec<state::Permission>,
+        admin: bool,
+        now: u64,
+    ) -> String {
+        let header = serde_json::json!({ "alg": "HS256", "typ": "JWT" });
+        let claims = Claims {
+            iss: ISSUER.to_string(),
+            sub: subject.to_string(),
+            aud: self.service.clone(),
+            exp: now + self.ttl,
+            nbf: now,
+            iat: now,
+            jti: uuid::Uuid::new_v4().to_string(),
+            access,
+            permissions,
+            admin,
+        };
+        let signing_input = format!(
+            "{}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+            BASE64_URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap_or_default())
+        );
+        let signature = self.mac(&signing_input).finalize().into_bytes();
+        format!(
+            "{}.{}",
+            signing_input,
+            BASE64_URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// Check a token's signature, audience and validity window, returning why it is refused
+    pub(crate) fn verify(&self, token: &str, now: u64) -> Result<Claims, TokenError> {
+        let (signing_input, signature) = token.rsplit_once('.').ok_or(TokenError::Malformed)?;
+        let (header, claims) = signing_input.split_once('.').ok_or(TokenError::Malformed)?;
+
+        let signature = BASE64_URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| TokenError::Malformed)?;
+        self.mac(signing_input)
+            .verify_slice(&signature)
+            .map_err(|_| TokenError::BadSignature("signature does not match".to_string()))?;
+
+        // Only HS256 is issued; anything else (notably "none") is refused even if signed
+        let header: serde_json::Value = BASE64_URL_SAFE_NO_PAD
+            .decode(header)
+            .ok()
+            .and_then(|h| serde_json::from_slice(&h).ok())
+            .ok_or(TokenError::Malformed)?;
+        if header.get("alg").and_then(|a| a.as_str()) != Some("HS256") {
+            return Err(TokenError::BadSignature(
+                "unsupported token algorithm".to_string(),
+            ));
+        }
+
+        let claims: Claims = BASE64_URL_SAFE_NO_PAD
+            .decode(claims)
+            .ok()
+            .and_then(|c| serde_json::from_slice(&c).ok())
+            .ok_or(TokenError::Malformed)?;
+        if claims.iss != ISSUER || claims.aud != self.service {
+            return Err(TokenError::InvalidClaims(
+                "token issued for another service".to_string(),
+            ));
+        }
+        check_validity(claims.exp, Some(claims.nbf), now, self.clock_skew)?;
+        Ok(claims)
+    }
+}
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Parse a `repository:<name>:<actions>` scope; other resource types are ignored
+fn parse_scope(scope: &str) -> Option<(String, Vec<Action>)> {
+    let (kind, rest) = scope.split_once(':')?;
+    let (name, actions) = rest.rsplit_once(':')?;
+    if kind != "repository" || name.is_empty() {
+        return None;
+    }
+
+    let mut parsed = Vec::new();
+    for action in actions.split(',') {
+        let expanded: &[Action] = match action {
+            "pull" => &[Action::Pull],
+            "push" => &[Action::Push],
+            "delete" => &[Action::Delete],
+            "*" => &[Action::Pull, Action::Push, Action::Delete],
+            _ => &[],
+        };
+        for action in expanded {
+            if !parsed.contains(action) {
+                parsed.push(*action);
+            }
+        }
+    }
+    Some((name.to_string(), parsed))
+}
+
+/// Intersect the requested scopes with what `user` may do; anonymous users only get public pulls
+fn granted_access(
+    state: &state::App,
+    user: Option<&state::User>,
+    scopes: &[String],
+) -> Vec<Access> {
+    let mut access: Vec<Access> = Vec::new();
+    for (name, actions) in scopes.iter().filter_map(|s| parse_scope(s)) {
+        let actions: Vec<String> = actions
+            .into_iter()
+            .filter(|action| match user {
+                Some(user) => {
+                    permissions::has_permission(user, &name, None, *action)
+                        || (*action == Action::Pull && state.repositories.is_public(&name))
+                }
+                None => *action == Action::Pull && state.repositories.is_public(&name),
+            })
+            .map(|action| action.as_str().to_string())
+            .collect();
+
+        match access.iter_mut().find(|a| a.name == name) {
+            Some(existing) => {
+                for action in actions {
+                    if !existing.actions.contains(&action) {
+                        existing.actions.push(action);
+                    }
+                }
+            }
+            None => access.push(Access {
+                kind: "repository".to_string(),
+                name,
+                actions,
+            }),
+        }
+    }
+    access
+}
+
+/// Docker token endpoint: `GET /token?service=<service>&scope=repository:<name>:<actions>`.
+/// Credentials are optional; without them the token only covers pulls of public repositories.
+/// Scopes the caller may not use are granted with fewer (or no) actions rather than refused.
+pub(crate) async fn get_token(
+    State(state): State<Arc<state::App>>,
+    Query(params): Query<Vec<(String, String)>>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    if !state.args.token_auth {
+        return response::not_found();
+    }
+
+    let service = params.iter().find(|(k, _)| k == "service").map(|(_, v)| v);
+    if service.is_some_and(|s| *s != state.args.token_service) {
+        return response::token_request_invalid("unknown service");
+    }
+
+    // Several scopes may be passed as repeated parameters or space-separated in one
+    let scopes: Vec<String> = params
+        .iter()
+        .filter(|(k, _)| k == "scope")
+        .flat_map(|(_, v)| v.split(' ').map(str::to_string))
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    let user = if headers.contains_key("authorization") {
+        match auth::authenticate_user(&state, &headers).await {
+            Ok(user) => Some(user),
+            Err(_) => return response::unauthorized(state.args.primary_host()),
+        }
+    } else {
+        None
+    };
+    let subject = user.as_ref().map_or(ANONYMOUS, |u| u.username.as_str());
+
+    let access = granted_access(&state, user.as_ref(), &scopes);
+    let granted = access
+        .iter()
+        .map(|a| format!("{}:{}", a.name, a.actions.join(",")))
+        .collect::<Vec<_>>()
+        .join(" ");
+    log::info!(
+        "tokens/get_token: subject: {}, access: {}",
+        subject,
+        granted
+    );
+    state.audit.record(
+        AuditEvent::new(subject, "token.issue", "registry", Outcome::Success).with_detail(granted),
+    );
+
+    let identity = user
+        .as_ref()
+        .filter(|u| u.username.starts_with(oidc::IDENTITY_PREFIX));
+    let permissions = identity.map(|u| u.permissions.clone()).unwrap_or_default();
+    let admin = identity.is_some_and(|u| u.admin);
+    let token = state
+        .token_issuer
+        .issue(subject, access, permissions, admin, unix_now());
+    let body = serde_json::json!({
+        "token": token,
+        "access_token": token,
+        "expires_in": state.args.token_ttl,
+    });
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// The bearer token of an `Authorization: Bearer` header
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("authorization")?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Verify a bearer token presented to the registry API
+pub(crate) fn verify_bearer(state: &state::App, token: &str) -> Result<Claims, ()> {
+    if !state.args.token_auth {
+        return Err(());
+    }
+    state
+        .token_issuer
+        .verify(token, unix_now())
+        .map_err(|e| record_refusal("registry", &e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issuer() -> TokenIssuer {
+        TokenIssuer {
+            key: Secret::resolve("test-token-key").unwrap(),
+            service: "grain".to_string(),
+            ttl: 300,
+            clock_skew: 30,
+        }
+    }
+
+    #[test]
+    fn test_issue_and_verify() {
+        let issuer = issuer();
+        let access = vec![Access {
+            kind: "repository".to_string(),
+            name: "org/repo".to_string(),
+            actions: vec!["pull".to_string()],
+        }];
+        let token = issuer.issue("alice", access, Vec::new(), false, 1_000);
+
+        let claims = issuer.verify(&token, 1_100).unwrap();
+        assert_eq!(claims.sub, "alice");
+        assert!(claims.grants("org/repo", Action::Pull));
+        assert!(!claims.grants("org/repo", Action::Push));
+        assert!(!claims.grants("org/other", Action::Pull));
+
+        // A verifying clock up to 30s ahead of or behind the issuer's is tolerated
+        assert!(issuer.verify(&token, 1_320).is_ok());
+        assert!(issuer.verify(&token, 980).is_ok());
+        assert_eq!(
+            issuer.verify(&token, 1_330),
+            Err(TokenError::Expired {
+                exp: 1_300,
+                now: 1_330,
+                skew: 30
+            })
+        );
+        assert_eq!(
+            issuer.verify(&token, 960),
+            Err(TokenError::NotYetValid {
+                nbf: 1_000,
+                now: 960,
+                skew: 30
+            })
+        );
+
+        // Tampering with the claims breaks the signature
+        let parts: Vec<&str> = token.split('.').collect();
+        let forged = BASE64_URL_SAFE_NO_PAD.encode(
+            String::from_utf8(BASE64_URL_SAFE_NO_PAD.decode(parts[1]).unwrap())
+                .unwrap()
+                .replace("\"pull\"", "\"push\""),
+        );
+        let tampered = format!("{}.{}.{}", parts[0], forged, parts[2]);
+        assert_eq!(
+            issuer.verify(&tampered, 1_100).map_err(|e| e.class()),
+            Err("bad_signature")
+        );
+
+        let other = TokenIssuer {
+            service: "elsewhere".to_string(),
+            ..issuer
+        };
+        assert_eq!(
+            other.verify(&token, 1_100).map_err(|e| e.class()),
+            Err("invalid_claims")
+        );
+    }
+
+    #[test]
+    fn test_parse_scope() {
+        assert_eq!(
+            parse_scope("repository:org/repo:pull,push"),
+            Some(("org/repo".to_string(), vec![Action::Pull, Action::Push]))
+        );
+        assert_eq!(
+            parse_scope("repository:org/repo:*"),
+            Some((
+                "org/repo".to_string(),
+                vec![Action::Pull, Action::Push, Action::Delete]
+            ))
+        );
+        assert_eq!(parse_scope("registry:catalog:*"), None);
+        assert_eq!(parse_scope("repository:org/repo"), None);
+    }
+}
@@ -10,6 +10,11 @@ pub struct OciImageManifest {
     pub layers: Vec<Descriptor>,
     #[serde(default)]
     pub annotations: std::collections::HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    /// Manifest this one refers to, listed by the referrers API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<Descriptor>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -20,6 +25,11 @@ pub struct OciImageIndex {
     pub manifests: Vec<Descriptor>,
     #[serde(default)]
     pub annotations: std::collections::HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    /// Manifest this one refers to, listed by the referrers API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<Descriptor>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -141,6 +151,45 @@ pub fn validate_manifest(manifest_bytes: &[u8]) -> Result<String, ValidationErro
     }
 }
 
+/// Digest of the `subject` descriptor of a validated manifest, if it has one
+pub fn manifest_subject(manifest_bytes: &[u8]) -> Option<String> {
+    serde_json::from_slice::<serde_json::Value>(manifest_bytes)
+        .ok()?
+        .get("subject")?
+        .get("digest")?
+        .as_str()
+        .map(String::from)
+}
+
+/// Non-fatal conditions in an accepted manifest, reported to clients as `Warning` headers
+pub fn manifest_warnings(manifest_bytes: &[u8], media_type: &str) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    let declared = serde_json::from_slice::<serde_json::Value>(manifest_bytes)
+        .ok()
+        .and_then(|v| {
+            v.get("mediaType")
+                .and_then(|m| m.as_str())
+                .map(String::from)
+        });
+
+    if declared.is_none() {
+        warnings.push(format!(
+            "manifest has no mediaType field; treated as {}",
+            media_type
+        ));
+    }
+
+    if media_type.starts_with("application/vnd.docker.") {
+        warnings.push(format!(
+            "deprecated media type {} accepted; prefer OCI media types",
+            media_type
+        ));
+    }
+
+    warnings
+}
+
 fn validate_oci_image_manifest(manifest_str: &str) -> Result<(), ValidationError> {
     let manifest: OciImageManifest = serde_json::from_str(manifest_str)
         .map_err(|e| ValidationError::InvalidSchema(e.to_string()))?;
@@ -159,6 +208,10 @@ fn validate_oci_image_manifest(manifest_str: &str) -> Result<(), ValidationError
         validate_descriptor(layer)?;
     }
 
+    if let Some(subject) = &manifest.subject {
+        validate_descriptor(subject)?;
+    }
+
     Ok(())
 }
 
@@ -177,6 +230,10 @@ fn validate_oci_image_index(manifest_str: &str) -> Result<(), ValidationError> {
         validate_descriptor(manifest_desc)?;
     }
 
+    if let Some(subject) = &index.subject {
+        validate_descriptor(subject)?;
+    }
+
     Ok(())
 }
 
@@ -234,10 +291,73 @@ fn validate_digest(digest: &str) -> Result<(), ValidationError> {
     Ok(())
 }
 
+/// Whether `name` is a repository name the distribution spec allows: lowercase alphanumeric
+/// path components, separated within a component by `.`, `_`, `__` or dashes
+pub fn is_valid_repository_name(name: &str) -> bool {
+    lazy_static::lazy_static! {
+        static ref NAME_REGEX: Regex = Regex::new(
+            r"^[a-z0-9]+(?:(?:\.|_|__|-+)[a-z0-9]+)*(?:/[a-z0-9]+(?:(?:\.|_|__|-+)[a-z0-9]+)*)*$"
+        )
+        .unwrap();
+    }
+
+    NAME_REGEX.is_match(name)
+}
+
+/// Whether `tag` is a tag the distribution spec allows: up to 128 characters, starting with a
+/// letter, digit or underscore
+pub fn is_valid_tag(tag: &str) -> bool {
+    lazy_static::lazy_static! {
+        static ref TAG_REGEX: Regex = Regex::new(r"^[a-zA-Z0-9_][a-zA-Z0-9._-]{0,127}$").unwrap();
+    }
+
+    TAG_REGEX.is_match(tag)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_tag() {
+        for tag in ["latest", "v1.2.3", "_build-42", "V1_RC", &"a".repeat(128)] {
+            assert!(is_valid_tag(tag), "{}", tag);
+        }
+        for tag in [
+            "",
+            ".hidden",
+            "-rc",
+            "v1+build",
+            "a/b",
+            "a:b",
+            &"a".repeat(129),
+        ] {
+            assert!(!is_valid_tag(tag), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn test_repository_name() {
+        for name in ["org/repo", "my-org/app.v2", "a/b__c", "team/web--ui", "0/1"] {
+            assert!(is_valid_repository_name(name), "{}", name);
+        }
+        for name in [
+            "Org/repo",
+            "org/Repo",
+            "org/-repo",
+            "org/repo-",
+            "org/re..po",
+            "org/re___po",
+            "org//repo",
+            "org/repo/",
+            "../etc",
+            "org/re po",
+            "",
+        ] {
+            assert!(!is_valid_repository_name(name), "{}", name);
+        }
+    }
+
     #[test]
     fn test_valid_oci_manifest() {
         let manifest = r#"{
@@ -340,4 +460,66 @@ mod tests {
             "application/vnd.oci.image.manifest.v1+json"
         );
     }
+
+    #[test]
+    fn test_manifest_subject() {
+        let manifest = r#"{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "artifactType": "application/vnd.example.sbom",
+            "config": {
+                "mediaType": "application/vnd.oci.empty.v1+json",
+                "size": 2,
+                "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+            },
+            "layers": [
+                {
+                    "mediaType": "application/spdx+json",
+                    "size": 456,
+                    "digest": "sha256:abcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890"
+                }
+            ],
+            "subject": {
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "size": 123,
+                "digest": "sha256:1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef"
+            }
+        }"#;
+
+        assert!(validate_manifest(manifest.as_bytes()).is_ok());
+        assert_eq!(
+            manifest_subject(manifest.as_bytes()).as_deref(),
+            Some("sha256:1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef")
+        );
+
+        // The subject is a descriptor like any other
+        let invalid = manifest.replace(
+            "sha256:1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef",
+            "not-a-digest",
+        );
+        assert!(matches!(
+            validate_manifest(invalid.as_bytes()),
+            Err(ValidationError::InvalidDigest(_))
+        ));
+    }
+
+    #[test]
+    fn test_manifest_warnings() {
+        let oci =
+            br#"{"schemaVersion": 2, "mediaType": "application/vnd.oci.image.manifest.v1+json"}"#;
+        assert!(manifest_warnings(oci, "application/vnd.oci.image.manifest.v1+json").is_empty());
+
+        let docker = br#"{"schemaVersion": 2, "mediaType": "application/vnd.docker.distribution.manifest.v2+json"}"#;
+        let warnings = manifest_warnings(
+            docker,
+            "application/vnd.docker.distribution.manifest.v2+json",
+        );
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("deprecated media type"));
+
+        let untyped = br#"{"schemaVersion": 2}"#;
+        let warnings = manifest_warnings(untyped, "application/vnd.oci.image.manifest.v1+json");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("no mediaType"));
+    }
 }
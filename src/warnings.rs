@@ -0,0 +1,88 @@
+use axum::{http::HeaderValue, response::Response};
+
+/// Maximum warning text length allowed by the distribution spec
+const MAX_WARNING_LENGTH: usize = 4096;
+
+/// Non-fatal conditions collected while handling a request, sent to clients as
+/// `Warning: 299 - "<text>"` headers per the distribution spec
+#[derive(Debug, Default)]
+pub(crate) struct Warnings(Vec<String>);
+
+impl Warnings {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn push(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        log::info!("warnings: {}", text);
+        self.0.push(text);
+    }
+
+    pub(crate) fn extend(&mut self, texts: impl IntoIterator<Item = String>) {
+        for text in texts {
+            self.push(text);
+        }
+    }
+
+    /// Append one `Warning` header per collected warning
+    pub(crate) fn apply<B>(self, mut response: Response<B>) -> Response<B> {
+        for text in self.0 {
+            if let Ok(value) = HeaderValue::from_str(&header_value(&text)) {
+                response.headers_mut().append("Warning", value);
+            }
+        }
+        response
+    }
+}
+
+/// Format a warning as `299 - "<quoted text>"`, escaping and truncating the text
+fn header_value(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 8);
+    for c in text
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_WARNING_LENGTH)
+    {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        // Header values must be visible ASCII
+        quoted.push(if c.is_ascii() { c } else { '?' });
+    }
+    format!("299 - \"{}\"", quoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    #[test]
+    fn test_header_value_format() {
+        assert_eq!(header_value("plain"), "299 - \"plain\"");
+        assert_eq!(
+            header_value("say \"hi\" \\ bye"),
+            "299 - \"say \\\"hi\\\" \\\\ bye\""
+        );
+        assert_eq!(header_value("line\nbreak"), "299 - \"linebreak\"");
+    }
+
+    #[test]
+    fn test_header_value_truncated() {
+        let long = "a".repeat(MAX_WARNING_LENGTH + 100);
+        assert_eq!(header_value(&long).len(), MAX_WARNING_LENGTH + 8);
+    }
+
+    #[test]
+    fn test_apply_appends_each_warning() {
+        let mut warnings = Warnings::new();
+        warnings.push("first");
+        warnings.push("second");
+
+        let response = warnings.apply(Response::new(Body::empty()));
+        let values: Vec<_> = response.headers().get_all("Warning").iter().collect();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0], "299 - \"first\"");
+    }
+}
@@ -0,0 +1,370 @@
+//! Registry event notifications in the docker/distribution format, POSTed to
+//! `--webhook-urls` so CD systems can react when a tag is pushed, pulled or deleted
+
+use axum::http::HeaderMap;
+use clap::ValueEnum;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::{
+    sync::{
+        mpsc::{self, SyncSender, TrySendError},
+        Arc,
+    },
+    time::Duration,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{args::Args, metrics, secrets::Secret};
+
+/// Media type of the envelope, as sent by docker/distribution
+const ENVELOPE_MEDIA_TYPE: &str = "application/vnd.docker.distribution.events.v1+json";
+
+/// First wait before retrying a failed delivery, doubled on each retry
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Events waiting per webhook; once a slow endpoint has this many, new events for it are dropped
+const MAX_QUEUED: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Action {
+    Push,
+    Pull,
+    Delete,
+}
+
+/// Manifest an event is about
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Target {
+    pub media_type: String,
+    pub size: u64,
+    pub digest: String,
+    pub length: u64,
+    pub repository: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RequestInfo {
+    pub id: String,
+    pub host: String,
+    pub method: String,
+    pub useragent: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Actor {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Source {
+    pub addr: String,
+    #[serde(rename = "instanceID")]
+    pub instance_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Event {
+    pub id: String,
+    pub timestamp: String,
+    pub action: Action,
+    pub target: Target,
+    pub request: RequestInfo,
+    pub actor: Actor,
+    pub source: Source,
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    events: [&'a Event; 1],
+}
+
+impl Event {
+    /// Event on manifest `digest` (`sha256:<hex>`) of `repository`, reached by `tag` if any
+    pub(crate) fn new(
+        action: Action,
+        actor: &str,
+        repository: &str,
+        tag: Option<&str>,
+        digest: &str,
+        manifest: &[u8],
+    ) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            action,
+            target: Target {
+                media_type: serde_json::from_slice::<serde_json::Value>(manifest)
+                    .ok()
+                    .and_then(|m| m.get("mediaType")?.as_str().map(String::from))
+                    .unwrap_or_default(),
+                size: manifest.len() as u64,
+                digest: digest.to_string(),
+                length: manifest.len() as u64,
+                repository: repository.to_string(),
+                tag: tag.map(String::from),
+            },
+            request: RequestInfo {
+                id: String::new(),
+                host: String::new(),
+                method: String::new(),
+                useragent: String::new(),
+            },
+            actor: Actor {
+                name: actor.to_string(),
+            },
+            source: Source {
+                addr: String::new(),
+                instance_id: String::new(),
+            },
+        }
+    }
+
+    /// Describe the request that caused the event
+    pub(crate) fn with_request(mut self, method: &str, headers: &HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+        let id = header("x-request-id");
+        self.request = RequestInfo {
+            // Set by the request ID middleware, so the event matches the request's log lines
+            id: if id.is_empty() {
+                uuid::Uuid::new_v4().to_string()
+            } else {
+                id
+            },
+            host: header("host"),
+            method: method.to_string(),
+            useragent: header("user-agent"),
+        };
+        self
+    }
+}
+
+/// Sends events to every webhook on a dedicated thread, so handlers never wait on delivery
+pub(crate) struct Notifier {
+    /// Queue of each webhook's worker thread, by URL
+    queues: Vec<(String, SyncSender<Arc<Delivery>>)>,
+    secret: Option<Secret>,
+    actions: Vec<Action>,
+    source: Source,
+}
+
+/// Serialized event, shared by the queues of every webhook
+struct Delivery {
+    body: Vec<u8>,
+    signature: Option<String>,
+}
+
+impl Notifier {
+    pub(crate) fn from_args(args: &Args, instance_id: &str) -> Self {
+        let source = Source {
+            addr: args.primary_host().to_string(),
+            instance_id: instance_id.to_string(),
+        };
+        if args.webhook_urls.is_empty() {
+            return Self {
+                queues: Vec::new(),
+                secret: None,
+                actions: Vec::new(),
+                source,
+            };
+        }
+
+        let secret = args
+            .webhook_secret
+            .as_deref()
+            .map(|secret| Secret::resolve_or_exit("--webhook-secret", secret));
+        let retries = args.webhook_retries;
+        log::info!(
+            "webhooks: sending events to {}",
+            args.webhook_urls.join(", ")
+        );
+
+        // One worker per URL, so an endpoint that is down does not hold up the others
+        let queues = args
+            .webhook_urls
+            .iter()
+            .map(|url| {
+                let (sender, receiver) = mpsc::sync_channel::<Arc<Delivery>>(MAX_QUEUED);
+                let worker_url = url.clone();
+                std::thread::Builder::new()
+                    .name("webhooks".to_string())
+                    .spawn(move || {
+                        // Built on the thread: the blocking client must not be created inside
+                        // the runtime
+                        let client = match reqwest::blocking::Client::builder()
+                            .timeout(Duration::from_secs(5))
+                            .build()
+                        {
+                            Ok(client) => client,
+                            Err(e) => {
+                                log::error!("webhooks: cannot build HTTP client: {}", e);
+                                return;
+                            }
+                        };
+                        for delivery in receiver {
+                            deliver(
+                                &client,
+                                &worker_url,
+                                &delivery.body,
+                                delivery.signature.as_deref(),
+                                retries,
+                            );
+                        }
+                    })
+                    .expect("failed to spawn webhooks thread");
+                (url.clone(), sender)
+            })
+            .collect();
+
+        Self {
+            queues,
+            secret,
+            actions: args.webhook_actions.clone(),
+            source,
+        }
+    }
+
+    /// Queue `event` for every webhook, unless its action is filtered out
+    pub(crate) fn notify(&self, mut event: Event) {
+        if self.queues.is_empty() || !self.actions.contains(&event.action) {
+            return;
+        }
+        event.source = self.source.clone();
+        let body = match serde_json::to_vec(&Envelope { events: [&event] }) {
+            Ok(body) => body,
+            Err(e) => {
+                log::error!("webhooks: cannot serialize event: {}", e);
+                return;
+            }
+        };
+        let signature = self.secret.as_ref().map(|secret| sign(secret, &body));
+        let delivery = Arc::new(Delivery { body, signature });
+        for (url, queue) in &self.queues {
+            match queue.try_send(delivery.clone()) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    log::error!("webhooks: queue for {} full, dropped event", url);
+                    metrics::WEBHOOK_DELIVERIES_TOTAL
+                        .with_label_values(&["dropped"])
+                        .inc();
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    log::error!("webhooks: event dropped, thread for {} is not running", url);
+                }
+            }
+        }
+    }
+}
+
+/// `sha256=<hex HMAC of body>`, sent as `X-Grain-Signature`
+fn sign(secret: &Secret, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.get().as_bytes())
+        .expect("HMAC accepts any key length");
+    mac.update(body);
+    let hex: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256={}", hex)
+}
+
+/// POST `body` to `url`, retrying up to `retries` times with exponential backoff
+fn deliver(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    body: &[u8],
+    signature: Option<&str>,
+    retries: u32,
+) {
+    let mut backoff = RETRY_BACKOFF;
+    for attempt in 0..=retries {
+        let mut request = client
+            .post(url)
+            .header("Content-Type", ENVELOPE_MEDIA_TYPE)
+            .body(body.to_vec());
+        if let Some(signature) = signature {
+            request = request.header("X-Grain-Signature", signature);
+        }
+        match request.send().and_then(|r| r.error_for_status()) {
+            Ok(_) => {
+                metrics::WEBHOOK_DELIVERIES_TOTAL
+                    .with_label_values(&["success"])
+                    .inc();
+                return;
+            }
+            Err(e) if attempt < retries => {
+                log::warn!(
+                    "webhooks: delivery to {} failed ({}), retrying in {}s",
+                    url,
+                    e,
+                    backoff.as_secs()
+                );
+                std::thread::sleep(backoff);
+                backoff *= 2;
+            }
+            Err(e) => log::error!(
+                "webhooks: giving up on {} after {} attempts: {}",
+                url,
+                attempt + 1,
+                e
+            ),
+        }
+    }
+    metrics::WEBHOOK_DELIVERIES_TOTAL
+        .with_label_values(&["failure"])
+        .inc();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_payload() {
+        let manifest = br#"{"mediaType":"application/vnd.oci.image.manifest.v1+json"}"#;
+        let event = Event::new(
+            Action::Push,
+            "ci",
+            "team/app",
+            Some("v1"),
+            "sha256:abc",
+            manifest,
+        );
+        let json = serde_json::to_value(Envelope { events: [&event] }).unwrap();
+        let event = &json["events"][0];
+        assert_eq!(event["action"], "push");
+        assert_eq!(event["actor"]["name"], "ci");
+        assert_eq!(
+            event["target"]["mediaType"],
+            "application/vnd.oci.image.manifest.v1+json"
+        );
+        assert_eq!(event["target"]["length"], manifest.len());
+        assert_eq!(event["target"]["tag"], "v1");
+        assert!(event["source"].get("instanceID").is_some());
+    }
+
+    #[test]
+    fn test_signature() {
+        let secret = Secret::resolve("key").unwrap();
+        // HMAC-SHA256("key", "The quick brown fox jumps over the lazy dog")
+        assert_eq!(
+            sign(&secret, b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+}
@@ -58,6 +58,39 @@ fn test_admin_create_user() {
     assert_eq!(resp.status(), 200);
 }
 
+#[test]
+#[serial]
+fn test_admin_create_user_password_policy() {
+    let mut server = TestServer::new();
+    server.start_with_args(&[
+        "--password-min-length",
+        "10",
+        "--password-require-complexity",
+        "--password-disallow-username",
+    ]);
+    let client = server.client();
+
+    let resp = client
+        .post("/admin/users")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({"username": "carol", "password": "carol", "permissions": []}))
+        .send()
+        .unwrap();
+
+    assert_eq!(resp.status(), 400);
+    let json: serde_json::Value = resp.json().unwrap();
+    assert_eq!(json["violations"].as_array().unwrap().len(), 3);
+
+    let resp = client
+        .post("/admin/users")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({"username": "carol", "password": "Sufficient1Pass", "permissions": []}))
+        .send()
+        .unwrap();
+
+    assert_eq!(resp.status(), 201);
+}
+
 #[test]
 #[serial]
 fn test_admin_create_duplicate_user() {
@@ -195,6 +228,243 @@ fn test_admin_add_permission() {
     assert_eq!(resp.status(), 201);
 }
 
+#[test]
+#[serial]
+fn test_admin_update_user_and_change_password() {
+    use grain::client::AdminClient;
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    client
+        .post("/admin/users")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({
+            "username": "rotate",
+            "password": "old-pass",
+            "permissions": [{"repository": "test/*", "tag": "*", "actions": ["pull"]}]
+        }))
+        .send()
+        .unwrap();
+    let login = |password: &str| {
+        client
+            .get("/v2/")
+            .basic_auth("rotate", Some(password))
+            .send()
+            .unwrap()
+            .status()
+    };
+
+    // PUT replaces the password and the whole permission set
+    let update = serde_json::json!({
+        "password": "new-pass",
+        "permissions": [{"repository": "test/repo", "tag": "*", "actions": ["pull", "push"]}]
+    });
+    let resp = client
+        .put("/admin/users/rotate")
+        .basic_auth("admin", Some("admin"))
+        .json(&update)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = resp.json().unwrap();
+    assert_eq!(json["permissions"][0]["repository"], "test/repo");
+    assert_eq!(json["permissions"].as_array().unwrap().len(), 1);
+    assert_eq!(login("old-pass"), 401);
+    assert_eq!(login("new-pass"), 200);
+
+    let resp = client
+        .put("/admin/users/nobody")
+        .basic_auth("admin", Some("admin"))
+        .json(&update)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+    let resp = client
+        .put("/admin/users/rotate")
+        .basic_auth("reader", Some("reader"))
+        .json(&update)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+
+    // Users may change their own password given the current one, keeping their permissions,
+    // but no one else's
+    for current in [None, Some("guess")] {
+        let resp = client
+            .post("/admin/users/rotate/password")
+            .basic_auth("rotate", Some("new-pass"))
+            .json(&serde_json::json!({"password": "newer-pass", "current_password": current}))
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 403);
+    }
+    let resp = client
+        .post("/admin/users/rotate/password")
+        .basic_auth("rotate", Some("new-pass"))
+        .json(&serde_json::json!({"password": "newer-pass", "current_password": "new-pass"}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 204);
+    assert_eq!(login("new-pass"), 401);
+    assert_eq!(login("newer-pass"), 200);
+    let resp = client
+        .post("/admin/users/reader/password")
+        .basic_auth("rotate", Some("newer-pass"))
+        .json(&serde_json::json!({"password": "taken-over"}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+
+    let admin = AdminClient::new(&server.base_url, "admin", "admin");
+    admin.change_password("rotate", "admin-set").unwrap();
+    assert_eq!(login("admin-set"), 200);
+    let users = admin.list_users().unwrap();
+    let rotate = users.iter().find(|u| u.username == "rotate").unwrap();
+    assert_eq!(rotate.permissions[0].repository, "test/repo");
+}
+
+#[test]
+#[serial]
+fn test_change_own_password_body_limit() {
+    let mut server = TestServer::new();
+    server.start_with_args(&["--max-admin-body-size", "1024"]);
+
+    // Bodies are capped like admin requests, before authentication
+    let resp = server
+        .client()
+        .post("/user/password")
+        .body(vec![b' '; 4096])
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 413);
+}
+
+#[test]
+#[serial]
+fn test_change_own_password() {
+    use grain::client::AdminClient;
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    let login = |password: &str| {
+        client
+            .get("/v2/")
+            .basic_auth("reader", Some(password))
+            .send()
+            .unwrap()
+            .status()
+    };
+
+    // A wrong current password is refused even with valid credentials
+    let resp = client
+        .post("/user/password")
+        .basic_auth("reader", Some("reader"))
+        .json(&serde_json::json!({"current_password": "guess", "new_password": "rotated"}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+    let resp = client
+        .post("/user/password")
+        .json(&serde_json::json!({"current_password": "reader", "new_password": "rotated"}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+
+    // Non-admin users rotate their own password
+    let resp = client
+        .post("/user/password")
+        .basic_auth("reader", Some("reader"))
+        .json(&serde_json::json!({"current_password": "reader", "new_password": "rotated"}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 204);
+    assert_eq!(login("reader"), 401);
+    assert_eq!(login("rotated"), 200);
+
+    AdminClient::new(&server.base_url, "reader", "rotated")
+        .change_own_password("rotated-again")
+        .unwrap();
+    assert_eq!(login("rotated-again"), 200);
+
+    // Persisted to the users file
+    let users = std::fs::read_to_string(&server.users_file).unwrap();
+    assert!(users.contains("rotated-again"));
+}
+
+#[test]
+#[serial]
+fn test_admin_remove_permission() {
+    use grain::client::AdminClient;
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    client
+        .post("/admin/users")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({
+            "username": "narrow",
+            "password": "narrow-pass",
+            "permissions": [
+                {"repository": "test/repo", "tag": "*", "actions": ["pull", "push"]},
+                {"repository": "test/other", "tag": "*", "actions": ["pull"]}
+            ]
+        }))
+        .send()
+        .unwrap();
+
+    // Revoking some actions keeps the rest of the permission
+    let resp = client
+        .delete("/admin/users/narrow/permissions")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({"repository": "test/repo", "tag": "*", "actions": ["push"]}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = resp.json().unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 2);
+    assert_eq!(json[0]["actions"], serde_json::json!(["pull"]));
+
+    let resp = client
+        .put("/v2/test/repo/manifests/v1")
+        .basic_auth("narrow", Some("narrow-pass"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .body("{}")
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+
+    // Without actions the whole permission goes; nothing left to match is a 404
+    let admin = AdminClient::new(&server.base_url, "admin", "admin");
+    let remaining = admin
+        .remove_permission("narrow", "test/repo", "*", None)
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].repository, "test/other");
+    assert!(admin
+        .remove_permission("narrow", "test/repo", "*", None)
+        .is_err());
+
+    let resp = client
+        .delete("/admin/users/nobody/permissions")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({"repository": "test/other", "tag": "*"}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+    let resp = client
+        .delete("/admin/users/narrow/permissions")
+        .basic_auth("reader", Some("reader"))
+        .json(&serde_json::json!({"repository": "test/other", "tag": "*"}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+}
+
 #[test]
 #[serial]
 fn test_admin_requires_admin_permission() {
@@ -227,6 +497,336 @@ fn test_admin_requires_admin_permission() {
     assert_eq!(resp.status(), 200);
 }
 
+#[test]
+#[serial]
+fn test_roles_and_groups() {
+    use grain::client::{AccessGroup, AccessRole, AdminClient, CreateUserRequest, Permission};
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    let admin = AdminClient::new(&server.base_url, "admin", "admin");
+    let permission = |repository: &str, actions: &[&str]| Permission {
+        repository: repository.to_string(),
+        tag: "*".to_string(),
+        actions: actions.iter().map(|a| a.to_string()).collect(),
+        expires_at: None,
+    };
+    let tags_status = |user: &str, repository: &str| {
+        client
+            .get(&format!("/v2/{}/tags/list", repository))
+            .basic_auth(user, Some(user))
+            .send()
+            .unwrap()
+            .status()
+    };
+
+    admin
+        .set_role(
+            "app-reader",
+            &AccessRole {
+                permissions: vec![permission("app/*", &["pull"])],
+            },
+        )
+        .unwrap();
+    admin
+        .set_group(
+            "platform",
+            &AccessGroup {
+                roles: vec!["app-reader".to_string()],
+                permissions: vec![permission("infra/*", &["pull"])],
+            },
+        )
+        .unwrap();
+
+    // Unknown names are refused
+    let request = |username: &str, roles: &[&str], groups: &[&str]| CreateUserRequest {
+        username: username.to_string(),
+        password: username.to_string(),
+        permissions: vec![],
+        admin: false,
+        admin_orgs: vec![],
+        roles: roles.iter().map(|r| r.to_string()).collect(),
+        groups: groups.iter().map(|g| g.to_string()).collect(),
+    };
+    assert!(admin.create_user(&request("bad", &["nope"], &[])).is_err());
+    assert!(admin
+        .set_group(
+            "broken",
+            &AccessGroup {
+                roles: vec!["nope".to_string()],
+                permissions: vec![],
+            }
+        )
+        .is_err());
+
+    admin
+        .create_user(&request("viarole", &["app-reader"], &[]))
+        .unwrap();
+    admin
+        .create_user(&request("viagroup", &[], &["platform"]))
+        .unwrap();
+    assert_eq!(tags_status("viarole", "app/web"), 200);
+    assert_eq!(tags_status("viarole", "infra/dns"), 403);
+    assert_eq!(tags_status("viagroup", "app/web"), 200);
+    assert_eq!(tags_status("viagroup", "infra/dns"), 200);
+
+    // Editing a role applies to everyone holding it at once
+    admin
+        .set_role(
+            "app-reader",
+            &AccessRole {
+                permissions: vec![permission("other/*", &["pull"])],
+            },
+        )
+        .unwrap();
+    assert_eq!(tags_status("viarole", "app/web"), 403);
+    assert_eq!(tags_status("viagroup", "app/web"), 403);
+
+    // Referenced roles and groups with members cannot be deleted
+    let resp = client
+        .delete("/admin/roles/app-reader")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 409);
+    let resp = client
+        .delete("/admin/groups/platform")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 409);
+    admin.delete_user("viagroup").unwrap();
+    admin.delete_group("platform").unwrap();
+    admin.delete_user("viarole").unwrap();
+    admin.delete_role("app-reader").unwrap();
+
+    let roles = admin.list_roles().unwrap();
+    assert!(roles.roles.is_empty() && roles.groups.is_empty());
+
+    // Roles live in the users file next to the users
+    admin
+        .set_role(
+            "kept",
+            &AccessRole {
+                permissions: vec![permission("kept/*", &["pull"])],
+            },
+        )
+        .unwrap();
+    let users: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&server.users_file).unwrap()).unwrap();
+    assert_eq!(
+        users["roles"]["kept"]["permissions"][0]["repository"],
+        "kept/*"
+    );
+}
+
+#[test]
+#[serial]
+fn test_admin_flag() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    let list_users = |user: &str, password: &str| {
+        client
+            .get("/admin/users")
+            .basic_auth(user, Some(password))
+            .send()
+            .unwrap()
+            .status()
+    };
+
+    // Deleting everything does not grant the admin API, and the admin flag needs no permission
+    for (username, admin, permissions) in [
+        (
+            "janitor",
+            false,
+            serde_json::json!([{"repository": "*", "tag": "*", "actions": ["pull", "push", "delete"]}]),
+        ),
+        ("operator", true, serde_json::json!([])),
+    ] {
+        let resp = client
+            .post("/admin/users")
+            .basic_auth("admin", Some("admin"))
+            .json(&serde_json::json!({
+                "username": username,
+                "password": username,
+                "admin": admin,
+                "permissions": permissions,
+            }))
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+    }
+    assert_eq!(list_users("janitor", "janitor"), 403);
+    assert_eq!(list_users("operator", "operator"), 200);
+    let resp = client
+        .get("/v2/test/repo/tags/list")
+        .basic_auth("operator", Some("operator"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+
+    // Admins cannot revoke their own flag; an omitted flag is left unchanged
+    let resp = client
+        .put("/admin/users/operator")
+        .basic_auth("operator", Some("operator"))
+        .json(&serde_json::json!({"password": "operator", "admin": false}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+    let resp = client
+        .put("/admin/users/operator")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({"password": "operator"}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(list_users("operator", "operator"), 200);
+    let resp = client
+        .put("/admin/users/operator")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({"password": "operator", "admin": false}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(list_users("operator", "operator"), 403);
+}
+
+#[test]
+#[serial]
+fn test_admin_flag_legacy_users_file() {
+    // Files without any admin flag grant no admin, even to */* deleters
+    let mut users = default_test_users();
+    users["users"][0].as_object_mut().unwrap().remove("admin");
+    let mut server = TestServer::new_with_users(users);
+    server.start();
+    let client = server.client();
+
+    let resp = client
+        .get("/admin/users")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+}
+
+#[test]
+#[serial]
+fn test_org_admin() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    let as_lead = |req: reqwest::blocking::RequestBuilder| {
+        req.basic_auth("lead", Some("lead"))
+            .send()
+            .unwrap()
+            .status()
+    };
+
+    let resp = client
+        .post("/admin/users")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({
+            "username": "lead",
+            "password": "lead",
+            "admin_orgs": ["team"],
+        }))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let resp = client
+        .post("/admin/users")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({"username": "lead2", "password": "lead2", "admin_orgs": ["team/app"]}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    // Accounts within the org can be created, listed and changed
+    let dev = |repository: &str| {
+        serde_json::json!({
+            "username": "dev",
+            "password": "dev",
+            "permissions": [{"repository": repository, "tag": "*", "actions": ["pull", "push"]}],
+        })
+    };
+    assert_eq!(
+        as_lead(client.post("/admin/users").json(&dev("other/*"))),
+        403
+    );
+    assert_eq!(as_lead(client.post("/admin/users").json(&dev("*"))), 403);
+    assert_eq!(
+        as_lead(client.post("/admin/users").json(&dev("team/*"))),
+        201
+    );
+    let resp = client
+        .get("/admin/users")
+        .basic_auth("lead", Some("lead"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = resp.json().unwrap();
+    let usernames: Vec<&str> = json["users"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|u| u["username"].as_str().unwrap())
+        .collect();
+    assert_eq!(usernames, ["dev"]);
+    assert_eq!(
+        as_lead(
+            client
+                .post("/admin/users/dev/password")
+                .json(&serde_json::json!({"password": "rotated"}))
+        ),
+        204
+    );
+
+    // Permissions can only be granted and revoked within the org, and never to admins
+    let grant = |username: &str, repository: &str| {
+        client
+            .post(&format!("/admin/users/{}/permissions", username))
+            .json(&serde_json::json!({"repository": repository, "tag": "*", "actions": ["pull"]}))
+    };
+    assert_eq!(as_lead(grant("dev", "team/app")), 200);
+    assert_eq!(as_lead(grant("dev", "other/app")), 403);
+    assert_eq!(as_lead(grant("admin", "team/app")), 403);
+    assert_eq!(as_lead(grant("missing", "team/app")), 404);
+    assert_eq!(
+        as_lead(
+            client
+                .delete("/admin/users/dev/permissions")
+                .json(&serde_json::json!({"repository": "team/app", "tag": "*"}))
+        ),
+        200
+    );
+    assert_eq!(
+        as_lead(
+            client
+                .put("/admin/users/dev")
+                .json(&serde_json::json!({"password": "rotated", "admin": true}))
+        ),
+        403
+    );
+
+    // Garbage collection must be scoped to the org, and the rest of the admin API is off limits
+    assert_eq!(as_lead(client.post("/admin/gc?dry_run=true")), 403);
+    assert_eq!(
+        as_lead(client.post("/admin/gc?dry_run=true&repository=other")),
+        403
+    );
+    assert_eq!(
+        as_lead(client.post("/admin/gc?dry_run=true&repository=team")),
+        200
+    );
+    assert_eq!(as_lead(client.get("/admin/robots")), 403);
+
+    // Accounts outside the org cannot be touched
+    assert_eq!(as_lead(client.delete("/admin/users/admin")), 403);
+    assert_eq!(as_lead(client.delete("/admin/users/dev")), 200);
+}
+
 #[test]
 #[serial]
 fn test_admin_api_requires_authentication() {
@@ -320,3 +920,1281 @@ fn test_admin_user_persistence() {
     let persistent_user = users.iter().find(|u| u["username"] == "persistent");
     assert!(persistent_user.is_some());
 }
+
+#[test]
+#[serial]
+fn test_admin_resolve_reference() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+
+    let manifest = sample_manifest();
+    client
+        .put("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .json(&manifest)
+        .send()
+        .unwrap();
+
+    let resp = client
+        .get("/admin/repos/test/repo/resolve?ref=latest")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = resp.json().unwrap();
+    assert_eq!(json["digest"], sample_manifest_digest(&manifest));
+    assert_eq!(
+        json["mediaType"],
+        "application/vnd.oci.image.manifest.v1+json"
+    );
+    assert_eq!(json["platforms"].as_array().unwrap().len(), 1);
+    // Config and layer share a digest, so the blob is only counted once
+    assert_eq!(json["total_size"], 27);
+
+    let resp = client
+        .get("/admin/repos/test/repo/resolve?ref=missing")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[test]
+#[serial]
+fn test_admin_repository_stats() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    let stats = || {
+        client
+            .get("/admin/repos/test/repo/stats")
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap()
+    };
+    assert_eq!(stats().status(), 404);
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let resp = client
+        .put("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .json(&sample_manifest())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    let json: serde_json::Value = stats().json().unwrap();
+    assert_eq!(json["tag_count"], 1);
+    assert_eq!(json["blob_count"], 1);
+    assert_eq!(json["total_size"], sample_blob().len());
+    assert!(json["last_pushed_at"].as_u64().unwrap() > 0);
+    assert!(json.get("last_pulled_at").is_none());
+
+    let resp = client
+        .get("/v2/test/repo/manifests/latest")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = stats().json().unwrap();
+    assert!(json["last_pulled_at"].as_u64().unwrap() >= json["last_pushed_at"].as_u64().unwrap());
+
+    let resp = client
+        .get("/admin/repos/test/repo/stats")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+}
+
+#[test]
+#[serial]
+fn test_admin_versioned_paths_and_aliases() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    for path in ["/admin/v1/users", "/admin/users"] {
+        let resp = client
+            .get(path)
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 200, "{}", path);
+    }
+}
+
+#[test]
+#[serial]
+fn test_admin_typed_client() {
+    use grain::client::{AdminClient, ClientError, CreateUserRequest, GcRunOptions, Permission};
+
+    let mut server = TestServer::new();
+    server.start();
+    let admin = AdminClient::new(&server.base_url, "admin", "admin");
+
+    admin
+        .create_user(&CreateUserRequest {
+            username: "typed".to_string(),
+            password: "typedpass".to_string(),
+            permissions: vec![],
+            admin: false,
+            admin_orgs: vec![],
+            roles: vec![],
+            groups: vec![],
+        })
+        .unwrap();
+
+    let permission = Permission {
+        repository: "typed/*".to_string(),
+        tag: "*".to_string(),
+        actions: vec!["pull".to_string()],
+        expires_at: None,
+    };
+    assert_eq!(
+        admin.add_permission("typed", &permission).unwrap(),
+        permission
+    );
+
+    let users = admin.list_users().unwrap();
+    let typed = users.iter().find(|u| u.username == "typed").unwrap();
+    assert_eq!(typed.permissions, vec![permission]);
+
+    let stats = admin
+        .run_gc(&GcRunOptions {
+            dry_run: Some(true),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(stats.blobs_deleted, 0);
+
+    admin.delete_user("typed").unwrap();
+
+    let unauthorized = AdminClient::new(&server.base_url, "reader", "reader");
+    match unauthorized.list_users() {
+        Err(ClientError::Api { status, .. }) => assert_eq!(status, 403),
+        other => panic!("expected 403, got {:?}", other.map(|u| u.len())),
+    }
+}
+
+#[test]
+#[serial]
+fn test_admin_pull_secret() {
+    use base64::{prelude::BASE64_STANDARD, Engine};
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let resp = client
+        .post("/admin/v1/repos/test/repo/pull-secret?namespace=apps")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let secret: serde_json::Value = resp.json().unwrap();
+    assert_eq!(secret["kind"], "Secret");
+    assert_eq!(secret["type"], "kubernetes.io/dockerconfigjson");
+    assert_eq!(secret["metadata"]["name"], "grain-test-repo-pull");
+    assert_eq!(secret["metadata"]["namespace"], "apps");
+
+    let config = BASE64_STANDARD
+        .decode(secret["data"][".dockerconfigjson"].as_str().unwrap())
+        .unwrap();
+    let config: serde_json::Value = serde_json::from_slice(&config).unwrap();
+    let auth = &config["auths"][&server.host];
+    assert_eq!(auth["username"], "robot$grain-test-repo-pull");
+    let token = auth["password"].as_str().unwrap();
+    assert!(token.starts_with("grain_"));
+    assert_eq!(
+        auth["auth"],
+        BASE64_STANDARD.encode(format!("robot$grain-test-repo-pull:{}", token))
+    );
+
+    // The embedded robot can pull from the repository and nothing else
+    let status = |request: reqwest::blocking::RequestBuilder| {
+        request
+            .basic_auth("robot$grain-test-repo-pull", Some(token))
+            .send()
+            .unwrap()
+            .status()
+    };
+    assert_eq!(status(client.get("/v2/test/repo/tags/list")), 200);
+    assert_eq!(status(client.get("/v2/other/repo/tags/list")), 403);
+    assert_eq!(status(client.post("/v2/test/repo/blobs/uploads/")), 403);
+
+    // Names are not reused, and must be valid robot names
+    let resp = client
+        .post("/admin/v1/repos/test/repo/pull-secret")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 409);
+
+    let resp = client
+        .post(&format!(
+            "/admin/v1/repos/test/repo/pull-secret?name={}",
+            "a".repeat(65)
+        ))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    let resp = client
+        .post("/admin/v1/repos/test/repo/pull-secret?name=mine")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+}
+
+#[test]
+#[serial]
+fn test_admin_blob_info_mount_source() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let digest = sample_blob_digest();
+    client
+        .post(&format!("/v2/test/source/blobs/uploads/?digest={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+
+    let resp = client
+        .post(&format!(
+            "/v2/test/target/blobs/uploads/?mount={}&from=test/source",
+            digest
+        ))
+        .basic_auth("writer", Some("writer"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    let resp = client
+        .get(&format!("/admin/v1/repos/test/target/blobs/{}", digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let info: serde_json::Value = resp.json().unwrap();
+    assert_eq!(info["digest"], digest);
+    assert_eq!(info["size"], sample_blob().len());
+    assert_eq!(info["mounted_from"]["source"], "test/source");
+    assert_eq!(info["mounted_from"]["mounted_by"], "writer");
+    assert_eq!(info["shared_with"], serde_json::json!(["test/source"]));
+
+    // Uploaded blobs have no mount origin
+    let resp = client
+        .get(&format!("/admin/v1/repos/test/source/blobs/{}", digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    let info: serde_json::Value = resp.json().unwrap();
+    assert!(info["mounted_from"].is_null());
+    assert_eq!(info["shared_with"], serde_json::json!(["test/target"]));
+
+    let resp = client
+        .get(&format!("/admin/v1/repos/test/missing/blobs/{}", digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+
+    let resp = client
+        .get(&format!("/admin/v1/repos/test/target/blobs/{}", digest))
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+}
+
+#[test]
+#[serial]
+fn test_admin_audit_file_sink() {
+    let dir = tempfile::tempdir().unwrap();
+    let audit_path = dir.path().join("audit.log");
+
+    let mut server = TestServer::new();
+    server.start_with_args(&["--audit-file", audit_path.to_str().unwrap()]);
+    let client = server.client();
+
+    client
+        .post("/admin/users")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({"username": "audited", "password": "audited-pass", "permissions": []}))
+        .send()
+        .unwrap();
+    client
+        .get("/admin/users")
+        .basic_auth("admin", Some("wrong"))
+        .send()
+        .unwrap();
+
+    // Events are written asynchronously
+    let mut events = Vec::new();
+    for _ in 0..50 {
+        events = std::fs::read_to_string(&audit_path)
+            .unwrap_or_default()
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect();
+        if events.len() >= 2 {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["action"], "user.create");
+    assert_eq!(events[0]["actor"], "admin");
+    assert_eq!(events[0]["target"], "audited");
+    assert_eq!(events[0]["outcome"], "success");
+    assert_eq!(events[1]["action"], "auth.login");
+    assert_eq!(events[1]["outcome"], "failure");
+}
+
+#[test]
+#[serial]
+fn test_admin_expired_permissions_cleaned_up() {
+    let mut server = TestServer::new();
+    server.start_with_args(&["--credential-cleanup-interval-secs", "1"]);
+    let client = server.client();
+
+    client
+        .post("/admin/users")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({"username": "temp", "password": "temp-pass", "permissions": []}))
+        .send()
+        .unwrap();
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    for (repository, expires_at) in [("test/*", now - 1), ("other/*", now + 3600)] {
+        let resp = client
+            .post("/admin/permissions")
+            .basic_auth("admin", Some("admin"))
+            .json(&serde_json::json!({
+                "username": "temp",
+                "repository": repository,
+                "tag": "*",
+                "actions": ["pull"],
+                "expires_at": expires_at
+            }))
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+    }
+
+    // A lapsed permission grants nothing, even before it is removed
+    let resp = client
+        .get("/v2/test/repo/tags/list")
+        .basic_auth("temp", Some("temp-pass"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+
+    let mut permissions = Vec::new();
+    for _ in 0..50 {
+        let users: serde_json::Value = client
+            .get("/admin/users")
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap()
+            .json()
+            .unwrap();
+        permissions = users["users"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|u| u["username"] == "temp")
+            .unwrap()["permissions"]
+            .as_array()
+            .unwrap()
+            .clone();
+        if permissions.len() == 1 {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+
+    assert_eq!(permissions.len(), 1);
+    assert_eq!(permissions[0]["repository"], "other/*");
+    assert_eq!(permissions[0]["expires_at"], now + 3600);
+}
+
+#[test]
+#[serial]
+fn test_admin_image_config() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let config = serde_json::json!({
+        "created": "2024-01-01T00:00:00Z",
+        "architecture": "arm64",
+        "os": "linux",
+        "variant": "v8",
+        "config": {
+            "Env": ["PATH=/usr/bin"],
+            "Labels": {"org.opencontainers.image.source": "https://example.com/app"},
+            "Entrypoint": ["/app"],
+            "Cmd": ["--serve"],
+            "ExposedPorts": {"8080/tcp": {}}
+        },
+        "rootfs": {"type": "layers", "diff_ids": ["sha256:aa", "sha256:bb"]},
+        "history": [
+            {"created": "2024-01-01T00:00:00Z", "created_by": "ADD rootfs /"},
+            {"created_by": "ENV PATH=/usr/bin", "empty_layer": true}
+        ]
+    });
+    let bytes = serde_json::to_vec(&config).unwrap();
+    let digest = format!("sha256:{}", sha256::digest(&bytes));
+    client
+        .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .body(bytes)
+        .send()
+        .unwrap();
+
+    let resp = client
+        .get(&format!("/admin/v1/repos/test/repo/config/{}", digest))
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let info: serde_json::Value = resp.json().unwrap();
+    assert_eq!(info["digest"], digest);
+    assert_eq!(info["architecture"], "arm64");
+    assert_eq!(info["variant"], "v8");
+    assert_eq!(info["env"], serde_json::json!(["PATH=/usr/bin"]));
+    assert_eq!(
+        info["labels"]["org.opencontainers.image.source"],
+        "https://example.com/app"
+    );
+    assert_eq!(info["entrypoint"], serde_json::json!(["/app"]));
+    assert_eq!(info["exposed_ports"], serde_json::json!(["8080/tcp"]));
+    assert_eq!(info["layers"], 2);
+    assert_eq!(info["history"][1]["empty_layer"], true);
+
+    // A layer blob is not a config
+    let resp = client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let resp = client
+        .get(&format!(
+            "/admin/v1/repos/test/repo/config/{}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 422);
+
+    let resp = client
+        .get("/admin/v1/repos/test/repo/config/sha256:nope")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+
+    let resp = client
+        .get(&format!("/admin/v1/repos/test/repo/config/{}", digest))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+}
+
+#[test]
+#[serial]
+fn test_admin_create_repository() {
+    use grain::client::{
+        AdminClient, ClientError, CreateRepositoryRequest, DefaultPermission, GcRunOptions,
+        RetentionPolicy, Visibility,
+    };
+
+    let mut server = TestServer::new();
+    server.start_with_args(&["--strict-repositories"]);
+    let client = server.client();
+    let admin = AdminClient::new(&server.base_url, "admin", "admin");
+
+    let push_blob = |user: &str, blob: &[u8]| {
+        client
+            .post(&format!(
+                "/v2/prod/app/blobs/uploads/?digest=sha256:{}",
+                sha256::digest(blob)
+            ))
+            .basic_auth(user, Some(user))
+            .body(blob.to_vec())
+            .send()
+            .unwrap()
+    };
+
+    // Strict mode rejects pushes to undeclared repositories
+    let resp = push_blob("admin", b"0123456789");
+    assert_eq!(resp.status(), 404);
+    let json: serde_json::Value = resp.json().unwrap();
+    assert_eq!(json["errors"][0]["code"], "NAME_UNKNOWN");
+
+    let request = CreateRepositoryRequest {
+        name: "prod/app".to_string(),
+        visibility: Visibility::Public,
+        quota_bytes: Some(20),
+        retention: Some(RetentionPolicy {
+            tags: "*".to_string(),
+            keep_last: Some(1),
+            max_age_days: None,
+        }),
+        immutable_tags: vec![],
+        default_permissions: vec![DefaultPermission {
+            username: "writer".to_string(),
+            tag: "*".to_string(),
+            actions: vec!["pull".to_string(), "push".to_string()],
+        }],
+    };
+    let repository = admin.create_repository(&request).unwrap();
+    assert_eq!(repository.name, "prod/app");
+    assert_eq!(repository.visibility, Visibility::Public);
+
+    let status = |result: Result<_, ClientError>| match result {
+        Err(ClientError::Api { status, .. }) => status.as_u16(),
+        other => panic!("expected an API error, got {:?}", other.map(|_| ())),
+    };
+    assert_eq!(status(admin.create_repository(&request)), 409);
+    let invalid = CreateRepositoryRequest {
+        name: "no-org".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(status(admin.create_repository(&invalid)), 400);
+    let unknown_user = CreateRepositoryRequest {
+        name: "prod/other".to_string(),
+        default_permissions: vec![DefaultPermission {
+            username: "ghost".to_string(),
+            tag: "*".to_string(),
+            actions: vec!["pull".to_string()],
+        }],
+        ..Default::default()
+    };
+    assert_eq!(status(admin.create_repository(&unknown_user)), 400);
+
+    let repositories = admin.list_repositories().unwrap();
+    assert_eq!(repositories.len(), 1);
+    assert_eq!(repositories[0].quota_bytes, Some(20));
+
+    // Default permissions let writer push; the quota caps the repository's blobs
+    assert_eq!(push_blob("writer", b"0123456789").status(), 201);
+    let resp = push_blob("writer", b"0123456789ab");
+    assert_eq!(resp.status(), 403);
+    let json: serde_json::Value = resp.json().unwrap();
+    assert_eq!(json["errors"][0]["code"], "DENIED");
+    assert_eq!(push_blob("writer", b"0123456789").status(), 201);
+
+    // Public repositories can be pulled anonymously, but not pushed to
+    let resp = client
+        .get(&format!(
+            "/v2/prod/app/blobs/sha256:{}",
+            sha256::digest(b"0123456789".as_slice())
+        ))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let resp = client.post("/v2/prod/app/blobs/uploads/").send().unwrap();
+    assert_eq!(resp.status(), 401);
+
+    // Retention keeps only the most recent tag
+    for (tag, annotation) in [("v1", "one"), ("v2", "two")] {
+        let mut manifest = sample_manifest();
+        manifest["annotations"] = serde_json::json!({ "release": annotation });
+        let resp = client
+            .put(&format!("/v2/prod/app/manifests/{}", tag))
+            .basic_auth("writer", Some("writer"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(&manifest)
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+
+    let run = |dry_run| GcRunOptions {
+        dry_run: Some(dry_run),
+        ..Default::default()
+    };
+    assert_eq!(admin.run_gc(&run(true)).unwrap().tags_expired, 1);
+    assert_eq!(admin.run_gc(&run(false)).unwrap().tags_expired, 1);
+    let tags: serde_json::Value = client
+        .get("/v2/prod/app/tags/list")
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(tags["tags"], serde_json::json!(["v2"]));
+}
+
+/// `(path, content)` of each regular file in a tar archive, following pax `path` records
+fn tar_entries(archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    let mut long_path = None;
+    while offset + 512 <= archive.len() && archive[offset] != 0 {
+        let header = &archive[offset..offset + 512];
+        let name = String::from_utf8_lossy(&header[..100])
+            .trim_end_matches('\0')
+            .to_string();
+        let size_field = String::from_utf8_lossy(&header[124..135]).to_string();
+        let size = usize::from_str_radix(size_field.trim_end_matches('\0'), 8).unwrap();
+        let content = archive[offset + 512..offset + 512 + size].to_vec();
+        offset += 512 + size.div_ceil(512) * 512;
+
+        if header[156] == b'x' {
+            let record = String::from_utf8(content).unwrap();
+            long_path = record
+                .split_once("path=")
+                .map(|(_, path)| path.trim_end_matches('\n').to_string());
+            continue;
+        }
+        entries.push((long_path.take().unwrap_or(name), content));
+    }
+    entries
+}
+
+#[test]
+#[serial]
+fn test_admin_download_urls() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let manifest = sample_manifest();
+    let manifest_bytes = serde_json::to_vec(&manifest).unwrap();
+    let manifest_digest = sample_manifest_digest(&manifest);
+    client
+        .put("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .body(manifest_bytes.clone())
+        .send()
+        .unwrap();
+
+    let create = |user: &str, body: serde_json::Value| {
+        client
+            .post("/admin/v1/repos/test/repo/download-urls")
+            .basic_auth(user, Some(user))
+            .json(&body)
+            .send()
+            .unwrap()
+    };
+
+    // Only admins create URLs
+    let resp = create(
+        "reader",
+        serde_json::json!({"kind": "blob", "reference": sample_blob_digest()}),
+    );
+    assert_eq!(resp.status(), 403);
+
+    let resp = create(
+        "admin",
+        serde_json::json!({"kind": "blob", "reference": sample_blob_digest(), "expires_in": 600}),
+    );
+    assert_eq!(resp.status(), 201);
+    let url: serde_json::Value = resp.json().unwrap();
+    let path = url["path"].as_str().unwrap().to_string();
+    assert!(path.starts_with(&format!(
+        "/downloads/test/repo/blobs/{}?expires=",
+        sample_blob_digest()
+    )));
+
+    // No credentials needed
+    let resp = client.get(&path).send().unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.bytes().unwrap().as_ref(), sample_blob().as_slice());
+
+    // Extending the expiry breaks the signature
+    let expires = url["expires_at"].as_u64().unwrap();
+    let tampered = path.replace(
+        &format!("expires={}", expires),
+        &format!("expires={}", expires + 3600),
+    );
+    let resp = client.get(&tampered).send().unwrap();
+    assert_eq!(resp.status(), 403);
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["errors"][0]["code"], "DENIED");
+
+    // The signature is bound to the repository
+    let resp = client
+        .get(&path.replace("/test/repo/", "/test/other/"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+
+    let resp = create(
+        "admin",
+        serde_json::json!({"kind": "blob", "reference": sample_blob_digest(), "expires_in": 365 * 24 * 3600}),
+    );
+    assert_eq!(resp.status(), 400);
+
+    let resp = create(
+        "admin",
+        serde_json::json!({"kind": "image", "reference": "missing"}),
+    );
+    assert_eq!(resp.status(), 404);
+
+    // Images are exported as an OCI image layout, with the tag resolved to its digest
+    let resp = create(
+        "admin",
+        serde_json::json!({"kind": "image", "reference": "latest"}),
+    );
+    assert_eq!(resp.status(), 201);
+    let url: serde_json::Value = resp.json().unwrap();
+    assert_eq!(url["digest"], manifest_digest.as_str());
+
+    let resp = client.get(url["path"].as_str().unwrap()).send().unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("Content-Type").unwrap(),
+        "application/x-tar"
+    );
+    let length: usize = resp
+        .headers()
+        .get("Content-Length")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let archive = resp.bytes().unwrap();
+    assert_eq!(archive.len(), length);
+
+    let entries = tar_entries(&archive);
+    let paths: Vec<&str> = entries.iter().map(|(path, _)| path.as_str()).collect();
+    let blob_hex = sample_blob_digest()
+        .trim_start_matches("sha256:")
+        .to_string();
+    let manifest_hex = manifest_digest.trim_start_matches("sha256:").to_string();
+    assert_eq!(
+        paths,
+        vec![
+            "oci-layout".to_string(),
+            "index.json".to_string(),
+            format!("blobs/sha256/{}", manifest_hex),
+            format!("blobs/sha256/{}", blob_hex),
+        ]
+    );
+    let index: serde_json::Value = serde_json::from_slice(&entries[1].1).unwrap();
+    assert_eq!(index["manifests"][0]["digest"], manifest_digest.as_str());
+    assert_eq!(entries[2].1, manifest_bytes);
+    assert_eq!(entries[3].1, sample_blob());
+}
+
+#[test]
+#[serial]
+fn test_robot_accounts() {
+    use grain::client::{AdminClient, ClientError, CreateRobotRequest, Permission};
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    let admin = AdminClient::new(&server.base_url, "admin", "admin");
+
+    let request = CreateRobotRequest {
+        name: "ci".to_string(),
+        description: "release pipeline".to_string(),
+        permissions: vec![Permission {
+            repository: "test/*".to_string(),
+            tag: "*".to_string(),
+            actions: vec!["pull".to_string(), "push".to_string()],
+            expires_at: None,
+        }],
+        expires_at: None,
+    };
+    let created = admin.create_robot(&request).unwrap();
+    assert_eq!(created.username, "robot$ci");
+    assert_eq!(created.robot.created_by, "admin");
+
+    fn status<T>(result: Result<T, ClientError>) -> u16 {
+        match result {
+            Err(ClientError::Api { status, .. }) => status.as_u16(),
+            Ok(_) => panic!("expected an API error"),
+            Err(e) => panic!("{}", e),
+        }
+    }
+    assert_eq!(status(admin.create_robot(&request)), 409);
+    let invalid = CreateRobotRequest {
+        name: "no/slash".to_string(),
+        ..request.clone()
+    };
+    assert_eq!(status(admin.create_robot(&invalid)), 400);
+    let reader = AdminClient::new(&server.base_url, "reader", "reader");
+    assert_eq!(status(reader.list_robots()), 403);
+
+    // The token is only stored hashed and never listed
+    let robots = admin.list_robots().unwrap();
+    assert_eq!(robots.len(), 1);
+    assert_eq!(robots[0].description, "release pipeline");
+    let resp = client
+        .get("/admin/v1/robots")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert!(!resp.text().unwrap().contains(&created.token));
+    let stored = std::fs::read_to_string(server.temp_dir.path().join("tmp/robots.json")).unwrap();
+    assert!(!stored.contains(&created.token));
+
+    let push = |repository: &str, token: &str| {
+        client
+            .post(&format!(
+                "/v2/{}/blobs/uploads/?digest={}",
+                repository,
+                sample_blob_digest()
+            ))
+            .basic_auth("robot$ci", Some(token))
+            .body(sample_blob())
+            .send()
+            .unwrap()
+            .status()
+    };
+    assert_eq!(push("test/app", &created.token), 201);
+    assert_eq!(push("other/app", &created.token), 403);
+    assert_eq!(push("test/app", "grain_wrong"), 401);
+
+    // Robots survive restarts, and revoked tokens stop working
+    server.stop();
+    server.start();
+    assert_eq!(push("test/app", &created.token), 201);
+    admin.revoke_robot("ci").unwrap();
+    assert_eq!(push("test/app", &created.token), 401);
+    assert_eq!(status(admin.revoke_robot("ci")), 404);
+    assert!(admin.list_robots().unwrap().is_empty());
+}
+
+#[test]
+#[serial]
+fn test_admin_archive_and_restore_repository() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    client
+        .post(&format!(
+            "/v2/test/old/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let mut manifest = sample_manifest();
+    manifest.as_object_mut().unwrap().remove("mediaType");
+    let docker_type = "application/vnd.docker.distribution.manifest.v2+json";
+    let resp = client
+        .put("/v2/test/old/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", docker_type)
+        .json(&manifest)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let stored = server.temp_dir.path().join("tmp/manifests/test/old/v1");
+    assert!(stored.exists());
+
+    let resp = client
+        .post("/admin/v1/repos/test/old/archive")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+
+    let resp = client
+        .post("/admin/v1/repos/test/old/archive")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let archive: serde_json::Value = resp.json().unwrap();
+    assert_eq!(archive["repository"], "test/old");
+    assert_eq!(archive["tags"], serde_json::json!(["v1"]));
+    assert_eq!(archive["blobs"], 1);
+    assert!(archive["bundle_bytes"].as_u64().unwrap() > 0);
+    let bundle = server.temp_dir.path().join("tmp/archives/test/old.tar.gz");
+    assert!(bundle.exists());
+    assert!(!stored.exists());
+
+    // Pulls get an error that says how to get the repository back
+    let resp = client
+        .get("/v2/test/old/manifests/v1")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["errors"][0]["code"], "NAME_UNKNOWN");
+    assert_eq!(body["errors"][0]["message"], "repository is archived");
+    assert!(body["errors"][0]["detail"]
+        .as_str()
+        .unwrap()
+        .contains("/admin/v1/repos/test/old/restore"));
+
+    // Callers without pull permission do not learn of the archive
+    let resp = client.get("/v2/test/old/manifests/v1").send().unwrap();
+    assert_eq!(resp.status(), 401);
+
+    let resp = client
+        .post("/admin/v1/repos/test/old/archive")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 409);
+    let resp = client
+        .get("/admin/v1/archives")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    let list: serde_json::Value = resp.json().unwrap();
+    assert_eq!(list["archives"][0]["repository"], "test/old");
+
+    let resp = client
+        .post("/admin/v1/repos/test/old/restore")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert!(!bundle.exists());
+    let resp = client
+        .get("/v2/test/old/manifests/v1")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], docker_type);
+    assert_eq!(resp.json::<serde_json::Value>().unwrap(), manifest);
+    let resp = client
+        .get(&format!("/v2/test/old/blobs/{}", sample_blob_digest()))
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.bytes().unwrap().to_vec(), sample_blob());
+
+    let resp = client
+        .post("/admin/v1/repos/test/old/restore")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+    let resp = client
+        .post("/admin/v1/repos/test/missing/archive")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[test]
+#[serial]
+fn test_archived_repository_refuses_push_only_callers() {
+    let mut users = default_test_users();
+    users["users"]
+        .as_array_mut()
+        .unwrap()
+        .push(serde_json::json!({
+            "username": "pusher",
+            "password": "pusher",
+            "permissions": [{"repository": "test/*", "tag": "*", "actions": ["push"]}]
+        }));
+    let mut server = TestServer::new_with_users(users);
+    server.start();
+    let client = server.client();
+
+    let resp = client
+        .put("/v2/test/old/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .json(&sample_manifest())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let resp = client
+        .post("/admin/v1/repos/test/old/archive")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    // Callers who may push but not pull are refused without reaching the handlers
+    let resp = client
+        .post(&format!(
+            "/v2/test/old/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("pusher", Some("pusher"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+    let resp = client
+        .put("/v2/test/old/manifests/v2")
+        .basic_auth("pusher", Some("pusher"))
+        .json(&sample_manifest())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+    assert!(!server
+        .temp_dir
+        .path()
+        .join("tmp/manifests/test/old/v2")
+        .exists());
+
+    // Callers who may pull learn of the archive, also on writes, without a denial recorded
+    let resp = client
+        .put("/v2/test/old/manifests/v2")
+        .basic_auth("writer", Some("writer"))
+        .json(&sample_manifest())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+    let metrics = client.get("/metrics").send().unwrap().text().unwrap();
+    let denials = metrics
+        .lines()
+        .find_map(|line| line.strip_prefix("grain_permission_denials_total "));
+    assert!(matches!(denials, None | Some("0")), "{:?}", denials);
+}
+
+#[test]
+#[serial]
+fn test_admin_dry_run() {
+    use grain::client::{AdminClient, ClientError, CreateRobotRequest};
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    let admin = AdminClient::new(&server.base_url, "admin", "admin");
+
+    client
+        .post(&format!(
+            "/v2/test/old/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let resp = client
+        .put("/v2/test/old/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .json(&sample_manifest())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    admin
+        .create_robot(&CreateRobotRequest {
+            name: "ci".to_string(),
+            description: String::new(),
+            permissions: Vec::new(),
+            expires_at: None,
+        })
+        .unwrap();
+
+    // Dry runs report what would change
+    let resp = client
+        .delete("/admin/v1/users/writer?dry_run=true")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["dry_run"], true);
+    assert_eq!(body["changes"][0]["action"], "user.delete");
+    assert_eq!(body["changes"][0]["target"], "writer");
+
+    let changes = admin.plan_revoke_robot("ci").unwrap();
+    assert_eq!(changes[0].action, "robot.revoke");
+    let changes = admin.plan_archive_repository("test", "old").unwrap();
+    assert_eq!(changes[0].action, "repo.archive");
+    assert_eq!(changes[0].target, "test/old");
+    assert!(changes[0].detail.as_ref().unwrap().contains("1 blobs"));
+
+    // ...without changing anything
+    let resp = client
+        .get("/v2/")
+        .basic_auth("writer", Some("writer"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(admin.list_robots().unwrap().len(), 1);
+    assert!(admin.list_archives().unwrap().is_empty());
+    assert!(server
+        .temp_dir
+        .path()
+        .join("tmp/manifests/test/old/v1")
+        .exists());
+
+    // ...and fail like the real request would
+    fn status<T>(result: Result<T, ClientError>) -> u16 {
+        match result {
+            Err(ClientError::Api { status, .. }) => status.as_u16(),
+            _ => 0,
+        }
+    }
+    assert_eq!(status(admin.plan_delete_user("nobody")), 404);
+    assert_eq!(status(admin.plan_delete_user("admin")), 400);
+    assert_eq!(status(admin.plan_revoke_robot("nobody")), 404);
+    assert_eq!(
+        status(admin.plan_archive_repository("test", "missing")),
+        404
+    );
+    let reader = AdminClient::new(&server.base_url, "reader", "reader");
+    assert_eq!(status(reader.plan_delete_user("writer")), 403);
+}
+
+#[test]
+#[serial]
+fn test_archived_repository_restored_on_pull() {
+    let mut server = TestServer::new();
+    server.start_with_args(&["--restore-archived-on-pull"]);
+    let client = server.client();
+
+    client
+        .post(&format!(
+            "/v2/test/old/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let manifest = sample_manifest();
+    client
+        .put("/v2/test/old/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .json(&manifest)
+        .send()
+        .unwrap();
+    let resp = client
+        .post("/admin/v1/repos/test/old/archive")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    // Pushes are still refused until the repository is back
+    let resp = client
+        .put("/v2/test/old/manifests/v2")
+        .basic_auth("writer", Some("writer"))
+        .json(&manifest)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+
+    let resp = client
+        .get("/v2/test/old/manifests/v1")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.json::<serde_json::Value>().unwrap(), manifest);
+
+    let resp = client
+        .get("/admin/v1/archives")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    let list: serde_json::Value = resp.json().unwrap();
+    assert!(list["archives"].as_array().unwrap().is_empty());
+}
+
+#[test]
+#[serial]
+fn test_admin_lists_paginate() {
+    use grain::client::AdminClient;
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let page = |query: &str| {
+        let resp = client
+            .get(&format!("/admin/v1/users{}", query))
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        let link = resp
+            .headers()
+            .get("link")
+            .map(|l| l.to_str().unwrap().to_string());
+        (resp.json::<serde_json::Value>().unwrap(), link)
+    };
+    let usernames = |body: &serde_json::Value| -> Vec<String> {
+        body["users"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|u| u["username"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    // Users are listed by name, a page at a time
+    let (first, link) = page("?n=2");
+    assert_eq!(usernames(&first), ["admin", "limited"]);
+    assert_eq!(first["next"], "limited");
+    assert_eq!(
+        link.as_deref(),
+        Some("</admin/v1/users?n=2&last=limited>; rel=\"next\"")
+    );
+    let (second, _) = page("?n=2&last=limited");
+    assert_eq!(usernames(&second), ["reader", "writer"]);
+    let (rest, link) = page("?last=reader");
+    assert_eq!(usernames(&rest), ["writer"]);
+    assert!(rest.get("next").is_none());
+    assert!(link.is_none());
+
+    // The client follows the pages
+    let admin = AdminClient::new(&server.base_url, "admin", "admin");
+    let all: Vec<String> = admin
+        .list_users()
+        .unwrap()
+        .into_iter()
+        .map(|u| u.username)
+        .collect();
+    assert_eq!(all, ["admin", "limited", "reader", "writer"]);
+}
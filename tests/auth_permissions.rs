@@ -394,6 +394,50 @@ fn test_permission_unauthorized_vs_forbidden() {
     assert_eq!(resp.status(), 403);
 }
 
+#[test]
+#[serial]
+fn test_permission_head_unauthorized_vs_forbidden() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let paths = [
+        format!("/v2/other/repo/blobs/{}", sample_blob_digest()),
+        "/v2/other/repo/manifests/latest".to_string(),
+    ];
+    for path in &paths {
+        // No credentials = 401 with the same challenge as GET
+        let get = client.get(path).send().unwrap();
+        let head = client.head(path).send().unwrap();
+        assert_eq!(head.status(), 401, "{}", path);
+        assert_eq!(
+            head.headers().get("WWW-Authenticate"),
+            get.headers().get("WWW-Authenticate"),
+            "{}",
+            path
+        );
+        assert_eq!(
+            head.headers().get("Content-Type"),
+            get.headers().get("Content-Type"),
+            "{}",
+            path
+        );
+
+        // Valid credentials but no permission = 403
+        let head = client
+            .head(path)
+            .basic_auth("limited", Some("limited"))
+            .send()
+            .unwrap();
+        assert_eq!(head.status(), 403, "{}", path);
+        assert!(head.headers().get("WWW-Authenticate").is_none());
+        assert_eq!(
+            head.headers().get("Content-Type").unwrap(),
+            "application/json"
+        );
+    }
+}
+
 #[test]
 #[serial]
 fn test_permission_admin_can_delete() {
@@ -484,3 +528,698 @@ fn test_permission_action_enforcement_on_manifest_operations() {
         .unwrap();
     assert_eq!(resp.status(), 202);
 }
+
+#[test]
+#[serial]
+fn test_permission_list_action() {
+    let users = serde_json::json!({
+        "users": [
+            {
+                "username": "admin",
+                "password": "admin",
+                "permissions": [
+                    { "repository": "*", "tag": "*", "actions": ["pull", "push", "delete", "list"] }
+                ]
+            },
+            {
+                "username": "partner",
+                "password": "partner",
+                "permissions": [
+                    { "repository": "test/*", "tag": "*", "actions": ["pull"] }
+                ]
+            },
+            {
+                "username": "lister",
+                "password": "lister",
+                "permissions": [
+                    { "repository": "test/*", "tag": "*", "actions": ["list"] }
+                ]
+            }
+        ]
+    });
+    let mut server = TestServer::new_with_users(users);
+    server.start();
+    let client = server.client();
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let resp = client
+        .put("/v2/test/repo/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&sample_manifest())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    let status = |user: &str, path: &str| {
+        client
+            .get(path)
+            .basic_auth(user, Some(user))
+            .send()
+            .unwrap()
+            .status()
+    };
+    let catalog = |user: &str| {
+        client
+            .get("/v2/_catalog")
+            .basic_auth(user, Some(user))
+            .send()
+            .unwrap()
+            .json::<serde_json::Value>()
+            .unwrap()["repositories"]
+            .clone()
+    };
+
+    // By default pull implies list
+    assert_eq!(status("partner", "/v2/test/repo/tags/list"), 200);
+    assert_eq!(status("lister", "/v2/test/repo/tags/list"), 200);
+    assert_eq!(catalog("partner"), serde_json::json!(["test/repo"]));
+
+    // With --strict-list, pull-only accounts can fetch known tags but not enumerate them
+    server.stop();
+    server.start_with_args(&["--strict-list"]);
+    assert_eq!(status("partner", "/v2/test/repo/manifests/v1"), 200);
+    assert_eq!(status("partner", "/v2/test/repo/tags/list"), 403);
+    assert_eq!(catalog("partner"), serde_json::json!([]));
+    assert_eq!(status("lister", "/v2/test/repo/tags/list"), 200);
+    assert_eq!(status("lister", "/v2/test/repo/manifests/v1"), 403);
+    assert_eq!(catalog("lister"), serde_json::json!(["test/repo"]));
+}
+
+fn token_claims(token: &str) -> serde_json::Value {
+    use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+    let payload = token.split('.').nth(1).unwrap();
+    serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap()
+}
+
+#[test]
+#[serial]
+fn test_bearer_token_flow() {
+    let mut server = TestServer::new();
+    server.start_with_args(&[
+        "--token-auth",
+        "--token-realm",
+        "https://registry.example/token",
+    ]);
+    let client = server.client();
+
+    // Challenges offer the token endpoint alongside Basic
+    let resp = client.get("/v2/").send().unwrap();
+    assert_eq!(resp.status(), 401);
+    let challenges: Vec<&str> = resp
+        .headers()
+        .get_all("WWW-Authenticate")
+        .iter()
+        .map(|v| v.to_str().unwrap())
+        .collect();
+    assert_eq!(
+        challenges[0],
+        "Bearer realm=\"https://registry.example/token\",service=\"grain\""
+    );
+    assert!(challenges[1].starts_with("Basic realm="));
+
+    // Requested actions are narrowed to what the user may do
+    let resp = client
+        .get("/token?service=grain&scope=repository:test/repo:pull,push")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = resp.json().unwrap();
+    let token = body["token"].as_str().unwrap().to_string();
+    assert_eq!(body["access_token"], body["token"]);
+    let claims = token_claims(&token);
+    assert_eq!(claims["sub"], "reader");
+    assert_eq!(claims["aud"], "grain");
+    assert_eq!(
+        claims["access"],
+        serde_json::json!([{"type": "repository", "name": "test/repo", "actions": ["pull"]}])
+    );
+
+    let resp = client.get("/v2/").bearer_auth(&token).send().unwrap();
+    assert_eq!(resp.status(), 200);
+    let resp = client
+        .get("/v2/test/repo/tags/list")
+        .bearer_auth(&token)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    // Outside the token's scope the client is challenged for a new token
+    let resp = client
+        .post("/v2/test/repo/blobs/uploads/")
+        .bearer_auth(&token)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+    let resp = client
+        .get("/v2/test/other/tags/list")
+        .bearer_auth(&token)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+
+    // A push token works for the whole upload flow
+    let resp = client
+        .get("/token?service=grain&scope=repository:test/repo:push,pull")
+        .basic_auth("writer", Some("writer"))
+        .send()
+        .unwrap();
+    let writer_token = resp.json::<serde_json::Value>().unwrap()["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let resp = client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .bearer_auth(&writer_token)
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    // Tampered and foreign tokens are refused
+    let mut tampered = token.clone();
+    tampered.push('x');
+    let resp = client.get("/v2/").bearer_auth(&tampered).send().unwrap();
+    assert_eq!(resp.status(), 401);
+    let (payload_end, _) = token.rsplit_once('.').unwrap();
+    let resigned = format!("{}.{}", payload_end, "A".repeat(43));
+    let resp = client.get("/v2/").bearer_auth(&resigned).send().unwrap();
+    assert_eq!(resp.status(), 401);
+    let metrics = client.get("/metrics").send().unwrap().text().unwrap();
+    assert!(metrics.contains(
+        "grain_token_validation_failures_total{reason=\"bad_signature\",source=\"registry\"}"
+    ));
+
+    // Bad credentials get no token; no credentials get an anonymous one without access
+    let resp = client
+        .get("/token?service=grain&scope=repository:test/repo:pull")
+        .basic_auth("reader", Some("wrong"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+    let resp = client
+        .get("/token?service=grain&scope=repository:test/repo:pull")
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let claims = token_claims(
+        resp.json::<serde_json::Value>().unwrap()["token"]
+            .as_str()
+            .unwrap(),
+    );
+    assert_eq!(claims["sub"], "anonymous");
+    assert_eq!(claims["access"][0]["actions"], serde_json::json!([]));
+
+    let resp = client
+        .get("/token?service=elsewhere")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+}
+
+#[test]
+#[serial]
+fn test_bearer_token_disabled_by_default() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let resp = client.get("/v2/").send().unwrap();
+    assert_eq!(resp.headers().get_all("WWW-Authenticate").iter().count(), 1);
+
+    let resp = client
+        .get("/token?service=grain")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+/// Minimal OIDC issuer serving discovery and a key set for one ES256 key
+struct MockIssuer {
+    url: String,
+    key_pair: ring::signature::EcdsaKeyPair,
+    rng: ring::rand::SystemRandom,
+}
+
+impl MockIssuer {
+    fn start() -> Self {
+        use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+        use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+        use std::io::{Read, Write};
+
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                .unwrap();
+        let point = key_pair.public_key().as_ref().to_vec();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let discovery = serde_json::json!({ "issuer": url, "jwks_uri": format!("{}/keys", url) });
+        let jwks = serde_json::json!({ "keys": [{
+            "kid": "ci-key",
+            "kty": "EC",
+            "crv": "P-256",
+            "x": BASE64_URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": BASE64_URL_SAFE_NO_PAD.encode(&point[33..]),
+        }]});
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0u8; 4096];
+                let n = stream.read(&mut request).unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..n]);
+                let body = if request.starts_with("GET /.well-known/openid-configuration") {
+                    discovery.to_string()
+                } else {
+                    jwks.to_string()
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+
+        Self { url, key_pair, rng }
+    }
+
+    fn token(&self, claims: serde_json::Value) -> String {
+        use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+        let header = serde_json::json!({ "alg": "ES256", "kid": "ci-key", "typ": "JWT" });
+        let signing_input = format!(
+            "{}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+            BASE64_URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let sig = self
+            .key_pair
+            .sign(&self.rng, signing_input.as_bytes())
+            .unwrap();
+        format!(
+            "{}.{}",
+            signing_input,
+            BASE64_URL_SAFE_NO_PAD.encode(sig.as_ref())
+        )
+    }
+
+    fn claims(&self, repository: &str, exp_offset: i64) -> serde_json::Value {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        serde_json::json!({
+            "iss": self.url,
+            "aud": "grain",
+            "sub": format!("repo:{}:ref:refs/heads/main", repository),
+            "repository": repository,
+            "repository_owner": repository.split('/').next().unwrap(),
+            "ref": "refs/heads/main",
+            "iat": now,
+            "exp": now + exp_offset,
+        })
+    }
+}
+
+#[test]
+#[serial]
+fn test_oidc_ci_identities() {
+    let issuer = MockIssuer::start();
+    let mut server = TestServer::new();
+    let trust_file = server.temp_dir.path().join("oidc.json");
+    std::fs::write(
+        &trust_file,
+        serde_json::json!({ "issuers": [{
+            "issuer": issuer.url,
+            "audience": "grain",
+            "rules": [{
+                "claims": { "repository_owner": "myorg", "ref": "refs/heads/main" },
+                "permissions": [
+                    { "repository": "{repository}", "tag": "*", "actions": ["pull", "push"] }
+                ]
+            }]
+        }]})
+        .to_string(),
+    )
+    .unwrap();
+    server.start_with_args(&[
+        "--oidc-trust-file",
+        trust_file.to_str().unwrap(),
+        "--token-auth",
+    ]);
+    let client = server.client();
+
+    let upload = |repository: &str, username: &str, password: &str| {
+        client
+            .post(&format!(
+                "/v2/{}/blobs/uploads/?digest={}",
+                repository,
+                sample_blob_digest()
+            ))
+            .basic_auth(username, Some(password))
+            .body(sample_blob())
+            .send()
+            .unwrap()
+            .status()
+    };
+
+    // The job's repository claim maps onto the grain repository of the same name
+    let token = issuer.token(issuer.claims("myorg/app", 300));
+    assert_eq!(upload("myorg/app", "oidc", &token), 201);
+    assert_eq!(upload("myorg/other", "oidc", &token), 403);
+
+    // Claims matching no rule, expired, tampered or foreign tokens are refused
+    let token = issuer.token(issuer.claims("otherorg/app", 300));
+    assert_eq!(upload("otherorg/app", "oidc", &token), 401);
+    let token = issuer.token(issuer.claims("myorg/app", -300));
+    assert_eq!(upload("myorg/app", "oidc", &token), 401);
+    let mut claims = issuer.claims("myorg/app", 300);
+    let token = issuer.token(claims.clone());
+    claims["repository"] = "myorg/other".into();
+    let forged = issuer.token(claims);
+    let tampered = format!(
+        "{}.{}",
+        forged.rsplit_once('.').unwrap().0,
+        token.rsplit_once('.').unwrap().1
+    );
+    assert_eq!(upload("myorg/other", "oidc", &tampered), 401);
+    let mut foreign = issuer.claims("myorg/app", 300);
+    foreign["aud"] = "sts.amazonaws.com".into();
+    assert_eq!(upload("myorg/app", "oidc", &issuer.token(foreign)), 401);
+
+    // The identity's permissions carry over into bearer tokens
+    let token = issuer.token(issuer.claims("myorg/app", 300));
+    let resp = client
+        .get("/token?service=grain&scope=repository:myorg/app:pull,push")
+        .basic_auth("oidc", Some(&token))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let bearer = resp.json::<serde_json::Value>().unwrap()["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let resp = client
+        .post(&format!(
+            "/v2/myorg/app/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .bearer_auth(&bearer)
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+}
+
+#[test]
+#[serial]
+fn test_oidc_sso() {
+    let issuer = MockIssuer::start();
+    let mut server = TestServer::new();
+    let trust_file = server.temp_dir.path().join("oidc.json");
+    std::fs::write(
+        &trust_file,
+        serde_json::json!({ "issuers": [{
+            "issuer": issuer.url,
+            "audience": "grain",
+            "username_claim": "email",
+            "rules": [
+                {
+                    "groups": ["platform-admins"],
+                    "admin": true,
+                    "permissions": [
+                        { "repository": "*", "tag": "*", "actions": ["pull", "push", "delete"] }
+                    ]
+                },
+                {
+                    "groups": ["dev-*"],
+                    "permissions": [{ "repository": "test/*", "tag": "*", "actions": ["pull"] }]
+                }
+            ]
+        }]})
+        .to_string(),
+    )
+    .unwrap();
+    server.start_with_args(&["--oidc-trust-file", trust_file.to_str().unwrap()]);
+    let client = server.client();
+
+    let sso_token = |email: &str, groups: &[&str]| {
+        let mut claims = issuer.claims("corp/sso", 300);
+        claims["email"] = email.into();
+        claims["groups"] = serde_json::json!(groups);
+        issuer.token(claims)
+    };
+    let admin = sso_token("alice@example.com", &["staff", "platform-admins"]);
+    let dev = sso_token("bob@example.com", &["dev-web"]);
+    let outsider = sso_token("eve@example.com", &["staff"]);
+
+    // Group membership decides access to the admin API
+    let list_users = |token: &str| {
+        client
+            .get("/admin/v1/users")
+            .bearer_auth(token)
+            .send()
+            .unwrap()
+            .status()
+    };
+    assert_eq!(list_users(&admin), 200);
+    assert_eq!(list_users(&dev), 403);
+    assert_eq!(list_users(&outsider), 401);
+
+    // SSO identities push and pull with the token as a bearer or as the `oidc` password
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .bearer_auth(&admin)
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let resp = client
+        .put("/v2/test/repo/manifests/v1")
+        .bearer_auth(&admin)
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&sample_manifest())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    let pull = |request: reqwest::blocking::RequestBuilder| request.send().unwrap().status();
+    assert_eq!(
+        pull(client.get("/v2/test/repo/manifests/v1").bearer_auth(&dev)),
+        200
+    );
+    assert_eq!(
+        pull(
+            client
+                .get("/v2/test/repo/manifests/v1")
+                .basic_auth("oidc", Some(&dev))
+        ),
+        200
+    );
+    assert_eq!(
+        pull(
+            client
+                .delete("/v2/test/repo/manifests/v1")
+                .bearer_auth(&dev)
+        ),
+        403
+    );
+    assert_eq!(pull(client.get("/v2/").bearer_auth(&dev)), 200);
+    assert_eq!(pull(client.get("/v2/").bearer_auth(&outsider)), 401);
+}
+
+#[test]
+#[serial]
+fn test_users_file_reloaded_on_change() {
+    let mut server = TestServer::new();
+    server.start_with_args(&["--users-reload-interval-secs", "1"]);
+    let client = server.client();
+
+    let status = |username: &str, password: &str| {
+        client
+            .get("/v2/")
+            .basic_auth(username, Some(password))
+            .send()
+            .unwrap()
+            .status()
+    };
+    let wait_for = |username: &str, password: &str, expected: u16| {
+        for _ in 0..50 {
+            if status(username, password) == expected {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        panic!("{} never got {}", username, expected);
+    };
+    assert_eq!(status("newcomer", "newcomer"), 401);
+
+    // A user added out-of-band can log in without a restart
+    let mut users = default_test_users();
+    users["users"]
+        .as_array_mut()
+        .unwrap()
+        .push(serde_json::json!({
+            "username": "newcomer",
+            "password": "newcomer",
+            "permissions": []
+        }));
+    std::fs::write(&server.users_file, users.to_string()).unwrap();
+    wait_for("newcomer", "newcomer", 200);
+    assert_eq!(status("admin", "admin"), 200);
+
+    // A broken file keeps the users loaded before it
+    std::fs::write(&server.users_file, "{ not json").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(2500));
+    assert_eq!(status("newcomer", "newcomer"), 200);
+
+    // Removed users lose access
+    std::fs::write(&server.users_file, default_test_users().to_string()).unwrap();
+    wait_for("newcomer", "newcomer", 401);
+    assert_eq!(status("admin", "admin"), 200);
+}
+
+#[test]
+#[serial]
+fn test_rate_limits() {
+    let mut server = TestServer::new();
+    server.start_with_args(&["--rate-limit-pulls", "3", "--rate-limit-auth-failures", "2"]);
+    let client = server.client();
+    let pull = |username: &str, password: &str| {
+        client
+            .get("/v2/test/repo/tags/list")
+            .basic_auth(username, Some(password))
+            .send()
+            .unwrap()
+    };
+
+    // Pulls are counted per user
+    for _ in 0..3 {
+        assert_ne!(pull("admin", "admin").status(), 429);
+    }
+    let resp = pull("admin", "admin");
+    assert_eq!(resp.status(), 429);
+    let retry_after: u64 = resp.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=20).contains(&retry_after));
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["errors"][0]["code"], "TOOMANYREQUESTS");
+    assert_ne!(pull("reader", "reader").status(), 429);
+
+    // Pushes are not limited here, and anonymous challenges are not login failures
+    let resp = client
+        .post("/v2/test/repo/blobs/uploads/")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    for _ in 0..3 {
+        assert_eq!(client.get("/v2/").send().unwrap().status(), 401);
+    }
+
+    // Failed logins are counted per client address, whatever the username
+    assert_eq!(pull("writer", "wrong").status(), 401);
+    assert_eq!(pull("nobody", "wrong").status(), 401);
+    assert_eq!(pull("writer", "writer").status(), 429);
+
+    let metrics = client.get("/metrics").send().unwrap().text().unwrap();
+    assert!(metrics.contains("grain_rate_limited_total{class=\"pull\"} 1"));
+    assert!(metrics.contains("grain_rate_limited_total{class=\"auth_failure\"}"));
+}
+
+#[test]
+#[serial]
+fn test_rate_limit_keys() {
+    let mut server = TestServer::new();
+    server.start_with_args(&["--rate-limit-pulls", "2", "--trusted-proxies", "127.0.0.1"]);
+    let client = server.client();
+    let pull = |username: &str, password: &str| {
+        client
+            .get("/v2/test/repo/tags/list")
+            .basic_auth(username, Some(password))
+            .send()
+            .unwrap()
+            .status()
+    };
+    let anonymous_pull = |forwarded_for: &str| {
+        client
+            .get("/v2/test/repo/tags/list")
+            .header("X-Forwarded-For", forwarded_for)
+            .send()
+            .unwrap()
+            .status()
+    };
+
+    for _ in 0..2 {
+        assert_ne!(pull("admin", "admin"), 429);
+    }
+    assert_eq!(pull("admin", "admin"), 429);
+    // Credentials that do not check out are not counted as the user they name
+    assert_eq!(pull("admin", "wrong"), 401);
+
+    // Behind a trusted proxy, each forwarded client has its own budget
+    for _ in 0..2 {
+        assert_ne!(anonymous_pull("198.51.100.1"), 429);
+    }
+    assert_eq!(anonymous_pull("198.51.100.1"), 429);
+    assert_ne!(anonymous_pull("198.51.100.2"), 429);
+}
+
+#[test]
+#[serial]
+fn test_rate_limit_bearer_subjects() {
+    let mut server = TestServer::new();
+    server.start_with_args(&["--token-auth", "--rate-limit-pulls", "2"]);
+    let client = server.client();
+    let token = |username: &str, repository: &str| {
+        let body: serde_json::Value = client
+            .get(&format!(
+                "/token?service=grain&scope=repository:{}:pull",
+                repository
+            ))
+            .basic_auth(username, Some(username))
+            .send()
+            .unwrap()
+            .json()
+            .unwrap();
+        body["token"].as_str().unwrap().to_string()
+    };
+    let pull = |token: &str, repository: &str| {
+        client
+            .get(&format!("/v2/{}/tags/list", repository))
+            .bearer_auth(token)
+            .send()
+            .unwrap()
+            .status()
+    };
+
+    // Every token of a subject shares its budget
+    let repo = token("reader", "test/repo");
+    let other = token("reader", "test/other");
+    assert_eq!(pull(&repo, "test/repo"), 200);
+    assert_eq!(pull(&other, "test/other"), 200);
+    assert_eq!(pull(&repo, "test/repo"), 429);
+
+    assert_eq!(pull(&token("admin", "test/repo"), "test/repo"), 200);
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use common::*;
+use grain::bench::{self, BenchMode, BenchOptions};
+use grain::sync::Registry;
+use serial_test::serial;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[test]
+#[serial]
+fn test_bench_push_and_pull() {
+    let mut server = TestServer::new();
+    server.start();
+    let registry = Registry::new(
+        &server.base_url,
+        Some(("writer".to_string(), "writer".to_string())),
+    );
+    let options = BenchOptions {
+        repository: "test/bench".to_string(),
+        size: 64 * 1024,
+        concurrency: 3,
+        count: 7,
+    };
+
+    let progress = AtomicUsize::new(0);
+    let stats = bench::run(&registry, BenchMode::Push, &options, |done| {
+        progress.fetch_max(done, Ordering::Relaxed);
+    })
+    .unwrap();
+    assert_eq!(progress.load(Ordering::Relaxed), 7);
+    assert_eq!(stats.operations, 7);
+    assert_eq!(stats.errors, 0);
+    assert_eq!(stats.bytes, 7 * 64 * 1024);
+    assert!(stats.latency_ms.p50 > 0.0);
+    assert!(stats.latency_ms.p50 <= stats.latency_ms.p99);
+    assert!(stats.latency_ms.p99 <= stats.latency_ms.max);
+
+    let stats = bench::run(&registry, BenchMode::Pull, &options, |_| {}).unwrap();
+    assert_eq!(stats.operations, 7);
+    assert_eq!(stats.bytes, 7 * 64 * 1024);
+    assert!(stats.megabytes_per_second > 0.0);
+
+    // Failures are counted rather than aborting the run
+    let reader = Registry::new(
+        &server.base_url,
+        Some(("reader".to_string(), "reader".to_string())),
+    );
+    let stats = bench::run(&reader, BenchMode::Push, &options, |_| {}).unwrap();
+    assert_eq!(stats.operations, 0);
+    assert_eq!(stats.errors, 7);
+    assert!(stats.first_error.unwrap().contains("403"));
+}
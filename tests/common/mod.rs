@@ -57,6 +57,10 @@ impl TestServer {
     }
 
     pub fn start(&mut self) {
+        self.start_with_args(&[]);
+    }
+
+    pub fn start_with_args(&mut self, extra_args: &[&str]) {
         // Get the workspace root directory
         let workspace_root = std::env::current_dir().expect("Failed to get current directory");
 
@@ -88,12 +92,16 @@ impl TestServer {
                 "--users-file",
                 self.users_file.to_str().unwrap(),
             ])
+            .args(extra_args)
             .current_dir(temp_path)
             .spawn()
             .expect("Failed to start grain server");
 
-        // Wait for server to be ready
-        let client = reqwest::blocking::Client::new();
+        // Wait for server to be ready (HTTPS servers use self-signed test certificates)
+        let client = reqwest::blocking::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
         let url = format!("{}/v2/", self.base_url);
 
         for _ in 0..50 {
@@ -180,6 +188,7 @@ pub fn default_test_users() -> serde_json::Value {
             {
                 "username": "admin",
                 "password": "admin",
+                "admin": true,
                 "permissions": [
                     {
                         "repository": "*",
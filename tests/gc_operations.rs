@@ -456,6 +456,9 @@ fn test_gc_preserves_shared_blobs_across_repos() {
         .unwrap();
 
     assert_eq!(resp.status(), 200);
+    let stats: serde_json::Value = resp.json().unwrap();
+    assert_eq!(stats["blobs_shared"], 1);
+    assert_eq!(stats["blobs_mounted"], 1);
 
     // Verify blob still exists in both repos
     let resp = client
@@ -472,3 +475,637 @@ fn test_gc_preserves_shared_blobs_across_repos() {
         .unwrap();
     assert_eq!(resp.status(), 200);
 }
+
+#[test]
+#[serial]
+fn test_gc_policy() {
+    use grain::client::{AdminClient, GcPolicy, GcRunOptions, UntaggedManifests};
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    let admin = AdminClient::new(&server.base_url, "admin", "admin");
+
+    let policy = admin.get_gc_policy().unwrap();
+    assert_eq!(policy.grace_period_hours, 24);
+    assert_eq!(policy.schedule_interval_minutes, 0);
+    assert_eq!(policy.untagged_manifests, UntaggedManifests::Keep);
+    assert!(!policy.dry_run);
+
+    let resp = client
+        .put("/admin/v1/gc/policy")
+        .basic_auth("reader", Some("reader"))
+        .json(&serde_json::json!({"dry_run": true}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+    let resp = client
+        .put("/admin/v1/gc/policy")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({"untagged_manifests": "sometimes"}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    let policy = GcPolicy {
+        grace_period_hours: 0,
+        schedule_interval_minutes: 0,
+        untagged_manifests: UntaggedManifests::Delete,
+        dry_run: true,
+    };
+    assert_eq!(admin.set_gc_policy(&policy).unwrap(), policy);
+
+    // The policy survives a restart
+    server.stop();
+    server.start();
+    assert_eq!(admin.get_gc_policy().unwrap(), policy);
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let put_manifest = |reference: &str, manifest: &serde_json::Value, media_type: &str| {
+        let resp = client
+            .put(&format!("/v2/test/repo/manifests/{}", reference))
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", media_type)
+            .body(serde_json::to_vec(manifest).unwrap())
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201, "{}", reference);
+    };
+    const IMAGE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+    // v1 is overwritten, leaving its first manifest untagged
+    let old = sample_manifest();
+    let mut current = sample_manifest();
+    current["annotations"] = serde_json::json!({"version": "2"});
+    put_manifest("v1", &old, IMAGE);
+    put_manifest("v1", &current, IMAGE);
+
+    // Children of a tagged index and referrers of a tagged manifest are kept
+    let mut child = sample_manifest();
+    child["annotations"] = serde_json::json!({"platform": "arm64"});
+    put_manifest(&sample_manifest_digest(&child), &child, IMAGE);
+    let index = serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.index.v1+json",
+        "manifests": [{
+            "mediaType": IMAGE,
+            "size": serde_json::to_vec(&child).unwrap().len(),
+            "digest": sample_manifest_digest(&child),
+        }]
+    });
+    put_manifest("multi", &index, "application/vnd.oci.image.index.v1+json");
+    let mut signature = sample_manifest();
+    signature["subject"] = serde_json::json!({
+        "mediaType": IMAGE,
+        "size": serde_json::to_vec(&current).unwrap().len(),
+        "digest": sample_manifest_digest(&current),
+    });
+    put_manifest(&sample_manifest_digest(&signature), &signature, IMAGE);
+
+    // Runs follow the policy: a dry run only counts
+    let stats = admin.run_gc(&GcRunOptions::default()).unwrap();
+    assert_eq!(stats.manifests_untagged, 1);
+    assert_eq!(stats.manifests_deleted, 0);
+
+    let stats = admin
+        .run_gc(&GcRunOptions {
+            dry_run: Some(false),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(stats.manifests_deleted, 1);
+
+    let status = |reference: String| {
+        client
+            .get(&format!("/v2/test/repo/manifests/{}", reference))
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap()
+            .status()
+    };
+    assert_eq!(status(sample_manifest_digest(&old)), 404);
+    assert_eq!(status(sample_manifest_digest(&current)), 200);
+    assert_eq!(status(sample_manifest_digest(&child)), 200);
+    assert_eq!(status(sample_manifest_digest(&signature)), 200);
+
+    // Overrides beat the policy, and untagged manifests are reported even when kept
+    let mut newer = sample_manifest();
+    newer["annotations"] = serde_json::json!({"version": "3"});
+    put_manifest("v1", &newer, IMAGE);
+    // The previous v1 and its signature are now untagged
+    let stats = admin
+        .run_gc(&GcRunOptions {
+            dry_run: Some(false),
+            untagged_manifests: Some(UntaggedManifests::Keep),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(stats.manifests_untagged, 2);
+    assert_eq!(stats.manifests_deleted, 0);
+
+    // Untagged manifests within the grace period are kept
+    let stats = admin
+        .run_gc(&GcRunOptions {
+            dry_run: Some(false),
+            grace_period_hours: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(stats.manifests_untagged, 2);
+    assert_eq!(stats.manifests_deleted, 0);
+    assert_eq!(status(sample_manifest_digest(&current)), 200);
+}
+
+#[test]
+#[serial]
+fn test_gc_retention_exemptions() {
+    use grain::client::{
+        AdminClient, ClientError, CreateRepositoryRequest, GcRunOptions, RetentionPolicy,
+    };
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    let admin = AdminClient::new(&server.base_url, "admin", "admin");
+    admin
+        .create_repository(&CreateRepositoryRequest {
+            name: "test/app".to_string(),
+            retention: Some(RetentionPolicy {
+                tags: "*".to_string(),
+                keep_last: Some(1),
+                max_age_days: None,
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+
+    client
+        .post(&format!(
+            "/v2/test/app/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    // Oldest first; v2 carries the keep annotation
+    for (tag, annotations) in [
+        ("v1", serde_json::json!({ "release": "1" })),
+        (
+            "v2",
+            serde_json::json!({ "release": "2", "grain.keep": "true" }),
+        ),
+        ("v3", serde_json::json!({ "release": "3" })),
+        ("v4", serde_json::json!({ "release": "4" })),
+    ] {
+        let mut manifest = sample_manifest();
+        manifest["annotations"] = annotations;
+        let resp = client
+            .put(&format!("/v2/test/app/manifests/{}", tag))
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(&manifest)
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+
+    // Pins are admin-only, on declared repositories and existing tags
+    let status = |result: Result<(), ClientError>| match result {
+        Ok(()) => 200,
+        Err(ClientError::Api { status, .. }) => status.as_u16(),
+        Err(e) => panic!("{}", e),
+    };
+    let reader = AdminClient::new(&server.base_url, "reader", "reader");
+    assert_eq!(status(reader.pin_tag("test", "app", "v1")), 403);
+    assert_eq!(status(admin.pin_tag("test", "other", "v1")), 404);
+    assert_eq!(status(admin.pin_tag("test", "app", "v9")), 404);
+    assert_eq!(status(admin.unpin_tag("test", "app", "v1")), 404);
+
+    let resp = client
+        .put("/admin/v1/repos/test/app/pins/v1")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let resp = client
+        .put("/admin/v1/repos/test/app/pins/v1")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 204);
+    assert_eq!(admin.list_pins("test", "app").unwrap(), vec!["v1"]);
+    assert_eq!(
+        admin.list_repositories().unwrap()[0].pinned_tags,
+        vec!["v1"]
+    );
+
+    // Exempt tags neither expire nor count towards keep_last
+    let stats = admin
+        .run_gc(&GcRunOptions {
+            dry_run: Some(false),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(stats.tags_exempt, 2);
+    assert_eq!(stats.tags_expired, 1);
+    let tags = |client: &TestClient| -> serde_json::Value {
+        client
+            .get("/v2/test/app/tags/list")
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap()
+            .json::<serde_json::Value>()
+            .unwrap()["tags"]
+            .clone()
+    };
+    assert_eq!(tags(&client), serde_json::json!(["v1", "v2", "v4"]));
+
+    // Once unpinned, v1 is subject to the policy again
+    admin.unpin_tag("test", "app", "v1").unwrap();
+    assert!(admin.list_pins("test", "app").unwrap().is_empty());
+    let stats = admin
+        .run_gc(&GcRunOptions {
+            dry_run: Some(false),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(stats.tags_exempt, 1);
+    assert_eq!(stats.tags_expired, 1);
+    assert_eq!(tags(&client), serde_json::json!(["v2", "v4"]));
+}
+
+#[test]
+#[serial]
+fn test_gc_journal_completes_interrupted_deletions() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let push_blob = |blob: &[u8]| {
+        let digest = format!("sha256:{}", sha256::digest(blob));
+        let resp = client
+            .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
+            .basic_auth("admin", Some("admin"))
+            .body(blob.to_vec())
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        digest
+    };
+    push_blob(&sample_blob());
+    let orphan_digest = push_blob(b"orphan interrupted by a crash");
+    let manifest = sample_manifest();
+    let resp = client
+        .put("/v2/test/repo/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&manifest)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let manifest_digest = sample_manifest_digest(&manifest);
+    let hex = manifest_digest.trim_start_matches("sha256:");
+
+    // A retention sweep crashed after deleting the tag but before its digest copy; the blob
+    // deletion was settled, and the last intent never fully reached disk
+    server.stop();
+    let journal_path = server.temp_dir.path().join("tmp/gc-journal.jsonl");
+    std::fs::remove_file(server.temp_dir.path().join("tmp/manifests/test/repo/v1")).unwrap();
+    let journal = [
+        serde_json::json!({"seq": 1, "deletions": [
+            {"kind": "manifest", "org": "test", "repo": "repo", "reference": "v1"},
+            {"kind": "manifest", "org": "test", "repo": "repo", "reference": hex},
+        ]}),
+        serde_json::json!({"seq": 2, "deletions": [
+            {"kind": "blob", "org": "test", "repo": "repo", "digest": orphan_digest},
+        ]}),
+        serde_json::json!({"seq": 2, "done": true}),
+    ];
+    let mut content: String = journal.iter().map(|entry| format!("{}\n", entry)).collect();
+    content.push_str(r#"{"seq":3,"deletions":[{"kind":"#);
+    std::fs::write(&journal_path, content).unwrap();
+
+    server.start();
+    let client = server.client();
+    let status = |path: &str| {
+        client
+            .head(path)
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap()
+            .status()
+    };
+    assert_eq!(
+        status(&format!("/v2/test/repo/manifests/{}", manifest_digest)),
+        404
+    );
+    assert_eq!(
+        status(&format!("/v2/test/repo/blobs/{}", orphan_digest)),
+        200
+    );
+    assert!(!journal_path.exists());
+
+    // Runs report journal entries left behind, and only real runs complete them
+    let pending = serde_json::json!({"seq": 1, "deletions": [
+        {"kind": "blob", "org": "test", "repo": "repo", "digest": orphan_digest},
+    ]});
+    std::fs::write(&journal_path, format!("{}\n", pending)).unwrap();
+    let run_gc = |dry_run: bool| -> serde_json::Value {
+        let resp = client
+            .post(&format!(
+                "/admin/gc?dry_run={}&grace_period_hours=24",
+                dry_run
+            ))
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        resp.json().unwrap()
+    };
+
+    let stats = run_gc(true);
+    assert_eq!(stats["journal_pending"], 1);
+    assert_eq!(stats["journal_recovered"], 0);
+    assert_eq!(
+        status(&format!("/v2/test/repo/blobs/{}", orphan_digest)),
+        200
+    );
+
+    let stats = run_gc(false);
+    assert_eq!(stats["journal_pending"], 0);
+    assert_eq!(stats["journal_recovered"], 1);
+    assert_eq!(stats["blobs_deleted"], 0);
+    assert_eq!(
+        status(&format!("/v2/test/repo/blobs/{}", orphan_digest)),
+        404
+    );
+    assert!(!journal_path.exists());
+}
+
+#[test]
+#[serial]
+fn test_gc_expired_manifests() {
+    use grain::client::{
+        AdminClient, ClientError, CreateRepositoryRequest, GcRunOptions, SetTagExpiryRequest,
+    };
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    let admin = AdminClient::new(&server.base_url, "admin", "admin");
+    admin
+        .create_repository(&CreateRepositoryRequest {
+            name: "test/app".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+    client
+        .post(&format!(
+            "/v2/test/app/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let push = |tag: &str, annotations: serde_json::Value| {
+        let mut manifest = sample_manifest();
+        manifest["annotations"] = annotations;
+        let resp = client
+            .put(&format!("/v2/test/app/manifests/{}", tag))
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(&manifest)
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        resp.headers()["docker-content-digest"]
+            .to_str()
+            .unwrap()
+            .to_string()
+    };
+    let pr1 = push("pr-1", serde_json::json!({ "grain.expires-at": "1000" }));
+    push("pr-2", serde_json::json!({ "pr": "2" }));
+    push(
+        "release",
+        serde_json::json!({ "grain.expires-at": "2100-01-01T00:00:00Z" }),
+    );
+    let pull = |reference: &str| {
+        client
+            .get(&format!("/v2/test/app/manifests/{}", reference))
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap()
+    };
+
+    // Expired manifests are not served, by tag or digest
+    let resp = pull("pr-1");
+    assert_eq!(resp.status(), 404);
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["errors"][0]["code"], "MANIFEST_UNKNOWN");
+    assert_eq!(body["errors"][0]["message"], "manifest expired");
+    assert_eq!(pull(&pr1).status(), 404);
+    assert_eq!(pull("release").status(), 200);
+
+    // Expiry can also be set on a tag through the API
+    let status = |result: Result<(), ClientError>| match result {
+        Ok(()) => 200,
+        Err(ClientError::Api { status, .. }) => status.as_u16(),
+        Err(e) => panic!("{}", e),
+    };
+    let ttl = |secs| SetTagExpiryRequest {
+        ttl_secs: Some(secs),
+        ..Default::default()
+    };
+    let reader = AdminClient::new(&server.base_url, "reader", "reader");
+    let set = |client: &AdminClient, tag: &str, request: &SetTagExpiryRequest| {
+        status(
+            client
+                .set_tag_expiry("test", "app", tag, request)
+                .map(|_| ()),
+        )
+    };
+    assert_eq!(set(&reader, "pr-2", &ttl(0)), 403);
+    assert_eq!(set(&admin, "pr-9", &ttl(0)), 404);
+    assert_eq!(set(&admin, "pr-2", &SetTagExpiryRequest::default()), 400);
+    assert_eq!(set(&admin, "pr-2", &ttl(3600)), 200);
+    assert_eq!(pull("pr-2").status(), 200);
+    assert_eq!(set(&admin, "pr-2", &ttl(0)), 200);
+    assert_eq!(pull("pr-2").status(), 404);
+    admin.clear_tag_expiry("test", "app", "pr-2").unwrap();
+    assert_eq!(status(admin.clear_tag_expiry("test", "app", "pr-2")), 404);
+    assert_eq!(pull("pr-2").status(), 200);
+
+    // Pushing the tag again clears its expiry
+    assert_eq!(set(&admin, "pr-2", &ttl(0)), 200);
+    push("pr-2", serde_json::json!({ "pr": "2" }));
+    assert_eq!(pull("pr-2").status(), 200);
+    assert!(admin.list_repositories().unwrap()[0].tag_expiry.is_empty());
+
+    // GC deletes expired tags along with their digest-addressed copies
+    assert_eq!(set(&admin, "pr-2", &ttl(0)), 200);
+    let stats = admin
+        .run_gc(&GcRunOptions {
+            dry_run: Some(false),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(stats.manifests_expired, 4);
+    let tags: serde_json::Value = client
+        .get("/v2/test/app/tags/list")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(tags["tags"], serde_json::json!(["release"]));
+    assert_eq!(
+        server
+            .temp_dir
+            .path()
+            .join("tmp/manifests/test/app")
+            .read_dir()
+            .unwrap()
+            .count(),
+        2
+    );
+}
+
+#[test]
+#[serial]
+fn test_gc_status() {
+    use grain::client::{AdminClient, GcRunOptions};
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    let admin = AdminClient::new(&server.base_url, "admin", "admin");
+
+    let status = admin.gc_status().unwrap();
+    assert!(!status.running);
+    assert_eq!(status.phase, "idle");
+
+    let resp = client
+        .get("/admin/v1/gc/status")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+
+    // A background run returns at once and reports its progress and result
+    let resp = client
+        .post("/admin/v1/gc?background=true&dry_run=true")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    assert_eq!(resp.headers()["location"], "/admin/v1/gc/status");
+    let mut status = admin.gc_status().unwrap();
+    for _ in 0..50 {
+        if !status.running {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        status = admin.gc_status().unwrap();
+    }
+    assert_eq!(status.phase, "done");
+    assert!(status.dry_run);
+    assert!(status.finished_at >= status.started_at);
+    assert_eq!(status.stats.blobs_scanned, 1);
+    assert_eq!(status.stats.blobs_unreferenced, 1);
+
+    // Blocking runs update the status too
+    admin
+        .run_gc(&GcRunOptions {
+            dry_run: Some(false),
+            grace_period_hours: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+    let status = admin.gc_status().unwrap();
+    assert!(!status.dry_run);
+    assert_eq!(status.stats.blobs_deleted, 1);
+
+    // Without a run in progress, the event stream sends the last status and ends
+    let resp = client
+        .get("/admin/v1/gc/status/events")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "text/event-stream");
+    let body = resp.text().unwrap();
+    assert!(body.starts_with("event: status\ndata: {"), "{}", body);
+    assert!(body.contains(r#""phase":"done""#), "{}", body);
+}
+
+#[test]
+#[serial]
+fn test_gc_scoped_to_repository() {
+    use grain::client::{AdminClient, GcRunOptions};
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    let admin = AdminClient::new(&server.base_url, "admin", "admin");
+
+    for repository in ["test/repo", "test/other"] {
+        client
+            .post(&format!(
+                "/v2/{}/blobs/uploads/?digest={}",
+                repository,
+                sample_blob_digest()
+            ))
+            .basic_auth("admin", Some("admin"))
+            .body(sample_blob())
+            .send()
+            .unwrap();
+    }
+
+    // Only the scoped repository's unreferenced copy is deleted
+    let stats = admin
+        .run_gc(&GcRunOptions {
+            dry_run: Some(false),
+            grace_period_hours: Some(0),
+            repository: Some("test/repo".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(stats.blobs_deleted, 1);
+
+    let blob = |repository: &str| {
+        client
+            .head(&format!(
+                "/v2/{}/blobs/{}",
+                repository,
+                sample_blob_digest()
+            ))
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap()
+            .status()
+    };
+    assert_eq!(blob("test/repo"), 404);
+    assert_eq!(blob("test/other"), 200);
+}
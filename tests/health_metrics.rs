@@ -238,6 +238,174 @@ fn test_metrics_manifest_upload_download_counters() {
     assert!(body.contains("grain_manifest_downloads_total"));
 }
 
+#[test]
+#[serial]
+fn test_metrics_blob_download_accounting() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let blob = sample_blob();
+    let digest = sample_blob_digest();
+    client
+        .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .body(blob.clone())
+        .send()
+        .unwrap();
+
+    let resp = client
+        .get(&format!("/v2/test/repo/blobs/{}", digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.bytes().unwrap().as_ref(), blob.as_slice());
+
+    let resp = client.get("/metrics").send().unwrap();
+    let body = resp.text().unwrap();
+
+    assert!(body.contains("grain_blob_download_responses_total{status=\"200\"} 1"));
+    assert!(body.contains(&format!("grain_blob_download_bytes_total {}", blob.len())));
+}
+
+#[test]
+#[serial]
+fn test_metrics_content_cache() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let blob = sample_blob();
+    let digest = sample_blob_digest();
+    client
+        .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .body(blob.clone())
+        .send()
+        .unwrap();
+    let manifest = sample_manifest();
+    let manifest_digest = sample_manifest_digest(&manifest);
+    client
+        .put("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&manifest)
+        .send()
+        .unwrap();
+
+    // The first blob pull reads storage, the second is served from memory
+    for _ in 0..2 {
+        let resp = client
+            .get(&format!("/v2/test/repo/blobs/{}", digest))
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.bytes().unwrap().as_ref(), blob.as_slice());
+    }
+    // Pushed manifests are cached right away
+    let resp = client
+        .get("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.json::<serde_json::Value>().unwrap(), manifest);
+
+    let body = client.get("/metrics").send().unwrap().text().unwrap();
+    assert!(body.contains("grain_content_cache_requests_total{result=\"hit\"} 2"));
+    assert!(body.contains("grain_content_cache_requests_total{result=\"miss\"} 1"));
+
+    // A deleted manifest is not served from the cache
+    let resp = client
+        .delete(&format!("/v2/test/repo/manifests/{}", manifest_digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let resp = client
+        .get(&format!("/v2/test/repo/manifests/{}", manifest_digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[test]
+#[serial]
+fn test_metrics_per_repository_cardinality_cap() {
+    let mut server = TestServer::new();
+    server.start_with_args(&["--per-repo-metrics", "--metrics-max-repos", "1"]);
+    let client = server.client();
+
+    let digest = sample_blob_digest();
+    for repo in ["test/first", "test/second"] {
+        client
+            .post(&format!("/v2/{}/blobs/uploads/?digest={}", repo, digest))
+            .basic_auth("admin", Some("admin"))
+            .body(sample_blob())
+            .send()
+            .unwrap();
+    }
+
+    let resp = client.get("/metrics").send().unwrap();
+    let body = resp.text().unwrap();
+
+    assert!(body.contains(
+        "grain_repository_operations_total{operation=\"push\",repository=\"test/first\"} 1"
+    ));
+    assert!(body
+        .contains("grain_repository_operations_total{operation=\"push\",repository=\"other\"} 1"));
+    assert!(!body.contains("repository=\"test/second\""));
+}
+
+#[test]
+#[serial]
+fn test_metrics_active_upload_sessions() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    let active = || {
+        let body = client.get("/metrics").send().unwrap().text().unwrap();
+        body.lines()
+            .find_map(|line| line.strip_prefix("grain_active_upload_sessions "))
+            .map(|value| value.parse::<i64>().unwrap())
+            .unwrap()
+    };
+    let start_upload = || {
+        let resp = client
+            .post("/v2/test/repo/blobs/uploads/")
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 202);
+        let location = resp.headers()["location"].to_str().unwrap().to_string();
+        location[location.find("/v2/").unwrap()..].to_string()
+    };
+
+    let first = start_upload();
+    let second = start_upload();
+    assert_eq!(active(), 2);
+
+    // Completed and cancelled sessions are no longer active
+    let resp = client
+        .put(&format!("{}?digest={}", first, sample_blob_digest()))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    assert_eq!(active(), 1);
+    let resp = client
+        .delete(&second)
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 204);
+    assert_eq!(active(), 0);
+}
+
 #[test]
 #[serial]
 fn test_metrics_request_duration_histogram() {
@@ -262,6 +430,53 @@ fn test_metrics_request_duration_histogram() {
     assert!(body.contains("_count"));
 }
 
+#[test]
+#[serial]
+fn test_metrics_request_duration_exemplars() {
+    let mut server = TestServer::new();
+    server.start_with_args(&["--trace-exemplars"]);
+    let client = server.client();
+
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+    for (trace, sampled) in [(trace_id, "01"), ("0af7651916cd43dd8448eb211c80319c", "00")] {
+        client
+            .get("/v2/_catalog")
+            .basic_auth("admin", Some("admin"))
+            .header(
+                "traceparent",
+                format!("00-{}-00f067aa0ba902b7-{}", trace, sampled),
+            )
+            .send()
+            .unwrap();
+    }
+
+    // Only sampled traces become exemplars, and only OpenMetrics scrapers see them
+    let resp = client
+        .get("/metrics")
+        .header("Accept", "application/openmetrics-text;version=1.0.0")
+        .send()
+        .unwrap();
+    assert!(resp.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("application/openmetrics-text"));
+    let body = resp.text().unwrap();
+    let exemplars: Vec<&str> = body
+        .lines()
+        .filter(|line| line.contains(" # {trace_id="))
+        .collect();
+    assert_eq!(exemplars.len(), 1);
+    assert!(exemplars[0].starts_with("grain_request_duration_seconds_bucket{"));
+    assert!(exemplars[0].contains("endpoint=\"/v2/_catalog\""));
+    assert!(exemplars[0].contains(&format!("{{trace_id=\"{}\"}}", trace_id)));
+    assert!(body.contains("# TYPE grain_http_requests counter"));
+    assert!(body.ends_with("# EOF\n"));
+
+    let body = client.get("/metrics").send().unwrap().text().unwrap();
+    assert!(!body.contains("trace_id"));
+    assert!(!body.contains("# EOF"));
+}
+
 #[test]
 #[serial]
 fn test_metrics_endpoint_normalization() {
@@ -283,9 +498,152 @@ fn test_metrics_endpoint_normalization() {
     let resp = client.get("/metrics").send().unwrap();
     let body = resp.text().unwrap();
 
-    // Endpoints should be normalized to avoid cardinality explosion
-    // Should see /v2/{name}/blobs/{digest} not the actual digest
+    // Endpoints are labeled by route template to avoid cardinality explosion
     assert!(body.contains("grain_http_requests_total"));
+    assert!(body.contains(r#"endpoint="/v2/{org}/{repo}/blobs/uploads/""#));
+    assert!(!body.contains(r#"endpoint="/v2/test/repo/"#));
+
+    // Nested admin routes keep their full template, parameters included
+    client
+        .delete("/admin/v1/users/nobody")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    client
+        .get("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+
+    let body = client.get("/metrics").send().unwrap().text().unwrap();
+    assert!(body.contains(r#"endpoint="/admin/v1/users/{username}""#));
+    assert!(body.contains(r#"endpoint="/v2/{org}/{repo}/manifests/{reference}""#));
+    assert!(!body.contains("nobody"));
+}
+
+#[test]
+#[serial]
+fn test_metrics_storage_operations() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    client
+        .put("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&sample_manifest())
+        .send()
+        .unwrap();
+    // A missing manifest is not a storage failure
+    client
+        .get("/v2/test/repo/manifests/missing")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+
+    let body = client.get("/metrics").send().unwrap().text().unwrap();
+    for operation in ["store_upload", "write_manifest", "manifest_metadata"] {
+        assert!(
+            body.contains(&format!(
+                r#"grain_storage_operation_duration_seconds_count{{backend="fs",operation="{}"}}"#,
+                operation
+            )),
+            "missing timing for {}",
+            operation
+        );
+    }
+    assert!(!body.contains(
+        r#"grain_storage_operation_errors_total{backend="fs",operation="read_manifest"}"#
+    ));
+}
+
+#[test]
+#[serial]
+fn test_metrics_storage_usage() {
+    let mut server = TestServer::new();
+    server.start_with_args(&["--storage-metrics-interval-secs", "1"]);
+    let client = server.client();
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    client
+        .put("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&sample_manifest())
+        .send()
+        .unwrap();
+    let resp = client
+        .post("/v2/test/repo/blobs/uploads/")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+
+    // The gauges follow storage on the next sample
+    let expected = [
+        format!("grain_storage_blob_bytes {}", sample_blob().len()),
+        "grain_storage_blobs 1".to_string(),
+        "grain_storage_manifests 1".to_string(),
+        "grain_upload_sessions 1".to_string(),
+    ];
+    let mut body = String::new();
+    for _ in 0..50 {
+        body = client.get("/metrics").send().unwrap().text().unwrap();
+        if expected.iter().all(|line| body.contains(line.as_str())) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    for line in &expected {
+        assert!(body.contains(line.as_str()), "missing {}", line);
+    }
+}
+
+#[test]
+#[serial]
+fn test_metrics_per_listener() {
+    let mut server = TestServer::new();
+    let second = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+    server.start_with_args(&["--host", &second]);
+    let client = server.client();
+
+    // Both addresses serve the registry
+    let resp = reqwest::blocking::get(format!("http://{}/v2/", second)).unwrap();
+    assert_eq!(resp.status(), 401);
+
+    let body = client.get("/metrics").send().unwrap().text().unwrap();
+    for listener in [&server.host, &second] {
+        assert!(
+            body.contains(&format!(
+                r#"grain_listener_requests_total{{listener="{}"}}"#,
+                listener
+            )),
+            "missing requests on {}",
+            listener
+        );
+    }
 }
 
 #[test]
@@ -62,6 +62,84 @@ fn test_end1_version_check_invalid_credentials() {
     assert_eq!(resp.status(), 401);
 }
 
+#[test]
+#[serial]
+fn test_invalid_repository_names() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    for name in ["Test/repo", "test/My_Repo", "test/-repo", "test/repo..old"] {
+        let resp = client
+            .post(&format!(
+                "/v2/{}/blobs/uploads/?digest={}",
+                name,
+                sample_blob_digest()
+            ))
+            .basic_auth("admin", Some("admin"))
+            .body(sample_blob())
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 400, "{}", name);
+        let body: serde_json::Value = resp.json().unwrap();
+        assert_eq!(body["errors"][0]["code"], "NAME_INVALID");
+        assert_eq!(body["errors"][0]["detail"], name);
+    }
+
+    let resp = client
+        .get("/v2/test/Repo/tags/list")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    let resp = client
+        .post(&format!(
+            "/v2/test/my-app.v2/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+}
+
+#[test]
+#[serial]
+fn test_request_id() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    // A client's ID is echoed, in the header and in OCI error bodies
+    let resp = client
+        .get("/v2/test/repo/manifests/missing")
+        .basic_auth("admin", Some("admin"))
+        .header("X-Request-Id", "ci-run-42")
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+    assert_eq!(resp.headers()["x-request-id"], "ci-run-42");
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["errors"][0]["code"], "MANIFEST_UNKNOWN");
+    assert_eq!(body["request_id"], "ci-run-42");
+
+    // Otherwise one is generated, and unusable ones are replaced
+    for header in [None, Some("x".repeat(200))] {
+        let mut request = client.get("/v2/");
+        if let Some(header) = header {
+            request = request.header("X-Request-Id", header);
+        }
+        let resp = request.send().unwrap();
+        assert_eq!(resp.status(), 401);
+        let id = resp.headers()["x-request-id"].to_str().unwrap().to_string();
+        assert_eq!(id.len(), 36);
+        let body: serde_json::Value = resp.json().unwrap();
+        assert_eq!(body["request_id"], id);
+    }
+}
+
 #[test]
 #[serial]
 fn test_end2_blob_get_nonexistent() {
@@ -94,6 +172,154 @@ fn test_end2_blob_head_nonexistent() {
     assert_eq!(resp.status(), 404);
 }
 
+#[test]
+#[serial]
+fn test_end2_blob_get_if_none_match() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    for name in ["test/private", "test/public"] {
+        let resp = client
+            .post(&format!(
+                "/v2/{}/blobs/uploads/?digest={}",
+                name,
+                sample_blob_digest()
+            ))
+            .basic_auth("admin", Some("admin"))
+            .body(sample_blob())
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+    }
+    let resp = client
+        .post("/admin/repos")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({"name": "test/public", "visibility": "public"}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    let etag = format!("\"{}\"", sample_blob_digest());
+    let get = |name: &str, if_none_match: Option<&str>| {
+        let mut request = client
+            .get(&format!("/v2/{}/blobs/{}", name, sample_blob_digest()))
+            .basic_auth("admin", Some("admin"));
+        if let Some(etag) = if_none_match {
+            request = request.header("If-None-Match", etag);
+        }
+        request.send().unwrap()
+    };
+
+    // Only public blobs may be kept by shared caches
+    let resp = get("test/private", None);
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["etag"], etag.as_str());
+    assert_eq!(
+        resp.headers()["cache-control"],
+        "private, max-age=31536000, immutable"
+    );
+    let resp = get("test/public", None);
+    assert_eq!(
+        resp.headers()["cache-control"],
+        "public, max-age=31536000, immutable"
+    );
+
+    let resp = get("test/private", Some(&etag));
+    assert_eq!(resp.status(), 304);
+    assert_eq!(resp.headers()["etag"], etag.as_str());
+    assert_eq!(
+        resp.headers()["cache-control"],
+        "private, max-age=31536000, immutable"
+    );
+    assert!(resp.bytes().unwrap().is_empty());
+    assert_eq!(get("test/private", Some("\"sha256:other\"")).status(), 200);
+
+    let resp = client
+        .head(&format!("/v2/test/private/blobs/{}", sample_blob_digest()))
+        .basic_auth("admin", Some("admin"))
+        .header("If-None-Match", &etag)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 304);
+
+    // A deleted blob is not reported as unchanged
+    let resp = client
+        .delete(&format!("/v2/test/private/blobs/{}", sample_blob_digest()))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    assert_eq!(get("test/private", Some(&etag)).status(), 404);
+}
+
+#[test]
+#[serial]
+fn test_end2_blob_get_range() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let blob = b"0123456789".to_vec();
+    let digest = format!("sha256:{}", sha256::digest(blob.as_slice()));
+    let resp = client
+        .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .body(blob)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    let get_range = |range: &str| {
+        client
+            .get(&format!("/v2/test/repo/blobs/{}", digest))
+            .basic_auth("admin", Some("admin"))
+            .header("Range", range)
+            .send()
+            .unwrap()
+    };
+
+    // Resume from an offset
+    let resp = get_range("bytes=4-");
+    assert_eq!(resp.status(), 206);
+    assert_eq!(resp.headers()["content-range"], "bytes 4-9/10");
+    assert_eq!(resp.headers()["content-length"], "6");
+    assert_eq!(resp.headers()["accept-ranges"], "bytes");
+    assert_eq!(resp.bytes().unwrap().as_ref(), b"456789");
+
+    let resp = get_range("bytes=2-4");
+    assert_eq!(resp.status(), 206);
+    assert_eq!(resp.headers()["content-range"], "bytes 2-4/10");
+    assert_eq!(resp.bytes().unwrap().as_ref(), b"234");
+
+    // Suffix ranges and ends past the blob are clamped
+    let resp = get_range("bytes=-3");
+    assert_eq!(resp.headers()["content-range"], "bytes 7-9/10");
+    assert_eq!(resp.bytes().unwrap().as_ref(), b"789");
+    let resp = get_range("bytes=8-100");
+    assert_eq!(resp.headers()["content-range"], "bytes 8-9/10");
+    assert_eq!(resp.bytes().unwrap().as_ref(), b"89");
+
+    // Ranges starting past the end are not satisfiable
+    let resp = get_range("bytes=10-");
+    assert_eq!(resp.status(), 416);
+    assert_eq!(resp.headers()["content-range"], "bytes */10");
+
+    // Malformed and multiple ranges are ignored
+    for range in ["items=0-1", "bytes=5-2", "bytes=0-1,4-5"] {
+        let resp = get_range(range);
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.bytes().unwrap().as_ref(), b"0123456789");
+    }
+
+    let resp = client
+        .head(&format!("/v2/test/repo/blobs/{}", digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.headers()["accept-ranges"], "bytes");
+}
+
 #[test]
 #[serial]
 fn test_end4a_blob_upload_initiate() {
@@ -135,6 +361,55 @@ fn test_end4b_monolithic_blob_upload() {
     assert!(resp.headers().contains_key("docker-content-digest"));
 }
 
+#[test]
+#[serial]
+fn test_end4b_upload_of_existing_blob_skipped() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let digest = sample_blob_digest();
+    let resp = client
+        .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    // The blob is already stored, so an empty body is not checked against the digest
+    let resp = client
+        .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    assert_eq!(
+        extract_path(resp.headers()["location"].to_str().unwrap()),
+        format!("/v2/test/repo/blobs/{}", digest)
+    );
+    assert_eq!(resp.headers()["docker-content-digest"], digest.as_str());
+
+    // Same for a mount of a blob the repository holds, whatever the source
+    let resp = client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?mount={}&from=missing/repo",
+            digest
+        ))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    // Other repositories still upload it
+    let resp = client
+        .post(&format!("/v2/test/other/blobs/uploads/?digest={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+}
+
 #[test]
 #[serial]
 fn test_end4b_monolithic_upload_digest_mismatch() {
@@ -202,184 +477,1168 @@ fn test_end5_end6_chunked_upload_complete() {
 
 #[test]
 #[serial]
-fn test_end6_complete_upload_with_digest_mismatch() {
+fn test_end13_upload_status_resumes_chunked_upload() {
     let mut server = TestServer::new();
     server.start();
     let client = server.client();
 
-    // Initiate and upload chunk
     let resp = client
         .post("/v2/test/repo/blobs/uploads/")
         .basic_auth("admin", Some("admin"))
         .send()
         .unwrap();
-    let location = resp.headers().get("location").unwrap().to_str().unwrap();
+    assert_eq!(resp.status(), 202);
+    let location = extract_path(resp.headers()["location"].to_str().unwrap()).to_string();
+    let uuid = resp.headers()["docker-upload-uuid"]
+        .to_str()
+        .unwrap()
+        .to_string();
 
     let blob = sample_blob();
+    let (first, rest) = blob.split_at(blob.len() / 2);
     let resp = client
-        .patch(extract_path(location))
+        .patch(&location)
         .basic_auth("admin", Some("admin"))
-        .body(blob)
+        .body(first.to_vec())
         .send()
         .unwrap();
-    let location = resp.headers().get("location").unwrap().to_str().unwrap();
+    assert_eq!(resp.status(), 202);
 
-    // Try to complete with wrong digest
-    let wrong_digest = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+    // After a dropped connection the client asks how much the registry has
     let resp = client
-        .put(&format!(
-            "{}?digest={}",
-            extract_path(location),
-            wrong_digest
-        ))
+        .get(&location)
         .basic_auth("admin", Some("admin"))
         .send()
         .unwrap();
+    assert_eq!(resp.status(), 204);
+    assert_eq!(
+        resp.headers()["range"],
+        format!("0-{}", first.len() - 1).as_str()
+    );
+    assert_eq!(resp.headers()["docker-upload-uuid"], uuid.as_str());
+    let location = extract_path(resp.headers()["location"].to_str().unwrap()).to_string();
 
-    assert_eq!(resp.status(), 400);
-}
-
-#[test]
-#[serial]
-fn test_end7_manifest_upload() {
-    let mut server = TestServer::new();
-    server.start();
-    let client = server.client();
-
-    // First upload the blob referenced in manifest
-    let blob = sample_blob();
-    let digest = sample_blob_digest();
-    client
-        .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
+    let resp = client
+        .patch(&location)
         .basic_auth("admin", Some("admin"))
-        .body(blob)
+        .body(rest.to_vec())
         .send()
         .unwrap();
-
-    // Upload manifest
-    let manifest = sample_manifest();
+    assert_eq!(resp.status(), 202);
     let resp = client
-        .put("/v2/test/repo/manifests/latest")
+        .put(&format!("{}?digest={}", location, sample_blob_digest()))
         .basic_auth("admin", Some("admin"))
-        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
-        .json(&manifest)
         .send()
         .unwrap();
-
     assert_eq!(resp.status(), 201);
-    assert!(resp.headers().contains_key("location"));
-    assert!(resp.headers().contains_key("docker-content-digest"));
-}
-
-#[test]
-#[serial]
-fn test_end7_manifest_upload_invalid_json() {
-    let mut server = TestServer::new();
-    server.start();
-    let client = server.client();
 
+    // A completed session is gone
     let resp = client
-        .put("/v2/test/repo/manifests/latest")
+        .get(&location)
         .basic_auth("admin", Some("admin"))
-        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
-        .body("invalid json")
         .send()
         .unwrap();
+    assert_eq!(resp.status(), 404);
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["errors"][0]["code"], "BLOB_UPLOAD_UNKNOWN");
 
-    assert_eq!(resp.status(), 400);
+    let resp = client
+        .get(&location)
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
 }
 
 #[test]
 #[serial]
-fn test_end7_manifest_upload_invalid_schema() {
+fn test_end5_out_of_order_chunk_rejected() {
     let mut server = TestServer::new();
     server.start();
     let client = server.client();
 
-    let invalid_manifest = serde_json::json!({
-        "schemaVersion": 99,
-        "config": {}
-    });
-
     let resp = client
-        .put("/v2/test/repo/manifests/latest")
+        .post("/v2/test/repo/blobs/uploads/")
         .basic_auth("admin", Some("admin"))
-        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
-        .json(&invalid_manifest)
         .send()
         .unwrap();
+    let location = extract_path(resp.headers()["location"].to_str().unwrap()).to_string();
 
-    assert_eq!(resp.status(), 400);
-}
-
-#[test]
-#[serial]
-fn test_end3_manifest_get_by_tag() {
-    let mut server = TestServer::new();
-    server.start();
-    let client = server.client();
-
-    // Upload blob and manifest first
     let blob = sample_blob();
-    let digest = sample_blob_digest();
-    client
-        .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
+    let (first, rest) = blob.split_at(10);
+    let resp = client
+        .patch(&location)
         .basic_auth("admin", Some("admin"))
-        .body(blob)
+        .header("Content-Range", format!("0-{}", first.len() - 1))
+        .body(first.to_vec())
         .send()
         .unwrap();
+    assert_eq!(resp.status(), 202);
+    assert_eq!(resp.headers()["range"], "0-9");
 
-    let manifest = sample_manifest();
-    client
-        .put("/v2/test/repo/manifests/v1.0")
+    // A chunk that skips ahead, repeats what was already sent or cannot exist is refused
+    let unbounded = format!("0-{}", u64::MAX);
+    for range in ["12-20", "0-8", unbounded.as_str()] {
+        let resp = client
+            .patch(&location)
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Range", range)
+            .body(vec![0; 9])
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 416);
+        assert_eq!(resp.headers()["range"], "0-9");
+        assert!(resp.headers().contains_key("location"));
+    }
+
+    let resp = client
+        .patch(&location)
         .basic_auth("admin", Some("admin"))
-        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
-        .json(&manifest)
+        .header("Content-Range", "bytes 10-12")
+        .body(vec![0; 3])
         .send()
         .unwrap();
+    assert_eq!(resp.status(), 400);
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["errors"][0]["code"], "BLOB_UPLOAD_INVALID");
 
-    // Get manifest by tag
+    // Nothing from the refused chunks was kept, so the upload resumes and completes
     let resp = client
-        .get("/v2/test/repo/manifests/v1.0")
+        .put(&format!("{}?digest={}", location, sample_blob_digest()))
         .basic_auth("admin", Some("admin"))
+        .header("Content-Range", format!("10-{}", blob.len() - 1))
+        .body(rest.to_vec())
         .send()
         .unwrap();
-
-    assert_eq!(resp.status(), 200);
-    assert!(resp.headers().contains_key("docker-content-digest"));
-    assert!(resp.headers().contains_key("content-type"));
+    assert_eq!(resp.status(), 201);
 }
 
 #[test]
 #[serial]
-fn test_end3_manifest_get_by_digest() {
+fn test_disabled_capabilities() {
     let mut server = TestServer::new();
-    server.start();
+    server.start_with_args(&[
+        "--disable-capabilities",
+        "mount,blob-delete,referrers,catalog",
+    ]);
     let client = server.client();
 
-    // Upload blob and manifest
-    let blob = sample_blob();
-    let blob_digest = sample_blob_digest();
-    client
+    let resp = client
         .post(&format!(
             "/v2/test/repo/blobs/uploads/?digest={}",
-            blob_digest
+            sample_blob_digest()
         ))
         .basic_auth("admin", Some("admin"))
-        .body(blob)
+        .body(sample_blob())
         .send()
         .unwrap();
+    assert_eq!(resp.status(), 201);
 
-    let manifest = sample_manifest();
-    let manifest_digest = sample_manifest_digest(&manifest);
-    client
-        .put("/v2/test/repo/manifests/latest")
+    // Mount requests open a regular upload session instead
+    let resp = client
+        .post(&format!(
+            "/v2/test/other/blobs/uploads/?mount={}&from=test/repo",
+            sample_blob_digest()
+        ))
         .basic_auth("admin", Some("admin"))
-        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
-        .json(&manifest)
         .send()
         .unwrap();
-
+    assert_eq!(resp.status(), 202);
+    assert!(resp.headers().contains_key("docker-upload-uuid"));
+
+    let resp = client
+        .delete(&format!("/v2/test/repo/blobs/{}", sample_blob_digest()))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 405);
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["errors"][0]["code"], "UNSUPPORTED");
+
+    let resp = client
+        .get("/v2/_catalog")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+
+    // Without the referrers API, pushes with a subject do not claim it was indexed
+    let image = serde_json::to_vec(&sample_manifest()).unwrap();
+    let resp = client
+        .put("/v2/test/repo/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .body(image.clone())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let image_digest = format!("sha256:{}", sha256::digest(&image));
+    let mut signature = sample_manifest();
+    signature["subject"] = serde_json::json!({
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "digest": image_digest,
+        "size": image.len()
+    });
+    let resp = client
+        .put(&format!(
+            "/v2/test/repo/manifests/{}",
+            sample_manifest_digest(&signature)
+        ))
+        .basic_auth("admin", Some("admin"))
+        .json(&signature)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    assert!(!resp.headers().contains_key("oci-subject"));
+
+    let resp = client
+        .get(&format!("/v2/test/repo/referrers/{}", image_digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[test]
+#[serial]
+fn test_cancel_upload_removes_session() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let resp = client
+        .post("/v2/test/repo/blobs/uploads/")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    let location = extract_path(resp.headers()["location"].to_str().unwrap()).to_string();
+    let uuid = resp.headers()["docker-upload-uuid"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let resp = client
+        .patch(&location)
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let staged = server
+        .temp_dir
+        .path()
+        .join("tmp/uploads/test/repo")
+        .join(&uuid);
+    assert!(staged.exists());
+
+    let resp = client
+        .delete(&location)
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+
+    let resp = client
+        .delete(&location)
+        .basic_auth("writer", Some("writer"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 204);
+    assert!(!staged.exists());
+
+    // The session can no longer be resumed, completed or cancelled again
+    let resp = client
+        .patch(&location)
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+    let resp = client
+        .delete(&location)
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["errors"][0]["code"], "BLOB_UPLOAD_UNKNOWN");
+}
+
+#[test]
+#[serial]
+fn test_idle_upload_sessions_expire() {
+    let mut server = TestServer::new();
+    server.start_with_args(&["--upload-session-ttl-secs", "1"]);
+    let client = server.client();
+
+    let resp = client
+        .post("/v2/test/repo/blobs/uploads/")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    assert_eq!(resp.headers()["grain-upload-expires-in"], "1");
+    let location = extract_path(resp.headers()["location"].to_str().unwrap()).to_string();
+    let uuid = resp.headers()["docker-upload-uuid"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let staged = server
+        .temp_dir
+        .path()
+        .join("tmp/uploads/test/repo")
+        .join(&uuid);
+    assert!(staged.exists());
+
+    // Abandoned by the client, the session is deleted by the background task
+    std::thread::sleep(std::time::Duration::from_secs(3));
+    assert!(!staged.exists());
+    let resp = client
+        .get(&location)
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+
+    let metrics = client.get("/metrics").send().unwrap().text().unwrap();
+    assert!(metrics.contains("grain_upload_sessions_expired_total 1"));
+}
+
+/// TCP relay standing in for a TLS-terminating proxy: clients address the proxy's host, which
+/// it forwards to grain as is. The TLS leg itself is left out.
+fn start_relay_proxy(upstream: &str) -> String {
+    use std::net::{Shutdown, TcpListener, TcpStream};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let upstream = upstream.to_string();
+    std::thread::spawn(move || {
+        for client in listener.incoming().flatten() {
+            let Ok(server) = TcpStream::connect(&upstream) else {
+                continue;
+            };
+            for (mut from, mut to) in [
+                (client.try_clone().unwrap(), server.try_clone().unwrap()),
+                (server, client),
+            ] {
+                std::thread::spawn(move || {
+                    let _ = std::io::copy(&mut from, &mut to);
+                    let _ = to.shutdown(Shutdown::Write);
+                });
+            }
+        }
+    });
+    address
+}
+
+#[test]
+#[serial]
+fn test_locations_behind_tls_terminating_proxy() {
+    let mut server = TestServer::new();
+    server.start_with_args(&["--url-scheme", "https"]);
+    let proxy = start_relay_proxy(&server.host);
+    let http = reqwest::blocking::Client::new();
+
+    // Locations point back at the proxy over https; the relay speaks plain http in its place
+    let follow = |location: &str| {
+        let prefix = format!("https://{}/", proxy);
+        assert!(
+            location.starts_with(&prefix),
+            "{} does not start with {}",
+            location,
+            prefix
+        );
+        location.replacen("https://", "http://", 1)
+    };
+
+    let resp = http
+        .post(format!("http://{}/v2/test/repo/blobs/uploads/", proxy))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let location = follow(resp.headers()["location"].to_str().unwrap());
+
+    let resp = http
+        .patch(&location)
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let location = follow(resp.headers()["location"].to_str().unwrap());
+
+    let resp = http
+        .put(format!("{}?digest={}", location, sample_blob_digest()))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let blob_location = follow(resp.headers()["location"].to_str().unwrap());
+    let resp = http
+        .get(&blob_location)
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.bytes().unwrap().as_ref(), sample_blob().as_slice());
+
+    let resp = http
+        .put(format!("http://{}/v2/test/repo/manifests/v1", proxy))
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&sample_manifest())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    assert_eq!(
+        follow(resp.headers()["location"].to_str().unwrap()),
+        format!("http://{}/v2/test/repo/manifests/v1", proxy)
+    );
+
+    // Monolithic uploads and mounts too
+    let resp = http
+        .post(format!(
+            "http://{}/v2/test/other/blobs/uploads/?mount={}&from=test/repo",
+            proxy,
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    follow(resp.headers()["location"].to_str().unwrap());
+    let blob = b"monolithic";
+    let resp = http
+        .post(format!(
+            "http://{}/v2/test/repo/blobs/uploads/?digest=sha256:{}",
+            proxy,
+            sha256::digest(blob.as_slice())
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(blob.to_vec())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    follow(resp.headers()["location"].to_str().unwrap());
+}
+
+#[test]
+#[serial]
+fn test_locations_default_to_http_and_request_host() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let resp = client
+        .post("/v2/test/repo/blobs/uploads/")
+        .basic_auth("admin", Some("admin"))
+        .header("Host", "registry.example.com")
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    assert!(resp.headers()["location"]
+        .to_str()
+        .unwrap()
+        .starts_with("http://registry.example.com/v2/test/repo/blobs/uploads/"));
+}
+
+#[test]
+#[serial]
+fn test_locations_from_forwarded_headers() {
+    let start_upload = |server: &TestServer| {
+        let resp = server
+            .client()
+            .post("/v2/test/repo/blobs/uploads/")
+            .basic_auth("admin", Some("admin"))
+            .header("X-Forwarded-Proto", "https")
+            .header("X-Forwarded-Host", "registry.example.com")
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 202);
+        resp.headers()["location"].to_str().unwrap().to_string()
+    };
+
+    // Ignored from peers that are not trusted proxies
+    let mut server = TestServer::new();
+    server.start();
+    assert!(start_upload(&server).starts_with(&format!("http://{}/v2/", server.host)));
+    server.stop();
+
+    // Challenge realms follow the forwarded origin too
+    let mut server = TestServer::new();
+    server.start_with_args(&["--trusted-proxies", "127.0.0.1", "--token-auth"]);
+    assert!(start_upload(&server).starts_with("https://registry.example.com/v2/test/repo/"));
+    let resp = server
+        .client()
+        .get("/v2/")
+        .header("X-Forwarded-Proto", "https")
+        .header("X-Forwarded-Host", "registry.example.com")
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+    let challenges: Vec<&str> = resp
+        .headers()
+        .get_all("www-authenticate")
+        .iter()
+        .map(|v| v.to_str().unwrap())
+        .collect();
+    assert_eq!(
+        challenges,
+        [
+            "Bearer realm=\"https://registry.example.com/token\",service=\"grain\"",
+            "Basic realm=\"registry.example.com\", charset=\"UTF-8\"",
+        ]
+    );
+    server.stop();
+
+    // The external URL wins, and gives the token realm
+    let mut server = TestServer::new();
+    server.start_with_args(&[
+        "--trusted-proxies",
+        "127.0.0.1",
+        "--external-url",
+        "https://grain.example.org",
+        "--token-auth",
+    ]);
+    assert!(start_upload(&server).starts_with("https://grain.example.org/v2/test/repo/"));
+    let resp = server.client().get("/v2/").send().unwrap();
+    assert_eq!(resp.status(), 401);
+    assert!(resp.headers().get_all("www-authenticate").iter().any(|v| v
+        .to_str()
+        .unwrap()
+        .starts_with("Bearer realm=\"https://grain.example.org/token\"")));
+}
+
+#[test]
+#[serial]
+fn test_native_tls_with_http_redirect() {
+    let mut server = TestServer::new();
+    let certified = rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+    let cert_path = server.temp_dir.path().join("cert.pem");
+    let key_path = server.temp_dir.path().join("key.pem");
+    std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+    std::fs::write(&key_path, certified.key_pair.serialize_pem()).unwrap();
+    let redirect_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let redirect_from = format!("127.0.0.1:{}", redirect_port);
+
+    server.base_url = format!("https://{}", server.host);
+    server.start_with_args(&[
+        "--tls-cert",
+        cert_path.to_str().unwrap(),
+        "--tls-key",
+        key_path.to_str().unwrap(),
+        "--tls-redirect-from",
+        &redirect_from,
+    ]);
+    let client = reqwest::blocking::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    // Served over HTTPS, with HTTPS Locations
+    let resp = client
+        .post(format!("{}/v2/test/repo/blobs/uploads/", server.base_url))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    assert!(resp.headers()["location"]
+        .to_str()
+        .unwrap()
+        .starts_with(&format!(
+            "https://{}/v2/test/repo/blobs/uploads/",
+            server.host
+        )));
+
+    // Plain HTTP on the main port is refused
+    assert!(client
+        .get(format!("http://{}/v2/", server.host))
+        .send()
+        .is_err());
+
+    // The redirect listener sends clients to the HTTPS port
+    let resp = client
+        .get(format!("http://{}/v2/_catalog?n=1", redirect_from))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 308);
+    assert_eq!(
+        resp.headers()["location"],
+        format!("https://127.0.0.1:{}/v2/_catalog?n=1", server.port).as_str()
+    );
+}
+
+#[test]
+#[serial]
+fn test_end6_complete_upload_with_digest_mismatch() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    // Initiate and upload chunk
+    let resp = client
+        .post("/v2/test/repo/blobs/uploads/")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    let location = resp.headers().get("location").unwrap().to_str().unwrap();
+
+    let blob = sample_blob();
+    let resp = client
+        .patch(extract_path(location))
+        .basic_auth("admin", Some("admin"))
+        .body(blob)
+        .send()
+        .unwrap();
+    let location = resp.headers().get("location").unwrap().to_str().unwrap();
+
+    // Try to complete with wrong digest
+    let wrong_digest = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+    let resp = client
+        .put(&format!(
+            "{}?digest={}",
+            extract_path(location),
+            wrong_digest
+        ))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+
+    assert_eq!(resp.status(), 400);
+}
+
+#[test]
+#[serial]
+fn test_end7_manifest_upload() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    // First upload the blob referenced in manifest
+    let blob = sample_blob();
+    let digest = sample_blob_digest();
+    client
+        .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .body(blob)
+        .send()
+        .unwrap();
+
+    // Upload manifest
+    let manifest = sample_manifest();
+    let resp = client
+        .put("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&manifest)
+        .send()
+        .unwrap();
+
+    assert_eq!(resp.status(), 201);
+    assert!(resp.headers().contains_key("location"));
+    assert!(resp.headers().contains_key("docker-content-digest"));
+}
+
+#[test]
+#[serial]
+fn test_end7_manifest_tag_and_layer_limits() {
+    let mut server = TestServer::new();
+    server.start_with_args(&["--max-tags-per-repo", "2", "--max-layers-per-manifest", "1"]);
+    let client = server.client();
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let put = |reference: &str, manifest: &serde_json::Value| {
+        client
+            .put(&format!("/v2/test/repo/manifests/{}", reference))
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(manifest)
+            .send()
+            .unwrap()
+    };
+
+    let manifest = sample_manifest();
+    assert_eq!(put("v1", &manifest).status(), 201);
+    assert_eq!(put("v2", &manifest).status(), 201);
+
+    // A third tag is refused, re-pushing an existing one and pushing by digest are not
+    let resp = put("v3", &manifest);
+    assert_eq!(resp.status(), 403);
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["errors"][0]["code"], "DENIED");
+    assert!(body["errors"][0]["detail"]
+        .as_str()
+        .unwrap()
+        .contains("2 tags (limit 2)"));
+    assert_eq!(put("v2", &manifest).status(), 201);
+    assert_eq!(
+        put(&sample_manifest_digest(&manifest), &manifest).status(),
+        201
+    );
+
+    let mut layered = manifest.clone();
+    layered["layers"] = serde_json::json!([manifest["layers"][0], manifest["layers"][0]]);
+    let resp = put("v2", &layered);
+    assert_eq!(resp.status(), 400);
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["errors"][0]["code"], "MANIFEST_INVALID");
+
+    // Current counts and limits are exposed in the repository stats
+    let stats: serde_json::Value = client
+        .get("/admin/repos/test/repo/stats")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(stats["tag_count"], 2);
+    assert_eq!(stats["manifest_count"], 1);
+    assert_eq!(stats["max_tags"], 2);
+    assert_eq!(stats["max_layers_per_manifest"], 1);
+}
+
+#[test]
+#[serial]
+fn test_config_file_with_overrides() {
+    let mut server = TestServer::new();
+    let toml_path = server.temp_dir.path().join("grain.toml");
+    std::fs::write(
+        &toml_path,
+        "max_tags_per_repo = 5\n\n[max]\nlayers_per_manifest = 3\n",
+    )
+    .unwrap();
+    // The command line overrides the file
+    server.start_with_args(&[
+        "--config",
+        toml_path.to_str().unwrap(),
+        "--max-layers-per-manifest",
+        "4",
+    ]);
+    let client = server.client();
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let resp = client
+        .put("/v2/test/repo/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&sample_manifest())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let stats = || {
+        client
+            .get("/admin/repos/test/repo/stats")
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap()
+            .json::<serde_json::Value>()
+            .unwrap()
+    };
+    assert_eq!(stats()["max_tags"], 5);
+    assert_eq!(stats()["max_layers_per_manifest"], 4);
+
+    // YAML works the same way
+    server.stop();
+    let yaml_path = server.temp_dir.path().join("grain.yaml");
+    std::fs::write(
+        &yaml_path,
+        "max_tags_per_repo: 7\nmax:\n  layers_per_manifest: 2\n",
+    )
+    .unwrap();
+    server.start_with_args(&["--config", yaml_path.to_str().unwrap()]);
+    assert_eq!(stats()["max_tags"], 7);
+    assert_eq!(stats()["max_layers_per_manifest"], 2);
+}
+
+#[test]
+#[serial]
+fn test_end7_manifest_upload_warning_headers() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    // OCI manifests are accepted without warnings
+    let resp = client
+        .put("/v2/test/repo/manifests/oci")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&sample_manifest())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    assert!(resp.headers().get("warning").is_none());
+
+    // Docker media types are accepted with a deprecation warning
+    let mut manifest = sample_manifest();
+    manifest["mediaType"] =
+        serde_json::json!("application/vnd.docker.distribution.manifest.v2+json");
+    let resp = client
+        .put("/v2/test/repo/manifests/docker")
+        .basic_auth("admin", Some("admin"))
+        .header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .json(&manifest)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let warnings: Vec<_> = resp.headers().get_all("warning").iter().collect();
+    assert_eq!(warnings.len(), 1);
+    let warning = warnings[0].to_str().unwrap();
+    assert!(warning.starts_with("299 - \""));
+    assert!(warning.contains("deprecated media type"));
+}
+
+#[test]
+#[serial]
+fn test_end7_manifest_upload_invalid_json() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let resp = client
+        .put("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .body("invalid json")
+        .send()
+        .unwrap();
+
+    assert_eq!(resp.status(), 400);
+}
+
+#[test]
+#[serial]
+fn test_end7_manifest_upload_invalid_schema() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let invalid_manifest = serde_json::json!({
+        "schemaVersion": 99,
+        "config": {}
+    });
+
+    let resp = client
+        .put("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&invalid_manifest)
+        .send()
+        .unwrap();
+
+    assert_eq!(resp.status(), 400);
+}
+
+#[test]
+#[serial]
+fn test_end7_manifest_upload_label_policy() {
+    let mut server = TestServer::new();
+    server.start_with_args(&[
+        "--require-labels",
+        "org.opencontainers.image.source",
+        "--forbid-labels",
+        "debug",
+    ]);
+    let client = server.client();
+
+    let push_config = |labels: serde_json::Value| {
+        let config = serde_json::to_vec(&serde_json::json!({
+            "architecture": "amd64",
+            "os": "linux",
+            "config": {"Labels": labels}
+        }))
+        .unwrap();
+        let digest = format!("sha256:{}", sha256::digest(&config));
+        let resp = client
+            .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
+            .basic_auth("admin", Some("admin"))
+            .body(config.clone())
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        (digest, config.len())
+    };
+    let put_manifest = |config: (String, usize), annotations: serde_json::Value| {
+        let mut manifest = sample_manifest();
+        manifest["config"]["digest"] = config.0.into();
+        manifest["config"]["size"] = config.1.into();
+        manifest["annotations"] = annotations;
+        client
+            .put("/v2/test/repo/manifests/latest")
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(&manifest)
+            .send()
+            .unwrap()
+    };
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+
+    let unlabeled = push_config(serde_json::json!({"debug": "true"}));
+    let resp = put_manifest(unlabeled, serde_json::json!({}));
+    assert_eq!(resp.status(), 400);
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["errors"][0]["code"], "MANIFEST_INVALID");
+    let detail = body["errors"][0]["detail"].as_str().unwrap();
+    assert!(detail.contains("missing required label org.opencontainers.image.source"));
+    assert!(detail.contains("forbidden label debug is set"));
+
+    // Manifest annotations count as labels
+    let resp = put_manifest(
+        push_config(serde_json::json!({})),
+        serde_json::json!({"org.opencontainers.image.source": "https://example.com/app"}),
+    );
+    assert_eq!(resp.status(), 201);
+
+    let labeled = push_config(
+        serde_json::json!({"org.opencontainers.image.source": "https://example.com/app"}),
+    );
+    assert_eq!(put_manifest(labeled, serde_json::json!({})).status(), 201);
+}
+
+#[test]
+#[serial]
+fn test_end7_manifest_upload_invalid_tag() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+
+    let too_long = "a".repeat(129);
+    for tag in [".hidden", "-rc", "v1+build", too_long.as_str()] {
+        let resp = client
+            .put(&format!("/v2/test/repo/manifests/{}", tag))
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(&sample_manifest())
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 400, "{}", tag);
+        let body: serde_json::Value = resp.json().unwrap();
+        assert_eq!(body["errors"][0]["code"], "TAG_INVALID");
+    }
+
+    let longest = "a".repeat(128);
+    for reference in [
+        longest.as_str(),
+        &sample_manifest_digest(&sample_manifest()),
+    ] {
+        let resp = client
+            .put(&format!("/v2/test/repo/manifests/{}", reference))
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(&sample_manifest())
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201, "{}", reference);
+    }
+}
+
+#[test]
+#[serial]
+fn test_end7_manifest_upload_digest_mismatch() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+
+    let other = format!("sha256:{}", "0".repeat(64));
+    for reference in [other.as_str(), "sha256:abc", "md5:0123"] {
+        let resp = client
+            .put(&format!("/v2/test/repo/manifests/{}", reference))
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(&sample_manifest())
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 400, "{}", reference);
+        let body: serde_json::Value = resp.json().unwrap();
+        assert_eq!(body["errors"][0]["code"], "DIGEST_INVALID");
+    }
+
+    let resp = client
+        .get(&format!("/v2/test/repo/manifests/{}", other))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[test]
+#[serial]
+fn test_end7_manifest_upload_immutable_tags() {
+    use grain::client::{AdminClient, CreateRepositoryRequest};
+
+    let mut server = TestServer::new();
+    server.start_with_args(&["--immutable-tags", "v*"]);
+    let client = server.client();
+    AdminClient::new(&server.base_url, "admin", "admin")
+        .create_repository(&CreateRepositoryRequest {
+            name: "test/frozen".to_string(),
+            immutable_tags: vec!["stable".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+
+    let put_manifest = |repo: &str, tag: &str, manifest: &serde_json::Value| {
+        client
+            .put(&format!("/v2/test/{}/manifests/{}", repo, tag))
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(manifest)
+            .send()
+            .unwrap()
+    };
+    for repo in ["repo", "frozen"] {
+        client
+            .post(&format!(
+                "/v2/test/{}/blobs/uploads/?digest={}",
+                repo,
+                sample_blob_digest()
+            ))
+            .basic_auth("admin", Some("admin"))
+            .body(sample_blob())
+            .send()
+            .unwrap();
+    }
+    let manifest = sample_manifest();
+    let mut other = sample_manifest();
+    other["annotations"] = serde_json::json!({"rebuilt": "true"});
+
+    // Re-pushing the same manifest is allowed, moving the tag is not
+    assert_eq!(put_manifest("repo", "v1", &manifest).status(), 201);
+    assert_eq!(put_manifest("repo", "v1", &manifest).status(), 201);
+    let resp = put_manifest("repo", "v1", &other);
+    assert_eq!(resp.status(), 409);
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["errors"][0]["code"], "TAG_INVALID");
+    let detail = body["errors"][0]["detail"].as_str().unwrap();
+    assert!(detail.contains(&sample_manifest_digest(&manifest)));
+
+    // Other tags move freely
+    assert_eq!(put_manifest("repo", "latest", &manifest).status(), 201);
+    assert_eq!(put_manifest("repo", "latest", &other).status(), 201);
+
+    // Patterns declared on the repository
+    assert_eq!(put_manifest("frozen", "stable", &manifest).status(), 201);
+    assert_eq!(put_manifest("frozen", "stable", &other).status(), 409);
+    assert_eq!(put_manifest("repo", "stable", &manifest).status(), 201);
+    assert_eq!(put_manifest("repo", "stable", &other).status(), 201);
+}
+
+#[test]
+#[serial]
+fn test_end3_manifest_get_by_tag() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    // Upload blob and manifest first
+    let blob = sample_blob();
+    let digest = sample_blob_digest();
+    client
+        .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .body(blob)
+        .send()
+        .unwrap();
+
+    let manifest = sample_manifest();
+    client
+        .put("/v2/test/repo/manifests/v1.0")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&manifest)
+        .send()
+        .unwrap();
+
+    // Get manifest by tag
+    let resp = client
+        .get("/v2/test/repo/manifests/v1.0")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+
+    assert_eq!(resp.status(), 200);
+    assert!(resp.headers().contains_key("docker-content-digest"));
+    assert!(resp.headers().contains_key("content-type"));
+}
+
+#[test]
+#[serial]
+fn test_end3_manifest_get_by_digest() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    // Upload blob and manifest
+    let blob = sample_blob();
+    let blob_digest = sample_blob_digest();
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            blob_digest
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(blob)
+        .send()
+        .unwrap();
+
+    let manifest = sample_manifest();
+    let manifest_digest = sample_manifest_digest(&manifest);
+    client
+        .put("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&manifest)
+        .send()
+        .unwrap();
+
     // Get by digest
     let resp = client
         .get(&format!("/v2/test/repo/manifests/{}", manifest_digest))
@@ -390,6 +1649,222 @@ fn test_end3_manifest_get_by_digest() {
     assert_eq!(resp.status(), 200);
 }
 
+#[test]
+#[serial]
+fn test_end3_manifest_get_if_none_match() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let manifest = sample_manifest();
+    client
+        .put("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&manifest)
+        .send()
+        .unwrap();
+
+    let get = |if_none_match: Option<&str>| {
+        let mut request = client
+            .get("/v2/test/repo/manifests/latest")
+            .basic_auth("admin", Some("admin"));
+        if let Some(etag) = if_none_match {
+            request = request.header("If-None-Match", etag);
+        }
+        request.send().unwrap()
+    };
+
+    // The digest is the entity tag
+    let etag = format!("\"{}\"", sample_manifest_digest(&manifest));
+    let resp = get(None);
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["etag"], etag.as_str());
+
+    // A current cached copy is not sent again
+    for header in [
+        etag.clone(),
+        format!("W/{}", etag),
+        format!("\"sha256:other\", {}", etag),
+        "*".to_string(),
+    ] {
+        let resp = get(Some(&header));
+        assert_eq!(resp.status(), 304, "If-None-Match: {}", header);
+        assert_eq!(resp.headers()["etag"], etag.as_str());
+        assert!(resp.bytes().unwrap().is_empty());
+    }
+    assert_eq!(get(Some("\"sha256:other\"")).status(), 200);
+
+    let resp = client
+        .head("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .header("If-None-Match", &etag)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 304);
+
+    // Permission is still checked first
+    let resp = client
+        .get("/v2/test/repo/manifests/latest")
+        .header("If-None-Match", &etag)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+}
+
+#[test]
+#[serial]
+fn test_end3_manifest_metadata() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let push = |manifest: &serde_json::Value| {
+        let resp = client
+            .put("/v2/test/repo/manifests/v1")
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(manifest)
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+    };
+    let head_digest = |reference: &str| {
+        let resp = client
+            .head(&format!("/v2/test/repo/manifests/{}", reference))
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        resp.headers()["docker-content-digest"]
+            .to_str()
+            .unwrap()
+            .to_string()
+    };
+    let meta_dir = server.temp_dir.path().join("tmp/manifest-meta/test/repo");
+    let read_meta = |reference: &str| -> serde_json::Value {
+        serde_json::from_slice(&std::fs::read(meta_dir.join(reference)).unwrap()).unwrap()
+    };
+
+    // Metadata is written with each manifest
+    let manifest = sample_manifest();
+    let digest = sample_manifest_digest(&manifest);
+    let hex = digest.trim_start_matches("sha256:");
+    push(&manifest);
+    let meta = read_meta("v1");
+    assert_eq!(meta["digest"], digest.as_str());
+    assert_eq!(
+        meta["media_type"],
+        "application/vnd.oci.image.manifest.v1+json"
+    );
+    assert_eq!(read_meta(hex), meta);
+
+    // HEAD serves it without reading the manifest
+    let mut altered = meta.clone();
+    altered["digest"] = format!("sha256:{}", "0".repeat(64)).into();
+    std::fs::write(meta_dir.join("v1"), altered.to_string()).unwrap();
+    assert_eq!(head_digest("v1"), altered["digest"]);
+
+    // Pushing over the tag replaces it
+    let mut updated = sample_manifest();
+    updated["annotations"] = serde_json::json!({"version": "2"});
+    push(&updated);
+    assert_eq!(head_digest("v1"), sample_manifest_digest(&updated));
+
+    // Manifests without metadata are hashed, and digests get their metadata back
+    std::fs::remove_file(meta_dir.join(hex)).unwrap();
+    assert_eq!(head_digest(&digest), digest);
+    assert_eq!(read_meta(hex)["digest"], digest.as_str());
+
+    let resp = client
+        .delete("/v2/test/repo/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    assert!(!meta_dir.join("v1").exists());
+}
+
+#[test]
+#[serial]
+fn test_end3_manifest_content_type() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+
+    // Without `mediaType`, only the pushed Content-Type tells a Docker manifest apart
+    let docker_type = "application/vnd.docker.distribution.manifest.v2+json";
+    let mut manifest = sample_manifest();
+    manifest.as_object_mut().unwrap().remove("mediaType");
+    let push = |tag: &str, content_type: &str| {
+        let resp = client
+            .put(&format!("/v2/test/repo/manifests/{}", tag))
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", content_type)
+            .body(serde_json::to_vec(&manifest).unwrap())
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+    };
+    push("docker", docker_type);
+    let digest = sample_manifest_digest(&manifest);
+    for reference in ["docker", digest.as_str()] {
+        let path = format!("/v2/test/repo/manifests/{}", reference);
+        let resp = client
+            .get(&path)
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap();
+        assert_eq!(resp.headers()["content-type"], docker_type);
+        let resp = client
+            .head(&path)
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap();
+        assert_eq!(resp.headers()["content-type"], docker_type);
+    }
+
+    // Plain JSON falls back to the media type of the manifest
+    push("json", "application/json");
+    let resp = client
+        .head("/v2/test/repo/manifests/json")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(
+        resp.headers()["content-type"],
+        "application/vnd.oci.image.manifest.v1+json"
+    );
+}
+
 #[test]
 #[serial]
 fn test_end3_manifest_head() {
@@ -511,20 +1986,59 @@ fn test_end8b_tag_list_pagination() {
     server.start();
     let client = server.client();
 
-    // Upload blob and multiple tagged manifests
-    let blob = sample_blob();
+    // Upload blob and multiple tagged manifests
+    let blob = sample_blob();
+    let digest = sample_blob_digest();
+    client
+        .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .body(blob)
+        .send()
+        .unwrap();
+
+    let manifest = sample_manifest();
+    for i in 1..=10 {
+        client
+            .put(&format!("/v2/test/repo/manifests/v{}", i))
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(&manifest)
+            .send()
+            .unwrap();
+    }
+
+    // Request with pagination
+    let resp = client
+        .get("/v2/test/repo/tags/list?n=5")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = resp.json().unwrap();
+    let tags = json["tags"].as_array().unwrap();
+    assert!(tags.len() <= 5);
+}
+
+#[test]
+#[serial]
+fn test_end8_tag_list_detail() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
     let digest = sample_blob_digest();
     client
         .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
         .basic_auth("admin", Some("admin"))
-        .body(blob)
+        .body(sample_blob())
         .send()
         .unwrap();
 
     let manifest = sample_manifest();
-    for i in 1..=10 {
+    for tag in &["v1", "v2"] {
         client
-            .put(&format!("/v2/test/repo/manifests/v{}", i))
+            .put(&format!("/v2/test/repo/manifests/{}", tag))
             .basic_auth("admin", Some("admin"))
             .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
             .json(&manifest)
@@ -532,17 +2046,34 @@ fn test_end8b_tag_list_pagination() {
             .unwrap();
     }
 
-    // Request with pagination
     let resp = client
-        .get("/v2/test/repo/tags/list?n=5")
-        .basic_auth("admin", Some("admin"))
+        .get("/v2/test/repo/tags/list?detail=true&n=1")
+        .basic_auth("reader", Some("reader"))
         .send()
         .unwrap();
 
     assert_eq!(resp.status(), 200);
     let json: serde_json::Value = resp.json().unwrap();
-    let tags = json["tags"].as_array().unwrap();
-    assert!(tags.len() <= 5);
+    assert_eq!(json["tags"], serde_json::json!(["v1"]));
+    let details = json["details"].as_array().unwrap();
+    assert_eq!(details.len(), 1);
+    assert_eq!(details[0]["tag"], "v1");
+    assert_eq!(details[0]["digest"], sample_manifest_digest(&manifest));
+    assert_eq!(
+        details[0]["mediaType"],
+        "application/vnd.oci.image.manifest.v1+json"
+    );
+    assert_eq!(details[0]["size"], 27);
+    assert!(details[0]["pushed_at"].as_u64().unwrap() > 0);
+
+    // Plain listing stays spec-shaped
+    let resp = client
+        .get("/v2/test/repo/tags/list")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    let json: serde_json::Value = resp.json().unwrap();
+    assert!(json.get("details").is_none());
 }
 
 #[test]
@@ -588,6 +2119,227 @@ fn test_end9_delete_manifest() {
         .unwrap();
 
     assert_eq!(resp.status(), 404);
+
+    // Deleting a tag only untags: the manifest and its other tags remain
+    let manifest_digest = sample_manifest_digest(&manifest);
+    for tag in ["one", "two"] {
+        let resp = client
+            .put(&format!("/v2/test/repo/manifests/{}", tag))
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(&manifest)
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+    }
+    let path = |reference: &str| format!("/v2/test/repo/manifests/{}", reference);
+    let get = |reference: &str| {
+        let resp = client
+            .get(&path(reference))
+            .basic_auth("admin", Some("admin"));
+        resp.send().unwrap().status()
+    };
+    let delete = |reference: &str| {
+        let resp = client
+            .delete(&path(reference))
+            .basic_auth("admin", Some("admin"));
+        resp.send().unwrap().status()
+    };
+    assert_eq!(delete("one"), 202);
+    assert_eq!(get("one"), 404);
+    assert_eq!(get("two"), 200);
+    assert_eq!(get(&manifest_digest), 200);
+
+    // Deleting the digest removes the manifest and every tag pointing to it
+    assert_eq!(delete(&manifest_digest), 202);
+    assert_eq!(get(&manifest_digest), 404);
+    assert_eq!(get("two"), 404);
+    let tags: serde_json::Value = client
+        .get("/v2/test/repo/tags/list")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert!(!tags["tags"]
+        .as_array()
+        .is_some_and(|tags| tags.iter().any(|t| t == "two")));
+}
+
+/// Webhook endpoint recording each delivery's headers and body. The first `failures`
+/// deliveries get a 500, so retries can be observed.
+fn start_webhook_receiver(
+    failures: usize,
+) -> (String, std::sync::mpsc::Receiver<(String, Vec<u8>)>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for (n, mut stream) in listener.incoming().flatten().enumerate() {
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let (mut head, mut line) = (String::new(), String::new());
+            while reader.read_line(&mut line).unwrap_or(0) > 2 {
+                head.push_str(&line.to_ascii_lowercase());
+                line.clear();
+            }
+            let length = head
+                .lines()
+                .find_map(|l| l.strip_prefix("content-length: "))
+                .and_then(|l| l.trim().parse().ok())
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let status = if n < failures {
+                "500 Internal Server Error"
+            } else {
+                let _ = sender.send((head, body));
+                "200 OK"
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            );
+        }
+    });
+    (url, receiver)
+}
+
+#[test]
+#[serial]
+fn test_webhook_failing_url_does_not_delay_others() {
+    use std::time::Duration;
+
+    // The first endpoint keeps failing, so its deliveries spend 1s + 2s + 4s retrying
+    let (failing, _) = start_webhook_receiver(usize::MAX);
+    let (url, events) = start_webhook_receiver(0);
+    let mut server = TestServer::new();
+    server.start_with_args(&[
+        "--webhook-urls",
+        &format!("{},{}", failing, url),
+        "--webhook-actions",
+        "push",
+        "--webhook-retries",
+        "3",
+    ]);
+    let client = server.client();
+    for tag in ["v1", "v2"] {
+        client
+            .post(&format!(
+                "/v2/test/repo/blobs/uploads/?digest={}",
+                sample_blob_digest()
+            ))
+            .basic_auth("admin", Some("admin"))
+            .body(sample_blob())
+            .send()
+            .unwrap();
+        let resp = client
+            .put(&format!("/v2/test/repo/manifests/{}", tag))
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(&sample_manifest())
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+    }
+
+    for tag in ["v1", "v2"] {
+        let (_, body) = events.recv_timeout(Duration::from_secs(3)).unwrap();
+        let payload: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(payload["events"][0]["target"]["tag"], tag);
+    }
+}
+
+#[test]
+#[serial]
+fn test_webhook_notifications() {
+    use hmac::{Hmac, Mac};
+    use std::time::Duration;
+
+    let (url, events) = start_webhook_receiver(1);
+    let mut server = TestServer::new();
+    server.start_with_args(&[
+        "--webhook-urls",
+        &url,
+        "--webhook-actions",
+        "push,delete",
+        "--webhook-secret",
+        "hook-secret",
+        "--webhook-retries",
+        "1",
+    ]);
+    let client = server.client();
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let manifest = sample_manifest();
+    let digest = sample_manifest_digest(&manifest);
+    let resp = client
+        .put("/v2/test/repo/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .json(&manifest)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    // The first delivery fails and is retried
+    let (head, body) = events.recv_timeout(Duration::from_secs(10)).unwrap();
+    let payload: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert!(head.contains("content-type: application/vnd.docker.distribution.events.v1+json"));
+    let event = &payload["events"][0];
+    assert_eq!(event["action"], "push");
+    assert_eq!(event["actor"]["name"], "admin");
+    assert_eq!(event["target"]["repository"], "test/repo");
+    assert_eq!(event["target"]["tag"], "v1");
+    assert_eq!(event["target"]["digest"], digest.as_str());
+    assert_eq!(event["request"]["method"], "PUT");
+
+    // The signature covers the body as sent
+    let signature = head
+        .lines()
+        .find_map(|l| l.strip_prefix("x-grain-signature: sha256="))
+        .unwrap()
+        .trim()
+        .to_string();
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"hook-secret").unwrap();
+    mac.update(&body);
+    let expected: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert_eq!(signature, expected);
+
+    // Pulls are filtered out by --webhook-actions
+    let resp = client
+        .get("/v2/test/repo/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let resp = client
+        .delete(&format!("/v2/test/repo/manifests/{}", digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let (_, body) = events.recv_timeout(Duration::from_secs(10)).unwrap();
+    let payload: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let event = &payload["events"][0];
+    assert_eq!(event["action"], "delete");
+    assert_eq!(event["target"]["digest"], digest.as_str());
+    assert!(event["target"].get("tag").is_none());
+    assert!(events.recv_timeout(Duration::from_millis(500)).is_err());
 }
 
 #[test]
@@ -666,6 +2418,63 @@ fn test_end11_cross_repo_blob_mount_success() {
     assert_eq!(resp.status(), 200);
 }
 
+#[test]
+#[serial]
+fn test_end11_cross_repo_mount_without_from() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let digest = sample_blob_digest();
+    client
+        .post(&format!("/v2/source/repo/blobs/uploads/?digest={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+
+    // Mounted from whichever repository holds the blob
+    let resp = client
+        .post(&format!("/v2/target/repo/blobs/uploads/?mount={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let resp = client
+        .head(&format!("/v2/target/repo/blobs/{}", digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    // Only from repositories the user can pull from; otherwise an upload session is opened
+    let resp = client
+        .post(&format!("/v2/test/repo/blobs/uploads/?mount={}", digest))
+        .basic_auth("writer", Some("writer"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    // Repositories the user cannot pull from are skipped without recording denials
+    let metrics = client.get("/metrics").send().unwrap().text().unwrap();
+    let denials = metrics
+        .lines()
+        .find_map(|line| line.strip_prefix("grain_permission_denials_total "));
+    assert!(matches!(denials, None | Some("0")), "{:?}", denials);
+
+    client
+        .post(&format!("/v2/test/source/blobs/uploads/?digest={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let resp = client
+        .post(&format!("/v2/test/repo/blobs/uploads/?mount={}", digest))
+        .basic_auth("writer", Some("writer"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+}
+
 #[test]
 #[serial]
 fn test_end11_cross_repo_mount_nonexistent_blob() {
@@ -688,3 +2497,412 @@ fn test_end11_cross_repo_mount_nonexistent_blob() {
     // Should fall back to regular upload initiation
     assert_eq!(resp.status(), 202);
 }
+
+#[test]
+#[serial]
+fn test_end12_referrers() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+
+    let subject = sample_manifest();
+    let subject_bytes = serde_json::to_vec(&subject).unwrap();
+    let subject_digest = sample_manifest_digest(&subject);
+    let resp = client
+        .put("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .body(subject_bytes.clone())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    assert!(resp.headers().get("OCI-Subject").is_none());
+
+    // No referrers yet
+    let resp = client
+        .get(&format!("/v2/test/repo/referrers/{}", subject_digest))
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("Content-Type").unwrap(),
+        "application/vnd.oci.image.index.v1+json"
+    );
+    let index: serde_json::Value = resp.json().unwrap();
+    assert_eq!(index["manifests"].as_array().unwrap().len(), 0);
+
+    // Attach two artifacts, pushed by digest as ORAS does
+    let mut digests = Vec::new();
+    for artifact_type in [
+        "application/vnd.example.sbom",
+        "application/vnd.example.sig",
+    ] {
+        let artifact = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "artifactType": artifact_type,
+            "config": {
+                "mediaType": "application/vnd.oci.empty.v1+json",
+                "size": 27,
+                "digest": sample_blob_digest()
+            },
+            "layers": [
+                {
+                    "mediaType": "application/octet-stream",
+                    "size": 27,
+                    "digest": sample_blob_digest()
+                }
+            ],
+            "subject": {
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "size": subject_bytes.len(),
+                "digest": subject_digest
+            }
+        });
+        let digest = sample_manifest_digest(&artifact);
+        let resp = client
+            .put(&format!("/v2/test/repo/manifests/{}", digest))
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(&artifact)
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        assert_eq!(
+            resp.headers().get("OCI-Subject").unwrap(),
+            subject_digest.as_str()
+        );
+        digests.push(digest);
+    }
+
+    // Artifacts pushed by digest are retrievable by digest and are not listed as tags
+    let resp = client
+        .get(&format!("/v2/test/repo/manifests/{}", digests[0]))
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let tags: serde_json::Value = client
+        .get("/v2/test/repo/tags/list")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(tags["tags"], serde_json::json!(["latest"]));
+
+    let resp = client
+        .get(&format!("/v2/test/repo/referrers/{}", subject_digest))
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert!(resp.headers().get("OCI-Filters-Applied").is_none());
+    let index: serde_json::Value = resp.json().unwrap();
+    let manifests = index["manifests"].as_array().unwrap();
+    assert_eq!(manifests.len(), 2);
+    for manifest in manifests {
+        assert!(digests.contains(&manifest["digest"].as_str().unwrap().to_string()));
+    }
+
+    // Filter by artifact type
+    let resp = client
+        .get(&format!(
+            "/v2/test/repo/referrers/{}?artifactType=application/vnd.example.sig",
+            subject_digest
+        ))
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("OCI-Filters-Applied").unwrap(),
+        "artifactType"
+    );
+    let index: serde_json::Value = resp.json().unwrap();
+    let manifests = index["manifests"].as_array().unwrap();
+    assert_eq!(manifests.len(), 1);
+    assert_eq!(manifests[0]["digest"], digests[1].as_str());
+    assert_eq!(manifests[0]["artifactType"], "application/vnd.example.sig");
+
+    // Deleted referrers drop out of the index
+    let resp = client
+        .delete(&format!("/v2/test/repo/manifests/{}", digests[1]))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    let index: serde_json::Value = client
+        .get(&format!("/v2/test/repo/referrers/{}", subject_digest))
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(index["manifests"].as_array().unwrap().len(), 1);
+
+    // Pull permission is required
+    let resp = client
+        .get(&format!("/v2/test/repo/referrers/{}", subject_digest))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+}
+
+#[test]
+#[serial]
+fn test_end12_notation_signatures() {
+    use base64::{
+        prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+        Engine,
+    };
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+    let push_blob = |blob: &[u8]| {
+        let digest = format!("sha256:{}", sha256::digest(blob));
+        let resp = client
+            .post(&format!("/v2/test/app/blobs/uploads/?digest={}", digest))
+            .basic_auth("admin", Some("admin"))
+            .body(blob.to_vec())
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        digest
+    };
+
+    push_blob(&sample_blob());
+    let image = serde_json::to_vec(&sample_manifest()).unwrap();
+    let resp = client
+        .put("/v2/test/app/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .body(image.clone())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let image_digest = format!("sha256:{}", sha256::digest(&image));
+
+    // A signature pushed the way `notation sign` does: an empty config, the JWS envelope as
+    // the only layer and the signed image as subject
+    let signer = rcgen::generate_simple_self_signed(vec!["release.example.com".to_string()])
+        .unwrap()
+        .cert;
+    let thumbprint = sha256::digest(signer.der().as_ref());
+    let protected = serde_json::json!({
+        "alg": "PS256",
+        "crit": ["io.cncf.notary.signingScheme"],
+        "cty": "application/vnd.cncf.notary.payload.v1+json",
+        "io.cncf.notary.signingScheme": "notary.x509",
+        "io.cncf.notary.signingTime": "2026-01-02T03:04:05Z"
+    });
+    let envelope = serde_json::json!({
+        "payload": BASE64_URL_SAFE_NO_PAD.encode(b"{}"),
+        "protected": BASE64_URL_SAFE_NO_PAD.encode(protected.to_string()),
+        "header": {
+            "x5c": [BASE64_STANDARD.encode(signer.der())],
+            "io.cncf.notary.signingAgent": "notation-go/1.3.0"
+        },
+        "signature": BASE64_URL_SAFE_NO_PAD.encode(b"signature")
+    })
+    .to_string();
+    let empty_config = push_blob(b"{}");
+    let push_signature = |envelope: &[u8]| {
+        let signature = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "artifactType": "application/vnd.cncf.notary.signature",
+            "config": {
+                "mediaType": "application/vnd.oci.empty.v1+json",
+                "digest": empty_config,
+                "size": 2
+            },
+            "layers": [{
+                "mediaType": "application/jose+json",
+                "digest": push_blob(envelope),
+                "size": envelope.len()
+            }],
+            "subject": {
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "digest": image_digest,
+                "size": image.len()
+            },
+            "annotations": {
+                "io.cncf.notary.x509chain.thumbprint#S256":
+                    serde_json::json!([thumbprint]).to_string()
+            }
+        });
+        let digest = sample_manifest_digest(&signature);
+        let resp = client
+            .put(&format!("/v2/test/app/manifests/{}", digest))
+            .basic_auth("admin", Some("admin"))
+            .header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .json(&signature)
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        // Tells notation the registry serves the referrers API
+        assert_eq!(resp.headers()["OCI-Subject"], image_digest.as_str());
+        digest
+    };
+    let signature_digest = push_signature(envelope.as_bytes());
+    let broken_digest = push_signature(b"not an envelope");
+
+    let resp = client
+        .get(&format!(
+            "/v2/test/app/referrers/{}?artifactType=application/vnd.cncf.notary.signature",
+            image_digest
+        ))
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    let index: serde_json::Value = resp.json().unwrap();
+    let manifests = index["manifests"].as_array().unwrap();
+    assert_eq!(manifests.len(), 2);
+    assert_eq!(
+        manifests[0]["annotations"]["io.cncf.notary.x509chain.thumbprint#S256"],
+        serde_json::json!([thumbprint]).to_string()
+    );
+
+    let resp = client
+        .get("/admin/v1/repos/test/app/signatures/v1")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let list: serde_json::Value = resp.json().unwrap();
+    assert_eq!(list["subject"], image_digest.as_str());
+    let signatures = list["signatures"].as_array().unwrap();
+    assert_eq!(signatures.len(), 2);
+    let find = |digest: &str| {
+        signatures
+            .iter()
+            .find(|s| s["digest"] == digest)
+            .unwrap()
+            .clone()
+    };
+
+    let signature = find(&signature_digest);
+    assert_eq!(signature["envelope_type"], "application/jose+json");
+    assert_eq!(signature["signer"], "CN=rcgen self signed cert");
+    assert_eq!(signature["signing_scheme"], "notary.x509");
+    assert_eq!(signature["signing_time"], "2026-01-02T03:04:05Z");
+    assert_eq!(signature["signing_agent"], "notation-go/1.3.0");
+    assert_eq!(
+        signature["certificate_chain"][0]["thumbprint"],
+        thumbprint.as_str()
+    );
+    assert_eq!(signature["thumbprints"], serde_json::json!([thumbprint]));
+    assert!(signature.get("error").is_none());
+
+    // Unreadable envelopes are still listed, with their annotated thumbprints
+    let broken = find(&broken_digest);
+    assert!(broken["error"].is_string());
+    assert_eq!(broken["thumbprints"], serde_json::json!([thumbprint]));
+
+    let resp = client
+        .get("/admin/v1/repos/test/app/signatures/missing")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+    let resp = client
+        .get("/admin/v1/repos/test/app/signatures/v1")
+        .basic_auth("limited", Some("limited"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+}
+
+#[test]
+#[serial]
+fn test_catalog() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    for repository in ["test/repo", "test/other", "myorg/myrepo", "private/repo"] {
+        let resp = client
+            .post(&format!(
+                "/v2/{}/blobs/uploads/?digest={}",
+                repository,
+                sample_blob_digest()
+            ))
+            .basic_auth("admin", Some("admin"))
+            .body(sample_blob())
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+    }
+
+    let catalog = |user: &str, query: &str| -> serde_json::Value {
+        let resp = client
+            .get(&format!("/v2/_catalog{}", query))
+            .basic_auth(user, Some(user))
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        resp.json().unwrap()
+    };
+
+    assert_eq!(
+        catalog("admin", "")["repositories"],
+        serde_json::json!(["myorg/myrepo", "private/repo", "test/other", "test/repo"])
+    );
+
+    // Only repositories the user may pull are listed
+    assert_eq!(
+        catalog("reader", "")["repositories"],
+        serde_json::json!(["test/other", "test/repo"])
+    );
+    assert_eq!(
+        catalog("limited", "")["repositories"],
+        serde_json::json!(["myorg/myrepo"])
+    );
+
+    // Pagination
+    assert_eq!(
+        catalog("admin", "?n=2")["repositories"],
+        serde_json::json!(["myorg/myrepo", "private/repo"])
+    );
+    assert_eq!(
+        catalog("admin", "?n=2&last=private/repo")["repositories"],
+        serde_json::json!(["test/other", "test/repo"])
+    );
+    assert_eq!(
+        catalog("reader", "?n=1&last=test/other")["repositories"],
+        serde_json::json!(["test/repo"])
+    );
+
+    // Truncated pages link to the next one
+    let resp = client
+        .get("/v2/_catalog?n=2")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(
+        resp.headers()["link"],
+        "</v2/_catalog?n=2&last=private%2Frepo>; rel=\"next\""
+    );
+    let resp = client
+        .get("/v2/_catalog?n=2&last=private%2Frepo")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert!(resp.headers().get("link").is_none());
+
+    let resp = client.get("/v2/_catalog").send().unwrap();
+    assert_eq!(resp.status(), 401);
+}
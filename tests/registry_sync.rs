@@ -0,0 +1,185 @@
+mod common;
+
+use common::*;
+use grain::sync::{Mirror, Registry, SyncOptions};
+use serial_test::serial;
+
+fn push_blob(client: &TestClient, repository: &str, blob: &[u8]) -> String {
+    let digest = format!("sha256:{}", sha256::digest(blob));
+    let resp = client
+        .post(&format!(
+            "/v2/{}/blobs/uploads/?digest={}",
+            repository, digest
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(blob.to_vec())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    digest
+}
+
+fn push_manifest(
+    client: &TestClient,
+    repository: &str,
+    reference: &str,
+    manifest: &serde_json::Value,
+) {
+    let resp = client
+        .put(&format!("/v2/{}/manifests/{}", repository, reference))
+        .basic_auth("admin", Some("admin"))
+        .header(
+            "Content-Type",
+            manifest["mediaType"].as_str().unwrap().to_string(),
+        )
+        .body(serde_json::to_vec(manifest).unwrap())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+}
+
+/// Push an image whose single layer is `layer`
+fn push_image(client: &TestClient, repository: &str, tag: &str, layer: &[u8]) {
+    push_blob(client, repository, &sample_blob());
+    let layer_digest = push_blob(client, repository, layer);
+    let mut manifest = sample_manifest();
+    manifest["layers"][0]["digest"] = layer_digest.into();
+    manifest["layers"][0]["size"] = layer.len().into();
+    push_manifest(client, repository, tag, &manifest);
+}
+
+fn tags(client: &TestClient, repository: &str) -> serde_json::Value {
+    client
+        .get(&format!("/v2/{}/tags/list", repository))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .unwrap()["tags"]
+        .clone()
+}
+
+#[test]
+#[serial]
+fn test_sync_filters_and_skips_present_content() {
+    let mut source_server = TestServer::new();
+    source_server.start();
+    let mut destination_server = TestServer::new();
+    destination_server.start();
+    let source_client = source_server.client();
+    let destination_client = destination_server.client();
+
+    push_image(&source_client, "team/app", "v1", b"layer one");
+    push_image(&source_client, "team/app", "v2", b"layer two");
+    push_image(&source_client, "team/app", "latest", b"layer two");
+    push_image(&source_client, "other/app", "v1", b"layer other");
+    // A multi-platform image whose child is only referenced by digest
+    let child = sample_manifest();
+    push_blob(&source_client, "team/web", &sample_blob());
+    push_manifest(
+        &source_client,
+        "team/web",
+        &sample_manifest_digest(&child),
+        &child,
+    );
+    push_manifest(&source_client, "team/web", "v1", &sample_image_index());
+
+    let credentials = || Some(("admin".to_string(), "admin".to_string()));
+    let source = Registry::new(&source_server.base_url, credentials());
+    let destination = Registry::new(&destination_server.base_url, credentials());
+    let options = SyncOptions {
+        repositories: vec!["team/*".to_string()],
+        tags: vec!["v*".to_string()],
+        dry_run: false,
+    };
+
+    let mut synced = Vec::new();
+    let stats = Mirror::new(&source, &destination, &options)
+        .run(|tag| synced.push(tag.to_string()))
+        .unwrap();
+    synced.sort();
+    assert_eq!(synced, vec!["team/app:v1", "team/app:v2", "team/web:v1"]);
+    assert_eq!(stats.repositories, 2);
+    assert_eq!(stats.tags_synced, 3);
+    assert_eq!(stats.manifests_copied, 4);
+    assert_eq!(
+        tags(&destination_client, "team/app"),
+        serde_json::json!(["v1", "v2"])
+    );
+    assert_eq!(
+        tags(&destination_client, "team/web"),
+        serde_json::json!(["v1"])
+    );
+    let resp = destination_client
+        .get("/v2/other/app/tags/list")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert!(resp.json::<serde_json::Value>().unwrap()["tags"]
+        .as_array()
+        .is_none_or(|tags| tags.is_empty()));
+
+    // The copied manifests and blobs are identical
+    for (repository, tag) in [("team/app", "v2"), ("team/web", "v1")] {
+        let fetch = |client: &TestClient| {
+            client
+                .get(&format!("/v2/{}/manifests/{}", repository, tag))
+                .basic_auth("admin", Some("admin"))
+                .send()
+                .unwrap()
+                .bytes()
+                .unwrap()
+        };
+        assert_eq!(fetch(&source_client), fetch(&destination_client));
+    }
+    let resp = destination_client
+        .get(&format!(
+            "/v2/team/app/blobs/sha256:{}",
+            sha256::digest(b"layer two".as_slice())
+        ))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.bytes().unwrap().as_ref(), b"layer two");
+
+    // A second run only copies what changed
+    push_image(&source_client, "team/app", "v3", b"layer one");
+    let stats = Mirror::new(&source, &destination, &options)
+        .run(|_| {})
+        .unwrap();
+    assert_eq!(stats.tags_synced, 1);
+    assert_eq!(stats.tags_up_to_date, 3);
+    assert_eq!(stats.blobs_copied, 0);
+    assert_eq!(stats.blobs_present, 2);
+    assert_eq!(
+        tags(&destination_client, "team/app"),
+        serde_json::json!(["v1", "v2", "v3"])
+    );
+
+    // Literal repository names do not need the catalog; dry runs write nothing
+    let options = SyncOptions {
+        repositories: vec!["other/app".to_string()],
+        dry_run: true,
+        ..Default::default()
+    };
+    let stats = Mirror::new(&source, &destination, &options)
+        .run(|_| {})
+        .unwrap();
+    assert_eq!(stats.tags_synced, 1);
+    assert_eq!(stats.blobs_copied, 2);
+    let resp = destination_client
+        .head("/v2/other/app/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+
+    // Registries refusing the credentials stop the sync
+    let intruder = Registry::new(
+        &destination_server.base_url,
+        Some(("reader".to_string(), "wrong".to_string())),
+    );
+    assert!(Mirror::new(&source, &intruder, &SyncOptions::default())
+        .run(|_| {})
+        .is_err());
+}
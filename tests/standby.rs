@@ -0,0 +1,364 @@
+mod common;
+
+use common::*;
+use serial_test::serial;
+use std::thread;
+use std::time::Duration;
+
+/// Poll until the request returns the expected status or the timeout elapses
+fn wait_for_status(
+    client: &TestClient,
+    path: &str,
+    user: (&str, &str),
+    expected: u16,
+) -> reqwest::blocking::Response {
+    for _ in 0..50 {
+        let resp = client
+            .get(path)
+            .basic_auth(user.0, Some(user.1))
+            .send()
+            .unwrap();
+        if resp.status() == expected {
+            return resp;
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    panic!("{} never returned {}", path, expected);
+}
+
+fn standby_of(primary: &TestServer) -> TestServer {
+    let mut standby = TestServer::new();
+    standby.start_with_args(&[
+        "--standby-of",
+        &primary.base_url,
+        "--standby-username",
+        "admin",
+        "--standby-password",
+        "admin",
+        "--standby-interval-secs",
+        "1",
+    ]);
+    standby
+}
+
+#[test]
+#[serial]
+fn test_standby_replicates_from_primary() {
+    let mut primary = TestServer::new();
+    primary.start();
+    let primary_client = primary.client();
+
+    // Content present before the standby starts is picked up by the initial snapshot
+    primary_client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let manifest = sample_manifest();
+    primary_client
+        .put("/v2/test/repo/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .json(&manifest)
+        .send()
+        .unwrap();
+
+    let standby = standby_of(&primary);
+    let standby_client = standby.client();
+
+    let resp = wait_for_status(
+        &standby_client,
+        "/v2/test/repo/manifests/v1",
+        ("admin", "admin"),
+        200,
+    );
+    assert_eq!(
+        resp.bytes().unwrap(),
+        serde_json::to_vec(&manifest).unwrap()
+    );
+    let resp = wait_for_status(
+        &standby_client,
+        &format!("/v2/test/repo/blobs/{}", sample_blob_digest()),
+        ("admin", "admin"),
+        200,
+    );
+    assert_eq!(resp.bytes().unwrap(), sample_blob());
+
+    // Later changes are applied incrementally from the change log
+    primary_client
+        .put("/v2/test/repo/manifests/v2")
+        .basic_auth("admin", Some("admin"))
+        .json(&manifest)
+        .send()
+        .unwrap();
+    primary_client
+        .delete("/v2/test/repo/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    primary_client
+        .post("/admin/v1/users")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({
+            "username": "replica",
+            "password": "replica-pass",
+            "permissions": [{"repository": "test/*", "tag": "*", "actions": ["pull"]}]
+        }))
+        .send()
+        .unwrap();
+
+    wait_for_status(
+        &standby_client,
+        "/v2/test/repo/manifests/v2",
+        ("admin", "admin"),
+        200,
+    );
+    wait_for_status(
+        &standby_client,
+        "/v2/test/repo/manifests/v1",
+        ("admin", "admin"),
+        404,
+    );
+    wait_for_status(
+        &standby_client,
+        "/v2/test/repo/manifests/v2",
+        ("replica", "replica-pass"),
+        200,
+    );
+}
+
+#[test]
+#[serial]
+fn test_standby_is_read_only() {
+    let mut primary = TestServer::new();
+    primary.start();
+
+    let standby = standby_of(&primary);
+    let client = standby.client();
+
+    let resp = client
+        .put("/v2/test/repo/manifests/latest")
+        .basic_auth("admin", Some("admin"))
+        .json(&sample_manifest())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 405);
+    let json: serde_json::Value = resp.json().unwrap();
+    assert_eq!(json["errors"][0]["code"], "UNSUPPORTED");
+
+    let resp = client
+        .post("/admin/v1/users")
+        .basic_auth("admin", Some("admin"))
+        .json(&serde_json::json!({"username": "x", "password": "x", "permissions": []}))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 405);
+
+    // Reads keep working
+    let resp = client
+        .get("/admin/v1/users")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+}
+
+#[test]
+#[serial]
+fn test_sync_endpoints_require_admin() {
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    for path in [
+        "/admin/v1/sync/changes",
+        "/admin/v1/sync/snapshot",
+        "/admin/v1/sync/users",
+    ] {
+        let resp = client.get(path).send().unwrap();
+        assert_eq!(resp.status(), 401);
+
+        let resp = client
+            .get(path)
+            .basic_auth("reader", Some("reader"))
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 403);
+    }
+
+    client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+
+    let resp = client
+        .get("/admin/v1/sync/changes?since=0")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let json: serde_json::Value = resp.json().unwrap();
+    assert_eq!(json["latest_seq"], 1);
+    assert_eq!(json["entries"][0]["kind"], "blob_put");
+    assert_eq!(json["entries"][0]["org"], "test");
+}
+
+#[test]
+#[serial]
+fn test_cluster_info_reports_topology() {
+    let mut primary = TestServer::new();
+    primary.start();
+    let primary_client = primary.client();
+    let cluster = |client: &TestClient| -> serde_json::Value {
+        let resp = client
+            .get("/admin/v1/cluster")
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        resp.json().unwrap()
+    };
+
+    let info = cluster(&primary_client);
+    assert_eq!(info["role"], "primary");
+    assert_eq!(info["storage"]["backend"], "fs");
+    assert_eq!(info["storage"]["layout_version"], 2);
+    assert_eq!(info["peers"], serde_json::json!([]));
+    let primary_id = info["instance_id"].as_str().unwrap().to_string();
+    assert!(!primary_id.is_empty());
+
+    let standby = standby_of(&primary);
+    let standby_client = standby.client();
+    let standby_info = cluster(&standby_client);
+    assert_eq!(standby_info["role"], "standby");
+    assert_eq!(standby_info["peers"][0]["role"], "primary");
+    assert_eq!(standby_info["peers"][0]["url"], primary.base_url.as_str());
+    let standby_id = standby_info["instance_id"].as_str().unwrap();
+    assert_ne!(standby_id, primary_id);
+
+    // The primary lists the standby once it has synced
+    let mut peers = serde_json::Value::Null;
+    for _ in 0..50 {
+        peers = cluster(&primary_client)["peers"].clone();
+        if peers.as_array().is_some_and(|p| !p.is_empty()) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    assert_eq!(peers[0]["instance_id"], standby_id);
+    assert_eq!(peers[0]["role"], "standby");
+    assert!(peers[0]["last_sync"].as_u64().is_some());
+
+    // The instance ID survives restarts
+    primary.stop();
+    primary.start();
+    assert_eq!(
+        cluster(&primary.client())["instance_id"],
+        primary_id.as_str()
+    );
+
+    let resp = primary
+        .client()
+        .get("/admin/v1/cluster")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+}
+
+#[test]
+#[serial]
+fn test_push_replication() {
+    let mut peer = TestServer::new();
+    peer.start();
+    let peer_client = peer.client();
+
+    let mut primary = TestServer::new();
+    primary.start_with_args(&[
+        "--replicate-to",
+        &format!("{},http://127.0.0.1:1", peer.base_url),
+        "--replication-username",
+        "admin",
+        "--replication-password",
+        "admin",
+    ]);
+    let primary_client = primary.client();
+
+    primary_client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    let manifest = sample_manifest();
+    let resp = primary_client
+        .put("/v2/test/repo/manifests/v1")
+        .basic_auth("admin", Some("admin"))
+        .json(&manifest)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    // The manifest and its blobs reach the peer
+    let resp = wait_for_status(
+        &peer_client,
+        "/v2/test/repo/manifests/v1",
+        ("admin", "admin"),
+        200,
+    );
+    assert_eq!(
+        resp.bytes().unwrap(),
+        serde_json::to_vec(&manifest).unwrap()
+    );
+    let resp = wait_for_status(
+        &peer_client,
+        &format!("/v2/test/repo/blobs/{}", sample_blob_digest()),
+        ("admin", "admin"),
+        200,
+    );
+    assert_eq!(resp.bytes().unwrap(), sample_blob());
+
+    // The unreachable peer keeps the push queued with its error
+    let mut status = serde_json::Value::Null;
+    for _ in 0..50 {
+        status = primary_client
+            .get("/admin/v1/replication/status")
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap()
+            .json()
+            .unwrap();
+        if status["peers"][0]["queued"] == 0 && status["peers"][1]["last_error"].is_string() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    assert_eq!(status["peers"][0]["url"], peer.base_url.as_str());
+    assert_eq!(status["peers"][0]["replicated"], 1);
+    assert!(status["peers"][0]["last_success"].as_u64().is_some());
+    assert_eq!(status["peers"][1]["queued"], 1);
+    assert_eq!(status["peers"][1]["pending"][0]["reference"], "v1");
+    assert_eq!(
+        status["peers"][1]["pending"][0]["digest"],
+        sample_manifest_digest(&manifest).as_str()
+    );
+    assert!(status["peers"][1]["pending"][0]["attempts"].as_u64() >= Some(1));
+
+    let resp = primary_client
+        .get("/admin/v1/replication/status")
+        .basic_auth("reader", Some("reader"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 403);
+}
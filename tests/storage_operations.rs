@@ -518,3 +518,374 @@ fn test_storage_blob_metadata() {
         .unwrap();
     assert_eq!(content_length, blob.len());
 }
+
+/// Request body that stalls before producing any data
+struct StalledBody {
+    delay: std::time::Duration,
+    done: bool,
+}
+
+impl std::io::Read for StalledBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.done {
+            return Ok(0);
+        }
+        std::thread::sleep(self.delay);
+        self.done = true;
+        buf[..4].copy_from_slice(b"late");
+        Ok(4)
+    }
+}
+
+#[test]
+#[serial]
+fn test_storage_request_timeout_leaves_session_intact() {
+    let mut server = TestServer::new();
+    server.start_with_args(&["--request-timeout-secs", "1"]);
+    let client = server.client();
+
+    let resp = client
+        .post("/v2/test/repo/blobs/uploads/")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    let location =
+        extract_path(resp.headers().get("location").unwrap().to_str().unwrap()).to_string();
+
+    // A client stalling mid-body is cut off instead of holding the request open
+    let resp = client
+        .patch(&location)
+        .basic_auth("admin", Some("admin"))
+        .body(reqwest::blocking::Body::new(StalledBody {
+            delay: std::time::Duration::from_secs(3),
+            done: false,
+        }))
+        .send();
+    if let Ok(resp) = resp {
+        assert_eq!(resp.status(), 408);
+    }
+
+    // Nothing was written, so the session can still be completed
+    let chunk = b"chunk";
+    let resp = client
+        .patch(&location)
+        .basic_auth("admin", Some("admin"))
+        .body(chunk.to_vec())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    assert_eq!(resp.headers().get("range").unwrap(), "0-4");
+
+    let digest = format!("sha256:{}", sha256::digest(chunk.as_slice()));
+    let resp = client
+        .put(&format!("{}?digest={}", location, digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+}
+
+#[test]
+#[serial]
+fn test_storage_body_limits_per_route() {
+    let mut server = TestServer::new();
+    server.start_with_args(&[
+        "--max-manifest-size",
+        "64",
+        "--max-blob-size",
+        "8",
+        "--upload-buffer-size",
+        "4",
+        "--max-admin-body-size",
+        "128",
+    ]);
+    let client = server.client();
+
+    // Manifests over the limit are rejected before validation
+    let resp = client
+        .put("/v2/test/repo/manifests/big")
+        .basic_auth("admin", Some("admin"))
+        .json(&sample_manifest())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 413);
+    let json: serde_json::Value = resp.json().unwrap();
+    assert_eq!(json["errors"][0]["code"], "SIZE_INVALID");
+
+    // Chunks within the blob limit are streamed through the small buffer
+    let resp = client
+        .post("/v2/test/repo/blobs/uploads/")
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    let location =
+        extract_path(resp.headers().get("location").unwrap().to_str().unwrap()).to_string();
+    let resp = client
+        .patch(&location)
+        .basic_auth("admin", Some("admin"))
+        .body(b"0123456".to_vec())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    assert_eq!(resp.headers().get("range").unwrap(), "0-6");
+
+    // Pushing the session past the blob limit fails and discards it
+    let resp = client
+        .patch(&location)
+        .basic_auth("admin", Some("admin"))
+        .body(b"789".to_vec())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 413);
+    let resp = client
+        .patch(&location)
+        .basic_auth("admin", Some("admin"))
+        .body(b"7".to_vec())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+
+    // Monolithic uploads share the blob limit
+    let blob = b"012345678".to_vec();
+    let resp = client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest=sha256:{}",
+            sha256::digest(blob.as_slice())
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(blob)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 413);
+
+    let blob = b"01234567".to_vec();
+    let resp = client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest=sha256:{}",
+            sha256::digest(blob.as_slice())
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(blob.clone())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    // Admin API bodies have their own limit
+    let create_user = |password: &str| {
+        client
+            .post("/admin/v1/users")
+            .basic_auth("admin", Some("admin"))
+            .json(&serde_json::json!({
+                "username": "bounded",
+                "password": password,
+                "permissions": []
+            }))
+            .send()
+            .unwrap()
+    };
+    let resp = create_user(&"x".repeat(200));
+    assert_eq!(resp.status(), 413);
+    let json: serde_json::Value = resp.json().unwrap();
+    assert_eq!(json["errors"][0]["code"], "SIZE_INVALID");
+    assert!(create_user("boundedpass1").status().is_success());
+}
+
+#[test]
+#[serial]
+fn test_storage_blobs_namespaced_by_algorithm() {
+    use sha2::{Digest, Sha512};
+
+    let mut server = TestServer::new();
+
+    // A blob in the pre-namespacing flat layout is migrated on startup
+    let legacy = b"legacy blob";
+    let legacy_hex = sha256::digest(legacy.as_slice());
+    let legacy_dir = server.temp_dir.path().join("tmp/blobs/test/legacy");
+    std::fs::create_dir_all(&legacy_dir).unwrap();
+    std::fs::write(legacy_dir.join(&legacy_hex), legacy).unwrap();
+
+    server.start();
+    let client = server.client();
+
+    assert!(legacy_dir.join("sha256").join(&legacy_hex).exists());
+    assert!(!legacy_dir.join(&legacy_hex).exists());
+    let resp = client
+        .get(&format!("/v2/test/legacy/blobs/sha256:{}", legacy_hex))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.bytes().unwrap().as_ref(), legacy);
+
+    // sha512 blobs are verified with sha512 and stored under their own directory
+    let blob = b"sha512 blob".to_vec();
+    let hex = format!("{:x}", Sha512::digest(&blob));
+    let digest = format!("sha512:{}", hex);
+    let resp = client
+        .post(&format!("/v2/test/repo/blobs/uploads/?digest={}", digest))
+        .basic_auth("admin", Some("admin"))
+        .body(blob.clone())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    assert_eq!(
+        resp.headers().get("docker-content-digest").unwrap(),
+        &digest
+    );
+    assert!(server
+        .temp_dir
+        .path()
+        .join("tmp/blobs/test/repo/sha512")
+        .join(&hex)
+        .exists());
+
+    let resp = client
+        .head(&format!("/v2/test/repo/blobs/{}", digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("docker-content-digest").unwrap(),
+        &digest
+    );
+
+    // Unsupported algorithms are rejected
+    let resp = client
+        .post("/v2/test/repo/blobs/uploads/?digest=md5:0123")
+        .basic_auth("admin", Some("admin"))
+        .body(blob)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+    let json: serde_json::Value = resp.json().unwrap();
+    assert_eq!(json["errors"][0]["code"], "DIGEST_INVALID");
+}
+
+#[test]
+#[serial]
+fn test_storage_upload_session_quota() {
+    let mut server = TestServer::new();
+    server.start_with_args(&[
+        "--max-upload-sessions-per-repo",
+        "2",
+        "--max-upload-bytes-per-repo",
+        "10",
+    ]);
+    let client = server.client();
+
+    let start_upload = |repo: &str| {
+        client
+            .post(&format!("/v2/test/{}/blobs/uploads/", repo))
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap()
+    };
+
+    let first = start_upload("repo");
+    assert_eq!(first.status(), 202);
+    let first = first.headers()["location"].to_str().unwrap().to_string();
+    let second = start_upload("repo");
+    assert_eq!(second.status(), 202);
+    let second = second.headers()["location"].to_str().unwrap().to_string();
+
+    let resp = start_upload("repo");
+    assert_eq!(resp.status(), 429);
+    let body: serde_json::Value = resp.json().unwrap();
+    assert_eq!(body["errors"][0]["code"], "TOOMANYREQUESTS");
+
+    // Monolithic uploads open a session too
+    let resp = client
+        .post(&format!(
+            "/v2/test/repo/blobs/uploads/?digest={}",
+            sample_blob_digest()
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(sample_blob())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 429);
+
+    // Quotas are per repository
+    assert_eq!(start_upload("other").status(), 202);
+
+    // Completing a session frees its slot, but the other one holds too many bytes
+    let data = b"hello".to_vec();
+    let digest = format!("sha256:{}", sha256::digest(&data));
+    let resp = client
+        .put(&format!("{}?digest={}", extract_path(&first), digest))
+        .basic_auth("admin", Some("admin"))
+        .body(data)
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    let resp = client
+        .patch(extract_path(&second))
+        .basic_auth("admin", Some("admin"))
+        .body(vec![0u8; 10])
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+    assert_eq!(start_upload("repo").status(), 429);
+
+    let resp = client.get("/metrics").send().unwrap().text().unwrap();
+    assert!(resp.contains("grain_upload_quota_rejections_total{limit=\"bytes\"} 1"));
+    assert!(resp.contains("grain_upload_quota_rejections_total{limit=\"sessions\"} 2"));
+}
+
+#[test]
+#[serial]
+fn test_storage_chunked_upload_digest_algorithms() {
+    use sha2::{Digest, Sha512};
+
+    let mut server = TestServer::new();
+    server.start();
+    let client = server.client();
+
+    let start_upload = || {
+        let resp = client
+            .post("/v2/test/repo/blobs/uploads/")
+            .basic_auth("admin", Some("admin"))
+            .send()
+            .unwrap();
+        extract_path(resp.headers()["location"].to_str().unwrap()).to_string()
+    };
+    let patch = |location: &str, chunk: &[u8]| {
+        client
+            .patch(location)
+            .basic_auth("admin", Some("admin"))
+            .body(chunk.to_vec())
+            .send()
+            .unwrap()
+            .status()
+    };
+
+    // sha256 uploads are hashed as chunks arrive, including the closing PUT body
+    let location = start_upload();
+    assert_eq!(patch(&location, b"abc"), 202);
+    assert_eq!(patch(&location, b"def"), 202);
+    let resp = client
+        .put(&format!(
+            "{}?digest=sha256:{}",
+            location,
+            sha256::digest(b"abcdefgh".as_slice())
+        ))
+        .basic_auth("admin", Some("admin"))
+        .body(b"gh".to_vec())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    // Chunked sha512 uploads are verified from disk
+    let location = start_upload();
+    assert_eq!(patch(&location, b"sha512 "), 202);
+    assert_eq!(patch(&location, b"chunks"), 202);
+    let digest = format!("sha512:{:x}", Sha512::digest(b"sha512 chunks"));
+    let resp = client
+        .put(&format!("{}?digest={}", location, digest))
+        .basic_auth("admin", Some("admin"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+    assert_eq!(resp.headers()["docker-content-digest"], digest.as_str());
+}